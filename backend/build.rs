@@ -0,0 +1,18 @@
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=BUILD_HASH");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+
+    let hash = std::env::var("BUILD_HASH")
+        .ok()
+        .filter(|hash| !hash.is_empty())
+        .or_else(|| {
+            let output = Command::new("git").args(["rev-parse", "--short=12", "HEAD"]).output().ok()?;
+            output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=LOCALDRIVE_BUILD_HASH={}", hash);
+}
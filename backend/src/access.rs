@@ -0,0 +1,103 @@
+use axum::http::StatusCode;
+use uuid::Uuid;
+use crate::models::{FileInfo, Folder, PermissionSet, User};
+use crate::{database, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    Read,
+    Write,
+    Delete,
+    Reshare,
+}
+
+impl PermissionSet {
+    pub const ALL: PermissionSet = PermissionSet { read: true, write: true, delete: true, reshare: true };
+
+    pub fn allows(&self, permission: Permission) -> bool {
+        self.read
+            && match permission {
+                Permission::Read => true,
+                Permission::Write => self.write,
+                Permission::Delete => self.delete,
+                Permission::Reshare => self.reshare,
+            }
+    }
+
+    pub fn covers(&self, other: &PermissionSet) -> bool {
+        (self.read || !other.read)
+            && (self.write || !other.write)
+            && (self.delete || !other.delete)
+            && (self.reshare || !other.reshare)
+    }
+}
+
+async fn effective(state: &AppState, user_id: &Uuid, owner_id: &Uuid, folder_id: Option<&Uuid>) -> anyhow::Result<PermissionSet> {
+    if user_id == owner_id {
+        return Ok(PermissionSet::ALL);
+    }
+
+    match folder_id {
+        Some(folder_id) => Ok(database::get_inherited_folder_permissions(&state.db, folder_id, user_id)
+            .await?
+            .unwrap_or_default()),
+        None => Ok(PermissionSet::default()),
+    }
+}
+
+pub async fn file_permissions(state: &AppState, user_id: &Uuid, file: &FileInfo) -> anyhow::Result<PermissionSet> {
+    effective(state, user_id, &file.user_id, file.folder_id.as_ref()).await
+}
+
+pub async fn folder_permissions(state: &AppState, user_id: &Uuid, folder: &Folder) -> anyhow::Result<PermissionSet> {
+    effective(state, user_id, &folder.user_id, Some(&folder.id)).await
+}
+
+fn check(permissions: &PermissionSet, permission: Permission) -> Result<(), StatusCode> {
+    if !permissions.read {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if !permissions.allows(permission) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+pub async fn authorize_file(state: &AppState, user: &User, file_id: &Uuid, permission: Permission) -> Result<FileInfo, StatusCode> {
+    let file = database::get_file_by_id(&state.db, file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if file.is_deleted && file.user_id != user.id {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let permissions = file_permissions(state, &user.id, &file)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    check(&permissions, permission)?;
+
+    Ok(file)
+}
+
+pub async fn authorize_folder(state: &AppState, user: &User, folder_id: &Uuid, permission: Permission) -> Result<Folder, StatusCode> {
+    let folder = database::get_folder_by_id(&state.db, folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let permissions = folder_permissions(state, &user.id, &folder)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    check(&permissions, permission)?;
+
+    Ok(folder)
+}
+
+pub async fn destination_owner(state: &AppState, user: &User, folder_id: Option<&Uuid>) -> Result<Uuid, StatusCode> {
+    match folder_id {
+        Some(folder_id) => Ok(authorize_folder(state, user, folder_id, Permission::Write).await?.user_id),
+        None => Ok(user.id),
+    }
+}
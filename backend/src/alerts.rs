@@ -0,0 +1,142 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use tracing::{error, warn};
+use crate::channels::{self, NotificationEvent};
+use crate::mailer::Mailer;
+use crate::{database, AppState};
+
+pub const ALERT_NOTIFICATION: &str = "alert";
+
+static LOGIN_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+pub fn record_login_failure() {
+    LOGIN_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+struct Reading {
+    key: String,
+    description: String,
+    value: i64,
+    threshold: i64,
+}
+
+impl Reading {
+    fn firing(&self) -> bool {
+        self.value >= self.threshold
+    }
+}
+
+async fn readings(state: &AppState) -> Vec<Reading> {
+    let mut readings = Vec::new();
+
+    match state.file_storage.get_disk_info() {
+        Ok(disks) => {
+            for disk in disks.iter().filter(|disk| disk.is_accessible) {
+                readings.push(Reading {
+                    key: format!("disk:{}", disk.path),
+                    description: format!("Disk usage percentage on {}", disk.path),
+                    value: disk.usage_percentage as i64,
+                    threshold: state.config.alert_disk_percent as i64,
+                });
+            }
+        }
+        Err(e) => error!("Failed to read disk usage for alerts: {}", e),
+    }
+
+    readings.push(Reading {
+        key: "failed_logins".to_string(),
+        description: "Failed logins per minute".to_string(),
+        value: LOGIN_FAILURES.swap(0, Ordering::Relaxed) as i64,
+        threshold: state.config.alert_failed_logins_per_minute as i64,
+    });
+
+    match database::get_queue_depth(&state.db).await {
+        Ok(depth) => readings.push(Reading {
+            key: "queue_depth".to_string(),
+            description: "Queued background jobs".to_string(),
+            value: depth,
+            threshold: state.config.alert_queue_depth,
+        }),
+        Err(e) => error!("Failed to read queue depth for alerts: {}", e),
+    }
+
+    readings
+}
+
+#[derive(Default)]
+pub struct Evaluator {
+    firing: Mutex<HashSet<String>>,
+}
+
+impl Evaluator {
+    fn transitions(&self, readings: Vec<Reading>) -> Vec<Reading> {
+        let mut firing = self.firing.lock().unwrap_or_else(|e| e.into_inner());
+
+        readings
+            .into_iter()
+            .filter(|reading| {
+                if reading.firing() {
+                    firing.insert(reading.key.clone())
+                } else {
+                    firing.remove(&reading.key)
+                }
+            })
+            .collect()
+    }
+
+    pub async fn evaluate(&self, state: &AppState) {
+        let changed = self.transitions(readings(state).await);
+        if changed.is_empty() {
+            return;
+        }
+
+        for reading in changed {
+            let (status, message) = if reading.firing() {
+                ("firing", format!("{} is {}, at or above the threshold of {}", reading.description, reading.value, reading.threshold))
+            } else {
+                ("resolved", format!("{} is back to {}, below the threshold of {}", reading.description, reading.value, reading.threshold))
+            };
+            warn!("Alert {} {}: {}", reading.key, status, message);
+
+            let data = serde_json::json!({
+                "alert": reading.key,
+                "status": status,
+                "value": reading.value,
+                "threshold": reading.threshold,
+            });
+            let subject = format!("[Local Drive] Alert {}: {}", status, reading.description);
+            notify_admins(state, &subject, &message, &data).await;
+        }
+    }
+}
+
+pub async fn notify_admins(state: &AppState, subject: &str, message: &str, data: &serde_json::Value) {
+    channels::send(state, NotificationEvent::Alert, subject, message);
+
+    let admins = match database::get_active_admins(&state.db).await {
+        Ok(admins) => admins,
+        Err(e) => {
+            error!("Failed to load admins for alert \"{}\": {}", subject, e);
+            return;
+        }
+    };
+    let mailer = match Mailer::from_config(&state.config) {
+        Ok(mailer) => mailer,
+        Err(e) => {
+            error!("Failed to set up the mailer for alerts: {}", e);
+            None
+        }
+    };
+
+    for admin in &admins {
+        if let Err(e) = database::create_notification(&state.db, &admin.id, ALERT_NOTIFICATION, message, Some(data)).await {
+            error!("Failed to notify {} of alert \"{}\": {}", admin.username, subject, e);
+        }
+        if let Some(mailer) = &mailer {
+            if let Err(e) = mailer.send(&admin.email, subject, message).await {
+                warn!("Failed to email alert \"{}\" to {}: {}", subject, admin.email, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use chrono::{Datelike, Timelike};
+use tracing::{info, warn};
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use crate::models::{ArchivePart, FileInfo, Folder, User};
+use crate::operations::Progress;
+use crate::{database, AppState};
+
+const MANIFEST_NAME: &str = "manifest.json";
+const FILES_PREFIX: &str = "files";
+
+struct Entry {
+    file: FileInfo,
+    path: String,
+    checksum: String,
+}
+
+fn clean_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    match name.trim() {
+        "" | "." | ".." => "_".to_string(),
+        name => name.to_string(),
+    }
+}
+
+fn folder_paths(folders: &[Folder]) -> HashMap<Uuid, String> {
+    let by_id: HashMap<Uuid, &Folder> = folders.iter().map(|folder| (folder.id, folder)).collect();
+
+    folders
+        .iter()
+        .map(|folder| {
+            let mut parts = vec![clean_name(&folder.name)];
+            let mut parent = folder.parent_id;
+            while let Some(parent_folder) = parent.and_then(|id| by_id.get(&id)) {
+                if parts.len() > by_id.len() {
+                    break;
+                }
+                parts.push(clean_name(&parent_folder.name));
+                parent = parent_folder.parent_id;
+            }
+            parts.reverse();
+            (folder.id, parts.join("/"))
+        })
+        .collect()
+}
+
+fn unique_path(taken: &mut HashSet<String>, path: String, file_id: &Uuid) -> String {
+    if taken.insert(path.to_lowercase()) {
+        return path;
+    }
+
+    let (dir, name) = match path.rsplit_once('/') {
+        Some((dir, name)) => (format!("{}/", dir), name.to_string()),
+        None => (String::new(), path.clone()),
+    };
+    let suffix = &file_id.simple().to_string()[..8];
+    let renamed = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}{} ({}).{}", dir, stem, suffix, extension),
+        _ => format!("{}{} ({})", dir, name, suffix),
+    };
+    taken.insert(renamed.to_lowercase());
+    renamed
+}
+
+async fn checksum(state: &AppState, file: &FileInfo) -> anyhow::Result<String> {
+    if let Some(checksum) = &file.checksum {
+        return Ok(checksum.clone());
+    }
+
+    let file_storage = state.file_storage.clone();
+    let (file_path, encoding) = (PathBuf::from(&file.file_path), file.encoding());
+    let checksum = tokio::task::spawn_blocking(move || file_storage.compute_sha256(&file_path, encoding)).await??;
+    database::set_file_checksum(&state.db, &file.id, &checksum).await?;
+    Ok(checksum)
+}
+
+async fn current_entries(state: &AppState, user: &User) -> anyhow::Result<Vec<Entry>> {
+    let folders = database::get_folders_by_user(&state.db, &user.id).await?;
+    let paths = folder_paths(&folders);
+
+    let mut files = database::get_all_files(&state.db, &user.id).await?;
+    files.retain(|file| !file.is_quarantined);
+    files.sort_by(|a, b| a.created_at.cmp(&b.created_at).then(a.id.cmp(&b.id)));
+
+    let mut taken = HashSet::new();
+    let mut entries = Vec::with_capacity(files.len());
+    for file in files {
+        let checksum = match checksum(state, &file).await {
+            Ok(checksum) => checksum,
+            Err(e) => {
+                warn!("Leaving file {} out of the archive for user {}: {}", file.id, user.id, e);
+                continue;
+            }
+        };
+
+        let name = clean_name(&file.original_filename);
+        let path = match file.folder_id.and_then(|id| paths.get(&id)) {
+            Some(folder_path) => format!("{}/{}/{}", FILES_PREFIX, folder_path, name),
+            None => format!("{}/{}", FILES_PREFIX, name),
+        };
+        let path = unique_path(&mut taken, path, &file.id);
+        entries.push(Entry { file, path, checksum });
+    }
+
+    Ok(entries)
+}
+
+fn chunk(entries: Vec<Entry>, max_size: i64) -> Vec<Vec<Entry>> {
+    let mut parts: Vec<Vec<Entry>> = Vec::new();
+    let mut current = Vec::new();
+    let mut current_size = 0i64;
+
+    for entry in entries {
+        if !current.is_empty() && current_size + entry.file.file_size > max_size {
+            parts.push(std::mem::take(&mut current));
+            current_size = 0;
+        }
+        current_size += entry.file.file_size;
+        current.push(entry);
+    }
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    parts
+}
+
+fn zip_time(timestamp: &chrono::DateTime<chrono::Utc>) -> Option<zip::DateTime> {
+    zip::DateTime::from_date_and_time(
+        u16::try_from(timestamp.year()).ok()?,
+        timestamp.month() as u8,
+        timestamp.day() as u8,
+        timestamp.hour() as u8,
+        timestamp.minute() as u8,
+        timestamp.second() as u8,
+    )
+    .ok()
+}
+
+fn entry_options(size: i64, modified_at: &chrono::DateTime<chrono::Utc>) -> SimpleFileOptions {
+    let options = SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(size >= u32::MAX as i64);
+    match zip_time(modified_at) {
+        Some(time) => options.last_modified_time(time),
+        None => options,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn write_part(
+    state: &AppState,
+    part: &ArchivePart,
+    archive_path: &Path,
+    entries: &[Entry],
+    removed: &[(Uuid, String)],
+    progress: &Progress,
+    done: &mut i64,
+    total: i64,
+) -> anyhow::Result<i64> {
+    let now = chrono::Utc::now();
+    let manifest = serde_json::json!({
+        "sequence": part.sequence,
+        "created_at": now,
+        "files": entries.iter().map(|entry| serde_json::json!({
+            "id": entry.file.id,
+            "path": entry.path,
+            "size": entry.file.file_size,
+            "sha256": entry.checksum,
+            "modified_at": entry.file.client_modified_at.unwrap_or(entry.file.updated_at),
+        })).collect::<Vec<_>>(),
+        "removed": removed.iter().map(|(id, path)| serde_json::json!({ "id": id, "path": path })).collect::<Vec<_>>(),
+    });
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let mut writer = {
+        let archive_path = archive_path.to_path_buf();
+        tokio::task::spawn_blocking(move || -> anyhow::Result<zip::ZipWriter<std::fs::File>> {
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(&archive_path)?);
+            writer.start_file(MANIFEST_NAME, entry_options(manifest.len() as i64, &now))?;
+            writer.write_all(&manifest)?;
+            Ok(writer)
+        })
+        .await??
+    };
+
+    for entry in entries {
+        let file_storage = state.file_storage.clone();
+        let (file_path, encoding) = (PathBuf::from(&entry.file.file_path), entry.file.encoding());
+        let path = entry.path.clone();
+        let options = entry_options(entry.file.file_size, &entry.file.client_modified_at.unwrap_or(entry.file.updated_at));
+        writer = tokio::task::spawn_blocking(move || -> anyhow::Result<zip::ZipWriter<std::fs::File>> {
+            let mut source = file_storage.open_file(&file_path, encoding)?;
+            writer.start_file(path, options)?;
+            std::io::copy(&mut source, &mut writer)?;
+            Ok(writer)
+        })
+        .await??;
+
+        *done += 1;
+        progress.update(*done, Some(total)).await?;
+    }
+
+    let file = tokio::task::spawn_blocking(move || -> anyhow::Result<std::fs::File> {
+        let file = writer.finish()?;
+        file.sync_all()?;
+        Ok(file)
+    })
+    .await??;
+
+    Ok(file.metadata()?.len() as i64)
+}
+
+async fn build_part(
+    state: &AppState,
+    user: &User,
+    entries: &[Entry],
+    removed: &[(Uuid, String)],
+    progress: &Progress,
+    done: &mut i64,
+    total: i64,
+) -> anyhow::Result<i64> {
+    let part = database::create_archive_part(&state.db, &user.id)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("another archive build is already running"))?;
+
+    let estimated_size: i64 = entries.iter().map(|entry| entry.file.file_size).sum();
+    let result = async {
+        let archive_path = state.file_storage.create_archive_path(&user.id, &part.id, estimated_size as u64)?;
+        database::set_archive_part_path(&state.db, &part.id, &archive_path.to_string_lossy()).await?;
+
+        let written = write_part(state, &part, &archive_path, entries, removed, progress, done, total).await;
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&archive_path).await;
+        }
+        written
+    }
+    .await;
+
+    let size = match result {
+        Ok(size) => size,
+        Err(e) => {
+            database::fail_archive_part(&state.db, &part.id, &format!("{:#}", e)).await?;
+            return Err(e);
+        }
+    };
+
+    let recorded: Vec<(Uuid, String, String, i64)> = entries
+        .iter()
+        .map(|entry| (entry.file.id, entry.path.clone(), entry.checksum.clone(), entry.file.file_size))
+        .collect();
+    let removed_ids: Vec<Uuid> = removed.iter().map(|(id, _)| *id).collect();
+    database::complete_archive_part(&state.db, &part, &recorded, &removed_ids, size).await?;
+
+    Ok(size)
+}
+
+pub async fn remove_files(parts: &[ArchivePart]) {
+    for part in parts {
+        if let Some(archive_path) = &part.archive_path {
+            if let Err(e) = tokio::fs::remove_file(archive_path).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to delete archive part {}: {}", archive_path, e);
+                }
+            }
+        }
+    }
+}
+
+pub async fn build(state: &AppState, user: &User, progress: &Progress) -> anyhow::Result<serde_json::Value> {
+    let failed = database::delete_archive_parts(&state.db, &user.id, true).await?;
+    remove_files(&failed).await;
+
+    let entries = current_entries(state, user).await?;
+    let manifest: HashMap<Uuid, (String, String)> = database::get_archive_manifest(&state.db, &user.id)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.file_id, (entry.path, entry.checksum)))
+        .collect();
+
+    let current: HashSet<Uuid> = entries.iter().map(|entry| entry.file.id).collect();
+    let removed: Vec<(Uuid, String)> = manifest
+        .iter()
+        .filter(|(id, _)| !current.contains(id))
+        .map(|(id, (path, _))| (*id, path.clone()))
+        .collect();
+    let pending: Vec<Entry> = entries
+        .into_iter()
+        .filter(|entry| match manifest.get(&entry.file.id) {
+            Some((path, checksum)) => *path != entry.path || *checksum != entry.checksum,
+            None => true,
+        })
+        .collect();
+
+    let total = pending.len() as i64;
+    let file_count = pending.len();
+    progress.update(0, Some(total)).await?;
+
+    let mut parts = chunk(pending, state.config.archive_part_max_size);
+    if parts.is_empty() && !removed.is_empty() {
+        parts.push(Vec::new());
+    }
+
+    let mut done = 0i64;
+    let mut bytes = 0i64;
+    for (i, entries) in parts.iter().enumerate() {
+        let removed = if i == 0 { removed.as_slice() } else { &[] };
+        bytes += build_part(state, user, entries, removed, progress, &mut done, total).await?;
+    }
+
+    if !parts.is_empty() {
+        info!(
+            "Built {} archive parts for user {} ({} files, {} removed, {} bytes)",
+            parts.len(), user.username, file_count, removed.len(), bytes
+        );
+    }
+
+    Ok(serde_json::json!({
+        "parts": parts.len(),
+        "files": file_count,
+        "removed": removed.len(),
+        "bytes": bytes,
+    }))
+}
@@ -1,24 +1,78 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use axum::{
-    extract::{Request, State},
-    http::{header::AUTHORIZATION, StatusCode},
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header::{AUTHORIZATION, WWW_AUTHENTICATE}, StatusCode},
     middleware::Next,
     response::Response,
 };
+use base64::Engine;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use crate::{database, AppState};
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::{rand_core::{OsRng, RngCore}, SaltString};
+use crate::config::Config;
+use crate::models::User;
+use crate::{alerts, database, login_limit, AppState};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub username: String,
     pub is_admin: bool,
+    pub sid: Uuid,
     pub exp: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct CurrentSession(pub Uuid);
 
+// Remembers recently verified Basic credentials so WebDAV clients, which send them on every
+// request, do not pay for an Argon2 verification each time. Changing the password changes the
+// key and so invalidates the entry.
+const BASIC_AUTH_CACHE_TTL: Duration = Duration::from_secs(300);
+static BASIC_AUTH_CACHE: Mutex<BTreeMap<[u8; 32], Instant>> = Mutex::new(BTreeMap::new());
+
+pub const DEFAULT_JWT_SECRET: &str = "your-secret-key";
+pub const MIN_JWT_SECRET_LENGTH: usize = 32;
+const PLACEHOLDER_JWT_SECRETS: &[&str] = &[
+    DEFAULT_JWT_SECRET,
+    "your-secret-key-change-this-in-production",
+    "your-super-secret-jwt-key-here",
+];
+
+pub fn jwt_secret_problem(secret: &str) -> Option<String> {
+    if PLACEHOLDER_JWT_SECRETS.contains(&secret) {
+        Some("JWT_SECRET is set to a placeholder value".to_string())
+    } else if secret.len() < MIN_JWT_SECRET_LENGTH {
+        Some(format!("JWT_SECRET is shorter than {} characters", MIN_JWT_SECRET_LENGTH))
+    } else {
+        None
+    }
+}
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 48];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?;
+    Ok(hash.to_string())
+}
 
 pub fn verify_password(password: &str, hash: &str) -> anyhow::Result<bool> {
     let parsed_hash = PasswordHash::new(hash)
@@ -27,33 +81,53 @@ pub fn verify_password(password: &str, hash: &str) -> anyhow::Result<bool> {
     Ok(argon2.verify_password(password.as_bytes(), &parsed_hash).is_ok())
 }
 
-pub fn create_jwt_token(user_id: &Uuid, username: &str, is_admin: bool, secret: &str) -> anyhow::Result<String> {
-    let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
-        .expect("valid timestamp")
-        .timestamp() as usize;
-
+pub fn create_jwt_token(
+    user_id: &Uuid,
+    username: &str,
+    is_admin: bool,
+    session_id: &Uuid,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    config: &Config,
+) -> anyhow::Result<String> {
     let claims = Claims {
         sub: user_id.to_string(),
         username: username.to_string(),
         is_admin,
-        exp: expiration,
+        sid: *session_id,
+        exp: expires_at.timestamp() as usize,
+        iss: config.jwt_issuer.clone(),
+        aud: config.jwt_audience.clone(),
     };
 
     let token = encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret.as_ref()),
+        &EncodingKey::from_secret(config.jwt_secret.as_ref()),
     )?;
 
     Ok(token)
 }
 
-pub fn verify_jwt_token(token: &str, secret: &str) -> anyhow::Result<Claims> {
+pub fn verify_jwt_token(token: &str, config: &Config) -> anyhow::Result<Claims> {
+    let mut validation = Validation::default();
+    let mut required = vec!["exp"];
+    if let Some(issuer) = &config.jwt_issuer {
+        validation.set_issuer(&[issuer]);
+        required.push("iss");
+    }
+    match &config.jwt_audience {
+        Some(audience) => {
+            validation.set_audience(&[audience]);
+            required.push("aud");
+        }
+        None => validation.validate_aud = false,
+    }
+    validation.set_required_spec_claims(&required);
+
     let token_data = decode::<Claims>(
         token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
+        &DecodingKey::from_secret(config.jwt_secret.as_ref()),
+        &validation,
     )?;
 
     Ok(token_data.claims)
@@ -76,7 +150,7 @@ pub async fn auth_middleware(
         _ => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    let claims = match verify_jwt_token(token, &state.config.jwt_secret) {
+    let claims = match verify_jwt_token(token, &state.config) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
@@ -86,13 +160,20 @@ pub async fn auth_middleware(
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
+    match database::touch_session(&state.db, &claims.sid, &user_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::UNAUTHORIZED),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+
     let user = match database::get_user_by_id(&state.db, &user_id).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+        Ok(Some(user)) if user.deactivated_at.is_none() => user,
+        Ok(_) => return Err(StatusCode::UNAUTHORIZED),
         Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
 
     request.extensions_mut().insert(user);
+    request.extensions_mut().insert(CurrentSession(claims.sid));
     Ok(next.run(request).await)
 }
 
@@ -113,7 +194,7 @@ pub async fn admin_middleware(
         _ => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    let claims = match verify_jwt_token(token, &state.config.jwt_secret) {
+    let claims = match verify_jwt_token(token, &state.config) {
         Ok(claims) => claims,
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
@@ -127,12 +208,170 @@ pub async fn admin_middleware(
         Err(_) => return Err(StatusCode::UNAUTHORIZED),
     };
 
+    match database::touch_session(&state.db, &claims.sid, &user_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(StatusCode::UNAUTHORIZED),
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+
     let user = match database::get_user_by_id(&state.db, &user_id).await {
-        Ok(Some(user)) => user,
-        Ok(None) => return Err(StatusCode::UNAUTHORIZED),
+        Ok(Some(user)) if user.deactivated_at.is_none() => user,
+        Ok(_) => return Err(StatusCode::UNAUTHORIZED),
         Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
     };
+    if !user.is_admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
 
     request.extensions_mut().insert(user);
+    request.extensions_mut().insert(CurrentSession(claims.sid));
+    Ok(next.run(request).await)
+}
+pub async fn instance_admin_middleware(request: Request, next: Next) -> Result<Response, StatusCode> {
+    match request.extensions().get::<User>() {
+        Some(user) if user.tenant_id.is_none() => Ok(next.run(request).await),
+        _ => Err(StatusCode::FORBIDDEN),
+    }
+}
+
+fn tokens_match(presented: &str, expected: &str) -> bool {
+    let presented = Sha256::digest(presented.as_bytes());
+    let expected = Sha256::digest(expected.as_bytes());
+    presented.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+pub async fn provisioning_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let expected = match &state.config.provisioning_token {
+        Some(token) => token,
+        None => return Err(StatusCode::NOT_FOUND),
+    };
+
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if !tokens_match(token, expected) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
     Ok(next.run(request).await)
-}
\ No newline at end of file
+}
+
+fn basic_challenge() -> Response {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(WWW_AUTHENTICATE, "Basic realm=\"local-drive\", charset=\"UTF-8\"")
+        .body(Body::empty())
+        .unwrap_or_default()
+}
+
+fn basic_credentials(header: &str) -> Option<(String, String)> {
+    let encoded = header.strip_prefix("Basic ")?.trim();
+    let decoded = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+    let (username, password) = String::from_utf8(decoded).ok()?.split_once(':').map(|(u, p)| (u.to_string(), p.to_string()))?;
+    Some((username, password))
+}
+
+fn basic_cache_key(username: &str, password: &str, password_hash: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in [username, password, password_hash] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hasher.finalize().into()
+}
+
+async fn verify_basic_credentials(
+    state: &AppState,
+    username: &str,
+    password: &str,
+    ip: &str,
+) -> Result<Option<User>, StatusCode> {
+    let keys = [login_limit::user_key(username), login_limit::ip_key(ip)];
+    if database::get_login_lockout(&state.db, &keys)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some()
+    {
+        return Err(StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    let user = database::get_user_by_username(&state.db, username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = match user {
+        Some(user) => user,
+        None => {
+            alerts::record_login_failure();
+            let _ = login_limit::record_failure(&state.db, &state.config, username, ip).await;
+            return Ok(None);
+        }
+    };
+
+    let cache_key = basic_cache_key(username, password, &user.password_hash);
+    let cached = BASIC_AUTH_CACHE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&cache_key)
+        .is_some_and(|verified_at| verified_at.elapsed() < BASIC_AUTH_CACHE_TTL);
+
+    if !cached {
+        if !verify_password(password, &user.password_hash).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)? {
+            alerts::record_login_failure();
+            let _ = login_limit::record_failure(&state.db, &state.config, username, ip).await;
+            return Ok(None);
+        }
+        let _ = database::clear_login_failures(&state.db, &keys[0]).await;
+
+        let mut cache = BASIC_AUTH_CACHE.lock().unwrap_or_else(|e| e.into_inner());
+        cache.retain(|_, verified_at| verified_at.elapsed() < BASIC_AUTH_CACHE_TTL);
+        cache.insert(cache_key, Instant::now());
+    }
+
+    Ok(Some(user).filter(|user| user.deactivated_at.is_none()))
+}
+
+// WebDAV clients cannot obtain a JWT, so /dav also accepts the account password over HTTP
+// Basic; a Bearer token still works for scripts.
+pub async fn webdav_auth_middleware(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    if !state.config.webdav_enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let auth_header = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .map(str::to_string);
+
+    let (username, password) = match auth_header.as_deref() {
+        Some(header) if header.starts_with("Bearer ") => return auth_middleware(State(state), request, next).await,
+        Some(header) => match basic_credentials(header) {
+            Some(credentials) => credentials,
+            None => return Ok(basic_challenge()),
+        },
+        None => return Ok(basic_challenge()),
+    };
+
+    let ip = crate::client_ip(&state.config, request.headers(), &addr);
+    match verify_basic_credentials(&state, &username, &password, &ip).await? {
+        Some(user) => {
+            request.extensions_mut().insert(user);
+            Ok(next.run(request).await)
+        }
+        None => Ok(basic_challenge()),
+    }
+}
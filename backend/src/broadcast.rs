@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::mailer::{self, Mailer};
+use crate::models::Broadcast;
+use crate::{database, AppState};
+
+const BATCH_SIZE: i64 = 50;
+const BATCH_PAUSE: Duration = Duration::from_secs(2);
+
+pub async fn process(state: &AppState) {
+    let mailer = match Mailer::from_config(&state.config) {
+        Ok(Some(mailer)) => mailer,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Failed to set up the mailer: {}", e);
+            return;
+        }
+    };
+
+    let mut broadcasts: HashMap<Uuid, Broadcast> = HashMap::new();
+
+    loop {
+        let batch = match database::claim_broadcast_recipients(&state.db, BATCH_SIZE).await {
+            Ok(batch) => batch,
+            Err(e) => {
+                error!("Failed to claim broadcast recipients: {}", e);
+                break;
+            }
+        };
+        if batch.is_empty() {
+            break;
+        }
+
+        for recipient in &batch {
+            if !broadcasts.contains_key(&recipient.broadcast_id) {
+                match database::get_broadcast(&state.db, &recipient.broadcast_id).await {
+                    Ok(Some(broadcast)) => {
+                        broadcasts.insert(broadcast.id, broadcast);
+                    }
+                    Ok(None) => continue,
+                    Err(e) => {
+                        error!("Failed to load broadcast {}: {}", recipient.broadcast_id, e);
+                        continue;
+                    }
+                }
+            }
+            let broadcast = &broadcasts[&recipient.broadcast_id];
+
+            let subject = mailer::render(&broadcast.subject, &recipient.username, &recipient.email);
+            let body = mailer::render(&broadcast.body, &recipient.username, &recipient.email);
+            let error = match mailer.send(&recipient.email, &subject, &body).await {
+                Ok(()) => None,
+                Err(e) => {
+                    warn!("Failed to send broadcast {} to {}: {}", broadcast.id, recipient.email, e);
+                    Some(e.to_string())
+                }
+            };
+
+            if let Err(e) = database::finish_broadcast_recipient(
+                &state.db,
+                &recipient.broadcast_id,
+                &recipient.user_id,
+                error.as_deref(),
+            )
+            .await
+            {
+                error!("Failed to record delivery of broadcast {}: {}", recipient.broadcast_id, e);
+            }
+        }
+
+        if (batch.len() as i64) < BATCH_SIZE {
+            break;
+        }
+        tokio::time::sleep(BATCH_PAUSE).await;
+    }
+
+    match database::complete_finished_broadcasts(&state.db).await {
+        Ok(completed) => {
+            for broadcast in completed {
+                info!(
+                    "Broadcast '{}' finished: {} sent, {} failed",
+                    broadcast.subject, broadcast.sent_count, broadcast.failed_count
+                );
+            }
+        }
+        Err(e) => error!("Failed to complete broadcasts: {}", e),
+    }
+}
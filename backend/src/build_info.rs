@@ -0,0 +1,33 @@
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use crate::models::ScheduledJob;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const BUILD_HASH: &str = env!("LOCALDRIVE_BUILD_HASH");
+
+pub struct Runtime {
+    pub started_at: DateTime<Utc>,
+    jobs: Mutex<Vec<ScheduledJob>>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Self { started_at: Utc::now(), jobs: Mutex::new(Vec::new()) }
+    }
+}
+
+impl Runtime {
+    pub fn schedule(&self, name: &'static str, schedule: &'static str) -> &'static str {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.push(ScheduledJob { name: name.to_string(), schedule: schedule.to_string() });
+        schedule
+    }
+
+    pub fn jobs(&self) -> Vec<ScheduledJob> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn uptime_seconds(&self) -> i64 {
+        (Utc::now() - self.started_at).num_seconds()
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::{DateTime, Datelike, Utc};
+use uuid::Uuid;
+use crate::{database, AppState};
+
+pub const DEFAULT_TEMPLATE: &str = "/Camera/{yyyy}/{mm}";
+const MAX_TEMPLATE_LENGTH: usize = 255;
+const MAX_DEPTH: usize = 8;
+const PLACEHOLDERS: &[&str] = &["yyyy", "yy", "mm", "dd"];
+
+pub fn normalize_template(template: &str) -> Option<String> {
+    let segments: Vec<&str> = template.trim().split('/').filter(|segment| !segment.is_empty()).collect();
+    if template.len() > MAX_TEMPLATE_LENGTH || segments.is_empty() || segments.len() > MAX_DEPTH {
+        return None;
+    }
+
+    for segment in &segments {
+        if segment.trim().is_empty() || *segment == "." || *segment == ".." || segment.contains('\\') {
+            return None;
+        }
+        let mut rest = *segment;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..].find('}')? + start;
+            if !PLACEHOLDERS.contains(&&rest[start + 1..end]) {
+                return None;
+            }
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return None;
+        }
+    }
+
+    Some(format!("/{}", segments.join("/")))
+}
+
+pub fn render(template: &str, taken_at: &DateTime<Utc>) -> Vec<String> {
+    template
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            segment
+                .replace("{yyyy}", &format!("{:04}", taken_at.year()))
+                .replace("{yy}", &format!("{:02}", taken_at.year() % 100))
+                .replace("{mm}", &format!("{:02}", taken_at.month()))
+                .replace("{dd}", &format!("{:02}", taken_at.day()))
+                .trim()
+                .to_string()
+        })
+        .collect()
+}
+
+pub async fn destination_folder(state: &AppState, user_id: &Uuid, taken_at: &DateTime<Utc>) -> anyhow::Result<Uuid> {
+    let template = database::get_camera_upload_template(&state.db, user_id)
+        .await?
+        .unwrap_or_else(|| DEFAULT_TEMPLATE.to_string());
+
+    let mut parent_id = None;
+    for name in render(&template, taken_at) {
+        let (folder, _) = database::get_or_create_folder(&state.db, user_id, parent_id.as_ref(), &name).await?;
+        parent_id = Some(folder.id);
+    }
+
+    parent_id.ok_or_else(|| anyhow::anyhow!("camera upload template {} has no folders", template))
+}
@@ -0,0 +1,180 @@
+use std::time::Duration;
+use serde::Serialize;
+use tracing::warn;
+use crate::AppState;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const TELEGRAM_API_URL: &str = "https://api.telegram.org";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationEvent {
+    Alert,
+    ShareCreated,
+    ShareDownload,
+    Test,
+}
+
+impl NotificationEvent {
+    pub const ALL: &'static [NotificationEvent] =
+        &[NotificationEvent::Alert, NotificationEvent::ShareCreated, NotificationEvent::ShareDownload];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEvent::Alert => "alert",
+            NotificationEvent::ShareCreated => "share_created",
+            NotificationEvent::ShareDownload => "share_download",
+            NotificationEvent::Test => "test",
+        }
+    }
+
+    pub fn parse_list(value: &str) -> anyhow::Result<Vec<Self>> {
+        if value.trim() == "all" {
+            return Ok(Self::ALL.to_vec());
+        }
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                Self::ALL
+                    .iter()
+                    .copied()
+                    .find(|event| event.as_str() == name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown notification event {}", name))
+            })
+            .collect()
+    }
+
+    fn urgent(&self) -> bool {
+        matches!(self, NotificationEvent::Alert)
+    }
+}
+
+#[derive(Clone)]
+pub enum ChannelKind {
+    Ntfy { url: String, token: Option<String> },
+    Gotify { url: String, token: String },
+    Telegram { bot_token: String, chat_id: String },
+}
+
+#[derive(Clone)]
+pub struct Channel {
+    pub kind: ChannelKind,
+    pub events: Vec<NotificationEvent>,
+}
+
+impl std::fmt::Debug for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Channel")
+            .field("kind", &self.name())
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelTestResult {
+    pub channel: &'static str,
+    pub events: Vec<&'static str>,
+    pub delivered: bool,
+    pub error: Option<String>,
+}
+
+impl Channel {
+    pub fn name(&self) -> &'static str {
+        match self.kind {
+            ChannelKind::Ntfy { .. } => "ntfy",
+            ChannelKind::Gotify { .. } => "gotify",
+            ChannelKind::Telegram { .. } => "telegram",
+        }
+    }
+
+    async fn send(&self, client: &reqwest::Client, event: NotificationEvent, title: &str, message: &str) -> anyhow::Result<()> {
+        let request = match &self.kind {
+            ChannelKind::Ntfy { url, token } => {
+                let request = client
+                    .post(url)
+                    .header("Title", title)
+                    .header("Tags", event.as_str())
+                    .header("Priority", if event.urgent() { "high" } else { "default" })
+                    .body(message.to_string());
+                match token {
+                    Some(token) => request.bearer_auth(token),
+                    None => request,
+                }
+            }
+            ChannelKind::Gotify { url, token } => client
+                .post(format!("{}/message", url.trim_end_matches('/')))
+                .header("X-Gotify-Key", token)
+                .json(&serde_json::json!({
+                    "title": title,
+                    "message": message,
+                    "priority": if event.urgent() { 8 } else { 5 },
+                })),
+            ChannelKind::Telegram { bot_token, chat_id } => client
+                .post(format!("{}/bot{}/sendMessage", TELEGRAM_API_URL, bot_token))
+                .json(&serde_json::json!({
+                    "chat_id": chat_id,
+                    "text": format!("{}\n\n{}", title, message),
+                    "disable_web_page_preview": true,
+                })),
+        };
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} answered {}", self.name(), response.status());
+        }
+        Ok(())
+    }
+}
+
+fn client() -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder().timeout(SEND_TIMEOUT).build()
+}
+
+pub fn send(state: &AppState, event: NotificationEvent, title: &str, message: &str) {
+    let channels: Vec<Channel> = state
+        .config
+        .notification_channels
+        .iter()
+        .filter(|channel| channel.events.contains(&event))
+        .cloned()
+        .collect();
+    if channels.is_empty() {
+        return;
+    }
+
+    let (title, message) = (title.to_string(), message.to_string());
+    tokio::spawn(async move {
+        let client = match client() {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Failed to set up the notification client: {}", e);
+                return;
+            }
+        };
+        for channel in &channels {
+            if let Err(e) = channel.send(&client, event, &title, &message).await {
+                warn!("Failed to send {} notification via {}: {:#}", event.as_str(), channel.name(), e);
+            }
+        }
+    });
+}
+
+pub async fn test(state: &AppState) -> anyhow::Result<Vec<ChannelTestResult>> {
+    let client = client()?;
+    let mut results = Vec::new();
+    for channel in &state.config.notification_channels {
+        let sent = channel
+            .send(&client, NotificationEvent::Test, "[Local Drive] Test notification", "Notifications from Local Drive reach this channel.")
+            .await;
+        results.push(ChannelTestResult {
+            channel: channel.name(),
+            events: channel.events.iter().map(NotificationEvent::as_str).collect(),
+            delivered: sent.is_ok(),
+            error: sent.err().map(|e| format!("{:#}", e)),
+        });
+    }
+
+    Ok(results)
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+use crate::models::{ChaosFaults, UpdateChaosFaults};
+
+const MAX_DB_DELAY_MS: u64 = 60_000;
+
+static FAULTS: Mutex<ChaosFaults> = Mutex::new(ChaosFaults {
+    fail_chunk_writes: 0,
+    db_delay_ms: 0,
+    disk_full: false,
+});
+
+fn lock() -> std::sync::MutexGuard<'static, ChaosFaults> {
+    FAULTS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn faults() -> ChaosFaults {
+    lock().clone()
+}
+
+pub fn update(update: UpdateChaosFaults) -> ChaosFaults {
+    let mut faults = lock();
+    if let Some(count) = update.fail_chunk_writes {
+        faults.fail_chunk_writes = count;
+    }
+    if let Some(delay) = update.db_delay_ms {
+        faults.db_delay_ms = delay.min(MAX_DB_DELAY_MS);
+    }
+    if let Some(disk_full) = update.disk_full {
+        faults.disk_full = disk_full;
+    }
+    warn!(
+        fail_chunk_writes = faults.fail_chunk_writes,
+        db_delay_ms = faults.db_delay_ms,
+        disk_full = faults.disk_full,
+        "Updated injected faults"
+    );
+    faults.clone()
+}
+
+pub fn reset() -> ChaosFaults {
+    update(UpdateChaosFaults {
+        fail_chunk_writes: Some(0),
+        db_delay_ms: Some(0),
+        disk_full: Some(false),
+    })
+}
+
+pub fn take_chunk_write_failure() -> bool {
+    let mut faults = lock();
+    if faults.fail_chunk_writes == 0 {
+        return false;
+    }
+    faults.fail_chunk_writes -= 1;
+    true
+}
+
+pub fn disk_full() -> bool {
+    lock().disk_full
+}
+
+pub async fn delay_query() {
+    let delay = lock().db_delay_ms;
+    if delay > 0 {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+}
@@ -0,0 +1,122 @@
+use axum::http::{HeaderMap, StatusCode};
+use crate::config::Config;
+
+const KIB: i64 = 1024;
+const MIB: i64 = 1024 * KIB;
+
+pub const MIN_CHUNK_SIZE: i64 = 256 * KIB;
+pub const MAX_CHUNK_SIZE: i64 = 64 * MIB;
+const DEFAULT_CHUNK_SIZE: i64 = 8 * MIB;
+const SAVE_DATA_CHUNK_SIZE: i64 = MIB;
+const CHUNK_ALIGNMENT: i64 = 256 * KIB;
+const TARGET_CHUNK_SECONDS: f64 = 4.0;
+const BUSY_ACTIVE_UPLOADS: i64 = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub min_chunk_size: i64,
+    pub max_chunk_size: i64,
+    pub max_chunks: i64,
+    pub max_total_size: Option<i64>,
+}
+
+impl Limits {
+    pub fn from_config(config: &Config) -> Self {
+        Limits {
+            min_chunk_size: config.chunk_size_min,
+            max_chunk_size: config.chunk_size_max,
+            max_chunks: config.chunked_upload_max_chunks,
+            max_total_size: config.chunked_upload_max_size,
+        }
+    }
+
+    pub fn check_total_size(&self, total_size: i64) -> Result<(), StatusCode> {
+        if total_size < 0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        if self.max_total_size.is_some_and(|max| total_size > max)
+            || total_size > self.max_chunk_size.saturating_mul(self.max_chunks)
+        {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        Ok(())
+    }
+
+    pub fn total_chunks(&self, total_size: i64, chunk_size: i64) -> Result<i32, StatusCode> {
+        let single_chunk = chunk_size >= total_size;
+        if chunk_size > self.max_chunk_size || (chunk_size < self.min_chunk_size && !single_chunk) || chunk_size <= 0 {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        let total_chunks = (total_size + chunk_size - 1) / chunk_size;
+        if total_chunks > self.max_chunks {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(total_chunks as i32)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Hints {
+    pub connection_type: Option<String>,
+    pub downlink_mbps: Option<f64>,
+    pub save_data: bool,
+}
+
+impl Hints {
+    pub fn from_request(connection_type: Option<&str>, downlink_mbps: Option<f64>, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.trim().trim_matches('"').to_ascii_lowercase())
+        };
+
+        Hints {
+            connection_type: connection_type
+                .map(|value| value.trim().to_ascii_lowercase())
+                .or_else(|| header("ect")),
+            downlink_mbps: downlink_mbps
+                .or_else(|| header("downlink").and_then(|value| value.parse().ok()))
+                .filter(|mbps: &f64| mbps.is_finite() && *mbps > 0.0),
+            save_data: header("save-data").is_some_and(|value| value == "on"),
+        }
+    }
+}
+
+fn connection_chunk_size(connection_type: &str) -> Option<i64> {
+    match connection_type {
+        "slow-2g" | "2g" => Some(MIN_CHUNK_SIZE),
+        "3g" => Some(MIB),
+        "cellular" | "4g" => Some(4 * MIB),
+        "5g" | "wifi" => Some(8 * MIB),
+        "ethernet" | "lan" => Some(32 * MIB),
+        _ => None,
+    }
+}
+
+pub fn recommend(total_size: i64, hints: &Hints, rotational: bool, active_uploads: i64, limits: &Limits) -> i64 {
+    let mut size = match hints.downlink_mbps {
+        Some(mbps) => (mbps * 125_000.0 * TARGET_CHUNK_SECONDS) as i64,
+        None => hints
+            .connection_type
+            .as_deref()
+            .and_then(connection_chunk_size)
+            .unwrap_or(DEFAULT_CHUNK_SIZE),
+    };
+
+    if hints.save_data {
+        size = size.min(SAVE_DATA_CHUNK_SIZE);
+    }
+    if rotational {
+        size *= 2;
+    }
+    if active_uploads >= BUSY_ACTIVE_UPLOADS {
+        size /= 2;
+    }
+
+    size = size.max((total_size + limits.max_chunks - 1) / limits.max_chunks);
+    size = (size + CHUNK_ALIGNMENT - 1) / CHUNK_ALIGNMENT * CHUNK_ALIGNMENT;
+    size
+        .clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE)
+        .clamp(limits.min_chunk_size, limits.max_chunk_size)
+}
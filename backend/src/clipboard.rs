@@ -0,0 +1,151 @@
+use std::collections::HashSet;
+use uuid::Uuid;
+use crate::database::{self, NewFile, NewFolder};
+use crate::models::{FileInfo, Folder};
+use crate::operations::Progress;
+use crate::AppState;
+
+const BACKGROUND_COPY_FILES: usize = 100;
+const BACKGROUND_COPY_BYTES: i64 = 256 * 1024 * 1024;
+
+#[derive(Default)]
+pub struct CopyPlan {
+    pub folders: Vec<NewFolder>,
+    pub files: Vec<(FileInfo, Option<Uuid>, String)>,
+}
+
+impl CopyPlan {
+    pub fn total_bytes(&self) -> i64 {
+        self.files.iter().map(|(file, _, _)| file.file_size).sum()
+    }
+
+    pub fn should_run_in_background(&self) -> bool {
+        self.files.len() > BACKGROUND_COPY_FILES || self.total_bytes() > BACKGROUND_COPY_BYTES
+    }
+}
+
+fn name_key(name: &str, case_insensitive: bool) -> String {
+    if case_insensitive {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+fn unique_name(name: &str, taken: &HashSet<String>, case_insensitive: bool, split_extension: bool) -> String {
+    if !taken.contains(&name_key(name, case_insensitive)) {
+        return name.to_string();
+    }
+
+    let (stem, extension) = match name.rfind('.') {
+        Some(index) if split_extension && index > 0 => name.split_at(index),
+        _ => (name, ""),
+    };
+
+    (1..)
+        .map(|n| match n {
+            1 => format!("{} (copy){}", stem, extension),
+            n => format!("{} (copy {}){}", stem, n, extension),
+        })
+        .find(|candidate| !taken.contains(&name_key(candidate, case_insensitive)))
+        .unwrap_or_else(|| name.to_string())
+}
+
+pub async fn plan_copy(
+    state: &AppState,
+    user_id: &Uuid,
+    target_folder_id: Option<Uuid>,
+    files: Vec<FileInfo>,
+    folders: Vec<Folder>,
+) -> anyhow::Result<CopyPlan> {
+    let case_insensitive = state.config.case_insensitive_names;
+    let mut taken_folders: HashSet<String> = database::get_child_folders(&state.db, user_id, target_folder_id.as_ref())
+        .await?
+        .iter()
+        .map(|folder| name_key(&folder.name, case_insensitive))
+        .collect();
+    let mut taken_files: HashSet<String> = database::get_files_in_folder(&state.db, user_id, target_folder_id.as_ref())
+        .await?
+        .iter()
+        .map(|file| name_key(&file.original_filename, case_insensitive))
+        .collect();
+
+    let mut plan = CopyPlan::default();
+
+    for folder in folders {
+        let name = unique_name(&folder.name, &taken_folders, case_insensitive, false);
+        taken_folders.insert(name_key(&name, case_insensitive));
+
+        let copy_id = Uuid::new_v4();
+        plan.folders.push(NewFolder { id: copy_id, parent_id: target_folder_id, name });
+
+        let mut pending = vec![(folder.id, copy_id)];
+        while let Some((source_id, copy_id)) = pending.pop() {
+            for child in database::get_child_folders(&state.db, user_id, Some(&source_id)).await? {
+                let child_copy_id = Uuid::new_v4();
+                plan.folders.push(NewFolder { id: child_copy_id, parent_id: Some(copy_id), name: child.name });
+                pending.push((child.id, child_copy_id));
+            }
+
+            for file in database::get_files_in_folder(&state.db, user_id, Some(&source_id)).await? {
+                let name = file.original_filename.clone();
+                plan.files.push((file, Some(copy_id), name));
+            }
+        }
+    }
+
+    for file in files {
+        let name = unique_name(&file.original_filename, &taken_files, case_insensitive, true);
+        taken_files.insert(name_key(&name, case_insensitive));
+        plan.files.push((file, target_folder_id, name));
+    }
+
+    Ok(plan)
+}
+
+pub async fn execute_copy(
+    state: &AppState,
+    user_id: &Uuid,
+    plan: &CopyPlan,
+    progress: Option<&Progress>,
+) -> anyhow::Result<(Vec<Folder>, Vec<FileInfo>)> {
+    let mut copies = Vec::with_capacity(plan.files.len());
+    let mut copy_error = None;
+    let total = plan.files.len() as i64;
+
+    for (source, folder_id, name) in &plan.files {
+        let (file_path, encoding, owner_id, filename) = (source.file_path.clone(), source.encoding(), *user_id, source.original_filename.clone());
+        match state.file_storage.blocking(move |storage| storage.copy_file(&file_path, encoding, &owner_id, &filename)).await {
+            Ok(stored) => copies.push(NewFile {
+                folder_id: *folder_id,
+                original_filename: name.clone(),
+                mime_type: source.mime_type.as_deref(),
+                stored,
+            }),
+            Err(e) => {
+                copy_error = Some(e.context(format!("copying {}", source.id)));
+                break;
+            }
+        }
+
+        if let Some(progress) = progress {
+            if let Err(e) = progress.update(copies.len() as i64, Some(total)).await {
+                copy_error = Some(e);
+                break;
+            }
+        }
+    }
+
+    let result = match copy_error {
+        Some(e) => Err(e),
+        None => database::insert_copies(&state.db, user_id, &plan.folders, &copies).await,
+    };
+
+    if result.is_err() {
+        for copy in &copies {
+            let _ = state.file_storage.delete_file(&copy.stored.file_path);
+        }
+    }
+
+    result
+}
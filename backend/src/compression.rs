@@ -0,0 +1,302 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+pub const MAGIC: &[u8; 8] = b"\0LDZST1\n";
+pub const FRAME_SIZE: usize = 1024 * 1024;
+
+const TRAILER_LENGTH: u64 = 12;
+
+const COMPRESSIBLE_TYPES: &[&str] = &[
+    "application/json",
+    "application/ld+json",
+    "application/xml",
+    "application/javascript",
+    "application/x-javascript",
+    "application/ecmascript",
+    "application/sql",
+    "application/x-sh",
+    "application/x-tar",
+    "application/x-yaml",
+    "application/yaml",
+    "application/toml",
+    "application/rtf",
+    "application/postscript",
+    "application/x-ndjson",
+    "application/wasm",
+    "image/bmp",
+    "image/svg+xml",
+    "image/tiff",
+    "image/x-portable-pixmap",
+    "audio/wav",
+    "audio/x-wav",
+];
+
+pub fn is_compressible(mime_type: &str) -> bool {
+    let mime_type = mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    mime_type.starts_with("text/")
+        || mime_type.ends_with("+json")
+        || mime_type.ends_with("+xml")
+        || COMPRESSIBLE_TYPES.contains(&mime_type.as_str())
+}
+
+pub fn is_compressed(header: &[u8]) -> bool {
+    header.starts_with(MAGIC)
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+pub struct CompressWriter<W: Write> {
+    inner: W,
+    level: i32,
+    buffer: Vec<u8>,
+    frames: Vec<u32>,
+    length: u64,
+}
+
+impl<W: Write> CompressWriter<W> {
+    pub fn new(level: i32, mut inner: W) -> io::Result<Self> {
+        inner.write_all(MAGIC)?;
+        Ok(CompressWriter {
+            inner,
+            level,
+            buffer: Vec::with_capacity(FRAME_SIZE),
+            frames: Vec::new(),
+            length: 0,
+        })
+    }
+
+    fn compress_frame(&mut self) -> io::Result<()> {
+        let frame = zstd::bulk::compress(&self.buffer, self.level)?;
+        let frame_length = u32::try_from(frame.len()).map_err(|_| io::Error::other("compressed frame is too large"))?;
+        self.inner.write_all(&frame)?;
+        self.frames.push(frame_length);
+        self.length += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        if !self.buffer.is_empty() {
+            self.compress_frame()?;
+        }
+        let frame_count = u32::try_from(self.frames.len()).map_err(|_| io::Error::other("file is too large to compress"))?;
+        for frame in &self.frames {
+            self.inner.write_all(&frame.to_be_bytes())?;
+        }
+        self.inner.write_all(&self.length.to_be_bytes())?;
+        self.inner.write_all(&frame_count.to_be_bytes())?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for CompressWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let count = data.len().min(FRAME_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&data[..count]);
+        if self.buffer.len() == FRAME_SIZE {
+            self.compress_frame()?;
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct DecompressReader<R: Read + Seek> {
+    inner: R,
+    offsets: Vec<u64>,
+    length: u64,
+    position: u64,
+    frame: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> DecompressReader<R> {
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let total = inner.seek(SeekFrom::End(0))?;
+        let minimum = MAGIC.len() as u64 + TRAILER_LENGTH;
+        if total < minimum {
+            return Err(invalid_data("compressed file is truncated"));
+        }
+
+        let mut magic = [0u8; MAGIC.len()];
+        inner.seek(SeekFrom::Start(0))?;
+        inner.read_exact(&mut magic)?;
+        if !is_compressed(&magic) {
+            return Err(invalid_data("file is not compressed"));
+        }
+
+        let mut trailer = [0u8; TRAILER_LENGTH as usize];
+        inner.seek(SeekFrom::Start(total - TRAILER_LENGTH))?;
+        inner.read_exact(&mut trailer)?;
+        let (length, frame_count) = trailer.split_at(8);
+        let length = u64::from_be_bytes(length.try_into().unwrap_or_default());
+        let frame_count = u32::from_be_bytes(frame_count.try_into().unwrap_or_default()) as u64;
+
+        let table_length = frame_count * 4;
+        if total < minimum + table_length {
+            return Err(invalid_data("compressed file is truncated"));
+        }
+        let mut table = vec![0u8; table_length as usize];
+        inner.seek(SeekFrom::Start(total - TRAILER_LENGTH - table_length))?;
+        inner.read_exact(&mut table)?;
+
+        let mut offsets = Vec::with_capacity(frame_count as usize + 1);
+        let mut offset = MAGIC.len() as u64;
+        offsets.push(offset);
+        for size in table.chunks_exact(4) {
+            offset += u32::from_be_bytes([size[0], size[1], size[2], size[3]]) as u64;
+            offsets.push(offset);
+        }
+        if offset != total - TRAILER_LENGTH - table_length || length > frame_count * FRAME_SIZE as u64 {
+            return Err(invalid_data("compressed file is corrupted"));
+        }
+
+        Ok(DecompressReader {
+            inner,
+            offsets,
+            length,
+            position: 0,
+            frame: None,
+        })
+    }
+
+    fn load(&mut self, index: usize) -> io::Result<()> {
+        if matches!(&self.frame, Some((loaded, _)) if *loaded == index) {
+            return Ok(());
+        }
+
+        let start = self.offsets[index];
+        let mut compressed = vec![0u8; (self.offsets[index + 1] - start) as usize];
+        self.inner.seek(SeekFrom::Start(start))?;
+        self.inner.read_exact(&mut compressed)?;
+
+        let plain = zstd::bulk::decompress(&compressed, FRAME_SIZE)
+            .map_err(|_| invalid_data("compressed file is corrupted"))?;
+        self.frame = Some((index, plain));
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for DecompressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.length {
+            return Ok(0);
+        }
+
+        let index = (self.position / FRAME_SIZE as u64) as usize;
+        self.load(index)?;
+        let plain = match &self.frame {
+            Some((_, plain)) => plain,
+            None => return Ok(0),
+        };
+        let start = (self.position % FRAME_SIZE as u64) as usize;
+        let count = buf.len().min(plain.len().saturating_sub(start));
+        if count == 0 {
+            return Err(invalid_data("compressed file is corrupted"));
+        }
+        buf[..count].copy_from_slice(&plain[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecompressReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample(length: usize) -> Vec<u8> {
+        (0..length).map(|i| b"local drive "[i % 12]).collect()
+    }
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut writer = CompressWriter::new(3, Vec::new()).unwrap();
+        for piece in data.chunks(100_000) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trips_across_frame_boundaries() {
+        for length in [0, 1, FRAME_SIZE, FRAME_SIZE + 1, 2 * FRAME_SIZE + 500] {
+            let data = sample(length);
+            let compressed = compress(&data);
+            assert!(is_compressed(&compressed));
+
+            let mut plain = Vec::new();
+            DecompressReader::new(Cursor::new(compressed)).unwrap().read_to_end(&mut plain).unwrap();
+            assert_eq!(plain, data, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn compresses_repetitive_data() {
+        let data = sample(FRAME_SIZE);
+        assert!(compress(&data).len() < data.len() / 10);
+    }
+
+    #[test]
+    fn seeks_across_frames() {
+        let data = sample(2 * FRAME_SIZE + 500);
+        let mut reader = DecompressReader::new(Cursor::new(compress(&data))).unwrap();
+
+        let mut buffer = vec![0u8; 1000];
+        reader.seek(SeekFrom::Start(FRAME_SIZE as u64 - 500)).unwrap();
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, &data[FRAME_SIZE - 500..FRAME_SIZE + 500]);
+
+        reader.seek(SeekFrom::End(-10)).unwrap();
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 10..]);
+    }
+
+    #[test]
+    fn rejects_plain_and_truncated_files() {
+        let error = DecompressReader::new(Cursor::new(sample(100))).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut compressed = compress(&sample(1000));
+        compressed.truncate(compressed.len() - 1);
+        let error = DecompressReader::new(Cursor::new(compressed)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn detects_corrupted_frames() {
+        let mut compressed = compress(&sample(1000));
+        for byte in &mut compressed[MAGIC.len()..MAGIC.len() + 4] {
+            *byte ^= 0xff;
+        }
+
+        let mut plain = Vec::new();
+        let error = DecompressReader::new(Cursor::new(compressed)).unwrap().read_to_end(&mut plain).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn picks_compressible_types() {
+        assert!(is_compressible("text/plain; charset=utf-8"));
+        assert!(is_compressible("Application/JSON"));
+        assert!(is_compressible("application/vnd.api+json"));
+        assert!(!is_compressible("image/jpeg"));
+        assert!(!is_compressible("application/zip"));
+    }
+}
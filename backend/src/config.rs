@@ -1,11 +1,165 @@
 use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use crate::channels::{Channel, ChannelKind, NotificationEvent};
+use crate::encryption::StorageKey;
+use crate::s3::{S3Config, S3Placement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RiskyContentPolicy {
+    ForceDownload,
+    SanitizeSvg,
+    Allow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SchemaDriftPolicy {
+    Refuse,
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    StartTls,
+    Implicit,
+    None,
+}
+
+/// A reverse proxy whose `X-Forwarded-For` and `X-Real-IP` headers are
+/// believed, given as a single address or a CIDR range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedProxy {
+    fn parse(value: &str) -> Option<Self> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network: IpAddr = address.parse().ok()?;
+        let max = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().ok().filter(|p| *p <= max)?,
+            None => max,
+        };
+        Some(Self { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            IpAddr::V4(_) => ip,
+        };
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub database_url: String,
     pub storage_paths: Vec<String>,
+    pub read_only_storage_paths: Vec<String>,
+    pub replication_factor: usize,
+    pub external_mount_roots: Vec<String>,
+    pub smbclient_path: String,
     pub port: u16,
+    pub admin_listen_addr: Option<SocketAddr>,
+    pub s3_gateway_listen_addr: Option<SocketAddr>,
+    pub trusted_proxies: Vec<TrustedProxy>,
     pub jwt_secret: String,
+    pub dev_mode: bool,
+    pub oidc_issuer_url: Option<String>,
+    pub oidc_client_id: Option<String>,
+    pub oidc_client_secret: Option<String>,
+    pub oidc_redirect_url: Option<String>,
+    pub oidc_scopes: String,
+    pub oidc_post_login_redirect: String,
+    pub oidc_link_by_email: bool,
+    pub webauthn_rp_id: String,
+    pub webauthn_rp_name: String,
+    pub webauthn_origin: String,
+    pub login_max_attempts: i32,
+    pub login_max_attempts_per_ip: i32,
+    pub login_lockout_seconds: i64,
+    pub login_lockout_max_seconds: i64,
+    pub jwt_expiry_minutes: i64,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub quota_grace_period_days: i64,
+    pub smtp_host: Option<String>,
+    pub smtp_port: u16,
+    pub smtp_tls: SmtpTls,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub smtp_from: String,
+    pub clamd_address: Option<String>,
+    pub startup_self_check: bool,
+    pub content_security_policy: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub risky_content_policy: RiskyContentPolicy,
+    pub trash_retention_days: Option<i64>,
+    pub trash_size_limit: Option<i64>,
+    pub import_path: Option<String>,
+    pub remote_fetch_max_size: i64,
+    pub remote_fetch_allowed_types: Vec<String>,
+    pub remote_fetch_allow_private: bool,
+    pub provisioning_token: Option<String>,
+    pub content_indexing: bool,
+    pub content_index_max_size: i64,
+    pub pdftotext_path: String,
+    pub video_metadata: bool,
+    pub ffmpeg_path: String,
+    pub ffprobe_path: String,
+    pub printing_enabled: bool,
+    pub lp_path: String,
+    pub printer: Option<String>,
+    pub smart_enabled: bool,
+    pub smartctl_path: String,
+    pub disk_temperature_warning: i64,
+    pub archive_part_max_size: i64,
+    pub chunk_size_min: i64,
+    pub chunk_size_max: i64,
+    pub chunked_upload_max_chunks: i64,
+    pub chunked_upload_max_size: Option<i64>,
+    pub upload_session_ttl_hours: i64,
+    pub alerts_enabled: bool,
+    pub alert_disk_percent: u8,
+    pub alert_failed_logins_per_minute: u64,
+    pub alert_queue_depth: i64,
+    pub notification_channels: Vec<Channel>,
+    pub scrub_enabled: bool,
+    pub scrub_max_bytes: i64,
+    pub scrub_nightly: bool,
+    pub scrub_bytes_per_second: u64,
+    pub orphan_gc_enabled: bool,
+    pub orphan_gc_grace_hours: u64,
+    pub orphan_gc_delete: bool,
+    pub download_audit_visible: bool,
+    pub rclone_compat: bool,
+    pub webdav_enabled: bool,
+    pub case_insensitive_names: bool,
+    pub torrent_min_size: u64,
+    pub monthly_egress_limit: Option<i64>,
+    pub torrent_trackers: Vec<String>,
+    pub storage_encryption_key: Option<StorageKey>,
+    pub compression_enabled: bool,
+    pub compression_level: i32,
+    pub s3: Option<S3Config>,
+    pub schema_drift_policy: SchemaDriftPolicy,
 }
 
 impl Config {
@@ -21,19 +175,651 @@ impl Config {
             .map(|s| s.trim().to_string())
             .collect();
         
+        let read_only_storage_paths: Vec<String> = env::var("READ_ONLY_STORAGE_PATHS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        
+        let replication_factor = env::var("REPLICATION_FACTOR")
+            .unwrap_or_else(|_| "1".to_string())
+            .parse::<usize>()
+            .ok()
+            .filter(|factor| *factor >= 1)
+            .ok_or_else(|| anyhow::anyhow!("REPLICATION_FACTOR must be a whole number of at least 1"))?;
+        
+        let external_mount_roots: Vec<String> = env::var("EXTERNAL_MOUNT_ROOTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        
+        let smbclient_path = env::var("SMBCLIENT_PATH")
+            .unwrap_or_else(|_| "smbclient".to_string());
+        
         let port = env::var("PORT")
             .unwrap_or_else(|_| "3001".to_string())
             .parse::<u16>()
             .unwrap_or(3001);
         
-        let jwt_secret = env::var("JWT_SECRET")
-            .unwrap_or_else(|_| "your-secret-key".to_string());
+        let admin_listen_addr = match env::var("ADMIN_LISTEN_ADDR") {
+            Ok(value) if !value.trim().is_empty() => Some(value.trim().parse::<SocketAddr>().map_err(|_| {
+                anyhow::anyhow!("ADMIN_LISTEN_ADDR must be an address and port such as 127.0.0.1:3002, got {}", value)
+            })?),
+            _ => None,
+        };
+        
+        let s3_gateway_listen_addr = match env::var("S3_GATEWAY_LISTEN_ADDR") {
+            Ok(value) if !value.trim().is_empty() => Some(value.trim().parse::<SocketAddr>().map_err(|_| {
+                anyhow::anyhow!("S3_GATEWAY_LISTEN_ADDR must be an address and port such as 0.0.0.0:9000, got {}", value)
+            })?),
+            _ => None,
+        };
+        
+        let trusted_proxies = env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| TrustedProxy::parse(s).ok_or_else(|| {
+                anyhow::anyhow!("TRUSTED_PROXIES entries must be IP addresses or CIDR ranges such as 10.0.0.0/8, got {}", s)
+            }))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        
+        let jwt_secret = read_jwt_secret()?;
+        
+        let dev_mode = env::var("DEV_MODE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let jwt_expiry_minutes = env::var("JWT_EXPIRY_MINUTES")
+            .unwrap_or_else(|_| "1440".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|minutes| *minutes > 0)
+            .unwrap_or(1440);
+        
+        let jwt_issuer = env::var("JWT_ISSUER").ok().filter(|s| !s.is_empty());
+        
+        let jwt_audience = env::var("JWT_AUDIENCE").ok().filter(|s| !s.is_empty());
+        
+        let oidc_issuer_url = env::var("OIDC_ISSUER_URL").ok().filter(|s| !s.is_empty());
+        
+        let oidc_client_id = env::var("OIDC_CLIENT_ID").ok().filter(|s| !s.is_empty());
+        
+        let oidc_client_secret = env::var("OIDC_CLIENT_SECRET").ok().filter(|s| !s.is_empty());
+        
+        let oidc_redirect_url = env::var("OIDC_REDIRECT_URL").ok().filter(|s| !s.is_empty());
+        
+        let oidc_scopes = env::var("OIDC_SCOPES")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "openid email profile".to_string());
+        
+        let oidc_post_login_redirect = env::var("OIDC_POST_LOGIN_REDIRECT")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "http://localhost:3000/login/oidc".to_string());
+        
+        let oidc_link_by_email = env::var("OIDC_LINK_BY_EMAIL")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let webauthn_rp_id = env::var("WEBAUTHN_RP_ID")
+            .unwrap_or_else(|_| "localhost".to_string());
+
+        let webauthn_rp_name = env::var("WEBAUTHN_RP_NAME")
+            .unwrap_or_else(|_| "Local Drive".to_string());
+
+        let webauthn_origin = env::var("WEBAUTHN_ORIGIN")
+            .unwrap_or_else(|_| "http://localhost:3000".to_string())
+            .trim_end_matches('/')
+            .to_string();
+        
+        let login_max_attempts = env::var("LOGIN_MAX_ATTEMPTS")
+            .unwrap_or_else(|_| "5".to_string())
+            .parse::<i32>()
+            .ok()
+            .filter(|attempts| *attempts > 0)
+            .unwrap_or(5);
+        
+        let login_max_attempts_per_ip = env::var("LOGIN_MAX_ATTEMPTS_PER_IP")
+            .unwrap_or_else(|_| "20".to_string())
+            .parse::<i32>()
+            .ok()
+            .filter(|attempts| *attempts > 0)
+            .unwrap_or(20);
+        
+        let login_lockout_seconds = env::var("LOGIN_LOCKOUT_SECONDS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|seconds| *seconds > 0)
+            .unwrap_or(30);
+        
+        let login_lockout_max_seconds = env::var("LOGIN_LOCKOUT_MAX_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<i64>()
+            .unwrap_or(3600)
+            .max(login_lockout_seconds);
+        
+        let quota_grace_period_days = env::var("QUOTA_GRACE_PERIOD_DAYS")
+            .unwrap_or_else(|_| "7".to_string())
+            .parse::<i64>()
+            .unwrap_or(7);
+        
+        let smtp_host = env::var("SMTP_HOST").ok().filter(|s| !s.is_empty());
+        
+        let smtp_port = env::var("SMTP_PORT")
+            .unwrap_or_else(|_| "587".to_string())
+            .parse::<u16>()
+            .unwrap_or(587);
+        
+        let smtp_tls = match env::var("SMTP_TLS").as_deref() {
+            Ok("tls") => SmtpTls::Implicit,
+            Ok("none") => SmtpTls::None,
+            _ => SmtpTls::StartTls,
+        };
+        
+        let smtp_username = env::var("SMTP_USERNAME").ok().filter(|s| !s.is_empty());
+        
+        let smtp_password = env::var("SMTP_PASSWORD").ok().filter(|s| !s.is_empty());
+        
+        let smtp_from = env::var("SMTP_FROM")
+            .unwrap_or_else(|_| "Local Drive <noreply@localhost>".to_string());
+        
+        let clamd_address = env::var("CLAMD_ADDRESS").ok().filter(|s| !s.is_empty());
+        
+        let startup_self_check = env::var("STARTUP_SELF_CHECK")
+            .map(|v| v != "false" && v != "0")
+            .unwrap_or(true);
+        
+        let content_security_policy = optional_header_value(
+            "SECURITY_CSP",
+            "default-src 'none'; img-src 'self' data:; media-src 'self'; style-src 'unsafe-inline'; frame-ancestors 'none'; sandbox",
+        );
+        
+        let frame_options = optional_header_value("SECURITY_FRAME_OPTIONS", "DENY");
+        
+        let referrer_policy = optional_header_value("SECURITY_REFERRER_POLICY", "no-referrer");
+        
+        let risky_content_policy = match env::var("RISKY_CONTENT_POLICY").as_deref() {
+            Ok("allow") => RiskyContentPolicy::Allow,
+            Ok("sanitize-svg") => RiskyContentPolicy::SanitizeSvg,
+            _ => RiskyContentPolicy::ForceDownload,
+        };
+        
+        let trash_retention_days = env::var("TRASH_RETENTION_DAYS")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|days| *days > 0);
+        
+        let trash_size_limit = env::var("TRASH_SIZE_LIMIT")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|limit| *limit > 0);
+        
+        let import_path = env::var("IMPORT_PATH").ok().filter(|s| !s.is_empty());
+        
+        let remote_fetch_max_size = env::var("REMOTE_FETCH_MAX_SIZE")
+            .unwrap_or_else(|_| "5368709120".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(5 * 1024 * 1024 * 1024);
+        
+        let remote_fetch_allowed_types: Vec<String> = env::var("REMOTE_FETCH_ALLOWED_TYPES")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_ascii_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect();
+        
+        let remote_fetch_allow_private = env::var("REMOTE_FETCH_ALLOW_PRIVATE")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let provisioning_token = env::var("PROVISIONING_TOKEN").ok().filter(|s| !s.is_empty());
+        
+        let content_indexing = env::var("CONTENT_INDEXING")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .unwrap_or(true);
+        
+        let content_index_max_size = env::var("CONTENT_INDEX_MAX_SIZE")
+            .unwrap_or_else(|_| "52428800".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(50 * 1024 * 1024);
+        
+        let pdftotext_path = env::var("PDFTOTEXT_PATH")
+            .unwrap_or_else(|_| "pdftotext".to_string());
+        
+        let video_metadata = env::var("VIDEO_METADATA")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let ffmpeg_path = env::var("FFMPEG_PATH")
+            .unwrap_or_else(|_| "ffmpeg".to_string());
+        
+        let ffprobe_path = env::var("FFPROBE_PATH")
+            .unwrap_or_else(|_| "ffprobe".to_string());
+        
+        let printing_enabled = env::var("PRINTING_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let lp_path = env::var("LP_PATH")
+            .unwrap_or_else(|_| "lp".to_string());
+        
+        let printer = env::var("PRINTER").ok().filter(|s| !s.is_empty());
+        
+        let smart_enabled = env::var("SMART_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let smartctl_path = env::var("SMARTCTL_PATH")
+            .unwrap_or_else(|_| "smartctl".to_string());
+        
+        let disk_temperature_warning = env::var("DISK_TEMPERATURE_WARNING")
+            .unwrap_or_else(|_| "55".to_string())
+            .parse()
+            .unwrap_or(55);
+        
+        let archive_part_max_size = env::var("ARCHIVE_PART_MAX_SIZE")
+            .unwrap_or_else(|_| "4294967296".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(4 * 1024 * 1024 * 1024);
+        
+        let chunk_size_min = env::var("CHUNK_SIZE_MIN")
+            .unwrap_or_else(|_| "65536".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|size| *size > 0)
+            .unwrap_or(64 * 1024);
+        
+        let chunk_size_max = env::var("CHUNK_SIZE_MAX")
+            .unwrap_or_else(|_| "67108864".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|size| *size > 0)
+            .unwrap_or(64 * 1024 * 1024);
+        
+        if chunk_size_min > chunk_size_max {
+            anyhow::bail!("CHUNK_SIZE_MIN ({}) is larger than CHUNK_SIZE_MAX ({})", chunk_size_min, chunk_size_max);
+        }
+        
+        let chunked_upload_max_chunks = env::var("CHUNKED_UPLOAD_MAX_CHUNKS")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|chunks| *chunks > 0)
+            .unwrap_or(10_000);
+        
+        let chunked_upload_max_size = env::var("CHUNKED_UPLOAD_MAX_SIZE")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|size| *size > 0);
+        
+        let upload_session_ttl_hours = env::var("UPLOAD_SESSION_TTL_HOURS")
+            .unwrap_or_else(|_| "24".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|hours| *hours > 0 && *hours <= 24 * 365)
+            .unwrap_or(24);
+        
+        let alerts_enabled = env::var("ALERTS_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let alert_disk_percent = env::var("ALERT_DISK_PERCENT")
+            .unwrap_or_else(|_| "90".to_string())
+            .parse::<u8>()
+            .ok()
+            .filter(|percent| (1..=100).contains(percent))
+            .unwrap_or(90);
+        
+        let alert_failed_logins_per_minute = env::var("ALERT_FAILED_LOGINS_PER_MINUTE")
+            .unwrap_or_else(|_| "30".to_string())
+            .parse::<u64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(30);
+        
+        let alert_queue_depth = env::var("ALERT_QUEUE_DEPTH")
+            .unwrap_or_else(|_| "500".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(500);
+        
+        let notification_channels = read_notification_channels()?;
+        
+        let scrub_enabled = env::var("SCRUB_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let scrub_max_bytes = env::var("SCRUB_MAX_BYTES")
+            .unwrap_or_else(|_| "107374182400".to_string())
+            .parse::<i64>()
+            .ok()
+            .filter(|limit| *limit > 0)
+            .unwrap_or(100 * 1024 * 1024 * 1024);
+        
+        let scrub_nightly = match env::var("SCRUB_INTERVAL").as_deref() {
+            Ok("nightly") => true,
+            Ok("weekly") | Err(_) => false,
+            Ok(other) => anyhow::bail!("SCRUB_INTERVAL must be weekly or nightly, not {}", other),
+        };
+        
+        let scrub_bytes_per_second = env::var("SCRUB_BYTES_PER_SECOND")
+            .unwrap_or_else(|_| "67108864".to_string())
+            .parse::<u64>()
+            .unwrap_or(64 * 1024 * 1024);
+        
+        let orphan_gc_enabled = env::var("ORPHAN_GC_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let orphan_gc_grace_hours = env::var("ORPHAN_GC_GRACE_HOURS")
+            .unwrap_or_else(|_| "72".to_string())
+            .parse::<u64>()
+            .ok()
+            .filter(|hours| *hours > 0)
+            .unwrap_or(72);
+        
+        let orphan_gc_delete = match env::var("ORPHAN_GC_ACTION").as_deref() {
+            Ok("delete") => true,
+            Ok("quarantine") | Err(_) => false,
+            Ok(other) => anyhow::bail!("ORPHAN_GC_ACTION must be quarantine or delete, not {}", other),
+        };
+        
+        let download_audit_visible = env::var("DOWNLOAD_AUDIT_VISIBLE")
+            .unwrap_or_else(|_| "true".to_string())
+            .parse()
+            .unwrap_or(true);
+        
+        let rclone_compat = env::var("RCLONE_COMPAT")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let webdav_enabled = env::var("WEBDAV_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let case_insensitive_names = env::var("CASE_INSENSITIVE_NAMES")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let torrent_min_size = env::var("TORRENT_MIN_SIZE")
+            .unwrap_or_else(|_| "1073741824".to_string())
+            .parse::<u64>()
+            .unwrap_or(1024 * 1024 * 1024);
+        
+        let monthly_egress_limit = env::var("MONTHLY_EGRESS_LIMIT")
+            .ok()
+            .and_then(|s| s.parse::<i64>().ok())
+            .filter(|limit| *limit > 0);
+        
+        let torrent_trackers: Vec<String> = env::var("TORRENT_TRACKERS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        
+        let storage_encryption_key = read_storage_encryption_key()?;
+        
+        let s3 = read_s3_config()?;
+        
+        let compression_enabled = env::var("COMPRESSION_ENABLED")
+            .unwrap_or_else(|_| "false".to_string())
+            .parse()
+            .unwrap_or(false);
+        
+        let compression_level = env::var("COMPRESSION_LEVEL")
+            .unwrap_or_else(|_| "3".to_string())
+            .parse::<i32>()
+            .ok()
+            .filter(|level| (1..=19).contains(level))
+            .unwrap_or(3);
+        
+        let schema_drift_policy = match env::var("SCHEMA_DRIFT_POLICY").as_deref() {
+            Ok("warn") => SchemaDriftPolicy::Warn,
+            _ => SchemaDriftPolicy::Refuse,
+        };
         
         Ok(Config {
             database_url,
             storage_paths,
+            read_only_storage_paths,
+            replication_factor,
+            external_mount_roots,
+            smbclient_path,
             port,
+            admin_listen_addr,
+            s3_gateway_listen_addr,
+            trusted_proxies,
             jwt_secret,
+            dev_mode,
+            oidc_issuer_url,
+            oidc_client_id,
+            oidc_client_secret,
+            oidc_redirect_url,
+            oidc_scopes,
+            oidc_post_login_redirect,
+            oidc_link_by_email,
+            webauthn_rp_id,
+            webauthn_rp_name,
+            webauthn_origin,
+            login_max_attempts,
+            login_max_attempts_per_ip,
+            login_lockout_seconds,
+            login_lockout_max_seconds,
+            jwt_expiry_minutes,
+            jwt_issuer,
+            jwt_audience,
+            quota_grace_period_days,
+            smtp_host,
+            smtp_port,
+            smtp_tls,
+            smtp_username,
+            smtp_password,
+            smtp_from,
+            clamd_address,
+            startup_self_check,
+            content_security_policy,
+            frame_options,
+            referrer_policy,
+            risky_content_policy,
+            trash_retention_days,
+            trash_size_limit,
+            import_path,
+            remote_fetch_max_size,
+            remote_fetch_allowed_types,
+            remote_fetch_allow_private,
+            provisioning_token,
+            content_indexing,
+            content_index_max_size,
+            pdftotext_path,
+            video_metadata,
+            ffmpeg_path,
+            ffprobe_path,
+            printing_enabled,
+            lp_path,
+            printer,
+            smart_enabled,
+            smartctl_path,
+            disk_temperature_warning,
+            archive_part_max_size,
+            chunk_size_min,
+            chunk_size_max,
+            chunked_upload_max_chunks,
+            chunked_upload_max_size,
+            upload_session_ttl_hours,
+            alerts_enabled,
+            alert_disk_percent,
+            alert_failed_logins_per_minute,
+            alert_queue_depth,
+            notification_channels,
+            scrub_enabled,
+            scrub_max_bytes,
+            scrub_nightly,
+            scrub_bytes_per_second,
+            orphan_gc_enabled,
+            orphan_gc_grace_hours,
+            orphan_gc_delete,
+            download_audit_visible,
+            rclone_compat,
+            webdav_enabled,
+            case_insensitive_names,
+            torrent_min_size,
+            monthly_egress_limit,
+            torrent_trackers,
+            storage_encryption_key,
+            compression_enabled,
+            compression_level,
+            s3,
+            schema_drift_policy,
         })
     }
+}
+
+const DOCKER_JWT_SECRET_PATH: &str = "/run/secrets/jwt_secret";
+
+fn read_jwt_secret() -> anyhow::Result<String> {
+    if let Ok(secret) = env::var("JWT_SECRET") {
+        if !secret.is_empty() {
+            return Ok(secret);
+        }
+    }
+
+    let path = match env::var("JWT_SECRET_FILE") {
+        Ok(path) if !path.is_empty() => path,
+        _ if Path::new(DOCKER_JWT_SECRET_PATH).is_file() => DOCKER_JWT_SECRET_PATH.to_string(),
+        _ => return Ok(crate::auth::DEFAULT_JWT_SECRET.to_string()),
+    };
+
+    let secret = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow::anyhow!("Failed to read JWT secret from {}: {}", path, e))?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn read_storage_encryption_key() -> anyhow::Result<Option<StorageKey>> {
+    let key = match env::var("STORAGE_ENCRYPTION_KEY") {
+        Ok(key) if !key.is_empty() => key,
+        _ => match env::var("STORAGE_ENCRYPTION_KEY_FILE") {
+            Ok(path) if !path.is_empty() => std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Failed to read storage encryption key from {}: {}", path, e))?,
+            _ => return Ok(None),
+        },
+    };
+
+    StorageKey::parse(&key).map(Some)
+}
+
+fn read_s3_config() -> anyhow::Result<Option<S3Config>> {
+    let bucket = match env::var("S3_BUCKET") {
+        Ok(bucket) if !bucket.trim().is_empty() => bucket.trim().to_string(),
+        _ => return Ok(None),
+    };
+    let required = |name: &str| match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => Ok(value.trim().to_string()),
+        _ => Err(anyhow::anyhow!("{} is required when S3_BUCKET is set", name)),
+    };
+
+    let endpoint = required("S3_ENDPOINT")?;
+    reqwest::Url::parse(&endpoint).map_err(|e| anyhow::anyhow!("S3_ENDPOINT is not a valid URL: {}", e))?;
+    let prefix = env::var("S3_PREFIX").unwrap_or_default().trim_matches('/').to_string();
+
+    Ok(Some(S3Config {
+        endpoint,
+        bucket,
+        region: env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string()),
+        access_key: required("S3_ACCESS_KEY")?,
+        secret_key: required("S3_SECRET_KEY")?,
+        prefix: if prefix.is_empty() { prefix } else { format!("{}/", prefix) },
+        placement: match env::var("S3_PLACEMENT").as_deref() {
+            Ok("primary") => S3Placement::Primary,
+            _ => S3Placement::Overflow,
+        },
+    }))
+}
+
+fn read_notification_channels() -> anyhow::Result<Vec<Channel>> {
+    let optional = |name: &str| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+    let required = |name: &str, by: &str| {
+        optional(name).ok_or_else(|| anyhow::anyhow!("{} is required when {} is set", name, by))
+    };
+    let events = |name: &str| match optional(name) {
+        Some(value) => NotificationEvent::parse_list(&value).map_err(|e| anyhow::anyhow!("{}: {}", name, e)),
+        None => Ok(vec![NotificationEvent::Alert]),
+    };
+    let url = |name: &str| -> anyhow::Result<Option<String>> {
+        match optional(name) {
+            Some(url) => {
+                reqwest::Url::parse(&url).map_err(|e| anyhow::anyhow!("{} is not a valid URL: {}", name, e))?;
+                Ok(Some(url))
+            }
+            None => Ok(None),
+        }
+    };
+
+    let mut channels = Vec::new();
+    if let Some(url) = url("NTFY_URL")? {
+        channels.push(Channel {
+            kind: ChannelKind::Ntfy { url, token: optional("NTFY_TOKEN") },
+            events: events("NTFY_EVENTS")?,
+        });
+    }
+    if let Some(url) = url("GOTIFY_URL")? {
+        channels.push(Channel {
+            kind: ChannelKind::Gotify { url, token: required("GOTIFY_TOKEN", "GOTIFY_URL")? },
+            events: events("GOTIFY_EVENTS")?,
+        });
+    }
+    if let Some(bot_token) = optional("TELEGRAM_BOT_TOKEN") {
+        channels.push(Channel {
+            kind: ChannelKind::Telegram { bot_token, chat_id: required("TELEGRAM_CHAT_ID", "TELEGRAM_BOT_TOKEN")? },
+            events: events("TELEGRAM_EVENTS")?,
+        });
+    }
+
+    Ok(channels)
+}
+
+impl Config {
+    pub fn oidc_enabled(&self) -> bool {
+        self.oidc_issuer_url.is_some() && self.oidc_client_id.is_some()
+    }
+
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|proxy| proxy.contains(ip))
+    }
+}
+
+fn optional_header_value(name: &str, default: &str) -> Option<String> {
+    match env::var(name) {
+        Ok(value) if value.trim().is_empty() || value == "off" => None,
+        Ok(value) => Some(value),
+        Err(_) => Some(default.to_string()),
+    }
 }
\ No newline at end of file
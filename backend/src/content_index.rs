@@ -0,0 +1,345 @@
+use std::io::{Cursor, Read};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+use crate::models::{FileInfo, SearchIndexStats};
+use crate::operations::Progress;
+use crate::{database, preview, AppState};
+
+const BATCH_SIZE: i64 = 20;
+const MAX_CONTENT_BYTES: usize = 512 * 1024;
+const MAX_ARCHIVE_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+const PDFTOTEXT_TIMEOUT: Duration = Duration::from_secs(120);
+
+const INDEXED_TYPES: &[&str] = &[
+    "text/%",
+    "application/json",
+    "application/xml",
+    "application/pdf",
+    "application/vnd.openxmlformats-officedocument.%",
+    "application/vnd.oasis.opendocument.%",
+];
+
+const INDEXED_EXTENSIONS: &[&str] = &[
+    "txt", "md", "csv", "html", "htm", "json", "pdf", "docx", "xlsx", "pptx", "odt", "ods", "odp",
+];
+
+const DOCUMENT_BREAK_TAGS: &[&str] = &["p", "h", "br", "tab", "si", "s", "line-break"];
+
+static REBUILDING: AtomicBool = AtomicBool::new(false);
+
+enum Extractor {
+    Text,
+    Markup,
+    Pdf,
+    Document(fn(&str) -> bool),
+}
+
+fn extractor_for(file: &FileInfo) -> Option<Extractor> {
+    let declared = file.mime_type.as_deref().filter(|mime_type| *mime_type != "application/octet-stream");
+    let mime_type = preview::effective_mime_type(declared, &file.original_filename)?;
+
+    match mime_type.as_str() {
+        "text/html" | "text/xml" | "application/xml" => Some(Extractor::Markup),
+        "application/json" => Some(Extractor::Text),
+        "application/pdf" => Some(Extractor::Pdf),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+            Some(Extractor::Document(|name| name == "word/document.xml"))
+        }
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+            Some(Extractor::Document(|name| name == "xl/sharedStrings.xml"))
+        }
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => {
+            Some(Extractor::Document(|name| name.starts_with("ppt/slides/slide") && name.ends_with(".xml")))
+        }
+        mime_type if mime_type.starts_with("application/vnd.oasis.opendocument.") => {
+            Some(Extractor::Document(|name| name == "content.xml"))
+        }
+        mime_type if mime_type.starts_with("text/") => Some(Extractor::Text),
+        _ => None,
+    }
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let code = entity.strip_prefix('#')?;
+            let value = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+            char::from_u32(value)
+        }
+    }
+}
+
+fn markup_text(markup: &str, break_tags: Option<&[&str]>) -> String {
+    let mut text = String::with_capacity(markup.len() / 2);
+    let mut rest = markup;
+
+    while let Some(start) = rest.find(['<', '&']) {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            let entity = rest[1..]
+                .find(';')
+                .filter(|end| *end <= 10)
+                .and_then(|end| decode_entity(&rest[1..1 + end]).map(|c| (c, end)));
+            match entity {
+                Some((c, end)) => {
+                    text.push(c);
+                    rest = &rest[end + 2..];
+                }
+                None => {
+                    text.push('&');
+                    rest = &rest[1..];
+                }
+            }
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => return text,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default();
+        let local = name.rsplit(':').next().unwrap_or_default().to_ascii_lowercase();
+
+        match break_tags {
+            Some(tags) => {
+                if tags.contains(&local.as_str()) {
+                    text.push(' ');
+                }
+            }
+            None => {
+                if !tag.starts_with('/') && (local == "script" || local == "style") {
+                    let closing = format!("</{}", local);
+                    rest = match rest.to_ascii_lowercase().find(&closing) {
+                        Some(position) => &rest[position..],
+                        None => "",
+                    };
+                }
+                text.push(' ');
+            }
+        }
+    }
+
+    text.push_str(rest);
+    text
+}
+
+fn document_text(data: &[u8], wanted: fn(&str) -> bool) -> anyhow::Result<String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data))?;
+    let mut text = String::new();
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        if !wanted(entry.name()) {
+            continue;
+        }
+        let mut xml = String::new();
+        entry.take(MAX_ARCHIVE_ENTRY_SIZE).read_to_string(&mut xml)?;
+        text.push_str(&markup_text(&xml, Some(DOCUMENT_BREAK_TAGS)));
+        text.push(' ');
+        if text.len() > MAX_CONTENT_BYTES {
+            break;
+        }
+    }
+
+    Ok(text)
+}
+
+async fn pdf_text(state: &AppState, file: &FileInfo) -> anyhow::Result<String> {
+    let file_storage = state.file_storage.clone();
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let source = tokio::task::spawn_blocking(move || file_storage.materialize(&file_path, encoding)).await??;
+
+    let config = &state.config;
+    let mut command = Command::new(&config.pdftotext_path);
+    command
+        .args(["-q", "-enc", "UTF-8"])
+        .arg(source.path())
+        .arg("-")
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(PDFTOTEXT_TIMEOUT, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("pdftotext timed out"))?
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", config.pdftotext_path, e))?;
+    if !output.status.success() {
+        anyhow::bail!("pdftotext exited with {}", output.status);
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+fn normalize(text: &str) -> String {
+    let mut normalized = String::new();
+    for word in text.split_whitespace() {
+        if normalized.len() + word.len() + 1 > MAX_CONTENT_BYTES {
+            break;
+        }
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        normalized.extend(word.chars().filter(|c| !c.is_control()));
+    }
+    normalized
+}
+
+async fn extract(state: &AppState, file: &FileInfo) -> anyhow::Result<Option<String>> {
+    let extractor = match extractor_for(file) {
+        Some(extractor) => extractor,
+        None => return Ok(None),
+    };
+
+    let text = match extractor {
+        Extractor::Pdf => pdf_text(state, file).await?,
+        extractor => {
+            let file_storage = state.file_storage.clone();
+            let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+            tokio::task::spawn_blocking(move || -> anyhow::Result<String> {
+                let data = file_storage.get_file_data(&file_path, encoding)?;
+                match extractor {
+                    Extractor::Markup => Ok(markup_text(&String::from_utf8_lossy(&data), None)),
+                    Extractor::Document(wanted) => document_text(&data, wanted),
+                    _ => Ok(String::from_utf8_lossy(&data).into_owned()),
+                }
+            })
+            .await??
+        }
+    };
+
+    Ok(Some(normalize(&text)))
+}
+
+async fn index_file(state: &AppState, file: &FileInfo) -> anyhow::Result<()> {
+    let result = match extract(state, file).await {
+        Ok(Some(content)) => {
+            database::finish_file_indexing(&state.db, &file.id, "indexed", Some(&content), None).await
+        }
+        Ok(None) => database::finish_file_indexing(&state.db, &file.id, "skipped", None, None).await,
+        Err(e) => Err(e),
+    };
+
+    if let Err(e) = result {
+        warn!("Failed to index the contents of file {}: {:#}", file.id, e);
+        database::finish_file_indexing(&state.db, &file.id, "failed", None, Some(&format!("{:#}", e))).await?;
+    }
+    Ok(())
+}
+
+async fn index_batch(state: &AppState) -> anyhow::Result<i64> {
+    let files = database::claim_files_for_indexing(
+        &state.db,
+        INDEXED_TYPES,
+        INDEXED_EXTENSIONS,
+        state.config.content_index_max_size,
+        BATCH_SIZE,
+    )
+    .await?;
+
+    for file in &files {
+        if let Err(e) = index_file(state, file).await {
+            error!("Failed to record the content index of file {}: {}", file.id, e);
+        }
+    }
+
+    Ok(files.len() as i64)
+}
+
+pub async fn process(state: &AppState) {
+    let mut indexed = 0;
+
+    loop {
+        let count = match index_batch(state).await {
+            Ok(count) => count,
+            Err(e) => {
+                error!("Failed to claim files for content indexing: {}", e);
+                break;
+            }
+        };
+
+        indexed += count;
+        if count < BATCH_SIZE {
+            break;
+        }
+    }
+
+    if indexed > 0 {
+        info!("Indexed the contents of {} files", indexed);
+    }
+}
+
+pub fn is_rebuilding() -> bool {
+    REBUILDING.load(Ordering::SeqCst)
+}
+
+pub async fn stats(state: &AppState) -> anyhow::Result<SearchIndexStats> {
+    database::get_search_index_stats(&state.db, INDEXED_TYPES, INDEXED_EXTENSIONS, state.config.content_index_max_size).await
+}
+
+async fn reindex(state: &AppState, progress: &Progress) -> anyhow::Result<serde_json::Value> {
+    let indexes = database::reindex_search_indexes(&state.db).await?;
+    if !state.config.content_indexing {
+        info!("Rebuilt search indexes {:?}; content indexing is disabled", indexes);
+        return Ok(serde_json::json!({
+            "indexes": indexes,
+            "content_indexing": false,
+            "files": 0,
+        }));
+    }
+
+    database::mark_file_contents_stale(&state.db).await?;
+    let total = database::count_files_pending_indexing(
+        &state.db,
+        INDEXED_TYPES,
+        INDEXED_EXTENSIONS,
+        state.config.content_index_max_size,
+    )
+    .await?;
+    progress.update(0, Some(total)).await?;
+
+    let mut indexed = 0i64;
+    loop {
+        let count = index_batch(state).await?;
+        indexed += count;
+        progress.update(indexed, Some(total.max(indexed))).await?;
+        if count < BATCH_SIZE {
+            break;
+        }
+    }
+
+    info!("Rebuilt search indexes {:?} and re-indexed the contents of {} files", indexes, indexed);
+    Ok(serde_json::json!({
+        "indexes": indexes,
+        "content_indexing": true,
+        "files": indexed,
+    }))
+}
+
+pub async fn rebuild(state: &AppState, progress: &Progress) -> anyhow::Result<serde_json::Value> {
+    if REBUILDING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("a search index rebuild is already running");
+    }
+    let result = reindex(state, progress).await;
+    REBUILDING.store(false, Ordering::SeqCst);
+    result
+}
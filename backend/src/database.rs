@@ -1,9 +1,29 @@
-use sqlx::PgPool;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::str::FromStr;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use tracing::warn;
 use uuid::Uuid;
-use crate::models::{User, FileInfo, ChunkedUpload};
+use crate::models::{User, FileInfo, ChunkedUpload, SharedLink, UserQuota, UserStorageUsage, Clipboard, StorageResult, Operation, Session, ShareTorrent, ApiUsageRow, LoginLockout, TransferDay, PreviewHandlerRow, WebauthnCredential, AdminFileSearchQuery, ExportJob, ExportRun, ExportDestination, Folder, Gallery, ExternalMount, MountEntry, SmbCredentials, Notification, Broadcast, BroadcastRecipient, ClaimedRecipient, RemoteFetch, ContentSearchResult, DirectoryUser, Group, GroupMembership, ArchivePart, ArchiveManifestEntry, FileMetadata, ScrubFinding, LifecycleRule, OrphanGcRun, StorageTier, PermissionSet, FolderPermission, FolderShareDefaults, FileDownload, ShareDownloadCount, TagSummary, Tenant, TenantSummary, FileActivity, RecentFile, CameraUploadSettings, Snippet, SearchIndexStats, SharedFolder, ImportJob, PhotoMetadata, Alias, S3AccessKey, S3MultipartUpload, S3MultipartPart, StorageEncoding};
+
+const FILE_COLUMNS: &str = "id, user_id, filename, original_filename, file_path, disk_path, file_size, stored_size, mime_type, is_deleted, deleted_at, is_quarantined, last_accessed_at, checksum, folder_id, client_modified_at, keep_offline, storage_tier, storage_encoding, tags, custom_metadata, created_at, updated_at";
+
+const FILE_NAME_SEARCH_VECTOR: &str = "to_tsvector('simple', regexp_replace(original_filename, '[^[:alnum:]]+', ' ', 'g'))";
+
+const PHOTO_METADATA_SEARCH_VECTOR: &str = "(to_tsvector('simple', COALESCE(description, '')) || jsonb_to_tsvector('simple', COALESCE(raw, '{}'::jsonb), '[\"string\"]'))";
+
+pub const EXPECTED_TABLES: &[&str] = &["users", "files", "shared_links", "chunked_uploads", "export_jobs", "export_runs", "folders", "import_jobs", "photo_metadata", "upload_chunks", "aliases", "clipboards", "operations", "sessions", "share_torrents", "api_usage", "login_attempts", "oidc_logins", "user_identities", "user_transfers", "preview_handlers", "webauthn_credentials", "webauthn_challenges", "galleries", "external_mounts", "external_mount_entries", "notifications", "broadcasts", "broadcast_recipients", "remote_fetches", "folder_permissions", "groups", "user_groups", "file_contents", "archive_parts", "archive_manifest", "file_metadata", "file_scrubs", "folder_share_defaults", "file_downloads", "share_download_counts", "tags", "file_tags", "starred_files", "tenants", "file_activity", "camera_upload_settings", "snippets", "lifecycle_rules", "orphan_gc_runs", "storage_path_changes", "s3_access_keys", "s3_multipart_uploads", "s3_multipart_parts"];
 
 pub async fn create_connection_pool(database_url: &str) -> anyhow::Result<PgPool> {
-    let pool = PgPool::connect(database_url).await?;
+    let options = PgPoolOptions::new();
+    #[cfg(feature = "chaos")]
+    let options = options.before_acquire(|_, _| {
+        Box::pin(async {
+            crate::chaos::delay_query().await;
+            Ok(true)
+        })
+    });
+    let pool = options.connect(database_url).await?;
     Ok(pool)
 }
 
@@ -42,302 +62,6946 @@ pub async fn initialize_database(pool: &PgPool) -> anyhow::Result<()> {
         )
         "#,
     )
-    .execute(pool)
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS is_deleted BOOLEAN DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS storage_used BIGINT NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_soft_bytes BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_hard_bytes BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS quota_grace_started_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS deactivated_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS egress_limit_bytes BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS egress_unlimited BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS trash_limit_bytes BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_user_deleted ON files (user_id, is_deleted)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS is_quarantined BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS last_accessed_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS checksum VARCHAR(64)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS folders (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            parent_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+            name VARCHAR(255) NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE folders ADD COLUMN IF NOT EXISTS color VARCHAR(32)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE folders ADD COLUMN IF NOT EXISTS icon VARCHAR(64)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE folders ADD COLUMN IF NOT EXISTS keep_offline BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_folders_user_parent_name ON folders (user_id, COALESCE(parent_id, '00000000-0000-0000-0000-000000000000'::uuid), name)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS folder_id UUID REFERENCES folders(id) ON DELETE SET NULL"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS client_modified_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS keep_offline BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_user_updated_at ON files(user_id, updated_at)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS external_mounts (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name VARCHAR(255) NOT NULL,
+            host_path TEXT NOT NULL,
+            read_only BOOLEAN NOT NULL DEFAULT TRUE,
+            last_scanned_at TIMESTAMP WITH TIME ZONE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            UNIQUE (user_id, name)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS external_mount_entries (
+            mount_id UUID NOT NULL REFERENCES external_mounts(id) ON DELETE CASCADE,
+            parent_path TEXT NOT NULL,
+            path TEXT NOT NULL,
+            name TEXT NOT NULL,
+            is_dir BOOLEAN NOT NULL,
+            size BIGINT NOT NULL DEFAULT 0,
+            modified_at TIMESTAMP WITH TIME ZONE,
+            PRIMARY KEY (mount_id, path)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_external_mount_entries_parent ON external_mount_entries (mount_id, parent_path)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS kind VARCHAR(16) NOT NULL DEFAULT 'local'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS smb_credentials JSONB"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS sync_folder_id UUID REFERENCES folders(id) ON DELETE SET NULL"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS last_synced_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS last_sync_error TEXT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE external_mounts ADD COLUMN IF NOT EXISTS sync_started_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS notifications (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(64) NOT NULL,
+            message TEXT NOT NULL,
+            data JSONB,
+            read_at TIMESTAMP WITH TIME ZONE,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_notifications_user ON notifications (user_id, created_at DESC)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS broadcasts (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+            subject TEXT NOT NULL,
+            body TEXT NOT NULL,
+            status VARCHAR(16) NOT NULL DEFAULT 'sending',
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS broadcast_recipients (
+            broadcast_id UUID NOT NULL REFERENCES broadcasts(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            email TEXT NOT NULL,
+            status VARCHAR(16) NOT NULL DEFAULT 'pending',
+            error TEXT,
+            claimed_at TIMESTAMP WITH TIME ZONE,
+            sent_at TIMESTAMP WITH TIME ZONE,
+            PRIMARY KEY (broadcast_id, user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_broadcast_recipients_status ON broadcast_recipients (status, broadcast_id)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS folder_permissions (
+            folder_id UUID NOT NULL REFERENCES folders(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            can_read BOOLEAN NOT NULL DEFAULT TRUE,
+            can_write BOOLEAN NOT NULL DEFAULT FALSE,
+            can_delete BOOLEAN NOT NULL DEFAULT FALSE,
+            can_reshare BOOLEAN NOT NULL DEFAULT FALSE,
+            granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (folder_id, user_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_folder_permissions_user ON folder_permissions (user_id)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS groups (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(255) UNIQUE NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_groups (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            group_id UUID NOT NULL REFERENCES groups(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (user_id, group_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE groups ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_user_groups_group ON user_groups (group_id)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE users ADD COLUMN IF NOT EXISTS scim_external_id TEXT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_contents (
+            file_id UUID PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            checksum VARCHAR(64),
+            status VARCHAR(16) NOT NULL DEFAULT 'indexing',
+            content TEXT,
+            error TEXT,
+            search_vector TSVECTOR GENERATED ALWAYS AS (to_tsvector('english', COALESCE(content, ''))) STORED,
+            indexed_at TIMESTAMP WITH TIME ZONE,
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_file_contents_search ON file_contents USING GIN (search_vector)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE file_contents ADD COLUMN IF NOT EXISTS stale BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archive_parts (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            sequence INTEGER NOT NULL,
+            status VARCHAR(16) NOT NULL DEFAULT 'building',
+            file_count INTEGER NOT NULL DEFAULT 0,
+            removed_count INTEGER NOT NULL DEFAULT 0,
+            size_bytes BIGINT NOT NULL DEFAULT 0,
+            archive_path TEXT,
+            error TEXT,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE,
+            UNIQUE (user_id, sequence)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_metadata (
+            file_id UUID PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            checksum VARCHAR(64),
+            status VARCHAR(16) NOT NULL,
+            duration_seconds DOUBLE PRECISION,
+            width INTEGER,
+            height INTEGER,
+            video_codec TEXT,
+            poster_path TEXT,
+            error TEXT,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_activity (
+            id BIGSERIAL PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            file_id UUID NOT NULL,
+            filename VARCHAR(255) NOT NULL,
+            action VARCHAR(16) NOT NULL,
+            occurred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_file_activity_user ON file_activity (user_id, occurred_at DESC)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS camera_upload_settings (
+            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            template VARCHAR(255) NOT NULL,
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_downloads (
+            id BIGSERIAL PRIMARY KEY,
+            file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            downloaded_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_file_downloads_file ON file_downloads (file_id, downloaded_at DESC)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS folder_share_defaults (
+            folder_id UUID PRIMARY KEY REFERENCES folders(id) ON DELETE CASCADE,
+            expiry_days INTEGER,
+            password_required BOOLEAN NOT NULL DEFAULT FALSE,
+            max_downloads INTEGER,
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_scrubs (
+            file_id UUID PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            status VARCHAR(16) NOT NULL,
+            expected_checksum VARCHAR(64) NOT NULL,
+            actual_checksum VARCHAR(64),
+            error TEXT,
+            checked_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_archive_parts_building ON archive_parts (user_id) WHERE status = 'building'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS archive_manifest (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            file_id UUID NOT NULL,
+            part_id UUID NOT NULL REFERENCES archive_parts(id) ON DELETE CASCADE,
+            path TEXT NOT NULL,
+            checksum VARCHAR(64) NOT NULL,
+            file_size BIGINT NOT NULL,
+            archived_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (user_id, file_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS remote_fetches (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            url TEXT NOT NULL,
+            folder_id UUID REFERENCES folders(id) ON DELETE SET NULL,
+            filename VARCHAR(255),
+            status VARCHAR(16) NOT NULL DEFAULT 'pending',
+            bytes_downloaded BIGINT NOT NULL DEFAULT 0,
+            total_bytes BIGINT,
+            mime_type VARCHAR(255),
+            file_id UUID REFERENCES files(id) ON DELETE SET NULL,
+            error TEXT,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_remote_fetches_user ON remote_fetches (user_id, created_at DESC)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS galleries (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            folder_id UUID NOT NULL UNIQUE REFERENCES folders(id) ON DELETE CASCADE,
+            token VARCHAR(64) NOT NULL UNIQUE,
+            title VARCHAR(255),
+            description TEXT,
+            theme VARCHAR(32) NOT NULL DEFAULT 'light',
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS aliases (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            folder_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+            name VARCHAR(255) NOT NULL,
+            target_file_id UUID REFERENCES files(id) ON DELETE CASCADE,
+            target_folder_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            CHECK ((target_file_id IS NULL) <> (target_folder_id IS NULL))
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS clipboards (
+            user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            mode VARCHAR(8) NOT NULL,
+            file_ids UUID[] NOT NULL DEFAULT '{}',
+            folder_ids UUID[] NOT NULL DEFAULT '{}',
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS sessions (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            user_agent VARCHAR(512),
+            ip_address VARCHAR(64),
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            last_used_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+            revoked_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS shared_links (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            token VARCHAR(255) UNIQUE NOT NULL,
+            expires_at TIMESTAMP WITH TIME ZONE,
+            is_read_only BOOLEAN DEFAULT TRUE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS is_encrypted BOOLEAN NOT NULL DEFAULT FALSE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS encryption_metadata JSONB"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS egress_limit_bytes BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS egress_month DATE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS egress_bytes BIGINT NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS password_hash TEXT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS max_downloads INTEGER"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE shared_links ADD COLUMN IF NOT EXISTS download_count INTEGER NOT NULL DEFAULT 0"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS share_download_counts (
+            share_id UUID NOT NULL REFERENCES shared_links(id) ON DELETE CASCADE,
+            day DATE NOT NULL,
+            downloads INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (share_id, day)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS snippets (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            file_id UUID NOT NULL UNIQUE REFERENCES files(id) ON DELETE CASCADE,
+            share_id UUID NOT NULL REFERENCES shared_links(id) ON DELETE CASCADE,
+            title VARCHAR(255) NOT NULL,
+            language VARCHAR(32),
+            burn_after_reading BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query("CREATE INDEX IF NOT EXISTS idx_snippets_user ON snippets (user_id, created_at DESC)")
+        .execute(pool)
+        .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS preview_handlers (
+            mime_type TEXT PRIMARY KEY,
+            strategy TEXT NOT NULL,
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_transfers (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            day DATE NOT NULL,
+            bytes_uploaded BIGINT NOT NULL DEFAULT 0,
+            bytes_downloaded BIGINT NOT NULL DEFAULT 0,
+            PRIMARY KEY (user_id, day)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS oidc_logins (
+            state VARCHAR(128) PRIMARY KEY,
+            nonce VARCHAR(128) NOT NULL,
+            code_verifier VARCHAR(128) NOT NULL,
+            redirect_uri TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS user_identities (
+            issuer TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (issuer, subject)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS webauthn_credentials (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            credential_id BYTEA NOT NULL UNIQUE,
+            public_key BYTEA NOT NULL,
+            sign_count BIGINT NOT NULL DEFAULT 0,
+            name VARCHAR(255) NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            last_used_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS webauthn_challenges (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            purpose VARCHAR(16) NOT NULL,
+            challenge BYTEA NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS login_attempts (
+            key VARCHAR(320) PRIMARY KEY,
+            failures INTEGER NOT NULL DEFAULT 0,
+            last_failure_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            locked_until TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS share_torrents (
+            share_id UUID PRIMARY KEY REFERENCES shared_links(id) ON DELETE CASCADE,
+            info_hash VARCHAR(40) NOT NULL,
+            name VARCHAR(255) NOT NULL,
+            web_seed_url TEXT NOT NULL,
+            torrent BYTEA NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS api_usage (
+            bucket TIMESTAMP WITH TIME ZONE NOT NULL,
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            method VARCHAR(16) NOT NULL,
+            route VARCHAR(255) NOT NULL,
+            request_count BIGINT NOT NULL DEFAULT 0,
+            client_error_count BIGINT NOT NULL DEFAULT 0,
+            server_error_count BIGINT NOT NULL DEFAULT 0,
+            total_duration_ms BIGINT NOT NULL DEFAULT 0
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_api_usage_key
+        ON api_usage(bucket, COALESCE(user_id, '00000000-0000-0000-0000-000000000000'::uuid), method, route)
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS chunked_uploads (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            filename VARCHAR(255) NOT NULL,
+            total_size BIGINT NOT NULL,
+            chunk_size BIGINT NOT NULL,
+            total_chunks INTEGER NOT NULL,
+            uploaded_chunks INTEGER DEFAULT 0,
+            temp_path VARCHAR(500) NOT NULL,
+            disk_path VARCHAR(500) NOT NULL,
+            is_completed BOOLEAN DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS export_jobs (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID REFERENCES users(id) ON DELETE CASCADE,
+            name VARCHAR(255) NOT NULL,
+            destination JSONB NOT NULL,
+            schedule VARCHAR(255) NOT NULL,
+            is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            next_run_at TIMESTAMP WITH TIME ZONE,
+            last_run_at TIMESTAMP WITH TIME ZONE,
+            last_success_at TIMESTAMP WITH TIME ZONE,
+            last_error TEXT,
+            created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS export_runs (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            job_id UUID NOT NULL REFERENCES export_jobs(id) ON DELETE CASCADE,
+            status VARCHAR(32) NOT NULL DEFAULT 'running',
+            files_exported INTEGER NOT NULL DEFAULT 0,
+            bytes_exported BIGINT NOT NULL DEFAULT 0,
+            error TEXT,
+            started_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS import_jobs (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(32),
+            source_path VARCHAR(1000) NOT NULL,
+            target_folder VARCHAR(255) NOT NULL,
+            status VARCHAR(32) NOT NULL DEFAULT 'running',
+            files_imported INTEGER NOT NULL DEFAULT 0,
+            folders_created INTEGER NOT NULL DEFAULT 0,
+            bytes_imported BIGINT NOT NULL DEFAULT 0,
+            error TEXT,
+            created_by UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS operations (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            kind VARCHAR(32) NOT NULL,
+            status VARCHAR(32) NOT NULL DEFAULT 'running',
+            progress_current BIGINT NOT NULL DEFAULT 0,
+            progress_total BIGINT,
+            result JSONB,
+            error TEXT,
+            cancel_requested BOOLEAN NOT NULL DEFAULT FALSE,
+            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            finished_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_operations_user_created_at ON operations(user_id, created_at DESC)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS photo_metadata (
+            file_id UUID PRIMARY KEY REFERENCES files(id) ON DELETE CASCADE,
+            taken_at TIMESTAMP WITH TIME ZONE,
+            latitude DOUBLE PRECISION,
+            longitude DOUBLE PRECISION,
+            altitude DOUBLE PRECISION,
+            description TEXT,
+            raw JSONB
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE chunked_uploads ADD COLUMN IF NOT EXISTS status VARCHAR(32) NOT NULL DEFAULT 'active'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE chunked_uploads ADD COLUMN IF NOT EXISTS client_modified_at TIMESTAMP WITH TIME ZONE"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE chunked_uploads ADD COLUMN IF NOT EXISTS folder_id UUID REFERENCES folders(id) ON DELETE SET NULL"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE chunked_uploads ADD COLUMN IF NOT EXISTS expires_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW() + INTERVAL '1 day'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_user_folder_lower_name ON files (user_id, folder_id, LOWER(original_filename))"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS tags TEXT[] NOT NULL DEFAULT '{}'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_files_tags ON files USING GIN (tags)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS custom_metadata JSONB NOT NULL DEFAULT '{}'"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS stored_size BIGINT"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS storage_tier VARCHAR(8) NOT NULL DEFAULT 'hot'"
+    )
+    .execute(pool)
+    .await?;
+
+    // NULL for files stored before the encoding was recorded; those are
+    // still recognised by their header when read.
+    sqlx::query(
+        "ALTER TABLE files ADD COLUMN IF NOT EXISTS storage_encoding VARCHAR(16)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS lifecycle_rules (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(255) NOT NULL,
+            idle_days INTEGER NOT NULL,
+            min_size BIGINT,
+            mime_prefix VARCHAR(255),
+            is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+            last_run_at TIMESTAMP WITH TIME ZONE,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS storage_path_changes (
+            path TEXT PRIMARY KEY,
+            is_removed BOOLEAN NOT NULL,
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS s3_access_keys (
+            access_key_id VARCHAR(32) PRIMARY KEY,
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            secret_key TEXT NOT NULL,
+            description TEXT,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            last_used_at TIMESTAMP WITH TIME ZONE
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS s3_multipart_uploads (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            bucket TEXT NOT NULL,
+            object_key TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            expires_at TIMESTAMP WITH TIME ZONE NOT NULL
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS s3_multipart_parts (
+            upload_id UUID NOT NULL REFERENCES s3_multipart_uploads(id) ON DELETE CASCADE,
+            part_number INTEGER NOT NULL,
+            temp_path TEXT NOT NULL,
+            size BIGINT NOT NULL,
+            etag TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (upload_id, part_number)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS orphan_gc_runs (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            action VARCHAR(16) NOT NULL,
+            scanned_blobs BIGINT NOT NULL,
+            orphaned_blobs BIGINT NOT NULL,
+            orphaned_bytes BIGINT NOT NULL,
+            reclaimed_bytes BIGINT NOT NULL,
+            failed_blobs BIGINT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tags (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            UNIQUE (user_id, name)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS file_tags (
+            file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            tag_id UUID NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (file_id, tag_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        "CREATE INDEX IF NOT EXISTS idx_file_tags_tag ON file_tags (tag_id)"
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS tenants (
+            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+            name VARCHAR(255) UNIQUE NOT NULL,
+            storage_paths TEXT[] NOT NULL DEFAULT '{}',
+            storage_quota_bytes BIGINT,
+            created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            updated_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    for table in ["users", "files", "shared_links"] {
+        sqlx::query(&format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS tenant_id UUID REFERENCES tenants(id)",
+            table
+        ))
+        .execute(pool)
+        .await?;
+
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS idx_{0}_tenant ON {0} (tenant_id) WHERE tenant_id IS NOT NULL",
+            table
+        ))
+        .execute(pool)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS starred_files (
+            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+            starred_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+            PRIMARY KEY (user_id, file_id)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO tags (user_id, name)
+        SELECT DISTINCT user_id, UNNEST(tags) FROM files WHERE tags <> '{}'
+        ON CONFLICT (user_id, name) DO NOTHING
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO file_tags (file_id, tag_id)
+        SELECT f.id, t.id FROM files f JOIN tags t ON t.user_id = f.user_id AND t.name = ANY(f.tags)
+        WHERE f.tags <> '{}'
+        ON CONFLICT (file_id, tag_id) DO NOTHING
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+        "CREATE INDEX IF NOT EXISTS idx_files_name_search ON files USING GIN (({}))",
+        FILE_NAME_SEARCH_VECTOR
+    ))
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+        "CREATE INDEX IF NOT EXISTS idx_photo_metadata_search ON photo_metadata USING GIN ({})",
+        PHOTO_METADATA_SEARCH_VECTOR
+    ))
+    .execute(pool)
+    .await?;
+
+    match sqlx::query("CREATE EXTENSION IF NOT EXISTS pg_trgm").execute(pool).await {
+        Ok(_) => {
+            sqlx::query(
+                "CREATE INDEX IF NOT EXISTS idx_files_name_trgm ON files USING GIN (LOWER(original_filename) gin_trgm_ops)"
+            )
+            .execute(pool)
+            .await?;
+        }
+        Err(e) => warn!("pg_trgm is unavailable, substring file search will not be indexed: {}", e),
+    }
+
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS upload_chunks (
+            upload_id UUID NOT NULL REFERENCES chunked_uploads(id) ON DELETE CASCADE,
+            chunk_number INTEGER NOT NULL,
+            chunk_size BIGINT NOT NULL,
+            received_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
+            PRIMARY KEY (upload_id, chunk_number)
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_missing_tables(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let existing: Vec<(String,)> = sqlx::query_as(
+        "SELECT table_name::TEXT FROM information_schema.tables WHERE table_schema = current_schema()"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let missing = EXPECTED_TABLES
+        .iter()
+        .filter(|table| !existing.iter().any(|(name,)| name == *table))
+        .map(|table| table.to_string())
+        .collect();
+
+    Ok(missing)
+}
+
+pub async fn get_current_schema(pool: &PgPool) -> anyhow::Result<String> {
+    let schema: String = sqlx::query_scalar("SELECT current_schema()::TEXT")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(schema)
+}
+
+pub async fn get_schema_columns(pool: &PgPool, schema: &str) -> anyhow::Result<Vec<(String, String, String)>> {
+    let columns = sqlx::query_as(
+        r#"
+        SELECT c.table_name::TEXT, c.column_name::TEXT, c.data_type::TEXT
+        FROM information_schema.columns c
+        JOIN information_schema.tables t ON t.table_schema = c.table_schema AND t.table_name = c.table_name
+        WHERE c.table_schema = $1 AND t.table_type = 'BASE TABLE'
+        "#,
+    )
+    .bind(schema)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(columns)
+}
+
+pub async fn get_schema_indexes(pool: &PgPool, schema: &str) -> anyhow::Result<Vec<String>> {
+    let indexes = sqlx::query_scalar("SELECT indexname::TEXT FROM pg_indexes WHERE schemaname = $1")
+        .bind(schema)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(indexes)
+}
+
+pub async fn create_schema(pool: &PgPool, schema: &str) -> anyhow::Result<()> {
+    sqlx::query(&format!("CREATE SCHEMA {}", schema)).execute(pool).await?;
+    Ok(())
+}
+
+pub async fn drop_schema(pool: &PgPool, schema: &str) -> anyhow::Result<()> {
+    sqlx::query(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema)).execute(pool).await?;
+    Ok(())
+}
+
+pub async fn create_scratch_pool(database_url: &str, schema: &str) -> anyhow::Result<PgPool> {
+    let options = PgConnectOptions::from_str(database_url)?
+        .options([("search_path", format!("{},public", schema))]);
+    let pool = PgPoolOptions::new().max_connections(1).connect_with(options).await?;
+    Ok(pool)
+}
+
+pub async fn create_user(
+    pool: &PgPool,
+    username: &str,
+    email: &str,
+    password_hash: &str,
+    is_admin: bool,
+) -> anyhow::Result<User> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        INSERT INTO users (username, email, password_hash, is_admin)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at
+        "#,
+    )
+    .bind(username)
+    .bind(email)
+    .bind(password_hash)
+    .bind(is_admin)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_by_username(pool: &PgPool, username: &str) -> anyhow::Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE username = $1",
+    )
+    .bind(username)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_by_email(pool: &PgPool, email: &str) -> anyhow::Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE email = $1",
+    )
+    .bind(email)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_by_id(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn update_user_email(pool: &PgPool, user_id: &Uuid, email: &str) -> anyhow::Result<User> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        UPDATE users SET email = $1, updated_at = NOW()
+        WHERE id = $2
+        RETURNING id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at
+        "#,
+    )
+    .bind(email)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn update_user_password(pool: &PgPool, user_id: &Uuid, password_hash: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+        .bind(password_hash)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+const TENANT_COLUMNS: &str = "id, name, storage_paths, storage_quota_bytes, created_at, updated_at";
+const TENANT_SUMMARY_COLUMNS: &str = r#"
+    t.id, t.name, t.storage_paths, t.storage_quota_bytes, t.created_at, t.updated_at,
+    (SELECT COUNT(*) FROM users u WHERE u.tenant_id = t.id) AS user_count,
+    (SELECT COALESCE(SUM(u.storage_used), 0)::BIGINT FROM users u WHERE u.tenant_id = t.id) AS storage_used
+"#;
+
+pub async fn create_tenant(
+    pool: &PgPool,
+    name: &str,
+    storage_paths: &[String],
+    storage_quota_bytes: Option<i64>,
+) -> anyhow::Result<Option<Tenant>> {
+    let tenant = sqlx::query_as::<_, Tenant>(&format!(
+        r#"
+        INSERT INTO tenants (name, storage_paths, storage_quota_bytes)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (name) DO NOTHING
+        RETURNING {}
+        "#,
+        TENANT_COLUMNS
+    ))
+    .bind(name)
+    .bind(storage_paths)
+    .bind(storage_quota_bytes)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(tenant)
+}
+
+pub async fn update_tenant(
+    pool: &PgPool,
+    tenant_id: &Uuid,
+    name: &str,
+    storage_paths: &[String],
+    storage_quota_bytes: Option<i64>,
+) -> anyhow::Result<Option<Tenant>> {
+    let tenant = sqlx::query_as::<_, Tenant>(&format!(
+        r#"
+        UPDATE tenants SET name = $2, storage_paths = $3, storage_quota_bytes = $4, updated_at = NOW()
+        WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM tenants WHERE name = $2 AND id <> $1)
+        RETURNING {}
+        "#,
+        TENANT_COLUMNS
+    ))
+    .bind(tenant_id)
+    .bind(name)
+    .bind(storage_paths)
+    .bind(storage_quota_bytes)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(tenant)
+}
+
+pub async fn get_tenant(pool: &PgPool, tenant_id: &Uuid) -> anyhow::Result<Option<TenantSummary>> {
+    let tenant = sqlx::query_as::<_, TenantSummary>(&format!("SELECT {} FROM tenants t WHERE t.id = $1", TENANT_SUMMARY_COLUMNS))
+        .bind(tenant_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(tenant)
+}
+
+pub async fn get_tenants(pool: &PgPool) -> anyhow::Result<Vec<TenantSummary>> {
+    let tenants = sqlx::query_as::<_, TenantSummary>(&format!("SELECT {} FROM tenants t ORDER BY t.name", TENANT_SUMMARY_COLUMNS))
+        .fetch_all(pool)
+        .await?;
+
+    Ok(tenants)
+}
+
+pub async fn delete_tenant(pool: &PgPool, tenant_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "DELETE FROM tenants WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM users WHERE tenant_id = $1)"
+    )
+    .bind(tenant_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn set_user_tenant(pool: &PgPool, user_id: &Uuid, tenant_id: Option<&Uuid>, is_admin: bool) -> anyhow::Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query("UPDATE users SET tenant_id = $2, is_admin = $3, updated_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .bind(tenant_id)
+        .bind(is_admin)
+        .execute(&mut *tx)
+        .await?;
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE files SET tenant_id = $2 WHERE user_id = $1")
+        .bind(user_id)
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE shared_links SET tenant_id = $2 WHERE file_id IN (SELECT id FROM files WHERE user_id = $1)")
+        .bind(user_id)
+        .bind(tenant_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM folder_permissions p
+        USING folders f, users owner, users grantee
+        WHERE f.id = p.folder_id AND owner.id = f.user_id AND grantee.id = p.user_id
+          AND (owner.id = $1 OR grantee.id = $1)
+          AND owner.tenant_id IS DISTINCT FROM grantee.tenant_id
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn get_tenant_storage(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<(Option<i64>, i64)>> {
+    let storage: Option<(Option<i64>, i64)> = sqlx::query_as(
+        r#"
+        SELECT t.storage_quota_bytes, (SELECT COALESCE(SUM(u.storage_used), 0)::BIGINT FROM users u WHERE u.tenant_id = t.id)
+        FROM tenants t
+        WHERE t.id = (SELECT tenant_id FROM users WHERE id = $1)
+        "#,
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(storage)
+}
+
+pub async fn get_tenant_storage_roots(pool: &PgPool) -> anyhow::Result<Vec<(Uuid, Vec<String>)>> {
+    let roots: Vec<(Uuid, Vec<String>)> = sqlx::query_as(
+        "SELECT u.id, t.storage_paths FROM users u JOIN tenants t ON t.id = u.tenant_id WHERE t.storage_paths <> '{}'"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(roots)
+}
+
+pub async fn get_user_storage_used(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<i64> {
+    let (storage_used,): (i64,) = sqlx::query_as("SELECT storage_used FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(storage_used)
+}
+
+pub async fn get_user_storage_usage(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<UserStorageUsage> {
+    let usage = sqlx::query_as::<_, UserStorageUsage>(
+        r#"
+        SELECT
+            COALESCE(SUM(file_size) FILTER (WHERE NOT is_deleted), 0)::BIGINT AS used_bytes,
+            COALESCE(SUM(file_size) FILTER (WHERE is_deleted), 0)::BIGINT AS trash_bytes,
+            COUNT(*) FILTER (WHERE NOT is_deleted) AS file_count,
+            COUNT(*) FILTER (WHERE is_deleted) AS trash_count
+        FROM files
+        WHERE user_id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(usage)
+}
+
+pub async fn set_user_storage_used(pool: &PgPool, user_id: &Uuid, storage_used: i64) -> anyhow::Result<()> {
+    sqlx::query("UPDATE users SET storage_used = $1, updated_at = NOW() WHERE id = $2")
+        .bind(storage_used)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_user_quota(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<UserQuota>> {
+    let quota = sqlx::query_as::<_, UserQuota>(
+        "SELECT storage_used, quota_soft_bytes, quota_hard_bytes, quota_grace_started_at FROM users WHERE id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(quota)
+}
+
+pub async fn set_user_quota(
+    pool: &PgPool,
+    user_id: &Uuid,
+    soft_limit: Option<i64>,
+    hard_limit: Option<i64>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE users SET quota_soft_bytes = $1, quota_hard_bytes = $2, quota_grace_started_at = NULL, updated_at = NOW() WHERE id = $3"
+    )
+    .bind(soft_limit)
+    .bind(hard_limit)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_quota_grace_started_at(
+    pool: &PgPool,
+    user_id: &Uuid,
+    started_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE users SET quota_grace_started_at = $1 WHERE id = $2")
+        .bind(started_at)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn provision_user(
+    pool: &PgPool,
+    username: &str,
+    email: &str,
+    password_hash: &str,
+    soft_limit: Option<i64>,
+    hard_limit: Option<i64>,
+    groups: &[String],
+) -> anyhow::Result<Option<User>> {
+    let mut tx = pool.begin().await?;
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        INSERT INTO users (username, email, password_hash, is_admin, quota_soft_bytes, quota_hard_bytes)
+        VALUES ($1, $2, $3, FALSE, $4, $5)
+        ON CONFLICT DO NOTHING
+        RETURNING id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at
+        "#,
+    )
+    .bind(username)
+    .bind(email)
+    .bind(password_hash)
+    .bind(soft_limit)
+    .bind(hard_limit)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let user = match user {
+        Some(user) => user,
+        None => return Ok(None),
+    };
+
+    if !groups.is_empty() {
+        sqlx::query("INSERT INTO groups (name) SELECT UNNEST($1::TEXT[]) ON CONFLICT (name) DO NOTHING")
+            .bind(groups)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query(
+            "INSERT INTO user_groups (user_id, group_id) SELECT $1, id FROM groups WHERE name = ANY($2) ON CONFLICT DO NOTHING",
+        )
+        .bind(user.id)
+        .bind(groups)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(Some(user))
+}
+
+const GROUP_COLUMNS: &str = "id, name, created_at, updated_at";
+
+const GROUP_MEMBERSHIP_QUERY: &str = r#"
+    SELECT ug.group_id, g.name AS group_name, ug.user_id, u.username
+    FROM user_groups ug
+    JOIN groups g ON g.id = ug.group_id
+    JOIN users u ON u.id = ug.user_id
+"#;
+
+pub async fn list_groups(
+    pool: &PgPool,
+    name: Option<&str>,
+    offset: i64,
+    limit: i64,
+) -> anyhow::Result<(i64, Vec<Group>)> {
+    let (total,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM groups WHERE $1::TEXT IS NULL OR LOWER(name) = LOWER($1)")
+        .bind(name)
+        .fetch_one(pool)
+        .await?;
+
+    let groups = sqlx::query_as::<_, Group>(&format!(
+        "SELECT {} FROM groups WHERE $1::TEXT IS NULL OR LOWER(name) = LOWER($1) ORDER BY name LIMIT $2 OFFSET $3",
+        GROUP_COLUMNS
+    ))
+    .bind(name)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+
+    Ok((total, groups))
+}
+
+pub async fn get_group(pool: &PgPool, group_id: &Uuid) -> anyhow::Result<Option<Group>> {
+    let group = sqlx::query_as::<_, Group>(&format!("SELECT {} FROM groups WHERE id = $1", GROUP_COLUMNS))
+        .bind(group_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(group)
+}
+
+pub async fn create_group(pool: &PgPool, name: &str) -> anyhow::Result<Option<Group>> {
+    let group = sqlx::query_as::<_, Group>(&format!(
+        "INSERT INTO groups (name) VALUES ($1) ON CONFLICT (name) DO NOTHING RETURNING {}",
+        GROUP_COLUMNS
+    ))
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(group)
+}
+
+pub async fn group_name_taken(pool: &PgPool, exclude: Option<&Uuid>, name: &str) -> anyhow::Result<bool> {
+    let (taken,): (bool,) = sqlx::query_as(
+        "SELECT EXISTS (SELECT 1 FROM groups WHERE id IS DISTINCT FROM $1 AND name = $2)",
+    )
+    .bind(exclude)
+    .bind(name)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(taken)
+}
+
+pub async fn rename_group(pool: &PgPool, group_id: &Uuid, name: &str) -> anyhow::Result<Option<Group>> {
+    let group = sqlx::query_as::<_, Group>(&format!(
+        "UPDATE groups SET name = $2, updated_at = NOW() WHERE id = $1 RETURNING {}",
+        GROUP_COLUMNS
+    ))
+    .bind(group_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(group)
+}
+
+pub async fn delete_group(pool: &PgPool, group_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM groups WHERE id = $1")
+        .bind(group_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn update_group_members(
+    pool: &PgPool,
+    group_id: &Uuid,
+    replace: bool,
+    add: &[Uuid],
+    remove: &[Uuid],
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    if replace {
+        sqlx::query("DELETE FROM user_groups WHERE group_id = $1")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    if !remove.is_empty() {
+        sqlx::query("DELETE FROM user_groups WHERE group_id = $1 AND user_id = ANY($2)")
+            .bind(group_id)
+            .bind(remove)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    if !add.is_empty() {
+        sqlx::query(
+            "INSERT INTO user_groups (user_id, group_id) SELECT id, $1 FROM users WHERE id = ANY($2) ON CONFLICT DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(add)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query("UPDATE groups SET updated_at = NOW() WHERE id = $1")
+        .bind(group_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_group_memberships(pool: &PgPool, group_ids: &[Uuid]) -> anyhow::Result<Vec<GroupMembership>> {
+    let memberships = sqlx::query_as::<_, GroupMembership>(&format!(
+        "{} WHERE ug.group_id = ANY($1) ORDER BY u.username",
+        GROUP_MEMBERSHIP_QUERY
+    ))
+    .bind(group_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(memberships)
+}
+
+pub async fn get_user_memberships(pool: &PgPool, user_ids: &[Uuid]) -> anyhow::Result<Vec<GroupMembership>> {
+    let memberships = sqlx::query_as::<_, GroupMembership>(&format!(
+        "{} WHERE ug.user_id = ANY($1) ORDER BY g.name",
+        GROUP_MEMBERSHIP_QUERY
+    ))
+    .bind(user_ids)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(memberships)
+}
+
+pub async fn get_user_groups(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<String>> {
+    let groups: Vec<(String,)> = sqlx::query_as(
+        "SELECT g.name FROM user_groups ug JOIN groups g ON g.id = ug.group_id WHERE ug.user_id = $1 ORDER BY g.name",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(groups.into_iter().map(|(name,)| name).collect())
+}
+
+const DIRECTORY_USER_COLUMNS: &str = "id, username, email, is_admin, scim_external_id, deactivated_at, created_at, updated_at";
+
+pub async fn list_directory_users(
+    pool: &PgPool,
+    filter: Option<(&str, &str)>,
+    offset: i64,
+    limit: i64,
+) -> anyhow::Result<(i64, Vec<DirectoryUser>)> {
+    let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM users");
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM users", DIRECTORY_USER_COLUMNS));
+    if let Some((column, value)) = filter {
+        for builder in [&mut count, &mut query] {
+            builder
+                .push(format!(" WHERE LOWER({}) = LOWER(", column))
+                .push_bind(value.to_string())
+                .push(")");
+        }
+    }
+    query.push(" ORDER BY created_at, id LIMIT ").push_bind(limit).push(" OFFSET ").push_bind(offset);
+
+    let (total,): (i64,) = count.build_query_as().fetch_one(pool).await?;
+    let users = query.build_query_as::<DirectoryUser>().fetch_all(pool).await?;
+
+    Ok((total, users))
+}
+
+pub async fn get_directory_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<DirectoryUser>> {
+    let user = sqlx::query_as::<_, DirectoryUser>(&format!("SELECT {} FROM users WHERE id = $1", DIRECTORY_USER_COLUMNS))
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn directory_identity_taken(
+    pool: &PgPool,
+    exclude: Option<&Uuid>,
+    username: &str,
+    email: &str,
+) -> anyhow::Result<bool> {
+    let (taken,): (bool,) = sqlx::query_as(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM users
+            WHERE id IS DISTINCT FROM $1 AND (LOWER(username) = LOWER($2) OR LOWER(email) = LOWER($3))
+        )
+        "#,
+    )
+    .bind(exclude)
+    .bind(username)
+    .bind(email)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(taken)
+}
+
+pub async fn create_directory_user(
+    pool: &PgPool,
+    username: &str,
+    email: &str,
+    password_hash: &str,
+    external_id: Option<&str>,
+    active: bool,
+) -> anyhow::Result<Option<DirectoryUser>> {
+    let user = sqlx::query_as::<_, DirectoryUser>(&format!(
+        r#"
+        INSERT INTO users (username, email, password_hash, is_admin, scim_external_id, deactivated_at)
+        VALUES ($1, $2, $3, FALSE, $4, CASE WHEN $5 THEN NULL ELSE NOW() END)
+        ON CONFLICT DO NOTHING
+        RETURNING {}
+        "#,
+        DIRECTORY_USER_COLUMNS
+    ))
+    .bind(username)
+    .bind(email)
+    .bind(password_hash)
+    .bind(external_id)
+    .bind(active)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn update_directory_user(
+    pool: &PgPool,
+    user_id: &Uuid,
+    username: &str,
+    email: &str,
+    external_id: Option<&str>,
+) -> anyhow::Result<Option<DirectoryUser>> {
+    let user = sqlx::query_as::<_, DirectoryUser>(&format!(
+        r#"
+        UPDATE users SET username = $2, email = $3, scim_external_id = $4, updated_at = NOW()
+        WHERE id = $1
+        RETURNING {}
+        "#,
+        DIRECTORY_USER_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(username)
+    .bind(email)
+    .bind(external_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn deactivate_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let result = sqlx::query(
+        "UPDATE users SET deactivated_at = NOW(), updated_at = NOW() WHERE id = $1 AND deactivated_at IS NULL",
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query("UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn reactivate_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET deactivated_at = NULL, updated_at = NOW() WHERE id = $1 AND deactivated_at IS NOT NULL",
+    )
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_server_version(pool: &PgPool) -> anyhow::Result<String> {
+    let version: String = sqlx::query_scalar("SELECT current_setting('server_version')")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(version)
+}
+
+pub async fn get_active_admins(pool: &PgPool) -> anyhow::Result<Vec<User>> {
+    let users = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE is_admin = TRUE AND tenant_id IS NULL AND deactivated_at IS NULL ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn get_users_deactivated_before(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<Vec<User>> {
+    let users = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE deactivated_at < $1 ORDER BY deactivated_at",
+    )
+    .bind(before)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn delete_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_all_users(pool: &PgPool, tenant_id: Option<&Uuid>) -> anyhow::Result<Vec<User>> {
+    let users = sqlx::query_as::<_, User>(
+        "SELECT id, username, email, password_hash, is_admin, tenant_id, deactivated_at, created_at, updated_at FROM users WHERE $1::UUID IS NULL OR tenant_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(tenant_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
+}
+
+
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_file_record(
+    pool: &PgPool,
+    user_id: &Uuid,
+    filename: &str,
+    original_filename: &str,
+    file_path: &str,
+    disk_path: &str,
+    file_size: i64,
+    stored_size: i64,
+    encoding: StorageEncoding,
+    mime_type: Option<&str>,
+    checksum: Option<&str>,
+) -> anyhow::Result<FileInfo> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        INSERT INTO files (user_id, filename, original_filename, file_path, disk_path, file_size, stored_size, mime_type, checksum, storage_tier, storage_encoding, is_deleted, tenant_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, FALSE, (SELECT tenant_id FROM users WHERE id = $1))
+        RETURNING {}
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(filename)
+    .bind(original_filename)
+    .bind(file_path)
+    .bind(disk_path)
+    .bind(file_size)
+    .bind(stored_size)
+    .bind(mime_type)
+    .bind(checksum)
+    .bind(StorageTier::of_path(file_path).as_str())
+    .bind(encoding.as_str())
+    .fetch_one(pool)
+    .await?;
+
+    sqlx::query("UPDATE users SET storage_used = storage_used + $1 WHERE id = $2")
+        .bind(file.file_size)
+        .bind(file.user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(file)
+}
+
+
+
+pub async fn get_file_by_id(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(
+        &format!("SELECT {} FROM files WHERE id = $1", FILE_COLUMNS),
+    )
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn delete_file_record(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<()> {
+    let deleted: Option<(Uuid, i64)> = sqlx::query_as(
+        "DELETE FROM files WHERE id = $1 RETURNING user_id, file_size"
+    )
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some((user_id, file_size)) = deleted {
+        sqlx::query("UPDATE users SET storage_used = GREATEST(storage_used - $1, 0) WHERE id = $2")
+            .bind(file_size)
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+pub async fn get_files_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!("SELECT {} FROM files WHERE user_id = $1 ORDER BY created_at DESC", FILE_COLUMNS),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn sum_user_file_sizes(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<i64> {
+    let total = sqlx::query_scalar::<_, i64>("SELECT COALESCE(SUM(file_size), 0)::BIGINT FROM files WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(total)
+}
+
+pub async fn set_file_stored_size(pool: &PgPool, file_id: &Uuid, stored_size: i64) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET stored_size = $1 WHERE id = $2")
+        .bind(stored_size)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_all_files(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!("SELECT {} FROM files WHERE user_id = $1 AND is_deleted = FALSE ORDER BY created_at DESC, id", FILE_COLUMNS),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_files_by_metadata(pool: &PgPool, user_id: &Uuid, filters: &[(String, String)]) -> anyhow::Result<Vec<FileInfo>> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM files WHERE user_id = ", FILE_COLUMNS));
+    query.push_bind(*user_id).push(" AND is_deleted = FALSE");
+    for (key, value) in filters {
+        query
+            .push(" AND custom_metadata ->> ")
+            .push_bind(key.clone())
+            .push(" = ")
+            .push_bind(value.clone());
+    }
+    query.push(" ORDER BY created_at DESC, id");
+
+    let files = query.build_query_as::<FileInfo>().fetch_all(pool).await?;
+
+    Ok(files)
+}
+
+pub async fn update_file_custom_metadata(
+    pool: &PgPool,
+    file_id: &Uuid,
+    set: &serde_json::Value,
+    remove: &[String],
+    max_keys: i64,
+) -> anyhow::Result<Option<FileInfo>> {
+    let merged = "(custom_metadata - $3::TEXT[]) || $2::JSONB";
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        UPDATE files SET custom_metadata = {0}, updated_at = NOW()
+        WHERE id = $1 AND (SELECT COUNT(*) FROM JSONB_OBJECT_KEYS({0})) <= $4
+        RETURNING {1}
+        "#,
+        merged, FILE_COLUMNS
+    ))
+    .bind(file_id)
+    .bind(set)
+    .bind(remove)
+    .bind(max_keys)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn search_files_admin(pool: &PgPool, filter: &AdminFileSearchQuery) -> anyhow::Result<Vec<FileInfo>> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM files WHERE TRUE", FILE_COLUMNS));
+
+    if let Some(user_id) = filter.user_id {
+        query.push(" AND user_id = ").push_bind(user_id);
+    }
+    if let Some(username) = &filter.username {
+        query.push(" AND user_id = (SELECT id FROM users WHERE username = ").push_bind(username.clone()).push(")");
+    }
+    if let Some(min_size) = filter.min_size {
+        query.push(" AND file_size >= ").push_bind(min_size);
+    }
+    if let Some(max_size) = filter.max_size {
+        query.push(" AND file_size <= ").push_bind(max_size);
+    }
+    if let Some(mime_type) = &filter.mime_type {
+        query.push(" AND mime_type LIKE ").push_bind(format!("{}%", mime_type));
+    }
+    if let Some(days) = filter.older_than_days {
+        query.push(" AND created_at < NOW() - ").push_bind(days as f64).push(" * INTERVAL '1 day'");
+    }
+    if let Some(days) = filter.newer_than_days {
+        query.push(" AND created_at >= NOW() - ").push_bind(days as f64).push(" * INTERVAL '1 day'");
+    }
+    if let Some(disk_path) = &filter.disk_path {
+        query.push(" AND disk_path = ").push_bind(disk_path.clone());
+    }
+    if !filter.include_deleted.unwrap_or(true) {
+        query.push(" AND is_deleted = FALSE");
+    }
+    if let Some(quarantined) = filter.quarantined {
+        query.push(" AND is_quarantined = ").push_bind(quarantined);
+    }
+
+    query.push(" ORDER BY file_size DESC");
+    query.push(" LIMIT ").push_bind(filter.limit.unwrap_or(100).clamp(1, 1000));
+    query.push(" OFFSET ").push_bind(filter.offset.unwrap_or(0).max(0));
+
+    let files = query.build_query_as::<FileInfo>().fetch_all(pool).await?;
+
+    Ok(files)
+}
+
+fn search_prefix_query(term: &str) -> Option<String> {
+    let words: Vec<String> = term
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("{}:*", word))
+        .collect();
+
+    if words.is_empty() {
+        None
+    } else {
+        Some(words.join(" & "))
+    }
+}
+
+pub async fn search_files(
+    pool: &PgPool,
+    user_id: &Uuid,
+    terms: &[String],
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let mut query = QueryBuilder::<Postgres>::new(format!("SELECT {} FROM files WHERE user_id = ", FILE_COLUMNS));
+    query.push_bind(*user_id).push(" AND is_deleted = FALSE");
+
+    for term in terms {
+        let pattern = format!("%{}%", term.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+        query.push(" AND (LOWER(original_filename) LIKE ").push_bind(pattern);
+        query.push(" OR tags @> ARRAY[").push_bind(term.clone()).push("]");
+        if let Some(prefix) = search_prefix_query(term) {
+            query
+                .push(format!(" OR {} @@ to_tsquery('simple', ", FILE_NAME_SEARCH_VECTOR))
+                .push_bind(prefix.clone())
+                .push(")");
+            query
+                .push(format!(
+                    " OR EXISTS (SELECT 1 FROM photo_metadata WHERE photo_metadata.file_id = files.id AND {} @@ to_tsquery('simple', ",
+                    PHOTO_METADATA_SEARCH_VECTOR
+                ))
+                .push_bind(prefix)
+                .push("))");
+        }
+        query.push(")");
+    }
+
+    let ranking: Vec<String> = terms.iter().filter_map(|term| search_prefix_query(term)).collect();
+    if ranking.is_empty() {
+        query.push(" ORDER BY updated_at DESC");
+    } else {
+        query
+            .push(format!(" ORDER BY ts_rank({}, to_tsquery('simple', ", FILE_NAME_SEARCH_VECTOR))
+            .push_bind(ranking.join(" & "))
+            .push(")) DESC, updated_at DESC");
+    }
+    query.push(" LIMIT ").push_bind(limit.unwrap_or(50).clamp(1, 200));
+    query.push(" OFFSET ").push_bind(offset.unwrap_or(0).max(0));
+
+    let files = query.build_query_as::<FileInfo>().fetch_all(pool).await?;
+
+    Ok(files)
+}
+
+const SEARCH_INDEXES: &[&str] = &["idx_files_name_trgm", "idx_file_contents_search"];
+
+const INDEXABLE_FILE_CONDITION: &str = r#"f.is_deleted = FALSE AND f.file_size <= $3
+              AND (f.mime_type LIKE ANY($1) OR LOWER(SUBSTRING(f.original_filename FROM '\.([^.]+)$')) = ANY($2))"#;
+
+const INDEX_OUTDATED_CONDITION: &str = "c.file_id IS NULL OR c.checksum IS DISTINCT FROM f.checksum OR c.stale";
+
+pub async fn claim_files_for_indexing(
+    pool: &PgPool,
+    mime_patterns: &[&str],
+    extensions: &[&str],
+    max_size: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        WITH candidates AS (
+            SELECT f.id, f.checksum
+            FROM files f
+            LEFT JOIN file_contents c ON c.file_id = f.id
+            WHERE {}
+              AND ({}
+                   OR (c.status = 'indexing' AND c.updated_at < NOW() - INTERVAL '1 hour')
+                   OR (c.status = 'failed' AND c.updated_at < NOW() - INTERVAL '1 day'))
+            ORDER BY f.created_at
+            LIMIT $4
+            FOR UPDATE OF f SKIP LOCKED
+        ),
+        claimed AS (
+            INSERT INTO file_contents (file_id, checksum, status, updated_at)
+            SELECT id, checksum, 'indexing', NOW() FROM candidates
+            ON CONFLICT (file_id) DO UPDATE
+            SET checksum = EXCLUDED.checksum, status = 'indexing', error = NULL, stale = FALSE, updated_at = NOW()
+            RETURNING file_id
+        )
+        SELECT {} FROM files WHERE id IN (SELECT file_id FROM claimed)
+        "#,
+        INDEXABLE_FILE_CONDITION, INDEX_OUTDATED_CONDITION, FILE_COLUMNS
+    ))
+    .bind(mime_patterns)
+    .bind(extensions)
+    .bind(max_size)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn mark_file_contents_stale(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query("UPDATE file_contents SET stale = TRUE WHERE status <> 'indexing'")
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn count_files_pending_indexing(
+    pool: &PgPool,
+    mime_patterns: &[&str],
+    extensions: &[&str],
+    max_size: i64,
+) -> anyhow::Result<i64> {
+    let count: i64 = sqlx::query_scalar(&format!(
+        r#"
+        SELECT COUNT(*)
+        FROM files f
+        LEFT JOIN file_contents c ON c.file_id = f.id
+        WHERE {} AND ({})
+        "#,
+        INDEXABLE_FILE_CONDITION, INDEX_OUTDATED_CONDITION
+    ))
+    .bind(mime_patterns)
+    .bind(extensions)
+    .bind(max_size)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+pub async fn reindex_search_indexes(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let mut rebuilt = Vec::new();
+    for index in SEARCH_INDEXES {
+        let exists: bool = sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(index)
+            .fetch_one(pool)
+            .await?;
+        if exists {
+            sqlx::query(&format!("REINDEX INDEX CONCURRENTLY {}", index)).execute(pool).await?;
+            rebuilt.push(index.to_string());
+        }
+    }
+
+    Ok(rebuilt)
+}
+
+pub async fn get_search_index_stats(
+    pool: &PgPool,
+    mime_patterns: &[&str],
+    extensions: &[&str],
+    max_size: i64,
+) -> anyhow::Result<SearchIndexStats> {
+    let stats = sqlx::query_as::<_, SearchIndexStats>(&format!(
+        r#"
+        SELECT to_regclass('idx_files_name_trgm') IS NOT NULL AS filename_index_available,
+               COALESCE(pg_relation_size(to_regclass('idx_files_name_trgm')), 0) AS filename_index_bytes,
+               pg_total_relation_size('file_contents') AS content_index_bytes,
+               (SELECT COUNT(*) FROM file_contents WHERE status = 'indexed') AS indexed_files,
+               (SELECT COUNT(*) FROM file_contents WHERE status = 'skipped') AS skipped_files,
+               (SELECT COUNT(*) FROM file_contents WHERE status = 'failed') AS failed_files,
+               pending.count AS pending_files,
+               pending.oldest AS oldest_pending_at,
+               EXTRACT(EPOCH FROM NOW() - pending.oldest)::BIGINT AS lag_seconds,
+               (SELECT MAX(indexed_at) FROM file_contents) AS last_indexed_at
+        FROM (
+            SELECT COUNT(*) AS count, MIN(f.updated_at) AS oldest
+            FROM files f
+            LEFT JOIN file_contents c ON c.file_id = f.id
+            WHERE {} AND ({})
+        ) pending
+        "#,
+        INDEXABLE_FILE_CONDITION, INDEX_OUTDATED_CONDITION
+    ))
+    .bind(mime_patterns)
+    .bind(extensions)
+    .bind(max_size)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(stats)
+}
+
+pub async fn claim_videos_for_metadata(pool: &PgPool, extensions: &[&str], limit: i64) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        WITH candidates AS (
+            SELECT f.id, f.checksum
+            FROM files f
+            LEFT JOIN file_metadata m ON m.file_id = f.id
+            WHERE f.is_deleted = FALSE
+              AND f.is_quarantined = FALSE
+              AND (f.mime_type LIKE 'video/%'
+                   OR LOWER(SUBSTRING(f.original_filename FROM '\.([^.]+)$')) = ANY($1))
+              AND (m.file_id IS NULL
+                   OR m.checksum IS DISTINCT FROM f.checksum
+                   OR (m.status = 'processing' AND m.updated_at < NOW() - INTERVAL '1 hour')
+                   OR (m.status = 'failed' AND m.updated_at < NOW() - INTERVAL '1 day'))
+            ORDER BY f.created_at
+            LIMIT $2
+            FOR UPDATE OF f SKIP LOCKED
+        ),
+        claimed AS (
+            INSERT INTO file_metadata (file_id, checksum, status, updated_at)
+            SELECT id, checksum, 'processing', NOW() FROM candidates
+            ON CONFLICT (file_id) DO UPDATE
+            SET checksum = EXCLUDED.checksum, status = 'processing', error = NULL, updated_at = NOW()
+            RETURNING file_id
+        )
+        SELECT {} FROM files WHERE id IN (SELECT file_id FROM claimed)
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(extensions)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn finish_file_metadata(pool: &PgPool, metadata: &FileMetadata) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE file_metadata
+        SET status = $2, duration_seconds = $3, width = $4, height = $5, video_codec = $6,
+            poster_path = $7, error = $8, updated_at = NOW()
+        WHERE file_id = $1
+        "#,
+    )
+    .bind(metadata.file_id)
+    .bind(&metadata.status)
+    .bind(metadata.duration_seconds)
+    .bind(metadata.width)
+    .bind(metadata.height)
+    .bind(&metadata.video_codec)
+    .bind(&metadata.poster_path)
+    .bind(&metadata.error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_file_metadata(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<Option<FileMetadata>> {
+    let metadata = sqlx::query_as::<_, FileMetadata>(
+        r#"
+        SELECT file_id, status, duration_seconds, width, height, video_codec, poster_path, error, updated_at
+        FROM file_metadata WHERE file_id = $1
+        "#,
+    )
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(metadata)
+}
+
+pub async fn get_files_to_scrub(pool: &PgPool, recheck_after_days: i32, exclude: &[Uuid], limit: i64) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {}
+        FROM files
+        LEFT JOIN file_scrubs ON file_scrubs.file_id = files.id
+        WHERE is_deleted = FALSE
+          AND checksum IS NOT NULL
+          AND (checked_at IS NULL OR checked_at < NOW() - make_interval(days => $1))
+          AND NOT (id = ANY($2))
+        ORDER BY checked_at NULLS FIRST, created_at
+        LIMIT $3
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(recheck_after_days)
+    .bind(exclude)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_files_after(pool: &PgPool, after: Option<Uuid>, limit: i64) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {}
+        FROM files
+        WHERE ($1::UUID IS NULL OR id > $1)
+        ORDER BY id
+        LIMIT $2
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(after)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_stored_file_paths(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let paths = sqlx::query_scalar::<_, String>("SELECT file_path FROM files")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(paths)
+}
+
+pub async fn set_file_size(pool: &PgPool, file_id: &Uuid, file_size: i64) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET file_size = $2 WHERE id = $1")
+        .bind(file_id)
+        .bind(file_size)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn record_orphan_gc_run(pool: &PgPool, run: &OrphanGcRun) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO orphan_gc_runs (id, action, scanned_blobs, orphaned_blobs, orphaned_bytes, reclaimed_bytes, failed_blobs, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(run.id)
+    .bind(&run.action)
+    .bind(run.scanned_blobs)
+    .bind(run.orphaned_blobs)
+    .bind(run.orphaned_bytes)
+    .bind(run.reclaimed_bytes)
+    .bind(run.failed_blobs)
+    .bind(run.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_orphan_gc_runs(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<OrphanGcRun>> {
+    let runs = sqlx::query_as::<_, OrphanGcRun>(
+        r#"
+        SELECT id, action, scanned_blobs, orphaned_blobs, orphaned_bytes, reclaimed_bytes, failed_blobs, created_at
+        FROM orphan_gc_runs
+        ORDER BY created_at DESC
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(runs)
+}
+
+pub async fn get_orphan_gc_reclaimed_bytes(pool: &PgPool) -> anyhow::Result<i64> {
+    let bytes = sqlx::query_scalar::<_, Option<i64>>("SELECT SUM(reclaimed_bytes)::BIGINT FROM orphan_gc_runs")
+        .fetch_one(pool)
+        .await?;
+
+    Ok(bytes.unwrap_or(0))
+}
+
+pub async fn get_lifecycle_rules(pool: &PgPool) -> anyhow::Result<Vec<LifecycleRule>> {
+    let rules = sqlx::query_as::<_, LifecycleRule>(
+        "SELECT id, name, idle_days, min_size, mime_prefix, is_enabled, last_run_at, created_at FROM lifecycle_rules ORDER BY created_at",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rules)
+}
+
+pub async fn create_lifecycle_rule(
+    pool: &PgPool,
+    name: &str,
+    idle_days: i32,
+    min_size: Option<i64>,
+    mime_prefix: Option<&str>,
+    is_enabled: bool,
+) -> anyhow::Result<LifecycleRule> {
+    let rule = sqlx::query_as::<_, LifecycleRule>(
+        r#"
+        INSERT INTO lifecycle_rules (name, idle_days, min_size, mime_prefix, is_enabled)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, idle_days, min_size, mime_prefix, is_enabled, last_run_at, created_at
+        "#,
+    )
+    .bind(name)
+    .bind(idle_days)
+    .bind(min_size)
+    .bind(mime_prefix)
+    .bind(is_enabled)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn update_lifecycle_rule(
+    pool: &PgPool,
+    rule_id: &Uuid,
+    name: &str,
+    idle_days: i32,
+    min_size: Option<i64>,
+    mime_prefix: Option<&str>,
+    is_enabled: bool,
+) -> anyhow::Result<Option<LifecycleRule>> {
+    let rule = sqlx::query_as::<_, LifecycleRule>(
+        r#"
+        UPDATE lifecycle_rules
+        SET name = $2, idle_days = $3, min_size = $4, mime_prefix = $5, is_enabled = $6
+        WHERE id = $1
+        RETURNING id, name, idle_days, min_size, mime_prefix, is_enabled, last_run_at, created_at
+        "#,
+    )
+    .bind(rule_id)
+    .bind(name)
+    .bind(idle_days)
+    .bind(min_size)
+    .bind(mime_prefix)
+    .bind(is_enabled)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(rule)
+}
+
+pub async fn delete_lifecycle_rule(pool: &PgPool, rule_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM lifecycle_rules WHERE id = $1")
+        .bind(rule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn mark_lifecycle_rule_run(pool: &PgPool, rule_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("UPDATE lifecycle_rules SET last_run_at = NOW() WHERE id = $1")
+        .bind(rule_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_lifecycle_candidates(
+    pool: &PgPool,
+    rule: &LifecycleRule,
+    exclude: &[Uuid],
+    limit: i64,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {}
+        FROM files
+        WHERE is_deleted = FALSE
+          AND storage_tier = $1
+          AND COALESCE(last_accessed_at, created_at) < NOW() - make_interval(days => $2)
+          AND ($3::BIGINT IS NULL OR file_size >= $3)
+          AND ($4::TEXT IS NULL OR mime_type LIKE $4 || '%')
+          AND NOT (id = ANY($5))
+        ORDER BY COALESCE(last_accessed_at, created_at)
+        LIMIT $6
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(StorageTier::Hot.as_str())
+    .bind(rule.idle_days)
+    .bind(rule.min_size)
+    .bind(&rule.mime_prefix)
+    .bind(exclude)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn set_file_storage(
+    pool: &PgPool,
+    file_id: &Uuid,
+    old_path: &str,
+    file_path: &str,
+    disk_path: &str,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE files SET file_path = $3, disk_path = $4, storage_tier = $5 WHERE id = $1 AND file_path = $2",
+    )
+    .bind(file_id)
+    .bind(old_path)
+    .bind(file_path)
+    .bind(disk_path)
+    .bind(StorageTier::of_path(file_path).as_str())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_largest_file_on_disk(
+    pool: &PgPool,
+    disk_path: &str,
+    max_size: Option<i64>,
+    exclude: &[Uuid],
+) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {}
+        FROM files
+        WHERE disk_path = $1
+          AND ($2::BIGINT IS NULL OR file_size <= $2)
+          AND NOT (id = ANY($3))
+        ORDER BY file_size DESC
+        LIMIT 1
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(disk_path)
+    .bind(max_size)
+    .bind(exclude)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn count_files_on_disk(pool: &PgPool, disk_path: &str) -> anyhow::Result<i64> {
+    let count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM files WHERE disk_path = $1")
+        .bind(disk_path)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(count)
+}
+
+pub async fn get_pending_upload_bytes(pool: &PgPool, owner_id: &Uuid) -> anyhow::Result<i64> {
+    let bytes = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT (
+            SELECT COALESCE(SUM(c.total_size), 0)
+            FROM chunked_uploads c
+            LEFT JOIN folders f ON f.id = c.folder_id
+            WHERE c.is_completed = FALSE AND c.status <> 'failed' AND c.expires_at > NOW() AND COALESCE(f.user_id, c.user_id) = $1
+        )::BIGINT + (
+            SELECT COALESCE(SUM(p.size), 0)
+            FROM s3_multipart_parts p
+            JOIN s3_multipart_uploads u ON u.id = p.upload_id
+            WHERE u.user_id = $1 AND u.expires_at > NOW()
+        )::BIGINT
+        "#,
+    )
+    .bind(owner_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(bytes)
+}
+
+pub async fn count_active_uploads_on_disk(pool: &PgPool, disk_path: &str) -> anyhow::Result<i64> {
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM chunked_uploads WHERE disk_path = $1 AND is_completed = FALSE",
+    )
+    .bind(disk_path)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
+
+pub async fn save_storage_path_change(pool: &PgPool, path: &str, is_removed: bool) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO storage_path_changes (path, is_removed)
+        VALUES ($1, $2)
+        ON CONFLICT (path) DO UPDATE SET is_removed = EXCLUDED.is_removed, updated_at = NOW()
+        "#,
+    )
+    .bind(path)
+    .bind(is_removed)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_storage_path_changes(pool: &PgPool) -> anyhow::Result<Vec<(String, bool)>> {
+    let changes = sqlx::query_as::<_, (String, bool)>("SELECT path, is_removed FROM storage_path_changes ORDER BY updated_at")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(changes)
+}
+
+pub async fn move_file_poster(pool: &PgPool, file_id: &Uuid, old_path: &str, poster_path: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE file_metadata SET poster_path = $3 WHERE file_id = $1 AND poster_path = $2")
+        .bind(file_id)
+        .bind(old_path)
+        .bind(poster_path)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn record_file_scrub(
+    pool: &PgPool,
+    file_id: &Uuid,
+    status: &str,
+    expected_checksum: &str,
+    actual_checksum: Option<&str>,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO file_scrubs (file_id, status, expected_checksum, actual_checksum, error, checked_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        ON CONFLICT (file_id) DO UPDATE
+        SET status = EXCLUDED.status, expected_checksum = EXCLUDED.expected_checksum,
+            actual_checksum = EXCLUDED.actual_checksum, error = EXCLUDED.error, checked_at = NOW()
+        "#,
+    )
+    .bind(file_id)
+    .bind(status)
+    .bind(expected_checksum)
+    .bind(actual_checksum)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_scrub_findings(pool: &PgPool) -> anyhow::Result<Vec<ScrubFinding>> {
+    let findings = sqlx::query_as::<_, ScrubFinding>(
+        r#"
+        SELECT s.file_id, f.user_id, u.username, f.original_filename, f.file_path, s.status,
+               s.expected_checksum, s.actual_checksum, s.error, s.checked_at
+        FROM file_scrubs s
+        JOIN files f ON f.id = s.file_id
+        JOIN users u ON u.id = f.user_id
+        WHERE s.status <> 'ok' AND f.is_deleted = FALSE
+        ORDER BY s.checked_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(findings)
+}
+
+pub async fn finish_file_indexing(
+    pool: &PgPool,
+    file_id: &Uuid,
+    status: &str,
+    content: Option<&str>,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE file_contents
+        SET status = $2, content = $3, error = $4, indexed_at = NOW(), updated_at = NOW()
+        WHERE file_id = $1
+        "#,
+    )
+    .bind(file_id)
+    .bind(status)
+    .bind(content)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn search_file_contents(
+    pool: &PgPool,
+    user_id: &Uuid,
+    query: &str,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> anyhow::Result<Vec<ContentSearchResult>> {
+    let results = sqlx::query_as::<_, ContentSearchResult>(&format!(
+        r#"
+        WITH matches AS (
+            SELECT c.file_id, c.content, ts_rank(c.search_vector, q.query) AS rank
+            FROM file_contents c
+            JOIN files f ON f.id = c.file_id
+            CROSS JOIN websearch_to_tsquery('english', $2) AS q(query)
+            WHERE f.user_id = $1 AND f.is_deleted = FALSE AND c.search_vector @@ q.query
+            ORDER BY rank DESC, f.updated_at DESC
+            LIMIT $3 OFFSET $4
+        )
+        SELECT {},
+               ts_headline('english', m.content, websearch_to_tsquery('english', $2),
+                           'MaxFragments=2, MinWords=5, MaxWords=20, FragmentDelimiter=" ... ", StartSel=**, StopSel=**') AS snippet,
+               m.rank
+        FROM matches m
+        JOIN files ON files.id = m.file_id
+        ORDER BY m.rank DESC, files.updated_at DESC
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(query)
+    .bind(limit.unwrap_or(50).clamp(1, 200))
+    .bind(offset.unwrap_or(0).max(0))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(results)
+}
+
+async fn sync_file_tags(conn: &mut sqlx::PgConnection, file_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO tags (user_id, name)
+        SELECT user_id, UNNEST(tags) FROM files WHERE id = $1
+        ON CONFLICT (user_id, name) DO NOTHING
+        "#,
+    )
+    .bind(file_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        WITH removed AS (
+            DELETE FROM file_tags ft
+            USING tags t, files f
+            WHERE ft.file_id = $1 AND t.id = ft.tag_id AND f.id = ft.file_id
+              AND (t.user_id <> f.user_id OR NOT t.name = ANY(f.tags))
+            RETURNING ft.tag_id
+        )
+        DELETE FROM tags t
+        WHERE t.id IN (SELECT tag_id FROM removed)
+          AND NOT EXISTS (SELECT 1 FROM file_tags ft WHERE ft.tag_id = t.id AND ft.file_id <> $1)
+        "#,
+    )
+    .bind(file_id)
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO file_tags (file_id, tag_id)
+        SELECT f.id, t.id FROM files f JOIN tags t ON t.user_id = f.user_id AND t.name = ANY(f.tags)
+        WHERE f.id = $1
+        ON CONFLICT (file_id, tag_id) DO NOTHING
+        "#,
+    )
+    .bind(file_id)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn set_file_tags(pool: &PgPool, file_id: &Uuid, tags: &[String]) -> anyhow::Result<Option<FileInfo>> {
+    let mut tx = pool.begin().await?;
+
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        "UPDATE files SET tags = $1, updated_at = NOW() WHERE id = $2 RETURNING {}",
+        FILE_COLUMNS
+    ))
+    .bind(tags)
+    .bind(file_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if file.is_some() {
+        sync_file_tags(&mut tx, file_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(file)
+}
+
+pub async fn add_file_tags(pool: &PgPool, file_id: &Uuid, tags: &[String], max_tags: usize) -> anyhow::Result<Option<FileInfo>> {
+    let mut tx = pool.begin().await?;
+
+    let merged = "tags || ARRAY(SELECT tag FROM UNNEST($2::TEXT[]) tag WHERE NOT tag = ANY(files.tags))";
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        "UPDATE files SET tags = {0}, updated_at = NOW() WHERE id = $1 AND CARDINALITY({0}) <= $3 RETURNING {1}",
+        merged, FILE_COLUMNS
+    ))
+    .bind(file_id)
+    .bind(tags)
+    .bind(max_tags as i32)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if file.is_some() {
+        sync_file_tags(&mut tx, file_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(file)
+}
+
+pub async fn remove_file_tag(pool: &PgPool, file_id: &Uuid, tag: &str) -> anyhow::Result<Option<FileInfo>> {
+    let mut tx = pool.begin().await?;
+
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        "UPDATE files SET tags = ARRAY_REMOVE(tags, $2), updated_at = NOW() WHERE id = $1 AND $2 = ANY(tags) RETURNING {}",
+        FILE_COLUMNS
+    ))
+    .bind(file_id)
+    .bind(tag)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if file.is_some() {
+        sync_file_tags(&mut tx, file_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(file)
+}
+
+pub async fn get_user_tags(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<TagSummary>> {
+    let tags = sqlx::query_as::<_, TagSummary>(
+        r#"
+        SELECT t.name, COUNT(f.id) AS file_count, t.created_at
+        FROM tags t
+        LEFT JOIN file_tags ft ON ft.tag_id = t.id
+        LEFT JOIN files f ON f.id = ft.file_id AND f.is_deleted = FALSE
+        WHERE t.user_id = $1
+        GROUP BY t.id
+        HAVING COUNT(ft.file_id) > 0
+        ORDER BY t.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tags)
+}
+
+pub async fn get_files_by_tag(pool: &PgPool, user_id: &Uuid, tag: &str) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files
+        WHERE is_deleted = FALSE AND id IN (
+            SELECT ft.file_id FROM file_tags ft JOIN tags t ON t.id = ft.tag_id
+            WHERE t.user_id = $1 AND t.name = $2
+        )
+        ORDER BY LOWER(original_filename), id
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(tag)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn star_file(pool: &PgPool, user_id: &Uuid, file_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO starred_files (user_id, file_id) VALUES ($1, $2) ON CONFLICT (user_id, file_id) DO NOTHING")
+        .bind(user_id)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn unstar_file(pool: &PgPool, user_id: &Uuid, file_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM starred_files WHERE user_id = $1 AND file_id = $2")
+        .bind(user_id)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_starred_files(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files
+        JOIN (SELECT file_id, starred_at FROM starred_files WHERE user_id = $1) s ON s.file_id = files.id
+        WHERE files.is_deleted = FALSE
+        ORDER BY s.starred_at DESC
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_camera_upload_template(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<String>> {
+    let template: Option<(String,)> = sqlx::query_as("SELECT template FROM camera_upload_settings WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(template.map(|(template,)| template))
+}
+
+pub async fn get_camera_upload_settings(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<CameraUploadSettings>> {
+    let settings = sqlx::query_as::<_, CameraUploadSettings>(
+        "SELECT template, updated_at FROM camera_upload_settings WHERE user_id = $1"
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+pub async fn set_camera_upload_template(pool: &PgPool, user_id: &Uuid, template: &str) -> anyhow::Result<CameraUploadSettings> {
+    let settings = sqlx::query_as::<_, CameraUploadSettings>(
+        r#"
+        INSERT INTO camera_upload_settings (user_id, template)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET template = EXCLUDED.template, updated_at = NOW()
+        RETURNING template, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(template)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+pub async fn delete_camera_upload_settings(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM camera_upload_settings WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn reassign_file(pool: &PgPool, file_id: &Uuid, new_user_id: &Uuid) -> anyhow::Result<bool> {
+    let mut tx = pool.begin().await?;
+
+    let previous: Option<(Uuid, i64)> = sqlx::query_as(
+        "SELECT user_id, file_size FROM files WHERE id = $1 FOR UPDATE"
+    )
+    .bind(file_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (old_user_id, file_size) = match previous {
+        Some(previous) => previous,
+        None => return Ok(false),
+    };
+
+    sqlx::query(
+        "UPDATE files SET user_id = $1, tenant_id = (SELECT tenant_id FROM users WHERE id = $1), updated_at = NOW() WHERE id = $2"
+    )
+    .bind(new_user_id)
+    .bind(file_id)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE shared_links SET tenant_id = (SELECT tenant_id FROM users WHERE id = $1) WHERE file_id = $2")
+        .bind(new_user_id)
+        .bind(file_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sync_file_tags(&mut tx, file_id).await?;
+
+    sqlx::query("UPDATE users SET storage_used = GREATEST(storage_used - $1, 0) WHERE id = $2")
+        .bind(file_size)
+        .bind(old_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE users SET storage_used = storage_used + $1 WHERE id = $2")
+        .bind(file_size)
+        .bind(new_user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(true)
+}
+
+pub async fn set_file_quarantined(pool: &PgPool, file_id: &Uuid, quarantined: bool) -> anyhow::Result<bool> {
+    let result = sqlx::query("UPDATE files SET is_quarantined = $1, updated_at = NOW() WHERE id = $2")
+        .bind(quarantined)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_deleted_files(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!("SELECT {} FROM files WHERE user_id = $1 AND is_deleted = TRUE ORDER BY deleted_at DESC", FILE_COLUMNS),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn set_file_checksum(pool: &PgPool, file_id: &Uuid, checksum: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET checksum = $1 WHERE id = $2")
+        .bind(checksum)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn touch_file_access(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET last_accessed_at = NOW() WHERE id = $1")
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_largest_files(pool: &PgPool, user_id: Option<&Uuid>, limit: i64) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!(
+            "SELECT {} FROM files WHERE ($1::UUID IS NULL OR user_id = $1) AND is_deleted = FALSE ORDER BY file_size DESC LIMIT $2",
+            FILE_COLUMNS
+        ),
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_stale_files(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    unaccessed_for_seconds: i64,
+    limit: i64,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!(
+            r#"
+            SELECT {} FROM files
+            WHERE ($1::UUID IS NULL OR user_id = $1)
+              AND is_deleted = FALSE
+              AND COALESCE(last_accessed_at, created_at) < NOW() - $2 * INTERVAL '1 second'
+            ORDER BY COALESCE(last_accessed_at, created_at), file_size DESC
+            LIMIT $3
+            "#,
+            FILE_COLUMNS
+        ),
+    )
+    .bind(user_id)
+    .bind(unaccessed_for_seconds as f64)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_expired_trash_files(pool: &PgPool, retention_days: i64) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!(
+            "SELECT {} FROM files WHERE is_deleted = TRUE AND deleted_at < NOW() - $1 * INTERVAL '1 day' ORDER BY deleted_at",
+            FILE_COLUMNS
+        ),
+    )
+    .bind(retention_days as f64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn soft_delete_file(pool: &PgPool, file_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE files SET is_deleted = TRUE, deleted_at = NOW(), updated_at = NOW() WHERE id = $1 AND user_id = $2 AND is_deleted = FALSE"
+    )
+    .bind(file_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn restore_file(pool: &PgPool, file_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE files SET is_deleted = FALSE, deleted_at = NULL, updated_at = NOW() WHERE id = $1 AND user_id = $2 AND is_deleted = TRUE"
+    )
+    .bind(file_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+
+
+const SHARED_LINK_COLUMNS: &str = "s.id, s.file_id, s.token, s.expires_at, s.is_read_only, s.is_encrypted, s.encryption_metadata, \
+    s.egress_limit_bytes, CASE WHEN s.egress_month = date_trunc('month', NOW() AT TIME ZONE 'UTC')::date THEN s.egress_bytes ELSE 0 END AS egress_used_bytes, \
+    s.password_hash, s.password_hash IS NOT NULL AS password_protected, s.max_downloads, s.download_count, s.created_at";
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_shared_link(
+    pool: &PgPool,
+    file_id: &Uuid,
+    token: &str,
+    expires_at: Option<DateTime<Utc>>,
+    encryption_metadata: Option<&serde_json::Value>,
+    egress_limit_bytes: Option<i64>,
+    password_hash: Option<&str>,
+    max_downloads: Option<i32>,
+) -> anyhow::Result<SharedLink> {
+    let link = sqlx::query_as::<_, SharedLink>(&format!(
+        r#"
+        INSERT INTO shared_links AS s (file_id, token, expires_at, is_read_only, is_encrypted, encryption_metadata, egress_limit_bytes, password_hash, max_downloads, tenant_id)
+        VALUES ($1, $2, $3, TRUE, $4, $5, $6, $7, $8, (SELECT tenant_id FROM files WHERE id = $1))
+        RETURNING {}
+        "#,
+        SHARED_LINK_COLUMNS
+    ))
+    .bind(file_id)
+    .bind(token)
+    .bind(expires_at)
+    .bind(encryption_metadata.is_some())
+    .bind(encryption_metadata)
+    .bind(egress_limit_bytes)
+    .bind(password_hash)
+    .bind(max_downloads)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(link)
+}
+
+pub async fn get_shared_links_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<SharedLink>> {
+    let links = sqlx::query_as::<_, SharedLink>(&format!(
+        r#"
+        SELECT {}
+        FROM shared_links s
+        JOIN files f ON f.id = s.file_id
+        WHERE f.user_id = $1
+        ORDER BY s.created_at DESC
+        "#,
+        SHARED_LINK_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(links)
+}
+
+pub async fn get_shared_link_for_user(pool: &PgPool, share_id: &Uuid, user_id: &Uuid) -> anyhow::Result<Option<SharedLink>> {
+    let link = sqlx::query_as::<_, SharedLink>(&format!(
+        r#"
+        SELECT {}
+        FROM shared_links s
+        JOIN files f ON f.id = s.file_id
+        WHERE s.id = $1 AND f.user_id = $2
+        "#,
+        SHARED_LINK_COLUMNS
+    ))
+    .bind(share_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(link)
+}
+
+pub async fn get_active_shared_link_by_token(pool: &PgPool, token: &str) -> anyhow::Result<Option<SharedLink>> {
+    let link = sqlx::query_as::<_, SharedLink>(&format!(
+        r#"
+        SELECT {}
+        FROM shared_links s
+        JOIN files f ON f.id = s.file_id
+        WHERE s.token = $1
+          AND (s.expires_at IS NULL OR s.expires_at > NOW())
+          AND (s.max_downloads IS NULL OR s.download_count < s.max_downloads)
+          AND f.is_deleted = FALSE
+        "#,
+        SHARED_LINK_COLUMNS
+    ))
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(link)
+}
+
+pub async fn claim_share_download(pool: &PgPool, share_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE shared_links SET download_count = download_count + 1
+        WHERE id = $1 AND (max_downloads IS NULL OR download_count < max_downloads)
+        "#,
+    )
+    .bind(share_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+const SNIPPET_COLUMNS: &str = "n.id, n.file_id, n.title, n.language, n.burn_after_reading, s.token, s.expires_at, \
+    s.download_count AS views, n.created_at";
+
+pub async fn create_snippet(
+    pool: &PgPool,
+    user_id: &Uuid,
+    file_id: &Uuid,
+    share_id: &Uuid,
+    title: &str,
+    language: Option<&str>,
+    burn_after_reading: bool,
+) -> anyhow::Result<Snippet> {
+    let snippet = sqlx::query_as::<_, Snippet>(&format!(
+        r#"
+        WITH n AS (
+            INSERT INTO snippets (user_id, file_id, share_id, title, language, burn_after_reading)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+        )
+        SELECT {} FROM n JOIN shared_links s ON s.id = n.share_id
+        "#,
+        SNIPPET_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(file_id)
+    .bind(share_id)
+    .bind(title)
+    .bind(language)
+    .bind(burn_after_reading)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(snippet)
+}
+
+pub async fn get_snippets(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Snippet>> {
+    let snippets = sqlx::query_as::<_, Snippet>(&format!(
+        r#"
+        SELECT {} FROM snippets n
+        JOIN shared_links s ON s.id = n.share_id
+        WHERE n.user_id = $1
+        ORDER BY n.created_at DESC
+        "#,
+        SNIPPET_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(snippets)
+}
+
+pub async fn get_snippet(pool: &PgPool, snippet_id: &Uuid, user_id: &Uuid) -> anyhow::Result<Option<Snippet>> {
+    let snippet = sqlx::query_as::<_, Snippet>(&format!(
+        r#"
+        SELECT {} FROM snippets n
+        JOIN shared_links s ON s.id = n.share_id
+        WHERE n.id = $1 AND n.user_id = $2
+        "#,
+        SNIPPET_COLUMNS
+    ))
+    .bind(snippet_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(snippet)
+}
+
+pub async fn get_snippet_by_share(pool: &PgPool, share_id: &Uuid) -> anyhow::Result<Option<Snippet>> {
+    let snippet = sqlx::query_as::<_, Snippet>(&format!(
+        r#"
+        SELECT {} FROM snippets n
+        JOIN shared_links s ON s.id = n.share_id
+        WHERE n.share_id = $1
+        "#,
+        SNIPPET_COLUMNS
+    ))
+    .bind(share_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(snippet)
+}
+
+pub async fn get_spent_snippet_files(pool: &PgPool) -> anyhow::Result<Vec<Uuid>> {
+    let files: Vec<(Uuid,)> = sqlx::query_as(
+        r#"
+        SELECT n.file_id FROM snippets n
+        JOIN shared_links s ON s.id = n.share_id
+        WHERE s.expires_at <= NOW()
+           OR (s.max_downloads IS NOT NULL AND s.download_count >= s.max_downloads)
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files.into_iter().map(|(file_id,)| file_id).collect())
+}
+
+pub async fn record_file_activity(pool: &PgPool, user_id: &Uuid, file_id: &Uuid, filename: &str, action: &str) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO file_activity (user_id, file_id, filename, action) VALUES ($1, $2, $3, $4)")
+        .bind(user_id)
+        .bind(file_id)
+        .bind(filename)
+        .bind(action)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_file_activity(pool: &PgPool, user_id: &Uuid, limit: i64) -> anyhow::Result<Vec<FileActivity>> {
+    let activity = sqlx::query_as::<_, FileActivity>(
+        r#"
+        SELECT id, file_id, filename, action, occurred_at
+        FROM file_activity
+        WHERE user_id = $1
+        ORDER BY occurred_at DESC, id DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(activity)
+}
+
+pub async fn get_recent_files(pool: &PgPool, user_id: &Uuid, action: Option<&str>, limit: i64) -> anyhow::Result<Vec<RecentFile>> {
+    let files = sqlx::query_as::<_, RecentFile>(&format!(
+        r#"
+        SELECT {}, a.action, a.occurred_at
+        FROM files
+        JOIN (
+            SELECT DISTINCT ON (file_id) file_id, action, occurred_at
+            FROM file_activity
+            WHERE user_id = $1 AND ($2::TEXT IS NULL OR action = $2)
+            ORDER BY file_id, occurred_at DESC, id DESC
+        ) a ON a.file_id = files.id
+        WHERE files.is_deleted = FALSE
+        ORDER BY a.occurred_at DESC
+        LIMIT $3
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(action)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn delete_old_file_activity(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+    let result = sqlx::query("DELETE FROM file_activity WHERE occurred_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn record_file_download(pool: &PgPool, file_id: &Uuid, user_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("INSERT INTO file_downloads (file_id, user_id) VALUES ($1, $2)")
+        .bind(file_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn record_share_download(pool: &PgPool, share_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO share_download_counts (share_id, day, downloads)
+        VALUES ($1, (NOW() AT TIME ZONE 'UTC')::date, 1)
+        ON CONFLICT (share_id, day) DO UPDATE SET downloads = share_download_counts.downloads + 1
+        "#,
+    )
+    .bind(share_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_file_downloads(pool: &PgPool, file_id: &Uuid, limit: i64) -> anyhow::Result<Vec<FileDownload>> {
+    let downloads = sqlx::query_as::<_, FileDownload>(
+        r#"
+        SELECT d.user_id, u.username, d.downloaded_at
+        FROM file_downloads d
+        JOIN users u ON u.id = d.user_id
+        WHERE d.file_id = $1
+        ORDER BY d.downloaded_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(file_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(downloads)
+}
+
+pub async fn get_share_download_counts(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<Vec<ShareDownloadCount>> {
+    let counts = sqlx::query_as::<_, ShareDownloadCount>(
+        r#"
+        SELECT c.share_id, c.day, c.downloads
+        FROM share_download_counts c
+        JOIN shared_links s ON s.id = c.share_id
+        WHERE s.file_id = $1
+        ORDER BY c.day DESC, c.share_id
+        "#,
+    )
+    .bind(file_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(counts)
+}
+
+pub async fn delete_old_downloads(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+    let downloads = sqlx::query("DELETE FROM file_downloads WHERE downloaded_at < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+    let counts = sqlx::query("DELETE FROM share_download_counts WHERE day < $1::date")
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(downloads.rows_affected() + counts.rows_affected())
+}
+
+pub async fn get_folder_share_defaults(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<Option<FolderShareDefaults>> {
+    let defaults = sqlx::query_as::<_, FolderShareDefaults>(
+        "SELECT folder_id, expiry_days, password_required, max_downloads, updated_at FROM folder_share_defaults WHERE folder_id = $1"
+    )
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(defaults)
+}
+
+pub async fn get_effective_share_defaults(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<Option<FolderShareDefaults>> {
+    let defaults = sqlx::query_as::<_, FolderShareDefaults>(
+        r#"
+        WITH RECURSIVE ancestors AS (
+            SELECT id, parent_id, 0 AS depth FROM folders WHERE id = $1
+            UNION ALL
+            SELECT f.id, f.parent_id, a.depth + 1
+            FROM folders f JOIN ancestors a ON f.id = a.parent_id
+            WHERE a.depth < 256
+        )
+        SELECT d.folder_id, d.expiry_days, d.password_required, d.max_downloads, d.updated_at
+        FROM ancestors a
+        JOIN folder_share_defaults d ON d.folder_id = a.id
+        ORDER BY a.depth
+        LIMIT 1
+        "#,
+    )
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(defaults)
+}
+
+pub async fn set_folder_share_defaults(
+    pool: &PgPool,
+    folder_id: &Uuid,
+    expiry_days: Option<i32>,
+    password_required: bool,
+    max_downloads: Option<i32>,
+) -> anyhow::Result<FolderShareDefaults> {
+    let defaults = sqlx::query_as::<_, FolderShareDefaults>(
+        r#"
+        INSERT INTO folder_share_defaults (folder_id, expiry_days, password_required, max_downloads, updated_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        ON CONFLICT (folder_id) DO UPDATE
+        SET expiry_days = EXCLUDED.expiry_days, password_required = EXCLUDED.password_required,
+            max_downloads = EXCLUDED.max_downloads, updated_at = NOW()
+        RETURNING folder_id, expiry_days, password_required, max_downloads, updated_at
+        "#,
+    )
+    .bind(folder_id)
+    .bind(expiry_days)
+    .bind(password_required)
+    .bind(max_downloads)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(defaults)
+}
+
+pub async fn delete_folder_share_defaults(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM folder_share_defaults WHERE folder_id = $1")
+        .bind(folder_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_shared_link(pool: &PgPool, share_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "DELETE FROM shared_links s USING files f WHERE s.id = $1 AND s.file_id = f.id AND f.user_id = $2"
+    )
+    .bind(share_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_chunked_upload(
+    pool: &PgPool,
+    user_id: &Uuid,
+    filename: &str,
+    total_size: i64,
+    chunk_size: i64,
+    total_chunks: i32,
+    temp_path: &str,
+    disk_path: &str,
+    client_modified_at: Option<DateTime<Utc>>,
+    folder_id: Option<&Uuid>,
+    ttl_hours: i64,
+) -> anyhow::Result<ChunkedUpload> {
+    let upload = sqlx::query_as::<_, ChunkedUpload>(
+        r#"
+        INSERT INTO chunked_uploads (user_id, filename, total_size, chunk_size, total_chunks, temp_path, disk_path, client_modified_at, folder_id, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW() + make_interval(hours => $10))
+        RETURNING id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, status, client_modified_at, folder_id, expires_at, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(filename)
+    .bind(total_size)
+    .bind(chunk_size)
+    .bind(total_chunks)
+    .bind(temp_path)
+    .bind(disk_path)
+    .bind(client_modified_at)
+    .bind(folder_id)
+    .bind(ttl_hours as i32)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(upload)
+}
+
+pub async fn get_chunked_upload(pool: &PgPool, upload_id: &Uuid) -> anyhow::Result<Option<ChunkedUpload>> {
+    let upload = sqlx::query_as::<_, ChunkedUpload>(
+        "SELECT id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, status, client_modified_at, folder_id, expires_at, created_at, updated_at FROM chunked_uploads WHERE id = $1"
+    )
+    .bind(upload_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(upload)
+}
+
+pub async fn get_incomplete_chunked_uploads(pool: &PgPool) -> anyhow::Result<Vec<ChunkedUpload>> {
+    let uploads = sqlx::query_as::<_, ChunkedUpload>(
+        "SELECT id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, status, client_modified_at, folder_id, expires_at, created_at, updated_at FROM chunked_uploads WHERE is_completed = FALSE AND status <> 'failed'"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(uploads)
+}
+
+pub async fn delete_expired_chunked_uploads(pool: &PgPool) -> anyhow::Result<Vec<ChunkedUpload>> {
+    let uploads = sqlx::query_as::<_, ChunkedUpload>(
+        "DELETE FROM chunked_uploads WHERE is_completed = FALSE AND expires_at < NOW() RETURNING id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, status, client_modified_at, folder_id, expires_at, created_at, updated_at"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(uploads)
+}
+
+const S3_MULTIPART_UPLOAD_COLUMNS: &str = "id, bucket, object_key";
+
+pub async fn create_s3_access_key(
+    pool: &PgPool,
+    user_id: &Uuid,
+    access_key_id: &str,
+    secret_key: &str,
+    description: Option<&str>,
+) -> anyhow::Result<S3AccessKey> {
+    let key = sqlx::query_as::<_, S3AccessKey>(
+        r#"
+        INSERT INTO s3_access_keys (access_key_id, user_id, secret_key, description)
+        VALUES ($1, $2, $3, $4)
+        RETURNING access_key_id, user_id, secret_key, description, created_at, last_used_at
+        "#,
+    )
+    .bind(access_key_id)
+    .bind(user_id)
+    .bind(secret_key)
+    .bind(description)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(key)
+}
+
+pub async fn get_s3_access_key(pool: &PgPool, access_key_id: &str) -> anyhow::Result<Option<S3AccessKey>> {
+    let key = sqlx::query_as::<_, S3AccessKey>(
+        "SELECT access_key_id, user_id, secret_key, description, created_at, last_used_at FROM s3_access_keys WHERE access_key_id = $1",
+    )
+    .bind(access_key_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(key)
+}
+
+pub async fn get_s3_access_keys(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<S3AccessKey>> {
+    let keys = sqlx::query_as::<_, S3AccessKey>(
+        "SELECT access_key_id, user_id, secret_key, description, created_at, last_used_at FROM s3_access_keys WHERE user_id = $1 ORDER BY created_at",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(keys)
+}
+
+// Backup tools send a steady stream of requests, so the timestamp is only kept to the minute.
+pub async fn touch_s3_access_key(pool: &PgPool, access_key_id: &str) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE s3_access_keys SET last_used_at = NOW() WHERE access_key_id = $1 AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')",
+    )
+        .bind(access_key_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_s3_access_key(pool: &PgPool, access_key_id: &str, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM s3_access_keys WHERE access_key_id = $1 AND user_id = $2")
+        .bind(access_key_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn create_s3_multipart_upload(
+    pool: &PgPool,
+    user_id: &Uuid,
+    bucket: &str,
+    object_key: &str,
+    ttl_hours: i64,
+) -> anyhow::Result<S3MultipartUpload> {
+    let upload = sqlx::query_as::<_, S3MultipartUpload>(&format!(
+        r#"
+        INSERT INTO s3_multipart_uploads (user_id, bucket, object_key, expires_at)
+        VALUES ($1, $2, $3, NOW() + make_interval(hours => $4))
+        RETURNING {}
+        "#,
+        S3_MULTIPART_UPLOAD_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(bucket)
+    .bind(object_key)
+    .bind(ttl_hours as i32)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(upload)
+}
+
+pub async fn get_s3_multipart_upload(pool: &PgPool, upload_id: &Uuid, user_id: &Uuid) -> anyhow::Result<Option<S3MultipartUpload>> {
+    let upload = sqlx::query_as::<_, S3MultipartUpload>(&format!(
+        "SELECT {} FROM s3_multipart_uploads WHERE id = $1 AND user_id = $2 AND expires_at > NOW()",
+        S3_MULTIPART_UPLOAD_COLUMNS
+    ))
+    .bind(upload_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(upload)
+}
+
+pub async fn get_s3_multipart_parts(pool: &PgPool, upload_id: &Uuid) -> anyhow::Result<Vec<S3MultipartPart>> {
+    let parts = sqlx::query_as::<_, S3MultipartPart>(
+        "SELECT part_number, temp_path, size, etag FROM s3_multipart_parts WHERE upload_id = $1 ORDER BY part_number",
+    )
+    .bind(upload_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(parts)
+}
+
+// Stores a part and returns the temp file of the part it replaced, if the client re-sent one.
+pub async fn put_s3_multipart_part(
+    pool: &PgPool,
+    upload_id: &Uuid,
+    part_number: i32,
+    temp_path: &str,
+    size: i64,
+    etag: &str,
+    ttl_hours: i64,
+) -> anyhow::Result<Option<String>> {
+    let mut tx = pool.begin().await?;
+
+    let replaced = sqlx::query_scalar::<_, String>(
+        "DELETE FROM s3_multipart_parts WHERE upload_id = $1 AND part_number = $2 RETURNING temp_path",
+    )
+    .bind(upload_id)
+    .bind(part_number)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO s3_multipart_parts (upload_id, part_number, temp_path, size, etag) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(upload_id)
+    .bind(part_number)
+    .bind(temp_path)
+    .bind(size)
+    .bind(etag)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE s3_multipart_uploads SET expires_at = NOW() + make_interval(hours => $2) WHERE id = $1")
+        .bind(upload_id)
+        .bind(ttl_hours as i32)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(replaced)
+}
+
+// Returns the temp files of the deleted upload's parts.
+pub async fn delete_s3_multipart_upload(pool: &PgPool, upload_id: &Uuid) -> anyhow::Result<Vec<String>> {
+    let temp_paths = sqlx::query_scalar::<_, String>(
+        r#"
+        WITH deleted AS (DELETE FROM s3_multipart_uploads WHERE id = $1 RETURNING id)
+        SELECT temp_path FROM s3_multipart_parts WHERE upload_id IN (SELECT id FROM deleted)
+        "#,
+    )
+    .bind(upload_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(temp_paths)
+}
+
+// Returns how many uploads expired and the temp files of their parts.
+pub async fn delete_expired_s3_multipart_uploads(pool: &PgPool) -> anyhow::Result<(usize, Vec<String>)> {
+    let rows = sqlx::query_as::<_, (Uuid, Option<String>)>(
+        r#"
+        WITH deleted AS (DELETE FROM s3_multipart_uploads WHERE expires_at < NOW() RETURNING id)
+        SELECT d.id, p.temp_path FROM deleted d LEFT JOIN s3_multipart_parts p ON p.upload_id = d.id
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let uploads: std::collections::HashSet<Uuid> = rows.iter().map(|(id, _)| *id).collect();
+    Ok((uploads.len(), rows.into_iter().filter_map(|(_, temp_path)| temp_path).collect()))
+}
+
+pub async fn set_chunked_upload_status(
+    pool: &PgPool,
+    upload_id: &Uuid,
+    status: &str,
+    uploaded_chunks: i32,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE chunked_uploads SET status = $1, uploaded_chunks = $2, updated_at = NOW() WHERE id = $3"
+    )
+    .bind(status)
+    .bind(uploaded_chunks)
+    .bind(upload_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn record_uploaded_chunk(
+    pool: &PgPool,
+    upload_id: &Uuid,
+    chunk_number: i32,
+    chunk_size: i64,
+    ttl_hours: i64,
+) -> anyhow::Result<i32> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO upload_chunks (upload_id, chunk_number, chunk_size)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (upload_id, chunk_number) DO UPDATE SET chunk_size = EXCLUDED.chunk_size, received_at = NOW()
+        "#,
+    )
+    .bind(upload_id)
+    .bind(chunk_number)
+    .bind(chunk_size)
+    .execute(&mut *tx)
+    .await?;
+
+    let (uploaded_chunks,): (i32,) = sqlx::query_as(
+        r#"
+        UPDATE chunked_uploads
+        SET uploaded_chunks = (SELECT COUNT(*) FROM upload_chunks WHERE upload_id = $1), status = 'active',
+            expires_at = NOW() + make_interval(hours => $2), updated_at = NOW()
+        WHERE id = $1
+        RETURNING uploaded_chunks
+        "#,
+    )
+    .bind(upload_id)
+    .bind(ttl_hours as i32)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(uploaded_chunks)
+}
+
+pub async fn get_missing_chunks(
+    pool: &PgPool,
+    upload_id: &Uuid,
+    total_chunks: i32,
+) -> anyhow::Result<Vec<i32>> {
+    let missing: Vec<(i32,)> = sqlx::query_as(
+        r#"
+        SELECT n FROM generate_series(1, $2) AS n
+        WHERE NOT EXISTS (SELECT 1 FROM upload_chunks WHERE upload_id = $1 AND chunk_number = n)
+        ORDER BY n
+        "#,
+    )
+    .bind(upload_id)
+    .bind(total_chunks)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(missing.into_iter().map(|(n,)| n).collect())
+}
+
+pub async fn clear_uploaded_chunks(pool: &PgPool, upload_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM upload_chunks WHERE upload_id = $1")
+        .bind(upload_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Records the finalized file for a chunked upload and removes the upload in
+/// one transaction. Returns `None` if another request already completed it.
+pub async fn complete_chunked_upload(
+    pool: &PgPool,
+    upload_id: &Uuid,
+    owner_id: &Uuid,
+    original_filename: &str,
+    stored: &StorageResult,
+    folder_id: Option<&Uuid>,
+    client_modified_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<Option<FileInfo>> {
+    let mut tx = pool.begin().await?;
+
+    let is_completed: Option<bool> = sqlx::query_scalar(
+        "SELECT is_completed FROM chunked_uploads WHERE id = $1 FOR UPDATE"
+    )
+    .bind(upload_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if is_completed != Some(false) {
+        return Ok(None);
+    }
+
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        INSERT INTO files (user_id, filename, original_filename, file_path, disk_path, file_size, stored_size, mime_type, checksum, folder_id, client_modified_at, storage_tier, storage_encoding, is_deleted, tenant_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, FALSE, (SELECT tenant_id FROM users WHERE id = $1))
+        RETURNING {}
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(owner_id)
+    .bind(&stored.filename)
+    .bind(original_filename)
+    .bind(&stored.file_path)
+    .bind(&stored.disk_path)
+    .bind(stored.file_size)
+    .bind(stored.stored_size)
+    .bind(stored.mime_type.as_deref())
+    .bind(&stored.checksum)
+    .bind(folder_id)
+    .bind(client_modified_at)
+    .bind(StorageTier::of_path(&stored.file_path).as_str())
+    .bind(stored.encoding.as_str())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query("UPDATE users SET storage_used = storage_used + $1 WHERE id = $2")
+        .bind(file.file_size)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM chunked_uploads WHERE id = $1")
+        .bind(upload_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(file))
+}
+
+pub async fn delete_chunked_upload(
+    pool: &PgPool,
+    upload_id: &Uuid,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "DELETE FROM chunked_uploads WHERE id = $1"
+    )
+    .bind(upload_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+const EXPORT_JOB_COLUMNS: &str = "id, user_id, name, destination, schedule, is_enabled, next_run_at, last_run_at, last_success_at, last_error, created_by, created_at";
+
+pub async fn create_export_job(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    name: &str,
+    destination: &ExportDestination,
+    schedule: &str,
+    next_run_at: Option<DateTime<Utc>>,
+    created_by: &Uuid,
+) -> anyhow::Result<ExportJob> {
+    let job = sqlx::query_as::<_, ExportJob>(&format!(
+        r#"
+        INSERT INTO export_jobs (user_id, name, destination, schedule, next_run_at, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING {}
+        "#,
+        EXPORT_JOB_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(name)
+    .bind(sqlx::types::Json(destination))
+    .bind(schedule)
+    .bind(next_run_at)
+    .bind(created_by)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(job)
+}
+
+pub async fn get_export_job(pool: &PgPool, job_id: &Uuid) -> anyhow::Result<Option<ExportJob>> {
+    let job = sqlx::query_as::<_, ExportJob>(
+        &format!("SELECT {} FROM export_jobs WHERE id = $1", EXPORT_JOB_COLUMNS),
+    )
+    .bind(job_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+pub async fn get_export_jobs_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<ExportJob>> {
+    let jobs = sqlx::query_as::<_, ExportJob>(
+        &format!("SELECT {} FROM export_jobs WHERE user_id = $1 ORDER BY created_at DESC", EXPORT_JOB_COLUMNS),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+pub async fn get_all_export_jobs(pool: &PgPool) -> anyhow::Result<Vec<ExportJob>> {
+    let jobs = sqlx::query_as::<_, ExportJob>(
+        &format!("SELECT {} FROM export_jobs ORDER BY created_at DESC", EXPORT_JOB_COLUMNS),
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+pub async fn claim_due_export_jobs(pool: &PgPool) -> anyhow::Result<Vec<ExportJob>> {
+    let jobs = sqlx::query_as::<_, ExportJob>(&format!(
+        r#"
+        UPDATE export_jobs SET next_run_at = NULL, last_run_at = NOW()
+        WHERE is_enabled = TRUE AND next_run_at IS NOT NULL AND next_run_at <= NOW()
+        RETURNING {}
+        "#,
+        EXPORT_JOB_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+pub async fn finish_export_job(
+    pool: &PgPool,
+    job_id: &Uuid,
+    succeeded_at: Option<DateTime<Utc>>,
+    error: Option<&str>,
+    next_run_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE export_jobs
+        SET last_success_at = COALESCE($1, last_success_at), last_error = $2, next_run_at = $3
+        WHERE id = $4
+        "#,
+    )
+    .bind(succeeded_at)
+    .bind(error)
+    .bind(next_run_at)
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn delete_export_job(pool: &PgPool, job_id: &Uuid) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM export_jobs WHERE id = $1")
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+const EXPORT_RUN_COLUMNS: &str = "id, job_id, status, files_exported, bytes_exported, error, started_at, finished_at";
+
+pub async fn create_export_run(pool: &PgPool, job_id: &Uuid) -> anyhow::Result<ExportRun> {
+    let run = sqlx::query_as::<_, ExportRun>(
+        &format!("INSERT INTO export_runs (job_id) VALUES ($1) RETURNING {}", EXPORT_RUN_COLUMNS),
+    )
+    .bind(job_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(run)
+}
+
+pub async fn finish_export_run(
+    pool: &PgPool,
+    run_id: &Uuid,
+    status: &str,
+    files_exported: i32,
+    bytes_exported: i64,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE export_runs
+        SET status = $1, files_exported = $2, bytes_exported = $3, error = $4, finished_at = NOW()
+        WHERE id = $5
+        "#,
+    )
+    .bind(status)
+    .bind(files_exported)
+    .bind(bytes_exported)
+    .bind(error)
+    .bind(run_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_export_runs(pool: &PgPool, job_id: &Uuid) -> anyhow::Result<Vec<ExportRun>> {
+    let runs = sqlx::query_as::<_, ExportRun>(
+        &format!("SELECT {} FROM export_runs WHERE job_id = $1 ORDER BY started_at DESC LIMIT 100", EXPORT_RUN_COLUMNS),
+    )
+    .bind(job_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(runs)
+}
+
+pub async fn get_files_modified_since(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    since: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(
+        &format!(
+            r#"
+            SELECT {} FROM files
+            WHERE ($1::UUID IS NULL OR user_id = $1)
+              AND ($2::TIMESTAMPTZ IS NULL OR updated_at > $2)
+              AND is_deleted = FALSE
+            ORDER BY created_at
+            "#,
+            FILE_COLUMNS
+        ),
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+pub async fn get_folders_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Folder>> {
+    let folders = sqlx::query_as::<_, Folder>(
+        "SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at FROM folders WHERE user_id = $1 ORDER BY name",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(folders)
+}
+
+pub async fn get_or_create_folder(
+    pool: &PgPool,
+    user_id: &Uuid,
+    parent_id: Option<&Uuid>,
+    name: &str,
+) -> anyhow::Result<(Folder, bool)> {
+    let existing = sqlx::query_as::<_, Folder>(
+        r#"
+        SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        FROM folders
+        WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND name = $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    if let Some(folder) = existing {
+        return Ok((folder, false));
+    }
+
+    let folder = sqlx::query_as::<_, Folder>(
+        r#"
+        INSERT INTO folders (user_id, parent_id, name)
+        VALUES ($1, $2, $3)
+        RETURNING id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(parent_id)
+    .bind(name)
+    .fetch_one(pool)
+    .await?;
+
+    Ok((folder, true))
+}
+
+pub async fn set_file_folder(pool: &PgPool, file_id: &Uuid, folder_id: Option<&Uuid>) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET folder_id = $1 WHERE id = $2")
+        .bind(folder_id)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_file_timestamps(pool: &PgPool, file_id: &Uuid, timestamp: DateTime<Utc>) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET created_at = $1, updated_at = $1 WHERE id = $2")
+        .bind(timestamp)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+const IMPORT_JOB_COLUMNS: &str = "id, user_id, kind, source_path, target_folder, status, files_imported, folders_created, bytes_imported, error, created_by, created_at, finished_at";
+
+pub async fn create_import_job(
+    pool: &PgPool,
+    user_id: &Uuid,
+    kind: Option<&str>,
+    source_path: &str,
+    target_folder: &str,
+    created_by: &Uuid,
+) -> anyhow::Result<ImportJob> {
+    let job = sqlx::query_as::<_, ImportJob>(&format!(
+        r#"
+        INSERT INTO import_jobs (user_id, kind, source_path, target_folder, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        IMPORT_JOB_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(kind)
+    .bind(source_path)
+    .bind(target_folder)
+    .bind(created_by)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(job)
+}
+
+pub async fn get_import_job(pool: &PgPool, job_id: &Uuid) -> anyhow::Result<Option<ImportJob>> {
+    let job = sqlx::query_as::<_, ImportJob>(
+        &format!("SELECT {} FROM import_jobs WHERE id = $1", IMPORT_JOB_COLUMNS),
+    )
+    .bind(job_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(job)
+}
+
+pub async fn get_import_jobs_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<ImportJob>> {
+    let jobs = sqlx::query_as::<_, ImportJob>(
+        &format!(
+            "SELECT {} FROM import_jobs WHERE user_id = $1 OR created_by = $1 ORDER BY created_at DESC",
+            IMPORT_JOB_COLUMNS
+        ),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(jobs)
+}
+
+pub async fn update_import_progress(
+    pool: &PgPool,
+    job_id: &Uuid,
+    kind: &str,
+    files_imported: i32,
+    folders_created: i32,
+    bytes_imported: i64,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE import_jobs
+        SET kind = $1, files_imported = $2, folders_created = $3, bytes_imported = $4
+        WHERE id = $5
+        "#,
+    )
+    .bind(kind)
+    .bind(files_imported)
+    .bind(folders_created)
+    .bind(bytes_imported)
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn finish_import_job(
+    pool: &PgPool,
+    job_id: &Uuid,
+    status: &str,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE import_jobs SET status = $1, error = $2, finished_at = NOW() WHERE id = $3")
+        .bind(status)
+        .bind(error)
+        .bind(job_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn fail_interrupted_import_jobs(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "UPDATE import_jobs SET status = 'failed', error = 'interrupted by server restart', finished_at = NOW() WHERE status = 'running'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+const ARCHIVE_PART_COLUMNS: &str = "id, user_id, sequence, status, file_count, removed_count, size_bytes, archive_path, error, created_at, finished_at";
+
+pub async fn create_archive_part(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<ArchivePart>> {
+    let part = sqlx::query_as::<_, ArchivePart>(&format!(
+        r#"
+        INSERT INTO archive_parts (user_id, sequence)
+        SELECT $1, COALESCE(MAX(sequence), 0) + 1 FROM archive_parts WHERE user_id = $1
+        ON CONFLICT (user_id) WHERE status = 'building' DO NOTHING
+        RETURNING {}
+        "#,
+        ARCHIVE_PART_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(part)
+}
+
+pub async fn set_archive_part_path(pool: &PgPool, part_id: &Uuid, archive_path: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE archive_parts SET archive_path = $2 WHERE id = $1")
+        .bind(part_id)
+        .bind(archive_path)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn complete_archive_part(
+    pool: &PgPool,
+    part: &ArchivePart,
+    entries: &[(Uuid, String, String, i64)],
+    removed: &[Uuid],
+    size_bytes: i64,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    let file_ids: Vec<Uuid> = entries.iter().map(|entry| entry.0).collect();
+    let paths: Vec<&str> = entries.iter().map(|entry| entry.1.as_str()).collect();
+    let checksums: Vec<&str> = entries.iter().map(|entry| entry.2.as_str()).collect();
+    let sizes: Vec<i64> = entries.iter().map(|entry| entry.3).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO archive_manifest (user_id, file_id, part_id, path, checksum, file_size)
+        SELECT $1, e.file_id, $2, e.path, e.checksum, e.file_size
+        FROM UNNEST($3::UUID[], $4::TEXT[], $5::TEXT[], $6::BIGINT[]) AS e(file_id, path, checksum, file_size)
+        ON CONFLICT (user_id, file_id) DO UPDATE
+        SET part_id = EXCLUDED.part_id, path = EXCLUDED.path, checksum = EXCLUDED.checksum,
+            file_size = EXCLUDED.file_size, archived_at = NOW()
+        "#,
+    )
+    .bind(part.user_id)
+    .bind(part.id)
+    .bind(&file_ids)
+    .bind(&paths)
+    .bind(&checksums)
+    .bind(&sizes)
+    .execute(&mut *tx)
+    .await?;
+
+    if !removed.is_empty() {
+        sqlx::query("DELETE FROM archive_manifest WHERE user_id = $1 AND file_id = ANY($2)")
+            .bind(part.user_id)
+            .bind(removed)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE archive_parts
+        SET status = 'ready', file_count = $2, removed_count = $3, size_bytes = $4, finished_at = NOW()
+        WHERE id = $1
+        "#,
+    )
+    .bind(part.id)
+    .bind(entries.len() as i32)
+    .bind(removed.len() as i32)
+    .bind(size_bytes)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn fail_archive_part(pool: &PgPool, part_id: &Uuid, error: &str) -> anyhow::Result<()> {
+    sqlx::query("UPDATE archive_parts SET status = 'failed', error = $2, finished_at = NOW() WHERE id = $1")
+        .bind(part_id)
+        .bind(error)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_archive_parts(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<ArchivePart>> {
+    let parts = sqlx::query_as::<_, ArchivePart>(&format!(
+        "SELECT {} FROM archive_parts WHERE user_id = $1 ORDER BY sequence",
+        ARCHIVE_PART_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(parts)
+}
+
+pub async fn get_archive_part(pool: &PgPool, part_id: &Uuid) -> anyhow::Result<Option<ArchivePart>> {
+    let part = sqlx::query_as::<_, ArchivePart>(&format!("SELECT {} FROM archive_parts WHERE id = $1", ARCHIVE_PART_COLUMNS))
+        .bind(part_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(part)
+}
+
+pub async fn get_archive_manifest(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<ArchiveManifestEntry>> {
+    let entries = sqlx::query_as::<_, ArchiveManifestEntry>(
+        "SELECT file_id, path, checksum FROM archive_manifest WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+pub async fn delete_archive_parts(pool: &PgPool, user_id: &Uuid, failed_only: bool) -> anyhow::Result<Vec<ArchivePart>> {
+    let parts = sqlx::query_as::<_, ArchivePart>(&format!(
+        "DELETE FROM archive_parts WHERE user_id = $1 AND (status = 'failed' OR NOT $2) RETURNING {}",
+        ARCHIVE_PART_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(failed_only)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(parts)
+}
+
+pub async fn fail_interrupted_archive_parts(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "UPDATE archive_parts SET status = 'failed', error = 'interrupted by server restart', finished_at = NOW() WHERE status = 'building'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+const REMOTE_FETCH_COLUMNS: &str = "id, user_id, url, folder_id, filename, status, bytes_downloaded, total_bytes, mime_type, file_id, error, created_at, updated_at, finished_at";
+
+pub async fn create_remote_fetch(
+    pool: &PgPool,
+    user_id: &Uuid,
+    url: &str,
+    folder_id: Option<&Uuid>,
+    filename: Option<&str>,
+) -> anyhow::Result<RemoteFetch> {
+    let fetch = sqlx::query_as::<_, RemoteFetch>(&format!(
+        r#"
+        INSERT INTO remote_fetches (user_id, url, folder_id, filename)
+        VALUES ($1, $2, $3, $4)
+        RETURNING {}
+        "#,
+        REMOTE_FETCH_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(url)
+    .bind(folder_id)
+    .bind(filename)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(fetch)
+}
+
+pub async fn get_remote_fetch(pool: &PgPool, fetch_id: &Uuid) -> anyhow::Result<Option<RemoteFetch>> {
+    let fetch = sqlx::query_as::<_, RemoteFetch>(
+        &format!("SELECT {} FROM remote_fetches WHERE id = $1", REMOTE_FETCH_COLUMNS),
+    )
+    .bind(fetch_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(fetch)
+}
+
+pub async fn get_remote_fetches_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<RemoteFetch>> {
+    let fetches = sqlx::query_as::<_, RemoteFetch>(
+        &format!(
+            "SELECT {} FROM remote_fetches WHERE user_id = $1 ORDER BY created_at DESC LIMIT 100",
+            REMOTE_FETCH_COLUMNS
+        ),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(fetches)
+}
+
+pub async fn start_remote_fetch(
+    pool: &PgPool,
+    fetch_id: &Uuid,
+    filename: &str,
+    total_bytes: Option<i64>,
+    mime_type: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE remote_fetches
+        SET status = 'downloading', filename = $1, total_bytes = $2, mime_type = $3, updated_at = NOW()
+        WHERE id = $4
+        "#,
+    )
+    .bind(filename)
+    .bind(total_bytes)
+    .bind(mime_type)
+    .bind(fetch_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn update_remote_fetch_progress(pool: &PgPool, fetch_id: &Uuid, bytes_downloaded: i64) -> anyhow::Result<()> {
+    sqlx::query("UPDATE remote_fetches SET bytes_downloaded = $1, updated_at = NOW() WHERE id = $2")
+        .bind(bytes_downloaded)
+        .bind(fetch_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn finish_remote_fetch(
+    pool: &PgPool,
+    fetch_id: &Uuid,
+    file_id: Option<&Uuid>,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE remote_fetches
+        SET status = CASE WHEN $2::TEXT IS NULL THEN 'completed' ELSE 'failed' END,
+            file_id = $1, error = $2, updated_at = NOW(), finished_at = NOW()
+        WHERE id = $3
+        "#,
+    )
+    .bind(file_id)
+    .bind(error)
+    .bind(fetch_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fail_interrupted_remote_fetches(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        r#"
+        UPDATE remote_fetches
+        SET status = 'failed', error = 'interrupted by server restart', updated_at = NOW(), finished_at = NOW()
+        WHERE status IN ('pending', 'downloading')
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn upsert_photo_metadata(pool: &PgPool, metadata: &PhotoMetadata) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO photo_metadata (file_id, taken_at, latitude, longitude, altitude, description, raw)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (file_id) DO UPDATE
+        SET taken_at = EXCLUDED.taken_at, latitude = EXCLUDED.latitude, longitude = EXCLUDED.longitude,
+            altitude = EXCLUDED.altitude, description = EXCLUDED.description, raw = EXCLUDED.raw
+        "#,
+    )
+    .bind(metadata.file_id)
+    .bind(metadata.taken_at)
+    .bind(metadata.latitude)
+    .bind(metadata.longitude)
+    .bind(metadata.altitude)
+    .bind(&metadata.description)
+    .bind(&metadata.raw)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_photo_metadata(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<Option<PhotoMetadata>> {
+    let metadata = sqlx::query_as::<_, PhotoMetadata>(
+        "SELECT file_id, taken_at, latitude, longitude, altitude, description, raw FROM photo_metadata WHERE file_id = $1",
+    )
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(metadata)
+}
+
+pub async fn find_file_in_folder(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folder_id: Option<&Uuid>,
+    original_filename: &str,
+) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files
+        WHERE user_id = $1 AND folder_id IS NOT DISTINCT FROM $2 AND original_filename = $3 AND is_deleted = FALSE
+        ORDER BY created_at DESC, id
+        LIMIT 1
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .bind(original_filename)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn get_child_folders(pool: &PgPool, user_id: &Uuid, parent_id: Option<&Uuid>) -> anyhow::Result<Vec<Folder>> {
+    let folders = sqlx::query_as::<_, Folder>(
+        r#"
+        SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        FROM folders
+        WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2
+        ORDER BY name, id
+        "#,
+    )
+    .bind(user_id)
+    .bind(parent_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(folders)
+}
+
+pub async fn get_folder_by_name(
+    pool: &PgPool,
+    user_id: &Uuid,
+    parent_id: Option<&Uuid>,
+    name: &str,
+) -> anyhow::Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        r#"
+        SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        FROM folders
+        WHERE user_id = $1 AND parent_id IS NOT DISTINCT FROM $2 AND name = $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(folder)
+}
+
+pub async fn move_folder(
+    pool: &PgPool,
+    folder_id: &Uuid,
+    parent_id: Option<&Uuid>,
+    name: &str,
+) -> anyhow::Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        r#"
+        UPDATE folders SET parent_id = $2, name = $3, updated_at = NOW()
+        WHERE id = $1
+        RETURNING id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        "#,
+    )
+    .bind(folder_id)
+    .bind(parent_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(folder)
+}
+
+pub async fn trash_folder_tree(pool: &PgPool, user_id: &Uuid, folder_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        WITH RECURSIVE tree AS (
+            SELECT id FROM folders WHERE id = $2 AND user_id = $1
+            UNION
+            SELECT f.id FROM folders f JOIN tree t ON f.parent_id = t.id
+        )
+        UPDATE files SET is_deleted = TRUE, deleted_at = NOW(), updated_at = NOW()
+        WHERE user_id = $1 AND is_deleted = FALSE AND folder_id IN (SELECT id FROM tree)
+        RETURNING {}
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn delete_folder(pool: &PgPool, folder_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM folders WHERE id = $1 AND user_id = $2")
+        .bind(folder_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_files_in_folder(pool: &PgPool, user_id: &Uuid, folder_id: Option<&Uuid>) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files
+        WHERE user_id = $1 AND folder_id IS NOT DISTINCT FROM $2 AND is_deleted = FALSE
+        ORDER BY original_filename, created_at DESC, id
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+const EXTERNAL_MOUNT_COLUMNS: &str = "id, user_id, name, kind, host_path, read_only, smb_credentials, sync_folder_id, last_scanned_at, sync_started_at, last_synced_at, last_sync_error, created_at";
+
+pub async fn create_external_mount(
+    pool: &PgPool,
+    user_id: &Uuid,
+    name: &str,
+    kind: &str,
+    host_path: &str,
+    read_only: bool,
+    smb_credentials: Option<&SmbCredentials>,
+) -> anyhow::Result<ExternalMount> {
+    let mount = sqlx::query_as::<_, ExternalMount>(&format!(
+        r#"
+        INSERT INTO external_mounts (user_id, name, kind, host_path, read_only, smb_credentials)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING {}
+        "#,
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(name)
+    .bind(kind)
+    .bind(host_path)
+    .bind(read_only)
+    .bind(smb_credentials.map(sqlx::types::Json))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(mount)
+}
+
+pub async fn get_external_mounts(pool: &PgPool, user_id: Option<&Uuid>) -> anyhow::Result<Vec<ExternalMount>> {
+    let mounts = sqlx::query_as::<_, ExternalMount>(&format!(
+        "SELECT {} FROM external_mounts WHERE $1::uuid IS NULL OR user_id = $1 ORDER BY name",
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(mounts)
+}
+
+pub async fn get_external_mount(pool: &PgPool, mount_id: &Uuid) -> anyhow::Result<Option<ExternalMount>> {
+    let mount = sqlx::query_as::<_, ExternalMount>(&format!(
+        "SELECT {} FROM external_mounts WHERE id = $1",
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .bind(mount_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(mount)
+}
+
+pub async fn delete_external_mount(pool: &PgPool, mount_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM external_mounts WHERE id = $1")
+        .bind(mount_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn replace_mount_entries(
+    pool: &PgPool,
+    mount_id: &Uuid,
+    parent_path: &str,
+    entries: &[MountEntry],
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("DELETE FROM external_mount_entries WHERE mount_id = $1 AND parent_path = $2")
+        .bind(mount_id)
+        .bind(parent_path)
+        .execute(&mut *tx)
+        .await?;
+
+    for entry in entries {
+        sqlx::query(
+            r#"
+            INSERT INTO external_mount_entries (mount_id, parent_path, path, name, is_dir, size, modified_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (mount_id, path) DO UPDATE
+            SET parent_path = EXCLUDED.parent_path, name = EXCLUDED.name, is_dir = EXCLUDED.is_dir,
+                size = EXCLUDED.size, modified_at = EXCLUDED.modified_at
+            "#,
+        )
+        .bind(mount_id)
+        .bind(parent_path)
+        .bind(&entry.path)
+        .bind(&entry.name)
+        .bind(entry.is_dir)
+        .bind(entry.size)
+        .bind(entry.modified_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query("UPDATE external_mounts SET last_scanned_at = NOW() WHERE id = $1")
+        .bind(mount_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_mount_entries(pool: &PgPool, mount_id: &Uuid, parent_path: &str) -> anyhow::Result<Vec<MountEntry>> {
+    let entries = sqlx::query_as::<_, MountEntry>(
+        r#"
+        SELECT path, name, is_dir, size, modified_at FROM external_mount_entries
+        WHERE mount_id = $1 AND parent_path = $2
+        ORDER BY is_dir DESC, name
+        "#,
+    )
+    .bind(mount_id)
+    .bind(parent_path)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+pub async fn delete_mount_entry(pool: &PgPool, mount_id: &Uuid, path: &str) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM external_mount_entries WHERE mount_id = $1 AND (path = $2 OR starts_with(path, $3))")
+        .bind(mount_id)
+        .bind(path)
+        .bind(format!("{}/", path))
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn set_mount_sync_folder(pool: &PgPool, mount_id: &Uuid, folder_id: Option<&Uuid>) -> anyhow::Result<Option<ExternalMount>> {
+    let mount = sqlx::query_as::<_, ExternalMount>(&format!(
+        "UPDATE external_mounts SET sync_folder_id = $2 WHERE id = $1 RETURNING {}",
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .bind(mount_id)
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(mount)
+}
+
+pub async fn get_syncable_mounts(pool: &PgPool) -> anyhow::Result<Vec<ExternalMount>> {
+    let mounts = sqlx::query_as::<_, ExternalMount>(&format!(
+        "SELECT {} FROM external_mounts WHERE sync_folder_id IS NOT NULL ORDER BY last_synced_at NULLS FIRST",
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    Ok(mounts)
+}
+
+pub async fn claim_mount_sync(pool: &PgPool, mount_id: &Uuid, stale_after_hours: i64) -> anyhow::Result<Option<ExternalMount>> {
+    let mount = sqlx::query_as::<_, ExternalMount>(&format!(
+        r#"
+        UPDATE external_mounts SET sync_started_at = NOW()
+        WHERE id = $1 AND sync_folder_id IS NOT NULL
+          AND (sync_started_at IS NULL OR sync_started_at < NOW() - make_interval(hours => $2::int))
+        RETURNING {}
+        "#,
+        EXTERNAL_MOUNT_COLUMNS
+    ))
+    .bind(mount_id)
+    .bind(stale_after_hours as i32)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(mount)
+}
+
+pub async fn finish_mount_sync(pool: &PgPool, mount_id: &Uuid, error: Option<&str>) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE external_mounts
+        SET sync_started_at = NULL,
+            last_synced_at = CASE WHEN $2::text IS NULL THEN NOW() ELSE last_synced_at END,
+            last_sync_error = $2
+        WHERE id = $1
+        "#,
+    )
+    .bind(mount_id)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+const GALLERY_COLUMNS: &str = "id, user_id, folder_id, token, title, description, theme, created_at, updated_at";
+
+pub async fn upsert_gallery(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folder_id: &Uuid,
+    token: &str,
+    title: Option<&str>,
+    description: Option<&str>,
+    theme: &str,
+) -> anyhow::Result<Gallery> {
+    let gallery = sqlx::query_as::<_, Gallery>(&format!(
+        r#"
+        INSERT INTO galleries (user_id, folder_id, token, title, description, theme)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (folder_id) DO UPDATE
+        SET title = EXCLUDED.title, description = EXCLUDED.description, theme = EXCLUDED.theme, updated_at = NOW()
+        RETURNING {}
+        "#,
+        GALLERY_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .bind(token)
+    .bind(title)
+    .bind(description)
+    .bind(theme)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(gallery)
+}
+
+pub async fn get_gallery_by_folder(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<Option<Gallery>> {
+    let gallery = sqlx::query_as::<_, Gallery>(&format!(
+        "SELECT {} FROM galleries WHERE folder_id = $1",
+        GALLERY_COLUMNS
+    ))
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(gallery)
+}
+
+pub async fn get_gallery_by_token(pool: &PgPool, token: &str) -> anyhow::Result<Option<Gallery>> {
+    let gallery = sqlx::query_as::<_, Gallery>(&format!(
+        "SELECT {} FROM galleries WHERE token = $1",
+        GALLERY_COLUMNS
+    ))
+    .bind(token)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(gallery)
+}
+
+pub async fn get_galleries_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Gallery>> {
+    let galleries = sqlx::query_as::<_, Gallery>(&format!(
+        "SELECT {} FROM galleries WHERE user_id = $1 ORDER BY created_at DESC",
+        GALLERY_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(galleries)
+}
+
+pub async fn delete_gallery(pool: &PgPool, folder_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM galleries WHERE folder_id = $1 AND user_id = $2")
+        .bind(folder_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_gallery_files(pool: &PgPool, user_id: &Uuid, folder_id: &Uuid) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files f
+        WHERE user_id = $1 AND folder_id = $2 AND is_deleted = FALSE AND is_quarantined = FALSE
+        ORDER BY COALESCE((SELECT p.taken_at FROM photo_metadata p WHERE p.file_id = f.id), client_modified_at, created_at) DESC, id
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn set_file_client_modified_at(
+    pool: &PgPool,
+    file_id: &Uuid,
+    client_modified_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    sqlx::query("UPDATE files SET client_modified_at = $1 WHERE id = $2")
+        .bind(client_modified_at)
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn get_folder_by_id(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        "SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at FROM folders WHERE id = $1",
+    )
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(folder)
+}
+
+pub async fn find_case_insensitive_name_conflict(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folder_id: Option<&Uuid>,
+    name: &str,
+    exclude_file_id: Option<&Uuid>,
+) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        r#"
+        SELECT {} FROM files
+        WHERE user_id = $1 AND folder_id IS NOT DISTINCT FROM $2 AND LOWER(original_filename) = LOWER($3)
+          AND is_deleted = FALSE AND id IS DISTINCT FROM $4
+        LIMIT 1
+        "#,
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(folder_id)
+    .bind(name)
+    .bind(exclude_file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn rename_file(pool: &PgPool, file_id: &Uuid, original_filename: &str) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        "UPDATE files SET original_filename = $1, updated_at = NOW() WHERE id = $2 RETURNING {}",
+        FILE_COLUMNS
+    ))
+    .bind(original_filename)
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+const ALIAS_COLUMNS: &str = "a.id, a.user_id, a.folder_id, a.name, a.target_file_id, a.target_folder_id, a.created_at";
+
+const LIVE_ALIAS_FILTER: &str = "(a.target_file_id IS NULL OR EXISTS (SELECT 1 FROM files f WHERE f.id = a.target_file_id AND f.is_deleted = FALSE))";
+
+pub async fn create_alias(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folder_id: Option<&Uuid>,
+    name: &str,
+    target_file_id: Option<&Uuid>,
+    target_folder_id: Option<&Uuid>,
+) -> anyhow::Result<Alias> {
+    let alias = sqlx::query_as::<_, Alias>(
+        r#"
+        INSERT INTO aliases (user_id, folder_id, name, target_file_id, target_folder_id)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, user_id, folder_id, name, target_file_id, target_folder_id, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(folder_id)
+    .bind(name)
+    .bind(target_file_id)
+    .bind(target_folder_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(alias)
+}
+
+pub async fn get_alias(pool: &PgPool, alias_id: &Uuid) -> anyhow::Result<Option<Alias>> {
+    let alias = sqlx::query_as::<_, Alias>(
+        &format!("SELECT {} FROM aliases a WHERE a.id = $1 AND {}", ALIAS_COLUMNS, LIVE_ALIAS_FILTER),
+    )
+    .bind(alias_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(alias)
+}
+
+pub async fn get_aliases_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Alias>> {
+    let aliases = sqlx::query_as::<_, Alias>(
+        &format!(
+            "SELECT {} FROM aliases a WHERE a.user_id = $1 AND {} ORDER BY a.name, a.id",
+            ALIAS_COLUMNS, LIVE_ALIAS_FILTER
+        ),
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(aliases)
+}
+
+pub async fn get_aliases_in_folder(pool: &PgPool, user_id: &Uuid, folder_id: Option<&Uuid>) -> anyhow::Result<Vec<Alias>> {
+    let aliases = sqlx::query_as::<_, Alias>(
+        &format!(
+            "SELECT {} FROM aliases a WHERE a.user_id = $1 AND a.folder_id IS NOT DISTINCT FROM $2 AND {} ORDER BY a.name, a.id",
+            ALIAS_COLUMNS, LIVE_ALIAS_FILTER
+        ),
+    )
+    .bind(user_id)
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(aliases)
+}
+
+pub async fn get_alias_by_name(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folder_id: Option<&Uuid>,
+    name: &str,
+) -> anyhow::Result<Option<Alias>> {
+    let alias = sqlx::query_as::<_, Alias>(
+        &format!(
+            "SELECT {} FROM aliases a WHERE a.user_id = $1 AND a.folder_id IS NOT DISTINCT FROM $2 AND a.name = $3 AND {} ORDER BY a.created_at DESC LIMIT 1",
+            ALIAS_COLUMNS, LIVE_ALIAS_FILTER
+        ),
+    )
+    .bind(user_id)
+    .bind(folder_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(alias)
+}
+
+pub async fn delete_alias(pool: &PgPool, alias_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM aliases WHERE id = $1 AND user_id = $2")
+        .bind(alias_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn folder_reachable_from(pool: &PgPool, folder_id: &Uuid, start_folder_id: &Uuid) -> anyhow::Result<bool> {
+    let (reachable,): (bool,) = sqlx::query_as(
+        r#"
+        WITH RECURSIVE edges AS (
+            SELECT parent_id AS parent, id AS child FROM folders WHERE parent_id IS NOT NULL
+            UNION ALL
+            SELECT folder_id, target_folder_id FROM aliases WHERE folder_id IS NOT NULL AND target_folder_id IS NOT NULL
+        ),
+        reachable AS (
+            SELECT $2::uuid AS id
+            UNION
+            SELECT e.child FROM edges e JOIN reachable r ON e.parent = r.id
+        )
+        SELECT EXISTS (SELECT 1 FROM reachable WHERE id = $1)
+        "#,
+    )
+    .bind(folder_id)
+    .bind(start_folder_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(reachable)
+}
+
+pub async fn get_inherited_folder_permissions(
+    pool: &PgPool,
+    folder_id: &Uuid,
+    user_id: &Uuid,
+) -> anyhow::Result<Option<PermissionSet>> {
+    let permissions = sqlx::query_as::<_, PermissionSet>(
+        r#"
+        WITH RECURSIVE ancestors AS (
+            SELECT id, parent_id, 0 AS depth FROM folders WHERE id = $1
+            UNION ALL
+            SELECT f.id, f.parent_id, a.depth + 1
+            FROM folders f JOIN ancestors a ON f.id = a.parent_id
+            WHERE a.depth < 256
+        )
+        SELECT p.can_read, p.can_write, p.can_delete, p.can_reshare
+        FROM ancestors a
+        JOIN folder_permissions p ON p.folder_id = a.id AND p.user_id = $2
+        ORDER BY a.depth
+        LIMIT 1
+        "#,
+    )
+    .bind(folder_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(permissions)
+}
+
+const FOLDER_PERMISSION_COLUMNS: &str = "p.folder_id, p.user_id, u.username, p.can_read, p.can_write, p.can_delete, p.can_reshare, p.granted_by, p.created_at, p.updated_at";
+
+pub async fn get_folder_permissions(pool: &PgPool, folder_id: &Uuid) -> anyhow::Result<Vec<FolderPermission>> {
+    let permissions = sqlx::query_as::<_, FolderPermission>(&format!(
+        "SELECT {} FROM folder_permissions p JOIN users u ON u.id = p.user_id WHERE p.folder_id = $1 ORDER BY u.username",
+        FOLDER_PERMISSION_COLUMNS
+    ))
+    .bind(folder_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(permissions)
+}
+
+pub async fn set_folder_permission(
+    pool: &PgPool,
+    folder_id: &Uuid,
+    user_id: &Uuid,
+    permissions: &PermissionSet,
+    granted_by: &Uuid,
+) -> anyhow::Result<FolderPermission> {
+    let permission = sqlx::query_as::<_, FolderPermission>(&format!(
+        r#"
+        WITH p AS (
+            INSERT INTO folder_permissions (folder_id, user_id, can_read, can_write, can_delete, can_reshare, granted_by)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (folder_id, user_id) DO UPDATE
+            SET can_read = EXCLUDED.can_read,
+                can_write = EXCLUDED.can_write,
+                can_delete = EXCLUDED.can_delete,
+                can_reshare = EXCLUDED.can_reshare,
+                granted_by = EXCLUDED.granted_by,
+                updated_at = NOW()
+            RETURNING *
+        )
+        SELECT {} FROM p JOIN users u ON u.id = p.user_id
+        "#,
+        FOLDER_PERMISSION_COLUMNS
+    ))
+    .bind(folder_id)
+    .bind(user_id)
+    .bind(permissions.read)
+    .bind(permissions.write)
+    .bind(permissions.delete)
+    .bind(permissions.reshare)
+    .bind(granted_by)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(permission)
+}
+
+pub async fn delete_folder_permission(pool: &PgPool, folder_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM folder_permissions WHERE folder_id = $1 AND user_id = $2")
+        .bind(folder_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_shared_folders(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<SharedFolder>> {
+    let folders = sqlx::query_as::<_, SharedFolder>(
+        r#"
+        SELECT f.id, f.user_id, f.parent_id, f.name, f.color, f.icon, f.keep_offline, f.created_at, f.updated_at,
+               u.username AS owner_username, p.can_read, p.can_write, p.can_delete, p.can_reshare
+        FROM folder_permissions p
+        JOIN folders f ON f.id = p.folder_id
+        JOIN users u ON u.id = f.user_id
+        WHERE p.user_id = $1 AND p.can_read AND f.user_id <> $1
+        ORDER BY u.username, f.name
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(folders)
+}
+
+pub async fn update_folder_appearance(
+    pool: &PgPool,
+    folder_id: &Uuid,
+    color: Option<&str>,
+    icon: Option<&str>,
+) -> anyhow::Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        r#"
+        UPDATE folders SET color = $1, icon = $2, updated_at = NOW()
+        WHERE id = $3
+        RETURNING id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        "#,
+    )
+    .bind(color)
+    .bind(icon)
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(folder)
+}
+
+pub async fn set_file_keep_offline(pool: &PgPool, file_id: &Uuid, keep_offline: bool) -> anyhow::Result<Option<FileInfo>> {
+    let file = sqlx::query_as::<_, FileInfo>(&format!(
+        "UPDATE files SET keep_offline = $1, updated_at = NOW() WHERE id = $2 RETURNING {}",
+        FILE_COLUMNS
+    ))
+    .bind(keep_offline)
+    .bind(file_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(file)
+}
+
+pub async fn set_folder_keep_offline(pool: &PgPool, folder_id: &Uuid, keep_offline: bool) -> anyhow::Result<Option<Folder>> {
+    let folder = sqlx::query_as::<_, Folder>(
+        r#"
+        UPDATE folders SET keep_offline = $1, updated_at = NOW()
+        WHERE id = $2
+        RETURNING id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        "#,
+    )
+    .bind(keep_offline)
+    .bind(folder_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(folder)
+}
+
+pub async fn get_files_changed_since(
+    pool: &PgPool,
+    user_id: &Uuid,
+    since: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<FileInfo>> {
+    let files = sqlx::query_as::<_, FileInfo>(&format!(
+        "SELECT {} FROM files WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR updated_at > $2) ORDER BY updated_at, id",
+        FILE_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(files)
+}
+
+pub async fn get_folders_changed_since(
+    pool: &PgPool,
+    user_id: &Uuid,
+    since: Option<DateTime<Utc>>,
+) -> anyhow::Result<Vec<Folder>> {
+    let folders = sqlx::query_as::<_, Folder>(
+        r#"
+        SELECT id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+        FROM folders
+        WHERE user_id = $1 AND ($2::TIMESTAMPTZ IS NULL OR updated_at > $2)
+        ORDER BY updated_at, id
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(folders)
+}
+
+pub async fn get_clipboard(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<Clipboard>> {
+    let clipboard = sqlx::query_as::<_, Clipboard>(
+        "SELECT user_id, mode, file_ids, folder_ids, created_at FROM clipboards WHERE user_id = $1",
+    )
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(clipboard)
+}
+
+pub async fn set_clipboard(
+    pool: &PgPool,
+    user_id: &Uuid,
+    mode: &str,
+    file_ids: &[Uuid],
+    folder_ids: &[Uuid],
+) -> anyhow::Result<Clipboard> {
+    let clipboard = sqlx::query_as::<_, Clipboard>(
+        r#"
+        INSERT INTO clipboards (user_id, mode, file_ids, folder_ids)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (user_id) DO UPDATE
+        SET mode = EXCLUDED.mode, file_ids = EXCLUDED.file_ids, folder_ids = EXCLUDED.folder_ids, created_at = NOW()
+        RETURNING user_id, mode, file_ids, folder_ids, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(mode)
+    .bind(file_ids)
+    .bind(folder_ids)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(clipboard)
+}
+
+pub async fn clear_clipboard(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM clipboards WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn move_items(
+    pool: &PgPool,
+    user_id: &Uuid,
+    target_folder_id: Option<&Uuid>,
+    file_ids: &[Uuid],
+    folder_ids: &[Uuid],
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE files SET folder_id = $1, updated_at = NOW() WHERE user_id = $2 AND id = ANY($3)")
+        .bind(target_folder_id)
+        .bind(user_id)
+        .bind(file_ids)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE folders SET parent_id = $1, updated_at = NOW() WHERE user_id = $2 AND id = ANY($3)")
+        .bind(target_folder_id)
+        .bind(user_id)
+        .bind(folder_ids)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query("DELETE FROM clipboards WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub struct NewFolder {
+    pub id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+}
+
+pub struct NewFile<'a> {
+    pub folder_id: Option<Uuid>,
+    pub original_filename: String,
+    pub mime_type: Option<&'a str>,
+    pub stored: StorageResult,
+}
+
+pub async fn insert_copies(
+    pool: &PgPool,
+    user_id: &Uuid,
+    folders: &[NewFolder],
+    files: &[NewFile<'_>],
+) -> anyhow::Result<(Vec<Folder>, Vec<FileInfo>)> {
+    let mut tx = pool.begin().await?;
+    let mut created_folders = Vec::with_capacity(folders.len());
+    let mut created_files = Vec::with_capacity(files.len());
+
+    for folder in folders {
+        let created = sqlx::query_as::<_, Folder>(
+            r#"
+            INSERT INTO folders (id, user_id, parent_id, name)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, user_id, parent_id, name, color, icon, keep_offline, created_at, updated_at
+            "#,
+        )
+        .bind(folder.id)
+        .bind(user_id)
+        .bind(folder.parent_id)
+        .bind(&folder.name)
+        .fetch_one(&mut *tx)
+        .await?;
+        created_folders.push(created);
+    }
+
+    let mut total_size = 0i64;
+    for file in files {
+        let created = sqlx::query_as::<_, FileInfo>(&format!(
+            r#"
+            INSERT INTO files (user_id, filename, original_filename, file_path, disk_path, file_size, stored_size, mime_type, checksum, folder_id, storage_tier, storage_encoding, is_deleted, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, FALSE, (SELECT tenant_id FROM users WHERE id = $1))
+            RETURNING {}
+            "#,
+            FILE_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(&file.stored.filename)
+        .bind(&file.original_filename)
+        .bind(&file.stored.file_path)
+        .bind(&file.stored.disk_path)
+        .bind(file.stored.file_size)
+        .bind(file.stored.stored_size)
+        .bind(file.mime_type)
+        .bind(&file.stored.checksum)
+        .bind(file.folder_id)
+        .bind(StorageTier::of_path(&file.stored.file_path).as_str())
+        .bind(file.stored.encoding.as_str())
+        .fetch_one(&mut *tx)
+        .await?;
+        total_size += created.file_size;
+        created_files.push(created);
+    }
+
+    sqlx::query("UPDATE users SET storage_used = storage_used + $1 WHERE id = $2")
+        .bind(total_size)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok((created_folders, created_files))
+}
+
+const OPERATION_COLUMNS: &str = "id, user_id, kind, status, progress_current, progress_total, result, error, cancel_requested, created_at, updated_at, finished_at";
+
+pub async fn create_operation(
+    pool: &PgPool,
+    user_id: &Uuid,
+    kind: &str,
+    progress_total: Option<i64>,
+) -> anyhow::Result<Operation> {
+    let operation = sqlx::query_as::<_, Operation>(&format!(
+        "INSERT INTO operations (user_id, kind, progress_total) VALUES ($1, $2, $3) RETURNING {}",
+        OPERATION_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(kind)
+    .bind(progress_total)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(operation)
+}
+
+pub async fn get_operation(pool: &PgPool, operation_id: &Uuid) -> anyhow::Result<Option<Operation>> {
+    let operation = sqlx::query_as::<_, Operation>(
+        &format!("SELECT {} FROM operations WHERE id = $1", OPERATION_COLUMNS),
+    )
+    .bind(operation_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(operation)
+}
+
+pub async fn get_operations_by_user(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Operation>> {
+    let operations = sqlx::query_as::<_, Operation>(&format!(
+        "SELECT {} FROM operations WHERE user_id = $1 ORDER BY created_at DESC LIMIT 100",
+        OPERATION_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(operations)
+}
+
+pub async fn get_latest_operation(pool: &PgPool, kind: &str) -> anyhow::Result<Option<Operation>> {
+    let operation = sqlx::query_as::<_, Operation>(&format!(
+        "SELECT {} FROM operations WHERE kind = $1 ORDER BY created_at DESC LIMIT 1",
+        OPERATION_COLUMNS
+    ))
+    .bind(kind)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(operation)
+}
+
+pub async fn update_operation_progress(
+    pool: &PgPool,
+    operation_id: &Uuid,
+    progress_current: i64,
+    progress_total: Option<i64>,
+) -> anyhow::Result<bool> {
+    let cancel_requested: Option<(bool,)> = sqlx::query_as(
+        r#"
+        UPDATE operations SET progress_current = $1, progress_total = COALESCE($2, progress_total), updated_at = NOW()
+        WHERE id = $3
+        RETURNING cancel_requested
+        "#,
+    )
+    .bind(progress_current)
+    .bind(progress_total)
+    .bind(operation_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(cancel_requested.map(|(cancel,)| cancel).unwrap_or(false))
+}
+
+pub async fn finish_operation(
+    pool: &PgPool,
+    operation_id: &Uuid,
+    status: &str,
+    result: Option<&serde_json::Value>,
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        "UPDATE operations SET status = $1, result = $2, error = $3, updated_at = NOW(), finished_at = NOW() WHERE id = $4",
+    )
+    .bind(status)
+    .bind(result)
+    .bind(error)
+    .bind(operation_id)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn request_operation_cancel(pool: &PgPool, operation_id: &Uuid) -> anyhow::Result<Option<Operation>> {
+    let operation = sqlx::query_as::<_, Operation>(&format!(
+        "UPDATE operations SET cancel_requested = TRUE, updated_at = NOW() WHERE id = $1 AND status = 'running' RETURNING {}",
+        OPERATION_COLUMNS
+    ))
+    .bind(operation_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(operation)
+}
+
+pub async fn fail_interrupted_operations(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "UPDATE operations SET status = 'failed', error = 'interrupted by server restart', updated_at = NOW(), finished_at = NOW() WHERE status = 'running'",
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+const SESSION_COLUMNS: &str = "id, user_id, user_agent, ip_address, created_at, last_used_at, expires_at";
+
+pub async fn create_session(
+    pool: &PgPool,
+    user_id: &Uuid,
+    user_agent: Option<&str>,
+    ip_address: Option<&str>,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<Session> {
+    let session = sqlx::query_as::<_, Session>(&format!(
+        "INSERT INTO sessions (user_id, user_agent, ip_address, expires_at) VALUES ($1, $2, $3, $4) RETURNING {}",
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(user_agent)
+    .bind(ip_address)
+    .bind(expires_at)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(session)
+}
+
+pub async fn touch_session(pool: &PgPool, session_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let (valid,): (bool,) = sqlx::query_as(
+        r#"
+        WITH valid AS (
+            SELECT id, last_used_at FROM sessions
+            WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > NOW()
+        ),
+        touched AS (
+            UPDATE sessions s SET last_used_at = NOW()
+            FROM valid
+            WHERE s.id = valid.id AND valid.last_used_at < NOW() - INTERVAL '1 minute'
+            RETURNING s.id
+        )
+        SELECT EXISTS(SELECT 1 FROM valid)
+        "#,
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(valid)
+}
+
+pub async fn get_active_sessions(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<Session>> {
+    let sessions = sqlx::query_as::<_, Session>(&format!(
+        r#"
+        SELECT {} FROM sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        ORDER BY last_used_at DESC
+        "#,
+        SESSION_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+pub async fn revoke_session(pool: &PgPool, session_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW() WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > NOW()",
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn revoke_other_sessions(pool: &PgPool, user_id: &Uuid, keep_session_id: &Uuid) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "UPDATE sessions SET revoked_at = NOW() WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL",
+    )
+    .bind(user_id)
+    .bind(keep_session_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn delete_expired_sessions(pool: &PgPool) -> anyhow::Result<u64> {
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn save_share_torrent(
+    pool: &PgPool,
+    share_id: &Uuid,
+    info_hash: &str,
+    name: &str,
+    web_seed_url: &str,
+    torrent: &[u8],
+) -> anyhow::Result<ShareTorrent> {
+    let share_torrent = sqlx::query_as::<_, ShareTorrent>(
+        r#"
+        INSERT INTO share_torrents (share_id, info_hash, name, web_seed_url, torrent)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (share_id) DO UPDATE
+        SET info_hash = EXCLUDED.info_hash, name = EXCLUDED.name, web_seed_url = EXCLUDED.web_seed_url,
+            torrent = EXCLUDED.torrent, created_at = NOW()
+        RETURNING share_id, info_hash, name, web_seed_url, torrent, created_at
+        "#,
+    )
+    .bind(share_id)
+    .bind(info_hash)
+    .bind(name)
+    .bind(web_seed_url)
+    .bind(torrent)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(share_torrent)
+}
+
+pub async fn get_share_torrent(pool: &PgPool, share_id: &Uuid) -> anyhow::Result<Option<ShareTorrent>> {
+    let share_torrent = sqlx::query_as::<_, ShareTorrent>(
+        "SELECT share_id, info_hash, name, web_seed_url, torrent, created_at FROM share_torrents WHERE share_id = $1",
+    )
+    .bind(share_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(share_torrent)
+}
+
+pub async fn add_api_usage(
+    pool: &PgPool,
+    entries: &std::collections::HashMap<crate::usage::UsageKey, crate::usage::UsageCounts>,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for (key, counts) in entries {
+        sqlx::query(
+            r#"
+            INSERT INTO api_usage (bucket, user_id, method, route, request_count, client_error_count, server_error_count, total_duration_ms)
+            SELECT $1, u.id, $3, $4, $5, $6, $7, $8
+            FROM (SELECT $2::uuid AS requested) r
+            LEFT JOIN users u ON u.id = r.requested
+            ON CONFLICT (bucket, COALESCE(user_id, '00000000-0000-0000-0000-000000000000'::uuid), method, route) DO UPDATE
+            SET request_count = api_usage.request_count + EXCLUDED.request_count,
+                client_error_count = api_usage.client_error_count + EXCLUDED.client_error_count,
+                server_error_count = api_usage.server_error_count + EXCLUDED.server_error_count,
+                total_duration_ms = api_usage.total_duration_ms + EXCLUDED.total_duration_ms
+            "#,
+        )
+        .bind(key.bucket)
+        .bind(key.user_id)
+        .bind(&key.method)
+        .bind(&key.route)
+        .bind(counts.requests)
+        .bind(counts.client_errors)
+        .bind(counts.server_errors)
+        .bind(counts.total_duration_ms)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn get_api_usage(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+    interval: Option<&str>,
+    by_user: bool,
+    by_route: bool,
+    user_id: Option<&Uuid>,
+) -> anyhow::Result<Vec<ApiUsageRow>> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    match interval {
+        Some(interval) => query.push("date_trunc(").push_bind(interval.to_string()).push(", a.bucket) AS bucket, "),
+        None => query.push("NULL::timestamptz AS bucket, "),
+    };
+    if by_user {
+        query.push("a.user_id, u.username, ");
+    } else {
+        query.push("NULL::uuid AS user_id, NULL::varchar AS username, ");
+    }
+    if by_route {
+        query.push("a.method, a.route, ");
+    } else {
+        query.push("NULL::varchar AS method, NULL::varchar AS route, ");
+    }
+    query.push(
+        r#"
+        SUM(a.request_count)::BIGINT AS requests,
+        SUM(a.client_error_count)::BIGINT AS client_errors,
+        SUM(a.server_error_count)::BIGINT AS server_errors,
+        SUM(a.total_duration_ms)::BIGINT AS total_duration_ms
+        FROM api_usage a
+        LEFT JOIN users u ON u.id = a.user_id
+        WHERE a.bucket >= "#,
+    );
+    query.push_bind(since);
+    if let Some(user_id) = user_id {
+        query.push(" AND a.user_id = ").push_bind(*user_id);
+    }
+    query.push(" GROUP BY 1, 2, 3, 4, 5 ORDER BY 1 DESC NULLS LAST, requests DESC LIMIT 1000");
+
+    let rows = query.build_query_as::<ApiUsageRow>().fetch_all(pool).await?;
+    Ok(rows)
+}
+
+pub async fn delete_old_api_usage(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+    let result = sqlx::query("DELETE FROM api_usage WHERE bucket < $1")
+        .bind(before)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub async fn get_login_lockout(pool: &PgPool, keys: &[String]) -> anyhow::Result<Option<DateTime<Utc>>> {
+    let (locked_until,): (Option<DateTime<Utc>>,) = sqlx::query_as(
+        "SELECT MAX(locked_until) FROM login_attempts WHERE key = ANY($1) AND locked_until > NOW()",
+    )
+    .bind(keys)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(locked_until)
+}
+
+pub async fn record_login_failure(pool: &PgPool, key: &str, window_start: DateTime<Utc>) -> anyhow::Result<i32> {
+    let (failures,): (i32,) = sqlx::query_as(
+        r#"
+        INSERT INTO login_attempts (key, failures, last_failure_at)
+        VALUES ($1, 1, NOW())
+        ON CONFLICT (key) DO UPDATE
+        SET failures = CASE WHEN login_attempts.last_failure_at < $2 THEN 1 ELSE login_attempts.failures + 1 END,
+            last_failure_at = NOW()
+        RETURNING failures
+        "#,
+    )
+    .bind(key)
+    .bind(window_start)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(failures)
+}
+
+pub async fn set_login_lockout(pool: &PgPool, key: &str, locked_until: DateTime<Utc>) -> anyhow::Result<()> {
+    sqlx::query("UPDATE login_attempts SET locked_until = $1 WHERE key = $2")
+        .bind(locked_until)
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn clear_login_failures(pool: &PgPool, key: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM login_attempts WHERE key = $1")
+        .bind(key)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_login_lockouts(pool: &PgPool) -> anyhow::Result<Vec<LoginLockout>> {
+    let lockouts = sqlx::query_as::<_, LoginLockout>(
+        r#"
+        SELECT key, failures, last_failure_at, locked_until
+        FROM login_attempts
+        WHERE locked_until > NOW()
+        ORDER BY locked_until DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(lockouts)
+}
+
+pub async fn delete_stale_login_attempts(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<u64> {
+    let result = sqlx::query(
+        "DELETE FROM login_attempts WHERE last_failure_at < $1 AND (locked_until IS NULL OR locked_until < NOW())",
+    )
+    .bind(before)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+pub const OIDC_LOGIN_TTL_MINUTES: i64 = 10;
+
+pub async fn create_oidc_login(
+    pool: &PgPool,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+    redirect_uri: &str,
+) -> anyhow::Result<()> {
+    sqlx::query("DELETE FROM oidc_logins WHERE created_at < NOW() - make_interval(mins => $1)")
+        .bind(OIDC_LOGIN_TTL_MINUTES as i32)
+        .execute(pool)
+        .await?;
+
+    sqlx::query("INSERT INTO oidc_logins (state, nonce, code_verifier, redirect_uri) VALUES ($1, $2, $3, $4)")
+        .bind(state)
+        .bind(nonce)
+        .bind(code_verifier)
+        .bind(redirect_uri)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn take_oidc_login(pool: &PgPool, state: &str) -> anyhow::Result<Option<(String, String, String)>> {
+    let login = sqlx::query_as::<_, (String, String, String)>(
+        r#"
+        DELETE FROM oidc_logins
+        WHERE state = $1 AND created_at > NOW() - make_interval(mins => $2)
+        RETURNING nonce, code_verifier, redirect_uri
+        "#,
+    )
+    .bind(state)
+    .bind(OIDC_LOGIN_TTL_MINUTES as i32)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(login)
+}
+
+pub const WEBAUTHN_CHALLENGE_TTL_MINUTES: i64 = 5;
+
+const WEBAUTHN_CREDENTIAL_COLUMNS: &str = "id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at";
+
+pub async fn create_webauthn_challenge(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    purpose: &str,
+    challenge: &[u8],
+) -> anyhow::Result<Uuid> {
+    sqlx::query("DELETE FROM webauthn_challenges WHERE created_at < NOW() - make_interval(mins => $1)")
+        .bind(WEBAUTHN_CHALLENGE_TTL_MINUTES as i32)
+        .execute(pool)
+        .await?;
+
+    let id: Uuid = sqlx::query_scalar(
+        "INSERT INTO webauthn_challenges (user_id, purpose, challenge) VALUES ($1, $2, $3) RETURNING id",
+    )
+    .bind(user_id)
+    .bind(purpose)
+    .bind(challenge)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn take_webauthn_challenge(
+    pool: &PgPool,
+    id: &Uuid,
+    purpose: &str,
+) -> anyhow::Result<Option<(Option<Uuid>, Vec<u8>)>> {
+    let challenge = sqlx::query_as::<_, (Option<Uuid>, Vec<u8>)>(
+        r#"
+        DELETE FROM webauthn_challenges
+        WHERE id = $1 AND purpose = $2 AND created_at > NOW() - make_interval(mins => $3)
+        RETURNING user_id, challenge
+        "#,
+    )
+    .bind(id)
+    .bind(purpose)
+    .bind(WEBAUTHN_CHALLENGE_TTL_MINUTES as i32)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(challenge)
+}
+
+pub async fn create_webauthn_credential(
+    pool: &PgPool,
+    user_id: &Uuid,
+    credential_id: &[u8],
+    public_key: &[u8],
+    sign_count: i64,
+    name: &str,
+) -> anyhow::Result<WebauthnCredential> {
+    let credential = sqlx::query_as::<_, WebauthnCredential>(&format!(
+        r#"
+        INSERT INTO webauthn_credentials (user_id, credential_id, public_key, sign_count, name)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {}
+        "#,
+        WEBAUTHN_CREDENTIAL_COLUMNS
+    ))
+    .bind(user_id)
+    .bind(credential_id)
+    .bind(public_key)
+    .bind(sign_count)
+    .bind(name)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(credential)
+}
+
+pub async fn get_webauthn_credentials(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Vec<WebauthnCredential>> {
+    let credentials = sqlx::query_as::<_, WebauthnCredential>(&format!(
+        "SELECT {} FROM webauthn_credentials WHERE user_id = $1 ORDER BY created_at",
+        WEBAUTHN_CREDENTIAL_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(credentials)
+}
+
+pub async fn get_webauthn_credential(pool: &PgPool, credential_id: &[u8]) -> anyhow::Result<Option<WebauthnCredential>> {
+    let credential = sqlx::query_as::<_, WebauthnCredential>(&format!(
+        "SELECT {} FROM webauthn_credentials WHERE credential_id = $1",
+        WEBAUTHN_CREDENTIAL_COLUMNS
+    ))
+    .bind(credential_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(credential)
+}
+
+pub async fn update_webauthn_sign_count(pool: &PgPool, id: &Uuid, sign_count: i64) -> anyhow::Result<()> {
+    sqlx::query("UPDATE webauthn_credentials SET sign_count = $1, last_used_at = NOW() WHERE id = $2")
+        .bind(sign_count)
+        .bind(id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_webauthn_credential(pool: &PgPool, id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_user_by_identity(pool: &PgPool, issuer: &str, subject: &str) -> anyhow::Result<Option<User>> {
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        SELECT u.id, u.username, u.email, u.password_hash, u.is_admin, u.tenant_id, u.deactivated_at, u.created_at, u.updated_at
+        FROM user_identities i
+        JOIN users u ON u.id = i.user_id
+        WHERE i.issuer = $1 AND i.subject = $2
+        "#,
+    )
+    .bind(issuer)
+    .bind(subject)
+    .fetch_optional(pool)
     .await?;
 
-    sqlx::query(
-        "ALTER TABLE files ADD COLUMN IF NOT EXISTS is_deleted BOOLEAN DEFAULT FALSE"
-    )
-    .execute(pool)
-    .await?;
+    Ok(user)
+}
 
+pub async fn link_user_identity(pool: &PgPool, user_id: &Uuid, issuer: &str, subject: &str) -> anyhow::Result<()> {
     sqlx::query(
-        "ALTER TABLE files ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMP WITH TIME ZONE"
+        "INSERT INTO user_identities (issuer, subject, user_id) VALUES ($1, $2, $3) ON CONFLICT (issuer, subject) DO NOTHING",
     )
+    .bind(issuer)
+    .bind(subject)
+    .bind(user_id)
     .execute(pool)
     .await?;
 
+    Ok(())
+}
+
+pub async fn add_user_transfer(pool: &PgPool, user_id: &Uuid, uploaded: i64, downloaded: i64) -> anyhow::Result<()> {
     sqlx::query(
         r#"
-        CREATE TABLE IF NOT EXISTS shared_links (
-            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-            file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
-            token VARCHAR(255) UNIQUE NOT NULL,
-            expires_at TIMESTAMP WITH TIME ZONE,
-            is_read_only BOOLEAN DEFAULT TRUE,
-            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
-        )
+        INSERT INTO user_transfers (user_id, day, bytes_uploaded, bytes_downloaded)
+        VALUES ($1, (NOW() AT TIME ZONE 'UTC')::date, $2, $3)
+        ON CONFLICT (user_id, day) DO UPDATE
+        SET bytes_uploaded = user_transfers.bytes_uploaded + EXCLUDED.bytes_uploaded,
+            bytes_downloaded = user_transfers.bytes_downloaded + EXCLUDED.bytes_downloaded
         "#,
     )
+    .bind(user_id)
+    .bind(uploaded)
+    .bind(downloaded)
     .execute(pool)
     .await?;
 
-    sqlx::query(
+    Ok(())
+}
+
+pub async fn get_user_transfers(pool: &PgPool, user_id: &Uuid, since: NaiveDate) -> anyhow::Result<Vec<TransferDay>> {
+    let days = sqlx::query_as::<_, TransferDay>(
         r#"
-        CREATE TABLE IF NOT EXISTS chunked_uploads (
-            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-            user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
-            filename VARCHAR(255) NOT NULL,
-            total_size BIGINT NOT NULL,
-            chunk_size BIGINT NOT NULL,
-            total_chunks INTEGER NOT NULL,
-            uploaded_chunks INTEGER DEFAULT 0,
-            temp_path VARCHAR(500) NOT NULL,
-            disk_path VARCHAR(500) NOT NULL,
-            is_completed BOOLEAN DEFAULT FALSE,
-            created_at TIMESTAMP WITH TIME ZONE DEFAULT NOW(),
-            updated_at TIMESTAMP WITH TIME ZONE DEFAULT NOW()
-        )
+        SELECT day, bytes_uploaded, bytes_downloaded
+        FROM user_transfers
+        WHERE user_id = $1 AND day >= $2
+        ORDER BY day DESC
         "#,
     )
-    .execute(pool)
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
     .await?;
 
-    Ok(())
+    Ok(days)
 }
 
-pub async fn create_user(
-    pool: &PgPool,
-    username: &str,
-    email: &str,
-    password_hash: &str,
-    is_admin: bool,
-) -> anyhow::Result<User> {
-    let user = sqlx::query_as::<_, User>(
+pub async fn get_user_transfer_totals(pool: &PgPool, user_id: &Uuid, since: NaiveDate) -> anyhow::Result<(i64, i64)> {
+    let totals: (i64, i64) = sqlx::query_as(
         r#"
-        INSERT INTO users (username, email, password_hash, is_admin)
-        VALUES ($1, $2, $3, $4)
-        RETURNING id, username, email, password_hash, is_admin, created_at, updated_at
+        SELECT COALESCE(SUM(bytes_uploaded), 0)::BIGINT, COALESCE(SUM(bytes_downloaded), 0)::BIGINT
+        FROM user_transfers
+        WHERE user_id = $1 AND day >= $2
         "#,
     )
-    .bind(username)
-    .bind(email)
-    .bind(password_hash)
-    .bind(is_admin)
+    .bind(user_id)
+    .bind(since)
     .fetch_one(pool)
     .await?;
 
-    Ok(user)
+    Ok(totals)
 }
 
-pub async fn get_user_by_username(pool: &PgPool, username: &str) -> anyhow::Result<Option<User>> {
-    let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, is_admin, created_at, updated_at FROM users WHERE username = $1",
+pub async fn get_user_egress_settings(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<(Option<i64>, bool)> {
+    let settings: (Option<i64>, bool) = sqlx::query_as(
+        "SELECT egress_limit_bytes, egress_unlimited FROM users WHERE id = $1",
     )
-    .bind(username)
-    .fetch_optional(pool)
+    .bind(user_id)
+    .fetch_one(pool)
     .await?;
 
-    Ok(user)
+    Ok(settings)
 }
 
-pub async fn get_user_by_email(pool: &PgPool, email: &str) -> anyhow::Result<Option<User>> {
-    let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, is_admin, created_at, updated_at FROM users WHERE email = $1",
+pub async fn set_user_egress_settings(
+    pool: &PgPool,
+    user_id: &Uuid,
+    limit_bytes: Option<i64>,
+    unlimited: bool,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE users SET egress_limit_bytes = $1, egress_unlimited = $2, updated_at = NOW() WHERE id = $3",
     )
-    .bind(email)
-    .fetch_optional(pool)
+    .bind(limit_bytes)
+    .bind(unlimited)
+    .bind(user_id)
+    .execute(pool)
     .await?;
 
-    Ok(user)
+    Ok(result.rows_affected() > 0)
 }
 
-pub async fn get_user_by_id(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<User>> {
-    let user = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, is_admin, created_at, updated_at FROM users WHERE id = $1",
+pub async fn get_user_trash_limit(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<Option<i64>> {
+    let (limit,): (Option<i64>,) = sqlx::query_as("SELECT trash_limit_bytes FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(limit)
+}
+
+pub async fn set_user_trash_limit(pool: &PgPool, user_id: &Uuid, limit_bytes: Option<i64>) -> anyhow::Result<bool> {
+    let result = sqlx::query("UPDATE users SET trash_limit_bytes = $1, updated_at = NOW() WHERE id = $2")
+        .bind(limit_bytes)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn get_trash_usage(pool: &PgPool, user_id: &Uuid) -> anyhow::Result<(i64, i64)> {
+    let usage: (i64, i64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(file_size), 0)::BIGINT, COUNT(*) FROM files WHERE user_id = $1 AND is_deleted = TRUE",
     )
     .bind(user_id)
-    .fetch_optional(pool)
+    .fetch_one(pool)
     .await?;
 
-    Ok(user)
+    Ok(usage)
 }
 
-pub async fn get_all_users(pool: &PgPool) -> anyhow::Result<Vec<User>> {
-    let users = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, is_admin, created_at, updated_at FROM users ORDER BY created_at DESC",
+pub async fn get_users_over_trash_limit(pool: &PgPool, default_limit: Option<i64>) -> anyhow::Result<Vec<Uuid>> {
+    let users: Vec<(Uuid,)> = sqlx::query_as(
+        r#"
+        SELECT u.id FROM users u
+        JOIN files f ON f.user_id = u.id AND f.is_deleted = TRUE
+        WHERE COALESCE(u.trash_limit_bytes, $1) IS NOT NULL
+        GROUP BY u.id
+        HAVING SUM(f.file_size) > MAX(COALESCE(u.trash_limit_bytes, $1))
+        "#,
     )
+    .bind(default_limit)
     .fetch_all(pool)
     .await?;
 
-    Ok(users)
+    Ok(users.into_iter().map(|(id,)| id).collect())
 }
 
+pub async fn count_active_chunked_uploads(pool: &PgPool) -> anyhow::Result<i64> {
+    let count: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM chunked_uploads
+        WHERE is_completed = FALSE AND status = 'active' AND updated_at > NOW() - INTERVAL '5 minutes'
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(count)
+}
 
+pub async fn get_queue_depth(pool: &PgPool) -> anyhow::Result<i64> {
+    let depth: i64 = sqlx::query_scalar(
+        r#"
+        SELECT (SELECT COUNT(*) FROM broadcast_recipients WHERE status = 'pending')
+            + (SELECT COUNT(*) FROM remote_fetches WHERE status IN ('pending', 'downloading'))
+            + (SELECT COUNT(*) FROM import_jobs WHERE status = 'running')
+            + (SELECT COUNT(*) FROM operations WHERE status = 'running')
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
 
-pub async fn create_file_record(
+    Ok(depth)
+}
+
+pub async fn create_notification(
     pool: &PgPool,
     user_id: &Uuid,
-    filename: &str,
-    original_filename: &str,
-    file_path: &str,
-    disk_path: &str,
-    file_size: i64,
-    mime_type: Option<&str>,
-) -> anyhow::Result<FileInfo> {
-    let file = sqlx::query_as::<_, FileInfo>(
+    kind: &str,
+    message: &str,
+    data: Option<&serde_json::Value>,
+) -> anyhow::Result<Notification> {
+    let notification = sqlx::query_as::<_, Notification>(
         r#"
-        INSERT INTO files (user_id, filename, original_filename, file_path, disk_path, file_size, mime_type, is_deleted)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, FALSE)
-        RETURNING id, user_id, filename, original_filename, file_path, disk_path, file_size, mime_type, is_deleted, deleted_at, created_at, updated_at
+        INSERT INTO notifications (user_id, kind, message, data)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, user_id, kind, message, data, read_at, created_at
         "#,
     )
     .bind(user_id)
-    .bind(filename)
-    .bind(original_filename)
-    .bind(file_path)
-    .bind(disk_path)
-    .bind(file_size)
-    .bind(mime_type)
+    .bind(kind)
+    .bind(message)
+    .bind(data)
     .fetch_one(pool)
     .await?;
 
-    Ok(file)
+    Ok(notification)
 }
 
+pub async fn get_notifications(pool: &PgPool, user_id: &Uuid, unread_only: bool) -> anyhow::Result<Vec<Notification>> {
+    let notifications = sqlx::query_as::<_, Notification>(
+        r#"
+        SELECT id, user_id, kind, message, data, read_at, created_at FROM notifications
+        WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#,
+    )
+    .bind(user_id)
+    .bind(unread_only)
+    .fetch_all(pool)
+    .await?;
 
+    Ok(notifications)
+}
 
-pub async fn get_file_by_id(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<Option<FileInfo>> {
-    let file = sqlx::query_as::<_, FileInfo>(
-        "SELECT id, user_id, filename, original_filename, file_path, disk_path, file_size, mime_type, is_deleted, deleted_at, created_at, updated_at FROM files WHERE id = $1",
+pub async fn mark_notification_read(pool: &PgPool, notification_id: &Uuid, user_id: &Uuid) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = COALESCE(read_at, NOW()) WHERE id = $1 AND user_id = $2",
     )
-    .bind(file_id)
-    .fetch_optional(pool)
+    .bind(notification_id)
+    .bind(user_id)
+    .execute(pool)
     .await?;
 
-    Ok(file)
+    Ok(result.rows_affected() > 0)
 }
 
-pub async fn delete_file_record(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<()> {
-    sqlx::query("DELETE FROM files WHERE id = $1")
-        .bind(file_id)
-        .execute(pool)
-        .await?;
+const BROADCAST_COLUMNS: &str = "b.id, b.created_by, b.subject, b.body, b.status, \
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id) AS total_recipients, \
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'sent') AS sent_count, \
+    (SELECT COUNT(*) FROM broadcast_recipients r WHERE r.broadcast_id = b.id AND r.status = 'failed') AS failed_count, \
+    b.created_at, b.finished_at";
 
-    Ok(())
-}
+pub async fn create_broadcast(pool: &PgPool, created_by: &Uuid, subject: &str, body: &str) -> anyhow::Result<Broadcast> {
+    let mut tx = pool.begin().await?;
 
-pub async fn get_all_files(pool: &PgPool) -> anyhow::Result<Vec<FileInfo>> {
-    let files = sqlx::query_as::<_, FileInfo>(
-        "SELECT id, user_id, filename, original_filename, file_path, disk_path, file_size, mime_type, is_deleted, deleted_at, created_at, updated_at FROM files WHERE is_deleted = FALSE ORDER BY created_at DESC",
+    let (broadcast_id,): (Uuid,) = sqlx::query_as(
+        "INSERT INTO broadcasts (created_by, subject, body) VALUES ($1, $2, $3) RETURNING id",
     )
-    .fetch_all(pool)
+    .bind(created_by)
+    .bind(subject)
+    .bind(body)
+    .fetch_one(&mut *tx)
     .await?;
 
-    Ok(files)
+    sqlx::query(
+        r#"
+        INSERT INTO broadcast_recipients (broadcast_id, user_id, email)
+        SELECT $1, id, email FROM users
+        WHERE deactivated_at IS NULL AND email <> ''
+        "#,
+    )
+    .bind(broadcast_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let broadcast = sqlx::query_as::<_, Broadcast>(&format!(
+        "SELECT {} FROM broadcasts b WHERE b.id = $1",
+        BROADCAST_COLUMNS
+    ))
+    .bind(broadcast_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(broadcast)
 }
 
-pub async fn get_deleted_files(pool: &PgPool) -> anyhow::Result<Vec<FileInfo>> {
-    let files = sqlx::query_as::<_, FileInfo>(
-        "SELECT id, user_id, filename, original_filename, file_path, disk_path, file_size, mime_type, is_deleted, deleted_at, created_at, updated_at FROM files WHERE is_deleted = TRUE ORDER BY deleted_at DESC",
-    )
+pub async fn get_broadcasts(pool: &PgPool) -> anyhow::Result<Vec<Broadcast>> {
+    let broadcasts = sqlx::query_as::<_, Broadcast>(&format!(
+        "SELECT {} FROM broadcasts b ORDER BY b.created_at DESC",
+        BROADCAST_COLUMNS
+    ))
     .fetch_all(pool)
     .await?;
 
-    Ok(files)
+    Ok(broadcasts)
 }
 
-pub async fn soft_delete_file(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<()> {
-    sqlx::query("UPDATE files SET is_deleted = TRUE, deleted_at = NOW() WHERE id = $1")
-        .bind(file_id)
-        .execute(pool)
-        .await?;
+pub async fn get_broadcast(pool: &PgPool, broadcast_id: &Uuid) -> anyhow::Result<Option<Broadcast>> {
+    let broadcast = sqlx::query_as::<_, Broadcast>(&format!(
+        "SELECT {} FROM broadcasts b WHERE b.id = $1",
+        BROADCAST_COLUMNS
+    ))
+    .bind(broadcast_id)
+    .fetch_optional(pool)
+    .await?;
 
-    Ok(())
+    Ok(broadcast)
 }
 
-pub async fn restore_file(pool: &PgPool, file_id: &Uuid) -> anyhow::Result<()> {
-    sqlx::query("UPDATE files SET is_deleted = FALSE, deleted_at = NULL WHERE id = $1")
-        .bind(file_id)
-        .execute(pool)
-        .await?;
+pub async fn get_broadcast_recipients(pool: &PgPool, broadcast_id: &Uuid) -> anyhow::Result<Vec<BroadcastRecipient>> {
+    let recipients = sqlx::query_as::<_, BroadcastRecipient>(
+        r#"
+        SELECT user_id, email, status, error, sent_at FROM broadcast_recipients
+        WHERE broadcast_id = $1
+        ORDER BY email
+        "#,
+    )
+    .bind(broadcast_id)
+    .fetch_all(pool)
+    .await?;
 
-    Ok(())
+    Ok(recipients)
 }
 
+pub async fn claim_broadcast_recipients(pool: &PgPool, limit: i64) -> anyhow::Result<Vec<ClaimedRecipient>> {
+    let recipients = sqlx::query_as::<_, ClaimedRecipient>(
+        r#"
+        WITH claimed AS (
+            SELECT broadcast_id, user_id FROM broadcast_recipients
+            WHERE status = 'pending' OR (status = 'sending' AND claimed_at < NOW() - INTERVAL '10 minutes')
+            ORDER BY broadcast_id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        UPDATE broadcast_recipients r SET status = 'sending', claimed_at = NOW()
+        FROM claimed, users u
+        WHERE r.broadcast_id = claimed.broadcast_id AND r.user_id = claimed.user_id AND u.id = r.user_id
+        RETURNING r.broadcast_id, r.user_id, r.email, u.username
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
 
+    Ok(recipients)
+}
 
-pub async fn create_chunked_upload(
+pub async fn finish_broadcast_recipient(
     pool: &PgPool,
+    broadcast_id: &Uuid,
     user_id: &Uuid,
-    filename: &str,
-    total_size: i64,
-    chunk_size: i64,
-    total_chunks: i32,
-    temp_path: &str,
-    disk_path: &str,
-) -> anyhow::Result<ChunkedUpload> {
-    let upload = sqlx::query_as::<_, ChunkedUpload>(
+    error: Option<&str>,
+) -> anyhow::Result<()> {
+    sqlx::query(
         r#"
-        INSERT INTO chunked_uploads (user_id, filename, total_size, chunk_size, total_chunks, temp_path, disk_path)
-        VALUES ($1, $2, $3, $4, $5, $6, $7)
-        RETURNING id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, created_at, updated_at
+        UPDATE broadcast_recipients
+        SET status = CASE WHEN $3::text IS NULL THEN 'sent' ELSE 'failed' END,
+            error = $3,
+            sent_at = CASE WHEN $3::text IS NULL THEN NOW() ELSE NULL END
+        WHERE broadcast_id = $1 AND user_id = $2
         "#,
     )
+    .bind(broadcast_id)
     .bind(user_id)
-    .bind(filename)
-    .bind(total_size)
-    .bind(chunk_size)
-    .bind(total_chunks)
-    .bind(temp_path)
-    .bind(disk_path)
-    .fetch_one(pool)
+    .bind(error)
+    .execute(pool)
     .await?;
 
-    Ok(upload)
+    Ok(())
 }
 
-pub async fn get_chunked_upload(pool: &PgPool, upload_id: &Uuid) -> anyhow::Result<Option<ChunkedUpload>> {
-    let upload = sqlx::query_as::<_, ChunkedUpload>(
-        "SELECT id, user_id, filename, total_size, chunk_size, total_chunks, uploaded_chunks, temp_path, disk_path, is_completed, created_at, updated_at FROM chunked_uploads WHERE id = $1"
-    )
-    .bind(upload_id)
-    .fetch_optional(pool)
+pub async fn complete_finished_broadcasts(pool: &PgPool) -> anyhow::Result<Vec<Broadcast>> {
+    let broadcasts = sqlx::query_as::<_, Broadcast>(&format!(
+        r#"
+        UPDATE broadcasts b SET status = 'completed', finished_at = NOW()
+        WHERE b.status = 'sending' AND NOT EXISTS (
+            SELECT 1 FROM broadcast_recipients r
+            WHERE r.broadcast_id = b.id AND r.status IN ('pending', 'sending')
+        )
+        RETURNING {}
+        "#,
+        BROADCAST_COLUMNS
+    ))
+    .fetch_all(pool)
     .await?;
 
-    Ok(upload)
+    Ok(broadcasts)
 }
 
-pub async fn update_chunked_upload_progress(
-    pool: &PgPool,
-    upload_id: &Uuid,
-    uploaded_chunks: i32,
-) -> anyhow::Result<()> {
+pub async fn add_share_egress(pool: &PgPool, share_id: &Uuid, bytes: i64) -> anyhow::Result<()> {
     sqlx::query(
-        "UPDATE chunked_uploads SET uploaded_chunks = $1, updated_at = NOW() WHERE id = $2"
+        r#"
+        UPDATE shared_links
+        SET egress_bytes = CASE
+                WHEN egress_month = date_trunc('month', NOW() AT TIME ZONE 'UTC')::date THEN egress_bytes + $1
+                ELSE $1
+            END,
+            egress_month = date_trunc('month', NOW() AT TIME ZONE 'UTC')::date
+        WHERE id = $2
+        "#,
     )
-    .bind(uploaded_chunks)
-    .bind(upload_id)
+    .bind(bytes)
+    .bind(share_id)
     .execute(pool)
     .await?;
 
     Ok(())
 }
 
-pub async fn complete_chunked_upload(
+pub async fn set_share_egress_limit(
     pool: &PgPool,
-    upload_id: &Uuid,
-) -> anyhow::Result<()> {
-    sqlx::query(
-        "UPDATE chunked_uploads SET is_completed = TRUE, updated_at = NOW() WHERE id = $1"
+    share_id: &Uuid,
+    user_id: &Uuid,
+    limit_bytes: Option<i64>,
+) -> anyhow::Result<bool> {
+    let result = sqlx::query(
+        r#"
+        UPDATE shared_links s SET egress_limit_bytes = $1
+        FROM files f
+        WHERE s.id = $2 AND s.file_id = f.id AND f.user_id = $3
+        "#,
     )
-    .bind(upload_id)
+    .bind(limit_bytes)
+    .bind(share_id)
+    .bind(user_id)
     .execute(pool)
     .await?;
 
-    Ok(())
+    Ok(result.rows_affected() > 0)
 }
 
-pub async fn delete_chunked_upload(
-    pool: &PgPool,
-    upload_id: &Uuid,
-) -> anyhow::Result<()> {
+pub async fn get_preview_handlers(pool: &PgPool) -> anyhow::Result<Vec<PreviewHandlerRow>> {
+    let rows = sqlx::query_as::<_, PreviewHandlerRow>(
+        "SELECT mime_type, strategy, updated_at FROM preview_handlers ORDER BY mime_type",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+pub async fn set_preview_handler(pool: &PgPool, mime_type: &str, strategy: &str) -> anyhow::Result<()> {
     sqlx::query(
-        "DELETE FROM chunked_uploads WHERE id = $1"
+        r#"
+        INSERT INTO preview_handlers (mime_type, strategy)
+        VALUES ($1, $2)
+        ON CONFLICT (mime_type) DO UPDATE SET strategy = EXCLUDED.strategy, updated_at = NOW()
+        "#,
     )
-    .bind(upload_id)
+    .bind(mime_type)
+    .bind(strategy)
     .execute(pool)
     .await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+pub async fn delete_preview_handler(pool: &PgPool, mime_type: &str) -> anyhow::Result<bool> {
+    let result = sqlx::query("DELETE FROM preview_handlers WHERE mime_type = $1")
+        .bind(mime_type)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,173 @@
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use axum::http::HeaderMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256, Sha512};
+use crate::file_storage::ChecksumMismatch;
+
+pub const CONTENT_DIGEST_HEADERS: &[&str] = &["content-digest", "repr-digest", "digest"];
+pub const REPR_DIGEST_HEADERS: &[&str] = &["repr-digest", "digest"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha-256" => Some(Algorithm::Sha256),
+            "sha-512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha-256",
+            Algorithm::Sha512 => "sha-512",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpectedDigest {
+    pub algorithm: Algorithm,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct MalformedDigest(pub String);
+
+impl std::fmt::Display for MalformedDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "malformed digest header: {}", self.0)
+    }
+}
+
+impl std::error::Error for MalformedDigest {}
+
+fn parse_member(member: &str, structured: bool) -> Result<Option<ExpectedDigest>, MalformedDigest> {
+    let member = member.split(';').next().unwrap_or_default().trim();
+    if member.is_empty() {
+        return Ok(None);
+    }
+
+    let (name, value) = member
+        .split_once('=')
+        .ok_or_else(|| MalformedDigest(member.to_string()))?;
+    let algorithm = match Algorithm::from_name(name.trim()) {
+        Some(algorithm) => algorithm,
+        None => return Ok(None),
+    };
+
+    let value = value.trim();
+    let encoded = if structured {
+        value
+            .strip_prefix(':')
+            .and_then(|value| value.strip_suffix(':'))
+            .ok_or_else(|| MalformedDigest(member.to_string()))?
+    } else {
+        value
+    };
+
+    let value = STANDARD
+        .decode(encoded)
+        .map_err(|_| MalformedDigest(member.to_string()))?;
+    Ok(Some(ExpectedDigest { algorithm, value }))
+}
+
+pub fn expected_digests(headers: &HeaderMap, names: &[&str]) -> Result<Vec<ExpectedDigest>, MalformedDigest> {
+    let mut digests = Vec::new();
+
+    for name in names {
+        let structured = *name != "digest";
+        for value in headers.get_all(*name) {
+            let value = value.to_str().map_err(|_| MalformedDigest(name.to_string()))?;
+            for member in value.split(',') {
+                if let Some(digest) = parse_member(member, structured)? {
+                    digests.push(digest);
+                }
+            }
+        }
+    }
+
+    Ok(digests)
+}
+
+struct Hashers {
+    sha256: Option<Sha256>,
+    sha512: Option<Sha512>,
+}
+
+impl Hashers {
+    fn for_digests(expected: &[ExpectedDigest]) -> Self {
+        Hashers {
+            sha256: expected.iter().any(|d| d.algorithm == Algorithm::Sha256).then(Sha256::new),
+            sha512: expected.iter().any(|d| d.algorithm == Algorithm::Sha512).then(Sha512::new),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        if let Some(hasher) = &mut self.sha256 {
+            hasher.update(data);
+        }
+        if let Some(hasher) = &mut self.sha512 {
+            hasher.update(data);
+        }
+    }
+
+    fn verify(self, expected: &[ExpectedDigest]) -> anyhow::Result<()> {
+        let sha256 = self.sha256.map(|hasher| hasher.finalize().to_vec());
+        let sha512 = self.sha512.map(|hasher| hasher.finalize().to_vec());
+
+        for digest in expected {
+            let actual = match digest.algorithm {
+                Algorithm::Sha256 => sha256.as_deref(),
+                Algorithm::Sha512 => sha512.as_deref(),
+            }
+            .unwrap_or_default();
+
+            if actual != digest.value.as_slice() {
+                return Err(ChecksumMismatch {
+                    expected: format!("{}=:{}:", digest.algorithm.name(), STANDARD.encode(&digest.value)),
+                    actual: format!("{}=:{}:", digest.algorithm.name(), STANDARD.encode(actual)),
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn verify(expected: &[ExpectedDigest], data: &[u8]) -> anyhow::Result<()> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+
+    let mut hashers = Hashers::for_digests(expected);
+    hashers.update(data);
+    hashers.verify(expected)
+}
+
+pub fn verify_file(expected: &[ExpectedDigest], path: &Path) -> anyhow::Result<()> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+
+    let mut hashers = Hashers::for_digests(expected);
+    let mut file = fs::File::open(path)?;
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hashers.update(&buffer[..read]);
+    }
+
+    hashers.verify(expected)
+}
@@ -0,0 +1,150 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use serde_json::Value;
+use sysinfo::Disks;
+use crate::models::DiskHealth;
+
+const REALLOCATED_SECTORS: u64 = 5;
+const PENDING_SECTORS: u64 = 197;
+const UNCORRECTABLE_SECTORS: u64 = 198;
+
+// smartctl exit status bits 0 and 1 mean it could not parse its arguments or open the device;
+// the higher bits describe the disk itself and still come with a full report.
+const SMARTCTL_FATAL_BITS: i32 = 0b11;
+
+struct Device {
+    name: String,
+    read_only: bool,
+}
+
+fn device_for(path: &Path) -> Option<Device> {
+    let disks = Disks::new_with_refreshed_list();
+    let path_str = path.to_string_lossy();
+
+    disks
+        .iter()
+        .filter(|disk| path_str.starts_with(&*disk.mount_point().to_string_lossy()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| Device {
+            name: whole_device(&disk.name().to_string_lossy()),
+            read_only: disk.is_read_only(),
+        })
+}
+
+fn whole_device(name: &str) -> String {
+    if !cfg!(target_os = "linux") {
+        return name.to_string();
+    }
+    let Some(short_name) = name.strip_prefix("/dev/") else {
+        return name.to_string();
+    };
+    let block = match std::fs::canonicalize(PathBuf::from("/sys/class/block").join(short_name)) {
+        Ok(block) => block,
+        Err(_) => return name.to_string(),
+    };
+    if !block.join("partition").exists() {
+        return name.to_string();
+    }
+    block
+        .parent()
+        .and_then(Path::file_name)
+        .map(|parent| format!("/dev/{}", parent.to_string_lossy()))
+        .unwrap_or_else(|| name.to_string())
+}
+
+pub fn inspect(smartctl_path: &str, temperature_warning_celsius: i64, path: &Path) -> DiskHealth {
+    let mut health = DiskHealth {
+        device: None,
+        mount_read_only: false,
+        smart_passed: None,
+        temperature_celsius: None,
+        reallocated_sectors: None,
+        pending_sectors: None,
+        warnings: Vec::new(),
+    };
+
+    let device = match device_for(path) {
+        Some(device) => device,
+        None => {
+            health.warnings.push("no block device found for this path".to_string());
+            return health;
+        }
+    };
+    health.device = Some(device.name.clone());
+    health.mount_read_only = device.read_only;
+    if device.read_only {
+        health.warnings.push(format!("{} is mounted read-only", device.name));
+    }
+
+    let output = Command::new(smartctl_path)
+        .args(["--json=c", "--health", "--attributes", "--nocheck=standby"])
+        .arg(&device.name)
+        .stdin(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(e) => {
+            health.warnings.push(format!("failed to run {}: {}", smartctl_path, e));
+            return health;
+        }
+    };
+    if output.status.code().is_some_and(|code| code & SMARTCTL_FATAL_BITS != 0) {
+        health.warnings.push(format!("{} could not read SMART data from {}", smartctl_path, device.name));
+        return health;
+    }
+    let report: Value = match serde_json::from_slice(&output.stdout) {
+        Ok(report) => report,
+        Err(e) => {
+            health.warnings.push(format!("unreadable {} output: {}", smartctl_path, e));
+            return health;
+        }
+    };
+
+    apply_report(&mut health, &report, temperature_warning_celsius);
+    health
+}
+
+fn apply_report(health: &mut DiskHealth, report: &Value, temperature_warning_celsius: i64) {
+    health.smart_passed = report.pointer("/smart_status/passed").and_then(Value::as_bool);
+    health.temperature_celsius = report.pointer("/temperature/current").and_then(Value::as_i64);
+
+    let attribute = |id: u64| {
+        report
+            .pointer("/ata_smart_attributes/table")
+            .and_then(Value::as_array)?
+            .iter()
+            .find(|row| row.get("id").and_then(Value::as_u64) == Some(id))?
+            .pointer("/raw/value")
+            .and_then(Value::as_u64)
+    };
+    health.reallocated_sectors = attribute(REALLOCATED_SECTORS);
+    health.pending_sectors = match (attribute(PENDING_SECTORS), attribute(UNCORRECTABLE_SECTORS)) {
+        (None, None) => None,
+        (pending, uncorrectable) => Some(pending.unwrap_or(0).max(uncorrectable.unwrap_or(0))),
+    };
+    let nvme_log = report.get("nvme_smart_health_information_log");
+
+    if health.smart_passed == Some(false) {
+        health.warnings.push("SMART overall health self-assessment failed".to_string());
+    }
+    if let Some(temperature) = health.temperature_celsius.filter(|t| *t >= temperature_warning_celsius) {
+        health.warnings.push(format!("temperature is {}°C", temperature));
+    }
+    if let Some(sectors) = health.reallocated_sectors.filter(|s| *s > 0) {
+        health.warnings.push(format!("{} reallocated sectors", sectors));
+    }
+    if let Some(sectors) = health.pending_sectors.filter(|s| *s > 0) {
+        health.warnings.push(format!("{} pending or uncorrectable sectors", sectors));
+    }
+    if let Some(log) = nvme_log {
+        if let Some(flags) = log.get("critical_warning").and_then(Value::as_u64).filter(|f| *f != 0) {
+            health.warnings.push(format!("NVMe critical warning flags {:#04x}", flags));
+        }
+        if let Some(errors) = log.get("media_errors").and_then(Value::as_u64).filter(|e| *e > 0) {
+            health.warnings.push(format!("{} NVMe media errors", errors));
+        }
+        if let Some(used) = log.get("percentage_used").and_then(Value::as_u64).filter(|u| *u >= 90) {
+            health.warnings.push(format!("{}% of rated endurance used", used));
+        }
+    }
+}
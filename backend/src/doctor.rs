@@ -0,0 +1,301 @@
+use std::fs;
+use std::process::Command;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use crate::auth;
+use crate::config::Config;
+use crate::database;
+use crate::file_storage::{FileStorage, MIN_FREE_SPACE_BUFFER};
+use crate::s3::S3Store;
+
+const NETWORK_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Skipped,
+    Warning,
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Finding {
+    pub check: String,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(check: &str, severity: Severity, message: impl Into<String>) -> Self {
+        Finding {
+            check: check.to_string(),
+            severity,
+            message: message.into(),
+        }
+    }
+}
+
+pub async fn run_all_checks(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    findings.extend(check_database(config).await);
+    findings.extend(check_storage_paths(config));
+    findings.push(check_s3(config));
+    findings.push(check_jwt_secret(config));
+    findings.push(check_smtp(config).await);
+    findings.extend(check_ffmpeg(config));
+    findings.push(check_pdftotext(config));
+    findings.push(check_lp(config));
+    findings.push(check_smartctl(config));
+    findings.push(check_clamd(config).await);
+
+    findings
+}
+
+pub fn run_startup_checks(config: &Config) -> Vec<Finding> {
+    let mut findings = check_storage_paths(config);
+    findings.push(check_jwt_secret(config));
+    findings
+}
+
+pub fn print_report(findings: &[Finding]) -> bool {
+    for finding in findings {
+        let label = match finding.severity {
+            Severity::Ok => " OK ",
+            Severity::Skipped => "SKIP",
+            Severity::Warning => "WARN",
+            Severity::Error => "FAIL",
+        };
+        println!("[{}] {}: {}", label, finding.check, finding.message);
+    }
+
+    let errors = findings.iter().filter(|f| f.severity == Severity::Error).count();
+    let warnings = findings.iter().filter(|f| f.severity == Severity::Warning).count();
+    println!();
+    println!("{} error(s), {} warning(s)", errors, warnings);
+
+    errors == 0
+}
+
+async fn check_database(config: &Config) -> Vec<Finding> {
+    let pool = match database::create_connection_pool(&config.database_url).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            return vec![Finding::new(
+                "database",
+                Severity::Error,
+                format!("cannot connect: {} (check DATABASE_URL)", e),
+            )];
+        }
+    };
+
+    let mut findings = vec![Finding::new("database", Severity::Ok, "connected")];
+
+    match database::get_missing_tables(&pool).await {
+        Ok(missing) if missing.is_empty() => {
+            findings.push(Finding::new("migrations", Severity::Ok, "all expected tables present"));
+        }
+        Ok(missing) => {
+            findings.push(Finding::new(
+                "migrations",
+                Severity::Warning,
+                format!("missing tables: {} (start the server once to create them)", missing.join(", ")),
+            ));
+        }
+        Err(e) => {
+            findings.push(Finding::new("migrations", Severity::Error, format!("cannot inspect schema: {}", e)));
+        }
+    }
+
+    findings
+}
+
+fn check_storage_paths(config: &Config) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    let storage = match FileStorage::new(config) {
+        Ok(storage) => storage,
+        Err(e) => {
+            findings.push(Finding::new(
+                "storage",
+                Severity::Error,
+                format!("cannot initialize storage paths: {} (check STORAGE_PATHS)", e),
+            ));
+            return findings;
+        }
+    };
+
+    for path in &storage.storage_paths() {
+        let check = format!("storage {}", path.display());
+        let probe = path.join(format!(".doctor-{}", uuid::Uuid::new_v4()));
+
+        if let Err(e) = fs::write(&probe, b"probe").and_then(|_| fs::remove_file(&probe)) {
+            findings.push(Finding::new(&check, Severity::Error, format!("not writable: {}", e)));
+            continue;
+        }
+
+        match storage.get_single_disk_info(path, 0) {
+            Ok(info) if info.available_space <= MIN_FREE_SPACE_BUFFER => {
+                findings.push(Finding::new(
+                    &check,
+                    Severity::Warning,
+                    format!(
+                        "only {} MB free, below the {} MB placement buffer",
+                        info.available_space / (1024 * 1024),
+                        MIN_FREE_SPACE_BUFFER / (1024 * 1024)
+                    ),
+                ));
+            }
+            Ok(info) => {
+                findings.push(Finding::new(
+                    &check,
+                    Severity::Ok,
+                    format!("writable, {} MB free ({}% used)", info.available_space / (1024 * 1024), info.usage_percentage),
+                ));
+            }
+            Err(e) => {
+                findings.push(Finding::new(&check, Severity::Warning, format!("cannot read free space: {}", e)));
+            }
+        }
+    }
+
+    findings
+}
+
+fn check_s3(config: &Config) -> Finding {
+    let s3_config = match &config.s3 {
+        Some(s3_config) => s3_config,
+        None => return Finding::new("s3", Severity::Skipped, "S3_BUCKET not configured"),
+    };
+
+    match S3Store::new(s3_config.clone()).and_then(|store| store.check()) {
+        Ok(()) => Finding::new("s3", Severity::Ok, format!("bucket {} reachable", s3_config.bucket)),
+        Err(e) => Finding::new(
+            "s3",
+            Severity::Error,
+            format!("bucket {} unreachable: {:#} (check S3_ENDPOINT and credentials)", s3_config.bucket, e),
+        ),
+    }
+}
+
+fn check_jwt_secret(config: &Config) -> Finding {
+    match auth::jwt_secret_problem(&config.jwt_secret) {
+        Some(problem) if config.dev_mode => {
+            Finding::new("jwt secret", Severity::Warning, format!("{} (allowed because DEV_MODE is set)", problem))
+        }
+        Some(problem) => Finding::new(
+            "jwt secret",
+            Severity::Error,
+            format!("{}; run `generate-secret` to create one", problem),
+        ),
+        None => Finding::new("jwt secret", Severity::Ok, "configured"),
+    }
+}
+
+async fn check_tcp(address: &str) -> Result<(), String> {
+    match tokio::time::timeout(NETWORK_CHECK_TIMEOUT, TcpStream::connect(address)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("connection timed out".to_string()),
+    }
+}
+
+async fn check_smtp(config: &Config) -> Finding {
+    let host = match &config.smtp_host {
+        Some(host) => host,
+        None => return Finding::new("smtp", Severity::Skipped, "SMTP_HOST not configured"),
+    };
+
+    let address = format!("{}:{}", host, config.smtp_port);
+    match check_tcp(&address).await {
+        Ok(()) => Finding::new("smtp", Severity::Ok, format!("{} reachable", address)),
+        Err(e) => Finding::new("smtp", Severity::Warning, format!("{} unreachable: {}", address, e)),
+    }
+}
+
+fn check_ffmpeg(config: &Config) -> Vec<Finding> {
+    [("ffmpeg", &config.ffmpeg_path), ("ffprobe", &config.ffprobe_path)]
+        .into_iter()
+        .map(|(check, path)| match Command::new(path).arg("-version").output() {
+            Ok(output) if output.status.success() => {
+                let version = String::from_utf8_lossy(&output.stdout)
+                    .lines()
+                    .next()
+                    .unwrap_or(check)
+                    .to_string();
+                Finding::new(check, Severity::Ok, version)
+            }
+            _ if config.video_metadata => Finding::new(
+                check,
+                Severity::Warning,
+                format!("{} not found; video metadata and posters will not be extracted", path),
+            ),
+            _ => Finding::new(check, Severity::Skipped, format!("{} not found", path)),
+        })
+        .collect()
+}
+
+fn check_pdftotext(config: &Config) -> Finding {
+    if !config.content_indexing {
+        return Finding::new("pdftotext", Severity::Skipped, "CONTENT_INDEXING disabled");
+    }
+
+    match Command::new(&config.pdftotext_path).arg("-v").output() {
+        Ok(output) => {
+            let version = String::from_utf8_lossy(&output.stderr)
+                .lines()
+                .next()
+                .unwrap_or("pdftotext")
+                .to_string();
+            Finding::new("pdftotext", Severity::Ok, version)
+        }
+        Err(_) => Finding::new(
+            "pdftotext",
+            Severity::Warning,
+            format!("{} not found; PDFs will not be content-indexed", config.pdftotext_path),
+        ),
+    }
+}
+
+fn check_lp(config: &Config) -> Finding {
+    if !config.printing_enabled {
+        return Finding::new("lp", Severity::Skipped, "PRINTING_ENABLED disabled");
+    }
+
+    let printer = config.printer.as_deref().unwrap_or("the default CUPS destination");
+    match Command::new(&config.lp_path).arg("--help").output() {
+        Ok(_) => Finding::new("lp", Severity::Ok, format!("{} found; printing to {}", config.lp_path, printer)),
+        Err(_) => Finding::new(
+            "lp",
+            Severity::Warning,
+            format!("{} not found; POST /files/:id/print will fail", config.lp_path),
+        ),
+    }
+}
+
+fn check_smartctl(config: &Config) -> Finding {
+    if !config.smart_enabled {
+        return Finding::new("smartctl", Severity::Skipped, "SMART_ENABLED disabled");
+    }
+
+    match Command::new(&config.smartctl_path).arg("--version").output() {
+        Ok(_) => Finding::new("smartctl", Severity::Ok, format!("{} found", config.smartctl_path)),
+        Err(_) => Finding::new(
+            "smartctl",
+            Severity::Warning,
+            format!("{} not found; GET /admin/storage will report no SMART data", config.smartctl_path),
+        ),
+    }
+}
+
+async fn check_clamd(config: &Config) -> Finding {
+    let address = match &config.clamd_address {
+        Some(address) => address,
+        None => return Finding::new("clamd", Severity::Skipped, "CLAMD_ADDRESS not configured"),
+    };
+
+    match check_tcp(address).await {
+        Ok(()) => Finding::new("clamd", Severity::Ok, format!("{} reachable", address)),
+        Err(e) => Finding::new("clamd", Severity::Warning, format!("{} unreachable: {}", address, e)),
+    }
+}
@@ -0,0 +1,80 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use uuid::Uuid;
+use crate::models::SharedLink;
+use crate::{database, AppState};
+
+pub struct EgressExceeded {
+    scope: &'static str,
+    limit_bytes: i64,
+    used_bytes: i64,
+}
+
+impl IntoResponse for EgressExceeded {
+    fn into_response(self) -> Response {
+        let resets_at = next_month_start(Utc::now());
+        let retry_after = (resets_at - Utc::now()).num_seconds().max(1);
+        let message = match self.scope {
+            "share" => "This share has reached its monthly download limit",
+            _ => "The owner of this file has reached their monthly download limit",
+        };
+
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(serde_json::json!({
+                "error": "egress_limit_exceeded",
+                "message": message,
+                "scope": self.scope,
+                "limit_bytes": self.limit_bytes,
+                "used_bytes": self.used_bytes,
+                "resets_at": resets_at,
+            })),
+        )
+            .into_response()
+    }
+}
+
+pub fn month_start(now: DateTime<Utc>) -> NaiveDate {
+    now.date_naive().with_day(1).unwrap_or_else(|| now.date_naive())
+}
+
+fn next_month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = if now.month() == 12 { (now.year() + 1, 1) } else { (now.year(), now.month() + 1) };
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single().unwrap_or(now)
+}
+
+pub async fn user_limit(state: &AppState, user_id: &Uuid) -> anyhow::Result<Option<i64>> {
+    let (limit, unlimited) = database::get_user_egress_settings(&state.db, user_id).await?;
+    if unlimited {
+        return Ok(None);
+    }
+    Ok(limit.or(state.config.monthly_egress_limit))
+}
+
+pub async fn check(
+    state: &AppState,
+    owner_id: &Uuid,
+    share: Option<&SharedLink>,
+    bytes: i64,
+) -> anyhow::Result<Option<EgressExceeded>> {
+    if let Some(share) = share {
+        if let Some(limit) = share.egress_limit_bytes {
+            if share.egress_used_bytes + bytes > limit {
+                return Ok(Some(EgressExceeded { scope: "share", limit_bytes: limit, used_bytes: share.egress_used_bytes }));
+            }
+        }
+    }
+
+    if let Some(limit) = user_limit(state, owner_id).await? {
+        let (_, used) = database::get_user_transfer_totals(&state.db, owner_id, month_start(Utc::now())).await?;
+        if used + bytes > limit {
+            return Ok(Some(EgressExceeded { scope: "user", limit_bytes: limit, used_bytes: used }));
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,317 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::Engine;
+
+pub const MAGIC: &[u8; 8] = b"\0LDENC1\n";
+pub const SEGMENT_SIZE: usize = 64 * 1024;
+
+const KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+const TAG_LENGTH: usize = 16;
+const HEADER_LENGTH: u64 = (MAGIC.len() + NONCE_LENGTH + KEY_LENGTH + TAG_LENGTH) as u64;
+const SEALED_SEGMENT_SIZE: u64 = (SEGMENT_SIZE + TAG_LENGTH) as u64;
+
+pub trait ReadSeek: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> ReadSeek for T {}
+
+#[derive(Clone)]
+pub struct StorageKey([u8; KEY_LENGTH]);
+
+impl std::fmt::Debug for StorageKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StorageKey(..)")
+    }
+}
+
+impl StorageKey {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(value.trim())
+            .map_err(|_| anyhow::anyhow!("the storage encryption key is not valid base64"))?;
+        let key: [u8; KEY_LENGTH] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("the storage encryption key must be {} bytes", KEY_LENGTH))?;
+        Ok(StorageKey(key))
+    }
+
+    pub fn generate() -> String {
+        let mut key = [0u8; KEY_LENGTH];
+        OsRng.fill_bytes(&mut key);
+        base64::engine::general_purpose::STANDARD.encode(key)
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new((&self.0).into())
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn segment_nonce(index: u32, last: bool) -> [u8; NONCE_LENGTH] {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    nonce[7..11].copy_from_slice(&index.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+pub fn is_encrypted(header: &[u8]) -> bool {
+    header.starts_with(MAGIC)
+}
+
+pub fn plaintext_length(encrypted_length: u64) -> Option<u64> {
+    let body_length = encrypted_length.checked_sub(HEADER_LENGTH)?;
+    let segments = body_length.div_ceil(SEALED_SEGMENT_SIZE).max(1);
+    body_length.checked_sub(segments * TAG_LENGTH as u64)
+}
+
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    cipher: Aes256Gcm,
+    buffer: Vec<u8>,
+    index: u32,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(key: &StorageKey, mut inner: W) -> io::Result<Self> {
+        let mut data_key = [0u8; KEY_LENGTH];
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut data_key);
+        OsRng.fill_bytes(&mut nonce);
+
+        let wrapped = key
+            .cipher()
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &data_key, aad: MAGIC })
+            .map_err(|_| io::Error::other("failed to wrap the file key"))?;
+        inner.write_all(MAGIC)?;
+        inner.write_all(&nonce)?;
+        inner.write_all(&wrapped)?;
+
+        Ok(EncryptWriter {
+            inner,
+            cipher: Aes256Gcm::new((&data_key).into()),
+            buffer: Vec::with_capacity(SEGMENT_SIZE * 2),
+            index: 0,
+        })
+    }
+
+    fn seal(&mut self, length: usize, last: bool) -> io::Result<()> {
+        let sealed = self
+            .cipher
+            .encrypt(Nonce::from_slice(&segment_nonce(self.index, last)), &self.buffer[..length])
+            .map_err(|_| io::Error::other("failed to encrypt a segment"))?;
+        self.inner.write_all(&sealed)?;
+        self.buffer.drain(..length);
+        self.index = self
+            .index
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("file is too large to encrypt"))?;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal(self.buffer.len(), true)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(data);
+        while self.buffer.len() > SEGMENT_SIZE {
+            self.seal(SEGMENT_SIZE, false)?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub struct DecryptReader<R: Read + Seek> {
+    inner: R,
+    cipher: Aes256Gcm,
+    segments: u64,
+    body_length: u64,
+    length: u64,
+    position: u64,
+    segment: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> DecryptReader<R> {
+    pub fn new(key: &StorageKey, mut inner: R) -> io::Result<Self> {
+        let total = inner.seek(SeekFrom::End(0))?;
+        let length = plaintext_length(total).ok_or_else(|| invalid_data("encrypted file is truncated"))?;
+        inner.seek(SeekFrom::Start(0))?;
+
+        let mut header = [0u8; HEADER_LENGTH as usize];
+        inner.read_exact(&mut header)?;
+        if !is_encrypted(&header) {
+            return Err(invalid_data("file is not encrypted"));
+        }
+        let (nonce, wrapped) = header[MAGIC.len()..].split_at(NONCE_LENGTH);
+        let data_key = key
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: wrapped, aad: MAGIC })
+            .map_err(|_| invalid_data("the storage encryption key does not match this file"))?;
+
+        let body_length = total - HEADER_LENGTH;
+        Ok(DecryptReader {
+            inner,
+            cipher: Aes256Gcm::new_from_slice(&data_key).map_err(|_| invalid_data("invalid file key"))?,
+            segments: body_length.div_ceil(SEALED_SEGMENT_SIZE),
+            body_length,
+            length,
+            position: 0,
+            segment: None,
+        })
+    }
+
+    fn load(&mut self, index: u64) -> io::Result<()> {
+        if matches!(&self.segment, Some((loaded, _)) if *loaded == index) {
+            return Ok(());
+        }
+
+        let offset = index * SEALED_SEGMENT_SIZE;
+        let mut sealed = vec![0u8; SEALED_SEGMENT_SIZE.min(self.body_length - offset) as usize];
+        self.inner.seek(SeekFrom::Start(HEADER_LENGTH + offset))?;
+        self.inner.read_exact(&mut sealed)?;
+
+        let nonce = segment_nonce(index as u32, index + 1 == self.segments);
+        let plain = self
+            .cipher
+            .decrypt(Nonce::from_slice(&nonce), sealed.as_slice())
+            .map_err(|_| invalid_data("encrypted file is corrupted"))?;
+        self.segment = Some((index, plain));
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.length {
+            return Ok(0);
+        }
+
+        let index = self.position / SEGMENT_SIZE as u64;
+        self.load(index)?;
+        let plain = match &self.segment {
+            Some((_, plain)) => plain,
+            None => return Ok(0),
+        };
+        let start = (self.position % SEGMENT_SIZE as u64) as usize;
+        let count = buf.len().min(plain.len().saturating_sub(start));
+        buf[..count].copy_from_slice(&plain[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl<R: Read + Seek> Seek for DecryptReader<R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn key() -> StorageKey {
+        StorageKey::parse(&StorageKey::generate()).unwrap()
+    }
+
+    fn sample(length: usize) -> Vec<u8> {
+        (0..length).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn encrypt(key: &StorageKey, data: &[u8]) -> Vec<u8> {
+        let mut writer = EncryptWriter::new(key, Vec::new()).unwrap();
+        for piece in data.chunks(10_000) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trips_across_segment_boundaries() {
+        let key = key();
+        for length in [0, 1, SEGMENT_SIZE - 1, SEGMENT_SIZE, SEGMENT_SIZE + 1, 3 * SEGMENT_SIZE + 17] {
+            let data = sample(length);
+            let encrypted = encrypt(&key, &data);
+            assert!(is_encrypted(&encrypted));
+            assert_eq!(plaintext_length(encrypted.len() as u64), Some(length as u64));
+
+            let mut plain = Vec::new();
+            DecryptReader::new(&key, Cursor::new(encrypted)).unwrap().read_to_end(&mut plain).unwrap();
+            assert_eq!(plain, data, "length {}", length);
+        }
+    }
+
+    #[test]
+    fn seeks_within_the_plaintext() {
+        let key = key();
+        let data = sample(2 * SEGMENT_SIZE + 100);
+        let mut reader = DecryptReader::new(&key, Cursor::new(encrypt(&key, &data))).unwrap();
+
+        let mut buffer = vec![0u8; 200];
+        reader.seek(SeekFrom::Start(SEGMENT_SIZE as u64 - 100)).unwrap();
+        reader.read_exact(&mut buffer).unwrap();
+        assert_eq!(buffer, &data[SEGMENT_SIZE - 100..SEGMENT_SIZE + 100]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-50)).unwrap(), data.len() as u64 - 50);
+        let mut tail = Vec::new();
+        reader.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, &data[data.len() - 50..]);
+    }
+
+    #[test]
+    fn rejects_the_wrong_key() {
+        let encrypted = encrypt(&key(), b"secret");
+        let error = DecryptReader::new(&key(), Cursor::new(encrypted)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn detects_tampering() {
+        let key = key();
+        let mut encrypted = encrypt(&key, &sample(1000));
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+
+        let mut plain = Vec::new();
+        let error = DecryptReader::new(&key, Cursor::new(encrypted)).unwrap().read_to_end(&mut plain).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn detects_truncation_at_a_segment_boundary() {
+        let key = key();
+        let mut encrypted = encrypt(&key, &sample(2 * SEGMENT_SIZE + 10));
+        encrypted.truncate(HEADER_LENGTH as usize + SEALED_SEGMENT_SIZE as usize);
+
+        let mut plain = Vec::new();
+        let error = DecryptReader::new(&key, Cursor::new(encrypted)).unwrap().read_to_end(&mut plain).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert!(StorageKey::parse("not base64!").is_err());
+        assert!(StorageKey::parse("c2hvcnQ=").is_err());
+        assert!(StorageKey::parse(&StorageKey::generate()).is_ok());
+    }
+}
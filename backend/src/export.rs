@@ -0,0 +1,235 @@
+use std::path::Path;
+use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use tracing::{error, info};
+use crate::models::{ExportDestination, ExportJob, FileInfo, User};
+use crate::operations::Progress;
+use crate::{database, sigv4, tenants, AppState};
+
+pub fn normalize_schedule(schedule: &str) -> Option<String> {
+    let schedule = schedule.trim();
+    let normalized = match schedule.split_whitespace().count() {
+        5 => format!("0 {}", schedule),
+        6 | 7 => schedule.to_string(),
+        _ => return None,
+    };
+
+    Schedule::from_str(&normalized).ok().map(|_| normalized)
+}
+
+pub fn next_run_after(schedule: &str, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    Schedule::from_str(schedule).ok()?.after(&after).next()
+}
+
+pub async fn run_due_jobs(state: &AppState) {
+    let jobs = match database::claim_due_export_jobs(&state.db).await {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            error!("Failed to load due export jobs: {}", e);
+            return;
+        }
+    };
+
+    for job in jobs {
+        let state = state.clone();
+        tokio::spawn(async move {
+            let _ = run_job(&state, job, None).await;
+        });
+    }
+}
+
+pub async fn run_job(state: &AppState, job: ExportJob, progress: Option<&Progress>) -> anyhow::Result<(i32, i64)> {
+    let run = match database::create_export_run(&state.db, &job.id).await {
+        Ok(run) => run,
+        Err(e) => {
+            error!("Failed to record export run for job {}: {}", job.id, e);
+            return Err(e);
+        }
+    };
+
+    let started_at = Utc::now();
+    let result = export_files(state, &job, progress).await;
+    let next_run_at = next_run_after(&job.schedule, Utc::now());
+
+    let (status, files, bytes, error) = match &result {
+        Ok((files, bytes)) => ("succeeded", *files, *bytes, None),
+        Err((files, bytes, e)) => ("failed", *files, *bytes, Some(e.to_string())),
+    };
+
+    if let Some(error) = &error {
+        error!("Export job '{}' ({}) failed: {}", job.name, job.id, error);
+    } else {
+        info!("Export job '{}' finished: {} files, {} bytes", job.name, files, bytes);
+    }
+
+    let _ = database::finish_export_run(&state.db, &run.id, status, files, bytes, error.as_deref()).await;
+    let _ = database::finish_export_job(
+        &state.db,
+        &job.id,
+        error.is_none().then_some(started_at),
+        error.as_deref(),
+        next_run_at,
+    )
+    .await;
+
+    result.map_err(|(_, _, e)| e)
+}
+
+async fn export_files(
+    state: &AppState,
+    job: &ExportJob,
+    progress: Option<&Progress>,
+) -> Result<(i32, i64), (i32, i64, anyhow::Error)> {
+    let files = database::get_files_modified_since(&state.db, job.user_id.as_ref(), job.last_success_at)
+        .await
+        .map_err(|e| (0, 0, e))?;
+    let total = files.len() as i64;
+
+    let client = reqwest::Client::new();
+    let mut exported = 0;
+    let mut bytes = 0i64;
+
+    for file in files {
+        let remote_name = remote_name_for(job, &file);
+        let file_storage = state.file_storage.clone();
+        let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+
+        async {
+            let source = tokio::task::spawn_blocking(move || file_storage.materialize(&file_path, encoding)).await??;
+            push_file(&client, &job.destination, source.path(), &remote_name).await
+        }
+        .await
+        .map_err(|e| (exported, bytes, e.context(format!("exporting {}", file.id))))?;
+
+        exported += 1;
+        bytes += file.file_size;
+
+        if let Some(progress) = progress {
+            progress
+                .update(exported as i64, Some(total))
+                .await
+                .map_err(|e| (exported, bytes, e))?;
+        }
+    }
+
+    Ok((exported, bytes))
+}
+
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect()
+}
+
+fn remote_name_for(job: &ExportJob, file: &FileInfo) -> String {
+    let name = format!("{}_{}", file.id, sanitize_name(&file.original_filename));
+    match job.user_id {
+        Some(_) => name,
+        None => format!("{}/{}", file.user_id, name),
+    }
+}
+
+async fn push_file(
+    client: &reqwest::Client,
+    destination: &ExportDestination,
+    local_path: &Path,
+    remote_name: &str,
+) -> anyhow::Result<()> {
+    match destination {
+        ExportDestination::Local { path } => {
+            let target = Path::new(path).join(remote_name);
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(local_path, &target).await?;
+        }
+        ExportDestination::Webdav { url, username, password } => {
+            let base = url.trim_end_matches('/');
+            if let Some((dir, _)) = remote_name.rsplit_once('/') {
+                let mut request = client.request(reqwest::Method::from_bytes(b"MKCOL")?, format!("{}/{}", base, dir));
+                if let Some(username) = username {
+                    request = request.basic_auth(username, password.as_deref());
+                }
+                let _ = request.send().await;
+            }
+
+            let file = tokio::fs::File::open(local_path).await?;
+            let mut request = client
+                .put(format!("{}/{}", base, sigv4::uri_encode(remote_name, false)))
+                .body(reqwest::Body::from(file));
+            if let Some(username) = username {
+                request = request.basic_auth(username, password.as_deref());
+            }
+            request.send().await?.error_for_status()?;
+        }
+        ExportDestination::S3 { endpoint, bucket, region, access_key, secret_key, prefix } => {
+            let url = reqwest::Url::parse(endpoint)?;
+            let host = match url.port() {
+                Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+                None => url.host_str().unwrap_or_default().to_string(),
+            };
+            let key = match prefix.as_deref().map(|p| p.trim_matches('/')) {
+                Some(prefix) if !prefix.is_empty() => format!("{}/{}", prefix, remote_name),
+                _ => remote_name.to_string(),
+            };
+            let canonical_uri = format!("/{}/{}", sigv4::uri_encode(bucket, true), sigv4::uri_encode(&key, false));
+
+            let params = sigv4::SigningParams {
+                access_key,
+                secret_key,
+                region,
+                service: "s3",
+            };
+            let signed = sigv4::sign(&params, "PUT", &host, &canonical_uri, &[], sigv4::UNSIGNED_PAYLOAD, Utc::now());
+
+            let file = tokio::fs::File::open(local_path).await?;
+            let length = file.metadata().await?.len();
+            client
+                .put(format!("{}{}", endpoint.trim_end_matches('/'), canonical_uri))
+                .header("authorization", signed.authorization)
+                .header("x-amz-date", signed.amz_date)
+                .header("x-amz-content-sha256", signed.content_sha256)
+                .header(reqwest::header::CONTENT_LENGTH, length)
+                .body(reqwest::Body::from(file))
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        ExportDestination::Rsync { target, ssh_options } => {
+            let ssh = match ssh_options {
+                Some(options) => format!("ssh {}", options),
+                None => "ssh".to_string(),
+            };
+            let status = tokio::process::Command::new("rsync")
+                .arg("-a")
+                .arg("--mkpath")
+                .arg("-e")
+                .arg(ssh)
+                .arg(local_path)
+                .arg(format!("{}/{}", target.trim_end_matches('/'), remote_name))
+                .status()
+                .await?;
+            if !status.success() {
+                anyhow::bail!("rsync exited with {}", status);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `user` may run, inspect or delete `job`. `owner` is the job's
+/// user, if it has one; jobs covering every user belong to instance admins.
+pub fn can_manage(job: &ExportJob, user: &User, owner: Option<&User>) -> bool {
+    if job.user_id == Some(user.id) {
+        return true;
+    }
+    if !user.is_admin {
+        return false;
+    }
+    match owner {
+        Some(owner) => user.tenant_id.is_none() || tenants::same_tenant(user, owner),
+        None => user.tenant_id.is_none(),
+    }
+}
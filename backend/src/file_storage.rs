@@ -1,16 +1,143 @@
+use std::collections::{HashMap, VecDeque};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::io::{Write, Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
 use uuid::Uuid;
-use sysinfo::Disks;
-use crate::models::{DiskInfo, StorageInfo, StorageResult, TempFilesInfo, CleanupResult};
+use sha2::{Digest, Sha256};
+use sysinfo::{DiskKind, Disks};
+use crate::models::{DiskHealth, DiskInfo, PlacementCandidate, PlacementDecision, StorageEncoding, StorageInfo, StorageResult, StorageTier, TempFilesInfo, CleanupResult};
 use crate::config::Config;
+use crate::disk_health;
+use crate::compression::{self, CompressWriter, DecompressReader};
+use crate::encryption::{self, DecryptReader, EncryptWriter, ReadSeek, StorageKey};
+use crate::preview;
+use crate::s3::{self, S3Placement, S3Reader, S3Store};
+
+pub const MIN_FREE_SPACE_BUFFER: u64 = 1024 * 1024 * 100;
+pub const MAX_PLACEMENT_DECISIONS: usize = 500;
 
 pub struct FileStorage {
-    pub storage_paths: Vec<PathBuf>,
+    storage_paths: RwLock<Vec<PathBuf>>,
+    read_only_paths: RwLock<Vec<PathBuf>>,
+    replication_factor: usize,
+    decisions: Mutex<VecDeque<PlacementDecision>>,
+    tenant_roots: RwLock<HashMap<Uuid, Vec<PathBuf>>>,
+    reservations: Mutex<HashMap<PathBuf, (PathBuf, u64)>>,
+    placement_lock: Mutex<()>,
+    storage_key: Option<StorageKey>,
+    compression_level: Option<i32>,
+    s3: Option<Arc<S3Store>>,
+    smartctl_path: Option<String>,
+    disk_temperature_warning: i64,
+    temp_file_max_age_hours: u64,
+}
+
+pub struct StoredBlob {
+    pub root: PathBuf,
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+pub enum PlainFile {
+    Stored(PathBuf),
+    Decrypted(tempfile::TempPath),
+}
+
+impl PlainFile {
+    pub fn path(&self) -> &Path {
+        match self {
+            PlainFile::Stored(path) => path,
+            PlainFile::Decrypted(path) => path,
+        }
+    }
+}
+
+pub fn stream_reader<R: Read + Send + 'static>(mut reader: R) -> tokio_util::io::ReaderStream<tokio::io::DuplexStream> {
+    let (mut writer, output) = tokio::io::duplex(64 * 1024);
+    let handle = tokio::runtime::Handle::current();
+
+    tokio::task::spawn_blocking(move || {
+        let mut buffer = vec![0u8; 64 * 1024];
+        loop {
+            let bytes_read = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(bytes_read) => bytes_read,
+                Err(e) => {
+                    warn!("Failed to read a stored file while streaming it: {}", e);
+                    break;
+                }
+            };
+            if handle.block_on(tokio::io::AsyncWriteExt::write_all(&mut writer, &buffer[..bytes_read])).is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio_util::io::ReaderStream::new(output)
+}
+
+fn read_magic<R: Read + Seek + ?Sized>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut magic = Vec::with_capacity(encryption::MAGIC.len());
+    (&mut *reader).take(encryption::MAGIC.len() as u64).read_to_end(&mut magic)?;
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(magic)
 }
 
+struct Written {
+    file_size: u64,
+    stored_size: u64,
+    encoding: StorageEncoding,
+    checksum: String,
+    head: Vec<u8>,
+}
+
+fn copy_hashed<R: Read + ?Sized, W: Write>(reader: &mut R, writer: &mut W) -> anyhow::Result<Written> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut file_size = 0u64;
+    let mut head = Vec::new();
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        if head.len() < preview::SNIFF_LENGTH {
+            let wanted = (preview::SNIFF_LENGTH - head.len()).min(bytes_read);
+            head.extend_from_slice(&buffer[..wanted]);
+        }
+        hasher.update(&buffer[..bytes_read]);
+        writer.write_all(&buffer[..bytes_read])?;
+        file_size += bytes_read as u64;
+    }
+
+    Ok(Written {
+        file_size,
+        stored_size: file_size,
+        encoding: StorageEncoding::PLAIN,
+        checksum: hex::encode(hasher.finalize()),
+        head,
+    })
+}
+
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "checksum mismatch: expected {}, got {}", self.expected, self.actual)
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
 impl FileStorage {
     pub fn new(config: &Config) -> anyhow::Result<Self> {
         let mut storage_paths = Vec::new();
@@ -25,7 +152,38 @@ impl FileStorage {
             storage_paths.push(normalized_path);
         }
         
-        Ok(FileStorage { storage_paths })
+        if config.replication_factor > storage_paths.len() {
+            anyhow::bail!(
+                "REPLICATION_FACTOR is {} but only {} storage paths are configured",
+                config.replication_factor,
+                storage_paths.len()
+            );
+        }
+        
+        let mut read_only_paths = Vec::new();
+        for path_str in &config.read_only_storage_paths {
+            let normalized_path = Self::normalize_path(&PathBuf::from(path_str))?;
+            if !storage_paths.contains(&normalized_path) {
+                anyhow::bail!("READ_ONLY_STORAGE_PATHS entry {} is not one of STORAGE_PATHS", path_str);
+            }
+            read_only_paths.push(normalized_path);
+        }
+        
+        Ok(FileStorage {
+            storage_paths: RwLock::new(storage_paths),
+            read_only_paths: RwLock::new(read_only_paths),
+            replication_factor: config.replication_factor,
+            decisions: Mutex::new(VecDeque::new()),
+            tenant_roots: RwLock::new(HashMap::new()),
+            reservations: Mutex::new(HashMap::new()),
+            placement_lock: Mutex::new(()),
+            storage_key: config.storage_encryption_key.clone(),
+            compression_level: config.compression_enabled.then_some(config.compression_level),
+            s3: config.s3.clone().map(S3Store::new).transpose()?.map(Arc::new),
+            smartctl_path: config.smart_enabled.then(|| config.smartctl_path.clone()),
+            disk_temperature_warning: config.disk_temperature_warning,
+            temp_file_max_age_hours: config.upload_session_ttl_hours.max(24) as u64,
+        })
     }
     
     fn normalize_path(path: &Path) -> anyhow::Result<PathBuf> {
@@ -100,10 +258,19 @@ impl FileStorage {
         }
     }
     
+    pub async fn blocking<T, F>(self: &Arc<Self>, operation: F) -> anyhow::Result<T>
+    where
+        F: FnOnce(&FileStorage) -> anyhow::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let storage = Arc::clone(self);
+        tokio::task::spawn_blocking(move || operation(&storage)).await?
+    }
+    
     pub fn get_disk_info(&self) -> anyhow::Result<Vec<DiskInfo>> {
         let mut disk_infos = Vec::new();
         
-        for (index, path) in self.storage_paths.iter().enumerate() {
+        for (index, path) in self.storage_paths().iter().enumerate() {
             let disk_info = self.get_single_disk_info(path, index)?;
             disk_infos.push(disk_info);
         }
@@ -111,12 +278,14 @@ impl FileStorage {
         Ok(disk_infos)
     }
     
-    fn get_single_disk_info(&self, path: &Path, _index: usize) -> anyhow::Result<DiskInfo> {
+    pub fn get_single_disk_info(&self, path: &Path, _index: usize) -> anyhow::Result<DiskInfo> {
         let normalized_path = Self::normalize_path(path)?;
         
         let metadata = fs::metadata(&normalized_path).or_else(|_| fs::metadata(path))?;
         
         let (total_space, available_space) = self.get_disk_space_sysinfo(&normalized_path)?;
+        #[cfg(feature = "chaos")]
+        let available_space = if crate::chaos::disk_full() { 0 } else { available_space };
         
         let used_space = total_space.saturating_sub(available_space);
         let usage_percentage = if total_space > 0 {
@@ -141,8 +310,11 @@ impl FileStorage {
             total_space,
             used_space,
             available_space,
+            reserved_space: self.reserved_space(&normalized_path),
             usage_percentage,
             is_accessible: normalized_path.exists() && metadata.is_dir(),
+            is_read_only: self.is_read_only(&normalized_path),
+            health: None,
         })
     }
     
@@ -165,11 +337,24 @@ impl FileStorage {
             let total_space = disk.total_space();
             let available_space = disk.available_space();
             Ok((total_space, available_space))
+        } else if cfg!(target_os = "windows") {
+            self.get_windows_disk_space(path)
         } else {
-            Err(anyhow::anyhow!("Could not find disk information for path: {}", path_str))
+            self.get_unix_disk_space(path)
         }
     }
     
+    pub fn is_rotational(&self, path: &Path) -> bool {
+        let disks = Disks::new_with_refreshed_list();
+        let path_str = path.to_string_lossy();
+        
+        disks
+            .iter()
+            .filter(|disk| path_str.starts_with(&*disk.mount_point().to_string_lossy()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .is_some_and(|disk| disk.kind() == DiskKind::HDD)
+    }
+    
     #[cfg(target_os = "windows")]
     fn get_windows_disk_space(&self, path: &Path) -> anyhow::Result<(u64, u64)> {
         use std::ffi::OsStr;
@@ -237,6 +422,11 @@ impl FileStorage {
         Ok((0, 0))
     }
     
+    pub fn get_disk_health(&self, path: &Path) -> Option<DiskHealth> {
+        let smartctl_path = self.smartctl_path.as_deref()?;
+        Some(disk_health::inspect(smartctl_path, self.disk_temperature_warning, path))
+    }
+    
     pub fn get_storage_info(&self) -> anyhow::Result<StorageInfo> {
         let disk_infos = self.get_disk_info()?;
         
@@ -257,9 +447,23 @@ impl FileStorage {
             usage_percentage,
             disk_count: disk_infos.len(),
             disks: disk_infos,
+            warnings: Vec::new(),
         })
     }
     
+    pub fn get_storage_info_with_health(&self) -> anyhow::Result<StorageInfo> {
+        let mut storage_info = self.get_storage_info()?;
+        for disk in &mut storage_info.disks {
+            disk.health = self.get_disk_health(Path::new(&disk.path));
+            if let Some(health) = &disk.health {
+                storage_info
+                    .warnings
+                    .extend(health.warnings.iter().map(|warning| format!("{}: {}", disk.path, warning)));
+            }
+        }
+        Ok(storage_info)
+    }
+    
     pub fn get_disk_usage_report(&self) -> anyhow::Result<String> {
         let disk_infos = self.get_disk_info()?;
         let mut report = String::new();
@@ -287,30 +491,532 @@ impl FileStorage {
         Ok(report)
     }
     
-    pub fn find_available_disk(&self, file_size: u64) -> anyhow::Result<Option<PathBuf>> {
-        let mut best_disk: Option<(PathBuf, u64)> = None;
-        let min_free_space_buffer = 1024 * 1024 * 100;
-        
-        for path in &self.storage_paths {
-            let disk_info = self.get_single_disk_info(path, 0)?;
-            
-            if disk_info.is_accessible && 
-               disk_info.available_space > file_size + min_free_space_buffer {
-                
-                match &best_disk {
-                    None => {
-                        best_disk = Some((path.clone(), disk_info.available_space));
+    pub fn storage_paths(&self) -> Vec<PathBuf> {
+        self.storage_paths.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn add_storage_path(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let normalized_path = Self::normalize_path(&PathBuf::from(path))?;
+        if !normalized_path.is_dir() {
+            anyhow::bail!("{} is not an existing directory", path);
+        }
+        let probe = normalized_path.join(format!(".probe-{}", Uuid::new_v4()));
+        fs::write(&probe, b"probe")
+            .and_then(|_| fs::remove_file(&probe))
+            .map_err(|e| anyhow::anyhow!("{} is not writable: {}", path, e))?;
+
+        let _placement = self.placement_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut storage_paths = self.storage_paths.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(existing) = storage_paths
+            .iter()
+            .find(|existing| normalized_path.starts_with(existing) || existing.starts_with(&normalized_path))
+        {
+            anyhow::bail!("{} overlaps the storage path {}", path, existing.display());
+        }
+        storage_paths.push(normalized_path.clone());
+        Ok(normalized_path)
+    }
+
+    pub fn remove_storage_path(&self, disk_path: &Path) -> anyhow::Result<()> {
+        let _placement = self.placement_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut storage_paths = self.storage_paths.write().unwrap_or_else(|e| e.into_inner());
+        if !storage_paths.iter().any(|path| path == disk_path) {
+            anyhow::bail!("{} is not a storage path", disk_path.display());
+        }
+        if storage_paths.len() <= self.replication_factor.max(1) {
+            anyhow::bail!("at least {} storage paths are needed", self.replication_factor.max(1));
+        }
+        storage_paths.retain(|path| path != disk_path);
+        self.read_only_paths
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|path| path != disk_path);
+        Ok(())
+    }
+
+    pub fn storage_root(&self, path: &str) -> Option<PathBuf> {
+        let normalized_path = Self::normalize_path(&PathBuf::from(path)).ok()?;
+        self.storage_paths().into_iter().find(|root| *root == normalized_path)
+    }
+    
+    pub fn set_tenant_roots(&self, roots: HashMap<Uuid, Vec<PathBuf>>) {
+        *self.tenant_roots.write().unwrap_or_else(|e| e.into_inner()) = roots;
+    }
+    
+    pub fn reserve_space(&self, file_path: &Path, disk_path: &Path, size: u64) {
+        let disk_path = Self::normalize_path(disk_path).unwrap_or_else(|_| disk_path.to_path_buf());
+        self.reservations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(file_path.to_path_buf(), (disk_path, size));
+    }
+
+    pub fn release_space(&self, file_path: &Path) {
+        self.reservations.lock().unwrap_or_else(|e| e.into_inner()).remove(file_path);
+    }
+
+    pub fn reserved_space(&self, disk_path: &Path) -> u64 {
+        self.reservations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|(reserved_on, _)| reserved_on == disk_path)
+            .map(|(_, size)| size)
+            .sum()
+    }
+
+    fn place(&self, user_id: &Uuid, file_size: u64, file_path: impl FnOnce(&Path) -> anyhow::Result<PathBuf>) -> anyhow::Result<(PathBuf, PathBuf)> {
+        let _placement = self.placement_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let disk_path = match self.find_available_disk(user_id, file_size)? {
+            Some(path) => path,
+            None => {
+                return Err(anyhow::anyhow!("No available disk space for file"));
+            }
+        };
+
+        let file_path = file_path(&disk_path)?;
+        self.reserve_space(&file_path, &disk_path, file_size);
+        Ok((disk_path, file_path))
+    }
+
+    pub fn is_read_only(&self, disk_path: &Path) -> bool {
+        self.read_only_paths
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .any(|path| path == disk_path)
+    }
+
+    pub fn mark_read_only(&self, disk_path: &Path) {
+        let mut read_only_paths = self.read_only_paths.write().unwrap_or_else(|e| e.into_inner());
+        if !read_only_paths.iter().any(|path| path == disk_path) {
+            read_only_paths.push(disk_path.to_path_buf());
+        }
+    }
+
+    pub fn tenant_allows(&self, user_id: &Uuid, disk_path: &Path) -> bool {
+        self.tenant_roots
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(user_id)
+            .is_none_or(|allowed| allowed.iter().any(|path| path == disk_path))
+    }
+
+    pub fn copy_to_disk(&self, file_path: &str, user_id: &Uuid, filename: &str, disk_path: &Path) -> anyhow::Result<PathBuf> {
+        let source = Self::normalize_path(Path::new(file_path))?;
+        let size = fs::metadata(&source)?.len();
+        let target = {
+            let _placement = self.placement_lock.lock().unwrap_or_else(|e| e.into_inner());
+            if self.is_read_only(disk_path) || !self.tenant_allows(user_id, disk_path) {
+                anyhow::bail!("{} cannot take files of user {}", disk_path.display(), user_id);
+            }
+            let disk_info = self.get_single_disk_info(disk_path, 0)?;
+            if disk_info.available_space.saturating_sub(disk_info.reserved_space) <= size.saturating_add(MIN_FREE_SPACE_BUFFER) {
+                anyhow::bail!("{} has no room for {} bytes", disk_path.display(), size);
+            }
+            let target = Self::user_file_path(disk_path, user_id, filename)?;
+            if target == source {
+                anyhow::bail!("{} is already on {}", file_path, disk_path.display());
+            }
+            if target.exists() {
+                let source_poster = crate::video::poster_path(file_path);
+                if source_poster.exists() {
+                    fs::copy(&source_poster, crate::video::poster_path(&target.to_string_lossy()))?;
+                }
+                return Ok(target);
+            }
+            self.reserve_space(&target, disk_path, size);
+            target
+        };
+
+        let source_poster = crate::video::poster_path(file_path);
+        let target_poster = crate::video::poster_path(&target.to_string_lossy());
+        let copied = (|| -> anyhow::Result<()> {
+            fs::copy(&source, &target)?;
+            fs::File::open(&target)?.sync_all()?;
+            if source_poster.exists() {
+                fs::copy(&source_poster, &target_poster)?;
+            }
+            Ok(())
+        })();
+        self.release_space(&target);
+        if let Err(e) = copied {
+            let _ = fs::remove_file(&target);
+            let _ = fs::remove_file(&target_poster);
+            return Err(e);
+        }
+
+        Ok(target)
+    }
+
+    pub fn relative_blob_path(&self, file_path: &str) -> Option<PathBuf> {
+        let normalized_path = Self::normalize_path(Path::new(file_path)).ok()?;
+        self.storage_paths()
+            .iter()
+            .find_map(|root| normalized_path.strip_prefix(root).ok().map(Path::to_path_buf))
+    }
+
+    pub fn list_blobs(&self) -> anyhow::Result<Vec<StoredBlob>> {
+        let mut blobs = Vec::new();
+        for root in self.storage_paths() {
+            let users_dir = root.join("users");
+            if !users_dir.is_dir() {
+                continue;
+            }
+            for user_dir in fs::read_dir(&users_dir)? {
+                let user_dir = user_dir?.path();
+                if !user_dir.is_dir() {
+                    continue;
+                }
+                for entry in fs::read_dir(&user_dir)? {
+                    let entry = entry?;
+                    let metadata = entry.metadata()?;
+                    if metadata.is_file() {
+                        blobs.push(StoredBlob {
+                            root: root.clone(),
+                            path: entry.path(),
+                            size: metadata.len(),
+                            modified: metadata.modified()?,
+                        });
                     }
-                    Some((_, current_best_space)) => {
-                        if disk_info.available_space > *current_best_space {
-                            best_disk = Some((path.clone(), disk_info.available_space));
+                }
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    pub fn set_aside(&self, root: &Path, blob: &Path) -> anyhow::Result<PathBuf> {
+        let relative = blob.strip_prefix(root)?;
+        let target = root.join("lost+found").join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(blob, &target)?;
+        Ok(target)
+    }
+
+    fn replica_paths(&self, file_path: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let storage_paths = self.storage_paths();
+        let root = match storage_paths.iter().find(|root| file_path.starts_with(root)) {
+            Some(root) => root,
+            None => return Vec::new(),
+        };
+        let relative = match file_path.strip_prefix(root) {
+            Ok(relative) => relative,
+            Err(_) => return Vec::new(),
+        };
+        storage_paths
+            .iter()
+            .filter(|other| *other != root)
+            .map(|other| (other.clone(), other.join(relative)))
+            .collect()
+    }
+
+    fn resolve(&self, file_path: &Path) -> anyhow::Result<PathBuf> {
+        let normalized_path = Self::normalize_path(file_path)?;
+        if normalized_path.exists() {
+            return Ok(normalized_path);
+        }
+        let replica = self
+            .replica_paths(&normalized_path)
+            .into_iter()
+            .map(|(_, replica)| replica)
+            .find(|replica| replica.exists());
+        if let Some(replica) = &replica {
+            warn!("{} is missing, reading its replica {}", normalized_path.display(), replica.display());
+        }
+        Ok(replica.unwrap_or(normalized_path))
+    }
+
+    pub fn replicate(&self, file_path: &str, user_id: &Uuid) -> anyhow::Result<usize> {
+        if self.replication_factor <= 1 || s3::is_s3_path(file_path) {
+            return Ok(0);
+        }
+        let primary = Self::normalize_path(Path::new(file_path))?;
+        let source = self.resolve(&primary)?;
+        let size = fs::metadata(&source)?.len();
+        let replicas = self.replica_paths(&primary);
+
+        let targets = {
+            let _placement = self.placement_lock.lock().unwrap_or_else(|e| e.into_inner());
+            let mut targets = Vec::new();
+            if source != primary {
+                targets.push(primary.clone());
+            }
+            let existing = 1 + replicas.iter().filter(|(_, replica)| replica.exists()).count();
+            let mut candidates = Vec::new();
+            for (root, replica) in &replicas {
+                if replica.exists() || self.is_read_only(root) || !self.tenant_allows(user_id, root) {
+                    continue;
+                }
+                match self.get_single_disk_info(root, 0) {
+                    Ok(disk_info) if disk_info.is_accessible => {
+                        let available_space = disk_info.available_space.saturating_sub(disk_info.reserved_space);
+                        if available_space > size.saturating_add(MIN_FREE_SPACE_BUFFER) {
+                            candidates.push((available_space, replica.clone()));
                         }
                     }
+                    _ => {}
+                }
+            }
+            candidates.sort_by_key(|(available_space, _)| std::cmp::Reverse(*available_space));
+            targets.extend(
+                candidates
+                    .into_iter()
+                    .take(self.replication_factor.saturating_sub(existing))
+                    .map(|(_, replica)| replica),
+            );
+            for target in &targets {
+                if let Some((root, _)) = replicas.iter().find(|(_, replica)| replica == target) {
+                    self.reserve_space(target, root, size);
+                }
+            }
+            targets
+        };
+
+        let mut copied = 0;
+        for target in &targets {
+            let result = (|| -> anyhow::Result<()> {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&source, target)?;
+                fs::File::open(target)?.sync_all()?;
+                Ok(())
+            })();
+            self.release_space(target);
+            match result {
+                Ok(()) => copied += 1,
+                Err(e) => {
+                    let _ = fs::remove_file(target);
+                    warn!("Failed to write a replica of {} to {}: {:#}", file_path, target.display(), e);
+                }
+            }
+        }
+        let copies = usize::from(primary.exists()) + replicas.iter().filter(|(_, replica)| replica.exists()).count();
+        if copies < self.replication_factor {
+            warn!("{} has fewer than {} copies; add disk space to restore its replicas", file_path, self.replication_factor);
+        }
+
+        Ok(copied)
+    }
+
+    fn replicate_written(&self, file_path: &Path, user_id: &Uuid) {
+        if let Err(e) = self.replicate(&file_path.to_string_lossy(), user_id) {
+            warn!("Failed to replicate {}: {:#}", file_path.display(), e);
+        }
+    }
+
+    pub fn remove_copy(&self, file_path: &str) -> anyhow::Result<()> {
+        if s3::is_s3_path(file_path) {
+            return self.delete_file(file_path);
+        }
+
+        let normalized_path = Self::normalize_path(Path::new(file_path))?;
+        if normalized_path.exists() {
+            fs::remove_file(&normalized_path)?;
+        }
+        let _ = fs::remove_file(crate::thumbnail::cache_path(file_path));
+        let _ = fs::remove_file(crate::video::poster_path(file_path));
+
+        Ok(())
+    }
+
+    fn s3_blob(s3: &S3Store, user_id: &Uuid, filename: &str) -> (PathBuf, PathBuf) {
+        let file_path = s3.path_for(&format!("users/{}/{}", user_id, filename));
+        (PathBuf::from(s3.root()), PathBuf::from(file_path))
+    }
+
+    fn place_blob(&self, user_id: &Uuid, file_size: u64, filename: &str) -> anyhow::Result<(PathBuf, PathBuf)> {
+        if let Some(s3) = self.s3.as_deref().filter(|s3| s3.placement() == S3Placement::Primary) {
+            return Ok(Self::s3_blob(s3, user_id, filename));
+        }
+
+        let placed = self.place(user_id, file_size, |disk_path| Self::user_file_path(disk_path, user_id, filename));
+        match (placed, self.s3.as_deref()) {
+            (Err(e), Some(s3)) => {
+                debug!(size = file_size, "No local disk can take the file ({}), storing it in S3", e);
+                Ok(Self::s3_blob(s3, user_id, filename))
+            }
+            (placed, _) => placed,
+        }
+    }
+
+    fn user_file_path(disk_path: &Path, user_id: &Uuid, filename: &str) -> anyhow::Result<PathBuf> {
+        let user_dir = disk_path.join("users").join(user_id.to_string());
+        let normalized_user_dir = Self::normalize_path(&user_dir)?;
+        fs::create_dir_all(&normalized_user_dir)?;
+        Ok(normalized_user_dir.join(filename))
+    }
+
+    pub fn copy_to_tier(&self, file_path: &str, user_id: &Uuid, filename: &str, tier: StorageTier) -> anyhow::Result<(PathBuf, PathBuf)> {
+        if StorageTier::of_path(file_path) == tier {
+            anyhow::bail!("{} is already in the {} tier", file_path, tier.as_str());
+        }
+        let s3 = self
+            .s3
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("the cold tier needs S3_BUCKET to be set"))?;
+
+        match tier {
+            StorageTier::Cold => {
+                let (disk_path, target) = Self::s3_blob(s3, user_id, filename);
+                s3.upload(&target.to_string_lossy(), &Self::normalize_path(Path::new(file_path))?)?;
+                Ok((disk_path, target))
+            }
+            StorageTier::Hot => {
+                let size = s3
+                    .size(file_path)?
+                    .ok_or_else(|| anyhow::anyhow!("{} does not exist", file_path))?;
+                let (disk_path, target) = self.place(user_id, size, |disk_path| Self::user_file_path(disk_path, user_id, filename))?;
+                let copied = fs::File::create(&target)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|mut file| {
+                        std::io::copy(&mut S3Reader::open(s3.clone(), file_path)?, &mut file)?;
+                        file.sync_all()?;
+                        Ok(())
+                    });
+                self.release_space(&target);
+                if let Err(e) = copied {
+                    let _ = fs::remove_file(&target);
+                    return Err(e);
+                }
+                self.replicate_written(&target, user_id);
+                Ok((disk_path, target))
+            }
+        }
+    }
+
+    fn s3_store(&self, file_path: &str) -> anyhow::Result<Option<&Arc<S3Store>>> {
+        if !s3::is_s3_path(file_path) {
+            return Ok(None);
+        }
+        self.s3
+            .as_ref()
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("{} is stored in S3 but S3_BUCKET is not set", file_path))
+    }
+
+    fn staging_dir(&self, file_path: &str) -> anyhow::Result<PathBuf> {
+        let storage_paths = self.storage_paths();
+        let temp_dir = storage_paths
+            .iter()
+            .find(|root| Path::new(file_path).starts_with(root))
+            .or_else(|| storage_paths.first())
+            .map(|root| root.join("temp"))
+            .unwrap_or_else(std::env::temp_dir);
+        fs::create_dir_all(&temp_dir)?;
+        Ok(temp_dir)
+    }
+
+    pub fn find_available_disk(&self, user_id: &Uuid, file_size: u64) -> anyhow::Result<Option<PathBuf>> {
+        let required_space = file_size.saturating_add(MIN_FREE_SPACE_BUFFER);
+        let storage_paths = self.storage_paths();
+        let mut candidates = Vec::with_capacity(storage_paths.len());
+        let mut best_disk: Option<(usize, u64)> = None;
+        let allowed = self.tenant_roots.read().unwrap_or_else(|e| e.into_inner()).get(user_id).cloned();
+        
+        for path in &storage_paths {
+            let mut candidate = PlacementCandidate {
+                path: path.to_string_lossy().to_string(),
+                accessible: false,
+                available_space: None,
+                reserved_space: 0,
+                required_space,
+                chosen: false,
+                reason: String::new(),
+            };
+            
+            if allowed.as_ref().is_some_and(|allowed| !allowed.contains(path)) {
+                candidate.reason = "rejected: not a storage root of the user's tenant".to_string();
+                candidates.push(candidate);
+                continue;
+            }
+            
+            if self.is_read_only(path) {
+                candidate.reason = "rejected: read-only, files are only moved off it".to_string();
+                candidates.push(candidate);
+                continue;
+            }
+            
+            match self.get_single_disk_info(path, 0) {
+                Err(e) => candidate.reason = format!("rejected: could not read disk usage: {}", e),
+                Ok(disk_info) if !disk_info.is_accessible => {
+                    candidate.available_space = Some(disk_info.available_space);
+                    candidate.reason = "rejected: not an accessible directory".to_string();
+                }
+                Ok(disk_info) => {
+                    let available_space = disk_info.available_space.saturating_sub(disk_info.reserved_space);
+                    candidate.accessible = true;
+                    candidate.available_space = Some(available_space);
+                    candidate.reserved_space = disk_info.reserved_space;
+                    
+                    if available_space <= required_space {
+                        candidate.reason = format!(
+                            "rejected: {} bytes available after {} bytes reserved by uploads in progress, needs more than {} ({} bytes plus the {} byte free space buffer)",
+                            available_space, disk_info.reserved_space, required_space, file_size, MIN_FREE_SPACE_BUFFER
+                        );
+                    } else if best_disk.is_none_or(|(_, best_space)| available_space > best_space) {
+                        best_disk = Some((candidates.len(), available_space));
+                    }
+                }
+            }
+            
+            candidates.push(candidate);
+        }
+        
+        if let Some((index, best_space)) = best_disk {
+            let chosen_path = candidates[index].path.clone();
+            for (i, candidate) in candidates.iter_mut().enumerate() {
+                if i == index {
+                    candidate.chosen = true;
+                    candidate.reason = "chosen: most available space among disks with room".to_string();
+                } else if candidate.reason.is_empty() {
+                    candidate.reason = format!(
+                        "eligible: {} bytes available, less than {} on {}",
+                        candidate.available_space.unwrap_or(0), best_space, chosen_path
+                    );
                 }
             }
         }
         
-        Ok(best_disk.map(|(path, _)| path))
+        self.record_decision(file_size, candidates);
+        
+        Ok(best_disk.map(|(index, _)| storage_paths[index].clone()))
+    }
+    
+    fn record_decision(&self, file_size: u64, candidates: Vec<PlacementCandidate>) {
+        let chosen = candidates.iter().find(|candidate| candidate.chosen).map(|candidate| candidate.path.clone());
+        match &chosen {
+            Some(path) => debug!(size = file_size, disk = %path, candidates = candidates.len(), "Placed file on disk"),
+            None => warn!(size = file_size, candidates = candidates.len(), "No disk has room for file"),
+        }
+        for candidate in &candidates {
+            debug!(size = file_size, disk = %candidate.path, chosen = candidate.chosen, reason = %candidate.reason, "Placement candidate");
+        }
+        
+        let mut decisions = self.decisions.lock().unwrap_or_else(|e| e.into_inner());
+        if decisions.len() >= MAX_PLACEMENT_DECISIONS {
+            decisions.pop_front();
+        }
+        decisions.push_back(PlacementDecision {
+            decided_at: chrono::Utc::now(),
+            file_size,
+            chosen,
+            candidates,
+        });
+    }
+    
+    pub fn placement_decisions(&self, limit: usize, failed_only: bool) -> Vec<PlacementDecision> {
+        let decisions = self.decisions.lock().unwrap_or_else(|e| e.into_inner());
+        decisions
+            .iter()
+            .rev()
+            .filter(|decision| !failed_only || decision.chosen.is_none())
+            .take(limit)
+            .cloned()
+            .collect()
     }
     
     pub fn store_file(
@@ -321,13 +1027,6 @@ impl FileStorage {
     ) -> anyhow::Result<StorageResult> {
         let file_size = file_data.len() as u64;
         
-        let disk_path = match self.find_available_disk(file_size)? {
-            Some(path) => path,
-            None => {
-                return Err(anyhow::anyhow!("No available disk space for file"));
-            }
-        };
-        
         let file_id = Uuid::new_v4();
         let file_extension = Path::new(original_filename)
             .extension()
@@ -340,51 +1039,283 @@ impl FileStorage {
             format!("{}.{}", file_id, file_extension)
         };
         
-        let user_dir = disk_path.join("users").join(user_id.to_string());
-        let normalized_user_dir = Self::normalize_path(&user_dir)?;
-        fs::create_dir_all(&normalized_user_dir)?;
-
-        let file_path = normalized_user_dir.join(&filename);
-        
-        let mut file = fs::File::create(&file_path)?;
-        file.write_all(file_data)?;
-        file.sync_all()?;
+        let (disk_path, file_path) = self.place_blob(user_id, file_size, &filename)?;
+        let written = self.write_blob(&file_path, &mut &file_data[..], original_filename);
+        self.release_space(&file_path);
+        let written = written?;
+        self.replicate_written(&file_path, user_id);
         
         Ok(StorageResult {
             file_id,
             filename,
             file_path: file_path.to_string_lossy().to_string(),
             disk_path: disk_path.to_string_lossy().to_string(),
-            file_size: file_size as i64,
+            file_size: written.file_size as i64,
+            stored_size: written.stored_size as i64,
+            encoding: written.encoding,
+            checksum: written.checksum,
+            mime_type: preview::sniff_mime_type(&written.head, original_filename),
         })
     }
     
-    pub fn get_file_data(&self, file_path: &str) -> anyhow::Result<Vec<u8>> {
-        let path = PathBuf::from(file_path);
-        let normalized_path = Self::normalize_path(&path)?;
-        
-        if !normalized_path.exists() {
+    pub fn store_reader<R: Read + ?Sized>(
+        &self,
+        reader: &mut R,
+        size_hint: u64,
+        user_id: &Uuid,
+        original_filename: &str,
+    ) -> anyhow::Result<StorageResult> {
+        let file_id = Uuid::new_v4();
+        let file_extension = Path::new(original_filename)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+
+        let filename = if file_extension.is_empty() {
+            file_id.to_string()
+        } else {
+            format!("{}.{}", file_id, file_extension)
+        };
+
+        let (disk_path, file_path) = self.place_blob(user_id, size_hint, &filename)?;
+        let written = self.write_blob(&file_path, reader, original_filename);
+        self.release_space(&file_path);
+        let written = written?;
+        self.replicate_written(&file_path, user_id);
+
+        Ok(StorageResult {
+            file_id,
+            filename,
+            file_path: file_path.to_string_lossy().to_string(),
+            disk_path: disk_path.to_string_lossy().to_string(),
+            file_size: written.file_size as i64,
+            stored_size: written.stored_size as i64,
+            encoding: written.encoding,
+            checksum: written.checksum,
+            mime_type: preview::sniff_mime_type(&written.head, original_filename),
+        })
+    }
+
+    fn should_compress(&self, head: &[u8], original_filename: &str) -> bool {
+        self.compression_level.is_some()
+            && !head.is_empty()
+            && preview::sniff_mime_type(head, original_filename).is_some_and(|mime_type| compression::is_compressible(&mime_type))
+    }
+
+    fn copy_compressed<R: Read + ?Sized, W: Write>(&self, reader: &mut R, mut writer: W, compress: bool) -> anyhow::Result<(Written, W)> {
+        match self.compression_level.filter(|_| compress) {
+            Some(level) => {
+                let mut writer = CompressWriter::new(level, writer)?;
+                let written = copy_hashed(reader, &mut writer)?;
+                Ok((written, writer.finish()?))
+            }
+            None => {
+                let written = copy_hashed(reader, &mut writer)?;
+                Ok((written, writer))
+            }
+        }
+    }
+
+    fn write_blob<R: Read + ?Sized>(&self, file_path: &Path, reader: &mut R, original_filename: &str) -> anyhow::Result<Written> {
+        let path = file_path.to_string_lossy();
+        match self.s3_store(&path)? {
+            Some(s3) => {
+                let staging = tempfile::NamedTempFile::new_in(self.staging_dir(&path)?)?.into_temp_path();
+                let written = self.write_local_blob(&staging, reader, original_filename)?;
+                s3.upload(&path, &staging)?;
+                Ok(written)
+            }
+            None => self.write_local_blob(file_path, reader, original_filename),
+        }
+    }
+
+    fn write_local_blob<R: Read + ?Sized>(&self, file_path: &Path, reader: &mut R, original_filename: &str) -> anyhow::Result<Written> {
+        let mut file = fs::File::create(file_path)?;
+
+        let result = (|| {
+            let mut head = Vec::with_capacity(preview::SNIFF_LENGTH);
+            (&mut *reader).take(preview::SNIFF_LENGTH as u64).read_to_end(&mut head)?;
+            let compress = self.should_compress(&head, original_filename);
+            let mut reader = std::io::Cursor::new(head).chain(reader);
+
+            let written = match &self.storage_key {
+                Some(key) => {
+                    let writer = EncryptWriter::new(key, &mut file)?;
+                    let (written, writer) = self.copy_compressed(&mut reader, writer, compress)?;
+                    writer.finish()?;
+                    written
+                }
+                None => self.copy_compressed(&mut reader, &mut file, compress)?.0,
+            };
+            file.sync_all()?;
+            Ok(Written {
+                stored_size: file.metadata()?.len(),
+                encoding: StorageEncoding { compressed: compress, encrypted: self.storage_key.is_some() },
+                ..written
+            })
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(file_path);
+        }
+        result
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.storage_key.is_some()
+    }
+
+    /// Opens a stored file for reading its original content. `encoding` is the
+    /// one recorded for the file; only files from before encodings were
+    /// recorded pass `None` and are recognised by their header instead.
+    pub fn open_file(&self, file_path: &Path, encoding: Option<StorageEncoding>) -> anyhow::Result<Box<dyn ReadSeek>> {
+        let path = file_path.to_string_lossy();
+        let mut reader: Box<dyn ReadSeek> = match self.s3_store(&path)? {
+            Some(s3) => Box::new(S3Reader::open(s3.clone(), &path)?),
+            None => Box::new(fs::File::open(self.resolve(file_path)?)?),
+        };
+
+        let encrypted = match encoding {
+            Some(encoding) => encoding.encrypted,
+            None => encryption::is_encrypted(&read_magic(&mut reader)?),
+        };
+        if encrypted {
+            let key = self.storage_key.as_ref().ok_or_else(|| {
+                anyhow::anyhow!("{} is encrypted but STORAGE_ENCRYPTION_KEY is not set", file_path.display())
+            })?;
+            reader = Box::new(DecryptReader::new(key, reader)?);
+        }
+
+        let compressed = match encoding {
+            Some(encoding) => encoding.compressed,
+            None => compression::is_compressed(&read_magic(&mut reader)?),
+        };
+        if compressed {
+            reader = Box::new(DecompressReader::new(reader)?);
+        }
+        Ok(reader)
+    }
+
+    pub fn materialize(&self, file_path: &str, encoding: Option<StorageEncoding>) -> anyhow::Result<PlainFile> {
+        if self.s3_store(file_path)?.is_none() {
+            let path = self.resolve(Path::new(file_path))?;
+            let plain = match encoding {
+                Some(encoding) => encoding == StorageEncoding::PLAIN,
+                None => {
+                    let magic = read_magic(&mut fs::File::open(&path)?)?;
+                    !encryption::is_encrypted(&magic) && !compression::is_compressed(&magic)
+                }
+            };
+            if plain {
+                return Ok(PlainFile::Stored(path));
+            }
+        }
+
+        let mut reader = self.open_file(Path::new(file_path), encoding)?;
+        let mut temp = tempfile::NamedTempFile::new_in(self.staging_dir(file_path)?)?;
+        std::io::copy(&mut reader, &mut temp)?;
+        temp.as_file().sync_all()?;
+        Ok(PlainFile::Decrypted(temp.into_temp_path()))
+    }
+
+    pub fn copy_file(
+        &self,
+        source_path: &str,
+        encoding: Option<StorageEncoding>,
+        user_id: &Uuid,
+        original_filename: &str,
+    ) -> anyhow::Result<StorageResult> {
+        let size = self
+            .get_file_size(source_path, encoding)?
+            .ok_or_else(|| anyhow::anyhow!("File not found: {}", source_path))?;
+        let mut reader = self.open_file(Path::new(source_path), encoding)?;
+
+        self.store_reader(&mut reader, size, user_id, original_filename)
+    }
+
+    pub fn get_file_data(&self, file_path: &str, encoding: Option<StorageEncoding>) -> anyhow::Result<Vec<u8>> {
+        if !self.file_exists(file_path) {
             return Err(anyhow::anyhow!("File not found: {}", file_path));
         }
         
-        let data = fs::read(&normalized_path)?;
+        let mut data = Vec::new();
+        self.open_file(Path::new(file_path), encoding)?.read_to_end(&mut data)?;
         Ok(data)
     }
     
+    pub fn read_file_range(&self, file_path: &str, encoding: Option<StorageEncoding>, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+        let mut reader = std::io::BufReader::new(self.open_file(Path::new(file_path), encoding)?);
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut data = Vec::with_capacity(length as usize);
+        reader.take(length).read_to_end(&mut data)?;
+        Ok(data)
+    }
+
     pub fn delete_file(&self, file_path: &str) -> anyhow::Result<()> {
+        if let Some(s3) = self.s3_store(file_path)? {
+            return s3.delete(file_path);
+        }
+
         let path = PathBuf::from(file_path);
         let normalized_path = Self::normalize_path(&path)?;
         
         if normalized_path.exists() {
             fs::remove_file(&normalized_path)?;
         }
+        for (_, replica) in self.replica_paths(&normalized_path) {
+            if replica.exists() {
+                fs::remove_file(&replica)?;
+            }
+        }
+
+        let _ = fs::remove_file(crate::thumbnail::cache_path(file_path));
+        let _ = fs::remove_file(crate::video::poster_path(file_path));
         
         Ok(())
     }
     
+    pub fn get_file_size(&self, file_path: &str, encoding: Option<StorageEncoding>) -> anyhow::Result<Option<u64>> {
+        if let Some(s3) = self.s3_store(file_path)? {
+            return match s3.size(file_path)? {
+                Some(_) => Ok(Some(self.open_file(Path::new(file_path), encoding)?.seek(SeekFrom::End(0))?)),
+                None => Ok(None),
+            };
+        }
+
+        let normalized_path = self.resolve(Path::new(file_path))?;
+
+        match fs::metadata(&normalized_path) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(self.open_file(&normalized_path, encoding)?.seek(SeekFrom::End(0))?)),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Bytes the file takes up in storage, before any decryption or
+    /// decompression.
+    pub fn get_stored_size(&self, file_path: &str) -> anyhow::Result<Option<u64>> {
+        if let Some(s3) = self.s3_store(file_path)? {
+            return s3.size(file_path);
+        }
+
+        match fs::metadata(self.resolve(Path::new(file_path))?) {
+            Ok(metadata) if metadata.is_file() => Ok(Some(metadata.len())),
+            Ok(_) => Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     pub fn file_exists(&self, file_path: &str) -> bool {
-        let path = PathBuf::from(file_path);
-        if let Ok(normalized_path) = Self::normalize_path(&path) {
+        match self.s3_store(file_path) {
+            Ok(Some(s3)) => return s3.size(file_path).is_ok_and(|size| size.is_some()),
+            Ok(None) => {}
+            Err(_) => return false,
+        }
+
+        if let Ok(normalized_path) = self.resolve(Path::new(file_path)) {
             normalized_path.exists()
         } else {
             false
@@ -397,32 +1328,74 @@ impl FileStorage {
         upload_id: &Uuid,
         total_size: u64,
     ) -> anyhow::Result<(PathBuf, PathBuf)> {
-        let disk_path = match self.find_available_disk(total_size)? {
-            Some(path) => path,
-            None => {
-                return Err(anyhow::anyhow!("No available disk space for file"));
-            }
-        };
-
-        let temp_dir = disk_path.join("temp").join(user_id.to_string());
-        let normalized_temp_dir = Self::normalize_path(&temp_dir)?;
-        fs::create_dir_all(&normalized_temp_dir)?;
-
-        let temp_file_path = normalized_temp_dir.join(format!("{}.tmp", upload_id));
+        let (disk_path, temp_file_path) = self.place(user_id, total_size, |disk_path| {
+            let temp_dir = disk_path.join("temp").join(user_id.to_string());
+            let normalized_temp_dir = Self::normalize_path(&temp_dir)?;
+            fs::create_dir_all(&normalized_temp_dir)?;
+            Ok(normalized_temp_dir.join(format!("{}.tmp", upload_id)))
+        })?;
         
-        let file = fs::File::create(&temp_file_path)?;
-        file.set_len(total_size)?;
+        if let Err(e) = self.preallocate_temp_file(&temp_file_path, total_size) {
+            self.release_space(&temp_file_path);
+            return Err(e);
+        }
         
         Ok((temp_file_path, disk_path))
     }
 
+    pub fn create_archive_path(&self, user_id: &Uuid, part_id: &Uuid, estimated_size: u64) -> anyhow::Result<PathBuf> {
+        let disk_path = self
+            .find_available_disk(user_id, estimated_size)?
+            .ok_or_else(|| anyhow::anyhow!("No available disk space for archive"))?;
+
+        let archive_dir = disk_path.join("archives").join(user_id.to_string());
+        let normalized_archive_dir = Self::normalize_path(&archive_dir)?;
+        fs::create_dir_all(&normalized_archive_dir)?;
+
+        Ok(normalized_archive_dir.join(format!("{}.zip", part_id)))
+    }
+
+    pub fn preallocate_temp_file(&self, temp_file_path: &Path, total_size: u64) -> anyhow::Result<()> {
+        if let Some(parent) = temp_file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = fs::File::create(temp_file_path)?;
+        file.set_len(total_size)?;
+
+        Ok(())
+    }
+
+    pub fn temp_file_matches(&self, temp_file_path: &Path, expected_size: u64) -> bool {
+        fs::metadata(temp_file_path)
+            .map(|metadata| metadata.is_file() && metadata.len() == expected_size)
+            .unwrap_or(false)
+    }
+
     pub fn write_chunk(
         &self,
         temp_file_path: &Path,
         chunk_data: &[u8],
         chunk_number: i32,
         chunk_size: i64,
+        expected_sha256: Option<&str>,
     ) -> anyhow::Result<()> {
+        if let Some(expected) = expected_sha256 {
+            let actual = hex::encode(Sha256::digest(chunk_data));
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(ChecksumMismatch {
+                    expected: expected.trim().to_lowercase(),
+                    actual,
+                }
+                .into());
+            }
+        }
+
+        #[cfg(feature = "chaos")]
+        if crate::chaos::take_chunk_write_failure() {
+            anyhow::bail!("injected failure writing chunk {}", chunk_number);
+        }
+
         let mut file = fs::OpenOptions::new()
             .write(true)
             .open(temp_file_path)?;
@@ -454,17 +1427,37 @@ impl FileStorage {
             format!("{}.{}", file_id, file_extension)
         };
 
-        let user_dir = disk_path.join("users").join(user_id.to_string());
-        let normalized_user_dir = Self::normalize_path(&user_dir)?;
-        fs::create_dir_all(&normalized_user_dir)?;
-
-        let final_file_path = normalized_user_dir.join(&filename);
-        
-        fs::rename(temp_file_path, &final_file_path)?;
+        let (disk_path, final_file_path) = match self.s3.as_deref().filter(|s3| s3.placement() == S3Placement::Primary) {
+            Some(s3) => Self::s3_blob(s3, user_id, &filename),
+            None => (disk_path.to_path_buf(), Self::user_file_path(disk_path, user_id, &filename)?),
+        };
         
-        let file_size = fs::metadata(&final_file_path)?.len() as i64;
+        let mut temp_head = Vec::with_capacity(preview::SNIFF_LENGTH);
+        fs::File::open(temp_file_path)?
+            .take(preview::SNIFF_LENGTH as u64)
+            .read_to_end(&mut temp_head)?;
+
+        let rewrite = self.storage_key.is_some()
+            || s3::is_s3_path(&final_file_path.to_string_lossy())
+            || self.should_compress(&temp_head, original_filename);
+        let (file_size, stored_size, encoding, checksum, head) = if rewrite {
+            let written = self.write_blob(&final_file_path, &mut fs::File::open(temp_file_path)?, original_filename)?;
+            (written.file_size as i64, written.stored_size as i64, written.encoding, written.checksum, written.head)
+        } else {
+            fs::rename(temp_file_path, &final_file_path)?;
+
+            let file_size = fs::metadata(&final_file_path)?.len() as i64;
+            let checksum = self.compute_sha256(&final_file_path, Some(StorageEncoding::PLAIN))?;
+
+            let mut head = Vec::with_capacity(preview::SNIFF_LENGTH);
+            fs::File::open(&final_file_path)?
+                .take(preview::SNIFF_LENGTH as u64)
+                .read_to_end(&mut head)?;
+            (file_size, file_size, StorageEncoding::PLAIN, checksum, head)
+        };
 
         let _ = self.cleanup_temp_file(temp_file_path);
+        self.replicate_written(&final_file_path, user_id);
 
         Ok(StorageResult {
             file_id,
@@ -472,13 +1465,34 @@ impl FileStorage {
             file_path: final_file_path.to_string_lossy().to_string(),
             disk_path: disk_path.to_string_lossy().to_string(),
             file_size,
+            stored_size,
+            encoding,
+            checksum,
+            mime_type: preview::sniff_mime_type(&head, original_filename),
         })
     }
 
+    pub fn compute_sha256(&self, file_path: &Path, encoding: Option<StorageEncoding>) -> anyhow::Result<String> {
+        let mut file = self.open_file(file_path, encoding)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop {
+            let bytes_read = file.read(&mut buffer)?;
+            if bytes_read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..bytes_read]);
+        }
+
+        Ok(hex::encode(hasher.finalize()))
+    }
+
     pub fn cleanup_temp_file(&self, temp_file_path: &Path) -> anyhow::Result<()> {
         if temp_file_path.exists() {
             fs::remove_file(temp_file_path)?;
         }
+        self.release_space(temp_file_path);
         Ok(())
     }
 
@@ -505,7 +1519,7 @@ impl FileStorage {
         let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         let max_age_seconds = max_age_hours * 3600;
 
-        for storage_path in &self.storage_paths {
+        for storage_path in &self.storage_paths() {
             let temp_dir = storage_path.join("temp");
             if !temp_dir.exists() {
                 continue;
@@ -527,33 +1541,30 @@ impl FileStorage {
         let mut freed_space = 0u64;
 
         if let Ok(entries) = fs::read_dir(temp_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
+            for entry in entries.flatten() {
+                let path = entry.path();
+                
+                if path.is_dir() {
+                    let (count, space) = self.cleanup_temp_directory(&path, current_time, max_age_seconds)?;
+                    cleaned_count += count;
+                    freed_space += space;
                     
-                    if path.is_dir() {
-                        let (count, space) = self.cleanup_temp_directory(&path, current_time, max_age_seconds)?;
-                        cleaned_count += count;
-                        freed_space += space;
-                        
-                        if let Ok(entries) = fs::read_dir(&path) {
-                            if entries.count() == 0 {
-                                let _ = fs::remove_dir(&path);
-                            }
+                    if let Ok(entries) = fs::read_dir(&path) {
+                        if entries.count() == 0 {
+                            let _ = fs::remove_dir(&path);
                         }
-                    } else if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
-                        if let Ok(metadata) = entry.metadata() {
-                            let file_size = metadata.len();
-                            if let Ok(modified) = metadata.modified() {
-                                if let Ok(modified_time) = modified.duration_since(UNIX_EPOCH) {
-                                    let file_age = current_time.saturating_sub(modified_time.as_secs());
-                                    
-                                    if file_age > max_age_seconds {
-                                        if fs::remove_file(&path).is_ok() {
-                                            cleaned_count += 1;
-                                            freed_space += file_size;
-                                        }
-                                    }
+                    }
+                } else if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
+                    if let Ok(metadata) = entry.metadata() {
+                        let file_size = metadata.len();
+                        if let Ok(modified) = metadata.modified() {
+                            if let Ok(modified_time) = modified.duration_since(UNIX_EPOCH) {
+                                let file_age = current_time.saturating_sub(modified_time.as_secs());
+                                
+                                if file_age > max_age_seconds && fs::remove_file(&path).is_ok() {
+                                    self.release_space(&path);
+                                    cleaned_count += 1;
+                                    freed_space += file_size;
                                 }
                             }
                         }
@@ -566,7 +1577,7 @@ impl FileStorage {
     }
 
     pub fn cleanup_orphaned_temp_files(&self) -> anyhow::Result<CleanupResult> {
-        self.cleanup_old_temp_files(24)
+        self.cleanup_old_temp_files(self.temp_file_max_age_hours)
     }
 
     pub fn get_temp_files_info(&self) -> anyhow::Result<TempFilesInfo> {
@@ -575,7 +1586,7 @@ impl FileStorage {
         let mut oldest_file_age_hours: Option<f64> = None;
         let current_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
 
-        for storage_path in &self.storage_paths {
+        for storage_path in &self.storage_paths() {
             let temp_dir = storage_path.join("temp");
             if !temp_dir.exists() {
                 continue;
@@ -600,47 +1611,40 @@ impl FileStorage {
         })
     }
 
-    fn scan_temp_directory(&self, temp_dir: &Path, current_time: u64) -> anyhow::Result<(usize, u64)> {
-        let (file_count, total_size, _) = self.scan_temp_directory_with_age(temp_dir, current_time)?;
-        Ok((file_count, total_size))
-    }
-
     fn scan_temp_directory_with_age(&self, temp_dir: &Path, current_time: u64) -> anyhow::Result<(usize, u64, Option<f64>)> {
         let mut file_count = 0;
         let mut total_size = 0u64;
         let mut oldest_age_hours: Option<f64> = None;
 
         if let Ok(entries) = fs::read_dir(temp_dir) {
-            for entry in entries {
-                if let Ok(entry) = entry {
-                    let path = entry.path();
+            for entry in entries.flatten() {
+                let path = entry.path();
+                
+                if path.is_dir() {
+                    let (sub_files, sub_size, sub_oldest) = self.scan_temp_directory_with_age(&path, current_time)?;
+                    file_count += sub_files;
+                    total_size += sub_size;
                     
-                    if path.is_dir() {
-                        let (sub_files, sub_size, sub_oldest) = self.scan_temp_directory_with_age(&path, current_time)?;
-                        file_count += sub_files;
-                        total_size += sub_size;
+                    if let Some(age) = sub_oldest {
+                        oldest_age_hours = Some(match oldest_age_hours {
+                            Some(current_oldest) => current_oldest.max(age),
+                            None => age,
+                        });
+                    }
+                } else if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
+                    file_count += 1;
+                    if let Ok(metadata) = entry.metadata() {
+                        total_size += metadata.len();
                         
-                        if let Some(age) = sub_oldest {
-                            oldest_age_hours = Some(match oldest_age_hours {
-                                Some(current_oldest) => current_oldest.max(age),
-                                None => age,
-                            });
-                        }
-                    } else if path.extension().and_then(|s| s.to_str()) == Some("tmp") {
-                        file_count += 1;
-                        if let Ok(metadata) = entry.metadata() {
-                            total_size += metadata.len();
-                            
-                            if let Ok(modified) = metadata.modified() {
-                                if let Ok(duration) = modified.duration_since(SystemTime::UNIX_EPOCH) {
-                                    let file_age_seconds = current_time.saturating_sub(duration.as_secs());
-                                    let file_age_hours = file_age_seconds as f64 / 3600.0;
-                                    
-                                    oldest_age_hours = Some(match oldest_age_hours {
-                                        Some(current_oldest) => current_oldest.max(file_age_hours),
-                                        None => file_age_hours,
-                                    });
-                                }
+                        if let Ok(modified) = metadata.modified() {
+                            if let Ok(duration) = modified.duration_since(SystemTime::UNIX_EPOCH) {
+                                let file_age_seconds = current_time.saturating_sub(duration.as_secs());
+                                let file_age_hours = file_age_seconds as f64 / 3600.0;
+                                
+                                oldest_age_hours = Some(match oldest_age_hours {
+                                    Some(current_oldest) => current_oldest.max(file_age_hours),
+                                    None => file_age_hours,
+                                });
                             }
                         }
                     }
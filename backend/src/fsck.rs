@@ -0,0 +1,199 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::file_storage::StoredBlob;
+use crate::models::{FileInfo, FsckIssue, FsckReport, FsckRequest};
+use crate::operations::Progress;
+use crate::{database, s3, AppState};
+
+const BATCH_SIZE: i64 = 200;
+const MAX_LISTED_ISSUES: usize = 1000;
+const MIN_ORPHAN_AGE: Duration = Duration::from_secs(60 * 60);
+const SIDECAR_SUFFIXES: &[&str] = &[".poster.jpg", ".thumb.jpg"];
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+fn push(issues: &mut Vec<FsckIssue>, truncated: &mut bool, file: &FileInfo, detail: String) {
+    warn!("Storage check: file {} at {}: {}", file.id, file.file_path, detail);
+    if issues.len() < MAX_LISTED_ISSUES {
+        issues.push(FsckIssue {
+            file_id: file.id,
+            path: file.file_path.clone(),
+            detail,
+        });
+    } else {
+        *truncated = true;
+    }
+}
+
+async fn check_file(state: &AppState, file: &FileInfo, request: &FsckRequest, report: &mut FsckReport) -> anyhow::Result<()> {
+    let file_storage = state.file_storage.clone();
+    let (file_path, encoding, checksums) = (file.file_path.clone(), file.encoding(), request.checksums);
+    let (size, checksum) = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let size = match file_storage.get_file_size(&file_path, encoding)? {
+            Some(size) => size,
+            None => return Ok((None, None)),
+        };
+        let checksum = checksums
+            .then(|| file_storage.compute_sha256(Path::new(&file_path), encoding))
+            .transpose()?;
+        Ok((Some(size), checksum))
+    })
+    .await?
+    .unwrap_or_else(|e| {
+        warn!("Storage check could not read file {}: {:#}", file.id, e);
+        (None, None)
+    });
+
+    let size = match size {
+        Some(size) => size as i64,
+        None => {
+            report.missing_count += 1;
+            push(&mut report.missing, &mut report.truncated, file, "blob is missing or unreadable".to_string());
+            return Ok(());
+        }
+    };
+
+    let checksum_matches = match (&checksum, &file.checksum) {
+        (Some(actual), Some(expected)) if !actual.eq_ignore_ascii_case(expected) => {
+            report.checksum_mismatch_count += 1;
+            let detail = format!("expected sha256 {}, found {}", expected, actual);
+            push(&mut report.checksum_mismatches, &mut report.truncated, file, detail);
+            false
+        }
+        (Some(_), Some(_)) => true,
+        _ => false,
+    };
+
+    if size != file.file_size {
+        report.size_mismatch_count += 1;
+        let detail = format!("recorded {} bytes, blob holds {}", file.file_size, size);
+        push(&mut report.size_mismatches, &mut report.truncated, file, detail);
+        if request.repair && checksum_matches {
+            database::set_file_size(&state.db, &file.id, size).await?;
+            report.fixed_sizes += 1;
+        }
+    }
+
+    if request.repair {
+        let file_storage = state.file_storage.clone();
+        let (file_path, user_id) = (file.file_path.clone(), file.user_id);
+        match tokio::task::spawn_blocking(move || file_storage.replicate(&file_path, &user_id)).await? {
+            Ok(restored) => report.restored_copies += restored as i64,
+            Err(e) => warn!("Storage check could not restore the copies of file {}: {:#}", file.id, e),
+        }
+    }
+
+    Ok(())
+}
+
+fn is_known(known: &HashSet<PathBuf>, relative: &Path) -> bool {
+    if known.contains(relative) {
+        return true;
+    }
+    let name = relative.to_string_lossy();
+    SIDECAR_SUFFIXES
+        .iter()
+        .filter_map(|suffix| name.strip_suffix(suffix))
+        .any(|base| known.contains(&PathBuf::from(base)))
+}
+
+pub async fn find_orphans(state: &AppState, min_age: Duration) -> anyhow::Result<(i64, Vec<StoredBlob>)> {
+    let known: HashSet<PathBuf> = database::get_stored_file_paths(&state.db)
+        .await?
+        .iter()
+        .filter(|path| !s3::is_s3_path(path))
+        .filter_map(|path| state.file_storage.relative_blob_path(path))
+        .collect();
+
+    let file_storage = state.file_storage.clone();
+    let blobs = tokio::task::spawn_blocking(move || file_storage.list_blobs()).await??;
+    let scanned = blobs.len() as i64;
+    let cutoff = SystemTime::now() - min_age;
+    let orphans = blobs
+        .into_iter()
+        .filter(|blob| blob.modified <= cutoff)
+        .filter(|blob| {
+            blob.path
+                .strip_prefix(&blob.root)
+                .is_ok_and(|relative| !is_known(&known, relative))
+        })
+        .collect();
+
+    Ok((scanned, orphans))
+}
+
+async fn check_orphans(state: &AppState, request: &FsckRequest, report: &mut FsckReport) -> anyhow::Result<()> {
+    let (scanned, orphans) = find_orphans(state, MIN_ORPHAN_AGE).await?;
+    report.checked_blobs = scanned;
+    for blob in orphans {
+        report.orphaned_count += 1;
+        warn!("Storage check: {} has no file record", blob.path.display());
+        if report.orphaned.len() < MAX_LISTED_ISSUES {
+            report.orphaned.push(blob.path.to_string_lossy().to_string());
+        } else {
+            report.truncated = true;
+        }
+        if request.repair {
+            match state.file_storage.set_aside(&blob.root, &blob.path) {
+                Ok(_) => report.set_aside += 1,
+                Err(e) => warn!("Storage check could not move {} to lost+found: {:#}", blob.path.display(), e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn check(state: &AppState, request: &FsckRequest, progress: Option<&Progress>) -> anyhow::Result<FsckReport> {
+    let mut report = FsckReport {
+        repair: request.repair,
+        checksums: request.checksums,
+        ..FsckReport::default()
+    };
+
+    let mut after: Option<Uuid> = None;
+    loop {
+        let files = database::get_files_after(&state.db, after, BATCH_SIZE).await?;
+        for file in &files {
+            check_file(state, file, request, &mut report).await?;
+            report.checked_files += 1;
+            if let Some(progress) = progress {
+                progress.update(report.checked_files, None).await?;
+            }
+        }
+        match files.last() {
+            Some(last) if files.len() as i64 == BATCH_SIZE => after = Some(last.id),
+            _ => break,
+        }
+    }
+
+    check_orphans(state, request, &mut report).await?;
+
+    info!(
+        "Storage check looked at {} files and {} blobs: {} missing, {} size and {} checksum mismatches, {} orphaned",
+        report.checked_files,
+        report.checked_blobs,
+        report.missing_count,
+        report.size_mismatch_count,
+        report.checksum_mismatch_count,
+        report.orphaned_count
+    );
+    Ok(report)
+}
+
+pub async fn run(state: &AppState, request: &FsckRequest, progress: Option<&Progress>) -> anyhow::Result<FsckReport> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("a storage check is already running");
+    }
+    let result = check(state, request, progress).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
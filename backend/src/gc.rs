@@ -0,0 +1,48 @@
+use std::fs;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+use crate::models::OrphanGcRun;
+use crate::{database, fsck, AppState};
+
+pub fn action(state: &AppState) -> &'static str {
+    if state.config.orphan_gc_delete { "delete" } else { "quarantine" }
+}
+
+pub async fn run(state: &AppState) -> anyhow::Result<OrphanGcRun> {
+    let grace = Duration::from_secs(state.config.orphan_gc_grace_hours * 60 * 60);
+    let (scanned, orphans) = fsck::find_orphans(state, grace).await?;
+
+    let mut run = OrphanGcRun {
+        id: Uuid::new_v4(),
+        action: action(state).to_string(),
+        scanned_blobs: scanned,
+        orphaned_blobs: orphans.len() as i64,
+        orphaned_bytes: orphans.iter().map(|blob| blob.size as i64).sum(),
+        reclaimed_bytes: 0,
+        failed_blobs: 0,
+        created_at: chrono::Utc::now(),
+    };
+    for blob in orphans {
+        let removed = if state.config.orphan_gc_delete {
+            fs::remove_file(&blob.path).map_err(anyhow::Error::from)
+        } else {
+            state.file_storage.set_aside(&blob.root, &blob.path).map(|_| ())
+        };
+        match removed {
+            Ok(()) if state.config.orphan_gc_delete => run.reclaimed_bytes += blob.size as i64,
+            Ok(()) => {}
+            Err(e) => {
+                warn!("Orphaned blob cleanup could not {} {}: {:#}", run.action, blob.path.display(), e);
+                run.failed_blobs += 1;
+            }
+        }
+    }
+
+    database::record_orphan_gc_run(&state.db, &run).await?;
+    info!(
+        "Orphaned blob cleanup scanned {} blobs: {} orphaned ({} bytes, action {}), {} bytes reclaimed",
+        run.scanned_blobs, run.orphaned_blobs, run.orphaned_bytes, run.action, run.reclaimed_bytes
+    );
+    Ok(run)
+}
@@ -0,0 +1,653 @@
+use std::collections::HashMap;
+use std::io::{BufReader, Cursor, Read, Seek};
+use std::path::{Component, Path, PathBuf};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use axum::http::StatusCode;
+use tokio::runtime::Handle;
+use tracing::{error, info};
+use uuid::Uuid;
+use crate::file_storage::FileStorage;
+use crate::models::{ArchiveContents, ArchiveEntry, FileInfo, ImportJob, ImportKind, PhotoMetadata, StorageEncoding, StorageResult};
+use crate::{database, quota, AppState};
+
+const MAX_SIDECAR_SIZE: u64 = 1024 * 1024;
+const PROGRESS_INTERVAL: i32 = 50;
+const MAX_LISTED_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum ArchiveFormat {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+pub fn detect_format(filename: &str) -> Option<ArchiveFormat> {
+    let lower = filename.to_lowercase();
+    if lower.ends_with(".zip") {
+        Some(ArchiveFormat::Zip)
+    } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        Some(ArchiveFormat::TarGz)
+    } else if lower.ends_with(".tar") {
+        Some(ArchiveFormat::Tar)
+    } else {
+        None
+    }
+}
+
+pub fn detect_file_format(file: &FileInfo) -> Option<ArchiveFormat> {
+    detect_format(&file.original_filename).or(match file.mime_type.as_deref() {
+        Some("application/zip") => Some(ArchiveFormat::Zip),
+        Some("application/x-tar") => Some(ArchiveFormat::Tar),
+        _ => None,
+    })
+}
+
+pub fn resolve_server_path(import_root: &str, relative: &str) -> Option<PathBuf> {
+    let relative = Path::new(relative);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        return None;
+    }
+
+    let root = Path::new(import_root).canonicalize().ok()?;
+    let path = root.join(relative).canonicalize().ok()?;
+
+    (path.starts_with(&root) && path.is_file()).then_some(path)
+}
+
+pub fn default_target_folder(kind: Option<ImportKind>, archive_name: &str) -> String {
+    match kind {
+        Some(ImportKind::Takeout) => "Google Takeout".to_string(),
+        Some(ImportKind::Dropbox) => "Dropbox".to_string(),
+        Some(ImportKind::Archive) | None => {
+            let lower = archive_name.to_lowercase();
+            let stem_len = [".tar.gz", ".tgz", ".zip", ".tar"]
+                .iter()
+                .find(|ext| lower.ends_with(*ext))
+                .map(|ext| archive_name.len() - ext.len())
+                .unwrap_or(archive_name.len());
+            archive_name[..stem_len].to_string()
+        }
+    }
+}
+
+fn zip_modified(entry: &zip::read::ZipFile) -> Option<DateTime<Utc>> {
+    entry.last_modified().and_then(|dt| {
+        NaiveDate::from_ymd_opt(dt.year() as i32, dt.month() as u32, dt.day() as u32)?
+            .and_hms_opt(dt.hour() as u32, dt.minute() as u32, dt.second() as u32)
+            .map(|naive| naive.and_utc())
+    })
+}
+
+fn tar_modified(header: &tar::Header) -> Option<DateTime<Utc>> {
+    header
+        .mtime()
+        .ok()
+        .and_then(|secs| Utc.timestamp_opt(secs as i64, 0).single())
+}
+
+struct Listing {
+    contents: ArchiveContents,
+}
+
+impl Listing {
+    fn add(&mut self, raw_path: &str, is_dir: bool, size: u64, modified_at: Option<DateTime<Utc>>) {
+        let components = match sanitize_path(raw_path) {
+            Some(components) if !components.is_empty() => components,
+            _ => return,
+        };
+
+        self.contents.entry_count += 1;
+        if !is_dir {
+            self.contents.total_size += size;
+        }
+        if self.contents.entries.len() >= MAX_LISTED_ENTRIES {
+            self.contents.truncated = true;
+            return;
+        }
+        self.contents.entries.push(ArchiveEntry {
+            name: components.last().cloned().unwrap_or_default(),
+            path: components.join("/"),
+            size: if is_dir { 0 } else { size },
+            is_dir,
+            modified_at,
+        });
+    }
+
+    fn add_tar<R: Read>(&mut self, reader: R) -> anyhow::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if !entry_type.is_file() && !entry_type.is_dir() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().to_string();
+            self.add(&name, entry_type.is_dir(), entry.size(), tar_modified(entry.header()));
+        }
+        Ok(())
+    }
+}
+
+pub fn list_contents<R: Read + Seek>(archive: R, format: ArchiveFormat) -> anyhow::Result<ArchiveContents> {
+    let file = BufReader::new(archive);
+    let mut listing = Listing {
+        contents: ArchiveContents {
+            format: format.as_str().to_string(),
+            entry_count: 0,
+            total_size: 0,
+            truncated: false,
+            entries: Vec::new(),
+        },
+    };
+
+    match format {
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for index in 0..zip.len() {
+                let entry = zip.by_index_raw(index)?;
+                if entry.is_symlink() {
+                    continue;
+                }
+                let name = entry.name().to_string();
+                listing.add(&name, entry.is_dir(), entry.size(), zip_modified(&entry));
+            }
+        }
+        ArchiveFormat::Tar => listing.add_tar(file)?,
+        ArchiveFormat::TarGz => listing.add_tar(flate2::read::GzDecoder::new(file))?,
+    }
+
+    Ok(listing.contents)
+}
+
+pub async fn run_import(
+    state: AppState,
+    job: ImportJob,
+    archive: PathBuf,
+    encoding: Option<StorageEncoding>,
+    format: ArchiveFormat,
+    kind: Option<ImportKind>,
+    parent_id: Option<Uuid>,
+) {
+    let handle = Handle::current();
+    let db = state.db.clone();
+    let job_id = job.id;
+
+    let result = tokio::task::spawn_blocking(move || {
+        let mut importer = Importer::new(&state, handle, &job, kind, parent_id);
+        let result = importer.run(&archive, encoding, format);
+        importer.save_progress();
+        result.map(|_| importer.files_imported)
+    })
+    .await
+    .unwrap_or_else(|e| Err(anyhow::anyhow!("import task panicked: {}", e)));
+
+    match result {
+        Ok(files) => {
+            info!("Import {} finished: {} files", job_id, files);
+            let _ = database::finish_import_job(&db, &job_id, "completed", None).await;
+        }
+        Err(e) => {
+            error!("Import {} failed: {}", job_id, e);
+            let _ = database::finish_import_job(&db, &job_id, "failed", Some(&e.to_string())).await;
+        }
+    }
+}
+
+// The quota was checked against the size the archive header declares, but a
+// zip entry can inflate past it. Read at most one byte more than declared and
+// reject the entry if that byte arrives.
+fn store_declared_size(
+    storage: &FileStorage,
+    reader: &mut dyn Read,
+    size: u64,
+    user_id: &Uuid,
+    name: &str,
+) -> anyhow::Result<StorageResult> {
+    let stored = storage.store_reader(&mut Read::take(reader, size.saturating_add(1)), size, user_id, name)?;
+    if stored.file_size as u64 > size {
+        let _ = storage.delete_file(&stored.file_path);
+        anyhow::bail!("{} is larger than its archive header declares", name);
+    }
+    Ok(stored)
+}
+
+struct Importer<'a> {
+    state: &'a AppState,
+    handle: Handle,
+    job: &'a ImportJob,
+    kind: Option<ImportKind>,
+    parent_id: Option<Uuid>,
+    folders: HashMap<Vec<String>, Uuid>,
+    imported: HashMap<Vec<String>, Uuid>,
+    sidecars: Vec<(Vec<String>, serde_json::Value)>,
+    files_imported: i32,
+    folders_created: i32,
+    bytes_imported: i64,
+}
+
+impl<'a> Importer<'a> {
+    fn new(state: &'a AppState, handle: Handle, job: &'a ImportJob, kind: Option<ImportKind>, parent_id: Option<Uuid>) -> Self {
+        Self {
+            state,
+            handle,
+            job,
+            kind,
+            parent_id,
+            folders: HashMap::new(),
+            imported: HashMap::new(),
+            sidecars: Vec::new(),
+            files_imported: 0,
+            folders_created: 0,
+            bytes_imported: 0,
+        }
+    }
+
+    fn run(&mut self, archive: &Path, encoding: Option<StorageEncoding>, format: ArchiveFormat) -> anyhow::Result<()> {
+        let file = BufReader::new(self.state.file_storage.open_file(archive, encoding)?);
+
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = zip::ZipArchive::new(file)?;
+                for index in 0..zip.len() {
+                    let mut entry = zip.by_index(index)?;
+                    if entry.is_symlink() {
+                        continue;
+                    }
+                    let name = entry.name().to_string();
+                    let is_dir = entry.is_dir();
+                    let size = entry.size();
+                    let modified = zip_modified(&entry);
+                    self.handle_entry(&name, is_dir, size, modified, &mut entry)?;
+                }
+            }
+            ArchiveFormat::Tar => self.run_tar(file)?,
+            ArchiveFormat::TarGz => self.run_tar(flate2::read::GzDecoder::new(file))?,
+        }
+
+        self.apply_sidecars()
+    }
+
+    fn run_tar<R: Read>(&mut self, reader: R) -> anyhow::Result<()> {
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let entry_type = entry.header().entry_type();
+            if !entry_type.is_file() && !entry_type.is_dir() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().to_string();
+            let size = entry.size();
+            let modified = tar_modified(entry.header());
+            self.handle_entry(&name, entry_type.is_dir(), size, modified, &mut entry)?;
+        }
+        Ok(())
+    }
+
+    fn handle_entry(
+        &mut self,
+        raw_path: &str,
+        is_dir: bool,
+        size: u64,
+        modified: Option<DateTime<Utc>>,
+        reader: &mut dyn Read,
+    ) -> anyhow::Result<()> {
+        let mut components = match sanitize_path(raw_path) {
+            Some(components) => components,
+            None => return Ok(()),
+        };
+
+        let kind = *self.kind.get_or_insert(if components.first().map(String::as_str) == Some("Takeout") {
+            ImportKind::Takeout
+        } else {
+            ImportKind::Dropbox
+        });
+
+        if kind == ImportKind::Takeout && components.first().map(String::as_str) == Some("Takeout") {
+            components.remove(0);
+        }
+
+        if components.is_empty() {
+            return Ok(());
+        }
+
+        if is_dir {
+            self.ensure_folder(&components)?;
+            return Ok(());
+        }
+
+        let name = components.last().cloned().unwrap_or_default();
+        let folder_id = self.ensure_folder(&components[..components.len() - 1])?;
+
+        if kind == ImportKind::Takeout && name.to_lowercase().ends_with(".json") && size <= MAX_SIDECAR_SIZE {
+            let mut data = Vec::with_capacity(size as usize);
+            Read::take(&mut *reader, MAX_SIDECAR_SIZE + 1).read_to_end(&mut data)?;
+            if data.len() as u64 > MAX_SIDECAR_SIZE {
+                anyhow::bail!("{} is larger than its archive header declares", name);
+            }
+            if let Some(sidecar) = parse_sidecar(&data) {
+                self.sidecars.push((components, sidecar));
+                return Ok(());
+            }
+            return self.store_entry(components, folder_id, &mut Cursor::new(data), size, modified);
+        }
+
+        self.store_entry(components, folder_id, reader, size, modified)
+    }
+
+    fn store_entry(
+        &mut self,
+        components: Vec<String>,
+        folder_id: Uuid,
+        reader: &mut dyn Read,
+        size: u64,
+        modified: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<()> {
+        let name = components.last().cloned().unwrap_or_default();
+        // Quota was checked for the whole archive up front, but the owner may
+        // have uploaded other files since.
+        match self.handle.block_on(quota::check_upload_quota(self.state, &self.job.user_id, size as i64)) {
+            Ok(_) => {}
+            Err(StatusCode::INSUFFICIENT_STORAGE) => anyhow::bail!("storage quota exceeded while importing {}", name),
+            Err(status) => anyhow::bail!("could not check the storage quota while importing {}: {}", name, status),
+        }
+        let stored = store_declared_size(&self.state.file_storage, reader, size, &self.job.user_id, &name)?;
+
+        let db = &self.state.db;
+        let record = self.handle.block_on(database::create_file_record(
+            db,
+            &self.job.user_id,
+            &stored.filename,
+            &name,
+            &stored.file_path,
+            &stored.disk_path,
+            stored.file_size,
+            stored.stored_size,
+            stored.encoding,
+            stored.mime_type.as_deref(),
+            Some(&stored.checksum),
+        ));
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let _ = self.state.file_storage.delete_file(&stored.file_path);
+                return Err(e);
+            }
+        };
+
+        self.handle.block_on(database::set_file_folder(db, &record.id, Some(&folder_id)))?;
+        if let Some(modified) = modified {
+            self.handle.block_on(database::set_file_timestamps(db, &record.id, modified))?;
+        }
+
+        self.imported.insert(components, record.id);
+        self.files_imported += 1;
+        self.bytes_imported += stored.file_size;
+
+        if self.files_imported % PROGRESS_INTERVAL == 0 {
+            self.save_progress();
+        }
+
+        Ok(())
+    }
+
+    fn ensure_folder(&mut self, path: &[String]) -> anyhow::Result<Uuid> {
+        if let Some(id) = self.folders.get(path) {
+            return Ok(*id);
+        }
+
+        let parent_id = match path.split_last() {
+            Some((_, parent)) => self.ensure_folder(parent)?,
+            None => {
+                let (folder, created) = self.handle.block_on(database::get_or_create_folder(
+                    &self.state.db,
+                    &self.job.user_id,
+                    self.parent_id.as_ref(),
+                    &self.job.target_folder,
+                ))?;
+                if created {
+                    self.folders_created += 1;
+                }
+                self.folders.insert(Vec::new(), folder.id);
+                return Ok(folder.id);
+            }
+        };
+
+        let name = path.last().cloned().unwrap_or_default();
+        let (folder, created) = self.handle.block_on(database::get_or_create_folder(
+            &self.state.db,
+            &self.job.user_id,
+            Some(&parent_id),
+            &name,
+        ))?;
+        if created {
+            self.folders_created += 1;
+        }
+        self.folders.insert(path.to_vec(), folder.id);
+
+        Ok(folder.id)
+    }
+
+    fn apply_sidecars(&mut self) -> anyhow::Result<()> {
+        let sidecars = std::mem::take(&mut self.sidecars);
+
+        for (path, sidecar) in sidecars {
+            let dir = &path[..path.len() - 1];
+            let file_id = match self.find_sidecar_target(dir, &path[path.len() - 1], &sidecar)? {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let taken_at = sidecar_timestamp(&sidecar, "photoTakenTime")
+                .or_else(|| sidecar_timestamp(&sidecar, "creationTime"));
+            let geo = ["geoDataExif", "geoData"]
+                .iter()
+                .filter_map(|key| sidecar.get(*key))
+                .find(|geo| {
+                    geo.get("latitude").and_then(|v| v.as_f64()).unwrap_or(0.0) != 0.0
+                        || geo.get("longitude").and_then(|v| v.as_f64()).unwrap_or(0.0) != 0.0
+                });
+            let description = sidecar
+                .get("description")
+                .and_then(|v| v.as_str())
+                .filter(|s| !s.is_empty())
+                .map(|s| s.to_string());
+
+            if let Some(taken_at) = taken_at {
+                self.handle.block_on(database::set_file_timestamps(&self.state.db, &file_id, taken_at))?;
+            }
+
+            let metadata = PhotoMetadata {
+                file_id,
+                taken_at,
+                latitude: geo.and_then(|g| g.get("latitude")).and_then(|v| v.as_f64()),
+                longitude: geo.and_then(|g| g.get("longitude")).and_then(|v| v.as_f64()),
+                altitude: geo.and_then(|g| g.get("altitude")).and_then(|v| v.as_f64()),
+                description,
+                raw: Some(sidecar),
+            };
+            self.handle.block_on(database::upsert_photo_metadata(&self.state.db, &metadata))?;
+        }
+
+        Ok(())
+    }
+
+    fn find_sidecar_target(
+        &mut self,
+        dir: &[String],
+        sidecar_name: &str,
+        sidecar: &serde_json::Value,
+    ) -> anyhow::Result<Option<Uuid>> {
+        if let Some(title) = sidecar.get("title").and_then(|v| v.as_str()) {
+            let mut candidate = dir.to_vec();
+            candidate.push(title.to_string());
+            if let Some(id) = self.imported.get(&candidate) {
+                return Ok(Some(*id));
+            }
+        }
+
+        let stem = sidecar_stem(sidecar_name);
+        if !stem.is_empty() {
+            let matched = self
+                .imported
+                .iter()
+                .filter(|(path, _)| path.len() == dir.len() + 1 && path[..dir.len()] == *dir)
+                .find(|(path, _)| path[dir.len()] == stem || path[dir.len()].starts_with(&stem))
+                .map(|(_, id)| *id);
+            if matched.is_some() {
+                return Ok(matched);
+            }
+        }
+
+        let title = match sidecar.get("title").and_then(|v| v.as_str()) {
+            Some(title) => title,
+            None => return Ok(None),
+        };
+        let folder_id = self.ensure_folder(dir)?;
+        let file = self.handle.block_on(database::find_file_in_folder(
+            &self.state.db,
+            &self.job.user_id,
+            Some(&folder_id),
+            title,
+        ))?;
+
+        Ok(file.map(|f| f.id))
+    }
+
+    fn save_progress(&self) {
+        let kind = self.kind.map(|k| k.as_str()).unwrap_or("unknown");
+        let _ = self.handle.block_on(database::update_import_progress(
+            &self.state.db,
+            &self.job.id,
+            kind,
+            self.files_imported,
+            self.folders_created,
+            self.bytes_imported,
+        ));
+    }
+}
+
+fn sanitize_path(raw_path: &str) -> Option<Vec<String>> {
+    let components: Vec<String> = raw_path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .map(|part| part.to_string())
+        .collect();
+
+    if components.iter().any(|part| part == ".." || part.contains(':') || part.chars().any(|c| c.is_control())) {
+        return None;
+    }
+
+    if components.first().map(String::as_str) == Some("__MACOSX")
+        || components.last().map(String::as_str) == Some(".DS_Store")
+    {
+        return None;
+    }
+
+    (!components.is_empty()).then_some(components)
+}
+
+fn parse_sidecar(data: &[u8]) -> Option<serde_json::Value> {
+    let value: serde_json::Value = serde_json::from_slice(data).ok()?;
+    let is_sidecar = value.get("title").is_some_and(|t| t.is_string())
+        && (value.get("photoTakenTime").is_some() || value.get("creationTime").is_some());
+
+    is_sidecar.then_some(value)
+}
+
+fn sidecar_timestamp(sidecar: &serde_json::Value, key: &str) -> Option<DateTime<Utc>> {
+    let timestamp = sidecar.get(key)?.get("timestamp")?;
+    let secs = match timestamp {
+        serde_json::Value::String(s) => s.parse::<i64>().ok()?,
+        other => other.as_i64()?,
+    };
+    Utc.timestamp_opt(secs, 0).single()
+}
+
+fn sidecar_stem(sidecar_name: &str) -> String {
+    let stem = sidecar_name
+        .strip_suffix(".json")
+        .or_else(|| sidecar_name.strip_suffix(".JSON"))
+        .unwrap_or(sidecar_name);
+
+    match stem.find(".supplemental-metadata").or_else(|| stem.rfind(".supp")) {
+        Some(index) => stem[..index].to_string(),
+        None => stem.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use crate::config::Config;
+
+    fn storage(root: &Path) -> FileStorage {
+        let mut config = Config::from_env().unwrap();
+        config.storage_paths = vec![root.to_string_lossy().to_string()];
+        config.read_only_storage_paths = Vec::new();
+        config.replication_factor = 1;
+        config.storage_encryption_key = None;
+        config.compression_enabled = false;
+        config.s3 = None;
+        FileStorage::new(&config).unwrap()
+    }
+
+    // A deflated entry whose local and central headers both claim `declared` bytes.
+    fn zip_with_declared_size(data: &[u8], declared: u32) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        writer.start_file("photo.bmp", options).unwrap();
+        writer.write_all(data).unwrap();
+        let mut archive = writer.finish().unwrap().into_inner();
+
+        archive[22..26].copy_from_slice(&declared.to_le_bytes());
+        let central = archive.windows(4).position(|window| window == b"PK\x01\x02").unwrap();
+        archive[central + 24..central + 28].copy_from_slice(&declared.to_le_bytes());
+        archive
+    }
+
+    fn stored_files(path: &Path) -> usize {
+        std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .map(|path| if path.is_dir() { stored_files(&path) } else { 1 })
+            .sum()
+    }
+
+    #[test]
+    fn stores_entry_of_declared_size() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = storage(root.path());
+        let data = vec![b'x'; 10_000];
+        let mut zip = zip::ZipArchive::new(Cursor::new(zip_with_declared_size(&data, data.len() as u32))).unwrap();
+        let mut entry = zip.by_index(0).unwrap();
+        let size = entry.size();
+
+        let stored = store_declared_size(&storage, &mut entry, size, &Uuid::new_v4(), "photo.bmp").unwrap();
+        assert_eq!(stored.file_size, 10_000);
+        assert_eq!(stored_files(root.path()), 1);
+    }
+
+    #[test]
+    fn rejects_entry_larger_than_its_header() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = storage(root.path());
+        let mut zip = zip::ZipArchive::new(Cursor::new(zip_with_declared_size(&vec![b'x'; 10_000], 100))).unwrap();
+        let mut entry = zip.by_index(0).unwrap();
+        assert_eq!(entry.size(), 100);
+
+        let error = store_declared_size(&storage, &mut entry, 100, &Uuid::new_v4(), "photo.bmp").unwrap_err();
+        assert!(error.to_string().contains("larger than its archive header"), "{}", error);
+        assert_eq!(stored_files(root.path()), 0);
+    }
+}
@@ -0,0 +1,42 @@
+use chrono::{Duration, Utc};
+use crate::config::Config;
+use crate::database;
+
+pub const FAILURE_WINDOW_HOURS: i64 = 24;
+
+pub fn user_key(username: &str) -> String {
+    format!("user:{}", username.trim().to_lowercase())
+}
+
+pub fn ip_key(ip: &str) -> String {
+    format!("ip:{}", ip)
+}
+
+pub fn lockout_duration(failures: i32, max_attempts: i32, config: &Config) -> Option<Duration> {
+    if failures < max_attempts {
+        return None;
+    }
+
+    let exponent = (failures - max_attempts).min(20) as u32;
+    let seconds = config
+        .login_lockout_seconds
+        .saturating_mul(1i64 << exponent)
+        .min(config.login_lockout_max_seconds);
+    Some(Duration::seconds(seconds))
+}
+
+pub async fn record_failure(pool: &sqlx::PgPool, config: &Config, username: &str, ip: &str) -> anyhow::Result<()> {
+    let attempts = [
+        (user_key(username), config.login_max_attempts),
+        (ip_key(ip), config.login_max_attempts_per_ip),
+    ];
+
+    for (key, max_attempts) in attempts {
+        let failures = database::record_login_failure(pool, &key, Utc::now() - Duration::hours(FAILURE_WINDOW_HOURS)).await?;
+        if let Some(duration) = lockout_duration(failures, max_attempts, config) {
+            database::set_login_lockout(pool, &key, Utc::now() + duration).await?;
+        }
+    }
+
+    Ok(())
+}
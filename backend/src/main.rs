@@ -1,31 +1,104 @@
 use axum::{
-    extract::{Path, State, Extension},
-    http::{StatusCode, Method, HeaderValue, header},
+    extract::{ConnectInfo, Path, Query, State, Extension},
+    http::{StatusCode, Method, HeaderMap, HeaderValue, header},
     middleware,
-    response::{Json, Response},
-    routing::{delete, get, post},
+    response::{IntoResponse, Json, Redirect, Response},
+    routing::{any, delete, get, patch, post, put},
     Router,
     body::Body,
 };
-use axum::body::Bytes;
+use http_body_util::BodyExt;
 use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use tower_http::limit::RequestBodyLimitLayer;
-use tracing::info;
+use tracing::{error, info, warn};
 use uuid::Uuid;
 use clap::{Parser, Subcommand};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_cron_scheduler::{JobScheduler, Job};
 
+mod access;
+mod alerts;
+mod archive;
 mod auth;
+mod broadcast;
+mod build_info;
+mod camera;
+mod channels;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod chunking;
+mod clipboard;
+mod compression;
 mod config;
+mod content_index;
 mod database;
+mod digest;
+mod disk_health;
+mod doctor;
+mod egress;
+mod encryption;
+mod export;
 mod file_storage;
+mod fsck;
+mod gc;
+mod import;
+mod login_limit;
+mod mailer;
 mod models;
+mod mounts;
+mod oidc;
+mod operations;
+mod paste;
+mod preview;
+mod print;
+mod quota;
+mod rclone;
+mod rebalance;
+mod remote_fetch;
+mod s3;
+mod s3_gateway;
+mod schema;
+mod scim;
+mod scrub;
+mod security;
+mod tenants;
+mod sigv4;
+mod smb;
+mod thumbnail;
+mod tiering;
+mod torrent;
+mod trash;
+mod usage;
+mod video;
+mod webauthn;
+mod webdav;
 
-use config::Config;
+use config::{Config, RiskyContentPolicy, SchemaDriftPolicy};
+use access::Permission;
+use channels::{ChannelTestResult, NotificationEvent};
+use quota::{check_upload_quota, quota_status};
+use scim::{ScimError, ScimJson};
 use models::*;
 
+const MAX_REQUEST_BODY_SIZE: usize = 1024 * 1024 * 1024;
+const MAX_SEARCH_TERMS: usize = 10;
+const MAX_FILE_TAGS: usize = 32;
+const MAX_CUSTOM_METADATA_KEYS: usize = 64;
+const MAX_CUSTOM_METADATA_VALUE_LENGTH: usize = 1024;
+const CUSTOM_METADATA_FILTER_PREFIX: &str = "meta.";
+const DOWNLOAD_AUDIT_RETENTION_DAYS: i64 = 90;
+const MAX_AUDITED_DOWNLOADS: i64 = 500;
+const FILE_ACTIVITY_RETENTION_DAYS: i64 = 90;
+const MAX_SNIPPET_SIZE: usize = 1024 * 1024;
+
+// Held from the quota check until the upload row exists, so concurrent initiations count each other.
+static UPLOAD_ADMISSION: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+const SNIPPETS_FOLDER_NAME: &str = "Snippets";
+
 #[derive(Parser)]
 #[command(name = "local-drive-backend")]
 #[command(about = "A self-hosted file storage backend")]
@@ -44,6 +117,36 @@ enum Commands {
         #[arg(short, long)]
         password: String,
     },
+    DeactivateUser {
+        #[arg(short, long)]
+        username: String,
+    },
+    ReactivateUser {
+        #[arg(short, long)]
+        username: String,
+    },
+    PurgeUser {
+        #[arg(long)]
+        after_days: i64,
+        #[arg(short, long)]
+        username: Option<String>,
+        #[arg(long)]
+        dry_run: bool,
+    },
+    EvacuateDisk {
+        #[arg(long)]
+        path: String,
+    },
+    Fsck {
+        #[arg(long)]
+        repair: bool,
+        #[arg(long)]
+        checksums: bool,
+    },
+    GenerateSecret,
+    GenerateStorageKey,
+    Doctor,
+    SchemaDiff,
     Serve,
 }
 
@@ -52,6 +155,69 @@ pub struct AppState {
     pub db: PgPool,
     pub config: Config,
     pub file_storage: Arc<file_storage::FileStorage>,
+    pub api_usage: Arc<usage::UsageRecorder>,
+    pub runtime: Arc<build_info::Runtime>,
+}
+
+enum FileError {
+    Status(StatusCode),
+    NameConflict(NameConflict),
+}
+
+impl From<StatusCode> for FileError {
+    fn from(status: StatusCode) -> Self {
+        FileError::Status(status)
+    }
+}
+
+impl FileError {
+    fn status(self) -> StatusCode {
+        match self {
+            FileError::Status(status) => status,
+            FileError::NameConflict(_) => StatusCode::CONFLICT,
+        }
+    }
+}
+
+impl IntoResponse for FileError {
+    fn into_response(self) -> Response {
+        match self {
+            FileError::Status(status) => status.into_response(),
+            FileError::NameConflict(conflict) => (StatusCode::CONFLICT, Json(conflict)).into_response(),
+        }
+    }
+}
+
+enum LoginError {
+    Status(StatusCode),
+    Locked(chrono::DateTime<chrono::Utc>),
+}
+
+impl From<StatusCode> for LoginError {
+    fn from(status: StatusCode) -> Self {
+        LoginError::Status(status)
+    }
+}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        match self {
+            LoginError::Status(status) => status.into_response(),
+            LoginError::Locked(until) => {
+                let retry_after = (until - chrono::Utc::now()).num_seconds().max(1);
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    Json(serde_json::json!({
+                        "error": "too_many_attempts",
+                        "message": "Too many failed login attempts, try again later",
+                        "locked_until": until,
+                    })),
+                )
+                    .into_response()
+            }
+        }
+    }
 }
 
 #[tokio::main]
@@ -60,311 +226,8309 @@ async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
 
     let cli = Cli::parse();
+
+    if let Some(Commands::GenerateSecret) = cli.command {
+        println!("{}", auth::generate_secret());
+        return Ok(());
+    }
+
+    if let Some(Commands::GenerateStorageKey) = cli.command {
+        println!("{}", encryption::StorageKey::generate());
+        return Ok(());
+    }
+
     let config = Config::from_env()?;
+
+    if let Some(Commands::Doctor) = cli.command {
+        let findings = doctor::run_all_checks(&config).await;
+        if !doctor::print_report(&findings) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     let db = database::create_connection_pool(&config.database_url).await?;
+
+    if let Some(Commands::SchemaDiff) = cli.command {
+        let diff = schema::diff(&db, &config.database_url).await?;
+        if let Some(reason) = &diff.skipped {
+            println!("Partial check: {}", reason);
+        }
+        if diff.is_empty() {
+            println!("The database schema matches this build");
+            return Ok(());
+        }
+        for pending in &diff.pending {
+            println!("pending migration: {}", pending);
+        }
+        for line in diff.lines() {
+            println!("{}", line);
+        }
+        if diff.is_breaking() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    check_schema_drift(&db, &config).await?;
     database::initialize_database(&db).await?;
+    check_declared_columns(&db, &config).await?;
 
     match cli.command {
         Some(Commands::CreateAdmin { username, email, password }) => {
             create_admin_user(&db, &username, &email, &password).await?;
             return Ok(());
         }
-        Some(Commands::Serve) | None => {
+        Some(Commands::DeactivateUser { username }) => {
+            let user = find_user_for_cli(&db, &username).await?;
+            if database::deactivate_user(&db, &user.id).await? {
+                println!("User '{}' deactivated and signed out of all sessions", user.username);
+            } else {
+                println!("User '{}' is already deactivated", user.username);
+            }
+            return Ok(());
+        }
+        Some(Commands::ReactivateUser { username }) => {
+            let user = find_user_for_cli(&db, &username).await?;
+            if database::reactivate_user(&db, &user.id).await? {
+                println!("User '{}' reactivated", user.username);
+            } else {
+                println!("User '{}' is not deactivated", user.username);
+            }
+            return Ok(());
+        }
+        Some(Commands::PurgeUser { after_days, username, dry_run }) => {
+            if after_days < 0 {
+                anyhow::bail!("--after-days must not be negative");
+            }
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(after_days);
+            let mut users = database::get_users_deactivated_before(&db, cutoff).await?;
+            if let Some(username) = username {
+                users.retain(|user| user.username == username);
+            }
+
+            if users.is_empty() {
+                println!("No users deactivated more than {} days ago", after_days);
+                return Ok(());
+            }
+
+            let file_storage = Arc::new(file_storage::FileStorage::new(&config)?);
+            for user in users {
+                if dry_run {
+                    println!("Would purge user '{}' ({})", user.username, user.id);
+                    continue;
+                }
+                let (files, bytes) = purge_user(&db, &file_storage, &user).await?;
+                println!("Purged user '{}': {} files, {} bytes", user.username, files, bytes);
+            }
+            return Ok(());
+        }
+        Some(Commands::EvacuateDisk { path }) => {
+            let state = cli_state(db, &config).await?;
+            let disk_path = state
+                .file_storage
+                .storage_root(&path)
+                .ok_or_else(|| anyhow::anyhow!("{} is not one of STORAGE_PATHS", path))?;
+
+            let result = rebalance::evacuate_disk(&state, &disk_path, None).await?;
+            println!(
+                "Moved {} files ({} bytes) off {}, {} failed, {} left",
+                result["moved"], result["bytes"], disk_path.display(), result["failed"], result["remaining"]
+            );
+            if result["remaining"] != 0 {
+                std::process::exit(1);
+            }
+            println!("{} holds no more files and can be removed from STORAGE_PATHS", disk_path.display());
+            return Ok(());
+        }
+        Some(Commands::Fsck { repair, checksums }) => {
+            let state = cli_state(db, &config).await?;
+            let report = fsck::run(&state, &FsckRequest { repair, checksums }, None).await?;
+            print_fsck_report(&report);
+            if !report.is_clean() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Commands::Serve)
+        | Some(Commands::Doctor)
+        | Some(Commands::GenerateSecret)
+        | Some(Commands::GenerateStorageKey)
+        | Some(Commands::SchemaDiff)
+        | None => {
+        }
+    }
+
+    if let Some(problem) = auth::jwt_secret_problem(&config.jwt_secret) {
+        if !config.dev_mode {
+            anyhow::bail!(
+                "{}. Set JWT_SECRET or JWT_SECRET_FILE to a random value (see `generate-secret`), or set DEV_MODE=true for local development",
+                problem
+            );
+        }
+        warn!("{}; continuing because DEV_MODE is set", problem);
+    }
+
+    if config.provisioning_token.as_ref().is_some_and(|token| token.len() < auth::MIN_JWT_SECRET_LENGTH) {
+        anyhow::bail!(
+            "PROVISIONING_TOKEN is shorter than {} characters. Use a random value (see `generate-secret`)",
+            auth::MIN_JWT_SECRET_LENGTH
+        );
+    }
+
+    if config.startup_self_check {
+        for finding in doctor::run_startup_checks(&config) {
+            if finding.severity >= doctor::Severity::Warning {
+                warn!("Startup check {}: {}", finding.check, finding.message);
+            }
         }
     }
 
     let file_storage = Arc::new(file_storage::FileStorage::new(&config)?);
-    let state = AppState { db, config: config.clone(), file_storage };
+    let state = AppState {
+        db,
+        config: config.clone(),
+        file_storage,
+        api_usage: Arc::new(usage::UsageRecorder::default()),
+        runtime: Arc::new(build_info::Runtime::default()),
+    };
+
+    let database_version = database::get_server_version(&state.db).await?;
+    info!(
+        version = build_info::VERSION,
+        build = build_info::BUILD_HASH,
+        port = config.port,
+        storage_paths = ?config.storage_paths,
+        database = %database_version,
+        "Starting Local Drive"
+    );
+
+    #[cfg(feature = "chaos")]
+    warn!("Failure injection is compiled in; do not run this build in production");
+
+    apply_storage_path_changes(&state).await?;
+    tenants::refresh_storage_roots(&state).await?;
+    reconcile_chunked_uploads(&state).await?;
+
+    let interrupted_imports = database::fail_interrupted_import_jobs(&state.db).await?;
+    if interrupted_imports > 0 {
+        warn!("Marked {} interrupted import jobs as failed", interrupted_imports);
+    }
+
+    let interrupted_fetches = database::fail_interrupted_remote_fetches(&state.db).await?;
+    if interrupted_fetches > 0 {
+        warn!("Marked {} interrupted URL imports as failed", interrupted_fetches);
+    }
+
+    let interrupted_archives = database::fail_interrupted_archive_parts(&state.db).await?;
+    if interrupted_archives > 0 {
+        warn!("Marked {} interrupted archive parts as failed", interrupted_archives);
+    }
+
+    let interrupted_operations = database::fail_interrupted_operations(&state.db).await?;
+    if interrupted_operations > 0 {
+        warn!("Marked {} interrupted operations as failed", interrupted_operations);
+    }
 
     let scheduler = JobScheduler::new().await?;
-    let file_storage_clone = state.file_storage.clone();
+    let cleanup_state = state.clone();
     
-    let cleanup_job = Job::new_async("0 0 */6 * * *", move |_uuid, _l| {
-        let file_storage = file_storage_clone.clone();
+    let cleanup_job = Job::new_async(state.runtime.schedule("temp_cleanup", "0 0 */6 * * *"), move |_uuid, _l| {
+        let state = cleanup_state.clone();
         Box::pin(async move {
-            if let Ok(result) = file_storage.cleanup_orphaned_temp_files() {
+            match expire_chunked_uploads(&state).await {
+                Ok(expired) if expired > 0 => info!("Removed {} expired upload sessions", expired),
+                Ok(_) => {}
+                Err(e) => warn!("Failed to remove expired upload sessions: {}", e),
+            }
+            if let Ok(result) = state.file_storage.blocking(|storage| storage.cleanup_orphaned_temp_files()).await {
                 info!("Automatic temp cleanup: {} files removed, {} bytes freed", result.cleaned_files, result.freed_space);
             }
         })
     })?;
     
     scheduler.add(cleanup_job).await?;
+    
+    if let Some(retention_days) = config.trash_retention_days {
+        let trash_state = state.clone();
+        let trash_job = Job::new_async(state.runtime.schedule("trash_retention", "0 30 3 * * *"), move |_uuid, _l| {
+            let state = trash_state.clone();
+            Box::pin(async move {
+                match purge_expired_trash(&state, retention_days).await {
+                    Ok((purged, freed)) if purged > 0 => {
+                        info!("Automatic trash purge: {} files removed, {} bytes freed", purged, freed);
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Automatic trash purge failed: {}", e),
+                }
+            })
+        })?;
+        scheduler.add(trash_job).await?;
+        info!("Automatic trash purge scheduled (daily, {} day retention)", retention_days);
+    }
+    
+    let broadcast_state = state.clone();
+    let broadcast_job = Job::new_async(state.runtime.schedule("broadcasts", "15 * * * * *"), move |_uuid, _l| {
+        let state = broadcast_state.clone();
+        Box::pin(async move {
+            broadcast::process(&state).await;
+        })
+    })?;
+    scheduler.add(broadcast_job).await?;
+    
+    if config.content_indexing {
+        let content_index_state = state.clone();
+        let content_index_job = Job::new_async(state.runtime.schedule("content_index", "45 * * * * *"), move |_uuid, _l| {
+            let state = content_index_state.clone();
+            Box::pin(async move {
+                content_index::process(&state).await;
+            })
+        })?;
+        scheduler.add(content_index_job).await?;
+    }
+    
+    if config.video_metadata {
+        let video_state = state.clone();
+        let video_job = Job::new_async(state.runtime.schedule("video_metadata", "50 * * * * *"), move |_uuid, _l| {
+            let state = video_state.clone();
+            Box::pin(async move {
+                video::process(&state).await;
+            })
+        })?;
+        scheduler.add(video_job).await?;
+    }
+    
+    if config.alerts_enabled {
+        let alerts_state = state.clone();
+        let evaluator = Arc::new(alerts::Evaluator::default());
+        let alerts_job = Job::new_async(state.runtime.schedule("alerts", "0 * * * * *"), move |_uuid, _l| {
+            let state = alerts_state.clone();
+            let evaluator = evaluator.clone();
+            Box::pin(async move {
+                evaluator.evaluate(&state).await;
+            })
+        })?;
+        scheduler.add(alerts_job).await?;
+    }
+    
+    if config.orphan_gc_enabled {
+        let gc_state = state.clone();
+        let gc_job = Job::new_async(state.runtime.schedule("orphan_gc", "0 10 5 * * *"), move |_uuid, _l| {
+            let state = gc_state.clone();
+            Box::pin(async move {
+                if let Err(e) = gc::run(&state).await {
+                    error!("Orphaned blob cleanup failed: {:#}", e);
+                }
+            })
+        })?;
+        scheduler.add(gc_job).await?;
+    }
+    
+    if config.scrub_enabled {
+        let scrub_state = state.clone();
+        let scrub_schedule = if config.scrub_nightly { "0 0 4 * * *" } else { "0 0 4 * * Sun" };
+        let scrub_job = Job::new_async(state.runtime.schedule("integrity_scrub", scrub_schedule), move |_uuid, _l| {
+            let state = scrub_state.clone();
+            Box::pin(async move {
+                if let Err(e) = scrub::run(&state, None).await {
+                    error!("Integrity scrub failed: {:#}", e);
+                }
+            })
+        })?;
+        scheduler.add(scrub_job).await?;
+    }
+
+    if config.s3.is_some() {
+        let lifecycle_state = state.clone();
+        let lifecycle_job = Job::new_async(state.runtime.schedule("lifecycle_rules", "0 0 5 * * *"), move |_uuid, _l| {
+            let state = lifecycle_state.clone();
+            Box::pin(async move {
+                if let Err(e) = tiering::run(&state, None).await {
+                    error!("Lifecycle rules failed: {:#}", e);
+                }
+            })
+        })?;
+        scheduler.add(lifecycle_job).await?;
+    }
+    
+    let trash_limit_state = state.clone();
+    let trash_limit_job = Job::new_async(state.runtime.schedule("trash_limit", "0 40 3 * * *"), move |_uuid, _l| {
+        let state = trash_limit_state.clone();
+        Box::pin(async move {
+            trash::enforce_all(&state).await;
+        })
+    })?;
+    scheduler.add(trash_limit_job).await?;
+    
+    let export_state = state.clone();
+    let export_job = Job::new_async(state.runtime.schedule("exports", "0 * * * * *"), move |_uuid, _l| {
+        let state = export_state.clone();
+        Box::pin(async move {
+            export::run_due_jobs(&state).await;
+        })
+    })?;
+    scheduler.add(export_job).await?;
+
+    let mount_sync_state = state.clone();
+    let mount_sync_job = Job::new_async(state.runtime.schedule("mount_sync", "0 45 * * * *"), move |_uuid, _l| {
+        let state = mount_sync_state.clone();
+        Box::pin(async move {
+            mounts::run_due_syncs(&state).await;
+        })
+    })?;
+    scheduler.add(mount_sync_job).await?;
+
+    let session_db = state.db.clone();
+    let session_job = Job::new_async(state.runtime.schedule("session_cleanup", "0 15 4 * * *"), move |_uuid, _l| {
+        let db = session_db.clone();
+        Box::pin(async move {
+            match database::delete_expired_sessions(&db).await {
+                Ok(removed) if removed > 0 => info!("Removed {} expired sessions", removed),
+                Ok(_) => {}
+                Err(e) => warn!("Expired session cleanup failed: {}", e),
+            }
+        })
+    })?;
+    scheduler.add(session_job).await?;
+
+    let login_attempts_db = state.db.clone();
+    let login_attempts_job = Job::new_async(state.runtime.schedule("login_attempts_cleanup", "0 20 * * * *"), move |_uuid, _l| {
+        let db = login_attempts_db.clone();
+        Box::pin(async move {
+            let cutoff = chrono::Utc::now() - chrono::Duration::hours(login_limit::FAILURE_WINDOW_HOURS);
+            if let Err(e) = database::delete_stale_login_attempts(&db, cutoff).await {
+                warn!("Login attempt cleanup failed: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(login_attempts_job).await?;
+
+    let usage_state = state.clone();
+    let usage_job = Job::new_async(state.runtime.schedule("api_usage_flush", "30 * * * * *"), move |_uuid, _l| {
+        let state = usage_state.clone();
+        Box::pin(async move {
+            if let Err(e) = state.api_usage.flush(&state.db).await {
+                warn!("Failed to flush API usage counters: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(usage_job).await?;
+
+    let usage_cleanup_db = state.db.clone();
+    let usage_cleanup_job = Job::new_async(state.runtime.schedule("api_usage_cleanup", "0 30 4 * * *"), move |_uuid, _l| {
+        let db = usage_cleanup_db.clone();
+        Box::pin(async move {
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(usage::RETENTION_DAYS);
+            if let Err(e) = database::delete_old_api_usage(&db, cutoff).await {
+                warn!("API usage cleanup failed: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(usage_cleanup_job).await?;
+
+    let download_audit_db = state.db.clone();
+    let download_audit_job = Job::new_async(state.runtime.schedule("download_audit_cleanup", "0 45 4 * * *"), move |_uuid, _l| {
+        let db = download_audit_db.clone();
+        Box::pin(async move {
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(DOWNLOAD_AUDIT_RETENTION_DAYS);
+            if let Err(e) = database::delete_old_downloads(&db, cutoff).await {
+                warn!("Download audit cleanup failed: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(download_audit_job).await?;
+
+    let file_activity_db = state.db.clone();
+    let file_activity_job = Job::new_async(state.runtime.schedule("file_activity_cleanup", "0 50 4 * * *"), move |_uuid, _l| {
+        let db = file_activity_db.clone();
+        Box::pin(async move {
+            let cutoff = chrono::Utc::now() - chrono::Duration::days(FILE_ACTIVITY_RETENTION_DAYS);
+            if let Err(e) = database::delete_old_file_activity(&db, cutoff).await {
+                warn!("File activity cleanup failed: {}", e);
+            }
+        })
+    })?;
+    scheduler.add(file_activity_job).await?;
+
+    let snippet_state = state.clone();
+    let snippet_job = Job::new_async(state.runtime.schedule("snippet_cleanup", "0 */10 * * * *"), move |_uuid, _l| {
+        let state = snippet_state.clone();
+        Box::pin(async move {
+            match purge_spent_snippets(&state).await {
+                Ok(purged) if purged > 0 => info!("Removed {} expired or burned snippets", purged),
+                Ok(_) => {}
+                Err(e) => warn!("Snippet cleanup failed: {}", e),
+            }
+        })
+    })?;
+    scheduler.add(snippet_job).await?;
+    
     scheduler.start().await?;
+    info!(jobs = state.runtime.jobs().len(), "Started background scheduler");
     
     info!("Automatic temp file cleanup scheduled (every 6 hours)");
 
     let protected_routes = Router::new()
         .route("/files", get(list_files))
+        .route("/files/search", get(search_files))
+        .route("/files/starred", get(list_starred_files))
+        .route("/files/recent", get(list_recent_files))
+        .route("/files/activity", get(list_file_activity))
+        .route("/files/:id/star", post(star_file).delete(unstar_file))
+        .route("/search/content", get(search_file_contents))
         .route("/files/:id/download", get(download_file))
         .route("/files/:id", delete(move_to_trash))
+        .route("/files/:id/rename", post(rename_file))
+        .route("/files/:id/move", post(move_file))
+        .route("/files/:id/copy", post(copy_file))
+        .route("/files/:id/tags", put(set_file_tags).post(add_file_tags))
+        .route("/files/:id/tags/:tag", delete(remove_file_tag))
+        .route("/tags", get(list_tags))
+        .route("/tags/:tag/files", get(list_files_by_tag))
+        .route("/files/import-url", get(list_remote_fetches).post(import_from_url))
+        .route("/files/import-url/:id", get(get_remote_fetch))
+        .route("/auth/webauthn/register/start", post(webauthn_register_start))
+        .route("/auth/webauthn/register/finish", post(webauthn_register_finish))
+        .route("/user/passkeys", get(list_passkeys))
+        .route("/user/passkeys/:id", delete(delete_passkey))
+        .route("/user/s3-keys", get(list_s3_access_keys).post(create_s3_access_key))
+        .route("/user/s3-keys/:id", delete(delete_s3_access_key))
+        .route("/files/:id/checksum", get(get_file_checksum))
+        .route("/files/:id/photo-metadata", get(get_file_photo_metadata))
+        .route("/files/:id/preview", get(get_file_preview))
+        .route("/files/:id/preview/content", get(get_file_preview_content))
+        .route("/files/:id/print", post(print_file))
+        .route("/files/:id/metadata", get(get_file_metadata).patch(update_file_custom_metadata))
+        .route("/files/:id/poster", get(get_file_poster))
+        .route("/files/:id/downloads", get(get_file_downloads))
+        .route("/files/:id/archive-contents", get(get_archive_contents))
+        .route("/files/:id/extract", post(extract_archive_file))
+        .route("/folders", get(list_folders))
+        .route("/folders/:id", patch(update_folder))
+        .route("/folders/:id/offline", put(set_folder_keep_offline))
+        .route("/folders/:id/share-defaults", get(get_folder_share_defaults).put(set_folder_share_defaults).delete(delete_folder_share_defaults))
+        .route("/folders/:id/contents", get(get_folder_contents))
+        .route("/folders/:id/permissions", get(list_folder_permissions).put(set_folder_permission))
+        .route("/folders/:id/permissions/:user_id", delete(delete_folder_permission))
+        .route("/shared-with-me", get(list_shared_with_me))
+        .route("/folders/:id/gallery", get(get_folder_gallery).put(set_folder_gallery).delete(delete_folder_gallery))
+        .route("/galleries", get(list_galleries))
+        .route("/mounts", get(list_mounts))
+        .route("/mounts/:id/list", get(list_mount_directory))
+        .route("/mounts/:id/file", get(download_mount_file).put(upload_mount_file).delete(delete_mount_path))
+        .route("/mounts/:id/sync", put(set_mount_sync).post(run_mount_sync))
+        .route("/files/:id/offline", put(set_file_keep_offline))
+        .route("/sync/changes", get(get_sync_changes))
+        .route("/clipboard", get(get_clipboard).put(set_clipboard).delete(clear_clipboard))
+        .route("/clipboard/paste", post(paste_clipboard))
+        .route("/operations", get(list_operations))
+        .route("/operations/:id", get(get_operation))
+        .route("/operations/:id/cancel", post(cancel_operation))
+        .route("/aliases", get(list_aliases).post(create_alias))
+        .route("/aliases/:id", delete(delete_alias))
+        .route("/aliases/:id/download", get(download_alias))
+        .route("/rclone/tree/", get(rclone_tree_root))
+        .route("/rclone/tree/*path", get(rclone_tree))
+        .route("/rclone/sha256sums", get(get_rclone_sha256sums))
+        .route("/imports", get(list_import_jobs).post(create_import_job))
+        .route("/imports/:id", get(get_import_job))
+        .route("/files/:id/share", post(create_share))
+        .route("/shares", get(list_shares))
+        .route("/snippets", get(list_snippets).post(create_snippet))
+        .route("/snippets/:id", delete(delete_snippet))
+        .route("/shares/:id", delete(delete_share))
+        .route("/shares/:id/egress", put(set_share_egress))
+        .route("/shares/:id/torrent", get(get_share_torrent_info).post(create_share_torrent))
         .route("/trash", get(list_trash_files))
+        .route("/trash/usage", get(get_trash_usage))
         .route("/trash/:id/restore", post(restore_file))
         .route("/trash/:id", delete(delete_file_permanently))
         .route("/upload/initiate", post(initiate_chunked_upload))
+        .route("/upload/paste", post(paste_upload))
         .route("/upload/:upload_id/chunk/:chunk_number", post(upload_chunk))
         .route("/upload/:upload_id/complete", post(complete_chunked_upload))
         .route("/upload/:upload_id/status", get(get_upload_status))
         .route("/upload/:upload_id/cancel", delete(cancel_chunked_upload))
+        .route("/exports", get(list_export_jobs).post(create_export_job))
+        .route("/exports/:id", delete(delete_export_job))
+        .route("/exports/:id/run", post(run_export_job))
+        .route("/exports/:id/runs", get(list_export_runs))
+        .route("/user/archive", get(list_archive_parts).post(build_archive).delete(delete_archive))
+        .route("/user/archive/:id/download", get(download_archive_part))
+        .route("/user/profile", get(get_user_profile).patch(update_user_profile))
+        .route("/user/password", post(change_user_password))
+        .route("/user/sessions", get(list_user_sessions))
+        .route("/user/sessions/:id", delete(revoke_user_session))
         .route("/user/storage", get(get_user_storage_info))
+        .route("/user/transfers", get(get_user_transfers))
+        .route("/user/notifications", get(list_notifications))
+        .route("/user/notifications/:id/read", post(mark_notification_read))
+        .route("/user/quota", get(get_user_quota_status))
+        .route(
+            "/user/camera-uploads",
+            get(get_camera_upload_settings).put(set_camera_upload_settings).delete(reset_camera_upload_settings),
+        )
+        .route("/user/files/largest", get(get_user_largest_files))
+        .route("/user/files/stale", get(get_user_stale_files))
         .route_layer(middleware::from_fn_with_state(state.clone(), auth::auth_middleware));
 
-    let admin_routes = Router::new()
+    let user_admin_routes = Router::new()
         .route("/admin/users", get(list_users))
+        .route("/admin/users/recalculate-usage", post(recalculate_all_users_usage))
+        .route("/admin/users/:id/recalculate-usage", post(recalculate_user_usage))
+        .route("/admin/users/:id", delete(purge_deactivated_user))
+        .route("/admin/users/:id/deactivate", post(deactivate_user))
+        .route("/admin/users/:id/reactivate", post(reactivate_user))
+        .route("/admin/users/:id/unlock", post(unlock_user_login))
+        .route("/admin/users/:id/quota", put(set_user_quota))
+        .route("/admin/users/:id/egress", put(set_user_egress))
+        .route("/admin/users/:id/trash-limit", put(set_user_trash_limit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::admin_middleware));
+
+    let admin_routes = Router::new()
+        .route("/admin/info", get(get_admin_info))
+        .route("/admin/tenants", get(list_tenants).post(create_tenant))
+        .route("/admin/tenants/:id", get(get_tenant).put(update_tenant).delete(delete_tenant))
+        .route("/admin/users/:id/tenant", put(set_user_tenant))
+        .route("/admin/login-lockouts", get(list_login_lockouts).delete(clear_login_lockout))
+        .route("/admin/broadcast", post(create_broadcast))
+        .route("/admin/broadcasts", get(list_broadcasts))
+        .route("/admin/notification-channels/test", post(test_notification_channels))
+        .route("/admin/broadcasts/:id", get(get_broadcast))
+        .route("/admin/broadcasts/:id/recipients", get(list_broadcast_recipients))
+        .route("/admin/files/search", get(admin_search_files))
+        .route("/admin/files/bulk", post(admin_bulk_file_action))
+        .route("/admin/files/largest", get(admin_largest_files))
+        .route("/admin/files/stale", get(admin_stale_files))
+        .route("/admin/exports", get(admin_list_export_jobs))
+        .route("/admin/usage/api", get(get_api_usage_report))
+        .route("/admin/mounts", get(admin_list_mounts).post(create_external_mount))
+        .route("/admin/mounts/:id", delete(delete_external_mount))
+        .route("/admin/preview-handlers", get(list_preview_handlers).put(set_preview_handler).delete(delete_preview_handler))
+        .route("/admin/scrub", get(list_scrub_findings).post(start_scrub))
+        .route("/admin/lifecycle-rules", get(list_lifecycle_rules).post(create_lifecycle_rule))
+        .route("/admin/lifecycle-rules/run", post(start_lifecycle_rules))
+        .route("/admin/lifecycle-rules/:id", put(update_lifecycle_rule).delete(delete_lifecycle_rule))
+        .route("/admin/search/status", get(get_search_index_status))
+        .route("/admin/search/reindex", post(start_search_reindex))
         .route("/admin/storage", get(get_storage_info))
         .route("/admin/storage/report", get(get_disk_usage_report))
+        .route("/admin/storage/decisions", get(get_placement_decisions))
+        .route("/admin/storage/rebalance", post(start_storage_rebalance))
+        .route("/admin/storage/rebalance/status", get(get_storage_rebalance_status))
+        .route("/admin/storage/evacuate", post(start_disk_evacuation))
+        .route("/admin/storage/verify", post(start_storage_verify))
+        .route("/admin/storage/paths", post(add_storage_path).delete(remove_storage_path))
+        .route("/admin/storage/gc/report", get(get_orphan_gc_report))
         .route("/admin/temp/info", get(get_temp_files_info))
         .route("/admin/temp/cleanup", post(cleanup_temp_files))
-        .route("/admin/temp/cleanup/:hours", post(cleanup_temp_files_with_age))
+        .route("/admin/temp/cleanup/:hours", post(cleanup_temp_files_with_age));
+    #[cfg(feature = "chaos")]
+    let admin_routes = admin_routes.route("/admin/chaos", get(get_chaos_faults).put(update_chaos_faults).delete(reset_chaos_faults));
+    let admin_routes = admin_routes
+        .route_layer(middleware::from_fn(auth::instance_admin_middleware))
         .route_layer(middleware::from_fn_with_state(state.clone(), auth::admin_middleware));
 
+    let webdav_routes = Router::new()
+        .route(webdav::PREFIX, any(webdav_root))
+        .route(&format!("{}/", webdav::PREFIX), any(webdav_root))
+        .route(&format!("{}/*path", webdav::PREFIX), any(webdav_path))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::webdav_auth_middleware));
+
+    let provisioning_routes = Router::new()
+        .route("/provisioning/users", post(provision_user))
+        .route("/scim/v2/ServiceProviderConfig", get(scim_service_provider_config))
+        .route("/scim/v2/ResourceTypes", get(scim_resource_types))
+        .route("/scim/v2/Users", get(scim_list_users).post(scim_create_user))
+        .route(
+            "/scim/v2/Users/:id",
+            get(scim_get_user).put(scim_replace_user).patch(scim_patch_user).delete(scim_delete_user),
+        )
+        .route("/scim/v2/Groups", get(scim_list_groups).post(scim_create_group))
+        .route(
+            "/scim/v2/Groups/:id",
+            get(scim_get_group).put(scim_replace_group).patch(scim_patch_group).delete(scim_delete_group),
+        )
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::provisioning_middleware));
+
     let app = Router::new()
         .route("/health", get(health_check))
+        .route("/capabilities", get(get_capabilities))
+        .route("/rclone", get(get_rclone_info))
         .route("/auth/login", post(login))
+        .route("/auth/oidc/login", get(oidc_login))
+        .route("/auth/oidc/callback", get(oidc_callback))
+        .route("/auth/webauthn/login/start", post(webauthn_login_start))
+        .route("/auth/webauthn/login/finish", post(webauthn_login_finish))
+        .route("/share/:token", get(download_shared_file))
+        .route("/share/:token/metadata", get(get_shared_file_metadata))
+        .route("/snippet/:token", get(view_snippet))
+        .route("/share/:token/torrent", get(download_share_torrent))
+        .route("/share/:token/webseed", get(download_share_webseed))
+        .route("/gallery/:token", get(get_gallery_feed))
+        .route("/gallery/:token/files/:file_id/thumbnail", get(get_gallery_thumbnail))
+        .route("/gallery/:token/files/:file_id/original", get(get_gallery_original))
         .merge(protected_routes)
-        .merge(admin_routes)
-        .layer(RequestBodyLimitLayer::new(1024 * 1024 * 1024))
+        .merge(webdav_routes)
+        .merge(provisioning_routes);
+
+    let admin_app = Router::new().merge(user_admin_routes).merge(admin_routes);
+
+    // The gateway needs a host of its own: S3 clients address buckets from the root of the endpoint.
+    let s3_gateway = match config.s3_gateway_listen_addr {
+        Some(s3_addr) => {
+            let s3_app = Router::new()
+                .fallback(s3_request)
+                .layer(middleware::from_fn_with_state(state.clone(), s3_gateway::auth_middleware));
+            let s3_listener = tokio::net::TcpListener::bind(s3_addr).await?;
+            info!("S3 gateway listening on {}", s3_addr);
+            Some((s3_listener, with_common_layers(s3_app, state.clone())))
+        }
+        None => None,
+    };
+    let serve_s3_gateway = async {
+        match s3_gateway {
+            Some((s3_listener, s3_app)) => axum::serve(s3_listener, s3_app.into_make_service_with_connect_info::<SocketAddr>()).await,
+            None => Ok(()),
+        }
+    };
+
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
+    match config.admin_listen_addr {
+        Some(admin_addr) => {
+            let admin_app = with_common_layers(admin_app.route("/health", get(health_check)), state.clone());
+            let admin_listener = tokio::net::TcpListener::bind(admin_addr).await?;
+            info!("Server running on port {}, admin routes on {}", config.port, admin_addr);
+            tokio::try_join!(
+                async { axum::serve(listener, with_common_layers(app, state.clone()).into_make_service_with_connect_info::<SocketAddr>()).await },
+                async { axum::serve(admin_listener, admin_app.into_make_service_with_connect_info::<SocketAddr>()).await },
+                serve_s3_gateway,
+            )?;
+        }
+        None => {
+            info!("Server running on port {}", config.port);
+            let app = with_common_layers(app.merge(admin_app), state);
+            tokio::try_join!(
+                async { axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await },
+                serve_s3_gateway,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn with_common_layers(router: Router<AppState>, state: AppState) -> Router {
+    router
+        .layer(middleware::from_fn_with_state(state.clone(), usage::api_usage_middleware))
+        .layer(middleware::from_fn_with_state(state.clone(), security::security_headers_middleware))
+        .layer(RequestBodyLimitLayer::new(MAX_REQUEST_BODY_SIZE))
         .layer(
             CorsLayer::new()
                 .allow_origin("http://localhost:3000".parse::<HeaderValue>().unwrap())
-                .allow_methods([Method::GET, Method::POST, Method::DELETE, Method::OPTIONS])
-                .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION])
-                .expose_headers([header::CONTENT_DISPOSITION, header::CONTENT_LENGTH])
+                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::OPTIONS])
+                .allow_headers([
+                    header::CONTENT_TYPE,
+                    header::AUTHORIZATION,
+                    header::HeaderName::from_static("x-chunk-sha256"),
+                    header::HeaderName::from_static("content-digest"),
+                    header::HeaderName::from_static("repr-digest"),
+                    header::HeaderName::from_static("digest"),
+                    header::HeaderName::from_static(rclone::MTIME_HEADER),
+                ])
+                .expose_headers([header::CONTENT_DISPOSITION, header::CONTENT_LENGTH, header::LAST_MODIFIED, header::RETRY_AFTER, header::CONTENT_RANGE, header::ACCEPT_RANGES, header::ETAG])
                 .allow_credentials(true)
         )
-        .with_state(state);
+        .with_state(state)
+}
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", config.port)).await?;
-    info!("Server running on port {}", config.port);
-    axum::serve(listener, app).await?;
+async fn reconcile_chunked_uploads(state: &AppState) -> anyhow::Result<()> {
+    let uploads = database::get_incomplete_chunked_uploads(&state.db).await?;
+    let mut restarted = 0;
+    let mut failed = 0;
+
+    for upload in uploads {
+        let temp_file_path = std::path::Path::new(&upload.temp_path);
+        let disk_path = std::path::Path::new(&upload.disk_path);
+        if state.file_storage.temp_file_matches(temp_file_path, upload.total_size as u64) {
+            state.file_storage.reserve_space(temp_file_path, disk_path, upload.total_size as u64);
+            continue;
+        }
+
+        if disk_path.is_dir()
+            && state.file_storage
+                .preallocate_temp_file(temp_file_path, upload.total_size as u64)
+                .is_ok()
+        {
+            state.file_storage.reserve_space(temp_file_path, disk_path, upload.total_size as u64);
+            database::clear_uploaded_chunks(&state.db, &upload.id).await?;
+            database::set_chunked_upload_status(&state.db, &upload.id, "restarted", 0).await?;
+            restarted += 1;
+        } else {
+            database::set_chunked_upload_status(&state.db, &upload.id, "failed", upload.uploaded_chunks).await?;
+            failed += 1;
+        }
+    }
+
+    if restarted > 0 || failed > 0 {
+        info!(
+            "Reconciled chunked uploads: {} restarted from zero, {} marked failed",
+            restarted, failed
+        );
+    }
 
     Ok(())
 }
 
+async fn expire_chunked_uploads(state: &AppState) -> anyhow::Result<usize> {
+    let uploads = database::delete_expired_chunked_uploads(&state.db).await?;
+    let (expired_multipart, part_paths) = database::delete_expired_s3_multipart_uploads(&state.db).await?;
+    let expired = uploads.len() + expired_multipart;
+    state.file_storage
+        .blocking(move |storage| {
+            for upload in &uploads {
+                if let Err(e) = storage.cleanup_temp_file(std::path::Path::new(&upload.temp_path)) {
+                    warn!("Failed to remove temp file of expired upload {}: {}", upload.id, e);
+                }
+            }
+            for part_path in &part_paths {
+                if let Err(e) = storage.cleanup_temp_file(std::path::Path::new(part_path)) {
+                    warn!("Failed to remove temp file {} of an expired multipart upload: {}", part_path, e);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+    Ok(expired)
+}
+
 async fn create_admin_user(
     db: &PgPool,
     username: &str,
     email: &str,
     password: &str,
 ) -> anyhow::Result<()> {
-    use argon2::{Argon2, PasswordHasher};
-    use argon2::password_hash::{rand_core::OsRng, SaltString};
-
     let existing_user = database::get_user_by_username(db, username).await?;
     if existing_user.is_some() {
         println!("Admin user '{}' already exists!", username);
         return Ok(());
     }
 
-    let existing_email = database::get_user_by_email(db, email).await?;
-    if existing_email.is_some() {
-        println!("User with email '{}' already exists!", email);
-        return Ok(());
+    let existing_email = database::get_user_by_email(db, email).await?;
+    if existing_email.is_some() {
+        println!("User with email '{}' already exists!", email);
+        return Ok(());
+    }
+
+    let password_hash = auth::hash_password(password)?;
+
+    let user = database::create_user(db, username, email, &password_hash, true).await?;
+    println!("Admin user created successfully!");
+    println!("Username: {}", user.username);
+    println!("Email: {}", user.email);
+    println!("User ID: {}", user.id);
+
+    Ok(())
+}
+
+async fn check_schema_drift(db: &PgPool, config: &Config) -> anyhow::Result<()> {
+    let diff = match schema::diff(db, &config.database_url).await {
+        Ok(diff) => diff,
+        Err(e) => {
+            warn!("Could not compare the database schema with this build: {:#}", e);
+            return Ok(());
+        }
+    };
+
+    if let Some(reason) = &diff.skipped {
+        warn!("Schema drift check skipped: {}", reason);
+    }
+    if !diff.pending.is_empty() {
+        info!("Migrations will add {} schema objects: {}", diff.pending.len(), diff.pending.join(", "));
+    }
+    for line in diff.lines() {
+        warn!("Schema drift: {}", line);
+    }
+    if diff.is_breaking() && config.schema_drift_policy == SchemaDriftPolicy::Refuse {
+        anyhow::bail!(
+            "The database schema has drifted in a way migrations cannot fix (run `schema-diff` for details). \
+             Fix the schema, or set SCHEMA_DRIFT_POLICY=warn to start anyway"
+        );
+    }
+    Ok(())
+}
+
+async fn check_declared_columns(db: &PgPool, config: &Config) -> anyhow::Result<()> {
+    let missing = match schema::missing_columns(db).await {
+        Ok(missing) => missing,
+        Err(e) => {
+            warn!("Could not check the migrated database schema: {:#}", e);
+            return Ok(());
+        }
+    };
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    warn!("Columns still missing after migrations: {}", missing.join(", "));
+    if config.schema_drift_policy == SchemaDriftPolicy::Refuse {
+        anyhow::bail!(
+            "The database is missing columns this build needs after migrations ({}). \
+             Fix the schema, or set SCHEMA_DRIFT_POLICY=warn to start anyway",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn cli_state(db: PgPool, config: &Config) -> anyhow::Result<AppState> {
+    let state = AppState {
+        db,
+        config: config.clone(),
+        file_storage: Arc::new(file_storage::FileStorage::new(config)?),
+        api_usage: Arc::new(usage::UsageRecorder::default()),
+        runtime: Arc::new(build_info::Runtime::default()),
+    };
+    apply_storage_path_changes(&state).await?;
+    tenants::refresh_storage_roots(&state).await?;
+    Ok(state)
+}
+
+async fn apply_storage_path_changes(state: &AppState) -> anyhow::Result<()> {
+    for (path, is_removed) in database::get_storage_path_changes(&state.db).await? {
+        let applied = match (is_removed, state.file_storage.storage_root(&path)) {
+            (false, None) => state.file_storage.add_storage_path(&path).map(|_| ()),
+            (true, Some(root)) => state.file_storage.remove_storage_path(&root),
+            _ => Ok(()),
+        };
+        if let Err(e) = applied {
+            warn!("Could not {} storage path {} as changed at runtime: {:#}", if is_removed { "remove" } else { "add" }, path, e);
+        }
+    }
+    Ok(())
+}
+
+fn print_fsck_report(report: &FsckReport) {
+    for issue in &report.missing {
+        println!("missing {} ({}): {}", issue.path, issue.file_id, issue.detail);
+    }
+    for issue in &report.size_mismatches {
+        println!("size mismatch {} ({}): {}", issue.path, issue.file_id, issue.detail);
+    }
+    for issue in &report.checksum_mismatches {
+        println!("checksum mismatch {} ({}): {}", issue.path, issue.file_id, issue.detail);
+    }
+    for path in &report.orphaned {
+        println!("orphaned {}", path);
+    }
+    if report.truncated {
+        println!("...more issues were found than listed");
+    }
+    println!(
+        "Checked {} files and {} blobs: {} missing, {} size mismatches, {} checksum mismatches, {} orphaned",
+        report.checked_files,
+        report.checked_blobs,
+        report.missing_count,
+        report.size_mismatch_count,
+        report.checksum_mismatch_count,
+        report.orphaned_count
+    );
+    if report.repair {
+        println!(
+            "Repaired: {} copies restored, {} sizes corrected, {} orphaned blobs moved to lost+found",
+            report.restored_copies, report.fixed_sizes, report.set_aside
+        );
+    }
+}
+
+async fn find_user_for_cli(db: &PgPool, username: &str) -> anyhow::Result<User> {
+    database::get_user_by_username(db, username)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("User '{}' not found", username))
+}
+
+async fn purge_user(
+    db: &PgPool,
+    file_storage: &Arc<file_storage::FileStorage>,
+    user: &User,
+) -> anyhow::Result<(usize, i64)> {
+    let files = database::get_files_by_user(db, &user.id).await?;
+    let bytes = files.iter().map(|file| file.file_size).sum();
+
+    let paths: Vec<String> = files.iter().map(|file| file.file_path.clone()).collect();
+    let user_id = user.id;
+    file_storage
+        .blocking(move |storage| {
+            for path in &paths {
+                if let Err(e) = storage.delete_file(path) {
+                    warn!("Failed to delete {} while purging user {}: {}", path, user_id, e);
+                }
+            }
+            Ok(())
+        })
+        .await?;
+
+    let archive_parts = database::get_archive_parts(db, &user.id).await?;
+    archive::remove_files(&archive_parts).await;
+
+    database::delete_user(db, &user.id).await?;
+    info!("Purged user {} ({} files, {} bytes)", user.username, files.len(), bytes);
+
+    Ok((files.len(), bytes))
+}
+
+async fn health_check() -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "status": "ok",
+        "timestamp": chrono::Utc::now()
+    }))
+}
+
+fn feature_flags(config: &Config) -> FeatureFlags {
+    FeatureFlags {
+        chunked_upload: true,
+        trash: true,
+        shares: true,
+        encrypted_shares: true,
+        webdav: config.webdav_enabled,
+        s3_gateway: config.s3_gateway_listen_addr.is_some(),
+        ocr: false,
+        encryption: config.storage_encryption_key.is_some(),
+        compression: config.compression_enabled,
+        s3: config.s3.is_some(),
+        quotas: true,
+        two_factor: false,
+        scheduled_exports: true,
+        archive_import: true,
+        personal_archive: true,
+        video_metadata: config.video_metadata,
+        printing: config.printing_enabled,
+        rclone_compat: config.rclone_compat,
+        case_insensitive_names: config.case_insensitive_names,
+        share_torrents: true,
+        oidc: config.oidc_enabled(),
+        passkeys: true,
+    }
+}
+
+async fn get_capabilities(State(state): State<AppState>) -> Json<Capabilities> {
+    Json(Capabilities {
+        version: build_info::VERSION.to_string(),
+        features: feature_flags(&state.config),
+        limits: CapabilityLimits {
+            max_request_body_size: MAX_REQUEST_BODY_SIZE as u64,
+            min_free_space_buffer: file_storage::MIN_FREE_SPACE_BUFFER,
+            quota_grace_period_days: state.config.quota_grace_period_days,
+            trash_retention_days: state.config.trash_retention_days,
+        },
+    })
+}
+
+async fn login(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<LoginRequest>,
+) -> Result<Json<AuthResponse>, LoginError> {
+    let ip = client_ip(&state.config, &headers, &addr);
+    let keys = [login_limit::user_key(&request.username), login_limit::ip_key(&ip)];
+    if let Some(until) = database::get_login_lockout(&state.db, &keys)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(LoginError::Locked(until));
+    }
+
+    let user = database::get_user_by_username(&state.db, &request.username)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let verified = match &user {
+        Some(user) => auth::verify_password(&request.password, &user.password_hash)
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => false,
+    };
+
+    let user = match user {
+        Some(user) if verified => user,
+        _ => {
+            alerts::record_login_failure();
+            if let Err(e) = login_limit::record_failure(&state.db, &state.config, &request.username, &ip).await {
+                warn!("Failed to record login failure: {}", e);
+            }
+            return Err(StatusCode::UNAUTHORIZED.into());
+        }
+    };
+
+    let _ = database::clear_login_failures(&state.db, &keys[0]).await;
+
+    if user.deactivated_at.is_some() {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    Ok(Json(issue_login_session(&state, user, &headers, &ip).await?))
+}
+
+async fn issue_login_session(
+    state: &AppState,
+    user: User,
+    headers: &HeaderMap,
+    ip: &str,
+) -> Result<AuthResponse, StatusCode> {
+    let expires_at = chrono::Utc::now() + chrono::Duration::minutes(state.config.jwt_expiry_minutes);
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(512).collect::<String>());
+    let session = database::create_session(
+        &state.db,
+        &user.id,
+        user_agent.as_deref(),
+        Some(ip),
+        expires_at,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let token = auth::create_jwt_token(&user.id, &user.username, user.is_admin, &session.id, expires_at, &state.config)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(AuthResponse { token, expires_at, user: user.into() })
+}
+
+async fn oidc_login(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Redirect, StatusCode> {
+    if !state.config.oidc_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let issuer_url = state.config.oidc_issuer_url.as_deref().unwrap_or_default();
+    let client = reqwest::Client::new();
+    let metadata = oidc::discover(&client, issuer_url).await.map_err(|e| {
+        warn!("OIDC discovery for {} failed: {}", issuer_url, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let redirect_uri = state
+        .config
+        .oidc_redirect_url
+        .clone()
+        .unwrap_or_else(|| format!("{}/auth/oidc/callback", request_base_url(&headers)));
+    let login_state = auth::generate_secret();
+    let nonce = auth::generate_secret();
+    let code_verifier = auth::generate_secret();
+
+    database::create_oidc_login(&state.db, &login_state, &nonce, &code_verifier, &redirect_uri)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let url = oidc::authorization_url(&metadata, &state.config, &redirect_uri, &login_state, &nonce, &code_verifier)
+        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+
+    Ok(Redirect::to(&url))
+}
+
+async fn oidc_callback(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> Redirect {
+    let target = &state.config.oidc_post_login_redirect;
+    match complete_oidc_login(&state, &headers, &client_ip(&state.config, &headers, &addr), query).await {
+        Ok(response) => Redirect::to(&format!(
+            "{}#token={}&expires_at={}",
+            target,
+            response.token,
+            response.expires_at.timestamp()
+        )),
+        Err(reason) => Redirect::to(&format!("{}?error={}", target, reason)),
+    }
+}
+
+async fn complete_oidc_login(
+    state: &AppState,
+    headers: &HeaderMap,
+    ip: &str,
+    query: OidcCallbackQuery,
+) -> Result<AuthResponse, &'static str> {
+    if !state.config.oidc_enabled() {
+        return Err("oidc_disabled");
+    }
+    if let Some(error) = query.error {
+        warn!("OIDC provider returned an error: {}", error);
+        return Err("access_denied");
+    }
+
+    let (code, login_state) = match (query.code, query.state) {
+        (Some(code), Some(login_state)) => (code, login_state),
+        _ => return Err("invalid_request"),
+    };
+
+    let (nonce, code_verifier, redirect_uri) = database::take_oidc_login(&state.db, &login_state)
+        .await
+        .map_err(|_| "server_error")?
+        .ok_or("invalid_state")?;
+
+    let issuer_url = state.config.oidc_issuer_url.as_deref().unwrap_or_default();
+    let client = reqwest::Client::new();
+    let claims = async {
+        let metadata = oidc::discover(&client, issuer_url).await?;
+        let id_token = oidc::exchange_code(&client, &metadata, &state.config, &redirect_uri, &code, &code_verifier).await?;
+        oidc::verify_id_token(&client, &metadata, &state.config, &id_token, &nonce).await
+    }
+    .await
+    .map_err(|e| {
+        warn!("OIDC login failed: {}", e);
+        "provider_error"
+    })?;
+
+    let user = match database::get_user_by_identity(&state.db, &claims.iss, &claims.sub)
+        .await
+        .map_err(|_| "server_error")?
+    {
+        Some(user) => user,
+        None => provision_oidc_user(state, &claims).await?,
+    };
+
+    if user.deactivated_at.is_some() {
+        return Err("account_deactivated");
+    }
+
+    issue_login_session(state, user, headers, ip).await.map_err(|_| "server_error")
+}
+
+async fn provision_oidc_user(state: &AppState, claims: &oidc::IdTokenClaims) -> Result<User, &'static str> {
+    let email = claims.email.as_deref().ok_or("missing_email")?;
+
+    if let Some(existing) = database::get_user_by_email(&state.db, email)
+        .await
+        .map_err(|_| "server_error")?
+    {
+        if !state.config.oidc_link_by_email || claims.email_verified != Some(true) {
+            return Err("email_conflict");
+        }
+        database::link_user_identity(&state.db, &existing.id, &claims.iss, &claims.sub)
+            .await
+            .map_err(|_| "server_error")?;
+        info!("Linked OIDC identity {} to existing user {}", claims.sub, existing.username);
+        return Ok(existing);
+    }
+
+    let base = oidc::username_candidate(claims);
+    let mut username = base.clone();
+    let mut suffix = 1;
+    while database::get_user_by_username(&state.db, &username)
+        .await
+        .map_err(|_| "server_error")?
+        .is_some()
+    {
+        suffix += 1;
+        if suffix > 100 {
+            return Err("username_unavailable");
+        }
+        username = format!("{}{}", base, suffix);
+    }
+
+    let password_hash = auth::hash_password(&auth::generate_secret()).map_err(|_| "server_error")?;
+    let user = database::create_user(&state.db, &username, email, &password_hash, false)
+        .await
+        .map_err(|_| "server_error")?;
+    database::link_user_identity(&state.db, &user.id, &claims.iss, &claims.sub)
+        .await
+        .map_err(|_| "server_error")?;
+
+    info!("Provisioned user {} from OIDC subject {}", user.username, claims.sub);
+    Ok(user)
+}
+
+
+
+async fn webauthn_register_start(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<WebauthnChallengeResponse>, StatusCode> {
+    let existing: Vec<Vec<u8>> = database::get_webauthn_credentials(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .map(|credential| credential.credential_id)
+        .collect();
+
+    let challenge = webauthn::new_challenge();
+    let challenge_id = database::create_webauthn_challenge(&state.db, Some(&user.id), "register", &challenge)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(WebauthnChallengeResponse {
+        challenge_id,
+        public_key: webauthn::creation_options(&state.config, &user, &challenge, &existing),
+    }))
+}
+
+async fn webauthn_register_finish(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<WebauthnRegisterFinishRequest>,
+) -> Result<Json<Passkey>, StatusCode> {
+    let (challenge_user, challenge) = database::take_webauthn_challenge(&state.db, &request.challenge_id, "register")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    if challenge_user != Some(user.id) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let response = &request.credential.response;
+    let registered = webauthn::decode(&response.client_data_json)
+        .and_then(|client_data| {
+            let attestation = webauthn::decode(&response.attestation_object)?;
+            webauthn::verify_registration(&state.config, &challenge, &client_data, &attestation)
+        })
+        .map_err(|e| {
+            warn!("Rejected passkey registration for {}: {}", user.username, e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    if webauthn::decode(&request.credential.id).ok().as_deref() != Some(registered.credential_id.as_slice()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if database::get_webauthn_credential(&state.db, &registered.credential_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .is_some()
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let name = request
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .unwrap_or("Passkey")
+        .chars()
+        .take(255)
+        .collect::<String>();
+
+    let credential = database::create_webauthn_credential(
+        &state.db,
+        &user.id,
+        &registered.credential_id,
+        &registered.public_key,
+        registered.sign_count as i64,
+        &name,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("User {} registered passkey {}", user.username, credential.id);
+    Ok(Json(credential.into()))
+}
+
+async fn webauthn_login_start(
+    State(state): State<AppState>,
+    Json(request): Json<WebauthnLoginStartRequest>,
+) -> Result<Json<WebauthnChallengeResponse>, StatusCode> {
+    let user = match request.username.as_deref() {
+        Some(username) => database::get_user_by_username(&state.db, username)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => None,
+    };
+
+    let allow: Vec<Vec<u8>> = match &user {
+        Some(user) => database::get_webauthn_credentials(&state.db, &user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .into_iter()
+            .map(|credential| credential.credential_id)
+            .collect(),
+        None => Vec::new(),
+    };
+    let allow = match (request.username.as_deref(), allow.is_empty()) {
+        (Some(username), true) => webauthn::decoy_credentials(&state.config, username),
+        _ => allow,
+    };
+
+    let challenge = webauthn::new_challenge();
+    let challenge_id = database::create_webauthn_challenge(&state.db, user.as_ref().map(|u| &u.id), "login", &challenge)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(WebauthnChallengeResponse {
+        challenge_id,
+        public_key: webauthn::request_options(&state.config, &challenge, &allow),
+    }))
+}
+
+async fn webauthn_login_finish(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<WebauthnLoginFinishRequest>,
+) -> Result<Json<AuthResponse>, LoginError> {
+    let ip = client_ip(&state.config, &headers, &addr);
+    if let Some(until) = database::get_login_lockout(&state.db, &[login_limit::ip_key(&ip)])
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(LoginError::Locked(until));
+    }
+
+    let (challenge_user, challenge) = database::take_webauthn_challenge(&state.db, &request.challenge_id, "login")
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let credential_id = webauthn::decode(&request.credential.id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let credential = database::get_webauthn_credential(&state.db, &credential_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if challenge_user.is_some_and(|user_id| user_id != credential.user_id) {
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    let response = &request.credential.response;
+    if let Some(user_handle) = &response.user_handle {
+        if webauthn::decode(user_handle).ok().as_deref() != Some(credential.user_id.as_bytes().as_slice()) {
+            return Err(StatusCode::UNAUTHORIZED.into());
+        }
+    }
+
+    let sign_count = webauthn::decode(&response.client_data_json)
+        .and_then(|client_data| {
+            let authenticator_data = webauthn::decode(&response.authenticator_data)?;
+            let signature = webauthn::decode(&response.signature)?;
+            webauthn::verify_assertion(
+                &state.config,
+                &challenge,
+                &credential.public_key,
+                credential.sign_count as u32,
+                &client_data,
+                &authenticator_data,
+                &signature,
+            )
+        })
+        .map_err(|e| {
+            warn!("Rejected passkey login with credential {}: {}", credential.id, e);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    database::update_webauthn_sign_count(&state.db, &credential.id, sign_count as i64)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = database::get_user_by_id(&state.db, &credential.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if user.deactivated_at.is_some() {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    Ok(Json(issue_login_session(&state, user, &headers, &ip).await?))
+}
+
+async fn list_passkeys(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Passkey>>, StatusCode> {
+    let credentials = database::get_webauthn_credentials(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(credentials.into_iter().map(Passkey::from).collect()))
+}
+
+async fn delete_passkey(
+    Path(passkey_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::delete_webauthn_credential(&state.db, &passkey_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_files(
+    Query(query): Query<HashMap<String, String>>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let mut filters = Vec::new();
+    for (param, value) in query {
+        if let Some(key) = param.strip_prefix(CUSTOM_METADATA_FILTER_PREFIX) {
+            if !valid_metadata_key(key) {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            filters.push((key.to_string(), value));
+        }
+    }
+    if filters.len() > MAX_SEARCH_TERMS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let files = if filters.is_empty() {
+        database::get_all_files(&state.db, &user.id).await
+    } else {
+        database::get_files_by_metadata(&state.db, &user.id, &filters).await
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(files))
+}
+
+fn valid_metadata_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().count() <= 64 && !key.chars().any(|c| c.is_control())
+}
+
+async fn update_file_custom_metadata(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<serde_json::Map<String, serde_json::Value>>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let mut set = serde_json::Map::new();
+    let mut remove = Vec::new();
+    for (key, value) in request {
+        if !valid_metadata_key(&key) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        match value {
+            serde_json::Value::Null => remove.push(key),
+            serde_json::Value::String(ref text) if text.chars().count() > MAX_CUSTOM_METADATA_VALUE_LENGTH => {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            serde_json::Value::String(_) | serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                set.insert(key, value);
+            }
+            serde_json::Value::Array(_) | serde_json::Value::Object(_) => return Err(StatusCode::BAD_REQUEST),
+        }
+    }
+
+    let updated = database::update_file_custom_metadata(
+        &state.db,
+        &file.id,
+        &serde_json::Value::Object(set),
+        &remove,
+        MAX_CUSTOM_METADATA_KEYS as i64,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::BAD_REQUEST)?;
+
+    Ok(Json(updated))
+}
+
+async fn search_files(
+    Query(query): Query<FileSearchQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let mut terms: Vec<String> = Vec::new();
+    for term in query.q.split_whitespace().map(str::to_lowercase) {
+        if !terms.contains(&term) {
+            terms.push(term);
+        }
+    }
+    if terms.is_empty() || terms.len() > MAX_SEARCH_TERMS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let files = database::search_files(&state.db, &user.id, &terms, query.limit, query.offset)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(files))
+}
+
+async fn search_file_contents(
+    Query(query): Query<FileSearchQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ContentSearchResult>>, StatusCode> {
+    let q = query.q.trim();
+    if q.is_empty() || q.split_whitespace().count() > MAX_SEARCH_TERMS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let results = database::search_file_contents(&state.db, &user.id, q, query.limit, query.offset)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(results))
+}
+
+async fn set_file_tags(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetFileTagsRequest>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let tags = normalize_tags(&request.tags)?;
+    if tags.len() > MAX_FILE_TAGS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let updated = database::set_file_tags(&state.db, &file.id, &tags)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(updated))
+}
+
+fn normalize_tag(tag: &str) -> Result<String, StatusCode> {
+    let tag = tag.trim().to_lowercase();
+    if tag.is_empty() || tag.chars().count() > 64 || tag.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(tag)
+}
+
+fn normalize_tags(requested: &[String]) -> Result<Vec<String>, StatusCode> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in requested {
+        let tag = normalize_tag(tag)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    Ok(tags)
+}
+
+async fn add_file_tags(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<AddFileTagsRequest>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let tags = normalize_tags(&request.tags)?;
+    if tags.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let updated = database::add_file_tags(&state.db, &file.id, &tags, MAX_FILE_TAGS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    Ok(Json(updated))
+}
+
+async fn remove_file_tag(
+    Path((file_id, tag)): Path<(Uuid, String)>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let tag = normalize_tag(&tag)?;
+    let updated = database::remove_file_tag(&state.db, &file.id, &tag)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(updated))
+}
+
+async fn list_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<TagSummary>>, StatusCode> {
+    let tags = database::get_user_tags(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(tags))
+}
+
+async fn list_files_by_tag(
+    Path(tag): Path<String>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let tag = normalize_tag(&tag)?;
+    let files = database::get_files_by_tag(&state.db, &user.id, &tag)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(files))
+}
+
+async fn star_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    database::star_file(&state.db, &user.id, &file.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unstar_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::unstar_file(&state.db, &user.id, &file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_starred_files(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let starred = database::get_starred_files(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut files = Vec::with_capacity(starred.len());
+    for file in starred {
+        let permissions = access::file_permissions(&state, &user.id, &file)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if permissions.read {
+            files.push(file);
+        }
+    }
+
+    Ok(Json(files))
+}
+
+async fn list_recent_files(
+    Query(query): Query<RecentFilesQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<RecentFile>>, StatusCode> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    let recent = database::get_recent_files(&state.db, &user.id, query.action.map(|kind| kind.as_str()), limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut files = Vec::with_capacity(recent.len());
+    for entry in recent {
+        let permissions = access::file_permissions(&state, &user.id, &entry.file)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if permissions.read {
+            files.push(entry);
+        }
+    }
+
+    Ok(Json(files))
+}
+
+async fn list_file_activity(
+    Query(query): Query<FileActivityQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileActivity>>, StatusCode> {
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+    let activity = database::get_file_activity(&state.db, &user.id, limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(activity))
+}
+
+async fn download_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    if let Some(response) = egress_limit_response(&state, &file, None, file.file_size).await? {
+        return Ok(response);
+    }
+
+    let response = file_download_response(&state, &file).await?;
+    let _ = database::touch_file_access(&state.db, &file.id).await;
+    record_download(&state, &file, &user);
+    record_activity(&state, user.id, &file, FileActivityKind::Download);
+    Ok(response)
+}
+
+async fn egress_limit_response(
+    state: &AppState,
+    file: &FileInfo,
+    share: Option<&SharedLink>,
+    bytes: i64,
+) -> Result<Option<Response<Body>>, StatusCode> {
+    let exceeded = egress::check(state, &file.user_id, share, bytes)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(exceeded.map(IntoResponse::into_response))
+}
+
+fn record_share_egress(state: &AppState, share_id: Uuid, bytes: i64) {
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = database::add_share_egress(&db, &share_id, bytes).await {
+            warn!("Failed to record egress for share {}: {}", share_id, e);
+        }
+    });
+}
+
+fn record_download(state: &AppState, file: &FileInfo, user: &models::User) {
+    if file.user_id == user.id {
+        return;
+    }
+    let db = state.db.clone();
+    let (file_id, user_id) = (file.id, user.id);
+    tokio::spawn(async move {
+        if let Err(e) = database::record_file_download(&db, &file_id, &user_id).await {
+            warn!("Failed to record download of file {} by user {}: {}", file_id, user_id, e);
+        }
+    });
+}
+
+fn record_activity(state: &AppState, user_id: Uuid, file: &FileInfo, kind: FileActivityKind) {
+    let db = state.db.clone();
+    let (file_id, filename) = (file.id, file.original_filename.clone());
+    tokio::spawn(async move {
+        if let Err(e) = database::record_file_activity(&db, &user_id, &file_id, &filename, kind.as_str()).await {
+            warn!("Failed to record {} of file {} by user {}: {}", kind.as_str(), file_id, user_id, e);
+        }
+    });
+}
+
+fn record_share_download(state: &AppState, share_id: Uuid) {
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = database::record_share_download(&db, &share_id).await {
+            warn!("Failed to record download of share {}: {}", share_id, e);
+        }
+    });
+}
+
+fn record_transfer(state: &AppState, user_id: Uuid, uploaded: i64, downloaded: i64) {
+    let db = state.db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = database::add_user_transfer(&db, &user_id, uploaded, downloaded).await {
+            warn!("Failed to record transfer for user {}: {}", user_id, e);
+        }
+    });
+}
+
+async fn file_download_response(state: &AppState, file: &FileInfo) -> Result<Response<Body>, StatusCode> {
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut content_type = file.mime_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
+    let mut sanitize_svg = false;
+
+    if security::is_risky_content(file.mime_type.as_deref(), &file.original_filename) {
+        match state.config.risky_content_policy {
+            RiskyContentPolicy::Allow => {}
+            RiskyContentPolicy::SanitizeSvg if security::is_svg(file.mime_type.as_deref(), &file.original_filename) => {
+                sanitize_svg = true;
+                content_type = security::SVG_CONTENT_TYPE;
+            }
+            _ => content_type = security::SANDBOXED_CONTENT_TYPE,
+        }
+    }
+
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let (body, file_size) = if sanitize_svg {
+        // Sanitizing rewrites the document, so it has to be read whole.
+        let file_data = state.file_storage
+            .blocking(move |storage| storage.get_file_data(&file_path, encoding))
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+        let file_data = security::sanitize_svg(&file_data);
+        let file_size = file_data.len() as i64;
+        (Body::from(file_data), file_size)
+    } else {
+        let data = state.file_storage
+            .blocking(move |storage| storage.open_file(std::path::Path::new(&file_path), encoding))
+            .await
+            .map_err(|_| StatusCode::NOT_FOUND)?;
+        let file_size = file.file_size;
+        let reader = std::io::Read::take(data, file_size as u64);
+        (Body::from_stream(file_storage::stream_reader(reader)), file_size)
+    };
+
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", file.original_filename)
+        )
+        .header(header::CONTENT_LENGTH, file_size)
+        .header(header::LAST_MODIFIED, http_date(&file.client_modified_at.unwrap_or(file.updated_at)))
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if sanitize_svg {
+        response.headers_mut().insert(
+            header::CONTENT_SECURITY_POLICY,
+            HeaderValue::from_static(security::SVG_CONTENT_SECURITY_POLICY),
+        );
+    }
+
+    record_transfer(state, file.user_id, 0, file_size);
+    tiering::retrieve(state, file);
+
+    Ok(response)
+}
+
+fn http_date(timestamp: &chrono::DateTime<chrono::Utc>) -> String {
+    timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+async fn check_name_conflict(
+    state: &AppState,
+    user_id: &Uuid,
+    folder_id: Option<&Uuid>,
+    name: &str,
+    exclude_file_id: Option<&Uuid>,
+) -> Result<(), FileError> {
+    if !state.config.case_insensitive_names {
+        return Ok(());
+    }
+
+    let existing = database::find_case_insensitive_name_conflict(&state.db, user_id, folder_id, name, exclude_file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match existing {
+        Some(existing) => Err(FileError::NameConflict(NameConflict {
+            error: format!(
+                "A file named \"{}\" already exists in this folder (names are case-insensitive)",
+                existing.original_filename
+            ),
+            existing_file_id: existing.id,
+            existing_name: existing.original_filename,
+        })),
+        None => Ok(()),
+    }
+}
+
+async fn rename_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<RenameFileRequest>,
+) -> Result<Json<FileInfo>, FileError> {
+    let filename = request.filename.trim();
+    if filename.is_empty() || filename.contains('/') || filename.contains('\\') {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    check_name_conflict(&state, &file.user_id, file.folder_id.as_ref(), filename, Some(&file.id)).await?;
+
+    let renamed = database::rename_file(&state.db, &file.id, filename)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    record_activity(&state, user.id, &renamed, FileActivityKind::Rename);
+
+    Ok(Json(renamed))
+}
+
+async fn move_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<MoveFileRequest>,
+) -> Result<Json<FileInfo>, FileError> {
+    let mut file = access::authorize_file(&state, &user, &file_id, Permission::Write).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    if access::destination_owner(&state, &user, request.folder_id.as_ref()).await? != file.user_id {
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    if file.folder_id == request.folder_id {
+        return Ok(Json(file));
+    }
+
+    check_name_conflict(&state, &file.user_id, request.folder_id.as_ref(), &file.original_filename, Some(&file.id)).await?;
+
+    database::set_file_folder(&state.db, &file.id, request.folder_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    file.folder_id = request.folder_id;
+
+    Ok(Json(file))
+}
+
+async fn copy_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CopyFileRequest>,
+) -> Result<Json<FileInfo>, FileError> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    let owner_id = access::destination_owner(&state, &user, request.folder_id.as_ref()).await?;
+    check_upload_quota(&state, &owner_id, file.file_size).await?;
+
+    let plan = clipboard::plan_copy(&state, &owner_id, request.folder_id, vec![file], Vec::new())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (_, mut files) = clipboard::execute_copy(&state, &owner_id, &plan, None)
+        .await
+        .map_err(|e| {
+            warn!("Failed to copy file {}: {}", file_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    files.pop().map(Json).ok_or(StatusCode::INTERNAL_SERVER_ERROR.into())
+}
+
+async fn import_from_url(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<RemoteFetchRequest>,
+) -> Result<(StatusCode, Json<RemoteFetch>), FileError> {
+    let url = remote_fetch::parse_url(request.url.trim(), state.config.remote_fetch_allow_private)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let filename = request.filename.as_deref().map(str::trim);
+    if filename.is_some_and(|name| name.is_empty() || name.contains('/') || name.contains('\\')) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    if let Some(folder_id) = &request.folder_id {
+        owned_folder(&state, folder_id, &user).await?;
+    }
+    if let Some(filename) = filename {
+        check_name_conflict(&state, &user.id, request.folder_id.as_ref(), filename, None).await?;
+    }
+    check_upload_quota(&state, &user.id, 0).await?;
+
+    let fetch = database::create_remote_fetch(&state.db, &user.id, url.as_str(), request.folder_id.as_ref(), filename)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tokio::spawn(remote_fetch::run(state.clone(), fetch.clone()));
+
+    Ok((StatusCode::ACCEPTED, Json(fetch)))
+}
+
+async fn list_remote_fetches(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<RemoteFetch>>, StatusCode> {
+    let fetches = database::get_remote_fetches_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(fetches))
+}
+
+async fn get_remote_fetch(
+    Path(fetch_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<RemoteFetch>, StatusCode> {
+    let fetch = database::get_remote_fetch(&state.db, &fetch_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if fetch.user_id != user.id {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(fetch))
+}
+
+async fn get_file_checksum(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FileChecksum>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    let checksum = ensure_file_checksum(&state, &file).await?;
+
+    Ok(Json(FileChecksum {
+        file_id: file.id,
+        algorithm: "sha256".to_string(),
+        checksum,
+    }))
+}
+
+async fn ensure_file_checksum(state: &AppState, file: &FileInfo) -> Result<String, StatusCode> {
+    if let Some(checksum) = &file.checksum {
+        return Ok(checksum.clone());
+    }
+
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let checksum = state.file_storage
+        .blocking(move |storage| storage.compute_sha256(std::path::Path::new(&file_path), encoding))
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    database::set_file_checksum(&state.db, &file.id, &checksum)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(checksum)
+}
+
+fn mtime_from_headers(headers: &HeaderMap) -> Result<Option<chrono::DateTime<chrono::Utc>>, StatusCode> {
+    match headers.get(rclone::MTIME_HEADER) {
+        Some(value) => {
+            let value = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?;
+            rclone::parse_mtime(value).map(Some).ok_or(StatusCode::BAD_REQUEST)
+        }
+        None => Ok(None),
+    }
+}
+
+/// The address a request came from. Forwarding headers are only believed
+/// when the connecting peer is one of `TRUSTED_PROXIES`, since anyone can
+/// send them and the login lockout is keyed on this value.
+fn client_ip(config: &Config, headers: &HeaderMap, addr: &SocketAddr) -> String {
+    if !config.is_trusted_proxy(addr.ip()) {
+        return addr.ip().to_string();
+    }
+    if let Some(ip) = headers
+        .get("x-real-ip")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<std::net::IpAddr>().ok())
+    {
+        return ip.to_string();
+    }
+    let forwarded: Vec<std::net::IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|value| value.trim().parse().ok())
+        .collect();
+    // Walk back from the nearest hop so a client cannot prepend its own entries.
+    forwarded
+        .iter()
+        .rev()
+        .find(|ip| !config.is_trusted_proxy(**ip))
+        .or_else(|| forwarded.first())
+        .copied()
+        .unwrap_or_else(|| addr.ip())
+        .to_string()
+}
+
+fn request_base_url(headers: &HeaderMap) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("localhost");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("http");
+
+    format!("{}://{}", scheme, host)
+}
+
+async fn get_rclone_info(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Json<RcloneInfo> {
+    let base_url = request_base_url(&headers);
+    let remote_url = format!("{}/rclone/tree/", base_url);
+
+    Json(RcloneInfo {
+        compatibility_mode: state.config.rclone_compat,
+        sha256sums_url: format!("{}/rclone/sha256sums", base_url),
+        mtime_header: "X-OC-Mtime".to_string(),
+        hash_type: "sha256".to_string(),
+        config: rclone::config_snippet(&remote_url),
+        notes: vec![
+            "The tree is read-only; upload through /upload/* and send X-OC-Mtime (unix seconds) on initiate or complete to preserve modification times.".to_string(),
+            "Listings are sorted by name and stable between calls; when several files share a name in a folder, the newest one is served.".to_string(),
+            "Verify a sync with: rclone checksum sha256 sums.txt local-drive: after downloading sha256sums_url to sums.txt.".to_string(),
+        ],
+        remote_url,
+    })
+}
+
+async fn rclone_tree_root(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    rclone_tree_response(&state, &user, "").await
+}
+
+async fn rclone_tree(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    rclone_tree_response(&state, &user, &path).await
+}
+
+async fn rclone_tree_response(
+    state: &AppState,
+    user: &models::User,
+    path: &str,
+) -> Result<Response<Body>, StatusCode> {
+    if !state.config.rclone_compat {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let components = rclone::split_path(path);
+    let mut folder_id: Option<Uuid> = None;
+
+    for (index, name) in components.iter().enumerate() {
+        let folder = database::get_folder_by_name(&state.db, &user.id, folder_id.as_ref(), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if let Some(folder) = folder {
+            folder_id = Some(folder.id);
+            continue;
+        }
+
+        let is_last = index == components.len() - 1;
+        if is_last {
+            let file = database::find_file_in_folder(&state.db, &user.id, folder_id.as_ref(), name)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if let Some(file) = file {
+                return rclone_file_response(state, &file).await;
+            }
+        }
+
+        let alias = database::get_alias_by_name(&state.db, &user.id, folder_id.as_ref(), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::NOT_FOUND)?;
+
+        match (alias.target_folder_id, alias.target_file_id) {
+            (Some(target_folder_id), _) => folder_id = Some(target_folder_id),
+            (None, Some(target_file_id)) if is_last => {
+                let file = alias_target_file(state, user, &target_file_id).await?;
+                return rclone_file_response(state, &file).await;
+            }
+            _ => return Err(StatusCode::NOT_FOUND),
+        }
+    }
+
+    let folders = database::get_child_folders(&state.db, &user.id, folder_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let files = database::get_files_in_folder(&state.db, &user.id, folder_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut entries: Vec<rclone::ListingEntry> = folders
+        .into_iter()
+        .map(|folder| rclone::ListingEntry {
+            name: folder.name,
+            is_dir: true,
+            size: 0,
+            modified: folder.updated_at,
+        })
+        .collect();
+
+    for file in files {
+        if entries.iter().any(|entry| entry.name == file.original_filename) {
+            continue;
+        }
+        entries.push(rclone::ListingEntry {
+            modified: file.client_modified_at.unwrap_or(file.updated_at),
+            name: file.original_filename,
+            is_dir: false,
+            size: file.file_size,
+        });
+    }
+
+    let aliases = database::get_aliases_in_folder(&state.db, &user.id, folder_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    for alias in aliases {
+        if entries.iter().any(|entry| entry.name == alias.name) {
+            continue;
+        }
+        let target_file = match alias.target_file_id {
+            Some(file_id) => match alias_target_file(state, user, &file_id).await {
+                Ok(file) => Some(file),
+                Err(StatusCode::NOT_FOUND | StatusCode::FORBIDDEN) => continue,
+                Err(status) => return Err(status),
+            },
+            None => None,
+        };
+        entries.push(match target_file {
+            Some(file) => rclone::ListingEntry {
+                name: alias.name,
+                is_dir: false,
+                size: file.file_size,
+                modified: file.client_modified_at.unwrap_or(file.updated_at),
+            },
+            None => rclone::ListingEntry {
+                name: alias.name,
+                is_dir: true,
+                size: 0,
+                modified: alias.created_at,
+            },
+        });
+    }
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(rclone::render_listing(&components.join("/"), &entries)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn rclone_file_response(state: &AppState, file: &FileInfo) -> Result<Response<Body>, StatusCode> {
+    let checksum = ensure_file_checksum(state, file).await?;
+
+    if let Some(response) = egress_limit_response(state, file, None, file.file_size).await? {
+        return Ok(response);
+    }
+
+    let mut response = file_download_response(state, file).await?;
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", checksum)) {
+        headers.insert(header::ETAG, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&format!("SHA256:{}", checksum)) {
+        headers.insert("oc-checksum", value);
+    }
+
+    Ok(response)
+}
+
+async fn get_rclone_sha256sums(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    if !state.config.rclone_compat {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let folders = database::get_folders_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let files = database::get_all_files(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let folder_paths: std::collections::HashMap<Uuid, String> = folders
+        .iter()
+        .map(|folder| {
+            let mut parts = vec![folder.name.clone()];
+            let mut parent = folder.parent_id;
+            while let Some(parent_id) = parent {
+                match folders.iter().find(|f| f.id == parent_id) {
+                    Some(p) => {
+                        parts.push(p.name.clone());
+                        parent = p.parent_id;
+                    }
+                    None => break,
+                }
+            }
+            parts.reverse();
+            (folder.id, parts.join("/"))
+        })
+        .collect();
+
+    let mut entries: Vec<(String, String)> = Vec::new();
+    for file in &files {
+        let path = match file.folder_id.and_then(|id| folder_paths.get(&id)) {
+            Some(folder_path) => format!("{}/{}", folder_path, file.original_filename),
+            None => file.original_filename.clone(),
+        };
+        if entries.iter().any(|(existing, _)| *existing == path) {
+            continue;
+        }
+        let checksum = ensure_file_checksum(&state, file).await?;
+        entries.push((path, checksum));
+    }
+    entries.sort();
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(rclone::render_sha256sums(&entries)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+enum DavNode {
+    Root,
+    Folder(Folder),
+    File(Box<FileInfo>),
+}
+
+struct DavTarget {
+    components: Vec<String>,
+    parent_id: Option<Uuid>,
+    node: Option<DavNode>,
+}
+
+// Returns None when one of the parent collections does not exist.
+async fn resolve_dav_path(state: &AppState, user: &models::User, components: Vec<String>) -> Result<Option<DavTarget>, StatusCode> {
+    let Some((name, parents)) = components.split_last() else {
+        return Ok(Some(DavTarget { components, parent_id: None, node: Some(DavNode::Root) }));
+    };
+
+    let mut parent_id: Option<Uuid> = None;
+    for parent in parents {
+        match database::get_folder_by_name(&state.db, &user.id, parent_id.as_ref(), parent)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            Some(folder) => parent_id = Some(folder.id),
+            None => return Ok(None),
+        }
+    }
+
+    let folder = database::get_folder_by_name(&state.db, &user.id, parent_id.as_ref(), name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let node = match folder {
+        Some(folder) => Some(DavNode::Folder(folder)),
+        None => database::find_file_in_folder(&state.db, &user.id, parent_id.as_ref(), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map(|file| DavNode::File(Box::new(file))),
+    };
+
+    Ok(Some(DavTarget { components, parent_id, node }))
+}
+
+async fn dav_children(state: &AppState, user_id: &Uuid, folder_id: Option<&Uuid>) -> Result<(Vec<Folder>, Vec<FileInfo>), StatusCode> {
+    let folders = database::get_child_folders(&state.db, user_id, folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut names: HashSet<String> = folders.iter().map(|folder| folder.name.clone()).collect();
+    let files = database::get_files_in_folder(&state.db, user_id, folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .into_iter()
+        .filter(|file| names.insert(file.original_filename.clone()))
+        .collect();
+
+    Ok((folders, files))
+}
+
+fn dav_etag(file: &FileInfo) -> String {
+    file.checksum
+        .clone()
+        .unwrap_or_else(|| format!("{}-{}", file.id.simple(), file.updated_at.timestamp_micros()))
+}
+
+fn dav_folder_resource(components: Vec<String>, folder: &Folder) -> webdav::Resource {
+    webdav::Resource {
+        components,
+        is_dir: true,
+        size: 0,
+        content_type: None,
+        etag: None,
+        created: folder.created_at,
+        modified: folder.updated_at,
+    }
+}
+
+fn dav_file_resource(components: Vec<String>, file: &FileInfo) -> webdav::Resource {
+    webdav::Resource {
+        components,
+        is_dir: false,
+        size: file.file_size,
+        content_type: file.mime_type.clone(),
+        etag: Some(dav_etag(file)),
+        created: file.created_at,
+        modified: file.client_modified_at.unwrap_or(file.updated_at),
+    }
+}
+
+async fn read_dav_body(body: Body) -> Result<String, StatusCode> {
+    let data = http_body_util::Limited::new(body, webdav::MAX_XML_BODY_SIZE)
+        .collect()
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?
+        .to_bytes();
+    String::from_utf8(data.to_vec()).map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+async fn webdav_root(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    method: Method,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    webdav_request(&state, &user, "", method, &headers, body).await
+}
+
+async fn webdav_path(
+    Path(path): Path<String>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    method: Method,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    webdav_request(&state, &user, &path, method, &headers, body).await
+}
+
+async fn webdav_request(
+    state: &AppState,
+    user: &models::User,
+    path: &str,
+    method: Method,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    let components = rclone::split_path(path);
+    if !components.iter().all(|name| webdav::valid_name(name)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match method.as_str() {
+        "OPTIONS" => Response::builder()
+            .status(StatusCode::OK)
+            .header("dav", webdav::COMPLIANCE_CLASSES)
+            .header("ms-author-via", "DAV")
+            .header(header::ALLOW, webdav::ALLOWED_METHODS)
+            .header(header::CONTENT_LENGTH, 0)
+            .body(Body::empty())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+        "GET" | "HEAD" => webdav_get(state, user, components, method == Method::HEAD).await,
+        "PUT" => webdav_put(state, user, components, headers, body).await,
+        "DELETE" => webdav_delete(state, user, components, headers).await,
+        "PROPFIND" => webdav_propfind(state, user, components, headers).await,
+        "PROPPATCH" => webdav_proppatch(state, user, components, headers, body).await,
+        "MKCOL" => webdav_mkcol(state, user, components, headers).await,
+        "MOVE" => webdav_move(state, user, components, headers).await,
+        "LOCK" => webdav_lock(state, user, components, headers, body).await,
+        "UNLOCK" => webdav_unlock(user, components, headers),
+        _ => Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(header::ALLOW, webdav::ALLOWED_METHODS)
+            .body(Body::empty())
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn webdav_get(state: &AppState, user: &models::User, components: Vec<String>, head: bool) -> Result<Response<Body>, StatusCode> {
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let folder_id = match target.node.ok_or(StatusCode::NOT_FOUND)? {
+        DavNode::File(file) if head => {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header(header::CONTENT_TYPE, file.mime_type.as_deref().unwrap_or("application/octet-stream"))
+                .header(header::CONTENT_LENGTH, file.file_size)
+                .header(header::LAST_MODIFIED, http_date(&file.client_modified_at.unwrap_or(file.updated_at)))
+                .header(header::ETAG, format!("\"{}\"", dav_etag(&file)))
+                .body(Body::empty())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        DavNode::File(file) => {
+            if let Some(response) = egress_limit_response(state, &file, None, file.file_size).await? {
+                return Ok(response);
+            }
+            let mut response = file_download_response(state, &file).await?;
+            if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", dav_etag(&file))) {
+                response.headers_mut().insert(header::ETAG, value);
+            }
+            let _ = database::touch_file_access(&state.db, &file.id).await;
+            record_activity(state, user.id, &file, FileActivityKind::Download);
+            return Ok(response);
+        }
+        DavNode::Folder(folder) => Some(folder.id),
+        DavNode::Root => None,
+    };
+
+    let (folders, files) = dav_children(state, &user.id, folder_id.as_ref()).await?;
+    let mut entries: Vec<rclone::ListingEntry> = folders
+        .into_iter()
+        .map(|folder| rclone::ListingEntry { name: folder.name, is_dir: true, size: 0, modified: folder.updated_at })
+        .collect();
+    entries.extend(files.into_iter().map(|file| rclone::ListingEntry {
+        modified: file.client_modified_at.unwrap_or(file.updated_at),
+        name: file.original_filename,
+        is_dir: false,
+        size: file.file_size,
+    }));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .body(Body::from(rclone::render_listing(&target.components.join("/"), &entries)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn webdav_propfind(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let depth = webdav::depth(headers, webdav::Depth::Infinity).ok_or(StatusCode::BAD_REQUEST)?;
+    if depth == webdav::Depth::Infinity {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let mut resources = Vec::new();
+    let folder_id = match target.node.ok_or(StatusCode::NOT_FOUND)? {
+        DavNode::File(file) => {
+            resources.push(dav_file_resource(target.components.clone(), &file));
+            None
+        }
+        DavNode::Folder(folder) => {
+            resources.push(dav_folder_resource(target.components.clone(), &folder));
+            Some(Some(folder.id))
+        }
+        DavNode::Root => {
+            resources.push(webdav::Resource {
+                components: Vec::new(),
+                is_dir: true,
+                size: 0,
+                content_type: None,
+                etag: None,
+                created: user.created_at,
+                modified: user.created_at,
+            });
+            Some(None)
+        }
+    };
+
+    if let (Some(folder_id), webdav::Depth::One) = (folder_id, depth) {
+        let (folders, files) = dav_children(state, &user.id, folder_id.as_ref()).await?;
+        let child_path = |name: &str| {
+            let mut path = target.components.clone();
+            path.push(name.to_string());
+            path
+        };
+        resources.extend(folders.iter().map(|folder| dav_folder_resource(child_path(&folder.name), folder)));
+        resources.extend(files.iter().map(|file| dav_file_resource(child_path(&file.original_filename), file)));
+    }
+
+    Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(header::CONTENT_TYPE, webdav::XML_CONTENT_TYPE)
+        .body(Body::from(webdav::multistatus(&resources)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn webdav_proppatch(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    let update = webdav::parse_property_update(&read_dav_body(body).await?);
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let node = target.node.ok_or(StatusCode::NOT_FOUND)?;
+    if !webdav::may_modify(user.id, &target.components, false, headers) {
+        return Err(StatusCode::LOCKED);
+    }
+
+    if let (DavNode::File(file), Some(modified)) = (&node, update.modified) {
+        database::set_file_client_modified_at(&state.db, &file.id, Some(modified))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    let is_dir = !matches!(node, DavNode::File(_));
+    Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(header::CONTENT_TYPE, webdav::XML_CONTENT_TYPE)
+        .body(Body::from(webdav::proppatch_multistatus(&target.components, is_dir, &update.properties)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+// Streams the body into a temp file and records it like a finished chunked upload; a file
+// that already had this name is moved to the trash.
+async fn store_dav_file(
+    state: &AppState,
+    user: &models::User,
+    target: &DavTarget,
+    size_hint: u64,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<(FileInfo, bool), StatusCode> {
+    let name = target.components.last().ok_or(StatusCode::METHOD_NOT_ALLOWED)?.clone();
+    let existing = match &target.node {
+        Some(DavNode::File(file)) => Some(file),
+        Some(_) => return Err(StatusCode::METHOD_NOT_ALLOWED),
+        None => None,
+    };
+
+    check_name_conflict(state, &user.id, target.parent_id.as_ref(), &name, existing.as_ref().map(|file| &file.id))
+        .await
+        .map_err(FileError::status)?;
+    check_upload_quota(state, &user.id, size_hint as i64).await?;
+
+    let (user_id, upload_id) = (user.id, Uuid::new_v4());
+    let (temp_path, disk_path) = state.file_storage
+        .blocking(move |storage| storage.create_temp_file(&user_id, &upload_id, size_hint))
+        .await
+        .map_err(|_| StatusCode::INSUFFICIENT_STORAGE)?;
+
+    let written = s3_gateway::write_stream(&temp_path, body, None)
+        .await
+        .map(|(written, _)| written)
+        .map_err(|e| e.status);
+    let written = match written {
+        Ok(written) if written as u64 == size_hint => Ok(written),
+        Ok(written) => check_upload_quota(state, &user.id, written).await.map(|_| written),
+        Err(status) => Err(status),
+    };
+    if let Err(status) = written {
+        let temp = temp_path.clone();
+        let _ = state.file_storage.blocking(move |storage| storage.cleanup_temp_file(&temp)).await;
+        return Err(status);
+    }
+
+    let client_modified_at = mtime_from_headers(headers)?;
+    let file_info = record_streamed_upload(
+        state,
+        user.id,
+        target.parent_id,
+        &name,
+        (temp_path, disk_path),
+        client_modified_at,
+        existing.map(|file| &**file),
+    )
+    .await?;
+
+    Ok((file_info, existing.is_some()))
+}
+
+// Turns a fully written temp file into a stored file in `folder_id`, moving the file it
+// replaces to the trash.
+async fn record_streamed_upload(
+    state: &AppState,
+    user_id: Uuid,
+    folder_id: Option<Uuid>,
+    name: &str,
+    (temp_path, disk_path): (std::path::PathBuf, std::path::PathBuf),
+    client_modified_at: Option<chrono::DateTime<chrono::Utc>>,
+    replaces: Option<&FileInfo>,
+) -> Result<FileInfo, StatusCode> {
+    let filename = name.to_string();
+    let stored = state.file_storage
+        .blocking(move |storage| storage.finalize_chunked_upload(&temp_path, &user_id, &filename, &disk_path))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut file_info = match database::create_file_record(
+        &state.db,
+        &user_id,
+        &stored.filename,
+        name,
+        &stored.file_path,
+        &stored.disk_path,
+        stored.file_size,
+        stored.stored_size,
+        stored.encoding,
+        stored.mime_type.as_deref(),
+        Some(&stored.checksum),
+    )
+    .await
+    {
+        Ok(file_info) => file_info,
+        Err(_) => {
+            let file_path = stored.file_path.clone();
+            let _ = state.file_storage.blocking(move |storage| storage.delete_file(&file_path)).await;
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    if folder_id.is_some() {
+        database::set_file_folder(&state.db, &file_info.id, folder_id.as_ref())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        file_info.folder_id = folder_id;
+    }
+
+    if client_modified_at.is_some() {
+        database::set_file_client_modified_at(&state.db, &file_info.id, client_modified_at)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        file_info.client_modified_at = client_modified_at;
+    }
+
+    if let Some(replaced) = replaces {
+        database::soft_delete_file(&state.db, &replaced.id, &user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        trash::enforce_limit_quietly(state, &user_id).await;
+    }
+
+    record_transfer(state, user_id, stored.file_size, 0);
+    record_activity(state, user_id, &file_info, FileActivityKind::Upload);
+
+    Ok(file_info)
+}
+
+async fn webdav_put(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::CONFLICT)?;
+    if !webdav::may_modify(user.id, &target.components, false, headers) {
+        return Err(StatusCode::LOCKED);
+    }
+
+    let size_hint = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    let (file, replaced) = store_dav_file(state, user, &target, size_hint, headers, body).await?;
+
+    Response::builder()
+        .status(if replaced { StatusCode::NO_CONTENT } else { StatusCode::CREATED })
+        .header(header::ETAG, format!("\"{}\"", dav_etag(&file)))
+        .body(Body::empty())
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn trash_dav_folder(state: &AppState, user: &models::User, folder: &Folder) -> Result<(), StatusCode> {
+    let trashed = database::trash_folder_tree(&state.db, &user.id, &folder.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for file in &trashed {
+        record_activity(state, user.id, file, FileActivityKind::Delete);
+    }
+    database::delete_folder(&state.db, &folder.id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(())
+}
+
+async fn trash_dav_node(state: &AppState, user: &models::User, node: &DavNode) -> Result<(), StatusCode> {
+    match node {
+        DavNode::Root => return Err(StatusCode::FORBIDDEN),
+        DavNode::Folder(folder) => trash_dav_folder(state, user, folder).await?,
+        DavNode::File(file) => {
+            database::soft_delete_file(&state.db, &file.id, &user.id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            record_activity(state, user.id, file, FileActivityKind::Delete);
+        }
+    }
+    trash::enforce_limit_quietly(state, &user.id).await;
+
+    Ok(())
+}
+
+async fn webdav_delete(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let node = target.node.ok_or(StatusCode::NOT_FOUND)?;
+    if !webdav::may_modify(user.id, &target.components, true, headers) {
+        return Err(StatusCode::LOCKED);
+    }
+
+    trash_dav_node(state, user, &node).await?;
+    webdav::discard(user.id, &target.components);
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn webdav_mkcol(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let has_body = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim() != "0");
+    if has_body {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::CONFLICT)?;
+    if target.node.is_some() {
+        return Err(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    if !webdav::may_modify(user.id, &target.components, false, headers) {
+        return Err(StatusCode::LOCKED);
+    }
+
+    let name = target.components.last().ok_or(StatusCode::METHOD_NOT_ALLOWED)?;
+    database::get_or_create_folder(&state.db, &user.id, target.parent_id.as_ref(), name)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::CREATED.into_response())
+}
+
+async fn webdav_move(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let destination = webdav::destination(headers).ok_or(StatusCode::BAD_REQUEST)?;
+    if destination.is_empty() || destination.starts_with(&components) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let source = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::NOT_FOUND)?;
+    let node = source.node.ok_or(StatusCode::NOT_FOUND)?;
+    if matches!(node, DavNode::Root) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !webdav::may_modify(user.id, &source.components, true, headers)
+        || !webdav::may_modify(user.id, &destination, true, headers)
+    {
+        return Err(StatusCode::LOCKED);
+    }
+
+    let target = resolve_dav_path(state, user, destination).await?.ok_or(StatusCode::CONFLICT)?;
+    let name = target.components.last().ok_or(StatusCode::FORBIDDEN)?;
+    let replaced = match &target.node {
+        Some(_) if !webdav::overwrite(headers) => return Err(StatusCode::PRECONDITION_FAILED),
+        Some(existing) => {
+            trash_dav_node(state, user, existing).await?;
+            webdav::discard(user.id, &target.components);
+            true
+        }
+        None => false,
+    };
+
+    match node {
+        DavNode::File(file) => {
+            check_name_conflict(state, &user.id, target.parent_id.as_ref(), name, Some(&file.id))
+                .await
+                .map_err(FileError::status)?;
+            if file.folder_id != target.parent_id {
+                database::set_file_folder(&state.db, &file.id, target.parent_id.as_ref())
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            }
+            if file.original_filename != *name {
+                let renamed = database::rename_file(&state.db, &file.id, name)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                    .ok_or(StatusCode::NOT_FOUND)?;
+                record_activity(state, user.id, &renamed, FileActivityKind::Rename);
+            }
+        }
+        DavNode::Folder(folder) => {
+            database::move_folder(&state.db, &folder.id, target.parent_id.as_ref(), name)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+        }
+        DavNode::Root => return Err(StatusCode::FORBIDDEN),
+    }
+    webdav::discard(user.id, &source.components);
+
+    Ok(if replaced { StatusCode::NO_CONTENT } else { StatusCode::CREATED }.into_response())
+}
+
+async fn webdav_lock(
+    state: &AppState,
+    user: &models::User,
+    components: Vec<String>,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, StatusCode> {
+    let body = read_dav_body(body).await?;
+    let timeout = webdav::lock_timeout(headers);
+
+    if body.trim().is_empty() {
+        let lock = webdav::refresh(user.id, &components, &webdav::submitted_tokens(headers), timeout)
+            .ok_or(StatusCode::PRECONDITION_FAILED)?;
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, webdav::XML_CONTENT_TYPE)
+            .body(Body::from(webdav::lock_discovery(&lock)))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let deep = match webdav::depth(headers, webdav::Depth::Infinity) {
+        Some(webdav::Depth::Zero) => false,
+        Some(webdav::Depth::Infinity) => true,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    let target = resolve_dav_path(state, user, components).await?.ok_or(StatusCode::CONFLICT)?;
+    let lock = webdav::acquire(user.id, &target.components, deep, webdav::lock_owner(&body), timeout)
+        .map_err(|refused| match refused {
+            webdav::LockRefused::Conflict => StatusCode::LOCKED,
+            webdav::LockRefused::TooManyLocks => StatusCode::INSUFFICIENT_STORAGE,
+        })?;
+
+    // Locking an unmapped URL creates an empty file, which is how Finder and Office start a save.
+    let created = target.node.is_none();
+    if created {
+        if let Err(status) = store_dav_file(state, user, &target, 0, headers, Body::empty()).await {
+            webdav::release(user.id, &target.components, &lock.token);
+            return Err(status);
+        }
+    }
+
+    Response::builder()
+        .status(if created { StatusCode::CREATED } else { StatusCode::OK })
+        .header(header::CONTENT_TYPE, webdav::XML_CONTENT_TYPE)
+        .header("lock-token", format!("<{}>", lock.token))
+        .body(Body::from(webdav::lock_discovery(&lock)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn webdav_unlock(user: &models::User, components: Vec<String>, headers: &HeaderMap) -> Result<Response<Body>, StatusCode> {
+    let token = webdav::submitted_tokens(headers).into_iter().next().ok_or(StatusCode::BAD_REQUEST)?;
+    if !webdav::release(user.id, &components, &token) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+type S3Response = Result<Response<Body>, s3_gateway::S3Error>;
+
+fn s3_xml(xml: String) -> S3Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, s3_gateway::XML_CONTENT_TYPE)
+        .body(Body::from(xml))
+        .map_err(|_| s3_gateway::S3Error::internal())
+}
+
+fn s3_etag_response(etag: &str) -> S3Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::ETAG, format!("\"{}\"", etag))
+        .body(Body::empty())
+        .map_err(|_| s3_gateway::S3Error::internal())
+}
+
+async fn s3_bucket(state: &AppState, user_id: &Uuid, bucket: &str) -> Result<Folder, s3_gateway::S3Error> {
+    database::get_folder_by_name(&state.db, user_id, None, bucket)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?
+        .ok_or_else(s3_gateway::S3Error::no_such_bucket)
+}
+
+// Follows folder names down from `folder_id`; None when one of them does not exist.
+async fn s3_descend(state: &AppState, user_id: &Uuid, folder_id: Uuid, names: &[String]) -> Result<Option<Uuid>, StatusCode> {
+    let mut folder_id = folder_id;
+    for name in names {
+        match database::get_folder_by_name(&state.db, user_id, Some(&folder_id), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        {
+            Some(folder) => folder_id = folder.id,
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(folder_id))
+}
+
+async fn s3_ensure_folders(state: &AppState, user_id: &Uuid, folder_id: Uuid, names: &[String]) -> Result<Uuid, StatusCode> {
+    let mut folder_id = folder_id;
+    for name in names {
+        let (folder, _) = database::get_or_create_folder(&state.db, user_id, Some(&folder_id), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        folder_id = folder.id;
+    }
+    Ok(folder_id)
+}
+
+// Unlike WebDAV paths, an object key and a folder of the same name can coexist, so the
+// last component is always looked up as a file.
+async fn s3_find_object(state: &AppState, user_id: &Uuid, bucket: &Folder, components: &[String]) -> Result<Option<FileInfo>, StatusCode> {
+    let Some((name, parents)) = components.split_last() else {
+        return Ok(None);
+    };
+    match s3_descend(state, user_id, bucket.id, parents).await? {
+        Some(folder_id) => database::find_file_in_folder(&state.db, user_id, Some(&folder_id), name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
+        None => Ok(None),
+    }
+}
+
+fn s3_object_key(key: &str) -> Result<Vec<String>, s3_gateway::S3Error> {
+    match s3_gateway::key_components(key) {
+        Some((components, false)) => Ok(components),
+        _ => Err(s3_gateway::S3Error::invalid_argument("Object keys must be valid file paths")),
+    }
+}
+
+async fn s3_request(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Extension(signature): Extension<s3_gateway::RequestSignature>,
+    method: Method,
+    uri: axum::http::Uri,
+    headers: HeaderMap,
+    body: Body,
+) -> S3Response {
+    let path = remote_fetch::percent_decode(uri.path());
+    let path = path.trim_start_matches('/');
+    let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+    let query = s3_gateway::parse_query(uri.query().unwrap_or_default());
+    let has = |name: &str| s3_gateway::query_value(&query, name).is_some();
+
+    if bucket.is_empty() {
+        return match method {
+            Method::GET => s3_list_buckets(&state, &user).await,
+            _ => Err(s3_gateway::S3Error::method_not_allowed()),
+        };
+    }
+    if !webdav::valid_name(bucket) {
+        return Err(s3_gateway::S3Error::new(StatusCode::BAD_REQUEST, "InvalidBucketName", "The specified bucket is not valid"));
+    }
+
+    if key.is_empty() {
+        return match method.as_str() {
+            "GET" if has("location") => {
+                s3_bucket(&state, &user.id, bucket).await?;
+                s3_xml(s3_gateway::location_xml())
+            }
+            "GET" if has("uploads") => Err(s3_gateway::S3Error::not_implemented("ListMultipartUploads")),
+            "GET" => s3_list_objects(&state, &user, bucket, &query).await,
+            "HEAD" => s3_bucket(&state, &user.id, bucket).await.map(|_| StatusCode::OK.into_response()),
+            "PUT" => s3_create_bucket(&state, &user, bucket).await,
+            "DELETE" => s3_delete_bucket(&state, &user, bucket).await,
+            "POST" if has("delete") => s3_delete_objects(&state, &user, bucket, body, &signature).await,
+            _ => Err(s3_gateway::S3Error::method_not_allowed()),
+        };
+    }
+
+    match method.as_str() {
+        "GET" | "HEAD" if has("uploadId") => Err(s3_gateway::S3Error::not_implemented("ListParts")),
+        "GET" | "HEAD" => s3_get_object(&state, &user, bucket, key, &headers, method == Method::HEAD).await,
+        "PUT" if headers.contains_key("x-amz-copy-source") => Err(s3_gateway::S3Error::not_implemented("CopyObject")),
+        "PUT" if has("uploadId") => s3_upload_part(&state, &user, (bucket, key), &query, &headers, body, &signature).await,
+        "PUT" => s3_put_object(&state, &user, bucket, key, &headers, body, &signature).await,
+        "POST" if has("uploads") => s3_create_multipart_upload(&state, &user, bucket, key).await,
+        "POST" if has("uploadId") => s3_complete_multipart_upload(&state, &user, (bucket, key), &query, body, &signature).await,
+        "DELETE" if has("uploadId") => s3_abort_multipart_upload(&state, &user, (bucket, key), &query).await,
+        "DELETE" => {
+            let root = s3_bucket(&state, &user.id, bucket).await?;
+            s3_delete_object(&state, &user, &root, key).await?;
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        _ => Err(s3_gateway::S3Error::method_not_allowed()),
+    }
+}
+
+async fn s3_list_buckets(state: &AppState, user: &models::User) -> S3Response {
+    let buckets: Vec<(String, chrono::DateTime<chrono::Utc>)> = database::get_child_folders(&state.db, &user.id, None)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?
+        .into_iter()
+        .map(|folder| (folder.name, folder.created_at))
+        .collect();
+
+    s3_xml(s3_gateway::list_buckets_xml(&user.id.to_string(), &user.username, &buckets))
+}
+
+async fn s3_create_bucket(state: &AppState, user: &models::User, bucket: &str) -> S3Response {
+    let (_, created) = database::get_or_create_folder(&state.db, &user.id, None, bucket)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+    if !created {
+        return Err(s3_gateway::S3Error::new(
+            StatusCode::CONFLICT,
+            "BucketAlreadyOwnedByYou",
+            "Your previous request to create the named bucket succeeded and you already own it",
+        ));
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::LOCATION, format!("/{}", bucket))
+        .body(Body::empty())
+        .map_err(|_| s3_gateway::S3Error::internal())
+}
+
+async fn s3_delete_bucket(state: &AppState, user: &models::User, bucket: &str) -> S3Response {
+    let root = s3_bucket(state, &user.id, bucket).await?;
+    let (folders, files) = dav_children(state, &user.id, Some(&root.id)).await?;
+    if !folders.is_empty() || !files.is_empty() {
+        return Err(s3_gateway::S3Error::new(StatusCode::CONFLICT, "BucketNotEmpty", "The bucket you tried to delete is not empty"));
+    }
+
+    database::delete_folder(&state.db, &root.id, &user.id)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn s3_list_objects(state: &AppState, user: &models::User, bucket: &str, query: &[(String, String)]) -> S3Response {
+    let params = s3_gateway::ListParams::from_query(query)?;
+    let root = s3_bucket(state, &user.id, bucket).await?;
+
+    let start = params.prefix_folder();
+    let mut entries = Vec::new();
+    if let Some(folder_id) = s3_descend(state, &user.id, root.id, &start).await? {
+        let mut pending = vec![(folder_id, start.iter().map(|name| format!("{}/", name)).collect::<String>())];
+        while let Some((folder_id, key_prefix)) = pending.pop() {
+            let folders = database::get_child_folders(&state.db, &user.id, Some(&folder_id))
+                .await
+                .map_err(|_| s3_gateway::S3Error::internal())?;
+            for folder in folders {
+                let key = format!("{}{}/", key_prefix, folder.name);
+                if !key.starts_with(&params.prefix) && !params.prefix.starts_with(&key) {
+                    continue;
+                }
+                if params.recursive() {
+                    pending.push((folder.id, key));
+                } else {
+                    entries.push(s3_gateway::ListEntry::Prefix(key));
+                }
+            }
+
+            let files = database::get_files_in_folder(&state.db, &user.id, Some(&folder_id))
+                .await
+                .map_err(|_| s3_gateway::S3Error::internal())?;
+            entries.extend(files.into_iter().map(|file| {
+                s3_gateway::ListEntry::Object(s3_gateway::ObjectEntry {
+                    key: format!("{}{}", key_prefix, file.original_filename),
+                    size: file.file_size,
+                    etag: dav_etag(&file),
+                    modified: file.client_modified_at.unwrap_or(file.updated_at),
+                })
+            }));
+        }
+    }
+
+    let listing = s3_gateway::paginate(entries, &params)?;
+    s3_xml(s3_gateway::list_objects_xml(bucket, (&user.id.to_string(), &user.username), &params, &listing))
+}
+
+async fn s3_get_object(
+    state: &AppState,
+    user: &models::User,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    head: bool,
+) -> S3Response {
+    let (components, is_dir) = s3_gateway::key_components(key).ok_or_else(s3_gateway::S3Error::no_such_key)?;
+    let root = s3_bucket(state, &user.id, bucket).await?;
+    if is_dir {
+        s3_descend(state, &user.id, root.id, &components).await?.ok_or_else(s3_gateway::S3Error::no_such_key)?;
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/x-directory")
+            .header(header::CONTENT_LENGTH, 0)
+            .header(header::ETAG, format!("\"{}\"", sigv4::EMPTY_PAYLOAD_SHA256))
+            .body(Body::empty())
+            .map_err(|_| s3_gateway::S3Error::internal());
+    }
+
+    let file = s3_find_object(state, &user.id, &root, &components)
+        .await?
+        .ok_or_else(s3_gateway::S3Error::no_such_key)?;
+    if file.is_quarantined {
+        return Err(s3_gateway::S3Error::access_denied("The object is quarantined"));
+    }
+
+    let size = file.file_size as u64;
+    let range = match headers.get(header::RANGE).and_then(|value| value.to_str().ok()) {
+        Some(range) => Some(parse_byte_range(range, size).ok_or_else(|| {
+            s3_gateway::S3Error::new(StatusCode::RANGE_NOT_SATISFIABLE, "InvalidRange", "The requested range is not satisfiable")
+        })?),
+        None => None,
+    };
+    let (start, length) = range.map(|(start, end)| (start, end - start + 1)).unwrap_or((0, size));
+
+    let mut response = Response::builder()
+        .status(if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
+        .header(header::CONTENT_TYPE, file.mime_type.as_deref().unwrap_or("application/octet-stream"))
+        .header(header::CONTENT_LENGTH, length)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, format!("\"{}\"", dav_etag(&file)))
+        .header(header::LAST_MODIFIED, http_date(&file.updated_at));
+    if let Some((start, end)) = range {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size));
+    }
+    if let Some(modified) = file.client_modified_at {
+        response = response.header(
+            s3_gateway::MTIME_METADATA,
+            format!("{}.{:09}", modified.timestamp(), modified.timestamp_subsec_nanos()),
+        );
+    }
+    if head {
+        return response.body(Body::empty()).map_err(|_| s3_gateway::S3Error::internal());
+    }
+
+    if let Some(response) = egress_limit_response(state, &file, None, length as i64).await? {
+        return Ok(response);
+    }
+
+    let file_storage = state.file_storage.clone();
+    let (path, encoding) = (std::path::PathBuf::from(&file.file_path), file.encoding());
+    let data = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let mut data = file_storage.open_file(&path, encoding)?;
+        data.seek(std::io::SeekFrom::Start(start))?;
+        Ok(data)
+    })
+    .await
+    .map_err(|_| s3_gateway::S3Error::internal())?
+    .map_err(|_| s3_gateway::S3Error::no_such_key())?;
+
+    record_transfer(state, user.id, 0, length as i64);
+    if range.is_none() {
+        let _ = database::touch_file_access(&state.db, &file.id).await;
+        record_activity(state, user.id, &file, FileActivityKind::Download);
+        tiering::retrieve(state, &file);
+    }
+
+    response
+        .body(Body::from_stream(file_storage::stream_reader(std::io::Read::take(data, length))))
+        .map_err(|_| s3_gateway::S3Error::internal())
+}
+
+// Streams a payload into a fresh temp file and returns it with its size and SHA-256; the
+// quota is checked again when the payload turns out larger than announced.
+async fn s3_receive_body(
+    state: &AppState,
+    user_id: Uuid,
+    headers: &HeaderMap,
+    body: Body,
+    signature: &s3_gateway::RequestSignature,
+) -> Result<((std::path::PathBuf, std::path::PathBuf), i64, String), s3_gateway::S3Error> {
+    let size_hint = s3_gateway::declared_size(headers);
+    check_upload_quota(state, &user_id, size_hint as i64).await?;
+
+    let upload_id = Uuid::new_v4();
+    let (temp_path, disk_path) = state.file_storage
+        .blocking(move |storage| storage.create_temp_file(&user_id, &upload_id, size_hint))
+        .await
+        .map_err(|_| s3_gateway::S3Error::from(StatusCode::INSUFFICIENT_STORAGE))?;
+
+    let written = match s3_gateway::write_body(&temp_path, body, signature).await {
+        Ok((written, sha256)) if written as u64 == size_hint => Ok((written, sha256)),
+        Ok((written, sha256)) => check_upload_quota(state, &user_id, written)
+            .await
+            .map(|_| (written, sha256))
+            .map_err(s3_gateway::S3Error::from),
+        Err(e) => Err(e),
+    };
+    match written {
+        Ok((written, sha256)) => Ok(((temp_path, disk_path), written, sha256)),
+        Err(e) => {
+            let _ = state.file_storage.blocking(move |storage| storage.cleanup_temp_file(&temp_path)).await;
+            Err(e)
+        }
+    }
+}
+
+async fn s3_cleanup_parts(state: &AppState, part_paths: Vec<String>) {
+    let _ = state.file_storage
+        .blocking(move |storage| {
+            for part_path in &part_paths {
+                if let Err(e) = storage.cleanup_temp_file(std::path::Path::new(part_path)) {
+                    warn!("Failed to remove multipart upload part {}: {}", part_path, e);
+                }
+            }
+            Ok(())
+        })
+        .await;
+}
+
+async fn s3_put_object(
+    state: &AppState,
+    user: &models::User,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    body: Body,
+    signature: &s3_gateway::RequestSignature,
+) -> S3Response {
+    let (components, is_dir) = s3_gateway::key_components(key)
+        .ok_or_else(|| s3_gateway::S3Error::invalid_argument("Object keys must be valid file paths"))?;
+    let root = s3_bucket(state, &user.id, bucket).await?;
+    if is_dir {
+        s3_ensure_folders(state, &user.id, root.id, &components).await?;
+        return s3_etag_response(sigv4::EMPTY_PAYLOAD_SHA256);
+    }
+
+    let (name, parents) = components.split_last().ok_or_else(s3_gateway::S3Error::no_such_key)?;
+    let folder_id = s3_descend(state, &user.id, root.id, parents).await?;
+    let existing = match folder_id {
+        Some(folder_id) => database::find_file_in_folder(&state.db, &user.id, Some(&folder_id), name)
+            .await
+            .map_err(|_| s3_gateway::S3Error::internal())?,
+        None => None,
+    };
+    if let Some(folder_id) = folder_id {
+        check_name_conflict(state, &user.id, Some(&folder_id), name, existing.as_ref().map(|file| &file.id))
+            .await
+            .map_err(FileError::status)?;
+    }
+
+    let (reserved, _, _) = s3_receive_body(state, user.id, headers, body, signature).await?;
+    let folder_id = s3_ensure_folders(state, &user.id, root.id, parents).await?;
+    let client_modified_at = headers
+        .get(s3_gateway::MTIME_METADATA)
+        .and_then(|value| value.to_str().ok())
+        .and_then(rclone::parse_mtime);
+    let file = record_streamed_upload(state, user.id, Some(folder_id), name, reserved, client_modified_at, existing.as_ref()).await?;
+
+    s3_etag_response(&dav_etag(&file))
+}
+
+async fn s3_delete_object(state: &AppState, user: &models::User, root: &Folder, key: &str) -> Result<(), s3_gateway::S3Error> {
+    let (components, is_dir) = s3_gateway::key_components(key)
+        .ok_or_else(|| s3_gateway::S3Error::invalid_argument("Object keys must be valid file paths"))?;
+
+    // A folder marker only goes away with the folder, and only once the folder is empty.
+    if is_dir {
+        if let Some(folder_id) = s3_descend(state, &user.id, root.id, &components).await? {
+            let (folders, files) = dav_children(state, &user.id, Some(&folder_id)).await?;
+            if folders.is_empty() && files.is_empty() {
+                database::delete_folder(&state.db, &folder_id, &user.id)
+                    .await
+                    .map_err(|_| s3_gateway::S3Error::internal())?;
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(file) = s3_find_object(state, &user.id, root, &components).await? {
+        database::soft_delete_file(&state.db, &file.id, &user.id)
+            .await
+            .map_err(|_| s3_gateway::S3Error::internal())?;
+        record_activity(state, user.id, &file, FileActivityKind::Delete);
+        trash::enforce_limit_quietly(state, &user.id).await;
+    }
+
+    Ok(())
+}
+
+async fn s3_delete_objects(
+    state: &AppState,
+    user: &models::User,
+    bucket: &str,
+    body: Body,
+    signature: &s3_gateway::RequestSignature,
+) -> S3Response {
+    let root = s3_bucket(state, &user.id, bucket).await?;
+    let xml = s3_gateway::read_xml_body(body, signature).await?;
+    let (keys, quiet) = s3_gateway::parse_delete_objects(&xml).ok_or_else(s3_gateway::S3Error::malformed_xml)?;
+    if keys.len() > s3_gateway::MAX_KEYS {
+        return Err(s3_gateway::S3Error::malformed_xml());
+    }
+
+    let mut deleted = Vec::new();
+    let mut errors = Vec::new();
+    for key in keys {
+        match s3_delete_object(state, user, &root, &key).await {
+            Ok(()) if !quiet => deleted.push(key),
+            Ok(()) => {}
+            Err(e) => errors.push((key, e)),
+        }
+    }
+
+    s3_xml(s3_gateway::delete_result_xml(&deleted, &errors))
+}
+
+async fn s3_create_multipart_upload(state: &AppState, user: &models::User, bucket: &str, key: &str) -> S3Response {
+    s3_object_key(key)?;
+    s3_bucket(state, &user.id, bucket).await?;
+
+    let upload = database::create_s3_multipart_upload(&state.db, &user.id, bucket, key, state.config.upload_session_ttl_hours)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+    s3_xml(s3_gateway::initiate_multipart_xml(bucket, key, &upload.id.to_string()))
+}
+
+async fn s3_multipart_upload(
+    state: &AppState,
+    user: &models::User,
+    (bucket, key): (&str, &str),
+    query: &[(String, String)],
+) -> Result<S3MultipartUpload, s3_gateway::S3Error> {
+    let upload_id = s3_gateway::query_value(query, "uploadId")
+        .and_then(|value| Uuid::parse_str(value).ok())
+        .ok_or_else(s3_gateway::S3Error::no_such_upload)?;
+
+    database::get_s3_multipart_upload(&state.db, &upload_id, &user.id)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?
+        .filter(|upload| upload.bucket == bucket && upload.object_key == key)
+        .ok_or_else(s3_gateway::S3Error::no_such_upload)
+}
+
+async fn s3_upload_part(
+    state: &AppState,
+    user: &models::User,
+    object: (&str, &str),
+    query: &[(String, String)],
+    headers: &HeaderMap,
+    body: Body,
+    signature: &s3_gateway::RequestSignature,
+) -> S3Response {
+    let upload = s3_multipart_upload(state, user, object, query).await?;
+    let part_number = s3_gateway::query_value(query, "partNumber")
+        .and_then(|value| value.parse::<i32>().ok())
+        .filter(|number| (1..=s3_gateway::MAX_PART_NUMBER).contains(number))
+        .ok_or_else(|| s3_gateway::S3Error::invalid_argument("Part number must be an integer between 1 and 10000"))?;
+
+    let ((temp_path, _), size, etag) = s3_receive_body(state, user.id, headers, body, signature).await?;
+    let stored = database::put_s3_multipart_part(
+        &state.db,
+        &upload.id,
+        part_number,
+        &temp_path.to_string_lossy(),
+        size,
+        &etag,
+        state.config.upload_session_ttl_hours,
+    )
+    .await;
+    match stored {
+        Ok(replaced) => s3_cleanup_parts(state, replaced.into_iter().collect()).await,
+        Err(e) => {
+            warn!("Failed to record part {} of multipart upload {}: {}", part_number, upload.id, e);
+            s3_cleanup_parts(state, vec![temp_path.to_string_lossy().to_string()]).await;
+            return Err(s3_gateway::S3Error::internal());
+        }
+    }
+
+    s3_etag_response(&etag)
+}
+
+async fn s3_complete_multipart_upload(
+    state: &AppState,
+    user: &models::User,
+    (bucket, key): (&str, &str),
+    query: &[(String, String)],
+    body: Body,
+    signature: &s3_gateway::RequestSignature,
+) -> S3Response {
+    let upload = s3_multipart_upload(state, user, (bucket, key), query).await?;
+    let xml = s3_gateway::read_xml_body(body, signature).await?;
+    let requested = s3_gateway::parse_complete_multipart(&xml).ok_or_else(s3_gateway::S3Error::malformed_xml)?;
+    let parts = database::get_s3_multipart_parts(&state.db, &upload.id)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+
+    let mut part_paths = Vec::with_capacity(requested.len());
+    let mut total_size = 0i64;
+    let mut previous = 0;
+    for (number, etag) in &requested {
+        if *number <= previous {
+            return Err(s3_gateway::S3Error::new(StatusCode::BAD_REQUEST, "InvalidPartOrder", "The list of parts was not in ascending order"));
+        }
+        previous = *number;
+        let part = parts
+            .iter()
+            .find(|part| part.part_number == *number && part.etag.eq_ignore_ascii_case(etag))
+            .ok_or_else(|| s3_gateway::S3Error::new(StatusCode::BAD_REQUEST, "InvalidPart", "One or more of the specified parts could not be found"))?;
+        part_paths.push(std::path::PathBuf::from(&part.temp_path));
+        total_size += part.size;
+    }
+
+    let components = s3_object_key(key)?;
+    let (name, parents) = components.split_last().ok_or_else(s3_gateway::S3Error::no_such_key)?;
+    let root = s3_bucket(state, &user.id, bucket).await?;
+    let folder_id = s3_ensure_folders(state, &user.id, root.id, parents).await?;
+    let existing = database::find_file_in_folder(&state.db, &user.id, Some(&folder_id), name)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+    check_name_conflict(state, &user.id, Some(&folder_id), name, existing.as_ref().map(|file| &file.id))
+        .await
+        .map_err(FileError::status)?;
+
+    // The part bytes were checked against the quota as they arrived.
+    let (user_id, assembly_id) = (user.id, Uuid::new_v4());
+    let reserved = state.file_storage
+        .blocking(move |storage| {
+            let (temp_path, disk_path) = storage.create_temp_file(&user_id, &assembly_id, total_size as u64)?;
+            let assembled = (|| -> anyhow::Result<()> {
+                let mut output = std::fs::OpenOptions::new().write(true).open(&temp_path)?;
+                for part_path in &part_paths {
+                    std::io::copy(&mut std::fs::File::open(part_path)?, &mut output)?;
+                }
+                output.set_len(total_size as u64)?;
+                output.sync_all()?;
+                Ok(())
+            })();
+            if let Err(e) = assembled {
+                let _ = storage.cleanup_temp_file(&temp_path);
+                return Err(e);
+            }
+            Ok((temp_path, disk_path))
+        })
+        .await
+        .map_err(|e| {
+            warn!("Failed to assemble multipart upload {}: {}", upload.id, e);
+            s3_gateway::S3Error::from(StatusCode::INSUFFICIENT_STORAGE)
+        })?;
+
+    let file = record_streamed_upload(state, user.id, Some(folder_id), name, reserved, None, existing.as_ref()).await?;
+    match database::delete_s3_multipart_upload(&state.db, &upload.id).await {
+        Ok(part_paths) => s3_cleanup_parts(state, part_paths).await,
+        Err(e) => warn!("Failed to remove completed multipart upload {}: {}", upload.id, e),
+    }
+
+    s3_xml(s3_gateway::complete_multipart_xml(&format!("/{}/{}", bucket, key), bucket, key, &dav_etag(&file)))
+}
+
+async fn s3_abort_multipart_upload(
+    state: &AppState,
+    user: &models::User,
+    object: (&str, &str),
+    query: &[(String, String)],
+) -> S3Response {
+    let upload = s3_multipart_upload(state, user, object, query).await?;
+    let part_paths = database::delete_s3_multipart_upload(&state.db, &upload.id)
+        .await
+        .map_err(|_| s3_gateway::S3Error::internal())?;
+    s3_cleanup_parts(state, part_paths).await;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn list_s3_access_keys(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<S3AccessKeyInfo>>, StatusCode> {
+    let keys = database::get_s3_access_keys(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(keys.into_iter().map(S3AccessKeyInfo::from).collect()))
+}
+
+async fn create_s3_access_key(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateS3AccessKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedS3AccessKey>), StatusCode> {
+    let description = request.description.as_deref().map(str::trim).filter(|description| !description.is_empty());
+    let (access_key_id, secret_access_key) = s3_gateway::generate_access_key();
+    let key = database::create_s3_access_key(&state.db, &user.id, &access_key_id, &secret_access_key, description)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    info!("{} created S3 access key {}", user.username, key.access_key_id);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreatedS3AccessKey {
+            access_key_id: key.access_key_id,
+            secret_access_key,
+            description: key.description,
+            created_at: key.created_at,
+        }),
+    ))
+}
+
+async fn delete_s3_access_key(
+    Path(access_key_id): Path<String>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::delete_s3_access_key(&state.db, &access_key_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_share(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateShareRequest>,
+) -> Result<Json<SharedLink>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Reshare).await?;
+
+    if file.is_deleted {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(expires_at) = request.expires_at {
+        if expires_at <= chrono::Utc::now() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    if request.egress_limit_bytes.is_some_and(|limit| limit < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(metadata) = &request.encryption_metadata {
+        let has_algorithm = metadata
+            .get("algorithm")
+            .and_then(|v| v.as_str())
+            .is_some_and(|v| !v.is_empty());
+        if !has_algorithm {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    if request.max_downloads.is_some_and(|max| max < 1) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let password = request.password.as_deref();
+    if password == Some("") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut expires_at = request.expires_at;
+    let mut max_downloads = request.max_downloads;
+    let defaults = match file.folder_id {
+        Some(folder_id) => database::get_effective_share_defaults(&state.db, &folder_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => None,
+    };
+    if let Some(defaults) = &defaults {
+        if let Some(days) = defaults.expiry_days {
+            let latest = chrono::Utc::now() + chrono::Duration::days(days as i64);
+            match expires_at {
+                Some(requested) if requested > latest => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+                Some(_) => {}
+                None => expires_at = Some(latest),
+            }
+        }
+        if defaults.password_required && password.is_none() {
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        if let Some(cap) = defaults.max_downloads {
+            match max_downloads {
+                Some(requested) if requested > cap => return Err(StatusCode::UNPROCESSABLE_ENTITY),
+                Some(_) => {}
+                None => max_downloads = Some(cap),
+            }
+        }
+    }
+
+    let password_hash = password
+        .map(auth::hash_password)
+        .transpose()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let token = Uuid::new_v4().simple().to_string();
+    let link = database::create_shared_link(
+        &state.db,
+        &file.id,
+        &token,
+        expires_at,
+        request.encryption_metadata.as_ref(),
+        request.egress_limit_bytes,
+        password_hash.as_deref(),
+        max_downloads,
+    )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    channels::send(
+        &state,
+        NotificationEvent::ShareCreated,
+        "[Local Drive] Share link created",
+        &format!("{} shared {}", user.username, file.original_filename),
+    );
+
+    Ok(Json(link))
+}
+
+fn valid_snippet_language(language: &str) -> bool {
+    !language.is_empty()
+        && language.len() <= 32
+        && language.chars().all(|c| c.is_ascii_alphanumeric() || "+#-_.".contains(c))
+}
+
+async fn create_snippet(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateSnippetRequest>,
+) -> Result<Json<Snippet>, StatusCode> {
+    if request.content.is_empty() || request.content.len() > MAX_SNIPPET_SIZE {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.expires_at.is_some_and(|expires_at| expires_at <= chrono::Utc::now()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let language = request.language.as_deref().map(str::trim).map(str::to_ascii_lowercase);
+    if language.as_deref().is_some_and(|language| !valid_snippet_language(language)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let title = match request.title.as_deref().map(str::trim) {
+        Some(title) if title.is_empty() || title.len() > 200 || title.contains('/') || title.contains('\\') => {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Some(title) => title.to_string(),
+        None => format!("Snippet {}", chrono::Utc::now().format("%Y-%m-%d %H-%M-%S")),
+    };
+    let filename = format!("{}.txt", title);
+
+    check_upload_quota(&state, &user.id, request.content.len() as i64).await?;
+    let (folder, _) = database::get_or_create_folder(&state.db, &user.id, None, SNIPPETS_FOLDER_NAME)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let stored = {
+        let user_id = user.id;
+        let filename = filename.clone();
+        let content = request.content;
+        state.file_storage
+            .blocking(move |storage| storage.store_file(content.as_bytes(), &user_id, &filename))
+            .await
+            .map_err(|_| StatusCode::INSUFFICIENT_STORAGE)?
+    };
+
+    let file = match database::create_file_record(
+        &state.db,
+        &user.id,
+        &stored.filename,
+        &filename,
+        &stored.file_path,
+        &stored.disk_path,
+        stored.file_size,
+        stored.stored_size,
+        stored.encoding,
+        Some("text/plain"),
+        Some(&stored.checksum),
+    )
+    .await
+    {
+        Ok(file) => file,
+        Err(_) => {
+            let _ = state.file_storage.delete_file(&stored.file_path);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let created = async {
+        database::set_file_folder(&state.db, &file.id, Some(&folder.id)).await?;
+
+        let token = Uuid::new_v4().simple().to_string();
+        let max_downloads = request.burn_after_reading.then_some(1);
+        let link = database::create_shared_link(&state.db, &file.id, &token, request.expires_at, None, None, None, max_downloads)
+            .await?;
+        database::create_snippet(
+            &state.db,
+            &user.id,
+            &file.id,
+            &link.id,
+            &title,
+            language.as_deref(),
+            request.burn_after_reading,
+        )
+        .await
+    }
+    .await;
+
+    match created {
+        Ok(snippet) => {
+            record_transfer(&state, user.id, file.file_size, 0);
+            Ok(Json(snippet))
+        }
+        Err(e) => {
+            error!("Failed to create snippet for {}: {}", user.username, e);
+            let _ = purge_file(&state, &file.id).await;
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn list_snippets(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Snippet>>, StatusCode> {
+    let snippets = database::get_snippets(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(snippets))
+}
+
+async fn delete_snippet(
+    Path(snippet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let snippet = database::get_snippet(&state.db, &snippet_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    purge_file(&state, &snippet.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn view_snippet(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<SnippetContent>, StatusCode> {
+    let link = database::get_active_shared_link_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let snippet = database::get_snippet_by_share(&state.db, &link.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let file = database::get_file_by_id(&state.db, &snippet.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // The view is only used up once the content could actually be read.
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let content = state.file_storage
+        .blocking(move |storage| storage.get_file_data(&file_path, encoding))
+        .await
+        .ok()
+        .and_then(|data| String::from_utf8(data).ok())
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let claimed = database::claim_share_download(&state.db, &link.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !claimed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    record_share_download(&state, link.id);
+
+    if snippet.burn_after_reading {
+        if let Err(e) = purge_file(&state, &file.id).await {
+            warn!("Failed to burn snippet {} after reading: {}", snippet.id, e);
+        }
+    }
+
+    Ok(Json(SnippetContent {
+        title: snippet.title,
+        language: snippet.language,
+        content,
+        expires_at: snippet.expires_at,
+        burn_after_reading: snippet.burn_after_reading,
+    }))
+}
+
+async fn purge_spent_snippets(state: &AppState) -> anyhow::Result<usize> {
+    let mut purged = 0;
+    for file_id in database::get_spent_snippet_files(&state.db).await? {
+        if purge_file(state, &file_id).await? {
+            purged += 1;
+        }
+    }
+
+    Ok(purged)
+}
+
+async fn list_shares(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<SharedLink>>, StatusCode> {
+    let links = database::get_shared_links_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(links))
+}
+
+async fn delete_share(
+    Path(share_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = database::delete_shared_link(&state.db, &share_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_share_egress(
+    Path(share_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetShareEgressRequest>,
+) -> Result<Json<SharedLink>, StatusCode> {
+    if request.limit_bytes.is_some_and(|limit| limit < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let updated = database::set_share_egress_limit(&state.db, &share_id, &user.id, request.limit_bytes)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !updated {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let link = database::get_shared_link_for_user(&state.db, &share_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(link))
+}
+
+fn share_is_restricted(link: &SharedLink) -> bool {
+    link.password_hash.is_some() || link.max_downloads.is_some()
+}
+
+fn check_share_password(link: &SharedLink, headers: &HeaderMap, query: &SharePasswordQuery) -> Result<(), StatusCode> {
+    let hash = match &link.password_hash {
+        Some(hash) => hash,
+        None => return Ok(()),
+    };
+
+    let password = headers
+        .get("x-share-password")
+        .and_then(|value| value.to_str().ok())
+        .or(query.password.as_deref())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    match auth::verify_password(password, hash) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(StatusCode::UNAUTHORIZED),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn download_shared_file(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<SharePasswordQuery>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let link = database::get_active_shared_link_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    check_share_password(&link, &headers, &query)?;
+
+    let mut file = database::get_file_by_id(&state.db, &link.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if link.is_encrypted {
+        file.original_filename = format!("{}.bin", link.token);
+        file.mime_type = None;
+    }
+
+    if let Some(response) = egress_limit_response(&state, &file, Some(&link), file.file_size).await? {
+        return Ok(response);
+    }
+
+    if link.max_downloads.is_some() {
+        let claimed = database::claim_share_download(&state.db, &link.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !claimed {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    let response = file_download_response(&state, &file).await?;
+    record_share_egress(&state, link.id, file.file_size);
+    record_share_download(&state, link.id);
+    channels::send(
+        &state,
+        NotificationEvent::ShareDownload,
+        "[Local Drive] Shared file downloaded",
+        &format!("{} was downloaded through a share link", file.original_filename),
+    );
+    let _ = database::touch_file_access(&state.db, &file.id).await;
+    Ok(response)
+}
+
+async fn get_shared_file_metadata(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<SharePasswordQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ShareMetadata>, StatusCode> {
+    let link = database::get_active_shared_link_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    check_share_password(&link, &headers, &query)?;
+
+    let file = database::get_file_by_id(&state.db, &link.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (filename, mime_type) = if link.is_encrypted {
+        (None, None)
+    } else {
+        (Some(file.original_filename), file.mime_type)
+    };
+
+    let magnet_uri = database::get_share_torrent(&state.db, &link.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map(|t| torrent::magnet_uri(&t.info_hash, &t.name, file.file_size, &t.web_seed_url, &state.config.torrent_trackers));
+
+    Ok(Json(ShareMetadata {
+        token: link.token,
+        is_encrypted: link.is_encrypted,
+        encryption_metadata: link.encryption_metadata,
+        filename,
+        mime_type,
+        file_size: file.file_size,
+        expires_at: link.expires_at,
+        magnet_uri,
+    }))
+}
+
+const TORRENT_PROGRESS_INTERVAL: u64 = 64 * 1024 * 1024;
+const MAX_WEBSEED_RANGE: u64 = 32 * 1024 * 1024;
+
+fn share_download_name(link: &SharedLink, file: &FileInfo) -> String {
+    if link.is_encrypted {
+        format!("{}.bin", link.token)
+    } else {
+        file.original_filename.clone()
+    }
+}
+
+fn share_torrent_info(share_torrent: ShareTorrent, file_size: i64, base_url: &str, token: &str, trackers: &[String]) -> ShareTorrentInfo {
+    ShareTorrentInfo {
+        magnet_uri: torrent::magnet_uri(&share_torrent.info_hash, &share_torrent.name, file_size, &share_torrent.web_seed_url, trackers),
+        torrent_url: format!("{}/share/{}/torrent", base_url, token),
+        info_hash: share_torrent.info_hash,
+        created_at: share_torrent.created_at,
+    }
+}
+
+async fn generate_share_torrent(
+    state: &AppState,
+    link: &SharedLink,
+    file: &FileInfo,
+    base_url: &str,
+    progress: &operations::Progress,
+) -> anyhow::Result<serde_json::Value> {
+    let piece_length = torrent::piece_length_for(file.file_size as u64);
+    let (path, encoding) = (std::path::PathBuf::from(&file.file_path), file.encoding());
+    let file_storage = state.file_storage.clone();
+    let handle = tokio::runtime::Handle::current();
+    let blocking_progress = progress.clone();
+    let total = file.file_size;
+
+    let (pieces, length) = tokio::task::spawn_blocking(move || {
+        let mut reported = 0;
+        torrent::hash_pieces(file_storage.open_file(&path, encoding)?, piece_length, |hashed| {
+            if hashed - reported < TORRENT_PROGRESS_INTERVAL {
+                return Ok(());
+            }
+            reported = hashed;
+            handle.block_on(blocking_progress.update(hashed as i64, Some(total)))
+        })
+    })
+    .await??;
+    progress.update(length as i64, Some(total)).await?;
+
+    let name = share_download_name(link, file);
+    let web_seed = format!("{}/share/{}/webseed", base_url, link.token);
+    let built = torrent::build(&name, length, piece_length, &pieces, &web_seed, &state.config.torrent_trackers);
+    let saved = database::save_share_torrent(&state.db, &link.id, &built.info_hash, &name, &web_seed, &built.data).await?;
+
+    let info = share_torrent_info(saved, length as i64, base_url, &link.token, &state.config.torrent_trackers);
+    Ok(serde_json::to_value(info)?)
+}
+
+async fn create_share_torrent(
+    Path(share_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    headers: HeaderMap,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let link = database::get_shared_link_for_user(&state.db, &share_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if share_is_restricted(&link) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let file = database::get_file_by_id(&state.db, &link.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|file| !file.is_deleted)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if (file.file_size as u64) < state.config.torrent_min_size {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, "torrent", Some(file.file_size))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+    let base_url = request_base_url(&headers);
+
+    tokio::spawn(async move {
+        let result = generate_share_torrent(&state, &link, &file, &base_url, &progress).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn get_share_torrent_info(
+    Path(share_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    headers: HeaderMap,
+) -> Result<Json<ShareTorrentInfo>, StatusCode> {
+    let link = database::get_shared_link_for_user(&state.db, &share_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let file = database::get_file_by_id(&state.db, &link.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let share_torrent = database::get_share_torrent(&state.db, &link.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(share_torrent_info(
+        share_torrent,
+        file.file_size,
+        &request_base_url(&headers),
+        &link.token,
+        &state.config.torrent_trackers,
+    )))
+}
+
+async fn download_share_torrent(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response<Body>, StatusCode> {
+    let link = database::get_active_shared_link_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if share_is_restricted(&link) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let share_torrent = database::get_share_torrent(&state.db, &link.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-bittorrent")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.torrent\"", share_torrent.name)
+        )
+        .body(Body::from(share_torrent.torrent))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn parse_byte_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') || size == 0 {
+        return None;
+    }
+
+    let (start, end) = spec.split_once('-')?;
+    match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let length: u64 = suffix.parse().ok()?;
+            (length > 0).then(|| (size.saturating_sub(length), size - 1))
+        }
+        (start, "") => {
+            let start: u64 = start.parse().ok()?;
+            (start < size).then_some((start, size - 1))
+        }
+        (start, end) => {
+            let start: u64 = start.parse().ok()?;
+            let end: u64 = end.parse().ok()?;
+            (start <= end && start < size).then_some((start, end.min(size - 1)))
+        }
+    }
+}
+
+async fn download_share_webseed(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let link = database::get_active_shared_link_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if share_is_restricted(&link) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let mut file = database::get_file_by_id(&state.db, &link.file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let range = match headers.get(header::RANGE).and_then(|value| value.to_str().ok()) {
+        Some(range) => range,
+        None => {
+            if let Some(response) = egress_limit_response(&state, &file, Some(&link), file.file_size).await? {
+                return Ok(response);
+            }
+
+            file.original_filename = share_download_name(&link, &file);
+            file.mime_type = None;
+            let response = file_download_response(&state, &file).await?;
+            record_share_egress(&state, link.id, file.file_size);
+            record_share_download(&state, link.id);
+            return Ok(response);
+        }
+    };
+
+    let size = file.file_size as u64;
+    let (start, end) = match parse_byte_range(range, size) {
+        Some((start, end)) => (start, end.min(start + MAX_WEBSEED_RANGE - 1)),
+        None => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    if let Some(response) = egress_limit_response(&state, &file, Some(&link), (end - start + 1) as i64).await? {
+        return Ok(response);
+    }
+
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let data = state.file_storage
+        .blocking(move |storage| storage.read_file_range(&file_path, encoding, start, end - start + 1))
+        .await
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+    record_transfer(&state, file.user_id, 0, data.len() as i64);
+    record_share_egress(&state, link.id, data.len() as i64);
+
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, start + data.len() as u64 - 1, size))
+        .header(header::CONTENT_LENGTH, data.len())
+        .body(Body::from(data))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+const DEFAULT_REPORT_LIMIT: i64 = 50;
+const DEFAULT_STALE_DURATION: &str = "180d";
+
+fn parse_duration_param(value: &str) -> Option<chrono::Duration> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "d"),
+    };
+    let number = number.parse::<i64>().ok()?;
+
+    match unit {
+        "h" => Some(chrono::Duration::hours(number)),
+        "d" => Some(chrono::Duration::days(number)),
+        "w" => Some(chrono::Duration::weeks(number)),
+        "m" => Some(chrono::Duration::days(number * 30)),
+        "y" => Some(chrono::Duration::days(number * 365)),
+        _ => None,
+    }
+}
+
+async fn largest_files_report(
+    state: &AppState,
+    user_id: Option<&Uuid>,
+    query: &FileReportQuery,
+) -> Result<Vec<FileInfo>, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_REPORT_LIMIT).clamp(1, 1000);
+    database::get_largest_files(&state.db, user_id, limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn stale_files_report(
+    state: &AppState,
+    user_id: Option<&Uuid>,
+    query: &FileReportQuery,
+) -> Result<Vec<FileInfo>, StatusCode> {
+    let limit = query.limit.unwrap_or(DEFAULT_REPORT_LIMIT).clamp(1, 1000);
+    let unaccessed_for = parse_duration_param(query.unaccessed_for.as_deref().unwrap_or(DEFAULT_STALE_DURATION))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    database::get_stale_files(&state.db, user_id, unaccessed_for.num_seconds(), limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn get_user_largest_files(
+    Query(query): Query<FileReportQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    Ok(Json(largest_files_report(&state, Some(&user.id), &query).await?))
+}
+
+async fn get_user_stale_files(
+    Query(query): Query<FileReportQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    Ok(Json(stale_files_report(&state, Some(&user.id), &query).await?))
+}
+
+async fn admin_largest_files(
+    Query(query): Query<FileReportQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    Ok(Json(largest_files_report(&state, query.user_id.as_ref(), &query).await?))
+}
+
+async fn admin_stale_files(
+    Query(query): Query<FileReportQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    Ok(Json(stale_files_report(&state, query.user_id.as_ref(), &query).await?))
+}
+
+async fn get_api_usage_report(
+    Query(query): Query<ApiUsageQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<ApiUsageReport>, StatusCode> {
+    let hours = query.hours.unwrap_or(24).clamp(1, usage::RETENTION_DAYS * 24);
+    let group_by = query.group_by.unwrap_or_else(|| "route".to_string());
+    let (by_user, by_route) = match group_by.as_str() {
+        "route" => (false, true),
+        "user" => (true, false),
+        "user_route" => (true, true),
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    if !matches!(query.interval.as_deref(), None | Some("hour") | Some("day")) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let _ = state.api_usage.flush(&state.db).await;
+
+    let since = chrono::Utc::now() - chrono::Duration::hours(hours);
+    let rows = database::get_api_usage(
+        &state.db,
+        since,
+        query.interval.as_deref(),
+        by_user,
+        by_route,
+        query.user_id.as_ref(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ApiUsageReport {
+        since,
+        group_by,
+        interval: query.interval,
+        entries: rows.into_iter().map(ApiUsageEntry::from).collect(),
+    }))
+}
+
+async fn move_to_trash(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Delete).await?;
+
+    let trashed = database::soft_delete_file(&state.db, &file.id, &file.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !trashed {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    record_activity(&state, user.id, &file, FileActivityKind::Delete);
+
+    trash::enforce_limit_quietly(&state, &file.user_id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_trash_usage(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<TrashUsage>, StatusCode> {
+    let (used_bytes, file_count) = database::get_trash_usage(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let limit_bytes = trash::user_limit(&state, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(TrashUsage { used_bytes, file_count, limit_bytes }))
+}
+
+async fn list_notifications(
+    Query(query): Query<NotificationQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Notification>>, StatusCode> {
+    let notifications = database::get_notifications(&state.db, &user.id, query.unread)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(notifications))
+}
+
+async fn mark_notification_read(
+    Path(notification_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::mark_notification_read(&state.db, &notification_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_trash_files(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let files = database::get_deleted_files(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(files))
+}
+
+async fn restore_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let restored = database::restore_file(&state.db, &file_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !restored {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_file_permanently(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let file = database::get_file_by_id(&state.db, &file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if file.user_id != user.id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if !file.is_deleted {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let file_path = file.file_path.clone();
+    state.file_storage.blocking(move |storage| storage.delete_file(&file_path))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    database::delete_file_record(&state.db, &file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_users(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<Json<Vec<PublicUser>>, StatusCode> {
+    let users = database::get_all_users(&state.db, admin.tenant_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(users.into_iter().map(PublicUser::from).collect()))
+}
+
+async fn deactivate_user(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if user_id == admin.id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    if database::deactivate_user(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        info!("Admin {} deactivated user {}", admin.username, user_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reactivate_user(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    if database::reactivate_user(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        info!("Admin {} reactivated user {}", admin.username, user_id);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn unlock_user_login(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let user = tenants::managed_user(&state, &admin, &user_id).await?;
+
+    if database::clear_login_failures(&state.db, &login_limit::user_key(&user.username))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        info!("Admin {} unlocked login for user {}", admin.username, user.username);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_login_lockouts(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<LoginLockout>>, StatusCode> {
+    let lockouts = database::get_login_lockouts(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(lockouts))
+}
+
+async fn clear_login_lockout(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<UnlockLoginRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::clear_login_failures(&state.db, &request.key)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    info!("Admin {} cleared login lockout {}", admin.username, request.key);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn purge_deactivated_user(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let user = tenants::managed_user(&state, &admin, &user_id).await?;
+
+    if user.deactivated_at.is_none() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    purge_user(&state.db, &state.file_storage, &user)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn tenant_settings(state: &AppState, request: &TenantRequest) -> Result<(String, Vec<String>), StatusCode> {
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > 255 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.storage_quota_bytes.is_some_and(|quota| quota < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut storage_paths = Vec::new();
+    for path in &request.storage_paths {
+        let root = state.file_storage.storage_root(path).ok_or(StatusCode::BAD_REQUEST)?;
+        let root = root.to_string_lossy().to_string();
+        if !storage_paths.contains(&root) {
+            storage_paths.push(root);
+        }
+    }
+
+    Ok((name.to_string(), storage_paths))
+}
+
+async fn list_tenants(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<TenantSummary>>, StatusCode> {
+    let tenants = database::get_tenants(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(tenants))
+}
+
+async fn create_tenant(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<TenantRequest>,
+) -> Result<(StatusCode, Json<Tenant>), StatusCode> {
+    let (name, storage_paths) = tenant_settings(&state, &request)?;
+
+    let tenant = database::create_tenant(&state.db, &name, &storage_paths, request.storage_quota_bytes)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    info!("Admin {} created tenant {}", admin.username, tenant.name);
+    Ok((StatusCode::CREATED, Json(tenant)))
+}
+
+async fn get_tenant(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<TenantSummary>, StatusCode> {
+    let tenant = database::get_tenant(&state.db, &tenant_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(tenant))
+}
+
+async fn update_tenant(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<TenantRequest>,
+) -> Result<Json<Tenant>, StatusCode> {
+    let (name, storage_paths) = tenant_settings(&state, &request)?;
+    database::get_tenant(&state.db, &tenant_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let tenant = database::update_tenant(&state.db, &tenant_id, &name, &storage_paths, request.storage_quota_bytes)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    tenants::refresh_storage_roots(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(tenant))
+}
+
+async fn delete_tenant(
+    Path(tenant_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    database::get_tenant(&state.db, &tenant_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if !database::delete_tenant(&state.db, &tenant_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_user_tenant(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<SetUserTenantRequest>,
+) -> Result<Json<PublicUser>, StatusCode> {
+    if user_id == admin.id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let user = database::get_user_by_id(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(tenant_id) = &request.tenant_id {
+        database::get_tenant(&state.db, tenant_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::BAD_REQUEST)?;
+    }
+
+    let is_admin = request.is_admin.unwrap_or(user.is_admin);
+    database::set_user_tenant(&state.db, &user.id, request.tenant_id.as_ref(), is_admin)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tenants::refresh_storage_roots(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let updated = database::get_user_by_id(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!(
+        "Admin {} moved user {} to tenant {:?} (admin: {})",
+        admin.username, updated.username, updated.tenant_id, updated.is_admin
+    );
+    Ok(Json(PublicUser::from(updated)))
+}
+
+async fn recalculate_usage_for_user(
+    state: &AppState,
+    user_id: &Uuid,
+) -> anyhow::Result<UsageRecalculation> {
+    let previous_used_bytes = database::get_user_storage_used(&state.db, user_id).await?;
+    let used_bytes = database::sum_user_file_sizes(&state.db, user_id).await?;
+    let files = database::get_files_by_user(&state.db, user_id).await?;
+
+    // Only the bytes on disk are checked; `file_size` is the logical size
+    // recorded at upload and is what quotas count.
+    let paths: Vec<String> = files.iter().map(|file| file.file_path.clone()).collect();
+    let stored_sizes = state.file_storage
+        .blocking(move |storage| paths.iter().map(|path| storage.get_stored_size(path)).collect::<anyhow::Result<Vec<_>>>())
+        .await?;
+
+    let mut corrected_files = 0;
+    let mut missing_files = Vec::new();
+    for (file, stored_size) in files.iter().zip(stored_sizes) {
+        match stored_size {
+            Some(stored_size) if file.stored_size != Some(stored_size as i64) => {
+                database::set_file_stored_size(&state.db, &file.id, stored_size as i64).await?;
+                corrected_files += 1;
+            }
+            Some(_) => {}
+            None => missing_files.push(file.id),
+        }
+    }
+
+    if used_bytes != previous_used_bytes {
+        info!(
+            "Storage usage drift for user {}: recorded {} bytes, actual {} bytes",
+            user_id, previous_used_bytes, used_bytes
+        );
+        database::set_user_storage_used(&state.db, user_id, used_bytes).await?;
+    }
+
+    Ok(UsageRecalculation {
+        user_id: *user_id,
+        previous_used_bytes,
+        used_bytes,
+        drift: used_bytes - previous_used_bytes,
+        corrected_files,
+        missing_files,
+    })
+}
+
+async fn recalculate_user_usage(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<Json<UsageRecalculation>, StatusCode> {
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    let result = recalculate_usage_for_user(&state, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(result))
+}
+
+async fn recalculate_all_users_usage(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<Json<Vec<UsageRecalculation>>, StatusCode> {
+    let users = database::get_all_users(&state.db, admin.tenant_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut results = Vec::with_capacity(users.len());
+    for user in users {
+        let result = recalculate_usage_for_user(&state, &user.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        results.push(result);
+    }
+
+    Ok(Json(results))
+}
+
+async fn admin_search_files(
+    Query(filter): Query<AdminFileSearchQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FileInfo>>, StatusCode> {
+    let files = database::search_files_admin(&state.db, &filter)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(files))
+}
+
+async fn admin_bulk_file_action(
+    State(state): State<AppState>,
+    Json(request): Json<AdminBulkFileRequest>,
+) -> Result<Json<AdminBulkFileResult>, StatusCode> {
+    if let AdminFileAction::Reassign = request.action {
+        let target_user_id = request.target_user_id.ok_or(StatusCode::BAD_REQUEST)?;
+        database::get_user_by_id(&state.db, &target_user_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .ok_or(StatusCode::BAD_REQUEST)?;
+    }
+
+    let mut result = AdminBulkFileResult {
+        affected: 0,
+        not_found: Vec::new(),
+        failed: Vec::new(),
+    };
+
+    for file_id in &request.file_ids {
+        let outcome = match request.action {
+            AdminFileAction::Reassign => {
+                let target_user_id = request.target_user_id.unwrap_or_default();
+                database::reassign_file(&state.db, file_id, &target_user_id).await
+            }
+            AdminFileAction::Quarantine => database::set_file_quarantined(&state.db, file_id, true).await,
+            AdminFileAction::Unquarantine => database::set_file_quarantined(&state.db, file_id, false).await,
+            AdminFileAction::Purge => purge_file(&state, file_id).await,
+        };
+
+        match outcome {
+            Ok(true) => result.affected += 1,
+            Ok(false) => result.not_found.push(*file_id),
+            Err(_) => result.failed.push(*file_id),
+        }
+    }
+
+    info!("Admin bulk {:?}: {} files affected", request.action, result.affected);
+
+    Ok(Json(result))
+}
+
+async fn purge_expired_trash(state: &AppState, retention_days: i64) -> anyhow::Result<(usize, u64)> {
+    let files = database::get_expired_trash_files(&state.db, retention_days).await?;
+    let mut purged = 0;
+    let mut freed = 0u64;
+
+    for file in files {
+        let file_path = file.file_path.clone();
+        if let Err(e) = state.file_storage.blocking(move |storage| storage.delete_file(&file_path)).await {
+            warn!("Failed to remove expired trash blob {}: {}", file.file_path, e);
+            continue;
+        }
+        database::delete_file_record(&state.db, &file.id).await?;
+        purged += 1;
+        freed += file.file_size as u64;
+    }
+
+    Ok((purged, freed))
+}
+
+async fn purge_file(state: &AppState, file_id: &Uuid) -> anyhow::Result<bool> {
+    let file = match database::get_file_by_id(&state.db, file_id).await? {
+        Some(file) => file,
+        None => return Ok(false),
+    };
+
+    let file_path = file.file_path.clone();
+    state.file_storage.blocking(move |storage| storage.delete_file(&file_path)).await?;
+    database::delete_file_record(&state.db, file_id).await?;
+
+    Ok(true)
+}
+
+async fn get_file_photo_metadata(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<PhotoMetadata>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    let metadata = database::get_photo_metadata(&state.db, &file.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(metadata))
+}
+
+async fn get_file_metadata(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FileMetadata>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    let metadata = database::get_file_metadata(&state.db, &file.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(metadata))
+}
+
+async fn get_file_poster(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let poster_path = database::get_file_metadata(&state.db, &file.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .and_then(|metadata| metadata.poster_path)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let data = tokio::fs::read(&poster_path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/jpeg")
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .body(Body::from(data))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn file_preview_strategy(state: &AppState, mime_type: Option<&str>) -> Result<PreviewStrategy, StatusCode> {
+    let custom = database::get_preview_handlers(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(preview::resolve(&preview::handlers(custom), mime_type))
+}
+
+async fn get_file_preview(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FilePreview>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    let mime_type = preview::effective_mime_type(file.mime_type.as_deref(), &file.original_filename);
+    let strategy = if file.is_quarantined {
+        PreviewStrategy::None
+    } else {
+        file_preview_strategy(&state, mime_type.as_deref()).await?
+    };
+    let content_url = matches!(strategy, PreviewStrategy::Native | PreviewStrategy::Image)
+        .then(|| format!("/files/{}/preview/content", file.id));
+    let poster_url = match strategy {
+        PreviewStrategy::None => None,
+        _ => database::get_file_metadata(&state.db, &file.id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .and_then(|metadata| metadata.poster_path)
+            .map(|_| format!("/files/{}/poster", file.id)),
+    };
+
+    Ok(Json(FilePreview {
+        file_id: file.id,
+        mime_type,
+        strategy,
+        content_url,
+        poster_url,
+    }))
+}
+
+async fn get_file_preview_content(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let mut file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+
+    file.mime_type = preview::effective_mime_type(file.mime_type.as_deref(), &file.original_filename);
+    match file_preview_strategy(&state, file.mime_type.as_deref()).await? {
+        PreviewStrategy::Native | PreviewStrategy::Image => {}
+        PreviewStrategy::Office | PreviewStrategy::None => return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE),
+    }
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let rangeable = !security::is_risky_content(file.mime_type.as_deref(), &file.original_filename);
+    if let Some(range) = headers.get(header::RANGE).and_then(|value| value.to_str().ok()).filter(|_| rangeable) {
+        return preview_range_response(&state, &file, range).await;
+    }
+
+    if let Some(response) = egress_limit_response(&state, &file, None, file.file_size).await? {
+        return Ok(response);
+    }
+
+    let mut response = file_download_response(&state, &file).await?;
+    set_inline_disposition(&mut response);
+    if rangeable {
+        response.headers_mut().insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+    Ok(response)
+}
+
+async fn print_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<PrintRequest>,
+) -> Result<Json<PrintJob>, StatusCode> {
+    if !state.config.printing_enabled {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let copies = request.copies.unwrap_or(1);
+    if copies == 0 || copies > print::MAX_COPIES {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.page_ranges.as_deref().is_some_and(|page_ranges| !print::valid_page_ranges(page_ranges)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mime_type = preview::effective_mime_type(file.mime_type.as_deref(), &file.original_filename)
+        .filter(|mime_type| print::is_printable(Some(mime_type)))
+        .ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+
+    let file_storage = state.file_storage.clone();
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let source = tokio::task::spawn_blocking(move || file_storage.materialize(&file_path, encoding))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let job_id = print::submit(&state.config, &file, source.path(), &mime_type, copies, request.page_ranges.as_deref())
+        .await
+        .map_err(|e| {
+            error!("Failed to print file {} for {}: {:#}", file.id, user.username, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+    info!("{} sent file {} to the printer ({} copies)", user.username, file.id, copies);
+
+    Ok(Json(PrintJob {
+        file_id: file.id,
+        printer: state.config.printer.clone(),
+        job_id,
+        copies,
+    }))
+}
+
+async fn preview_range_response(state: &AppState, file: &FileInfo, range: &str) -> Result<Response<Body>, StatusCode> {
+    let size = file.file_size as u64;
+    let (start, end) = match parse_byte_range(range, size) {
+        Some(range) => range,
+        None => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let length = end - start + 1;
+
+    if let Some(response) = egress_limit_response(state, file, None, length as i64).await? {
+        return Ok(response);
+    }
+
+    let file_storage = state.file_storage.clone();
+    let (path, encoding) = (std::path::PathBuf::from(&file.file_path), file.encoding());
+    let data = tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+        let mut data = file_storage.open_file(&path, encoding)?;
+        data.seek(std::io::SeekFrom::Start(start))?;
+        Ok(data)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|_| StatusCode::NOT_FOUND)?;
+    record_transfer(state, file.user_id, 0, length as i64);
+
+    Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_TYPE, file.mime_type.as_deref().unwrap_or("application/octet-stream"))
+        .header(header::CONTENT_DISPOSITION, format!("inline; filename=\"{}\"", file.original_filename))
+        .header(header::CONTENT_LENGTH, length)
+        .header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size))
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::LAST_MODIFIED, http_date(&file.client_modified_at.unwrap_or(file.updated_at)))
+        .body(Body::from_stream(file_storage::stream_reader(std::io::Read::take(data, length))))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+fn set_inline_disposition(response: &mut Response<Body>) {
+    let headers = response.headers();
+    let sandboxed = headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value == security::SANDBOXED_CONTENT_TYPE)
+        || headers
+            .get(header::CONTENT_SECURITY_POLICY)
+            .is_some_and(|value| value == security::SVG_CONTENT_SECURITY_POLICY);
+    if sandboxed {
+        return;
+    }
+
+    let inline = response
+        .headers()
+        .get(header::CONTENT_DISPOSITION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("attachment"))
+        .and_then(|params| HeaderValue::from_str(&format!("inline{}", params)).ok())
+        .unwrap_or_else(|| HeaderValue::from_static("inline"));
+    response.headers_mut().insert(header::CONTENT_DISPOSITION, inline);
+}
+
+async fn list_preview_handlers(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PreviewHandler>>, StatusCode> {
+    let custom = database::get_preview_handlers(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(preview::handlers(custom)))
+}
+
+async fn set_preview_handler(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<SetPreviewHandlerRequest>,
+) -> Result<Json<Vec<PreviewHandler>>, StatusCode> {
+    let mime_type = preview::normalize_mime_type(&request.mime_type).ok_or(StatusCode::BAD_REQUEST)?;
+
+    database::set_preview_handler(&state.db, &mime_type, request.strategy.as_str())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Admin {} set preview handler for {} to {}", admin.username, mime_type, request.strategy.as_str());
+
+    list_preview_handlers(State(state)).await
+}
+
+async fn delete_preview_handler(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<DeletePreviewHandlerRequest>,
+) -> Result<Json<Vec<PreviewHandler>>, StatusCode> {
+    let mime_type = preview::normalize_mime_type(&request.mime_type).ok_or(StatusCode::BAD_REQUEST)?;
+
+    if !database::delete_preview_handler(&state.db, &mime_type)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    info!("Admin {} reset preview handler for {}", admin.username, mime_type);
+
+    list_preview_handlers(State(state)).await
+}
+
+async fn create_external_mount(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<CreateMountRequest>,
+) -> Result<Json<ExternalMount>, StatusCode> {
+    let name = request.name.trim();
+    if name.is_empty() || name.len() > 255 || name.contains('/') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let (kind, host_path, read_only) = match &request.smb {
+        Some(credentials) => {
+            let share = smb::parse_share(&request.host_path).map_err(|_| StatusCode::BAD_REQUEST)?;
+            let host_path = smb::display_path(&share);
+            if let Err(e) = smb::list(&state.config, &host_path, credentials, "").await {
+                warn!("Failed to connect to SMB share {}: {}", host_path, e);
+                return Err(StatusCode::BAD_GATEWAY);
+            }
+            (MountKind::Smb, host_path, true)
+        }
+        None => {
+            let host_path = std::path::Path::new(&request.host_path)
+                .canonicalize()
+                .map_err(|_| StatusCode::BAD_REQUEST)?;
+            if !host_path.is_dir() {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            if !mounts::is_allowed_root(&host_path, &state.config.external_mount_roots) {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            let host_path = host_path.to_str().ok_or(StatusCode::BAD_REQUEST)?.to_string();
+            (MountKind::Local, host_path, request.read_only.unwrap_or(true))
+        }
+    };
+
+    database::get_user_by_id(&state.db, &request.user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let existing = database::get_external_mounts(&state.db, Some(&request.user_id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if existing.iter().any(|mount| mount.name == name) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let mount = database::create_external_mount(
+        &state.db,
+        &request.user_id,
+        name,
+        kind.as_str(),
+        &host_path,
+        read_only,
+        request.smb.as_ref(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(
+        "Admin {} mounted {} as '{}' for user {} ({})",
+        admin.username,
+        mount.host_path,
+        mount.name,
+        mount.user_id,
+        if mount.read_only { "read-only" } else { "read-write" }
+    );
+    Ok(Json(mount))
+}
+
+async fn admin_list_mounts(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ExternalMount>>, StatusCode> {
+    let mounts = database::get_external_mounts(&state.db, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(mounts))
+}
+
+async fn delete_external_mount(
+    Path(mount_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::delete_external_mount(&state.db, &mount_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_mounts(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ExternalMount>>, StatusCode> {
+    let mounts = database::get_external_mounts(&state.db, Some(&user.id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(mounts))
+}
+
+async fn owned_mount(state: &AppState, mount_id: &Uuid, user: &User) -> Result<ExternalMount, StatusCode> {
+    let mount = database::get_external_mount(&state.db, mount_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if mount.user_id != user.id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(mount)
+}
+
+async fn list_mount_directory(
+    Path(mount_id): Path<Uuid>,
+    Query(query): Query<MountPathQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<MountListing>, StatusCode> {
+    let mount = owned_mount(&state, &mount_id, &user).await?;
+    let path = mounts::normalize_relative_path(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    match mounts::list(&state.config, &mount, &path).await {
+        Ok(entries) => {
+            if let Err(e) = database::replace_mount_entries(&state.db, &mount.id, &path, &entries).await {
+                warn!("Failed to cache listing of mount {}: {}", mount.id, e);
+            }
+            Ok(Json(MountListing { mount_id: mount.id, path, cached: false, entries }))
+        }
+        Err(e) => {
+            warn!("Failed to read {} in mount {}: {}", path, mount.id, e);
+            let entries = database::get_mount_entries(&state.db, &mount.id, &path)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if entries.is_empty() {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            Ok(Json(MountListing { mount_id: mount.id, path, cached: true, entries }))
+        }
+    }
+}
+
+async fn download_mount_file(
+    Path(mount_id): Path<Uuid>,
+    Query(query): Query<MountPathQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    let mount = owned_mount(&state, &mount_id, &user).await?;
+    let path = mounts::normalize_relative_path(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let entry = match mounts::stat(&state.config, &mount, &path).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            warn!("Failed to stat {} in mount {}: {}", path, mount.id, e);
+            return Err(StatusCode::NOT_FOUND);
+        }
+    };
+    if entry.is_dir {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(exceeded) = egress::check(&state, &mount.user_id, None, entry.size)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Ok(exceeded.into_response());
+    }
+
+    let body = match mounts::smb_credentials(&mount) {
+        Some(credentials) => {
+            let stdout = smb::stream(&state.config, &mount.host_path, credentials, &path).map_err(|e| {
+                warn!("Failed to start download of {} from mount {}: {}", path, mount.id, e);
+                StatusCode::BAD_GATEWAY
+            })?;
+            Body::from_stream(tokio_util::io::ReaderStream::new(stdout))
+        }
+        None => {
+            let target = mounts::resolve(&mount.host_path, &path).map_err(|_| StatusCode::NOT_FOUND)?;
+            let file = tokio::fs::File::open(&target).await.map_err(|_| StatusCode::NOT_FOUND)?;
+            Body::from_stream(tokio_util::io::ReaderStream::new(file))
+        }
+    };
+    let modified_at = entry.modified_at.unwrap_or_else(chrono::Utc::now);
+    record_transfer(&state, mount.user_id, 0, entry.size);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, security::SANDBOXED_CONTENT_TYPE)
+        .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", entry.name.replace('"', "_")))
+        .header(header::CONTENT_LENGTH, entry.size)
+        .header(header::LAST_MODIFIED, http_date(&modified_at))
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn set_mount_sync(
+    Path(mount_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetMountSyncRequest>,
+) -> Result<Json<ExternalMount>, StatusCode> {
+    owned_mount(&state, &mount_id, &user).await?;
+    if let Some(folder_id) = &request.folder_id {
+        owned_folder(&state, folder_id, &user).await?;
+    }
+
+    let mount = database::set_mount_sync_folder(&state.db, &mount_id, request.folder_id.as_ref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(mount))
+}
+
+async fn run_mount_sync(
+    Path(mount_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let mount = owned_mount(&state, &mount_id, &user).await?;
+    if mount.sync_folder_id.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mount = database::claim_mount_sync(&state.db, &mount.id, mounts::SYNC_STALE_AFTER_HOURS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    let operation = match database::create_operation(&state.db, &user.id, "mount_sync", None).await {
+        Ok(operation) => operation,
+        Err(_) => {
+            let _ = database::finish_mount_sync(&state.db, &mount.id, Some("failed to start sync")).await;
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = mounts::run_sync(&state, mount, Some(&progress))
+            .await
+            .map(|(files, bytes)| serde_json::json!({
+                "files_synced": files,
+                "bytes_synced": bytes,
+            }));
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn upload_mount_file(
+    Path(mount_id): Path<Uuid>,
+    Query(query): Query<MountPathQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    body: Body,
+) -> Result<Json<MountEntry>, StatusCode> {
+    let mount = owned_mount(&state, &mount_id, &user).await?;
+    if mount.read_only {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let path = mounts::normalize_relative_path(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if path.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let target = mounts::resolve(&mount.host_path, &path).map_err(|_| StatusCode::NOT_FOUND)?;
+    if target.is_dir() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let temp_path = target.with_file_name(format!(".{}.upload", Uuid::new_v4().simple()));
+    let written = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        let mut body = body;
+        let mut size = 0i64;
+        while let Some(frame) = body.frame().await {
+            if let Some(data) = frame?.data_ref() {
+                tokio::io::AsyncWriteExt::write_all(&mut file, data).await?;
+                size += data.len() as i64;
+            }
+        }
+        file.sync_all().await?;
+        tokio::fs::rename(&temp_path, &target).await?;
+        Ok::<i64, anyhow::Error>(size)
+    }
+    .await;
+
+    let size = match written {
+        Ok(size) => size,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            warn!("Failed to write {} in mount {}: {}", path, mount.id, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    record_transfer(&state, mount.user_id, size, 0);
+
+    let (parent_path, name) = match path.rsplit_once('/') {
+        Some((parent, name)) => (parent.to_string(), name.to_string()),
+        None => (String::new(), path.clone()),
+    };
+    let modified_at = tokio::fs::metadata(&target)
+        .await
+        .ok()
+        .and_then(|metadata| metadata.modified().ok())
+        .map(chrono::DateTime::<chrono::Utc>::from);
+    let entry = MountEntry { path, name, is_dir: false, size, modified_at };
+
+    let host_path = mount.host_path.clone();
+    let parent = parent_path.clone();
+    if let Ok(Ok(entries)) = tokio::task::spawn_blocking(move || {
+        mounts::resolve(&host_path, &parent).and_then(|dir| mounts::read_directory(&dir, &parent))
+    })
+    .await
+    {
+        let _ = database::replace_mount_entries(&state.db, &mount.id, &parent_path, &entries).await;
+    }
+
+    Ok(Json(entry))
+}
+
+async fn delete_mount_path(
+    Path(mount_id): Path<Uuid>,
+    Query(query): Query<MountPathQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let mount = owned_mount(&state, &mount_id, &user).await?;
+    if mount.read_only {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let path = mounts::normalize_relative_path(&query.path).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if path.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let target = mounts::resolve(&mount.host_path, &path).map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let metadata = tokio::fs::symlink_metadata(&target).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let removed = if metadata.is_dir() {
+        tokio::fs::remove_dir(&target).await
+    } else {
+        tokio::fs::remove_file(&target).await
+    };
+    if removed.is_err() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    database::delete_mount_entry(&state.db, &mount.id, &path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+const DEFAULT_GALLERY_PAGE_SIZE: i64 = 50;
+const MAX_GALLERY_PAGE_SIZE: i64 = 200;
+
+async fn list_shared_with_me(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<SharedFolder>>, StatusCode> {
+    let folders = database::get_shared_folders(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(folders))
+}
+
+async fn get_folder_contents(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FolderContents>, StatusCode> {
+    let folder = access::authorize_folder(&state, &user, &folder_id, Permission::Read).await?;
+    let permissions = access::folder_permissions(&state, &user.id, &folder)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut folders = Vec::new();
+    for child in database::get_child_folders(&state.db, &folder.user_id, Some(&folder.id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        let child_permissions = access::folder_permissions(&state, &user.id, &child)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if child_permissions.read {
+            folders.push(child);
+        }
+    }
+
+    let files = database::get_files_in_folder(&state.db, &folder.user_id, Some(&folder.id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(FolderContents { folder, permissions, folders, files }))
+}
+
+async fn list_folder_permissions(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<FolderPermission>>, StatusCode> {
+    let folder = access::authorize_folder(&state, &user, &folder_id, Permission::Reshare).await?;
+
+    let permissions = database::get_folder_permissions(&state.db, &folder.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(permissions))
+}
+
+async fn set_folder_permission(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetFolderPermissionRequest>,
+) -> Result<Json<FolderPermission>, StatusCode> {
+    let folder = access::authorize_folder(&state, &user, &folder_id, Permission::Reshare).await?;
+
+    let permissions = request.permissions;
+    if !permissions.read && (permissions.write || permissions.delete || permissions.reshare) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let grantee = database::get_user_by_username(&state.db, request.username.trim())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|grantee| tenants::same_tenant(grantee, &user))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if grantee.id == folder.user_id || grantee.id == user.id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if folder.user_id != user.id {
+        let own = access::folder_permissions(&state, &user.id, &folder)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if !own.covers(&permissions) {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let permission = database::set_folder_permission(&state.db, &folder.id, &grantee.id, &permissions, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(permission))
+}
+
+async fn delete_folder_permission(
+    Path((folder_id, grantee_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let folder = access::authorize_folder(&state, &user, &folder_id, Permission::Reshare).await?;
+
+    let deleted = database::delete_folder_permission(&state.db, &folder.id, &grantee_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn owned_folder(state: &AppState, folder_id: &Uuid, user: &User) -> Result<Folder, StatusCode> {
+    let folder = database::get_folder_by_id(&state.db, folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if folder.user_id != user.id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    Ok(folder)
+}
+
+async fn get_folder_gallery(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Gallery>, StatusCode> {
+    owned_folder(&state, &folder_id, &user).await?;
+
+    let gallery = database::get_gallery_by_folder(&state.db, &folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(gallery))
+}
+
+async fn set_folder_gallery(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetGalleryRequest>,
+) -> Result<Json<Gallery>, StatusCode> {
+    owned_folder(&state, &folder_id, &user).await?;
+
+    let title = request.title.as_deref().map(str::trim).filter(|title| !title.is_empty());
+    if title.is_some_and(|title| title.chars().count() > 255) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let description = request.description.as_deref().map(str::trim).filter(|d| !d.is_empty());
+
+    let existing = database::get_gallery_by_folder(&state.db, &folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let theme = match (request.theme, &existing) {
+        (Some(theme), _) => theme.as_str().to_string(),
+        (None, Some(gallery)) => gallery.theme.clone(),
+        (None, None) => GalleryTheme::Light.as_str().to_string(),
+    };
+    let token = match &existing {
+        Some(gallery) => gallery.token.clone(),
+        None => Uuid::new_v4().simple().to_string(),
+    };
+
+    let gallery = database::upsert_gallery(&state.db, &user.id, &folder_id, &token, title, description, &theme)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(gallery))
+}
+
+async fn delete_folder_gallery(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::delete_gallery(&state.db, &folder_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_galleries(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Gallery>>, StatusCode> {
+    let galleries = database::get_galleries_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(galleries))
+}
+
+fn gallery_mime_type(file: &FileInfo) -> Option<String> {
+    preview::effective_mime_type(file.mime_type.as_deref(), &file.original_filename)
+        .filter(|mime_type| mime_type.starts_with("image/"))
+}
+
+async fn get_gallery_feed(
+    Path(token): Path<String>,
+    Query(query): Query<GalleryQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<GalleryFeed>, StatusCode> {
+    let gallery = database::get_gallery_by_token(&state.db, &token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let folder = database::get_folder_by_id(&state.db, &gallery.folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let files = database::get_gallery_files(&state.db, &gallery.user_id, &gallery.folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let photos: Vec<(FileInfo, String)> = files
+        .into_iter()
+        .filter_map(|file| gallery_mime_type(&file).map(|mime_type| (file, mime_type)))
+        .collect();
+
+    let per_page = query.per_page.unwrap_or(DEFAULT_GALLERY_PAGE_SIZE).clamp(1, MAX_GALLERY_PAGE_SIZE);
+    let page = query.page.unwrap_or(1).max(1);
+    let items = photos
+        .iter()
+        .skip(((page - 1) * per_page) as usize)
+        .take(per_page as usize)
+        .map(|(file, mime_type)| GalleryItem {
+            id: file.id,
+            name: file.original_filename.clone(),
+            size: file.file_size,
+            mime_type: Some(mime_type.clone()),
+            modified_at: file.client_modified_at.unwrap_or(file.created_at),
+            thumbnail_url: thumbnail::is_supported(Some(mime_type))
+                .then(|| format!("/gallery/{}/files/{}/thumbnail", gallery.token, file.id)),
+            original_url: format!("/gallery/{}/files/{}/original", gallery.token, file.id),
+        })
+        .collect();
+
+    Ok(Json(GalleryFeed {
+        title: gallery.title.unwrap_or(folder.name),
+        description: gallery.description,
+        theme: gallery.theme,
+        page,
+        per_page,
+        total: photos.len() as i64,
+        items,
+    }))
+}
+
+async fn gallery_file(state: &AppState, token: &str, file_id: &Uuid) -> Result<FileInfo, StatusCode> {
+    let gallery = database::get_gallery_by_token(&state.db, token)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut file = database::get_file_by_id(&state.db, file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if file.user_id != gallery.user_id || file.folder_id != Some(gallery.folder_id) || file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    file.mime_type = Some(gallery_mime_type(&file).ok_or(StatusCode::NOT_FOUND)?);
+    Ok(file)
+}
+
+async fn get_gallery_thumbnail(
+    Path((token, file_id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Response<Body>, StatusCode> {
+    let file = gallery_file(&state, &token, &file_id).await?;
+
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if !thumbnail::is_supported(file.mime_type.as_deref()) {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let file_storage = state.file_storage.clone();
+    let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+    let data = tokio::task::spawn_blocking(move || thumbnail::load_or_create(&file_storage, &file_path, encoding))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            warn!("Failed to create thumbnail for file {}: {}", file.id, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/jpeg")
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(header::CACHE_CONTROL, "public, max-age=86400")
+        .body(Body::from(data))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+async fn get_gallery_original(
+    Path((token, file_id)): Path<(String, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<Response<Body>, StatusCode> {
+    let file = gallery_file(&state, &token, &file_id).await?;
+
+    if let Some(response) = egress_limit_response(&state, &file, None, file.file_size).await? {
+        return Ok(response);
+    }
+
+    let mut response = file_download_response(&state, &file).await?;
+    set_inline_disposition(&mut response);
+    Ok(response)
+}
+
+async fn set_file_keep_offline(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<KeepOfflineRequest>,
+) -> Result<Json<FileInfo>, StatusCode> {
+    let file = database::get_file_by_id(&state.db, &file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if file.user_id != user.id {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let updated = database::set_file_keep_offline(&state.db, &file.id, request.keep_offline)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(updated))
+}
+
+async fn set_folder_keep_offline(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<KeepOfflineRequest>,
+) -> Result<Json<Folder>, StatusCode> {
+    let folder = database::get_folder_by_id(&state.db, &folder_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if folder.user_id != user.id {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let updated = database::set_folder_keep_offline(&state.db, &folder.id, request.keep_offline)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(updated))
+}
+
+async fn get_folder_share_defaults(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FolderShareDefaultsInfo>, StatusCode> {
+    let folder = owned_folder(&state, &folder_id, &user).await?;
+
+    let defaults = database::get_folder_share_defaults(&state.db, &folder.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let effective = database::get_effective_share_defaults(&state.db, &folder.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(FolderShareDefaultsInfo { defaults, effective }))
+}
+
+async fn set_folder_share_defaults(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<SetFolderShareDefaultsRequest>,
+) -> Result<Json<FolderShareDefaults>, StatusCode> {
+    let folder = owned_folder(&state, &folder_id, &user).await?;
+
+    if request.expiry_days.is_some_and(|days| days < 1) || request.max_downloads.is_some_and(|max| max < 1) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let defaults = database::set_folder_share_defaults(
+        &state.db,
+        &folder.id,
+        request.expiry_days,
+        request.password_required,
+        request.max_downloads,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(defaults))
+}
+
+async fn delete_folder_share_defaults(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let folder = owned_folder(&state, &folder_id, &user).await?;
+
+    let deleted = database::delete_folder_share_defaults(&state.db, &folder.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_sync_changes(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Query(query): Query<SyncChangesQuery>,
+) -> Result<Json<SyncChanges>, StatusCode> {
+    let started_at = chrono::Utc::now();
+
+    let files = database::get_files_changed_since(&state.db, &user.id, query.since)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let folders = database::get_folders_changed_since(&state.db, &user.id, query.since)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let cursor = files
+        .iter()
+        .map(|f| f.updated_at)
+        .chain(folders.iter().map(|f| f.updated_at))
+        .max()
+        .or(query.since)
+        .unwrap_or(started_at);
+
+    Ok(Json(SyncChanges { files, folders, cursor }))
+}
+
+const MAX_CLIPBOARD_ITEMS: usize = 1000;
+
+async fn load_clipboard_items(
+    state: &AppState,
+    user_id: &Uuid,
+    file_ids: &[Uuid],
+    folder_ids: &[Uuid],
+) -> Result<Option<(Vec<FileInfo>, Vec<Folder>)>, StatusCode> {
+    let mut files = Vec::with_capacity(file_ids.len());
+    for file_id in file_ids {
+        let file = database::get_file_by_id(&state.db, file_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        match file {
+            Some(file) if file.user_id == *user_id && !file.is_deleted => files.push(file),
+            _ => return Ok(None),
+        }
+    }
+
+    let mut folders = Vec::with_capacity(folder_ids.len());
+    for folder_id in folder_ids {
+        let folder = database::get_folder_by_id(&state.db, folder_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        match folder {
+            Some(folder) if folder.user_id == *user_id => folders.push(folder),
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(Some((files, folders)))
+}
+
+async fn get_clipboard(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Clipboard>, StatusCode> {
+    let clipboard = database::get_clipboard(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(clipboard))
+}
+
+async fn set_clipboard(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(mut request): Json<SetClipboardRequest>,
+) -> Result<Json<Clipboard>, StatusCode> {
+    request.file_ids.sort();
+    request.file_ids.dedup();
+    request.folder_ids.sort();
+    request.folder_ids.dedup();
+
+    let item_count = request.file_ids.len() + request.folder_ids.len();
+    if item_count == 0 || item_count > MAX_CLIPBOARD_ITEMS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    load_clipboard_items(&state, &user.id, &request.file_ids, &request.folder_ids)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let clipboard = database::set_clipboard(
+        &state.db,
+        &user.id,
+        request.mode.as_str(),
+        &request.file_ids,
+        &request.folder_ids,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(clipboard))
+}
+
+async fn clear_clipboard(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let cleared = database::clear_clipboard(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !cleared {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn paste_clipboard(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<PasteRequest>,
+) -> Result<Response, FileError> {
+    let clipboard = database::get_clipboard(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(folder_id) = &request.folder_id {
+        match database::get_folder_by_id(&state.db, folder_id).await {
+            Ok(Some(folder)) if folder.user_id == user.id => {}
+            Ok(_) => return Err(StatusCode::NOT_FOUND.into()),
+            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into()),
+        }
+    }
+
+    let (files, folders) = load_clipboard_items(&state, &user.id, &clipboard.file_ids, &clipboard.folder_ids)
+        .await?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    if clipboard.mode == ClipboardMode::Copy.as_str() {
+        let plan = clipboard::plan_copy(&state, &user.id, request.folder_id, files, folders)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        check_upload_quota(&state, &user.id, plan.total_bytes()).await?;
+
+        if plan.should_run_in_background() {
+            let operation = database::create_operation(&state.db, &user.id, "copy", Some(plan.files.len() as i64))
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+            tokio::spawn(async move {
+                let result = clipboard::execute_copy(&state, &user.id, &plan, Some(&progress))
+                    .await
+                    .map(|(folders, files)| serde_json::json!({
+                        "folders_created": folders.len(),
+                        "files_copied": files.len(),
+                        "bytes_copied": files.iter().map(|f| f.file_size).sum::<i64>(),
+                    }));
+                operations::finish(&state.db, &progress.operation_id, result).await;
+            });
+
+            return Ok((StatusCode::ACCEPTED, Json(operation)).into_response());
+        }
+
+        let (folders, files) = clipboard::execute_copy(&state, &user.id, &plan, None)
+            .await
+            .map_err(|e| {
+                warn!("Failed to paste clipboard for user {}: {}", user.id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+        info!("User {} pasted {} copied files", user.username, files.len());
+        return Ok(Json(PasteResult { mode: ClipboardMode::Copy, files, folders }).into_response());
+    }
+
+    let mut folder_names = HashSet::new();
+    for folder in &folders {
+        if let Some(target) = &request.folder_id {
+            let cycle = database::folder_reachable_from(&state.db, target, &folder.id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if cycle {
+                return Err(StatusCode::UNPROCESSABLE_ENTITY.into());
+            }
+        }
+
+        let existing = database::get_folder_by_name(&state.db, &user.id, request.folder_id.as_ref(), &folder.name)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if existing.is_some_and(|existing| existing.id != folder.id) || !folder_names.insert(folder.name.clone()) {
+            return Err(StatusCode::CONFLICT.into());
+        }
+    }
+
+    for file in &files {
+        check_name_conflict(&state, &user.id, request.folder_id.as_ref(), &file.original_filename, Some(&file.id)).await?;
+    }
+
+    database::move_items(&state.db, &user.id, request.folder_id.as_ref(), &clipboard.file_ids, &clipboard.folder_ids)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (files, folders) = load_clipboard_items(&state, &user.id, &clipboard.file_ids, &clipboard.folder_ids)
+        .await?
+        .unwrap_or_default();
+
+    info!("User {} moved {} files and {} folders", user.username, files.len(), folders.len());
+    Ok(Json(PasteResult { mode: ClipboardMode::Cut, files, folders }).into_response())
+}
+
+async fn list_operations(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Operation>>, StatusCode> {
+    let operations = database::get_operations_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(operations))
+}
+
+async fn get_operation(
+    Path(operation_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Operation>, StatusCode> {
+    let operation = database::get_operation(&state.db, &operation_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|operation| operation.user_id == user.id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(operation))
+}
+
+async fn cancel_operation(
+    Path(operation_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let operation = database::get_operation(&state.db, &operation_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|operation| operation.user_id == user.id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let operation = database::request_operation_cancel(&state.db, &operation.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::CONFLICT)?;
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn list_folders(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Folder>>, StatusCode> {
+    let folders = database::get_folders_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(folders))
+}
+
+const FOLDER_COLORS: &[&str] = &["red", "orange", "yellow", "green", "teal", "blue", "purple", "pink", "gray"];
+
+fn is_valid_folder_color(color: &str) -> bool {
+    if FOLDER_COLORS.contains(&color) {
+        return true;
+    }
+
+    match color.strip_prefix('#') {
+        Some(hex) => (hex.len() == 3 || hex.len() == 6) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+fn is_valid_folder_icon(icon: &str) -> bool {
+    !icon.is_empty()
+        && icon.len() <= 64
+        && icon.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+async fn update_folder(
+    Path(folder_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<UpdateFolderRequest>,
+) -> Result<Json<Folder>, StatusCode> {
+    let folder = access::authorize_folder(&state, &user, &folder_id, Permission::Write).await?;
+
+    let color = match request.color.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(color) if is_valid_folder_color(color) => Some(color.to_lowercase()),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+        None => folder.color,
+    };
+
+    let icon = match request.icon.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(icon) if is_valid_folder_icon(icon) => Some(icon.to_string()),
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+        None => folder.icon,
+    };
+
+    let updated = database::update_folder_appearance(&state.db, &folder.id, color.as_deref(), icon.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(updated))
+}
+
+async fn create_alias(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateAliasRequest>,
+) -> Result<Json<Alias>, StatusCode> {
+    if let Some(folder_id) = &request.folder_id {
+        let folder = database::get_folder_by_id(&state.db, folder_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if folder.map(|f| f.user_id) != Some(user.id) {
+            return Err(StatusCode::NOT_FOUND);
+        }
+    }
+
+    let target_name = match (&request.target_file_id, &request.target_folder_id) {
+        (Some(file_id), None) => {
+            let file = database::get_file_by_id(&state.db, file_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            if file.user_id != user.id || file.is_deleted {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            file.original_filename
+        }
+        (None, Some(target_folder_id)) => {
+            let folder = database::get_folder_by_id(&state.db, target_folder_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            if folder.user_id != user.id {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            if let Some(location) = &request.folder_id {
+                let cyclic = database::folder_reachable_from(&state.db, location, target_folder_id)
+                    .await
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                if cyclic {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+            }
+            folder.name
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let name = request
+        .name
+        .as_deref()
+        .map(str::trim)
+        .unwrap_or(&target_name)
+        .to_string();
+    if name.is_empty() || name.contains('/') {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let alias = database::create_alias(
+        &state.db,
+        &user.id,
+        request.folder_id.as_ref(),
+        &name,
+        request.target_file_id.as_ref(),
+        request.target_folder_id.as_ref(),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(alias))
+}
+
+async fn list_aliases(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<Alias>>, StatusCode> {
+    let aliases = database::get_aliases_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(aliases))
+}
+
+async fn delete_alias(
+    Path(alias_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = database::delete_alias(&state.db, &alias_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+// An alias outlives its target's ownership and trash state, so the target is authorized
+// again on every use.
+async fn alias_target_file(state: &AppState, user: &models::User, file_id: &Uuid) -> Result<FileInfo, StatusCode> {
+    let file = access::authorize_file(state, user, file_id, Permission::Read).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(file)
+}
+
+async fn download_alias(
+    Path(alias_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Response<Body>, StatusCode> {
+    let alias = database::get_alias(&state.db, &alias_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if alias.user_id != user.id {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let file_id = alias.target_file_id.ok_or(StatusCode::BAD_REQUEST)?;
+    let file = alias_target_file(&state, &user, &file_id).await?;
+
+    if let Some(response) = egress_limit_response(&state, &file, None, file.file_size).await? {
+        return Ok(response);
+    }
+
+    let response = file_download_response(&state, &file).await?;
+    let _ = database::touch_file_access(&state.db, &file.id).await;
+    record_download(&state, &file, &user);
+    record_activity(&state, user.id, &file, FileActivityKind::Download);
+    Ok(response)
+}
+
+async fn create_import_job(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateImportRequest>,
+) -> Result<Json<ImportJob>, StatusCode> {
+    let target_user_id = match request.user_id {
+        Some(user_id) if user_id != user.id => {
+            if !user.is_admin {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            tenants::managed_user(&state, &user, &user_id).await?.id
+        }
+        _ => user.id,
+    };
+
+    let (archive, encoding, archive_name) = match (request.file_id, request.server_path.as_deref()) {
+        (Some(file_id), None) => {
+            let file = database::get_file_by_id(&state.db, &file_id)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .ok_or(StatusCode::NOT_FOUND)?;
+            if file.user_id != user.id || file.is_deleted {
+                return Err(StatusCode::NOT_FOUND);
+            }
+            (std::path::PathBuf::from(&file.file_path), file.encoding(), file.original_filename)
+        }
+        (None, Some(server_path)) => {
+            if !user.is_admin || user.tenant_id.is_some() {
+                return Err(StatusCode::FORBIDDEN);
+            }
+            let import_root = state.config.import_path.as_deref().ok_or(StatusCode::BAD_REQUEST)?;
+            let path = import::resolve_server_path(import_root, server_path).ok_or(StatusCode::NOT_FOUND)?;
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default();
+            (path, Some(StorageEncoding::PLAIN), name)
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let format = import::detect_format(&archive_name).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+    let contents = {
+        let archive = archive.clone();
+        state.file_storage
+            .blocking(move |storage| import::list_contents(storage.open_file(&archive, encoding)?, format))
+            .await
+            .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?
+    };
+    check_upload_quota(&state, &target_user_id, contents.total_size as i64).await?;
+
+    let target_folder = request
+        .target_folder
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| import::default_target_folder(request.kind, &archive_name));
+
+    let job = database::create_import_job(
+        &state.db,
+        &target_user_id,
+        request.kind.map(|kind| kind.as_str()),
+        &archive.to_string_lossy(),
+        &target_folder,
+        &user.id,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tokio::spawn(import::run_import(state.clone(), job.clone(), archive, encoding, format, request.kind, None));
+
+    Ok(Json(job))
+}
+
+async fn get_file_downloads(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<FileDownloadAudit>, StatusCode> {
+    if !state.config.download_audit_visible {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let file = database::get_file_by_id(&state.db, &file_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    if file.user_id != user.id {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let downloads = database::get_file_downloads(&state.db, &file.id, MAX_AUDITED_DOWNLOADS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let public_download_counts = database::get_share_download_counts(&state.db, &file.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(FileDownloadAudit {
+        file_id: file.id,
+        downloads,
+        public_downloads: public_download_counts.iter().map(|count| count.downloads as i64).sum(),
+        public_download_counts,
+    }))
+}
+
+async fn get_archive_contents(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<ArchiveContents>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+    let format = import::detect_file_format(&file).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let (archive, encoding) = (std::path::PathBuf::from(&file.file_path), file.encoding());
+    let file_storage = state.file_storage.clone();
+    let contents = tokio::task::spawn_blocking(move || import::list_contents(file_storage.open_file(&archive, encoding)?, format))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            warn!("Failed to list the contents of archive {}: {}", file_id, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        })?;
+
+    Ok(Json(contents))
+}
+
+async fn extract_archive_file(
+    Path(file_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<ExtractArchiveRequest>,
+) -> Result<Json<ImportJob>, StatusCode> {
+    let file = access::authorize_file(&state, &user, &file_id, Permission::Read).await?;
+    if file.is_deleted {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if file.is_quarantined {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let format = import::detect_file_format(&file).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let parent_id = request.folder_id.or(file.folder_id);
+    let owner_id = access::destination_owner(&state, &user, parent_id.as_ref()).await?;
+    let folder_name = request
+        .folder_name
+        .as_deref()
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| import::default_target_folder(Some(ImportKind::Archive), &file.original_filename));
+
+    let (archive, encoding) = (std::path::PathBuf::from(&file.file_path), file.encoding());
+    let contents = {
+        let archive = archive.clone();
+        let file_storage = state.file_storage.clone();
+        tokio::task::spawn_blocking(move || import::list_contents(file_storage.open_file(&archive, encoding)?, format))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?
+    };
+    check_upload_quota(&state, &owner_id, contents.total_size as i64).await?;
+
+    let job = database::create_import_job(
+        &state.db,
+        &owner_id,
+        Some(ImportKind::Archive.as_str()),
+        &file.file_path,
+        &folder_name,
+        &user.id,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    tokio::spawn(import::run_import(state.clone(), job.clone(), archive, encoding, format, Some(ImportKind::Archive), parent_id));
+
+    Ok(Json(job))
+}
+
+async fn list_import_jobs(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ImportJob>>, StatusCode> {
+    let jobs = database::get_import_jobs_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(jobs))
+}
+
+async fn get_import_job(
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<ImportJob>, StatusCode> {
+    let job = database::get_import_job(&state.db, &job_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if job.user_id != user.id && job.created_by != user.id {
+        if !user.is_admin {
+            return Err(StatusCode::NOT_FOUND);
+        }
+        tenants::managed_user(&state, &user, &job.user_id).await?;
+    }
+
+    Ok(Json(job))
+}
+
+async fn create_export_job(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<CreateExportJobRequest>,
+) -> Result<Json<ExportJob>, StatusCode> {
+    if request.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // Whole-instance exports and server-side destinations are for instance
+    // admins only; a tenant admin must not reach other tenants or the host.
+    let instance_admin = user.is_admin && user.tenant_id.is_none();
+    let all_users = request.all_users.unwrap_or(false);
+    if all_users && !instance_admin {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    if let ExportDestination::Local { .. } | ExportDestination::Rsync { .. } = request.destination {
+        if !instance_admin {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    let schedule = export::normalize_schedule(&request.schedule).ok_or(StatusCode::BAD_REQUEST)?;
+    let next_run_at = export::next_run_after(&schedule, chrono::Utc::now());
+    let owner = if all_users { None } else { Some(&user.id) };
+
+    let job = database::create_export_job(
+        &state.db,
+        owner,
+        request.name.trim(),
+        &request.destination,
+        &schedule,
+        next_run_at,
+        &user.id,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(job))
+}
+
+async fn list_export_jobs(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ExportJob>>, StatusCode> {
+    let jobs = database::get_export_jobs_by_user(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(jobs))
+}
+
+async fn admin_list_export_jobs(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ExportJob>>, StatusCode> {
+    let jobs = database::get_all_export_jobs(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(jobs))
+}
+
+async fn get_manageable_export_job(
+    state: &AppState,
+    job_id: &Uuid,
+    user: &models::User,
+) -> Result<ExportJob, StatusCode> {
+    let job = database::get_export_job(&state.db, job_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let owner = match &job.user_id {
+        Some(owner_id) => database::get_user_by_id(&state.db, owner_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        None => None,
+    };
+    if !export::can_manage(&job, user, owner.as_ref()) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(job)
+}
+
+async fn delete_export_job(
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let job = get_manageable_export_job(&state, &job_id, &user).await?;
+
+    database::delete_export_job(&state.db, &job.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn run_export_job(
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let job = get_manageable_export_job(&state, &job_id, &user).await?;
+
+    let operation = database::create_operation(&state.db, &user.id, "export", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = export::run_job(&state, job, Some(&progress))
+            .await
+            .map(|(files, bytes)| serde_json::json!({ "files_exported": files, "bytes_exported": bytes }));
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn list_export_runs(
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ExportRun>>, StatusCode> {
+    let job = get_manageable_export_job(&state, &job_id, &user).await?;
+
+    let runs = database::get_export_runs(&state.db, &job.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(runs))
+}
+
+async fn list_archive_parts(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<Vec<ArchivePart>>, StatusCode> {
+    let parts = database::get_archive_parts(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(parts))
+}
+
+async fn ensure_archive_idle(state: &AppState, user: &User) -> Result<(), StatusCode> {
+    let parts = database::get_archive_parts(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if parts.iter().any(|part| part.status == "building") {
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(())
+}
+
+async fn build_archive(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    ensure_archive_idle(&state, &user).await?;
+
+    let operation = database::create_operation(&state.db, &user.id, "archive", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = archive::build(&state, &user, &progress).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn delete_archive(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    ensure_archive_idle(&state, &user).await?;
+
+    let parts = database::delete_archive_parts(&state.db, &user.id, false)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    archive::remove_files(&parts).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn download_archive_part(
+    Path(part_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, StatusCode> {
+    let part = database::get_archive_part(&state.db, &part_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .filter(|part| part.user_id == user.id && part.status == "ready")
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let archive_path = part.archive_path.as_deref().ok_or(StatusCode::NOT_FOUND)?;
+
+    let mut file = tokio::fs::File::open(archive_path).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let size = file.metadata().await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.len();
+    let etag = format!("\"{}\"", part.id);
+
+    let range_applies = headers
+        .get(header::IF_RANGE)
+        .is_none_or(|value| value.to_str().is_ok_and(|value| value == etag));
+    let range = match headers.get(header::RANGE).and_then(|value| value.to_str().ok()) {
+        Some(range) if range_applies => match parse_byte_range(range, size) {
+            Some(range) => Some(range),
+            None => {
+                return Response::builder()
+                    .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                    .body(Body::empty())
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        },
+        _ => None,
+    };
+
+    let (status, start, length) = match range {
+        Some((start, end)) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        None => (StatusCode::OK, 0, size),
+    };
+    file.seek(std::io::SeekFrom::Start(start))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_transfer(&state, user.id, 0, length as i64);
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/zip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"localdrive-{}-part-{:04}.zip\"", user.username, part.sequence)
+        )
+        .header(header::CONTENT_LENGTH, length)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, etag)
+        .header(header::LAST_MODIFIED, http_date(&part.finished_at.unwrap_or(part.created_at)));
+    if let Some((start, end)) = range {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, size));
+    }
+
+    response
+        .body(Body::from_stream(tokio_util::io::ReaderStream::new(file.take(length))))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+#[cfg(feature = "chaos")]
+async fn get_chaos_faults() -> Json<ChaosFaults> {
+    Json(chaos::faults())
+}
+
+#[cfg(feature = "chaos")]
+async fn update_chaos_faults(Json(request): Json<UpdateChaosFaults>) -> Json<ChaosFaults> {
+    Json(chaos::update(request))
+}
+
+#[cfg(feature = "chaos")]
+async fn reset_chaos_faults() -> Json<ChaosFaults> {
+    Json(chaos::reset())
+}
+
+async fn test_notification_channels(State(state): State<AppState>) -> Result<Json<Vec<ChannelTestResult>>, StatusCode> {
+    let results = channels::test(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(results))
+}
+
+async fn list_scrub_findings(State(state): State<AppState>) -> Result<Json<Vec<ScrubFinding>>, StatusCode> {
+    let findings = database::get_scrub_findings(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(findings))
+}
+
+async fn start_scrub(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    if scrub::is_running() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, "integrity_scrub", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = scrub::run(&state, Some(&progress)).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+fn validate_lifecycle_rule(request: &LifecycleRuleRequest) -> Result<Option<String>, StatusCode> {
+    if request.name.trim().is_empty() || request.idle_days < 1 || request.min_size.is_some_and(|size| size < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(request
+        .mime_prefix
+        .as_deref()
+        .map(|prefix| prefix.trim().to_ascii_lowercase())
+        .filter(|prefix| !prefix.is_empty()))
+}
+
+async fn list_lifecycle_rules(State(state): State<AppState>) -> Result<Json<Vec<LifecycleRule>>, StatusCode> {
+    let rules = database::get_lifecycle_rules(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rules))
+}
+
+async fn create_lifecycle_rule(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<LifecycleRuleRequest>,
+) -> Result<Json<LifecycleRule>, StatusCode> {
+    let mime_prefix = validate_lifecycle_rule(&request)?;
+
+    let rule = database::create_lifecycle_rule(
+        &state.db,
+        request.name.trim(),
+        request.idle_days,
+        request.min_size,
+        mime_prefix.as_deref(),
+        request.is_enabled.unwrap_or(true),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Admin {} created lifecycle rule {} ({})", admin.username, rule.id, rule.name);
+
+    Ok(Json(rule))
+}
+
+async fn update_lifecycle_rule(
+    Path(rule_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<LifecycleRuleRequest>,
+) -> Result<Json<LifecycleRule>, StatusCode> {
+    let mime_prefix = validate_lifecycle_rule(&request)?;
+
+    let rule = database::update_lifecycle_rule(
+        &state.db,
+        &rule_id,
+        request.name.trim(),
+        request.idle_days,
+        request.min_size,
+        mime_prefix.as_deref(),
+        request.is_enabled.unwrap_or(true),
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    info!("Admin {} updated lifecycle rule {} ({})", admin.username, rule.id, rule.name);
+
+    Ok(Json(rule))
+}
+
+async fn delete_lifecycle_rule(
+    Path(rule_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    if !database::delete_lifecycle_rule(&state.db, &rule_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    info!("Admin {} deleted lifecycle rule {}", admin.username, rule_id);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn start_lifecycle_rules(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    if state.config.s3.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if tiering::is_running() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, "lifecycle_rules", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = tiering::run(&state, Some(&progress)).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+const SEARCH_REINDEX_OPERATION: &str = "search_reindex";
+
+async fn get_search_index_status(State(state): State<AppState>) -> Result<Json<SearchIndexStatus>, StatusCode> {
+    let stats = content_index::stats(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let last_rebuild = database::get_latest_operation(&state.db, SEARCH_REINDEX_OPERATION)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(SearchIndexStatus {
+        content_indexing: state.config.content_indexing,
+        rebuilding: content_index::is_rebuilding(),
+        stats,
+        last_rebuild,
+    }))
+}
+
+async fn start_search_reindex(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    if content_index::is_rebuilding() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, SEARCH_REINDEX_OPERATION, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = content_index::rebuild(&state, &progress).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+const DEFAULT_PLACEMENT_DECISIONS: usize = 50;
+
+async fn get_placement_decisions(
+    State(state): State<AppState>,
+    Query(query): Query<PlacementDecisionQuery>,
+) -> Json<Vec<PlacementDecision>> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PLACEMENT_DECISIONS)
+        .clamp(1, file_storage::MAX_PLACEMENT_DECISIONS);
+
+    Json(state.file_storage.placement_decisions(limit, query.failed_only))
+}
+
+async fn get_admin_info(
+    State(state): State<AppState>,
+) -> Result<Json<AdminInfo>, StatusCode> {
+    let database_version = database::get_server_version(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let disks = state.file_storage.get_disk_info()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(AdminInfo {
+        version: build_info::VERSION.to_string(),
+        build_hash: build_info::BUILD_HASH.to_string(),
+        features: feature_flags(&state.config),
+        services: RuntimeServices {
+            content_indexing: state.config.content_indexing,
+            alerts: state.config.alerts_enabled,
+            provisioning: state.config.provisioning_token.is_some(),
+            smtp: state.config.smtp_host.is_some(),
+            virus_scanning: state.config.clamd_address.is_some(),
+        },
+        storage: StorageBackendInfo {
+            kind: "local".to_string(),
+            paths: state
+                .file_storage
+                .storage_paths()
+                .iter()
+                .map(|path| path.to_string_lossy().to_string())
+                .collect(),
+            disks,
+        },
+        database_version,
+        started_at: state.runtime.started_at,
+        uptime_seconds: state.runtime.uptime_seconds(),
+        scheduled_jobs: state.runtime.jobs(),
+    }))
+}
+
+async fn get_storage_info(
+    State(state): State<AppState>,
+) -> Result<Json<StorageInfo>, StatusCode> {
+    let file_storage = state.file_storage.clone();
+    let storage_info = tokio::task::spawn_blocking(move || file_storage.get_storage_info_with_health())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(storage_info))
+}
+
+const STORAGE_REBALANCE_OPERATION: &str = "storage_rebalance";
+
+async fn get_storage_rebalance_status(State(state): State<AppState>) -> Result<Json<RebalanceStatus>, StatusCode> {
+    let disks = state.file_storage.get_disk_info()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let last_run = database::get_latest_operation(&state.db, STORAGE_REBALANCE_OPERATION)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(RebalanceStatus {
+        running: rebalance::is_running(),
+        disks,
+        last_run,
+    }))
+}
+
+async fn start_storage_rebalance(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    request: Option<Json<RebalanceRequest>>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let tolerance_percent = request
+        .and_then(|Json(request)| request.tolerance_percent)
+        .unwrap_or(rebalance::DEFAULT_TOLERANCE_PERCENT);
+    if !(0.0..=100.0).contains(&tolerance_percent) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if rebalance::is_running() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, STORAGE_REBALANCE_OPERATION, None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = rebalance::run(&state, tolerance_percent, Some(&progress)).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn start_disk_evacuation(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<StoragePathRequest>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let disk_path = state.file_storage.storage_root(&request.path).ok_or(StatusCode::BAD_REQUEST)?;
+    if rebalance::is_running() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, "disk_evacuation", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = rebalance::evacuate_disk(&state, &disk_path, Some(&progress)).await;
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+async fn add_storage_path(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<StoragePathRequest>,
+) -> Result<Json<DiskInfo>, StatusCode> {
+    if state.file_storage.storage_root(&request.path).is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+    let disk_path = state.file_storage.add_storage_path(&request.path).map_err(|e| {
+        warn!("Rejected storage path {}: {:#}", request.path, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let path = disk_path.to_string_lossy().to_string();
+    database::save_storage_path_change(&state.db, &path, false)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tenants::refresh_storage_roots(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let disk_info = state.file_storage.get_single_disk_info(&disk_path, 0)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Admin {} added storage path {}", admin.username, path);
+
+    Ok(Json(disk_info))
+}
+
+async fn remove_storage_path(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<StoragePathRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let disk_path = state.file_storage.storage_root(&request.path).ok_or(StatusCode::NOT_FOUND)?;
+    let path = disk_path.to_string_lossy().to_string();
+    let files = database::count_files_on_disk(&state.db, &path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let uploads = database::count_active_uploads_on_disk(&state.db, &path)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if files > 0 || uploads > 0 {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    state.file_storage.remove_storage_path(&disk_path).map_err(|e| {
+        warn!("Could not remove storage path {}: {:#}", path, e);
+        StatusCode::BAD_REQUEST
+    })?;
+    database::save_storage_path_change(&state.db, &path, true)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tenants::refresh_storage_roots(&state)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Admin {} removed storage path {}", admin.username, path);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+const MAX_LISTED_GC_RUNS: i64 = 30;
+
+async fn get_orphan_gc_report(State(state): State<AppState>) -> Result<Json<OrphanGcReport>, StatusCode> {
+    let runs = database::get_orphan_gc_runs(&state.db, MAX_LISTED_GC_RUNS)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let total_reclaimed_bytes = database::get_orphan_gc_reclaimed_bytes(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(OrphanGcReport {
+        enabled: state.config.orphan_gc_enabled,
+        action: gc::action(&state),
+        grace_hours: state.config.orphan_gc_grace_hours,
+        total_reclaimed_bytes,
+        runs,
+    }))
+}
+
+async fn start_storage_verify(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    request: Option<Json<FsckRequest>>,
+) -> Result<(StatusCode, Json<Operation>), StatusCode> {
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    if fsck::is_running() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let operation = database::create_operation(&state.db, &user.id, "storage_verify", None)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let progress = operations::Progress::new(state.db.clone(), operation.id);
+
+    tokio::spawn(async move {
+        let result = fsck::run(&state, &request, Some(&progress))
+            .await
+            .and_then(|report| Ok(serde_json::to_value(report)?));
+        operations::finish(&state.db, &progress.operation_id, result).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(operation)))
+}
+
+const MIN_PASSWORD_LENGTH: usize = 8;
+
+fn verify_current_password(user: &models::User, password: &str) -> Result<(), StatusCode> {
+    match auth::verify_password(password, &user.password_hash) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(StatusCode::FORBIDDEN),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn get_user_transfers(
+    Query(query): Query<TransferQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<UserTransfers>, StatusCode> {
+    let today = chrono::Utc::now().date_naive();
+    let days = query.days.unwrap_or(30).clamp(1, 366);
+    let since = today - chrono::Duration::days(days - 1);
+    let month_start = egress::month_start(chrono::Utc::now());
+
+    let history = database::get_user_transfers(&state.db, &user.id, since)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (month_uploaded, month_downloaded) = database::get_user_transfer_totals(&state.db, &user.id, month_start)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let month_egress_limit = egress::user_limit(&state, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(UserTransfers {
+        month_start,
+        month_egress_limit,
+        month_uploaded,
+        month_downloaded,
+        days: history,
+    }))
+}
+
+async fn get_user_profile(
+    Extension(user): Extension<models::User>,
+) -> Json<PublicUser> {
+    Json(user.into())
+}
+
+async fn update_user_profile(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<UpdateProfileRequest>,
+) -> Result<Json<PublicUser>, StatusCode> {
+    verify_current_password(&user, &request.current_password)?;
+
+    let email = match request.email.as_deref().map(str::trim) {
+        Some(email) if email.len() > 255 || !email.contains('@') || email.starts_with('@') || email.ends_with('@') => {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Some(email) => email.to_string(),
+        None => return Ok(Json(user.into())),
+    };
+
+    if email != user.email {
+        let existing = database::get_user_by_email(&state.db, &email)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if existing.is_some_and(|existing| existing.id != user.id) {
+            return Err(StatusCode::CONFLICT);
+        }
+    }
+
+    let updated = database::update_user_email(&state.db, &user.id, &email)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("User {} updated their email address", user.username);
+    Ok(Json(updated.into()))
+}
+
+async fn change_user_password(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Extension(session): Extension<auth::CurrentSession>,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, StatusCode> {
+    verify_current_password(&user, &request.current_password)?;
+
+    if request.new_password.chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let password_hash = auth::hash_password(&request.new_password)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    database::update_user_password(&state.db, &user.id, &password_hash)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let revoked = database::revoke_other_sessions(&state.db, &user.id, &session.0)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("User {} changed their password, {} other sessions revoked", user.username, revoked);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_user_sessions(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Extension(current): Extension<auth::CurrentSession>,
+) -> Result<Json<Vec<SessionInfo>>, StatusCode> {
+    let sessions = database::get_active_sessions(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionInfo { current: session.id == current.0, session })
+            .collect(),
+    ))
+}
+
+async fn revoke_user_session(
+    Path(session_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    let revoked = database::revoke_session(&state.db, &session_id, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !revoked {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    info!("User {} revoked session {}", user.username, session_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_user_storage_info(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<UserStorageInfo>, StatusCode> {
+    let usage = database::get_user_storage_usage(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let quota = database::get_user_quota(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let storage_info = state.file_storage.get_storage_info()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let charged = usage.used_bytes + usage.trash_bytes;
+    let disk_available = i64::try_from(storage_info.available_space).unwrap_or(i64::MAX);
+    let quota_bytes = quota.quota_hard_bytes.or(quota.quota_soft_bytes);
+    let remaining_bytes = match quota_bytes {
+        Some(limit) => (limit - charged).clamp(0, disk_available),
+        None => disk_available,
+    };
+
+    let capacity = quota_bytes.unwrap_or(charged.saturating_add(remaining_bytes));
+    let usage_percentage = if capacity > 0 {
+        ((charged as f64 / capacity as f64) * 100.0).clamp(0.0, 100.0) as u8
+    } else {
+        0
+    };
+
+    Ok(Json(UserStorageInfo {
+        used_bytes: usage.used_bytes,
+        trash_bytes: usage.trash_bytes,
+        file_count: usage.file_count,
+        trash_count: usage.trash_count,
+        quota_bytes,
+        remaining_bytes,
+        usage_percentage,
+    }))
+}
+
+async fn get_user_quota_status(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<QuotaStatus>, StatusCode> {
+    let quota = database::get_user_quota(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(quota_status(&quota, state.config.quota_grace_period_days)))
+}
+
+async fn set_user_egress(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<SetUserEgressRequest>,
+) -> Result<Json<EgressSettings>, StatusCode> {
+    if request.limit_bytes.is_some_and(|limit| limit < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    let updated = database::set_user_egress_settings(&state.db, &user_id, request.limit_bytes, request.unlimited)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if !updated {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let effective_limit_bytes = egress::user_limit(&state, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(EgressSettings {
+        limit_bytes: request.limit_bytes,
+        unlimited: request.unlimited,
+        effective_limit_bytes,
+    }))
+}
+
+async fn create_broadcast(
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<BroadcastRequest>,
+) -> Result<(StatusCode, Json<Broadcast>), StatusCode> {
+    let subject = request.subject.trim();
+    if subject.is_empty() || subject.chars().count() > 255 || subject.contains(['\r', '\n']) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if request.body.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    match mailer::Mailer::from_config(&state.config) {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(StatusCode::SERVICE_UNAVAILABLE),
+        Err(e) => {
+            warn!("Mailer is misconfigured: {}", e);
+            return Err(StatusCode::SERVICE_UNAVAILABLE);
+        }
+    }
+
+    let broadcast = database::create_broadcast(&state.db, &admin.id, subject, &request.body)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(
+        "Admin {} queued broadcast '{}' to {} users",
+        admin.username, broadcast.subject, broadcast.total_recipients
+    );
+
+    tokio::spawn(async move {
+        broadcast::process(&state).await;
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(broadcast)))
+}
+
+async fn list_broadcasts(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Broadcast>>, StatusCode> {
+    let broadcasts = database::get_broadcasts(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(broadcasts))
+}
+
+async fn get_broadcast(
+    Path(broadcast_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Broadcast>, StatusCode> {
+    let broadcast = database::get_broadcast(&state.db, &broadcast_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(broadcast))
+}
+
+async fn list_broadcast_recipients(
+    Path(broadcast_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<BroadcastRecipient>>, StatusCode> {
+    database::get_broadcast(&state.db, &broadcast_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let recipients = database::get_broadcast_recipients(&state.db, &broadcast_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(recipients))
+}
+
+async fn set_user_trash_limit(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<SetTrashLimitRequest>,
+) -> Result<Json<TrashSettings>, StatusCode> {
+    if request.limit_bytes.is_some_and(|limit| limit < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    if !database::set_user_trash_limit(&state.db, &user_id, request.limit_bytes)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    trash::enforce_limit_quietly(&state, &user_id).await;
+
+    Ok(Json(TrashSettings {
+        limit_bytes: request.limit_bytes,
+        effective_limit_bytes: request.limit_bytes.or(state.config.trash_size_limit),
+    }))
+}
+
+const MAX_PROVISIONED_GROUPS: usize = 32;
+
+fn valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.chars().count() <= 64
+        && username.chars().all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+}
+
+fn valid_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.contains('@')
+                && email.len() <= 255
+                && !email.chars().any(|c| c.is_whitespace() || c.is_control())
+        }
+        None => false,
+    }
+}
+
+async fn provision_user(
+    State(state): State<AppState>,
+    Json(request): Json<ProvisionUserRequest>,
+) -> Result<(StatusCode, Json<ProvisionedUser>), StatusCode> {
+    let username = request.username.trim();
+    let email = request.email.trim();
+    if !valid_username(username) || !valid_email(email) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if request.password.as_ref().is_some_and(|password| password.chars().count() < MIN_PASSWORD_LENGTH) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let (Some(soft), Some(hard)) = (request.soft_limit, request.hard_limit) {
+        if soft > hard {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    if request.soft_limit.is_some_and(|l| l < 0) || request.hard_limit.is_some_and(|l| l < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut groups: Vec<String> = Vec::new();
+    for group in &request.groups {
+        let group = group.trim();
+        if group.is_empty() || group.len() > 255 || group.chars().any(char::is_control) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        if !groups.iter().any(|existing| existing == group) {
+            groups.push(group.to_string());
+        }
+    }
+    if groups.len() > MAX_PROVISIONED_GROUPS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let password = request.password.unwrap_or_else(auth::generate_secret);
+    let password_hash = auth::hash_password(&password).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let user = database::provision_user(
+        &state.db,
+        username,
+        email,
+        &password_hash,
+        request.soft_limit,
+        request.hard_limit,
+        &groups,
+    )
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::CONFLICT)?;
+
+    let quota = database::get_user_quota(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    let groups = database::get_user_groups(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Provisioned user {} with groups {:?}", user.username, groups);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ProvisionedUser {
+            user: user.into(),
+            quota: quota_status(&quota, state.config.quota_grace_period_days),
+            groups,
+        }),
+    ))
+}
+
+async fn scim_service_provider_config() -> ScimJson<serde_json::Value> {
+    ScimJson(StatusCode::OK, scim::service_provider_config())
+}
+
+async fn scim_resource_types() -> ScimJson<serde_json::Value> {
+    ScimJson(StatusCode::OK, scim::resource_types())
+}
+
+async fn load_scim_user(state: &AppState, user_id: &Uuid) -> Result<scim::UserResource, ScimError> {
+    let user = database::get_directory_user(&state.db, user_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("User", user_id))?;
+    let memberships = database::get_user_memberships(&state.db, &[user.id]).await?;
+    Ok(scim::user_resource(user, &memberships))
+}
+
+async fn load_scim_group(state: &AppState, group_id: &Uuid) -> Result<scim::GroupResource, ScimError> {
+    let group = database::get_group(&state.db, group_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("Group", group_id))?;
+    let memberships = database::get_group_memberships(&state.db, &[group.id]).await?;
+    Ok(scim::group_resource(group, &memberships))
+}
+
+fn scim_identity(username: &str, email: &str) -> Result<(String, String), ScimError> {
+    let username = username.trim();
+    let email = email.trim();
+    if !valid_username(username) {
+        return Err(ScimError::invalid_value(format!("invalid userName \"{}\"", username)));
+    }
+    if !valid_email(email) {
+        return Err(ScimError::invalid_value(format!("invalid email \"{}\"", email)));
+    }
+    Ok((username.to_string(), email.to_string()))
+}
+
+fn scim_password_hash(password: Option<&str>) -> Result<Option<String>, ScimError> {
+    match password {
+        Some(password) if password.chars().count() < MIN_PASSWORD_LENGTH => Err(ScimError::invalid_value(format!(
+            "password must be at least {} characters",
+            MIN_PASSWORD_LENGTH
+        ))),
+        Some(password) => Ok(Some(auth::hash_password(password)?)),
+        None => Ok(None),
     }
+}
 
-    let argon2 = Argon2::default();
-    let salt = SaltString::generate(&mut OsRng);
-    let password_hash = argon2.hash_password(password.as_bytes(), &salt)
-        .map_err(|e| anyhow::anyhow!("Failed to hash password: {}", e))?
-        .to_string();
-
-    let user = database::create_user(db, username, email, &password_hash, true).await?;
-    println!("Admin user created successfully!");
-    println!("Username: {}", user.username);
-    println!("Email: {}", user.email);
-    println!("User ID: {}", user.id);
+// The provisioning token belongs to an identity provider, not to an instance
+// administrator, so it cannot rename, re-password or deactivate admins.
+fn check_scim_managed(user: &DirectoryUser) -> Result<(), ScimError> {
+    if user.is_admin {
+        return Err(ScimError::forbidden("admin accounts cannot be changed through SCIM"));
+    }
+    Ok(())
+}
 
+async fn set_scim_user_active(state: &AppState, user: &DirectoryUser, active: bool) -> Result<(), ScimError> {
+    let changed = if active {
+        database::reactivate_user(&state.db, &user.id).await?
+    } else {
+        database::deactivate_user(&state.db, &user.id).await?
+    };
+    if changed {
+        info!("SCIM {} user {}", if active { "reactivated" } else { "deactivated" }, user.username);
+    }
     Ok(())
 }
 
-async fn health_check() -> Json<serde_json::Value> {
-    Json(serde_json::json!({
-        "status": "ok",
-        "timestamp": chrono::Utc::now()
-    }))
+async fn scim_list_users(
+    Query(query): Query<scim::ListQuery>,
+    State(state): State<AppState>,
+) -> Result<ScimJson<scim::ListResponse<scim::UserResource>>, ScimError> {
+    let filter = match &query.filter {
+        Some(filter) => {
+            let (attribute, value) = scim::parse_filter(filter)?;
+            Some((scim::user_filter_column(&attribute)?, value))
+        }
+        None => None,
+    };
+
+    let (total, users) = database::list_directory_users(
+        &state.db,
+        filter.as_ref().map(|(column, value)| (*column, value.as_str())),
+        query.offset(),
+        query.limit(),
+    )
+    .await?;
+    let user_ids: Vec<Uuid> = users.iter().map(|user| user.id).collect();
+    let memberships = database::get_user_memberships(&state.db, &user_ids).await?;
+
+    let resources = users.into_iter().map(|user| scim::user_resource(user, &memberships)).collect();
+    Ok(ScimJson(StatusCode::OK, scim::list(total, query.offset(), resources)))
 }
 
-async fn login(
+async fn scim_create_user(
     State(state): State<AppState>,
-    Json(request): Json<LoginRequest>,
-) -> Result<Json<AuthResponse>, StatusCode> {
-    let user = database::get_user_by_username(&state.db, &request.username)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+    Json(request): Json<scim::UserRequest>,
+) -> Result<ScimJson<scim::UserResource>, ScimError> {
+    let email = request.email().ok_or_else(|| ScimError::invalid_value("an email address is required"))?;
+    let (username, email) = scim_identity(&request.user_name, email)?;
+    let active = request.active()?;
+    let password_hash = match scim_password_hash(request.password.as_deref())? {
+        Some(hash) => hash,
+        None => auth::hash_password(&auth::generate_secret())?,
+    };
 
-    if !auth::verify_password(&request.password, &user.password_hash)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    {
-        return Err(StatusCode::UNAUTHORIZED);
+    if database::directory_identity_taken(&state.db, None, &username, &email).await? {
+        return Err(ScimError::uniqueness("a user with this userName or email already exists"));
     }
 
-    let token = auth::create_jwt_token(&user.id, &user.username, user.is_admin, &state.config.jwt_secret)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let user = database::create_directory_user(
+        &state.db,
+        &username,
+        &email,
+        &password_hash,
+        request.external_id.as_deref(),
+        active,
+    )
+    .await?
+    .ok_or_else(|| ScimError::uniqueness("a user with this userName or email already exists"))?;
 
-    Ok(Json(AuthResponse { token, user }))
+    info!("SCIM created user {}", user.username);
+    Ok(ScimJson(StatusCode::CREATED, scim::user_resource(user, &[])))
 }
 
-
-
-async fn list_files(
+async fn scim_get_user(
+    Path(user_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<FileInfo>>, StatusCode> {
-    let files = database::get_all_files(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(files))
+) -> Result<ScimJson<scim::UserResource>, ScimError> {
+    Ok(ScimJson(StatusCode::OK, load_scim_user(&state, &user_id).await?))
 }
 
-async fn download_file(
-    Path(file_id): Path<Uuid>,
+async fn scim_replace_user(
+    Path(user_id): Path<Uuid>,
     State(state): State<AppState>,
-    Extension(user): Extension<models::User>,
-) -> Result<Response<Body>, StatusCode> {
-    let file = database::get_file_by_id(&state.db, &file_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+    Json(request): Json<scim::UserRequest>,
+) -> Result<ScimJson<scim::UserResource>, ScimError> {
+    let email = request.email().ok_or_else(|| ScimError::invalid_value("an email address is required"))?;
+    let (username, email) = scim_identity(&request.user_name, email)?;
+    let active = request.active()?;
+    let password_hash = scim_password_hash(request.password.as_deref())?;
 
-    if file.user_id != user.id {
-        return Err(StatusCode::FORBIDDEN);
+    let existing = database::get_directory_user(&state.db, &user_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("User", &user_id))?;
+    check_scim_managed(&existing)?;
+    if database::directory_identity_taken(&state.db, Some(&user_id), &username, &email).await? {
+        return Err(ScimError::uniqueness("a user with this userName or email already exists"));
     }
 
-    let file_data = state.file_storage
-        .get_file_data(&file.file_path)
-        .map_err(|_| StatusCode::NOT_FOUND)?;
+    let user = database::update_directory_user(&state.db, &user_id, &username, &email, request.external_id.as_deref())
+        .await?
+        .ok_or_else(|| ScimError::not_found("User", &user_id))?;
+    if let Some(password_hash) = password_hash {
+        database::update_user_password(&state.db, &user.id, &password_hash).await?;
+    }
+    set_scim_user_active(&state, &user, active).await?;
 
-    let content_type = file.mime_type
-        .as_deref()
-        .unwrap_or("application/octet-stream");
+    Ok(ScimJson(StatusCode::OK, load_scim_user(&state, &user_id).await?))
+}
 
-    let response = Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, content_type)
-        .header(
-            header::CONTENT_DISPOSITION,
-            format!("attachment; filename=\"{}\"", file.original_filename)
-        )
-        .header(header::CONTENT_LENGTH, file_data.len())
-        .body(Body::from(file_data))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn scim_patch_user(
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<scim::PatchRequest>,
+) -> Result<ScimJson<scim::UserResource>, ScimError> {
+    let patch = scim::user_patch(&request)?;
+    let user = database::get_directory_user(&state.db, &user_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("User", &user_id))?;
+    check_scim_managed(&user)?;
 
-    Ok(response)
+    if patch.user_name.is_some() || patch.email.is_some() || patch.external_id.is_some() {
+        let (username, email) = scim_identity(
+            patch.user_name.as_deref().unwrap_or(&user.username),
+            patch.email.as_deref().unwrap_or(&user.email),
+        )?;
+        if database::directory_identity_taken(&state.db, Some(&user.id), &username, &email).await? {
+            return Err(ScimError::uniqueness("a user with this userName or email already exists"));
+        }
+        let external_id = patch.external_id.unwrap_or(user.scim_external_id.clone());
+        database::update_directory_user(&state.db, &user.id, &username, &email, external_id.as_deref()).await?;
+    }
+    if let Some(active) = patch.active {
+        set_scim_user_active(&state, &user, active).await?;
+    }
+
+    Ok(ScimJson(StatusCode::OK, load_scim_user(&state, &user_id).await?))
 }
 
-async fn move_to_trash(
-    Path(file_id): Path<Uuid>,
+async fn scim_delete_user(
+    Path(user_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<StatusCode, StatusCode> {
-    database::soft_delete_file(&state.db, &file_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
+) -> Result<StatusCode, ScimError> {
+    let user = database::get_directory_user(&state.db, &user_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("User", &user_id))?;
+    check_scim_managed(&user)?;
+    set_scim_user_active(&state, &user, false).await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
-async fn list_trash_files(
+async fn scim_list_groups(
+    Query(query): Query<scim::ListQuery>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<FileInfo>>, StatusCode> {
-    let files = database::get_deleted_files(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    Ok(Json(files))
+) -> Result<ScimJson<scim::ListResponse<scim::GroupResource>>, ScimError> {
+    let name = match &query.filter {
+        Some(filter) => match scim::parse_filter(filter)? {
+            (attribute, value) if attribute == "displayname" => Some(value),
+            (attribute, _) => return Err(ScimError::invalid_filter(format!("cannot filter groups by {}", attribute))),
+        },
+        None => None,
+    };
+
+    let (total, groups) = database::list_groups(&state.db, name.as_deref(), query.offset(), query.limit()).await?;
+    let group_ids: Vec<Uuid> = groups.iter().map(|group| group.id).collect();
+    let memberships = database::get_group_memberships(&state.db, &group_ids).await?;
+
+    let resources = groups.into_iter().map(|group| scim::group_resource(group, &memberships)).collect();
+    Ok(ScimJson(StatusCode::OK, scim::list(total, query.offset(), resources)))
 }
 
-async fn restore_file(
-    Path(file_id): Path<Uuid>,
+fn scim_group_name(name: &str) -> Result<String, ScimError> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > 255 || name.chars().any(char::is_control) {
+        return Err(ScimError::invalid_value(format!("invalid displayName \"{}\"", name)));
+    }
+    Ok(name.to_string())
+}
+
+async fn scim_create_group(
     State(state): State<AppState>,
-) -> Result<StatusCode, StatusCode> {
-    database::restore_file(&state.db, &file_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Json(request): Json<scim::GroupRequest>,
+) -> Result<ScimJson<scim::GroupResource>, ScimError> {
+    let name = scim_group_name(&request.display_name)?;
+    let members = scim::parse_member_ids(&request.members)?;
 
-    Ok(StatusCode::NO_CONTENT)
+    let group = database::create_group(&state.db, &name)
+        .await?
+        .ok_or_else(|| ScimError::uniqueness(format!("group \"{}\" already exists", name)))?;
+    if !members.is_empty() {
+        database::update_group_members(&state.db, &group.id, false, &members, &[]).await?;
+    }
+
+    info!("SCIM created group {}", group.name);
+    Ok(ScimJson(StatusCode::CREATED, load_scim_group(&state, &group.id).await?))
 }
 
-async fn delete_file_permanently(
-    Path(file_id): Path<Uuid>,
+async fn scim_get_group(
+    Path(group_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<StatusCode, StatusCode> {
-    let file = database::get_file_by_id(&state.db, &file_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+) -> Result<ScimJson<scim::GroupResource>, ScimError> {
+    Ok(ScimJson(StatusCode::OK, load_scim_group(&state, &group_id).await?))
+}
 
-    if !file.is_deleted {
-        return Err(StatusCode::BAD_REQUEST);
+async fn rename_scim_group(state: &AppState, group: &Group, name: &str) -> Result<(), ScimError> {
+    let name = scim_group_name(name)?;
+    if name == group.name {
+        return Ok(());
     }
+    if database::group_name_taken(&state.db, Some(&group.id), &name).await? {
+        return Err(ScimError::uniqueness(format!("group \"{}\" already exists", name)));
+    }
+    database::rename_group(&state.db, &group.id, &name).await?;
+    Ok(())
+}
 
-    state.file_storage.delete_file(&file.file_path)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn scim_replace_group(
+    Path(group_id): Path<Uuid>,
+    State(state): State<AppState>,
+    Json(request): Json<scim::GroupRequest>,
+) -> Result<ScimJson<scim::GroupResource>, ScimError> {
+    let members = scim::parse_member_ids(&request.members)?;
+    let group = database::get_group(&state.db, &group_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("Group", &group_id))?;
 
-    database::delete_file_record(&state.db, &file_id)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    rename_scim_group(&state, &group, &request.display_name).await?;
+    database::update_group_members(&state.db, &group.id, true, &members, &[]).await?;
 
-    Ok(StatusCode::NO_CONTENT)
+    Ok(ScimJson(StatusCode::OK, load_scim_group(&state, &group_id).await?))
 }
 
-async fn list_users(
+async fn scim_patch_group(
+    Path(group_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<User>>, StatusCode> {
-    let users = database::get_all_users(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Json(request): Json<scim::PatchRequest>,
+) -> Result<ScimJson<scim::GroupResource>, ScimError> {
+    let patch = scim::group_patch(&request)?;
+    let group = database::get_group(&state.db, &group_id)
+        .await?
+        .ok_or_else(|| ScimError::not_found("Group", &group_id))?;
+
+    if let Some(name) = &patch.display_name {
+        rename_scim_group(&state, &group, name).await?;
+    }
+    if patch.replace_members || !patch.add.is_empty() || !patch.remove.is_empty() {
+        database::update_group_members(&state.db, &group.id, patch.replace_members, &patch.add, &patch.remove).await?;
+    }
 
-    Ok(Json(users))
+    Ok(ScimJson(StatusCode::OK, load_scim_group(&state, &group_id).await?))
 }
 
-async fn get_storage_info(
+async fn scim_delete_group(
+    Path(group_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<StorageInfo>, StatusCode> {
-    let storage_info = state.file_storage.get_storage_info()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    Ok(Json(storage_info))
+) -> Result<StatusCode, ScimError> {
+    if !database::delete_group(&state.db, &group_id).await? {
+        return Err(ScimError::not_found("Group", &group_id));
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
 
-async fn get_user_storage_info(
+async fn set_user_quota(
+    Path(user_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<StorageInfo>, StatusCode> {
-    let storage_info = state.file_storage.get_storage_info()
+    Extension(admin): Extension<models::User>,
+    Json(request): Json<SetQuotaRequest>,
+) -> Result<Json<QuotaStatus>, StatusCode> {
+    if let (Some(soft), Some(hard)) = (request.soft_limit, request.hard_limit) {
+        if soft > hard {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    if request.soft_limit.is_some_and(|l| l < 0) || request.hard_limit.is_some_and(|l| l < 0) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    tenants::managed_user(&state, &admin, &user_id).await?;
+
+    database::set_user_quota(&state.db, &user_id, request.soft_limit, request.hard_limit)
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    Ok(Json(storage_info))
+
+    let quota = database::get_user_quota(&state.db, &user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(quota_status(&quota, state.config.quota_grace_period_days)))
 }
 
 async fn initiate_chunked_upload(
     State(state): State<AppState>,
     Extension(user): Extension<models::User>,
+    headers: HeaderMap,
     Json(request): Json<models::InitiateChunkedUploadRequest>,
-) -> Result<Json<models::InitiateChunkedUploadResponse>, StatusCode> {
+) -> Result<Json<models::InitiateChunkedUploadResponse>, FileError> {
     let user_id = user.id;
+    let client_modified_at = match request.client_modified_at {
+        Some(modified) => Some(modified),
+        None => mtime_from_headers(&headers)?,
+    };
+    
+    let limits = chunking::Limits::from_config(&state.config);
+    limits.check_total_size(request.total_size)?;
+    let requested_chunk_size = request.chunk_size.filter(|size| *size > 0);
+    if let Some(chunk_size) = requested_chunk_size {
+        limits.total_chunks(request.total_size, chunk_size)?;
+    }
+    
+    let folder_id = if request.camera_upload {
+        if request.folder_id.is_some() {
+            return Err(StatusCode::BAD_REQUEST.into());
+        }
+        let taken_at = client_modified_at.unwrap_or_else(chrono::Utc::now);
+        let folder_id = camera::destination_folder(&state, &user.id, &taken_at)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        Some(folder_id)
+    } else {
+        request.folder_id
+    };
+    
+    let owner_id = access::destination_owner(&state, &user, folder_id.as_ref()).await?;
+    check_name_conflict(&state, &owner_id, folder_id.as_ref(), &request.filename, None).await?;
+    
+    let _admission = UPLOAD_ADMISSION.lock().await;
+    let quota_warning = check_upload_quota(&state, &owner_id, request.total_size).await?;
     
-    let total_chunks = (request.total_size as f64 / request.chunk_size as f64).ceil() as i32;
     let upload_id = Uuid::new_v4();
     
+    let total_size = request.total_size as u64;
     let (temp_file_path, disk_path) = state.file_storage
-        .create_temp_file(&user_id, &upload_id, request.total_size as u64)
+        .blocking(move |storage| storage.create_temp_file(&user_id, &upload_id, total_size))
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
+    let hints = chunking::Hints::from_request(request.connection_type.as_deref(), request.downlink_mbps, &headers);
+    let active_uploads = database::count_active_chunked_uploads(&state.db).await.unwrap_or(0);
+    let recommended_chunk_size = chunking::recommend(
+        request.total_size,
+        &hints,
+        state.file_storage.is_rotational(&disk_path),
+        active_uploads,
+        &limits,
+    );
+    let chunk_size = requested_chunk_size.unwrap_or(recommended_chunk_size);
+    let total_chunks = (request.total_size as f64 / chunk_size as f64).ceil() as i32;
+    
     let upload = database::create_chunked_upload(
         &state.db,
         &user_id,
         &request.filename,
         request.total_size,
-        request.chunk_size,
+        chunk_size,
         total_chunks,
         &temp_file_path.to_string_lossy(),
         &disk_path.to_string_lossy(),
+        client_modified_at,
+        folder_id.as_ref(),
+        state.config.upload_session_ttl_hours,
     )
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map_err(|_| {
+        if let Err(e) = state.file_storage.cleanup_temp_file(&temp_file_path) {
+            warn!("Failed to remove temp file {}: {}", temp_file_path.display(), e);
+        }
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
     
     Ok(Json(models::InitiateChunkedUploadResponse {
         upload_id: upload.id,
         chunk_size: upload.chunk_size,
+        recommended_chunk_size,
         total_chunks: upload.total_chunks,
+        quota_warning,
     }))
 }
 
@@ -372,7 +8536,8 @@ async fn upload_chunk(
     Path((upload_id, chunk_number)): Path<(Uuid, i32)>,
     State(state): State<AppState>,
     Extension(user): Extension<models::User>,
-    body: Bytes,
+    headers: HeaderMap,
+    body: Body,
 ) -> Result<Json<models::UploadChunkResponse>, StatusCode> {
     let upload = database::get_chunked_upload(&state.db, &upload_id)
         .await
@@ -387,17 +8552,74 @@ async fn upload_chunk(
         return Err(StatusCode::BAD_REQUEST);
     }
     
-    let temp_file_path = std::path::Path::new(&upload.temp_path);
+    if upload.status == "failed" {
+        return Err(StatusCode::CONFLICT);
+    }
+    
+    if upload.expires_at < chrono::Utc::now() {
+        return Err(StatusCode::GONE);
+    }
     
-    state.file_storage
-        .write_chunk(temp_file_path, &body, chunk_number, upload.chunk_size)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if chunk_number < 1 || chunk_number > upload.total_chunks {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let expected_len = (upload.total_size - (chunk_number as i64 - 1) * upload.chunk_size).min(upload.chunk_size);
+    let collected = http_body_util::Limited::new(body, expected_len as usize)
+        .collect()
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    
+    let mut headers = headers;
+    if let Some(trailers) = collected.trailers() {
+        headers.extend(trailers.clone());
+    }
+    
+    let body = collected.to_bytes();
+    if body.len() as i64 != expected_len {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    let expected_digests = digest::expected_digests(&headers, digest::CONTENT_DIGEST_HEADERS)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Err(e) = digest::verify(&expected_digests, &body) {
+        warn!("Rejected chunk {} of upload {}: {}", chunk_number, upload_id, e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    
+    let expected_sha256 = match headers.get("x-chunk-sha256") {
+        Some(value) => Some(value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.to_string()),
+        None => None,
+    };
     
-    let new_uploaded_chunks = upload.uploaded_chunks + 1;
+    let temp_path = upload.temp_path.clone();
+    let chunk_data = body.clone();
+    let chunk_size = upload.chunk_size;
+    
+    state.file_storage
+        .blocking(move |storage| {
+            storage.write_chunk(
+                std::path::Path::new(&temp_path),
+                &chunk_data,
+                chunk_number,
+                chunk_size,
+                expected_sha256.as_deref(),
+            )
+        })
+        .await
+        .map_err(|e| {
+            if e.downcast_ref::<file_storage::ChecksumMismatch>().is_some() {
+                warn!("Rejected chunk {} of upload {}: {}", chunk_number, upload_id, e);
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
     
-    database::update_chunked_upload_progress(&state.db, &upload_id, new_uploaded_chunks)
+    let new_uploaded_chunks = database::record_uploaded_chunk(&state.db, &upload_id, chunk_number, body.len() as i64, state.config.upload_session_ttl_hours)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    record_transfer(&state, user.id, body.len() as i64, 0);
     
     let upload_completed = new_uploaded_chunks >= upload.total_chunks;
     
@@ -413,48 +8635,206 @@ async fn complete_chunked_upload(
     Path(upload_id): Path<Uuid>,
     State(state): State<AppState>,
     Extension(user): Extension<models::User>,
-) -> Result<Json<models::FileInfo>, StatusCode> {
+    headers: HeaderMap,
+) -> Result<Json<models::FileInfo>, FileError> {
     let upload = database::get_chunked_upload(&state.db, &upload_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
     
     if upload.user_id != user.id {
-        return Err(StatusCode::FORBIDDEN);
+        return Err(StatusCode::FORBIDDEN.into());
     }
     
-    if upload.uploaded_chunks < upload.total_chunks {
-        return Err(StatusCode::BAD_REQUEST);
+    if upload.status == "failed" || upload.is_completed {
+        return Err(StatusCode::CONFLICT.into());
+    }
+    
+    if upload.expires_at < chrono::Utc::now() {
+        return Err(StatusCode::GONE.into());
+    }
+    
+    let missing_chunks = database::get_missing_chunks(&state.db, &upload.id, upload.total_chunks)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !missing_chunks.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
     }
     
-    let temp_file_path = std::path::Path::new(&upload.temp_path);
-    let disk_path = std::path::Path::new(&upload.disk_path);
+    let owner_id = access::destination_owner(&state, &user, upload.folder_id.as_ref()).await?;
+    check_name_conflict(&state, &owner_id, upload.folder_id.as_ref(), &upload.filename, None).await?;
+    
+    let expected_digests = digest::expected_digests(&headers, digest::REPR_DIGEST_HEADERS)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let temp_path = upload.temp_path.clone();
+    tokio::task::spawn_blocking(move || digest::verify_file(&expected_digests, std::path::Path::new(&temp_path)))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .map_err(|e| {
+            if e.downcast_ref::<file_storage::ChecksumMismatch>().is_some() {
+                warn!("Rejected completion of upload {}: {}", upload_id, e);
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
     
+    let (temp_path, disk_path, filename) = (upload.temp_path.clone(), upload.disk_path.clone(), upload.filename.clone());
     let storage_result = state.file_storage
-        .finalize_chunked_upload(temp_file_path, &upload.user_id, &upload.filename, disk_path)
+        .blocking(move |storage| {
+            storage.finalize_chunked_upload(
+                std::path::Path::new(&temp_path),
+                &owner_id,
+                &filename,
+                std::path::Path::new(&disk_path),
+            )
+        })
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
-    let file_info = database::create_file_record(
+    let client_modified_at = mtime_from_headers(&headers)?.or(upload.client_modified_at);
+    let completed = database::complete_chunked_upload(
         &state.db,
-        &upload.user_id,
-        &storage_result.filename,
+        &upload_id,
+        &owner_id,
         &upload.filename,
-        &storage_result.file_path,
-        &storage_result.disk_path,
-        storage_result.file_size,
-        None,
+        &storage_result,
+        upload.folder_id.as_ref(),
+        client_modified_at,
     )
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .await;
+    let file_info = match completed {
+        Ok(Some(file_info)) => file_info,
+        other => {
+            // Either another request completed the upload first or the
+            // transaction failed; the blob just written belongs to no record.
+            let stored_path = storage_result.file_path.clone();
+            let _ = state.file_storage.blocking(move |storage| storage.delete_file(&stored_path)).await;
+            let status = if other.is_ok() { StatusCode::CONFLICT } else { StatusCode::INTERNAL_SERVER_ERROR };
+            return Err(status.into());
+        }
+    };
+    record_activity(&state, user.id, &file_info, FileActivityKind::Upload);
     
-    database::complete_chunked_upload(&state.db, &upload_id)
+    Ok(Json(file_info))
+}
+
+async fn get_camera_upload_settings(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<Json<CameraUploadSettings>, StatusCode> {
+    let settings = database::get_camera_upload_settings(&state.db, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .unwrap_or_else(|| CameraUploadSettings {
+            template: camera::DEFAULT_TEMPLATE.to_string(),
+            updated_at: None,
+        });
+
+    Ok(Json(settings))
+}
+
+async fn set_camera_upload_settings(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    Json(request): Json<UpdateCameraUploadSettingsRequest>,
+) -> Result<Json<CameraUploadSettings>, StatusCode> {
+    let template = camera::normalize_template(&request.template).ok_or(StatusCode::BAD_REQUEST)?;
+
+    let settings = database::set_camera_upload_template(&state.db, &user.id, &template)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    database::delete_chunked_upload(&state.db, &upload_id)
+
+    Ok(Json(settings))
+}
+
+async fn reset_camera_upload_settings(
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+) -> Result<StatusCode, StatusCode> {
+    database::delete_camera_upload_settings(&state.db, &user.id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn paste_upload(
+    Query(query): Query<PasteQuery>,
+    State(state): State<AppState>,
+    Extension(user): Extension<models::User>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<models::FileInfo>, FileError> {
+    if query.name.as_deref().is_some_and(|name| name.contains('/') || name.contains('\\')) {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let data = http_body_util::Limited::new(body, paste::MAX_SIZE)
+        .collect()
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?
+        .to_bytes();
+    if data.is_empty() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let declared = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+    let mime_type = paste::detect_mime_type(&data, declared).ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+    let filename = paste::filename(&mime_type, query.name.as_deref(), &chrono::Utc::now());
+
+    let folder_id = match query.folder_id {
+        Some(folder_id) => folder_id,
+        None => {
+            database::get_or_create_folder(&state.db, &user.id, None, paste::FOLDER_NAME)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+                .0
+                .id
+        }
+    };
+    let owner_id = access::destination_owner(&state, &user, Some(&folder_id)).await?;
+    check_name_conflict(&state, &owner_id, Some(&folder_id), &filename, None).await?;
+    check_upload_quota(&state, &owner_id, data.len() as i64).await?;
+
+    let stored = {
+        let file_storage = state.file_storage.clone();
+        let filename = filename.clone();
+        tokio::task::spawn_blocking(move || file_storage.store_file(&data, &owner_id, &filename))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map_err(|_| StatusCode::INSUFFICIENT_STORAGE)?
+    };
+
+    let mut file_info = match database::create_file_record(
+        &state.db,
+        &owner_id,
+        &stored.filename,
+        &filename,
+        &stored.file_path,
+        &stored.disk_path,
+        stored.file_size,
+        stored.stored_size,
+        stored.encoding,
+        Some(&mime_type),
+        Some(&stored.checksum),
+    )
+    .await
+    {
+        Ok(file_info) => file_info,
+        Err(_) => {
+            let _ = state.file_storage.delete_file(&stored.file_path);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
+    database::set_file_folder(&state.db, &file_info.id, Some(&folder_id))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    file_info.folder_id = Some(folder_id);
+    record_transfer(&state, user.id, stored.file_size, 0);
+    record_activity(&state, user.id, &file_info, FileActivityKind::Upload);
+
     Ok(Json(file_info))
 }
 
@@ -462,7 +8842,7 @@ async fn get_upload_status(
     Path(upload_id): Path<Uuid>,
     State(state): State<AppState>,
     Extension(user): Extension<models::User>,
-) -> Result<Json<models::ChunkedUpload>, StatusCode> {
+) -> Result<Json<models::UploadStatusResponse>, StatusCode> {
     let upload = database::get_chunked_upload(&state.db, &upload_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
@@ -472,7 +8852,14 @@ async fn get_upload_status(
         return Err(StatusCode::FORBIDDEN);
     }
     
-    Ok(Json(upload))
+    let missing_chunks = database::get_missing_chunks(&state.db, &upload.id, upload.total_chunks)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    
+    Ok(Json(models::UploadStatusResponse {
+        upload,
+        missing_chunks,
+    }))
 }
 
 async fn cancel_chunked_upload(
@@ -489,8 +8876,9 @@ async fn cancel_chunked_upload(
         return Err(StatusCode::FORBIDDEN);
     }
     
-    let temp_file_path = std::path::Path::new(&upload.temp_path);
-    state.file_storage.cleanup_temp_file(temp_file_path)
+    let temp_path = upload.temp_path.clone();
+    state.file_storage.blocking(move |storage| storage.cleanup_temp_file(std::path::Path::new(&temp_path)))
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     
     database::delete_chunked_upload(&state.db, &upload_id)
@@ -513,7 +8901,8 @@ async fn get_temp_files_info(
     State(state): State<AppState>,
 ) -> Result<Json<models::TempFilesInfo>, StatusCode> {
     let temp_info = state.file_storage
-        .get_temp_files_info()
+        .blocking(|storage| storage.get_temp_files_info())
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(temp_info))
 }
@@ -521,7 +8910,8 @@ async fn get_temp_files_info(
 async fn cleanup_temp_files(
     State(state): State<AppState>,
 ) -> Result<Json<models::CleanupResult>, StatusCode> {
-    let result = state.file_storage.cleanup_orphaned_temp_files()
+    let result = state.file_storage.blocking(|storage| storage.cleanup_orphaned_temp_files())
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(result))
 }
@@ -530,7 +8920,8 @@ async fn cleanup_temp_files_with_age(
     Path(hours): Path<u64>,
     State(state): State<AppState>,
 ) -> Result<Json<models::CleanupResult>, StatusCode> {
-    let result = state.file_storage.cleanup_old_temp_files(hours)
+    let result = state.file_storage.blocking(move |storage| storage.cleanup_old_temp_files(hours))
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     Ok(Json(result))
 }
@@ -1,4 +1,4 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
@@ -8,12 +8,42 @@ pub struct User {
     pub id: Uuid,
     pub username: String,
     pub email: String,
+    #[serde(skip_serializing)]
     pub password_hash: String,
     pub is_admin: bool,
+    pub tenant_id: Option<Uuid>,
+    pub deactivated_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub is_admin: bool,
+    pub tenant_id: Option<Uuid>,
+    pub deactivated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<User> for PublicUser {
+    fn from(user: User) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            is_admin: user.is_admin,
+            tenant_id: user.tenant_id,
+            deactivated_at: user.deactivated_at,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct FileInfo {
     pub id: Uuid,
@@ -23,25 +53,425 @@ pub struct FileInfo {
     pub file_path: String,
     pub disk_path: String,
     pub file_size: i64,
+    pub stored_size: Option<i64>,
     pub mime_type: Option<String>,
     pub is_deleted: bool,
     pub deleted_at: Option<DateTime<Utc>>,
+    pub is_quarantined: bool,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+    pub checksum: Option<String>,
+    pub folder_id: Option<Uuid>,
+    pub client_modified_at: Option<DateTime<Utc>>,
+    pub keep_offline: bool,
+    pub storage_tier: String,
+    pub storage_encoding: Option<String>,
+    pub tags: Vec<String>,
+    pub custom_metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl FileInfo {
+    /// How the stored bytes are wrapped, or `None` for files written before
+    /// the encoding was recorded.
+    pub fn encoding(&self) -> Option<StorageEncoding> {
+        self.storage_encoding.as_deref().and_then(StorageEncoding::parse)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageTier {
+    Hot,
+    Cold,
+}
+
+impl StorageTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageTier::Hot => "hot",
+            StorageTier::Cold => "cold",
+        }
+    }
+
+    pub fn of_path(file_path: &str) -> Self {
+        if crate::s3::is_s3_path(file_path) {
+            StorageTier::Cold
+        } else {
+            StorageTier::Hot
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct StorageEncoding {
+    pub compressed: bool,
+    pub encrypted: bool,
+}
+
+impl StorageEncoding {
+    pub const PLAIN: StorageEncoding = StorageEncoding { compressed: false, encrypted: false };
+
+    pub fn as_str(&self) -> &'static str {
+        match (self.compressed, self.encrypted) {
+            (false, false) => "plain",
+            (true, false) => "zstd",
+            (false, true) => "encrypted",
+            (true, true) => "zstd+encrypted",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let (compressed, encrypted) = match value {
+            "plain" => (false, false),
+            "zstd" => (true, false),
+            "encrypted" => (false, true),
+            "zstd+encrypted" => (true, true),
+            _ => return None,
+        };
+        Some(StorageEncoding { compressed, encrypted })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileChecksum {
+    pub file_id: Uuid,
+    pub algorithm: String,
+    pub checksum: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileReportQuery {
+    pub user_id: Option<Uuid>,
+    pub unaccessed_for: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminFileSearchQuery {
+    pub user_id: Option<Uuid>,
+    pub username: Option<String>,
+    pub min_size: Option<i64>,
+    pub max_size: Option<i64>,
+    pub mime_type: Option<String>,
+    pub older_than_days: Option<i64>,
+    pub newer_than_days: Option<i64>,
+    pub disk_path: Option<String>,
+    pub include_deleted: Option<bool>,
+    pub quarantined: Option<bool>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminFileAction {
+    Reassign,
+    Purge,
+    Quarantine,
+    Unquarantine,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminBulkFileRequest {
+    pub action: AdminFileAction,
+    pub file_ids: Vec<Uuid>,
+    pub target_user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminBulkFileResult {
+    pub affected: usize,
+    pub not_found: Vec<Uuid>,
+    pub failed: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct SharedLink {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub is_read_only: bool,
+    pub is_encrypted: bool,
+    pub encryption_metadata: Option<serde_json::Value>,
+    pub egress_limit_bytes: Option<i64>,
+    pub egress_used_bytes: i64,
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    pub password_protected: bool,
+    pub max_downloads: Option<i32>,
+    pub download_count: i32,
     pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateShareRequest {
+    pub expires_at: Option<DateTime<Utc>>,
+    pub encryption_metadata: Option<serde_json::Value>,
+    pub egress_limit_bytes: Option<i64>,
+    pub password: Option<String>,
+    pub max_downloads: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SharePasswordQuery {
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileActivityKind {
+    Upload,
+    Download,
+    Rename,
+    Delete,
+}
+
+impl FileActivityKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileActivityKind::Upload => "upload",
+            FileActivityKind::Download => "download",
+            FileActivityKind::Rename => "rename",
+            FileActivityKind::Delete => "delete",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct FileActivity {
+    pub id: i64,
+    pub file_id: Uuid,
+    pub filename: String,
+    pub action: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct RecentFile {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub file: FileInfo,
+    pub action: String,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RecentFilesQuery {
+    pub action: Option<FileActivityKind>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileActivityQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct FileDownload {
+    pub user_id: Uuid,
+    pub username: String,
+    pub downloaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ShareDownloadCount {
+    pub share_id: Uuid,
+    pub day: NaiveDate,
+    pub downloads: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDownloadAudit {
+    pub file_id: Uuid,
+    pub downloads: Vec<FileDownload>,
+    pub public_downloads: i64,
+    pub public_download_counts: Vec<ShareDownloadCount>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct FolderShareDefaults {
+    pub folder_id: Uuid,
+    pub expiry_days: Option<i32>,
+    pub password_required: bool,
+    pub max_downloads: Option<i32>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct SetFolderShareDefaultsRequest {
+    pub expiry_days: Option<i32>,
+    #[serde(default)]
+    pub password_required: bool,
+    pub max_downloads: Option<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderShareDefaultsInfo {
+    pub defaults: Option<FolderShareDefaults>,
+    pub effective: Option<FolderShareDefaults>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetShareEgressRequest {
+    pub limit_bytes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetUserEgressRequest {
+    pub limit_bytes: Option<i64>,
+    #[serde(default)]
+    pub unlimited: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EgressSettings {
+    pub limit_bytes: Option<i64>,
+    pub unlimited: bool,
+    pub effective_limit_bytes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTrashLimitRequest {
+    pub limit_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashUsage {
+    pub used_bytes: i64,
+    pub file_count: i64,
+    pub limit_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashSettings {
+    pub limit_bytes: Option<i64>,
+    pub effective_limit_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub message: String,
+    pub data: Option<serde_json::Value>,
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    #[serde(default)]
+    pub unread: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastRequest {
+    pub subject: String,
+    pub body: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Broadcast {
+    pub id: Uuid,
+    pub created_by: Option<Uuid>,
+    pub subject: String,
+    pub body: String,
+    pub status: String,
+    pub total_recipients: i64,
+    pub sent_count: i64,
+    pub failed_count: i64,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct BroadcastRecipient {
+    pub user_id: Uuid,
+    pub email: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct ClaimedRecipient {
+    pub broadcast_id: Uuid,
+    pub user_id: Uuid,
+    pub email: String,
+    pub username: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareMetadata {
+    pub token: String,
+    pub is_encrypted: bool,
+    pub encryption_metadata: Option<serde_json::Value>,
+    pub filename: Option<String>,
+    pub mime_type: Option<String>,
+    pub file_size: i64,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub magnet_uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Snippet {
+    pub id: Uuid,
+    pub file_id: Uuid,
+    pub title: String,
+    pub language: Option<String>,
+    pub burn_after_reading: bool,
+    pub token: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub views: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSnippetRequest {
+    pub content: String,
+    pub title: Option<String>,
+    pub language: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub burn_after_reading: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnippetContent {
+    pub title: String,
+    pub language: Option<String>,
+    pub content: String,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub burn_after_reading: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateProfileRequest {
+    pub current_password: String,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
 
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
     pub token: String,
-    pub user: User,
+    pub expires_at: DateTime<Utc>,
+    pub user: PublicUser,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,8 +480,65 @@ pub struct DiskInfo {
     pub total_space: u64,
     pub used_space: u64,
     pub available_space: u64,
+    pub reserved_space: u64,
     pub usage_percentage: u8,
     pub is_accessible: bool,
+    pub is_read_only: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub health: Option<DiskHealth>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskHealth {
+    pub device: Option<String>,
+    pub mount_read_only: bool,
+    pub smart_passed: Option<bool>,
+    pub temperature_celsius: Option<i64>,
+    pub reallocated_sectors: Option<u64>,
+    pub pending_sectors: Option<u64>,
+    pub warnings: Vec<String>,
+}
+
+#[cfg(feature = "chaos")]
+#[derive(Debug, Clone, Serialize)]
+pub struct ChaosFaults {
+    pub fail_chunk_writes: u32,
+    pub db_delay_ms: u64,
+    pub disk_full: bool,
+}
+
+#[cfg(feature = "chaos")]
+#[derive(Debug, Deserialize)]
+pub struct UpdateChaosFaults {
+    pub fail_chunk_writes: Option<u32>,
+    pub db_delay_ms: Option<u64>,
+    pub disk_full: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlacementCandidate {
+    pub path: String,
+    pub accessible: bool,
+    pub available_space: Option<u64>,
+    pub reserved_space: u64,
+    pub required_space: u64,
+    pub chosen: bool,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlacementDecision {
+    pub decided_at: DateTime<Utc>,
+    pub file_size: u64,
+    pub chosen: Option<String>,
+    pub candidates: Vec<PlacementCandidate>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlacementDecisionQuery {
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub failed_only: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -62,6 +549,7 @@ pub struct StorageInfo {
     pub usage_percentage: u8,
     pub disk_count: usize,
     pub disks: Vec<DiskInfo>,
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -71,6 +559,10 @@ pub struct StorageResult {
     pub file_path: String,
     pub disk_path: String,
     pub file_size: i64,
+    pub stored_size: i64,
+    pub encoding: StorageEncoding,
+    pub checksum: String,
+    pub mime_type: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -85,22 +577,52 @@ pub struct ChunkedUpload {
     pub temp_path: String,
     pub disk_path: String,
     pub is_completed: bool,
+    pub status: String,
+    pub client_modified_at: Option<DateTime<Utc>>,
+    pub folder_id: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadStatusResponse {
+    #[serde(flatten)]
+    pub upload: ChunkedUpload,
+    pub missing_chunks: Vec<i32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitiateChunkedUploadRequest {
     pub filename: String,
     pub total_size: i64,
-    pub chunk_size: i64,
+    pub chunk_size: Option<i64>,
+    pub client_modified_at: Option<DateTime<Utc>>,
+    pub folder_id: Option<Uuid>,
+    pub connection_type: Option<String>,
+    pub downlink_mbps: Option<f64>,
+    #[serde(default)]
+    pub camera_upload: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct CameraUploadSettings {
+    pub template: String,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCameraUploadSettingsRequest {
+    pub template: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InitiateChunkedUploadResponse {
     pub upload_id: Uuid,
     pub chunk_size: i64,
+    pub recommended_chunk_size: i64,
     pub total_chunks: i32,
+    pub quota_warning: Option<QuotaStatus>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,12 +633,6 @@ pub struct UploadChunkResponse {
     pub file_info: Option<FileInfo>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ChunkInfo {
-    pub chunk_number: i32,
-    pub is_uploaded: bool,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TempFilesInfo {
     pub total_files: usize,
@@ -128,4 +644,1303 @@ pub struct TempFilesInfo {
 pub struct CleanupResult {
     pub cleaned_files: usize,
     pub freed_space: u64,
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UserQuota {
+    pub storage_used: i64,
+    pub quota_soft_bytes: Option<i64>,
+    pub quota_hard_bytes: Option<i64>,
+    pub quota_grace_started_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct UserStorageUsage {
+    pub used_bytes: i64,
+    pub trash_bytes: i64,
+    pub file_count: i64,
+    pub trash_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserStorageInfo {
+    pub used_bytes: i64,
+    pub trash_bytes: i64,
+    pub file_count: i64,
+    pub trash_count: i64,
+    pub quota_bytes: Option<i64>,
+    pub remaining_bytes: i64,
+    pub usage_percentage: u8,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub used_bytes: i64,
+    pub soft_limit: Option<i64>,
+    pub hard_limit: Option<i64>,
+    pub over_soft_limit: bool,
+    pub grace_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetQuotaRequest {
+    pub soft_limit: Option<i64>,
+    pub hard_limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvisionUserRequest {
+    pub username: String,
+    pub email: String,
+    pub password: Option<String>,
+    pub soft_limit: Option<i64>,
+    pub hard_limit: Option<i64>,
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvisionedUser {
+    #[serde(flatten)]
+    pub user: PublicUser,
+    pub quota: QuotaStatus,
+    pub groups: Vec<String>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct DirectoryUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub is_admin: bool,
+    pub scim_external_id: Option<String>,
+    pub deactivated_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Group {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct GroupMembership {
+    pub group_id: Uuid,
+    pub group_name: String,
+    pub user_id: Uuid,
+    pub username: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageRecalculation {
+    pub user_id: Uuid,
+    pub previous_used_bytes: i64,
+    pub used_bytes: i64,
+    pub drift: i64,
+    pub corrected_files: usize,
+    pub missing_files: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FeatureFlags {
+    pub chunked_upload: bool,
+    pub trash: bool,
+    pub shares: bool,
+    pub encrypted_shares: bool,
+    pub webdav: bool,
+    pub ocr: bool,
+    pub encryption: bool,
+    pub compression: bool,
+    pub s3: bool,
+    pub s3_gateway: bool,
+    pub quotas: bool,
+    pub two_factor: bool,
+    pub scheduled_exports: bool,
+    pub archive_import: bool,
+    pub personal_archive: bool,
+    pub video_metadata: bool,
+    pub printing: bool,
+    pub rclone_compat: bool,
+    pub case_insensitive_names: bool,
+    pub share_torrents: bool,
+    pub oidc: bool,
+    pub passkeys: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilityLimits {
+    pub max_request_body_size: u64,
+    pub min_free_space_buffer: u64,
+    pub quota_grace_period_days: i64,
+    pub trash_retention_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub version: String,
+    pub features: FeatureFlags,
+    pub limits: CapabilityLimits,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledJob {
+    pub name: String,
+    pub schedule: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuntimeServices {
+    pub content_indexing: bool,
+    pub alerts: bool,
+    pub provisioning: bool,
+    pub smtp: bool,
+    pub virus_scanning: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StorageBackendInfo {
+    pub kind: String,
+    pub paths: Vec<String>,
+    pub disks: Vec<DiskInfo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminInfo {
+    pub version: String,
+    pub build_hash: String,
+    pub features: FeatureFlags,
+    pub services: RuntimeServices,
+    pub storage: StorageBackendInfo,
+    pub database_version: String,
+    pub started_at: DateTime<Utc>,
+    pub uptime_seconds: i64,
+    pub scheduled_jobs: Vec<ScheduledJob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportDestination {
+    Local {
+        path: String,
+    },
+    Webdav {
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    S3 {
+        endpoint: String,
+        bucket: String,
+        region: String,
+        access_key: String,
+        secret_key: String,
+        prefix: Option<String>,
+    },
+    Rsync {
+        target: String,
+        ssh_options: Option<String>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub name: String,
+    pub destination: sqlx::types::Json<ExportDestination>,
+    pub schedule: String,
+    pub is_enabled: bool,
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub last_success_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateExportJobRequest {
+    pub name: String,
+    pub destination: ExportDestination,
+    pub schedule: String,
+    pub all_users: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ExportRun {
+    pub id: Uuid,
+    pub job_id: Uuid,
+    pub status: String,
+    pub files_exported: i32,
+    pub bytes_exported: i64,
+    pub error: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Folder {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub parent_id: Option<Uuid>,
+    pub name: String,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub keep_offline: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+#[serde(default)]
+pub struct PermissionSet {
+    #[sqlx(rename = "can_read")]
+    pub read: bool,
+    #[sqlx(rename = "can_write")]
+    pub write: bool,
+    #[sqlx(rename = "can_delete")]
+    pub delete: bool,
+    #[sqlx(rename = "can_reshare")]
+    pub reshare: bool,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct FolderPermission {
+    pub folder_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub permissions: PermissionSet,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct Tenant {
+    pub id: Uuid,
+    pub name: String,
+    pub storage_paths: Vec<String>,
+    pub storage_quota_bytes: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TenantSummary {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub tenant: Tenant,
+    pub user_count: i64,
+    pub storage_used: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TenantRequest {
+    pub name: String,
+    #[serde(default)]
+    pub storage_paths: Vec<String>,
+    pub storage_quota_bytes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetUserTenantRequest {
+    pub tenant_id: Option<Uuid>,
+    pub is_admin: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFolderPermissionRequest {
+    pub username: String,
+    #[serde(flatten)]
+    pub permissions: PermissionSet,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SharedFolder {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub folder: Folder,
+    pub owner_username: String,
+    #[sqlx(flatten)]
+    pub permissions: PermissionSet,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FolderContents {
+    pub folder: Folder,
+    pub permissions: PermissionSet,
+    pub folders: Vec<Folder>,
+    pub files: Vec<FileInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MountKind {
+    Local,
+    Smb,
+}
+
+impl MountKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MountKind::Local => "local",
+            MountKind::Smb => "smb",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmbCredentials {
+    pub username: String,
+    pub password: String,
+    pub domain: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ExternalMount {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub kind: String,
+    pub host_path: String,
+    pub read_only: bool,
+    #[serde(skip_serializing)]
+    pub smb_credentials: Option<sqlx::types::Json<SmbCredentials>>,
+    pub sync_folder_id: Option<Uuid>,
+    pub last_scanned_at: Option<DateTime<Utc>>,
+    pub sync_started_at: Option<DateTime<Utc>>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub last_sync_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMountRequest {
+    pub user_id: Uuid,
+    pub name: String,
+    pub host_path: String,
+    pub read_only: Option<bool>,
+    pub smb: Option<SmbCredentials>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetMountSyncRequest {
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MountEntry {
+    pub path: String,
+    pub name: String,
+    pub is_dir: bool,
+    pub size: i64,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MountListing {
+    pub mount_id: Uuid,
+    pub path: String,
+    pub cached: bool,
+    pub entries: Vec<MountEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasteQuery {
+    pub name: Option<String>,
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MountPathQuery {
+    #[serde(default)]
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GalleryTheme {
+    Light,
+    Dark,
+    Minimal,
+}
+
+impl GalleryTheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GalleryTheme::Light => "light",
+            GalleryTheme::Dark => "dark",
+            GalleryTheme::Minimal => "minimal",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Gallery {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub folder_id: Uuid,
+    pub token: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub theme: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetGalleryRequest {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub theme: Option<GalleryTheme>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GalleryQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GalleryItem {
+    pub id: Uuid,
+    pub name: String,
+    pub size: i64,
+    pub mime_type: Option<String>,
+    pub modified_at: DateTime<Utc>,
+    pub thumbnail_url: Option<String>,
+    pub original_url: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GalleryFeed {
+    pub title: String,
+    pub description: Option<String>,
+    pub theme: String,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub items: Vec<GalleryItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportKind {
+    Takeout,
+    Dropbox,
+    Archive,
+}
+
+impl ImportKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ImportKind::Takeout => "takeout",
+            ImportKind::Dropbox => "dropbox",
+            ImportKind::Archive => "archive",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateImportRequest {
+    pub kind: Option<ImportKind>,
+    pub file_id: Option<Uuid>,
+    pub server_path: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub target_folder: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveEntry {
+    pub path: String,
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveContents {
+    pub format: String,
+    pub entry_count: usize,
+    pub total_size: u64,
+    pub truncated: bool,
+    pub entries: Vec<ArchiveEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExtractArchiveRequest {
+    pub folder_id: Option<Uuid>,
+    pub folder_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RemoteFetchRequest {
+    pub url: String,
+    pub folder_id: Option<Uuid>,
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ArchivePart {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub sequence: i32,
+    pub status: String,
+    pub file_count: i32,
+    pub removed_count: i32,
+    pub size_bytes: i64,
+    #[serde(skip_serializing)]
+    pub archive_path: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, FromRow)]
+pub struct ArchiveManifestEntry {
+    pub file_id: Uuid,
+    pub path: String,
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct RemoteFetch {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub url: String,
+    pub folder_id: Option<Uuid>,
+    pub filename: Option<String>,
+    pub status: String,
+    pub bytes_downloaded: i64,
+    pub total_bytes: Option<i64>,
+    pub mime_type: Option<String>,
+    pub file_id: Option<Uuid>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ImportJob {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: Option<String>,
+    pub source_path: String,
+    pub target_folder: String,
+    pub status: String,
+    pub files_imported: i32,
+    pub folders_created: i32,
+    pub bytes_imported: i64,
+    pub error: Option<String>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PhotoMetadata {
+    pub file_id: Uuid,
+    pub taken_at: Option<DateTime<Utc>>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub altitude: Option<f64>,
+    pub description: Option<String>,
+    pub raw: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FileMetadata {
+    pub file_id: Uuid,
+    pub status: String,
+    pub duration_seconds: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub video_codec: Option<String>,
+    #[serde(skip_serializing)]
+    pub poster_path: Option<String>,
+    pub error: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchIndexStats {
+    pub filename_index_available: bool,
+    pub filename_index_bytes: i64,
+    pub content_index_bytes: i64,
+    pub indexed_files: i64,
+    pub skipped_files: i64,
+    pub failed_files: i64,
+    pub pending_files: i64,
+    pub oldest_pending_at: Option<DateTime<Utc>>,
+    pub lag_seconds: Option<i64>,
+    pub last_indexed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RebalanceStatus {
+    pub running: bool,
+    pub disks: Vec<DiskInfo>,
+    pub last_run: Option<Operation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RebalanceRequest {
+    pub tolerance_percent: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FsckRequest {
+    #[serde(default)]
+    pub repair: bool,
+    #[serde(default)]
+    pub checksums: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FsckIssue {
+    pub file_id: Uuid,
+    pub path: String,
+    pub detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct FsckReport {
+    pub repair: bool,
+    pub checksums: bool,
+    pub checked_files: i64,
+    pub checked_blobs: i64,
+    pub missing_count: i64,
+    pub size_mismatch_count: i64,
+    pub checksum_mismatch_count: i64,
+    pub orphaned_count: i64,
+    pub missing: Vec<FsckIssue>,
+    pub size_mismatches: Vec<FsckIssue>,
+    pub checksum_mismatches: Vec<FsckIssue>,
+    pub orphaned: Vec<String>,
+    pub truncated: bool,
+    pub restored_copies: i64,
+    pub fixed_sizes: i64,
+    pub set_aside: i64,
+}
+
+impl FsckReport {
+    pub fn is_clean(&self) -> bool {
+        self.missing_count == 0
+            && self.size_mismatch_count == 0
+            && self.checksum_mismatch_count == 0
+            && self.orphaned_count == 0
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoragePathRequest {
+    pub path: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchIndexStatus {
+    pub content_indexing: bool,
+    pub rebuilding: bool,
+    #[serde(flatten)]
+    pub stats: SearchIndexStats,
+    pub last_rebuild: Option<Operation>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ScrubFinding {
+    pub file_id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub original_filename: String,
+    pub file_path: String,
+    pub status: String,
+    pub expected_checksum: String,
+    pub actual_checksum: Option<String>,
+    pub error: Option<String>,
+    pub checked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LifecycleRule {
+    pub id: Uuid,
+    pub name: String,
+    pub idle_days: i32,
+    pub min_size: Option<i64>,
+    pub mime_prefix: Option<String>,
+    pub is_enabled: bool,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct OrphanGcRun {
+    pub id: Uuid,
+    pub action: String,
+    pub scanned_blobs: i64,
+    pub orphaned_blobs: i64,
+    pub orphaned_bytes: i64,
+    pub reclaimed_bytes: i64,
+    pub failed_blobs: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrphanGcReport {
+    pub enabled: bool,
+    pub action: &'static str,
+    pub grace_hours: u64,
+    pub total_reclaimed_bytes: i64,
+    pub runs: Vec<OrphanGcRun>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LifecycleRuleRequest {
+    pub name: String,
+    pub idle_days: i32,
+    pub min_size: Option<i64>,
+    pub mime_prefix: Option<String>,
+    pub is_enabled: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RcloneInfo {
+    pub compatibility_mode: bool,
+    pub remote_url: String,
+    pub sha256sums_url: String,
+    pub mtime_header: String,
+    pub hash_type: String,
+    pub config: String,
+    pub notes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FileSearchQuery {
+    pub q: String,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ContentSearchResult {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub file: FileInfo,
+    pub snippet: String,
+    pub rank: f32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFileTagsRequest {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddFileTagsRequest {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TagSummary {
+    pub name: String,
+    pub file_count: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameFileRequest {
+    pub filename: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MoveFileRequest {
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CopyFileRequest {
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NameConflict {
+    pub error: String,
+    pub existing_file_id: Uuid,
+    pub existing_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Alias {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub folder_id: Option<Uuid>,
+    pub name: String,
+    pub target_file_id: Option<Uuid>,
+    pub target_folder_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateAliasRequest {
+    pub folder_id: Option<Uuid>,
+    pub name: Option<String>,
+    pub target_file_id: Option<Uuid>,
+    pub target_folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateFolderRequest {
+    pub color: Option<String>,
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeepOfflineRequest {
+    pub keep_offline: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SyncChangesQuery {
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncChanges {
+    pub files: Vec<FileInfo>,
+    pub folders: Vec<Folder>,
+    pub cursor: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardMode {
+    Cut,
+    Copy,
+}
+
+impl ClipboardMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClipboardMode::Cut => "cut",
+            ClipboardMode::Copy => "copy",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewStrategy {
+    Native,
+    Image,
+    Office,
+    None,
+}
+
+impl PreviewStrategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PreviewStrategy::Native => "native",
+            PreviewStrategy::Image => "image",
+            PreviewStrategy::Office => "office",
+            PreviewStrategy::None => "none",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "native" => Some(PreviewStrategy::Native),
+            "image" => Some(PreviewStrategy::Image),
+            "office" => Some(PreviewStrategy::Office),
+            "none" => Some(PreviewStrategy::None),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PreviewHandlerRow {
+    pub mime_type: String,
+    pub strategy: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PreviewHandler {
+    pub mime_type: String,
+    pub strategy: PreviewStrategy,
+    pub is_default: bool,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetPreviewHandlerRequest {
+    pub mime_type: String,
+    pub strategy: PreviewStrategy,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeletePreviewHandlerRequest {
+    pub mime_type: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilePreview {
+    pub file_id: Uuid,
+    pub mime_type: Option<String>,
+    pub strategy: PreviewStrategy,
+    pub content_url: Option<String>,
+    pub poster_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PrintRequest {
+    pub copies: Option<u32>,
+    pub page_ranges: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PrintJob {
+    pub file_id: Uuid,
+    pub printer: Option<String>,
+    pub job_id: Option<String>,
+    pub copies: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Clipboard {
+    pub user_id: Uuid,
+    pub mode: String,
+    pub file_ids: Vec<Uuid>,
+    pub folder_ids: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SetClipboardRequest {
+    pub mode: ClipboardMode,
+    #[serde(default)]
+    pub file_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub folder_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasteRequest {
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasteResult {
+    pub mode: ClipboardMode,
+    pub files: Vec<FileInfo>,
+    pub folders: Vec<Folder>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Operation {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub status: String,
+    pub progress_current: i64,
+    pub progress_total: Option<i64>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub cancel_requested: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OidcCallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: i64,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Passkey {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<WebauthnCredential> for Passkey {
+    fn from(credential: WebauthnCredential) -> Self {
+        Passkey {
+            id: credential.id,
+            name: credential.name,
+            created_at: credential.created_at,
+            last_used_at: credential.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+pub struct S3AccessKey {
+    pub access_key_id: String,
+    pub user_id: Uuid,
+    pub secret_key: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct S3AccessKeyInfo {
+    pub access_key_id: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<S3AccessKey> for S3AccessKeyInfo {
+    fn from(key: S3AccessKey) -> Self {
+        S3AccessKeyInfo {
+            access_key_id: key.access_key_id,
+            description: key.description,
+            created_at: key.created_at,
+            last_used_at: key.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateS3AccessKeyRequest {
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedS3AccessKey {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub description: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct S3MultipartUpload {
+    pub id: Uuid,
+    pub bucket: String,
+    pub object_key: String,
+}
+
+#[derive(Debug, FromRow)]
+pub struct S3MultipartPart {
+    pub part_number: i32,
+    pub temp_path: String,
+    pub size: i64,
+    pub etag: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WebauthnChallengeResponse {
+    pub challenge_id: Uuid,
+    pub public_key: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnLoginStartRequest {
+    pub username: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistrationCredential {
+    pub id: String,
+    pub response: AttestationResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnRegisterFinishRequest {
+    pub challenge_id: Uuid,
+    pub name: Option<String>,
+    pub credential: RegistrationCredential,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthenticationCredential {
+    pub id: String,
+    pub response: AssertionResponse,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebauthnLoginFinishRequest {
+    pub challenge_id: Uuid,
+    pub credential: AuthenticationCredential,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct LoginLockout {
+    pub key: String,
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockLoginRequest {
+    pub key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: Session,
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct ShareTorrent {
+    pub share_id: Uuid,
+    pub info_hash: String,
+    pub name: String,
+    pub web_seed_url: String,
+    #[serde(skip)]
+    pub torrent: Vec<u8>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareTorrentInfo {
+    pub info_hash: String,
+    pub magnet_uri: String,
+    pub torrent_url: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct ApiUsageRow {
+    pub bucket: Option<DateTime<Utc>>,
+    pub user_id: Option<Uuid>,
+    pub username: Option<String>,
+    pub method: Option<String>,
+    pub route: Option<String>,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub total_duration_ms: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApiUsageQuery {
+    pub hours: Option<i64>,
+    pub group_by: Option<String>,
+    pub interval: Option<String>,
+    pub user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiUsageEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub error_rate: f64,
+    pub avg_duration_ms: f64,
+}
+
+impl From<ApiUsageRow> for ApiUsageEntry {
+    fn from(row: ApiUsageRow) -> Self {
+        let requests = row.requests.max(1) as f64;
+        Self {
+            bucket: row.bucket,
+            user_id: row.user_id,
+            username: row.username,
+            method: row.method,
+            route: row.route,
+            requests: row.requests,
+            client_errors: row.client_errors,
+            server_errors: row.server_errors,
+            error_rate: (row.client_errors + row.server_errors) as f64 / requests,
+            avg_duration_ms: row.total_duration_ms as f64 / requests,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApiUsageReport {
+    pub since: DateTime<Utc>,
+    pub group_by: String,
+    pub interval: Option<String>,
+    pub entries: Vec<ApiUsageEntry>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TransferDay {
+    pub day: NaiveDate,
+    pub bytes_uploaded: i64,
+    pub bytes_downloaded: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransferQuery {
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserTransfers {
+    pub month_start: NaiveDate,
+    pub month_egress_limit: Option<i64>,
+    pub month_uploaded: i64,
+    pub month_downloaded: i64,
+    pub days: Vec<TransferDay>,
+}
@@ -0,0 +1,250 @@
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+use chrono::{DateTime, Utc};
+use axum::http::StatusCode;
+use tracing::{error, info};
+use crate::config::Config;
+use crate::models::{ExternalMount, MountEntry, MountKind, SmbCredentials};
+use crate::operations::Progress;
+use crate::{database, quota, smb, trash, AppState};
+
+pub fn normalize_relative_path(path: &str) -> anyhow::Result<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path.trim_matches('/')).components() {
+        match component {
+            Component::Normal(part) => parts.push(
+                part.to_str()
+                    .ok_or_else(|| anyhow::anyhow!("path is not valid UTF-8"))?
+                    .to_string(),
+            ),
+            Component::CurDir => {}
+            _ => anyhow::bail!("path must be relative to the mount and may not contain '..'"),
+        }
+    }
+
+    Ok(parts.join("/"))
+}
+
+pub fn is_allowed_root(host_path: &Path, allowed_roots: &[String]) -> bool {
+    allowed_roots.iter().any(|root| {
+        Path::new(root)
+            .canonicalize()
+            .is_ok_and(|root| host_path.starts_with(root))
+    })
+}
+
+pub fn resolve(host_path: &str, relative: &str) -> anyhow::Result<PathBuf> {
+    let root = Path::new(host_path).canonicalize()?;
+    let target = root.join(relative);
+
+    let resolved = match target.canonicalize() {
+        Ok(resolved) => resolved,
+        Err(_) => {
+            let parent = target
+                .parent()
+                .ok_or_else(|| anyhow::anyhow!("path has no parent"))?
+                .canonicalize()?;
+            parent.join(target.file_name().ok_or_else(|| anyhow::anyhow!("path has no file name"))?)
+        }
+    };
+
+    if !resolved.starts_with(&root) {
+        anyhow::bail!("path escapes the mount root");
+    }
+
+    Ok(resolved)
+}
+
+pub fn read_directory(dir: &Path, parent_path: &str) -> anyhow::Result<Vec<MountEntry>> {
+    let mut entries = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        let metadata = match std::fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let modified_at = metadata.modified().ok().map(DateTime::<Utc>::from);
+
+        entries.push(MountEntry {
+            path: if parent_path.is_empty() { name.clone() } else { format!("{}/{}", parent_path, name) },
+            name,
+            is_dir: metadata.is_dir(),
+            size: if metadata.is_dir() { 0 } else { metadata.len() as i64 },
+            modified_at,
+        });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    Ok(entries)
+}
+
+pub fn smb_credentials(mount: &ExternalMount) -> Option<&SmbCredentials> {
+    if mount.kind != MountKind::Smb.as_str() {
+        return None;
+    }
+    mount.smb_credentials.as_ref().map(|credentials| &credentials.0)
+}
+
+pub async fn list(config: &Config, mount: &ExternalMount, path: &str) -> anyhow::Result<Vec<MountEntry>> {
+    if let Some(credentials) = smb_credentials(mount) {
+        return smb::list(config, &mount.host_path, credentials, path).await;
+    }
+
+    let host_path = mount.host_path.clone();
+    let relative = path.to_string();
+    tokio::task::spawn_blocking(move || read_directory(&resolve(&host_path, &relative)?, &relative)).await?
+}
+
+pub async fn stat(config: &Config, mount: &ExternalMount, path: &str) -> anyhow::Result<Option<MountEntry>> {
+    if let Some(credentials) = smb_credentials(mount) {
+        return smb::stat(config, &mount.host_path, credentials, path).await;
+    }
+
+    let target = resolve(&mount.host_path, path)?;
+    let metadata = match tokio::fs::metadata(&target).await {
+        Ok(metadata) => metadata,
+        Err(_) => return Ok(None),
+    };
+    let name = path.rsplit('/').next().unwrap_or(path).to_string();
+
+    Ok(Some(MountEntry {
+        path: path.to_string(),
+        name,
+        is_dir: metadata.is_dir(),
+        size: if metadata.is_dir() { 0 } else { metadata.len() as i64 },
+        modified_at: metadata.modified().ok().map(DateTime::<Utc>::from),
+    }))
+}
+
+fn open(config: &Config, mount: &ExternalMount, path: &str) -> anyhow::Result<Box<dyn Read + Send>> {
+    match smb_credentials(mount) {
+        Some(credentials) => Ok(Box::new(smb::open(config, &mount.host_path, credentials, path)?)),
+        None => Ok(Box::new(std::fs::File::open(resolve(&mount.host_path, path)?)?)),
+    }
+}
+
+pub const SYNC_STALE_AFTER_HOURS: i64 = 12;
+
+pub async fn run_due_syncs(state: &AppState) {
+    let mounts = match database::get_syncable_mounts(&state.db).await {
+        Ok(mounts) => mounts,
+        Err(e) => {
+            error!("Failed to load mounts to sync: {}", e);
+            return;
+        }
+    };
+
+    for mount in mounts {
+        match database::claim_mount_sync(&state.db, &mount.id, SYNC_STALE_AFTER_HOURS).await {
+            Ok(Some(mount)) => {
+                let _ = run_sync(state, mount, None).await;
+            }
+            Ok(None) => {}
+            Err(e) => error!("Failed to claim sync of mount {}: {}", mount.id, e),
+        }
+    }
+}
+
+pub async fn run_sync(state: &AppState, mount: ExternalMount, progress: Option<&Progress>) -> anyhow::Result<(i64, i64)> {
+    let result = sync(state, &mount, progress).await;
+
+    match &result {
+        Ok((files, bytes)) => info!("Synced mount '{}' ({}): {} files, {} bytes", mount.name, mount.id, files, bytes),
+        Err(e) => error!("Sync of mount '{}' ({}) failed: {:#}", mount.name, mount.id, e),
+    }
+
+    let error = result.as_ref().err().map(|e| format!("{:#}", e));
+    if let Err(e) = database::finish_mount_sync(&state.db, &mount.id, error.as_deref()).await {
+        error!("Failed to record sync of mount {}: {}", mount.id, e);
+    }
+
+    result
+}
+
+async fn sync(state: &AppState, mount: &ExternalMount, progress: Option<&Progress>) -> anyhow::Result<(i64, i64)> {
+    let root_folder = mount.sync_folder_id.ok_or_else(|| anyhow::anyhow!("mount has no sync folder"))?;
+    let mut pending = vec![(String::new(), root_folder)];
+    let mut files_synced = 0i64;
+    let mut bytes_synced = 0i64;
+
+    while let Some((path, folder_id)) = pending.pop() {
+        let entries = list(&state.config, mount, &path).await?;
+        let existing = database::get_files_in_folder(&state.db, &mount.user_id, Some(&folder_id)).await?;
+
+        for entry in entries {
+            if entry.is_dir {
+                let (folder, _) = database::get_or_create_folder(&state.db, &mount.user_id, Some(&folder_id), &entry.name).await?;
+                pending.push((entry.path, folder.id));
+                continue;
+            }
+
+            let current = existing.iter().find(|file| file.original_filename == entry.name);
+            if current.is_some_and(|file| {
+                file.file_size == entry.size
+                    && file.client_modified_at.map(|t| t.timestamp()) == entry.modified_at.map(|t| t.timestamp())
+            }) {
+                continue;
+            }
+
+            match quota::check_upload_quota(state, &mount.user_id, entry.size).await {
+                Ok(_) => {}
+                Err(StatusCode::INSUFFICIENT_STORAGE) => anyhow::bail!("storage quota exceeded while syncing {}", entry.path),
+                Err(status) => anyhow::bail!("could not check the storage quota while syncing {}: {}", entry.path, status),
+            }
+
+            let stored = {
+                let state = state.clone();
+                let mount = mount.clone();
+                let entry = entry.clone();
+                tokio::task::spawn_blocking(move || {
+                    let mut reader = open(&state.config, &mount, &entry.path)?;
+                    state.file_storage.store_reader(&mut reader, entry.size as u64, &mount.user_id, &entry.name)
+                })
+                .await??
+            };
+
+            let record = match database::create_file_record(
+                &state.db,
+                &mount.user_id,
+                &stored.filename,
+                &entry.name,
+                &stored.file_path,
+                &stored.disk_path,
+                stored.file_size,
+                stored.stored_size,
+                stored.encoding,
+                stored.mime_type.as_deref(),
+                Some(&stored.checksum),
+            )
+            .await
+            {
+                Ok(record) => record,
+                Err(e) => {
+                    let _ = state.file_storage.delete_file(&stored.file_path);
+                    return Err(e);
+                }
+            };
+            database::set_file_folder(&state.db, &record.id, Some(&folder_id)).await?;
+            database::set_file_client_modified_at(&state.db, &record.id, entry.modified_at).await?;
+
+            if let Some(current) = current {
+                database::soft_delete_file(&state.db, &current.id, &mount.user_id).await?;
+            }
+
+            files_synced += 1;
+            bytes_synced += stored.file_size;
+            if let Some(progress) = progress {
+                progress.update(files_synced, None).await?;
+            }
+        }
+    }
+
+    trash::enforce_limit_quietly(state, &mount.user_id).await;
+
+    Ok((files_synced, bytes_synced))
+}
@@ -0,0 +1,146 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::config::Config;
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub preferred_username: Option<String>,
+}
+
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+pub async fn discover(client: &reqwest::Client, issuer_url: &str) -> anyhow::Result<ProviderMetadata> {
+    let url = format!("{}/.well-known/openid-configuration", issuer_url.trim_end_matches('/'));
+    let metadata = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<ProviderMetadata>()
+        .await?;
+
+    Ok(metadata)
+}
+
+pub fn authorization_url(
+    metadata: &ProviderMetadata,
+    config: &Config,
+    redirect_uri: &str,
+    state: &str,
+    nonce: &str,
+    code_verifier: &str,
+) -> anyhow::Result<String> {
+    let client_id = config.oidc_client_id.as_deref().unwrap_or_default();
+    let mut url = reqwest::Url::parse(&metadata.authorization_endpoint)?;
+    url.query_pairs_mut()
+        .append_pair("response_type", "code")
+        .append_pair("client_id", client_id)
+        .append_pair("redirect_uri", redirect_uri)
+        .append_pair("scope", &config.oidc_scopes)
+        .append_pair("state", state)
+        .append_pair("nonce", nonce)
+        .append_pair("code_challenge", &pkce_challenge(code_verifier))
+        .append_pair("code_challenge_method", "S256");
+
+    Ok(url.to_string())
+}
+
+pub async fn exchange_code(
+    client: &reqwest::Client,
+    metadata: &ProviderMetadata,
+    config: &Config,
+    redirect_uri: &str,
+    code: &str,
+    code_verifier: &str,
+) -> anyhow::Result<String> {
+    let client_id = config.oidc_client_id.as_deref().unwrap_or_default();
+    let mut request = client.post(&metadata.token_endpoint).form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_uri),
+        ("client_id", client_id),
+        ("code_verifier", code_verifier),
+    ]);
+    if let Some(secret) = &config.oidc_client_secret {
+        request = request.basic_auth(client_id, Some(secret));
+    }
+
+    let response = request.send().await?.error_for_status()?.json::<TokenResponse>().await?;
+    Ok(response.id_token)
+}
+
+pub async fn verify_id_token(
+    client: &reqwest::Client,
+    metadata: &ProviderMetadata,
+    config: &Config,
+    id_token: &str,
+    nonce: &str,
+) -> anyhow::Result<IdTokenClaims> {
+    let header = decode_header(id_token)?;
+    let jwks = client
+        .get(&metadata.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<JwkSet>()
+        .await?;
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None => jwks.keys.first(),
+    }
+    .ok_or_else(|| anyhow::anyhow!("no signing key matches the ID token"))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&metadata.issuer]);
+    validation.set_audience(&[config.oidc_client_id.as_deref().unwrap_or_default()]);
+
+    let claims = decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?.claims;
+    if claims.nonce.as_deref() != Some(nonce) {
+        anyhow::bail!("ID token nonce does not match");
+    }
+
+    Ok(claims)
+}
+
+pub fn username_candidate(claims: &IdTokenClaims) -> String {
+    let base = claims
+        .preferred_username
+        .as_deref()
+        .or_else(|| claims.email.as_deref().and_then(|email| email.split('@').next()))
+        .unwrap_or(&claims.sub);
+
+    let cleaned: String = base
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+        .take(64)
+        .collect();
+
+    if cleaned.is_empty() {
+        "user".to_string()
+    } else {
+        cleaned
+    }
+}
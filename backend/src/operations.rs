@@ -0,0 +1,49 @@
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+use crate::database;
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Clone)]
+pub struct Progress {
+    db: PgPool,
+    pub operation_id: Uuid,
+}
+
+impl Progress {
+    pub fn new(db: PgPool, operation_id: Uuid) -> Self {
+        Self { db, operation_id }
+    }
+
+    pub async fn update(&self, current: i64, total: Option<i64>) -> anyhow::Result<()> {
+        if database::update_operation_progress(&self.db, &self.operation_id, current, total).await? {
+            return Err(Cancelled.into());
+        }
+        Ok(())
+    }
+}
+
+pub async fn finish(db: &PgPool, operation_id: &Uuid, result: anyhow::Result<serde_json::Value>) {
+    let recorded = match result {
+        Ok(value) => database::finish_operation(db, operation_id, "succeeded", Some(&value), None).await,
+        Err(e) if e.is::<Cancelled>() => database::finish_operation(db, operation_id, "cancelled", None, None).await,
+        Err(e) => {
+            warn!("Operation {} failed: {:#}", operation_id, e);
+            database::finish_operation(db, operation_id, "failed", None, Some(&format!("{:#}", e))).await
+        }
+    };
+
+    if let Err(e) = recorded {
+        warn!("Failed to record result of operation {}: {}", operation_id, e);
+    }
+}
@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use crate::preview;
+
+pub const FOLDER_NAME: &str = "Pasted";
+pub const MAX_SIZE: usize = 25 * 1024 * 1024;
+
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/webp", "webp"),
+    ("image/bmp", "bmp"),
+    ("image/tiff", "tiff"),
+    ("image/heic", "heic"),
+    ("text/plain", "txt"),
+    ("text/html", "html"),
+    ("text/markdown", "md"),
+    ("text/csv", "csv"),
+    ("text/rtf", "rtf"),
+];
+
+fn extension(mime_type: &str) -> Option<&'static str> {
+    EXTENSIONS
+        .iter()
+        .find(|(candidate, _)| *candidate == mime_type)
+        .map(|(_, extension)| *extension)
+}
+
+pub fn detect_mime_type(data: &[u8], declared: Option<&str>) -> Option<String> {
+    let sniffed = infer::get(&data[..data.len().min(preview::SNIFF_LENGTH)]).map(|kind| kind.mime_type());
+    if let Some(mime_type) = sniffed.filter(|mime_type| mime_type.starts_with("image/")) {
+        return extension(mime_type).map(|_| mime_type.to_string());
+    }
+    if sniffed.is_some() {
+        return None;
+    }
+
+    let text = std::str::from_utf8(data).is_ok();
+    match declared.and_then(|value| preview::normalize_mime_type(value.split(';').next().unwrap_or_default())) {
+        Some(declared) if declared.starts_with("text/") && text => {
+            Some(if extension(&declared).is_some() { declared } else { "text/plain".to_string() })
+        }
+        Some(declared) if declared.starts_with("image/") => None,
+        _ if text => Some("text/plain".to_string()),
+        _ => None,
+    }
+}
+
+pub fn filename(mime_type: &str, name: Option<&str>, now: &DateTime<Utc>) -> String {
+    let extension = extension(mime_type).unwrap_or("bin");
+    match name.map(str::trim).filter(|name| !name.is_empty()) {
+        Some(name) if std::path::Path::new(name).extension().is_some() => name.to_string(),
+        Some(name) => format!("{}.{}", name, extension),
+        None => {
+            let kind = if mime_type.starts_with("image/") { "image" } else { "text" };
+            format!("Pasted {} {}.{}", kind, now.format("%Y-%m-%d %H-%M-%S"), extension)
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::models::{PreviewHandler, PreviewHandlerRow, PreviewStrategy};
+
+pub const DEFAULT_HANDLERS: &[(&str, PreviewStrategy)] = &[
+    ("image/*", PreviewStrategy::Image),
+    ("image/svg+xml", PreviewStrategy::Native),
+    ("video/*", PreviewStrategy::Native),
+    ("audio/*", PreviewStrategy::Native),
+    ("text/*", PreviewStrategy::Native),
+    ("application/pdf", PreviewStrategy::Native),
+    ("application/json", PreviewStrategy::Native),
+    ("application/msword", PreviewStrategy::Office),
+    ("application/vnd.ms-excel", PreviewStrategy::Office),
+    ("application/vnd.ms-powerpoint", PreviewStrategy::Office),
+    ("application/vnd.openxmlformats-officedocument.*", PreviewStrategy::Office),
+    ("application/vnd.oasis.opendocument.*", PreviewStrategy::Office),
+];
+
+const EXTENSION_TYPES: &[(&str, &str)] = &[
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("heic", "image/heic"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("svg", "image/svg+xml"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("json", "application/json"),
+    ("pdf", "application/pdf"),
+    ("doc", "application/msword"),
+    ("xls", "application/vnd.ms-excel"),
+    ("ppt", "application/vnd.ms-powerpoint"),
+    ("docx", "application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+    ("xlsx", "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"),
+    ("pptx", "application/vnd.openxmlformats-officedocument.presentationml.presentation"),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("ods", "application/vnd.oasis.opendocument.spreadsheet"),
+    ("odp", "application/vnd.oasis.opendocument.presentation"),
+];
+
+pub fn effective_mime_type(mime_type: Option<&str>, filename: &str) -> Option<String> {
+    if let Some(mime_type) = mime_type {
+        return Some(mime_type.to_string());
+    }
+
+    let extension = std::path::Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())?
+        .to_ascii_lowercase();
+
+    EXTENSION_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime_type)| mime_type.to_string())
+}
+
+pub const SNIFF_LENGTH: usize = 8192;
+
+const CONTAINER_TYPES: &[&str] = &["application/zip", "application/x-ole-storage"];
+
+pub fn sniff_mime_type(data: &[u8], filename: &str) -> Option<String> {
+    let sniffed = infer::get(data).map(|kind| kind.mime_type());
+    if let Some(mime_type) = sniffed.filter(|mime_type| !CONTAINER_TYPES.contains(mime_type)) {
+        return Some(mime_type.to_string());
+    }
+
+    if let Some(mime_type) = effective_mime_type(None, filename) {
+        return Some(mime_type);
+    }
+
+    if let Some(mime_type) = sniffed {
+        return Some(mime_type.to_string());
+    }
+
+    let is_text = !data.is_empty()
+        && !data.contains(&0)
+        && std::str::from_utf8(data).map_or_else(|e| e.error_len().is_none(), |_| true);
+    is_text.then(|| "text/plain".to_string())
+}
+
+pub fn normalize_mime_type(value: &str) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    let (kind, subtype) = value.split_once('/')?;
+    let valid = |part: &str| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "!#$&^_.+-".contains(c))
+    };
+
+    if !valid(kind) {
+        return None;
+    }
+
+    match subtype.strip_suffix('*') {
+        Some("") => Some(value),
+        Some(prefix) if prefix.ends_with('.') && valid(prefix) => Some(value),
+        Some(_) => None,
+        None if valid(subtype) => Some(value),
+        None => None,
+    }
+}
+
+pub fn handlers(custom: Vec<PreviewHandlerRow>) -> Vec<PreviewHandler> {
+    let mut handlers: Vec<PreviewHandler> = DEFAULT_HANDLERS
+        .iter()
+        .filter(|(mime_type, _)| !custom.iter().any(|row| row.mime_type == *mime_type))
+        .map(|(mime_type, strategy)| PreviewHandler {
+            mime_type: mime_type.to_string(),
+            strategy: *strategy,
+            is_default: true,
+            updated_at: None,
+        })
+        .collect();
+
+    handlers.extend(custom.into_iter().filter_map(|row| {
+        Some(PreviewHandler {
+            strategy: PreviewStrategy::parse(&row.strategy)?,
+            mime_type: row.mime_type,
+            is_default: false,
+            updated_at: Some(row.updated_at),
+        })
+    }));
+
+    handlers.sort_by(|a, b| a.mime_type.cmp(&b.mime_type));
+    handlers
+}
+
+pub fn resolve(handlers: &[PreviewHandler], mime_type: Option<&str>) -> PreviewStrategy {
+    let mime_type = match mime_type.and_then(|m| m.split(';').next()) {
+        Some(mime_type) => mime_type.trim().to_ascii_lowercase(),
+        None => return PreviewStrategy::None,
+    };
+
+    handlers
+        .iter()
+        .filter_map(|handler| {
+            let specificity = match handler.mime_type.strip_suffix('*') {
+                Some(prefix) if mime_type.starts_with(prefix) => prefix.len(),
+                Some(_) => return None,
+                None if handler.mime_type == mime_type => usize::MAX,
+                None => return None,
+            };
+            Some((specificity, handler.strategy))
+        })
+        .max_by_key(|(specificity, _)| *specificity)
+        .map(|(_, strategy)| strategy)
+        .unwrap_or(PreviewStrategy::None)
+}
@@ -0,0 +1,73 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use crate::config::Config;
+use crate::models::FileInfo;
+
+const LP_TIMEOUT: Duration = Duration::from_secs(30);
+pub const MAX_COPIES: u32 = 20;
+
+const PRINTABLE_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/postscript",
+    "text/plain",
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/tiff",
+];
+
+pub fn is_printable(mime_type: Option<&str>) -> bool {
+    mime_type.is_some_and(|mime_type| PRINTABLE_TYPES.contains(&mime_type))
+}
+
+pub fn valid_page_ranges(page_ranges: &str) -> bool {
+    !page_ranges.is_empty()
+        && page_ranges.split(',').all(|range| {
+            let mut bounds = range.splitn(2, '-');
+            bounds.all(|bound| !bound.is_empty() && bound.len() <= 6 && bound.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+fn job_id(stdout: &str) -> Option<String> {
+    let rest = stdout.trim().strip_prefix("request id is ")?;
+    rest.split_whitespace().next().map(|id| id.to_string())
+}
+
+pub async fn submit(
+    config: &Config,
+    file: &FileInfo,
+    source: &Path,
+    mime_type: &str,
+    copies: u32,
+    page_ranges: Option<&str>,
+) -> anyhow::Result<Option<String>> {
+    let mut command = Command::new(&config.lp_path);
+    if let Some(printer) = &config.printer {
+        command.arg("-d").arg(printer);
+    }
+    command
+        .arg("-n")
+        .arg(copies.to_string())
+        .arg("-t")
+        .arg(&file.original_filename);
+    if let Some(page_ranges) = page_ranges {
+        command.arg("-P").arg(page_ranges);
+    }
+    if mime_type.starts_with("image/") {
+        command.arg("-o").arg("fit-to-page");
+    }
+    command.arg("--").arg(source).stdin(Stdio::null()).kill_on_drop(true);
+
+    let output = tokio::time::timeout(LP_TIMEOUT, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("{} timed out", config.lp_path))?
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", config.lp_path, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} exited with {}: {}", config.lp_path, output.status, stderr.lines().last().unwrap_or_default());
+    }
+
+    Ok(job_id(&String::from_utf8_lossy(&output.stdout)))
+}
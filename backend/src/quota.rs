@@ -0,0 +1,78 @@
+use axum::http::StatusCode;
+use tracing::info;
+use uuid::Uuid;
+use crate::models::{QuotaStatus, UserQuota};
+use crate::{database, tenants, AppState};
+
+pub fn quota_status(quota: &UserQuota, grace_period_days: i64) -> QuotaStatus {
+    let over_soft_limit = quota.quota_soft_bytes
+        .map(|limit| quota.storage_used > limit)
+        .unwrap_or(false);
+
+    QuotaStatus {
+        used_bytes: quota.storage_used,
+        soft_limit: quota.quota_soft_bytes,
+        hard_limit: quota.quota_hard_bytes,
+        over_soft_limit,
+        grace_expires_at: quota.quota_grace_started_at
+            .map(|started| started + chrono::Duration::days(grace_period_days)),
+    }
+}
+
+pub async fn check_upload_quota(
+    state: &AppState,
+    user_id: &Uuid,
+    upload_size: i64,
+) -> Result<Option<QuotaStatus>, StatusCode> {
+    tenants::check_quota(state, user_id, upload_size).await?;
+
+    let mut quota = database::get_user_quota(&state.db, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let pending = database::get_pending_upload_bytes(&state.db, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let projected = quota.storage_used.saturating_add(pending).saturating_add(upload_size);
+
+    if let Some(hard_limit) = quota.quota_hard_bytes {
+        if projected > hard_limit {
+            return Err(StatusCode::INSUFFICIENT_STORAGE);
+        }
+    }
+
+    let soft_limit = match quota.quota_soft_bytes {
+        Some(limit) => limit,
+        None => return Ok(None),
+    };
+
+    if projected <= soft_limit {
+        if quota.quota_grace_started_at.is_some() {
+            database::set_quota_grace_started_at(&state.db, user_id, None)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        }
+        return Ok(None);
+    }
+
+    let now = chrono::Utc::now();
+    match quota.quota_grace_started_at {
+        Some(started) if now > started + chrono::Duration::days(state.config.quota_grace_period_days) => {
+            return Err(StatusCode::INSUFFICIENT_STORAGE);
+        }
+        Some(_) => {}
+        None => {
+            database::set_quota_grace_started_at(&state.db, user_id, Some(now))
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            quota.quota_grace_started_at = Some(now);
+            info!("User {} exceeded soft quota, grace period started", user_id);
+        }
+    }
+
+    let mut status = quota_status(&quota, state.config.quota_grace_period_days);
+    status.used_bytes = projected;
+    status.over_soft_limit = true;
+    Ok(Some(status))
+}
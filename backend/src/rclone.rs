@@ -0,0 +1,78 @@
+use chrono::{DateTime, TimeZone, Utc};
+use crate::sigv4::uri_encode;
+
+pub const MTIME_HEADER: &str = "x-oc-mtime";
+
+pub struct ListingEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: i64,
+    pub modified: DateTime<Utc>,
+}
+
+pub fn parse_mtime(value: &str) -> Option<DateTime<Utc>> {
+    let seconds: f64 = value.trim().parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+
+    let whole = seconds.trunc() as i64;
+    let nanos = ((seconds - seconds.trunc()) * 1_000_000_000.0) as u32;
+    Utc.timestamp_opt(whole, nanos).single()
+}
+
+pub fn split_path(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.to_string())
+        .collect()
+}
+
+fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn render_listing(path: &str, entries: &[ListingEntry]) -> String {
+    let title = escape_html(&format!("/{}", path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<pre>\n",
+        title
+    );
+
+    for entry in entries {
+        let (href, label, size) = if entry.is_dir {
+            (format!("{}/", uri_encode(&entry.name, true)), format!("{}/", entry.name), "-".to_string())
+        } else {
+            (uri_encode(&entry.name, true), entry.name.clone(), entry.size.to_string())
+        };
+
+        html.push_str(&format!(
+            "<a href=\"{}\">{}</a> {} {}\n",
+            href,
+            escape_html(&label),
+            entry.modified.format("%Y-%m-%d %H:%M:%S"),
+            size
+        ));
+    }
+
+    html.push_str("</pre>\n</body>\n</html>\n");
+    html
+}
+
+pub fn render_sha256sums(entries: &[(String, String)]) -> String {
+    entries
+        .iter()
+        .map(|(path, checksum)| format!("{}  {}\n", checksum, path))
+        .collect()
+}
+
+pub fn config_snippet(remote_url: &str) -> String {
+    format!(
+        "[local-drive]\ntype = http\nurl = {}\nheaders = Authorization,Bearer YOUR_TOKEN\n",
+        remote_url
+    )
+}
@@ -0,0 +1,229 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::models::FileInfo;
+use crate::operations::Progress;
+use crate::{database, video, AppState};
+
+pub const DEFAULT_TOLERANCE_PERCENT: f64 = 5.0;
+
+const PAUSE_BETWEEN_FILES: Duration = Duration::from_millis(20);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+struct Disk {
+    path: PathBuf,
+    total: u64,
+    used: u64,
+}
+
+impl Disk {
+    fn usage(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.used as f64 / self.total as f64
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    moved: i64,
+    bytes: i64,
+    failed: i64,
+}
+
+impl Totals {
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "moved": self.moved,
+            "bytes": self.bytes,
+            "failed": self.failed,
+        })
+    }
+}
+
+async fn move_file(state: &AppState, file: &FileInfo, disk_path: PathBuf) -> anyhow::Result<bool> {
+    let file_storage = state.file_storage.clone();
+    let (file_path, user_id, filename) = (file.file_path.clone(), file.user_id, file.filename.clone());
+    let target = {
+        let disk_path = disk_path.clone();
+        tokio::task::spawn_blocking(move || file_storage.copy_to_disk(&file_path, &user_id, &filename, &disk_path)).await??
+    };
+    let (disk_path, target) = (disk_path.to_string_lossy().to_string(), target.to_string_lossy().to_string());
+
+    let moved = database::set_file_storage(&state.db, &file.id, &file.file_path, &target, &disk_path).await;
+    if let Ok(true) = moved {
+        let (old_poster, new_poster) = (video::poster_path(&file.file_path), video::poster_path(&target));
+        let poster = database::move_file_poster(
+            &state.db,
+            &file.id,
+            &old_poster.to_string_lossy(),
+            &new_poster.to_string_lossy(),
+        )
+        .await;
+        if let Err(e) = poster {
+            warn!("Failed to update the poster path of file {} after moving it: {:#}", file.id, e);
+        }
+    }
+
+    let (stale, current) = match &moved {
+        Ok(true) => (file.file_path.clone(), target),
+        _ => (target, file.file_path.clone()),
+    };
+    let file_storage = state.file_storage.clone();
+    let user_id = file.user_id;
+    let removed = tokio::task::spawn_blocking(move || {
+        file_storage.remove_copy(&stale)?;
+        file_storage.replicate(&current, &user_id)
+    })
+    .await?;
+    if let Err(e) = removed {
+        warn!("Failed to clean up the copies of file {} after moving it to {}: {:#}", file.id, disk_path, e);
+    }
+
+    moved
+}
+
+async fn record(state: &AppState, file: &FileInfo, target: PathBuf, progress: Option<&Progress>, totals: &mut Totals) -> anyhow::Result<bool> {
+    let target_display = target.display().to_string();
+    let moved = match move_file(state, file, target).await {
+        Ok(true) => {
+            totals.moved += 1;
+            totals.bytes += file.file_size;
+            true
+        }
+        Ok(false) => false,
+        Err(e) => {
+            error!("Failed to move file {} to {}: {:#}", file.id, target_display, e);
+            totals.failed += 1;
+            false
+        }
+    };
+    if let Some(progress) = progress {
+        progress.update(totals.moved, None).await?;
+    }
+    tokio::time::sleep(PAUSE_BETWEEN_FILES).await;
+
+    Ok(moved)
+}
+
+async fn evacuate(state: &AppState, disk_path: &Path, progress: Option<&Progress>, totals: &mut Totals) -> anyhow::Result<()> {
+    let disk = disk_path.to_string_lossy().to_string();
+    let mut seen: Vec<Uuid> = Vec::new();
+    while let Some(file) = database::get_largest_file_on_disk(&state.db, &disk, None, &seen).await? {
+        seen.push(file.id);
+        let target = state.file_storage.find_available_disk(&file.user_id, file.file_size.max(0) as u64)?;
+        match target {
+            Some(target) => {
+                record(state, &file, target, progress, totals).await?;
+            }
+            None => {
+                warn!("No writable disk has room for file {} ({} bytes) on read-only {}", file.id, file.file_size, disk);
+                totals.failed += 1;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn balance(state: &AppState, disks: &mut [Disk], tolerance: f64, progress: Option<&Progress>, totals: &mut Totals) -> anyhow::Result<()> {
+    let mut seen: Vec<Uuid> = Vec::new();
+    loop {
+        let (source, target) = match (
+            (0..disks.len()).max_by(|a, b| disks[*a].usage().total_cmp(&disks[*b].usage())),
+            (0..disks.len()).min_by(|a, b| disks[*a].usage().total_cmp(&disks[*b].usage())),
+        ) {
+            (Some(source), Some(target)) if source != target => (source, target),
+            _ => return Ok(()),
+        };
+        let gap = disks[source].usage() - disks[target].usage();
+        if gap <= tolerance {
+            return Ok(());
+        }
+
+        let excess = gap / (1.0 / disks[source].total as f64 + 1.0 / disks[target].total as f64);
+        let room = disks[target].total.saturating_sub(disks[target].used) as f64;
+        let max_size = excess.min(room) as i64;
+        let source_path = disks[source].path.to_string_lossy().to_string();
+        let file = match database::get_largest_file_on_disk(&state.db, &source_path, Some(max_size), &seen).await? {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        seen.push(file.id);
+        if !state.file_storage.tenant_allows(&file.user_id, &disks[target].path) {
+            continue;
+        }
+
+        let size = file.file_size.max(0) as u64;
+        if record(state, &file, disks[target].path.clone(), progress, totals).await? {
+            disks[source].used = disks[source].used.saturating_sub(size);
+            disks[target].used += size;
+        }
+    }
+}
+
+async fn rebalance(state: &AppState, tolerance_percent: f64, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    let mut totals = Totals::default();
+    for path in &state.file_storage.storage_paths() {
+        if state.file_storage.is_read_only(path) {
+            evacuate(state, path, progress, &mut totals).await?;
+        }
+    }
+
+    let mut disks: Vec<Disk> = state
+        .file_storage
+        .storage_paths()
+        .into_iter()
+        .zip(state.file_storage.get_disk_info()?)
+        .filter(|(_, disk_info)| disk_info.is_accessible && !disk_info.is_read_only && disk_info.total_space > 0)
+        .map(|(path, disk_info)| Disk {
+            path: path.clone(),
+            total: disk_info.total_space,
+            used: disk_info.used_space + disk_info.reserved_space,
+        })
+        .collect();
+    balance(state, &mut disks, tolerance_percent / 100.0, progress, &mut totals).await?;
+
+    info!("Storage rebalance moved {} files ({} bytes), {} failed", totals.moved, totals.bytes, totals.failed);
+    Ok(totals.to_json())
+}
+
+async fn evacuate_only(state: &AppState, disk_path: &Path, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    state.file_storage.mark_read_only(disk_path);
+    let mut totals = Totals::default();
+    evacuate(state, disk_path, progress, &mut totals).await?;
+
+    let disk = disk_path.to_string_lossy().to_string();
+    let remaining = database::count_files_on_disk(&state.db, &disk).await?;
+    info!("Evacuating {} moved {} files ({} bytes), {} left on it", disk, totals.moved, totals.bytes, remaining);
+    let mut result = totals.to_json();
+    result["disk_path"] = serde_json::json!(disk);
+    result["remaining"] = serde_json::json!(remaining);
+    Ok(result)
+}
+
+pub async fn run(state: &AppState, tolerance_percent: f64, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("a storage rebalance is already running");
+    }
+    let result = rebalance(state, tolerance_percent, progress).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+pub async fn evacuate_disk(state: &AppState, disk_path: &Path, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("a storage rebalance is already running");
+    }
+    let result = evacuate_only(state, disk_path, progress).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
@@ -0,0 +1,420 @@
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use axum::http::StatusCode;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{header, redirect, Url};
+use tokio::io::AsyncWriteExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::config::Config;
+use crate::models::{FileInfo, RemoteFetch};
+use crate::{database, preview, quota, AppState};
+
+const MAX_REDIRECTS: usize = 5;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || a == 0
+                || a >= 240
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(ip) => {
+            // Addresses that embed an IPv4 address reach that host, so they are
+            // only as public as it is.
+            let embedded = |high: u16, low: u16| Ipv4Addr::from(((high as u32) << 16) | low as u32);
+            let segments = ip.segments();
+            match segments {
+                [0, 0, 0, 0, 0, 0xffff, high, low] | [0, 0, 0, 0, 0, 0, high, low] | [0x64, 0xff9b, 0, 0, 0, 0, high, low] => {
+                    return is_public(IpAddr::V4(embedded(high, low)));
+                }
+                [0x2002, high, low, ..] => return is_public(IpAddr::V4(embedded(high, low))),
+                _ => {}
+            }
+            let [first, second, third, ..] = segments;
+            !(ip.is_multicast()
+                || first & 0xfe00 == 0xfc00
+                || first & 0xffc0 == 0xfe80
+                || (first == 0x2001 && second == 0x0db8)
+                || (first == 0x64 && second == 0xff9b && third == 1))
+        }
+    }
+}
+
+fn check_url(url: &Url, allow_private: bool) -> anyhow::Result<()> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        anyhow::bail!("only http and https URLs can be imported");
+    }
+
+    let host = url.host_str().ok_or_else(|| anyhow::anyhow!("URL has no host"))?;
+    if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() {
+        if !allow_private && !is_public(ip) {
+            anyhow::bail!("{} is not a public address", ip);
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_url(value: &str, allow_private: bool) -> anyhow::Result<Url> {
+    let url = Url::parse(value)?;
+    check_url(&url, allow_private)?;
+    Ok(url)
+}
+
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+fn client(config: &Config) -> reqwest::Result<reqwest::Client> {
+    let allow_private = config.remote_fetch_allow_private;
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(Duration::from_secs(30))
+        .read_timeout(Duration::from_secs(60))
+        .redirect(redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            match check_url(attempt.url(), allow_private) {
+                Ok(()) => attempt.follow(),
+                Err(e) => attempt.error(e.to_string()),
+            }
+        }));
+
+    if !allow_private {
+        builder = builder.no_proxy().dns_resolver(Arc::new(PublicResolver));
+    }
+
+    builder.build()
+}
+
+fn type_allowed(config: &Config, mime_type: Option<&str>) -> bool {
+    if config.remote_fetch_allowed_types.is_empty() {
+        return true;
+    }
+    let mime_type = match mime_type {
+        Some(mime_type) => mime_type,
+        None => return false,
+    };
+
+    config.remote_fetch_allowed_types.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => mime_type.starts_with(prefix),
+        None => pattern == mime_type,
+    })
+}
+
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            if let Some(byte) = value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn disposition_filename(value: &str) -> Option<String> {
+    let mut filename = None;
+    for part in value.split(';') {
+        let (key, value) = match part.split_once('=') {
+            Some(pair) => pair,
+            None => continue,
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                if let Some(encoded) = value.trim().splitn(3, '\'').nth(2) {
+                    return Some(percent_decode(encoded));
+                }
+            }
+            "filename" => filename = Some(value.trim().trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+    filename
+}
+
+fn sanitize_filename(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let name: String = name.chars().filter(|c| !c.is_control()).take(255).collect();
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+fn filename_for(fetch: &RemoteFetch, url: &Url, response: &reqwest::Response) -> String {
+    fetch
+        .filename
+        .clone()
+        .or_else(|| {
+            response
+                .headers()
+                .get(header::CONTENT_DISPOSITION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(disposition_filename)
+                .and_then(|name| sanitize_filename(&name))
+        })
+        .or_else(|| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .and_then(|name| sanitize_filename(&percent_decode(name)))
+        })
+        .unwrap_or_else(|| "download".to_string())
+}
+
+async fn check_size(state: &AppState, user_id: &Uuid, size: i64) -> anyhow::Result<()> {
+    if size > state.config.remote_fetch_max_size {
+        anyhow::bail!("file is larger than the {} byte import limit", state.config.remote_fetch_max_size);
+    }
+
+    match quota::check_upload_quota(state, user_id, size).await {
+        Ok(_) => Ok(()),
+        Err(StatusCode::INSUFFICIENT_STORAGE) => anyhow::bail!("storage quota exceeded"),
+        Err(status) => anyhow::bail!("could not check the storage quota: {}", status),
+    }
+}
+
+async fn receive(
+    state: &AppState,
+    fetch: &RemoteFetch,
+    response: &mut reqwest::Response,
+    temp_path: &Path,
+) -> anyhow::Result<i64> {
+    let mut file = tokio::fs::File::create(temp_path).await?;
+    let mut downloaded = 0i64;
+    let mut last_progress = Instant::now();
+
+    while let Some(chunk) = response.chunk().await? {
+        downloaded += chunk.len() as i64;
+        if downloaded > state.config.remote_fetch_max_size {
+            anyhow::bail!("file is larger than the {} byte import limit", state.config.remote_fetch_max_size);
+        }
+        file.write_all(&chunk).await?;
+
+        if last_progress.elapsed() >= PROGRESS_INTERVAL {
+            database::update_remote_fetch_progress(&state.db, &fetch.id, downloaded).await?;
+            last_progress = Instant::now();
+        }
+    }
+
+    file.sync_all().await?;
+    database::update_remote_fetch_progress(&state.db, &fetch.id, downloaded).await?;
+    Ok(downloaded)
+}
+
+async fn download(state: &AppState, fetch: &RemoteFetch) -> anyhow::Result<FileInfo> {
+    let url = parse_url(&fetch.url, state.config.remote_fetch_allow_private)?;
+    let mut response = client(&state.config)?.get(url.clone()).send().await?.error_for_status()?;
+
+    let filename = filename_for(fetch, &url, &response);
+    let declared_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| preview::normalize_mime_type(value.split(';').next().unwrap_or_default()))
+        .filter(|mime_type| mime_type != "application/octet-stream");
+    let mime_type = preview::effective_mime_type(declared_type.as_deref(), &filename);
+    if !type_allowed(&state.config, mime_type.as_deref()) {
+        anyhow::bail!("content type {} is not allowed", mime_type.as_deref().unwrap_or("unknown"));
+    }
+
+    let total_bytes = response.content_length().map(|length| length as i64);
+    check_size(state, &fetch.user_id, total_bytes.unwrap_or(0)).await?;
+
+    if state.config.case_insensitive_names {
+        if let Some(existing) = database::find_case_insensitive_name_conflict(
+            &state.db,
+            &fetch.user_id,
+            fetch.folder_id.as_ref(),
+            &filename,
+            None,
+        )
+        .await?
+        {
+            anyhow::bail!("a file named \"{}\" already exists in this folder", existing.original_filename);
+        }
+    }
+
+    database::start_remote_fetch(&state.db, &fetch.id, &filename, total_bytes, mime_type.as_deref()).await?;
+
+    let (user_id, fetch_id) = (fetch.user_id, fetch.id);
+    let (temp_path, disk_path) = state.file_storage
+        .blocking(move |storage| storage.create_temp_file(&user_id, &fetch_id, 0))
+        .await?;
+    let downloaded = match receive(state, fetch, &mut response, &temp_path).await {
+        Ok(downloaded) => downloaded,
+        Err(e) => {
+            cleanup_temp_file(state, temp_path).await;
+            return Err(e);
+        }
+    };
+    if let Err(e) = check_size(state, &fetch.user_id, downloaded).await {
+        cleanup_temp_file(state, temp_path).await;
+        return Err(e);
+    }
+
+    let stored = {
+        let filename = filename.clone();
+        state.file_storage
+            .blocking(move |storage| storage.finalize_chunked_upload(&temp_path, &user_id, &filename, &disk_path))
+            .await?
+    };
+    let mime_type = match (&stored.mime_type, mime_type) {
+        (Some(sniffed), Some(declared)) if sniffed == "text/plain" => Some(declared),
+        (sniffed, declared) => sniffed.clone().or(declared),
+    };
+    if !type_allowed(&state.config, mime_type.as_deref()) {
+        delete_file(state, stored.file_path).await;
+        anyhow::bail!("content type {} is not allowed", mime_type.as_deref().unwrap_or("unknown"));
+    }
+
+    let mut file = match database::create_file_record(
+        &state.db,
+        &fetch.user_id,
+        &stored.filename,
+        &filename,
+        &stored.file_path,
+        &stored.disk_path,
+        stored.file_size,
+        stored.stored_size,
+        stored.encoding,
+        mime_type.as_deref(),
+        Some(&stored.checksum),
+    )
+    .await
+    {
+        Ok(file) => file,
+        Err(e) => {
+            delete_file(state, stored.file_path).await;
+            return Err(e);
+        }
+    };
+
+    if fetch.folder_id.is_some() {
+        database::set_file_folder(&state.db, &file.id, fetch.folder_id.as_ref()).await?;
+        file.folder_id = fetch.folder_id;
+    }
+
+    Ok(file)
+}
+
+async fn cleanup_temp_file(state: &AppState, temp_path: PathBuf) {
+    let _ = state.file_storage.blocking(move |storage| storage.cleanup_temp_file(&temp_path)).await;
+}
+
+async fn delete_file(state: &AppState, file_path: String) {
+    let _ = state.file_storage.blocking(move |storage| storage.delete_file(&file_path)).await;
+}
+
+pub async fn run(state: AppState, fetch: RemoteFetch) {
+    let result = download(&state, &fetch).await;
+
+    let (file_id, error) = match &result {
+        Ok(file) => {
+            info!("URL import {} finished: file {} ({} bytes)", fetch.id, file.id, file.file_size);
+            (Some(file.id), None)
+        }
+        Err(e) => {
+            warn!("URL import {} failed: {:#}", fetch.id, e);
+            (None, Some(format!("{:#}", e)))
+        }
+    };
+
+    if let Err(e) = database::finish_remote_fetch(&state.db, &fetch.id, file_id.as_ref(), error.as_deref()).await {
+        error!("Failed to record the result of URL import {}: {}", fetch.id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn public(ip: &str) -> bool {
+        is_public(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn accepts_public_addresses() {
+        assert!(public("93.184.216.34"));
+        assert!(public("2606:2800:220:1:248:1893:25c8:1946"));
+        assert!(public("::ffff:93.184.216.34"));
+        assert!(public("64:ff9b::93.184.216.34"));
+        assert!(public("2002:5db8:d822::1"));
+    }
+
+    #[test]
+    fn rejects_private_ipv4() {
+        for ip in ["127.0.0.1", "10.0.0.1", "192.168.1.1", "172.16.0.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "240.0.0.1"] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn rejects_private_ipv6() {
+        for ip in ["::1", "::", "fc00::1", "fd12::1", "fe80::1", "ff02::1"] {
+            assert!(!public(ip), "{}", ip);
+        }
+    }
+
+    #[test]
+    fn rejects_ipv4_mapped_private_addresses() {
+        assert!(!public("::ffff:127.0.0.1"));
+        assert!(!public("::ffff:10.0.0.1"));
+    }
+
+    #[test]
+    fn rejects_ipv4_compatible_private_addresses() {
+        assert!(!public("::127.0.0.1"));
+        assert!(!public("::169.254.169.254"));
+    }
+
+    #[test]
+    fn rejects_nat64_private_addresses() {
+        assert!(!public("64:ff9b::127.0.0.1"));
+        assert!(!public("64:ff9b::10.0.0.1"));
+        assert!(!public("64:ff9b:1::5db8:d822"));
+    }
+
+    #[test]
+    fn rejects_6to4_private_addresses() {
+        assert!(!public("2002:7f00:1::1"));
+        assert!(!public("2002:c0a8:101::"));
+    }
+
+    #[test]
+    fn rejects_documentation_addresses() {
+        assert!(!public("2001:db8::1"));
+        assert!(!public("192.0.2.1"));
+    }
+}
@@ -0,0 +1,340 @@
+use std::future::Future;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+use chrono::Utc;
+use reqwest::{Method, StatusCode};
+use crate::sigv4;
+
+pub const SCHEME: &str = "s3://";
+
+const READ_BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+const MULTIPART_THRESHOLD: u64 = 64 * 1024 * 1024;
+const MAX_PARTS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum S3Placement {
+    Overflow,
+    Primary,
+}
+
+#[derive(Clone)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key: String,
+    pub secret_key: String,
+    pub prefix: String,
+    pub placement: S3Placement,
+}
+
+impl std::fmt::Debug for S3Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("S3Config")
+            .field("endpoint", &self.endpoint)
+            .field("bucket", &self.bucket)
+            .field("region", &self.region)
+            .field("prefix", &self.prefix)
+            .field("placement", &self.placement)
+            .finish_non_exhaustive()
+    }
+}
+
+pub fn is_s3_path(path: &str) -> bool {
+    path.starts_with(SCHEME)
+}
+
+pub struct S3Store {
+    config: S3Config,
+    host: String,
+    client: reqwest::Client,
+    runtime: Option<tokio::runtime::Runtime>,
+}
+
+impl Drop for S3Store {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+impl S3Store {
+    pub fn new(config: S3Config) -> anyhow::Result<Self> {
+        let url = reqwest::Url::parse(&config.endpoint)?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(2)
+            .thread_name("s3-store")
+            .enable_all()
+            .build()?;
+
+        Ok(S3Store {
+            config,
+            host,
+            client: reqwest::Client::new(),
+            runtime: Some(runtime),
+        })
+    }
+
+    pub fn placement(&self) -> S3Placement {
+        self.config.placement
+    }
+
+    pub fn root(&self) -> String {
+        format!("{}{}", SCHEME, self.config.bucket)
+    }
+
+    pub fn path_for(&self, key: &str) -> String {
+        format!("{}/{}{}", self.root(), self.config.prefix, key)
+    }
+
+    fn key_of<'a>(&self, path: &'a str) -> anyhow::Result<&'a str> {
+        path.strip_prefix(&self.root())
+            .and_then(|rest| rest.strip_prefix('/'))
+            .filter(|key| !key.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("{} is not in bucket {}", path, self.config.bucket))
+    }
+
+    fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send,
+        F::Output: Send,
+    {
+        let runtime = self.runtime.as_ref().expect("the S3 runtime lives until the store is dropped");
+        std::thread::scope(|scope| {
+            scope
+                .spawn(|| runtime.block_on(future))
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    fn request(&self, method: Method, key: &str, query: &[(String, String)], payload_hash: &str) -> reqwest::RequestBuilder {
+        let canonical_uri = if key.is_empty() {
+            format!("/{}", sigv4::uri_encode(&self.config.bucket, true))
+        } else {
+            format!("/{}/{}", sigv4::uri_encode(&self.config.bucket, true), sigv4::uri_encode(key, false))
+        };
+        let params = sigv4::SigningParams {
+            access_key: &self.config.access_key,
+            secret_key: &self.config.secret_key,
+            region: &self.config.region,
+            service: "s3",
+        };
+        let signed = sigv4::sign(&params, method.as_str(), &self.host, &canonical_uri, query, payload_hash, Utc::now());
+
+        let mut url = format!("{}{}", self.config.endpoint.trim_end_matches('/'), canonical_uri);
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&sigv4::canonical_query_string(query));
+        }
+        self.client
+            .request(method, url)
+            .header("authorization", signed.authorization)
+            .header("x-amz-date", signed.amz_date)
+            .header("x-amz-content-sha256", signed.content_sha256)
+    }
+
+    async fn send(request: reqwest::RequestBuilder, action: &str) -> anyhow::Result<reqwest::Response> {
+        let response = request.send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let code = xml_value(&body, "Code").unwrap_or_default();
+        anyhow::bail!("S3 {} failed with {} {}", action, status, code)
+    }
+
+    async fn head_object(&self, key: &str) -> anyhow::Result<Option<u64>> {
+        let response = self.request(Method::HEAD, key, &[], sigv4::EMPTY_PAYLOAD_SHA256).send().await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(None),
+            status if status.is_success() => Ok(response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())),
+            status => anyhow::bail!("S3 HEAD failed with {}", status),
+        }
+    }
+
+    async fn get_range(&self, key: &str, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+        let request = self
+            .request(Method::GET, key, &[], sigv4::EMPTY_PAYLOAD_SHA256)
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", offset, offset + length - 1));
+        Ok(Self::send(request, "GET").await?.bytes().await?.to_vec())
+    }
+
+    async fn delete_object(&self, key: &str) -> anyhow::Result<()> {
+        let response = self.request(Method::DELETE, key, &[], sigv4::EMPTY_PAYLOAD_SHA256).send().await?;
+        match response.status() {
+            status if status.is_success() || status == StatusCode::NOT_FOUND => Ok(()),
+            status => anyhow::bail!("S3 DELETE failed with {}", status),
+        }
+    }
+
+    async fn put_part(&self, key: &str, query: &[(String, String)], source: &Path, offset: u64, length: u64) -> anyhow::Result<reqwest::Response> {
+        let mut file = tokio::fs::File::open(source).await?;
+        tokio::io::AsyncSeekExt::seek(&mut file, SeekFrom::Start(offset)).await?;
+        let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(tokio::io::AsyncReadExt::take(file, length)));
+        let request = self
+            .request(Method::PUT, key, query, sigv4::UNSIGNED_PAYLOAD)
+            .header(reqwest::header::CONTENT_LENGTH, length)
+            .body(body);
+        Self::send(request, "PUT").await
+    }
+
+    async fn put_multipart(&self, key: &str, source: &Path, length: u64) -> anyhow::Result<()> {
+        let created = self.request(Method::POST, key, &[("uploads".to_string(), String::new())], sigv4::EMPTY_PAYLOAD_SHA256);
+        let created = Self::send(created, "CreateMultipartUpload").await?.text().await?;
+        let upload_id = xml_value(&created, "UploadId").ok_or_else(|| anyhow::anyhow!("S3 did not return an upload id"))?;
+
+        let part_size = MULTIPART_THRESHOLD.max(length.div_ceil(MAX_PARTS));
+        let result = async {
+            let mut parts = String::new();
+            let mut offset = 0;
+            let mut number = 1;
+            while offset < length {
+                let size = part_size.min(length - offset);
+                let query = [("partNumber".to_string(), number.to_string()), ("uploadId".to_string(), upload_id.clone())];
+                let response = self.put_part(key, &query, source, offset, size).await?;
+                let etag = response
+                    .headers()
+                    .get(reqwest::header::ETAG)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| anyhow::anyhow!("S3 did not return an ETag for part {}", number))?;
+                parts.push_str(&format!("<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>", number, etag));
+                offset += size;
+                number += 1;
+            }
+
+            let body = format!("<CompleteMultipartUpload>{}</CompleteMultipartUpload>", parts);
+            let query = [("uploadId".to_string(), upload_id.clone())];
+            let request = self.request(Method::POST, key, &query, sigv4::UNSIGNED_PAYLOAD).body(body);
+            let completed = Self::send(request, "CompleteMultipartUpload").await?.text().await?;
+            if let Some(code) = xml_value(&completed, "Code") {
+                anyhow::bail!("S3 CompleteMultipartUpload failed with {}", code);
+            }
+            Ok(())
+        }
+        .await;
+
+        if result.is_err() {
+            let query = [("uploadId".to_string(), upload_id)];
+            let _ = self.request(Method::DELETE, key, &query, sigv4::EMPTY_PAYLOAD_SHA256).send().await;
+        }
+        result
+    }
+
+    pub fn upload(&self, path: &str, source: &Path) -> anyhow::Result<()> {
+        let key = self.key_of(path)?;
+        let length = std::fs::metadata(source)?.len();
+        self.block_on(async {
+            if length > MULTIPART_THRESHOLD {
+                self.put_multipart(key, source, length).await
+            } else {
+                self.put_part(key, &[], source, 0, length).await.map(|_| ())
+            }
+        })
+    }
+
+    pub fn size(&self, path: &str) -> anyhow::Result<Option<u64>> {
+        let key = self.key_of(path)?;
+        self.block_on(self.head_object(key))
+    }
+
+    pub fn read_range(&self, path: &str, offset: u64, length: u64) -> anyhow::Result<Vec<u8>> {
+        let key = self.key_of(path)?;
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+        self.block_on(self.get_range(key, offset, length))
+    }
+
+    pub fn delete(&self, path: &str) -> anyhow::Result<()> {
+        let key = self.key_of(path)?;
+        self.block_on(self.delete_object(key))
+    }
+
+    pub fn check(&self) -> anyhow::Result<()> {
+        let request = self.request(Method::HEAD, "", &[], sigv4::EMPTY_PAYLOAD_SHA256);
+        self.block_on(async { Self::send(request, "HEAD bucket").await.map(|_| ()) })
+    }
+}
+
+fn xml_value(body: &str, tag: &str) -> Option<String> {
+    let start = body.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + body[start..].find(&format!("</{}>", tag))?;
+    Some(body[start..end].to_string())
+}
+
+pub struct S3Reader {
+    store: Arc<S3Store>,
+    path: String,
+    length: u64,
+    position: u64,
+    block: Option<(u64, Vec<u8>)>,
+}
+
+impl S3Reader {
+    pub fn open(store: Arc<S3Store>, path: &str) -> io::Result<Self> {
+        let length = store
+            .size(path)
+            .map_err(io::Error::other)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} does not exist", path)))?;
+
+        Ok(S3Reader {
+            store,
+            path: path.to_string(),
+            length,
+            position: 0,
+            block: None,
+        })
+    }
+}
+
+impl Read for S3Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.length {
+            return Ok(0);
+        }
+
+        let start = self.position / READ_BLOCK_SIZE * READ_BLOCK_SIZE;
+        if !matches!(&self.block, Some((loaded, _)) if *loaded == start) {
+            let length = READ_BLOCK_SIZE.min(self.length - start);
+            let data = self.store.read_range(&self.path, start, length).map_err(io::Error::other)?;
+            self.block = Some((start, data));
+        }
+        let data = match &self.block {
+            Some((_, data)) => data,
+            None => return Ok(0),
+        };
+
+        let offset = (self.position - start) as usize;
+        let count = buf.len().min(data.len().saturating_sub(offset));
+        if count == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "S3 returned a short read"));
+        }
+        buf[..count].copy_from_slice(&data[offset..offset + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for S3Reader {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let target = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        self.position = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+        Ok(self.position)
+    }
+}
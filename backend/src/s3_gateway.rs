@@ -0,0 +1,915 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::sync::OnceLock;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use http_body_util::BodyExt;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use crate::remote_fetch::percent_decode;
+use crate::sigv4;
+use crate::webdav::valid_name;
+use crate::{database, AppState};
+
+pub const XML_CONTENT_TYPE: &str = "application/xml";
+pub const MAX_XML_BODY_SIZE: usize = 1024 * 1024;
+pub const MAX_KEYS: usize = 1000;
+pub const MAX_PART_NUMBER: i32 = 10000;
+pub const MTIME_METADATA: &str = "x-amz-meta-mtime";
+
+const XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+const XML_NAMESPACE: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+const MAX_CLOCK_SKEW_MINUTES: i64 = 15;
+const MAX_CHUNK_HEADER_SIZE: usize = 4096;
+const ACCESS_KEY_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+const ACCESS_KEY_LENGTH: usize = 20;
+
+#[derive(Debug)]
+pub struct S3Error {
+    pub status: StatusCode,
+    code: &'static str,
+    message: String,
+}
+
+impl S3Error {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self { status, code, message: message.into() }
+    }
+
+    pub fn access_denied(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "AccessDenied", message)
+    }
+
+    pub fn invalid_argument(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "InvalidArgument", message)
+    }
+
+    pub fn method_not_allowed() -> Self {
+        Self::new(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "The specified method is not allowed against this resource")
+    }
+
+    pub fn malformed_xml() -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "MalformedXML", "The XML you provided was not well-formed")
+    }
+
+    pub fn no_such_bucket() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchBucket", "The specified bucket does not exist")
+    }
+
+    pub fn no_such_key() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchKey", "The specified key does not exist")
+    }
+
+    pub fn no_such_upload() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NoSuchUpload", "The specified multipart upload does not exist")
+    }
+
+    pub fn not_implemented(operation: &str) -> Self {
+        Self::new(StatusCode::NOT_IMPLEMENTED, "NotImplemented", format!("{} is not supported by this gateway", operation))
+    }
+
+    pub fn internal() -> Self {
+        Self::from(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+impl From<StatusCode> for S3Error {
+    fn from(status: StatusCode) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST => "InvalidRequest",
+            StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => "AccessDenied",
+            StatusCode::NOT_FOUND => "NoSuchKey",
+            StatusCode::CONFLICT => "OperationAborted",
+            StatusCode::PAYLOAD_TOO_LARGE => "EntityTooLarge",
+            StatusCode::INSUFFICIENT_STORAGE => "QuotaExceeded",
+            StatusCode::TOO_MANY_REQUESTS => "SlowDown",
+            StatusCode::NOT_IMPLEMENTED => "NotImplemented",
+            StatusCode::SERVICE_UNAVAILABLE => "ServiceUnavailable",
+            _ => "InternalError",
+        };
+        let status = if status == StatusCode::UNAUTHORIZED { StatusCode::FORBIDDEN } else { status };
+        Self::new(status, code, status.canonical_reason().unwrap_or("Request failed"))
+    }
+}
+
+impl IntoResponse for S3Error {
+    fn into_response(self) -> Response {
+        let xml = format!(
+            "{}<Error><Code>{}</Code><Message>{}</Message></Error>",
+            XML_DECLARATION,
+            self.code,
+            escape_xml(&self.message)
+        );
+        (self.status, [(header::CONTENT_TYPE, XML_CONTENT_TYPE)], xml).into_response()
+    }
+}
+
+// What the payload of a request has to be checked against once its body is read.
+#[derive(Clone)]
+pub struct RequestSignature {
+    signing_key: Vec<u8>,
+    amz_date: String,
+    scope: String,
+    seed_signature: String,
+    pub content_sha256: String,
+}
+
+pub fn generate_access_key() -> (String, String) {
+    let mut bytes = [0u8; ACCESS_KEY_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+    let access_key_id = bytes
+        .iter()
+        .map(|byte| ACCESS_KEY_ALPHABET[*byte as usize % ACCESS_KEY_ALPHABET.len()] as char)
+        .collect();
+
+    let mut secret = [0u8; 30];
+    OsRng.fill_bytes(&mut secret);
+    (access_key_id, STANDARD.encode(secret))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// Splits a raw query string into decoded pairs; S3 clients encode spaces as %20, so "+"
+// is kept literally.
+pub fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => (percent_decode(key), percent_decode(value)),
+            None => (percent_decode(pair), String::new()),
+        })
+        .collect()
+}
+
+pub fn query_value<'a>(query: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    query.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+fn canonical_header_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<String> = headers
+        .get_all(name)
+        .iter()
+        .map(|value| value.to_str().map(|value| value.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .collect::<Result<_, _>>()
+        .ok()?;
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+fn verify_request(request: &Request, secret_key: &str, credential: &sigv4::Credential) -> Result<RequestSignature, S3Error> {
+    let headers = request.headers();
+    let amz_date = headers
+        .get("x-amz-date")
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| S3Error::access_denied("Requests must carry an X-Amz-Date header"))?;
+    let timestamp = NaiveDateTime::parse_from_str(amz_date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| S3Error::access_denied("X-Amz-Date is not a valid ISO 8601 basic timestamp"))?
+        .and_utc();
+    if (Utc::now() - timestamp).num_minutes().abs() > MAX_CLOCK_SKEW_MINUTES {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "RequestTimeTooSkewed",
+            "The difference between the request time and the server's time is too large",
+        ));
+    }
+    if !amz_date.starts_with(&credential.date) || credential.service != "s3" {
+        return Err(S3Error::new(StatusCode::FORBIDDEN, "AuthorizationHeaderMalformed", "The credential scope does not match the request"));
+    }
+
+    let content_sha256 = headers
+        .get("x-amz-content-sha256")
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| S3Error::new(StatusCode::BAD_REQUEST, "MissingSecurityHeader", "Requests must carry an X-Amz-Content-Sha256 header"))?
+        .to_string();
+
+    let mut signed = Vec::with_capacity(credential.signed_headers.len());
+    for name in &credential.signed_headers {
+        let value = canonical_header_value(headers, name)
+            .ok_or_else(|| S3Error::access_denied(format!("Signed header {} is missing from the request", name)))?;
+        signed.push((name.clone(), value));
+    }
+    if !signed.iter().any(|(name, _)| name == "host") {
+        return Err(S3Error::access_denied("The host header must be signed"));
+    }
+
+    let uri = request.uri();
+    let canonical_uri = sigv4::uri_encode(&percent_decode(uri.path()), false);
+    let canonical_query = sigv4::canonical_query_string(&parse_query(uri.query().unwrap_or_default()));
+    let (canonical_request, _) = sigv4::canonical_request(
+        request.method().as_str(),
+        &canonical_uri,
+        &canonical_query,
+        &signed,
+        &content_sha256,
+    );
+
+    let scope = credential.scope();
+    let signing_key = sigv4::signing_key(secret_key, &credential.date, &credential.region, &credential.service);
+    let expected = sigv4::sign_string(&signing_key, &sigv4::string_to_sign(amz_date, &scope, &canonical_request));
+    if !constant_time_eq(expected.as_bytes(), credential.signature.as_bytes()) {
+        return Err(S3Error::new(
+            StatusCode::FORBIDDEN,
+            "SignatureDoesNotMatch",
+            "The request signature we calculated does not match the signature you provided",
+        ));
+    }
+
+    Ok(RequestSignature {
+        signing_key,
+        amz_date: amz_date.to_string(),
+        scope,
+        seed_signature: expected,
+        content_sha256,
+    })
+}
+
+pub async fn auth_middleware(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, S3Error> {
+    if request.uri().query().is_some_and(|query| query.contains("X-Amz-Signature=")) {
+        return Err(S3Error::not_implemented("Presigned URL authentication"));
+    }
+
+    let credential = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .ok_or_else(|| S3Error::access_denied("Anonymous requests are not allowed"))?;
+    let credential = sigv4::parse_authorization(credential)
+        .ok_or_else(|| S3Error::new(StatusCode::BAD_REQUEST, "AuthorizationHeaderMalformed", "The authorization header is malformed"))?;
+
+    let invalid_key = || S3Error::new(StatusCode::FORBIDDEN, "InvalidAccessKeyId", "The access key ID you provided does not exist");
+    let key = database::get_s3_access_key(&state.db, &credential.access_key)
+        .await
+        .map_err(|_| S3Error::internal())?
+        .ok_or_else(invalid_key)?;
+    let signature = verify_request(&request, &key.secret_key, &credential)?;
+
+    let user = match database::get_user_by_id(&state.db, &key.user_id).await {
+        Ok(Some(user)) if user.deactivated_at.is_none() => user,
+        Ok(_) => return Err(invalid_key()),
+        Err(_) => return Err(S3Error::internal()),
+    };
+
+    if let Err(e) = database::touch_s3_access_key(&state.db, &key.access_key_id).await {
+        warn!("Failed to record use of S3 access key {}: {}", key.access_key_id, e);
+    }
+
+    request.extensions_mut().insert(user);
+    request.extensions_mut().insert(signature);
+    Ok(next.run(request).await)
+}
+
+enum ChunkState {
+    Header,
+    Data { remaining: usize, signature: Option<String>, hasher: Sha256 },
+    DataEnd,
+    Done,
+}
+
+// Decodes an aws-chunked body. Chunk signatures are verified when the request used
+// STREAMING-AWS4-HMAC-SHA256-PAYLOAD; trailing checksums of the unsigned variant are ignored.
+pub struct ChunkDecoder<'a> {
+    signature: Option<&'a RequestSignature>,
+    previous_signature: String,
+    buffer: Vec<u8>,
+    state: ChunkState,
+}
+
+impl<'a> ChunkDecoder<'a> {
+    fn new(signature: Option<&'a RequestSignature>) -> Self {
+        Self {
+            previous_signature: signature.map(|signature| signature.seed_signature.clone()).unwrap_or_default(),
+            signature,
+            buffer: Vec::new(),
+            state: ChunkState::Header,
+        }
+    }
+
+    fn incomplete() -> S3Error {
+        S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", "The request body is not a valid aws-chunked stream")
+    }
+
+    fn verify_chunk(&mut self, claimed: Option<String>, chunk_sha256: &str) -> Result<(), S3Error> {
+        let Some(signature) = self.signature else {
+            return Ok(());
+        };
+        let claimed = claimed.ok_or_else(Self::incomplete)?;
+        let expected = sigv4::sign_string(
+            &signature.signing_key,
+            &sigv4::chunk_string_to_sign(&signature.amz_date, &signature.scope, &self.previous_signature, chunk_sha256),
+        );
+        if !constant_time_eq(expected.as_bytes(), claimed.as_bytes()) {
+            return Err(S3Error::new(
+                StatusCode::FORBIDDEN,
+                "SignatureDoesNotMatch",
+                "The chunk signature we calculated does not match the signature you provided",
+            ));
+        }
+        self.previous_signature = expected;
+        Ok(())
+    }
+
+    fn feed(&mut self, input: &[u8], output: &mut Vec<u8>) -> Result<(), S3Error> {
+        self.buffer.extend_from_slice(input);
+        let mut offset = 0;
+
+        loop {
+            let pending = &self.buffer[offset..];
+            match &mut self.state {
+                ChunkState::Header => {
+                    let Some(end) = pending.windows(2).position(|window| window == b"\r\n") else {
+                        if pending.len() > MAX_CHUNK_HEADER_SIZE {
+                            return Err(Self::incomplete());
+                        }
+                        break;
+                    };
+                    let line = std::str::from_utf8(&pending[..end]).map_err(|_| Self::incomplete())?;
+                    let (size, extension) = line.split_once(';').unwrap_or((line, ""));
+                    let size = usize::from_str_radix(size.trim(), 16).map_err(|_| Self::incomplete())?;
+                    let signature = extension
+                        .trim()
+                        .strip_prefix("chunk-signature=")
+                        .map(|value| value.to_ascii_lowercase());
+                    offset += end + 2;
+
+                    if size == 0 {
+                        self.verify_chunk(signature, sigv4::EMPTY_PAYLOAD_SHA256)?;
+                        self.state = ChunkState::Done;
+                    } else {
+                        self.state = ChunkState::Data { remaining: size, signature, hasher: Sha256::new() };
+                    }
+                }
+                ChunkState::Data { remaining, signature, hasher } => {
+                    if pending.is_empty() {
+                        break;
+                    }
+                    let take = pending.len().min(*remaining);
+                    hasher.update(&pending[..take]);
+                    output.extend_from_slice(&pending[..take]);
+                    *remaining -= take;
+                    offset += take;
+
+                    if *remaining == 0 {
+                        let chunk_sha256 = hex::encode(std::mem::take(hasher).finalize());
+                        let signature = signature.take();
+                        self.verify_chunk(signature, &chunk_sha256)?;
+                        self.state = ChunkState::DataEnd;
+                    }
+                }
+                ChunkState::DataEnd => {
+                    if pending.len() < 2 {
+                        break;
+                    }
+                    if &pending[..2] != b"\r\n" {
+                        return Err(Self::incomplete());
+                    }
+                    offset += 2;
+                    self.state = ChunkState::Header;
+                }
+                ChunkState::Done => {
+                    offset = self.buffer.len();
+                    break;
+                }
+            }
+        }
+
+        self.buffer.drain(..offset);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), S3Error> {
+        match self.state {
+            ChunkState::Done => Ok(()),
+            _ => Err(Self::incomplete()),
+        }
+    }
+}
+
+// The size the client announced for the object, which for aws-chunked bodies is not the
+// Content-Length.
+pub fn declared_size(headers: &HeaderMap) -> u64 {
+    ["x-amz-decoded-content-length", header::CONTENT_LENGTH.as_str()]
+        .iter()
+        .find_map(|name| headers.get(*name).and_then(|value| value.to_str().ok()).and_then(|value| value.parse().ok()))
+        .unwrap_or(0)
+}
+
+// Streams the payload into `path` and returns its size and SHA-256, checking it against the
+// signed payload hash or chunk signatures.
+pub async fn write_body(path: &Path, body: Body, signature: &RequestSignature) -> Result<(i64, String), S3Error> {
+    let decoder = match signature.content_sha256.as_str() {
+        sigv4::STREAMING_PAYLOAD => Some(ChunkDecoder::new(Some(signature))),
+        sigv4::STREAMING_UNSIGNED_PAYLOAD => Some(ChunkDecoder::new(None)),
+        _ => None,
+    };
+    let chunked = decoder.is_some();
+
+    let (written, sha256) = write_stream(path, body, decoder).await?;
+    let fixed_hash = !chunked && signature.content_sha256 != sigv4::UNSIGNED_PAYLOAD;
+    if fixed_hash && !signature.content_sha256.eq_ignore_ascii_case(&sha256) {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The provided x-amz-content-sha256 header does not match what was computed",
+        ));
+    }
+
+    Ok((written, sha256))
+}
+
+// Streams a request body into an existing temp file, undoing aws-chunked framing when a
+// decoder is given, and returns the length and SHA-256 of what was written. WebDAV uploads
+// use it without a decoder.
+pub async fn write_stream(path: &Path, mut body: Body, mut decoder: Option<ChunkDecoder<'_>>) -> Result<(i64, String), S3Error> {
+    use tokio::io::AsyncWriteExt;
+
+    let mut file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .await
+        .map_err(|_| S3Error::internal())?;
+    let mut hasher = Sha256::new();
+    let mut written = 0i64;
+    let mut decoded = Vec::new();
+    while let Some(frame) = body.frame().await {
+        let frame = frame.map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "IncompleteBody", "The request body could not be read"))?;
+        let Ok(data) = frame.into_data() else {
+            continue;
+        };
+        let data = match decoder.as_mut() {
+            Some(decoder) => {
+                decoded.clear();
+                decoder.feed(&data, &mut decoded)?;
+                &decoded[..]
+            }
+            None => &data[..],
+        };
+        hasher.update(data);
+        file.write_all(data).await.map_err(|_| S3Error::from(StatusCode::INSUFFICIENT_STORAGE))?;
+        written += data.len() as i64;
+    }
+    if let Some(decoder) = &decoder {
+        decoder.finish()?;
+    }
+    file.set_len(written as u64).await.map_err(|_| S3Error::internal())?;
+    file.flush().await.map_err(|_| S3Error::internal())?;
+
+    Ok((written, hex::encode(hasher.finalize())))
+}
+
+// Reads a small XML request body into memory, checking it against the signed payload hash.
+pub async fn read_xml_body(body: Body, signature: &RequestSignature) -> Result<String, S3Error> {
+    let body = axum::body::to_bytes(body, MAX_XML_BODY_SIZE)
+        .await
+        .map_err(|_| S3Error::new(StatusCode::BAD_REQUEST, "MaxMessageLengthExceeded", "The request body is too large"))?;
+    let fixed_hash = ![sigv4::UNSIGNED_PAYLOAD, sigv4::STREAMING_PAYLOAD, sigv4::STREAMING_UNSIGNED_PAYLOAD]
+        .contains(&signature.content_sha256.as_str());
+    if fixed_hash && !signature.content_sha256.eq_ignore_ascii_case(&hex::encode(Sha256::digest(&body))) {
+        return Err(S3Error::new(
+            StatusCode::BAD_REQUEST,
+            "XAmzContentSHA256Mismatch",
+            "The provided x-amz-content-sha256 header does not match what was computed",
+        ));
+    }
+    String::from_utf8(body.to_vec()).map_err(|_| S3Error::malformed_xml())
+}
+
+// Maps an object key onto folder and file names. Keys ending in "/" are folder markers.
+pub fn key_components(key: &str) -> Option<(Vec<String>, bool)> {
+    let (key, is_dir) = match key.strip_suffix('/') {
+        Some(key) => (key, true),
+        None => (key, false),
+    };
+    let components: Vec<String> = key.split('/').map(str::to_string).collect();
+    components.iter().all(|name| valid_name(name)).then_some((components, is_dir))
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape_xml(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn timestamp(value: &DateTime<Utc>) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+pub fn list_buckets_xml(owner_id: &str, owner_name: &str, buckets: &[(String, DateTime<Utc>)]) -> String {
+    let mut xml = format!("{}<ListAllMyBucketsResult xmlns=\"{}\">", XML_DECLARATION, XML_NAMESPACE);
+    xml.push_str(&format!(
+        "<Owner><ID>{}</ID><DisplayName>{}</DisplayName></Owner><Buckets>",
+        escape_xml(owner_id),
+        escape_xml(owner_name)
+    ));
+    for (name, created) in buckets {
+        xml.push_str(&format!(
+            "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+            escape_xml(name),
+            timestamp(created)
+        ));
+    }
+    xml.push_str("</Buckets></ListAllMyBucketsResult>");
+    xml
+}
+
+pub fn location_xml() -> String {
+    format!("{}<LocationConstraint xmlns=\"{}\"/>", XML_DECLARATION, XML_NAMESPACE)
+}
+
+pub struct ObjectEntry {
+    pub key: String,
+    pub size: i64,
+    pub etag: String,
+    pub modified: DateTime<Utc>,
+}
+
+pub enum ListEntry {
+    Object(ObjectEntry),
+    // A folder that was not descended into; always rolled up under the "/" delimiter.
+    Prefix(String),
+}
+
+impl ListEntry {
+    fn key(&self) -> &str {
+        match self {
+            ListEntry::Object(object) => &object.key,
+            ListEntry::Prefix(prefix) => prefix,
+        }
+    }
+}
+
+pub struct ListParams {
+    pub v2: bool,
+    pub prefix: String,
+    pub delimiter: Option<String>,
+    pub max_keys: usize,
+    pub url_encode: bool,
+    pub marker: Option<String>,
+    pub continuation_token: Option<String>,
+    pub start_after: Option<String>,
+    pub fetch_owner: bool,
+}
+
+impl ListParams {
+    pub fn from_query(query: &[(String, String)]) -> Result<Self, S3Error> {
+        let max_keys = match query_value(query, "max-keys") {
+            Some(value) => value.parse::<usize>().map_err(|_| S3Error::invalid_argument("max-keys must be a non-negative integer"))?,
+            None => MAX_KEYS,
+        };
+        let url_encode = match query_value(query, "encoding-type") {
+            None => false,
+            Some("url") => true,
+            Some(_) => return Err(S3Error::invalid_argument("Invalid Encoding Method specified in Request")),
+        };
+
+        Ok(Self {
+            v2: query_value(query, "list-type") == Some("2"),
+            prefix: query_value(query, "prefix").unwrap_or_default().to_string(),
+            delimiter: query_value(query, "delimiter").filter(|value| !value.is_empty()).map(str::to_string),
+            max_keys: max_keys.min(MAX_KEYS),
+            url_encode,
+            marker: query_value(query, "marker").filter(|value| !value.is_empty()).map(str::to_string),
+            continuation_token: query_value(query, "continuation-token").map(str::to_string),
+            start_after: query_value(query, "start-after").filter(|value| !value.is_empty()).map(str::to_string),
+            fetch_owner: query_value(query, "fetch-owner") == Some("true"),
+        })
+    }
+
+    // Keys up to and including this one were returned by an earlier page.
+    fn after(&self) -> Result<Option<String>, S3Error> {
+        if !self.v2 {
+            return Ok(self.marker.clone());
+        }
+        match &self.continuation_token {
+            Some(token) => URL_SAFE_NO_PAD
+                .decode(token)
+                .ok()
+                .and_then(|key| String::from_utf8(key).ok())
+                .map(Some)
+                .ok_or_else(|| S3Error::invalid_argument("The continuation token provided is incorrect")),
+            None => Ok(self.start_after.clone()),
+        }
+    }
+
+    // The folder whose listing covers every key under the prefix; only that subtree is read.
+    pub fn prefix_folder(&self) -> Vec<String> {
+        let directory = self.prefix.rfind('/').map(|index| &self.prefix[..index]).unwrap_or_default();
+        directory.split('/').filter(|name| !name.is_empty()).map(str::to_string).collect()
+    }
+
+    // With the "/" delimiter, folders below the prefix folder never need to be walked.
+    pub fn recursive(&self) -> bool {
+        self.delimiter.as_deref() != Some("/")
+    }
+
+    fn encode(&self, value: &str) -> String {
+        if self.url_encode {
+            escape_xml(&sigv4::uri_encode(value, false))
+        } else {
+            escape_xml(value)
+        }
+    }
+}
+
+pub struct Listing {
+    pub contents: Vec<ObjectEntry>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    pub next_marker: Option<String>,
+}
+
+pub fn paginate(mut entries: Vec<ListEntry>, params: &ListParams) -> Result<Listing, S3Error> {
+    let after = params.after()?;
+    entries.sort_by(|a, b| a.key().cmp(b.key()));
+
+    let mut listing = Listing { contents: Vec::new(), common_prefixes: Vec::new(), is_truncated: false, next_marker: None };
+    let mut seen_prefixes = BTreeSet::new();
+    let mut last_key = None;
+    for entry in entries {
+        let key = entry.key();
+        if !key.starts_with(&params.prefix) {
+            continue;
+        }
+
+        let rolled_up = match (&entry, &params.delimiter) {
+            (ListEntry::Prefix(prefix), _) => Some(prefix.clone()),
+            (ListEntry::Object(_), Some(delimiter)) => key[params.prefix.len()..]
+                .find(delimiter.as_str())
+                .map(|index| key[..params.prefix.len() + index + delimiter.len()].to_string()),
+            (ListEntry::Object(_), None) => None,
+        };
+        let sort_key = rolled_up.clone().unwrap_or_else(|| key.to_string());
+        if after.as_deref().is_some_and(|after| sort_key.as_str() <= after) {
+            continue;
+        }
+        if rolled_up.as_ref().is_some_and(|prefix| seen_prefixes.contains(prefix)) {
+            continue;
+        }
+
+        if listing.contents.len() + listing.common_prefixes.len() >= params.max_keys {
+            listing.is_truncated = true;
+            listing.next_marker = last_key;
+            break;
+        }
+        last_key = Some(sort_key);
+        match (rolled_up, entry) {
+            (Some(prefix), _) => {
+                seen_prefixes.insert(prefix.clone());
+                listing.common_prefixes.push(prefix);
+            }
+            (None, ListEntry::Object(object)) => listing.contents.push(object),
+            (None, ListEntry::Prefix(_)) => {}
+        }
+    }
+
+    Ok(listing)
+}
+
+pub fn list_objects_xml(bucket: &str, owner: (&str, &str), params: &ListParams, listing: &Listing) -> String {
+    let mut xml = format!("{}<ListBucketResult xmlns=\"{}\">", XML_DECLARATION, XML_NAMESPACE);
+    xml.push_str(&format!("<Name>{}</Name>", escape_xml(bucket)));
+    xml.push_str(&format!("<Prefix>{}</Prefix>", params.encode(&params.prefix)));
+    if let Some(delimiter) = &params.delimiter {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", params.encode(delimiter)));
+    }
+    xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", params.max_keys));
+    if params.url_encode {
+        xml.push_str("<EncodingType>url</EncodingType>");
+    }
+    xml.push_str(&format!("<IsTruncated>{}</IsTruncated>", listing.is_truncated));
+
+    if params.v2 {
+        xml.push_str(&format!("<KeyCount>{}</KeyCount>", listing.contents.len() + listing.common_prefixes.len()));
+        if let Some(token) = &params.continuation_token {
+            xml.push_str(&format!("<ContinuationToken>{}</ContinuationToken>", escape_xml(token)));
+        }
+        if let Some(start_after) = &params.start_after {
+            xml.push_str(&format!("<StartAfter>{}</StartAfter>", params.encode(start_after)));
+        }
+        if let Some(next) = &listing.next_marker {
+            xml.push_str(&format!("<NextContinuationToken>{}</NextContinuationToken>", URL_SAFE_NO_PAD.encode(next)));
+        }
+    } else {
+        xml.push_str(&format!("<Marker>{}</Marker>", params.encode(params.marker.as_deref().unwrap_or_default())));
+        if let Some(next) = &listing.next_marker {
+            xml.push_str(&format!("<NextMarker>{}</NextMarker>", params.encode(next)));
+        }
+    }
+
+    for object in &listing.contents {
+        xml.push_str("<Contents>");
+        xml.push_str(&format!("<Key>{}</Key>", params.encode(&object.key)));
+        xml.push_str(&format!("<LastModified>{}</LastModified>", timestamp(&object.modified)));
+        xml.push_str(&format!("<ETag>&quot;{}&quot;</ETag>", escape_xml(&object.etag)));
+        xml.push_str(&format!("<Size>{}</Size>", object.size));
+        if !params.v2 || params.fetch_owner {
+            xml.push_str(&format!(
+                "<Owner><ID>{}</ID><DisplayName>{}</DisplayName></Owner>",
+                escape_xml(owner.0),
+                escape_xml(owner.1)
+            ));
+        }
+        xml.push_str("<StorageClass>STANDARD</StorageClass></Contents>");
+    }
+    for prefix in &listing.common_prefixes {
+        xml.push_str(&format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", params.encode(prefix)));
+    }
+
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+pub fn initiate_multipart_xml(bucket: &str, key: &str, upload_id: &str) -> String {
+    format!(
+        "{}<InitiateMultipartUploadResult xmlns=\"{}\"><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+        XML_DECLARATION,
+        XML_NAMESPACE,
+        escape_xml(bucket),
+        escape_xml(key),
+        escape_xml(upload_id)
+    )
+}
+
+pub fn complete_multipart_xml(location: &str, bucket: &str, key: &str, etag: &str) -> String {
+    format!(
+        "{}<CompleteMultipartUploadResult xmlns=\"{}\"><Location>{}</Location><Bucket>{}</Bucket><Key>{}</Key><ETag>&quot;{}&quot;</ETag></CompleteMultipartUploadResult>",
+        XML_DECLARATION,
+        XML_NAMESPACE,
+        escape_xml(location),
+        escape_xml(bucket),
+        escape_xml(key),
+        escape_xml(etag)
+    )
+}
+
+pub fn delete_result_xml(deleted: &[String], errors: &[(String, S3Error)]) -> String {
+    let mut xml = format!("{}<DeleteResult xmlns=\"{}\">", XML_DECLARATION, XML_NAMESPACE);
+    for key in deleted {
+        xml.push_str(&format!("<Deleted><Key>{}</Key></Deleted>", escape_xml(key)));
+    }
+    for (key, error) in errors {
+        xml.push_str(&format!(
+            "<Error><Key>{}</Key><Code>{}</Code><Message>{}</Message></Error>",
+            escape_xml(key),
+            error.code,
+            escape_xml(&error.message)
+        ));
+    }
+    xml.push_str("</DeleteResult>");
+    xml
+}
+
+fn element_regex(cell: &'static OnceLock<Regex>, pattern: &str) -> &'static Regex {
+    cell.get_or_init(|| Regex::new(pattern).expect("valid XML element regex"))
+}
+
+// Returns the (part number, ETag) list of a CompleteMultipartUpload body.
+pub fn parse_complete_multipart(xml: &str) -> Option<Vec<(i32, String)>> {
+    static PART: OnceLock<Regex> = OnceLock::new();
+    static NUMBER: OnceLock<Regex> = OnceLock::new();
+    static ETAG: OnceLock<Regex> = OnceLock::new();
+    let part = element_regex(&PART, r"(?s)<Part>(.*?)</Part>");
+    let number = element_regex(&NUMBER, r"<PartNumber>\s*(\d+)\s*</PartNumber>");
+    let etag = element_regex(&ETAG, r"(?s)<ETag>(.*?)</ETag>");
+
+    let parts: Option<Vec<(i32, String)>> = part
+        .captures_iter(xml)
+        .map(|part| {
+            let body = part.get(1)?.as_str();
+            let number = number.captures(body)?.get(1)?.as_str().parse().ok()?;
+            let etag = unescape_xml(etag.captures(body)?.get(1)?.as_str().trim());
+            Some((number, etag.trim_matches('"').to_string()))
+        })
+        .collect();
+    parts.filter(|parts| !parts.is_empty())
+}
+
+// Returns the keys of a DeleteObjects body and whether the client asked for quiet mode.
+pub fn parse_delete_objects(xml: &str) -> Option<(Vec<String>, bool)> {
+    static KEY: OnceLock<Regex> = OnceLock::new();
+    static QUIET: OnceLock<Regex> = OnceLock::new();
+    let key = element_regex(&KEY, r"(?s)<Object>.*?<Key>(.*?)</Key>.*?</Object>");
+    let quiet = element_regex(&QUIET, r"<Quiet>\s*true\s*</Quiet>");
+
+    if !xml.contains("<Delete") {
+        return None;
+    }
+    let keys = key
+        .captures_iter(xml)
+        .filter_map(|captures| captures.get(1).map(|key| unescape_xml(key.as_str())))
+        .collect();
+    Some((keys, quiet.is_match(xml)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Chunk signatures computed independently (outside this crate) from the chunked upload
+    // signing rules, using the example key and scope from the AWS documentation.
+    fn example_signature() -> RequestSignature {
+        RequestSignature {
+            signing_key: sigv4::signing_key("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20130524", "us-east-1", "s3"),
+            amz_date: "20130524T000000Z".to_string(),
+            scope: "20130524/us-east-1/s3/aws4_request".to_string(),
+            seed_signature: "4f232c4386841ef735655705268965c44a0e4690baa4adea153f7db9fa80a0a9".to_string(),
+            content_sha256: sigv4::STREAMING_PAYLOAD.to_string(),
+        }
+    }
+
+    fn example_body() -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(b"10000;chunk-signature=61ed74d76ad0a0a3cd61199c82a3112c3c90ec6bf34d8b2c625093a11e569c2b\r\n");
+        body.extend(std::iter::repeat_n(b'a', 65536));
+        body.extend_from_slice(b"\r\n400;chunk-signature=1bc5ec3a09ab65cbdd970c67f8744614b27f6f762e6f7b998405f4c8b577a685\r\n");
+        body.extend(std::iter::repeat_n(b'a', 1024));
+        body.extend_from_slice(b"\r\n0;chunk-signature=7cd0adc4c8559a39c487847ea89a4137b7653d47264e872e48d980f945fc3927\r\n\r\n");
+        body
+    }
+
+    fn decode(decoder: &mut ChunkDecoder, body: &[u8], piece: usize) -> Result<Vec<u8>, S3Error> {
+        let mut output = Vec::new();
+        for input in body.chunks(piece) {
+            decoder.feed(input, &mut output)?;
+        }
+        decoder.finish()?;
+        Ok(output)
+    }
+
+    #[test]
+    fn decodes_signed_chunks() {
+        let signature = example_signature();
+        for piece in [1, 7, 4096, usize::MAX] {
+            let output = decode(&mut ChunkDecoder::new(Some(&signature)), &example_body(), piece).unwrap();
+            assert_eq!(output.len(), 66560);
+            assert!(output.iter().all(|&byte| byte == b'a'));
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_chunk() {
+        let signature = example_signature();
+        let mut body = example_body();
+        let data_start = body.windows(2).position(|window| window == b"\r\n").unwrap() + 2;
+        body[data_start] = b'b';
+
+        let error = decode(&mut ChunkDecoder::new(Some(&signature)), &body, 4096).unwrap_err();
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+    }
+
+    #[test]
+    fn rejects_missing_chunk_signature() {
+        let signature = example_signature();
+        let error = decode(&mut ChunkDecoder::new(Some(&signature)), b"3\r\nabc\r\n0\r\n\r\n", 4096).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn decodes_unsigned_chunks() {
+        let body = b"3\r\nabc\r\n4;ignored=1\r\ndefg\r\n0\r\nx-amz-checksum-crc32:AAAAAA==\r\n\r\n";
+        let output = decode(&mut ChunkDecoder::new(None), body, 2).unwrap();
+        assert_eq!(output, b"abcdefg");
+    }
+
+    #[test]
+    fn rejects_truncated_stream() {
+        let error = decode(&mut ChunkDecoder::new(None), b"5\r\nabc", 4096).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+
+        let error = decode(&mut ChunkDecoder::new(None), b"3\r\nabcXX0\r\n\r\n", 4096).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn rejects_oversized_header() {
+        let body = vec![b'1'; MAX_CHUNK_HEADER_SIZE + 1];
+        let error = decode(&mut ChunkDecoder::new(None), &body, 4096).unwrap_err();
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+    }
+}
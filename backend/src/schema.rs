@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, BTreeSet};
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::database;
+
+// Every table and column the queries in database.rs rely on. `initialize_database` has to
+// create all of them; a column listed here that no migration creates is reported as drift
+// instead of failing later when a query first touches it.
+const EXPECTED: &[(&str, &[&str])] = &[
+    ("users", &[
+        "id", "username", "email", "password_hash", "is_admin", "created_at", "updated_at", "storage_used",
+        "quota_soft_bytes", "quota_hard_bytes", "quota_grace_started_at", "deactivated_at",
+        "egress_limit_bytes", "egress_unlimited", "trash_limit_bytes", "scim_external_id"
+    ]),
+    ("files", &[
+        "id", "user_id", "filename", "original_filename", "file_path", "disk_path", "file_size",
+        "mime_type", "is_deleted", "deleted_at", "created_at", "updated_at", "is_quarantined",
+        "last_accessed_at", "checksum", "folder_id", "client_modified_at", "keep_offline", "tags",
+        "custom_metadata", "stored_size", "storage_tier", "storage_encoding"
+    ]),
+    ("folders", &[
+        "id", "user_id", "parent_id", "name", "created_at", "updated_at", "color", "icon", "keep_offline"
+    ]),
+    ("external_mounts", &[
+        "id", "user_id", "name", "host_path", "read_only", "last_scanned_at", "created_at", "kind",
+        "smb_credentials", "sync_folder_id", "last_synced_at", "last_sync_error", "sync_started_at"
+    ]),
+    ("external_mount_entries", &["mount_id", "parent_path", "path", "name", "is_dir", "size", "modified_at"]),
+    ("notifications", &["id", "user_id", "kind", "message", "data", "read_at", "created_at"]),
+    ("broadcasts", &["id", "created_by", "subject", "body", "status", "created_at", "finished_at"]),
+    ("broadcast_recipients", &[
+        "broadcast_id", "user_id", "email", "status", "error", "claimed_at", "sent_at"
+    ]),
+    ("folder_permissions", &[
+        "folder_id", "user_id", "can_read", "can_write", "can_delete", "can_reshare", "granted_by",
+        "created_at", "updated_at"
+    ]),
+    ("groups", &["id", "name", "created_at", "updated_at"]),
+    ("user_groups", &["user_id", "group_id", "created_at"]),
+    ("file_contents", &[
+        "file_id", "checksum", "status", "content", "error", "search_vector", "indexed_at", "updated_at",
+        "stale"
+    ]),
+    ("archive_parts", &[
+        "id", "user_id", "sequence", "status", "file_count", "removed_count", "size_bytes", "archive_path",
+        "error", "created_at", "finished_at"
+    ]),
+    ("file_metadata", &[
+        "file_id", "checksum", "status", "duration_seconds", "width", "height", "video_codec",
+        "poster_path", "error", "created_at", "updated_at"
+    ]),
+    ("file_activity", &["id", "user_id", "file_id", "filename", "action", "occurred_at"]),
+    ("camera_upload_settings", &["user_id", "template", "updated_at"]),
+    ("file_downloads", &["id", "file_id", "user_id", "downloaded_at"]),
+    ("folder_share_defaults", &[
+        "folder_id", "expiry_days", "password_required", "max_downloads", "updated_at"
+    ]),
+    ("file_scrubs", &["file_id", "status", "expected_checksum", "actual_checksum", "error", "checked_at"]),
+    ("archive_manifest", &["user_id", "file_id", "part_id", "path", "checksum", "file_size", "archived_at"]),
+    ("remote_fetches", &[
+        "id", "user_id", "url", "folder_id", "filename", "status", "bytes_downloaded", "total_bytes",
+        "mime_type", "file_id", "error", "created_at", "updated_at", "finished_at"
+    ]),
+    ("galleries", &[
+        "id", "user_id", "folder_id", "token", "title", "description", "theme", "created_at", "updated_at"
+    ]),
+    ("aliases", &["id", "user_id", "folder_id", "name", "target_file_id", "target_folder_id", "created_at"]),
+    ("clipboards", &["user_id", "mode", "file_ids", "folder_ids", "created_at"]),
+    ("sessions", &[
+        "id", "user_id", "user_agent", "ip_address", "created_at", "last_used_at", "expires_at",
+        "revoked_at"
+    ]),
+    ("shared_links", &[
+        "id", "file_id", "token", "expires_at", "is_read_only", "created_at", "is_encrypted",
+        "encryption_metadata", "egress_limit_bytes", "egress_month", "egress_bytes", "password_hash",
+        "max_downloads", "download_count"
+    ]),
+    ("share_download_counts", &["share_id", "day", "downloads"]),
+    ("snippets", &[
+        "id", "user_id", "file_id", "share_id", "title", "language", "burn_after_reading", "created_at"
+    ]),
+    ("preview_handlers", &["mime_type", "strategy", "updated_at"]),
+    ("user_transfers", &["user_id", "day", "bytes_uploaded", "bytes_downloaded"]),
+    ("oidc_logins", &["state", "nonce", "code_verifier", "redirect_uri", "created_at"]),
+    ("user_identities", &["issuer", "subject", "user_id", "created_at"]),
+    ("webauthn_credentials", &[
+        "id", "user_id", "credential_id", "public_key", "sign_count", "name", "created_at", "last_used_at"
+    ]),
+    ("webauthn_challenges", &["id", "user_id", "purpose", "challenge", "created_at"]),
+    ("login_attempts", &["key", "failures", "last_failure_at", "locked_until"]),
+    ("share_torrents", &["share_id", "info_hash", "name", "web_seed_url", "torrent", "created_at"]),
+    ("api_usage", &[
+        "bucket", "user_id", "method", "route", "request_count", "client_error_count", "server_error_count",
+        "total_duration_ms"
+    ]),
+    ("chunked_uploads", &[
+        "id", "user_id", "filename", "total_size", "chunk_size", "total_chunks", "uploaded_chunks",
+        "temp_path", "disk_path", "is_completed", "created_at", "updated_at", "status",
+        "client_modified_at", "folder_id", "expires_at"
+    ]),
+    ("export_jobs", &[
+        "id", "user_id", "name", "destination", "schedule", "is_enabled", "next_run_at", "last_run_at",
+        "last_success_at", "last_error", "created_by", "created_at"
+    ]),
+    ("export_runs", &[
+        "id", "job_id", "status", "files_exported", "bytes_exported", "error", "started_at", "finished_at"
+    ]),
+    ("import_jobs", &[
+        "id", "user_id", "kind", "source_path", "target_folder", "status", "files_imported",
+        "folders_created", "bytes_imported", "error", "created_by", "created_at", "finished_at"
+    ]),
+    ("operations", &[
+        "id", "user_id", "kind", "status", "progress_current", "progress_total", "result", "error",
+        "cancel_requested", "created_at", "updated_at", "finished_at"
+    ]),
+    ("photo_metadata", &["file_id", "taken_at", "latitude", "longitude", "altitude", "description", "raw"]),
+    ("lifecycle_rules", &[
+        "id", "name", "idle_days", "min_size", "mime_prefix", "is_enabled", "last_run_at", "created_at"
+    ]),
+    ("storage_path_changes", &["path", "is_removed", "updated_at"]),
+    ("s3_access_keys", &[
+        "access_key_id", "user_id", "secret_key", "description", "created_at", "last_used_at"
+    ]),
+    ("s3_multipart_uploads", &["id", "user_id", "bucket", "object_key", "created_at", "expires_at"]),
+    ("s3_multipart_parts", &["upload_id", "part_number", "temp_path", "size", "etag", "created_at"]),
+    ("orphan_gc_runs", &[
+        "id", "action", "scanned_blobs", "orphaned_blobs", "orphaned_bytes", "reclaimed_bytes",
+        "failed_blobs", "created_at"
+    ]),
+    ("tags", &["id", "user_id", "name", "created_at"]),
+    ("file_tags", &["file_id", "tag_id", "created_at"]),
+    ("tenants", &["id", "name", "storage_paths", "storage_quota_bytes", "created_at", "updated_at"]),
+    ("starred_files", &["user_id", "file_id", "starred_at"]),
+    ("upload_chunks", &["upload_id", "chunk_number", "chunk_size", "received_at"]),
+];
+
+// Postgres "insufficient_privilege", returned when the role may not CREATE SCHEMA.
+const INSUFFICIENT_PRIVILEGE: &str = "42501";
+
+#[derive(Debug, Default)]
+struct Snapshot {
+    columns: BTreeMap<(String, String), String>,
+    indexes: BTreeSet<String>,
+}
+
+impl Snapshot {
+    fn tables(&self) -> BTreeSet<&str> {
+        self.columns.keys().map(|(table, _)| table.as_str()).collect()
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> bool {
+        self.columns.contains_key(&(table.to_string(), column.to_string()))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct SchemaDiff {
+    pub missing_tables: Vec<String>,
+    pub missing_columns: Vec<String>,
+    pub mismatched_columns: Vec<String>,
+    pub pending: Vec<String>,
+    pub skipped: Option<String>,
+}
+
+impl SchemaDiff {
+    pub fn is_breaking(&self) -> bool {
+        !self.missing_tables.is_empty() || !self.missing_columns.is_empty() || !self.mismatched_columns.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.is_breaking() && self.pending.is_empty()
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        lines.extend(self.missing_tables.iter().map(|table| format!("missing table {}, and no migration creates it", table)));
+        lines.extend(self.missing_columns.iter().map(|column| format!("missing column {}, and no migration creates it", column)));
+        lines.extend(self.mismatched_columns.iter().map(|column| format!("column type differs: {}", column)));
+        lines
+    }
+}
+
+async fn snapshot(pool: &PgPool, schema: &str) -> anyhow::Result<Snapshot> {
+    let columns = database::get_schema_columns(pool, schema)
+        .await?
+        .into_iter()
+        .map(|(table, column, data_type)| ((table, column), data_type))
+        .collect();
+    let indexes = database::get_schema_indexes(pool, schema).await?.into_iter().collect();
+
+    Ok(Snapshot { columns, indexes })
+}
+
+// Builds the schema `initialize_database` produces in a throwaway Postgres schema.
+async fn migrated(pool: &PgPool, database_url: &str) -> anyhow::Result<Snapshot> {
+    let scratch = format!("ld_schema_check_{}", Uuid::new_v4().simple());
+    database::create_schema(pool, &scratch).await?;
+
+    let result = async {
+        let scratch_pool = database::create_scratch_pool(database_url, &scratch).await?;
+        let result = async {
+            database::initialize_database(&scratch_pool).await?;
+            snapshot(&scratch_pool, &scratch).await
+        }
+        .await;
+        scratch_pool.close().await;
+        result
+    }
+    .await;
+
+    database::drop_schema(pool, &scratch).await?;
+    result
+}
+
+fn is_insufficient_privilege(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        matches!(
+            cause.downcast_ref::<sqlx::Error>(),
+            Some(sqlx::Error::Database(db)) if db.code().as_deref() == Some(INSUFFICIENT_PRIVILEGE)
+        )
+    })
+}
+
+/// Declared columns the live database does not have, as `table.column`.
+pub async fn missing_columns(pool: &PgPool) -> anyhow::Result<Vec<String>> {
+    let live = snapshot(pool, &database::get_current_schema(pool).await?).await?;
+    Ok(EXPECTED
+        .iter()
+        .flat_map(|(table, columns)| columns.iter().map(move |column| (*table, *column)))
+        .filter(|(table, column)| !live.has_column(table, column))
+        .map(|(table, column)| format!("{}.{}", table, column))
+        .collect())
+}
+
+/// Compares the live schema, before migrations run, with the declared one.
+/// Anything the migrations will still add is `pending`; drift is what they
+/// cannot fix. Without the CREATE privilege the migrated schema cannot be
+/// built, so everything missing is reported as pending and `skipped` says why.
+pub async fn diff(pool: &PgPool, database_url: &str) -> anyhow::Result<SchemaDiff> {
+    let live = snapshot(pool, &database::get_current_schema(pool).await?).await?;
+    let live_tables = live.tables();
+
+    let mut diff = SchemaDiff::default();
+    let migrated = match migrated(pool, database_url).await {
+        Ok(migrated) => Some(migrated),
+        Err(e) if is_insufficient_privilege(&e) => {
+            diff.skipped = Some("the database role may not create schemas, so column types and migrations were not checked".to_string());
+            None
+        }
+        Err(e) => return Err(e),
+    };
+    let created_by_migrations = |table: &str, column: &str| migrated.as_ref().is_none_or(|m| m.has_column(table, column));
+
+    for (table, columns) in EXPECTED {
+        if !live_tables.contains(table) {
+            match columns.iter().all(|column| created_by_migrations(table, column)) {
+                true => diff.pending.push(format!("table {}", table)),
+                false => diff.missing_tables.push(table.to_string()),
+            }
+            continue;
+        }
+        for column in columns.iter().filter(|column| !live.has_column(table, column)) {
+            match created_by_migrations(table, column) {
+                true => diff.pending.push(format!("column {}.{}", table, column)),
+                false => diff.missing_columns.push(format!("{}.{}", table, column)),
+            }
+        }
+    }
+
+    if let Some(migrated) = &migrated {
+        for ((table, column), data_type) in &migrated.columns {
+            match live.columns.get(&(table.clone(), column.clone())) {
+                Some(actual) if actual != data_type => {
+                    diff.mismatched_columns.push(format!("{}.{} is {}, expected {}", table, column, actual, data_type))
+                }
+                _ => {}
+            }
+        }
+        diff.pending.extend(migrated.indexes.difference(&live.indexes).map(|index| format!("index {}", index)));
+    }
+
+    Ok(diff)
+}
@@ -0,0 +1,535 @@
+use std::sync::OnceLock;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::error;
+use uuid::Uuid;
+use crate::models::{DirectoryUser, Group, GroupMembership};
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const CONTENT_TYPE: &str = "application/scim+json";
+const MAX_PAGE_SIZE: i64 = 200;
+
+pub struct ScimJson<T>(pub StatusCode, pub T);
+
+impl<T: Serialize> IntoResponse for ScimJson<T> {
+    fn into_response(self) -> Response {
+        let mut response = (self.0, Json(self.1)).into_response();
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
+        response
+    }
+}
+
+#[derive(Debug)]
+pub struct ScimError {
+    status: StatusCode,
+    scim_type: Option<&'static str>,
+    detail: String,
+}
+
+impl ScimError {
+    pub fn invalid_value(detail: impl Into<String>) -> Self {
+        Self { status: StatusCode::BAD_REQUEST, scim_type: Some("invalidValue"), detail: detail.into() }
+    }
+
+    pub fn invalid_filter(detail: impl Into<String>) -> Self {
+        Self { status: StatusCode::BAD_REQUEST, scim_type: Some("invalidFilter"), detail: detail.into() }
+    }
+
+    pub fn uniqueness(detail: impl Into<String>) -> Self {
+        Self { status: StatusCode::CONFLICT, scim_type: Some("uniqueness"), detail: detail.into() }
+    }
+
+    pub fn forbidden(detail: impl Into<String>) -> Self {
+        Self { status: StatusCode::FORBIDDEN, scim_type: None, detail: detail.into() }
+    }
+
+    pub fn not_found(resource: &str, id: &Uuid) -> Self {
+        Self { status: StatusCode::NOT_FOUND, scim_type: None, detail: format!("{} {} not found", resource, id) }
+    }
+}
+
+impl From<anyhow::Error> for ScimError {
+    fn from(e: anyhow::Error) -> Self {
+        error!("SCIM request failed: {:#}", e);
+        Self { status: StatusCode::INTERNAL_SERVER_ERROR, scim_type: None, detail: "internal server error".to_string() }
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        let mut body = json!({
+            "schemas": [ERROR_SCHEMA],
+            "status": self.status.as_u16().to_string(),
+            "detail": self.detail,
+        });
+        if let Some(scim_type) = self.scim_type {
+            body["scimType"] = json!(scim_type);
+        }
+        ScimJson(self.status, body).into_response()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    pub filter: Option<String>,
+    pub start_index: Option<i64>,
+    pub count: Option<i64>,
+}
+
+impl ListQuery {
+    pub fn offset(&self) -> i64 {
+        self.start_index.unwrap_or(1).max(1) - 1
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.count.unwrap_or(100).clamp(0, MAX_PAGE_SIZE)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListResponse<T> {
+    schemas: [&'static str; 1],
+    total_results: i64,
+    start_index: i64,
+    items_per_page: usize,
+    #[serde(rename = "Resources")]
+    resources: Vec<T>,
+}
+
+pub fn list<T>(total: i64, offset: i64, resources: Vec<T>) -> ListResponse<T> {
+    ListResponse {
+        schemas: [LIST_SCHEMA],
+        total_results: total,
+        start_index: offset + 1,
+        items_per_page: resources.len(),
+        resources,
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Meta {
+    resource_type: &'static str,
+    created: DateTime<Utc>,
+    last_modified: DateTime<Utc>,
+    location: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Email {
+    pub value: String,
+    #[serde(rename = "type", default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub primary: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reference {
+    value: Uuid,
+    display: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserResource {
+    schemas: [&'static str; 1],
+    id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    external_id: Option<String>,
+    user_name: String,
+    active: bool,
+    emails: Vec<Email>,
+    groups: Vec<Reference>,
+    meta: Meta,
+}
+
+pub fn user_resource(user: DirectoryUser, memberships: &[GroupMembership]) -> UserResource {
+    UserResource {
+        schemas: [USER_SCHEMA],
+        id: user.id,
+        external_id: user.scim_external_id,
+        user_name: user.username,
+        active: user.deactivated_at.is_none(),
+        emails: vec![Email { value: user.email, kind: Some("work".to_string()), primary: true }],
+        groups: memberships
+            .iter()
+            .filter(|membership| membership.user_id == user.id)
+            .map(|membership| Reference { value: membership.group_id, display: membership.group_name.clone() })
+            .collect(),
+        meta: Meta {
+            resource_type: "User",
+            created: user.created_at,
+            last_modified: user.updated_at,
+            location: format!("/scim/v2/Users/{}", user.id),
+        },
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResource {
+    schemas: [&'static str; 1],
+    id: Uuid,
+    display_name: String,
+    members: Vec<Reference>,
+    meta: Meta,
+}
+
+pub fn group_resource(group: Group, memberships: &[GroupMembership]) -> GroupResource {
+    GroupResource {
+        schemas: [GROUP_SCHEMA],
+        id: group.id,
+        display_name: group.name,
+        members: memberships
+            .iter()
+            .filter(|membership| membership.group_id == group.id)
+            .map(|membership| Reference { value: membership.user_id, display: membership.username.clone() })
+            .collect(),
+        meta: Meta {
+            resource_type: "Group",
+            created: group.created_at,
+            last_modified: group.updated_at,
+            location: format!("/scim/v2/Groups/{}", group.id),
+        },
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRequest {
+    pub user_name: String,
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub emails: Vec<Email>,
+    pub active: Option<Value>,
+    pub password: Option<String>,
+}
+
+impl UserRequest {
+    pub fn email(&self) -> Option<&str> {
+        self.emails
+            .iter()
+            .find(|email| email.primary)
+            .or_else(|| self.emails.first())
+            .map(|email| email.value.as_str())
+            .or_else(|| Some(self.user_name.as_str()).filter(|name| name.contains('@')))
+    }
+
+    pub fn active(&self) -> Result<bool, ScimError> {
+        match &self.active {
+            Some(value) => bool_value(value),
+            None => Ok(true),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MemberRequest {
+    pub value: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupRequest {
+    pub display_name: String,
+    #[serde(default)]
+    pub members: Vec<MemberRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PatchRequest {
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Add,
+    Replace,
+    Remove,
+}
+
+fn parse_op(op: &str) -> Result<Op, ScimError> {
+    match op.to_ascii_lowercase().as_str() {
+        "add" => Ok(Op::Add),
+        "replace" => Ok(Op::Replace),
+        "remove" => Ok(Op::Remove),
+        _ => Err(ScimError::invalid_value(format!("unsupported patch op \"{}\"", op))),
+    }
+}
+
+fn bool_value(value: &Value) -> Result<bool, ScimError> {
+    match value {
+        Value::Bool(value) => Ok(*value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::invalid_value("expected a boolean")),
+    }
+}
+
+fn string_value(value: Option<&Value>) -> Result<String, ScimError> {
+    value
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| ScimError::invalid_value("expected a string"))
+}
+
+fn email_value(value: Option<&Value>) -> Result<String, ScimError> {
+    let value = match value {
+        Some(Value::Array(emails)) => emails
+            .iter()
+            .find(|email| email.get("primary").and_then(Value::as_bool) == Some(true))
+            .or_else(|| emails.first()),
+        other => other,
+    };
+
+    match value {
+        Some(Value::Object(email)) => string_value(email.get("value")),
+        other => string_value(other),
+    }
+}
+
+fn attribute_name(path: &str) -> String {
+    let path = path
+        .strip_prefix(USER_SCHEMA)
+        .or_else(|| path.strip_prefix(GROUP_SCHEMA))
+        .map(|rest| rest.trim_start_matches(':'))
+        .unwrap_or(path);
+    path.split(['[', '.']).next().unwrap_or_default().to_ascii_lowercase()
+}
+
+#[derive(Debug, Default)]
+pub struct UserPatch {
+    pub user_name: Option<String>,
+    pub email: Option<String>,
+    pub external_id: Option<Option<String>>,
+    pub active: Option<bool>,
+}
+
+fn patch_user_attribute(patch: &mut UserPatch, op: Op, path: &str, value: Option<&Value>) -> Result<(), ScimError> {
+    match attribute_name(path).as_str() {
+        "externalid" if op == Op::Remove => patch.external_id = Some(None),
+        "externalid" => patch.external_id = Some(Some(string_value(value)?)),
+        "active" | "username" | "emails" if op == Op::Remove => {
+            return Err(ScimError::invalid_value(format!("{} cannot be removed", path)));
+        }
+        "active" => patch.active = Some(bool_value(value.unwrap_or(&Value::Null))?),
+        "username" => patch.user_name = Some(string_value(value)?),
+        "emails" => patch.email = Some(email_value(value)?),
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn user_patch(request: &PatchRequest) -> Result<UserPatch, ScimError> {
+    let mut patch = UserPatch::default();
+
+    for operation in &request.operations {
+        let op = parse_op(&operation.op)?;
+        match &operation.path {
+            Some(path) => patch_user_attribute(&mut patch, op, path, operation.value.as_ref())?,
+            None => {
+                let attributes = operation
+                    .value
+                    .as_ref()
+                    .and_then(Value::as_object)
+                    .ok_or_else(|| ScimError::invalid_value("a patch without a path needs an object value"))?;
+                for (path, value) in attributes {
+                    patch_user_attribute(&mut patch, op, path, Some(value))?;
+                }
+            }
+        }
+    }
+
+    Ok(patch)
+}
+
+#[derive(Debug, Default)]
+pub struct GroupPatch {
+    pub display_name: Option<String>,
+    pub replace_members: bool,
+    pub add: Vec<Uuid>,
+    pub remove: Vec<Uuid>,
+}
+
+fn member_ids(value: Option<&Value>) -> Result<Vec<Uuid>, ScimError> {
+    let members = match value {
+        Some(Value::Array(members)) => members.iter().collect(),
+        Some(member) => vec![member],
+        None => Vec::new(),
+    };
+
+    members
+        .into_iter()
+        .map(|member| {
+            member
+                .get("value")
+                .and_then(Value::as_str)
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .ok_or_else(|| ScimError::invalid_value("members must reference user ids"))
+        })
+        .collect()
+}
+
+fn member_filter(path: &str) -> Option<Uuid> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(r#"(?i)^members\[\s*value\s+eq\s+"([^"]+)"\s*\]$"#).expect("valid member filter pattern")
+    });
+    pattern
+        .captures(path.trim())
+        .and_then(|captures| Uuid::parse_str(&captures[1]).ok())
+}
+
+fn patch_group_attribute(patch: &mut GroupPatch, op: Op, path: &str, value: Option<&Value>) -> Result<(), ScimError> {
+    if let Some(member) = member_filter(path) {
+        if op != Op::Remove {
+            return Err(ScimError::invalid_value("only remove is supported on a filtered members path"));
+        }
+        patch.add.retain(|id| *id != member);
+        patch.remove.push(member);
+        return Ok(());
+    }
+
+    match (attribute_name(path).as_str(), op) {
+        ("displayname", Op::Remove) => return Err(ScimError::invalid_value("displayName cannot be removed")),
+        ("displayname", _) => patch.display_name = Some(string_value(value)?),
+        ("members", Op::Add) => {
+            let ids = member_ids(value)?;
+            patch.remove.retain(|id| !ids.contains(id));
+            patch.add.extend(ids);
+        }
+        ("members", Op::Replace) => {
+            patch.replace_members = true;
+            patch.remove.clear();
+            patch.add = member_ids(value)?;
+        }
+        ("members", Op::Remove) => match value {
+            Some(_) => {
+                let ids = member_ids(value)?;
+                patch.add.retain(|id| !ids.contains(id));
+                patch.remove.extend(ids);
+            }
+            None => {
+                patch.replace_members = true;
+                patch.remove.clear();
+                patch.add.clear();
+            }
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+pub fn group_patch(request: &PatchRequest) -> Result<GroupPatch, ScimError> {
+    let mut patch = GroupPatch::default();
+
+    for operation in &request.operations {
+        let op = parse_op(&operation.op)?;
+        match &operation.path {
+            Some(path) => patch_group_attribute(&mut patch, op, path, operation.value.as_ref())?,
+            None => {
+                let attributes = operation
+                    .value
+                    .as_ref()
+                    .and_then(Value::as_object)
+                    .ok_or_else(|| ScimError::invalid_value("a patch without a path needs an object value"))?;
+                for (path, value) in attributes {
+                    patch_group_attribute(&mut patch, op, path, Some(value))?;
+                }
+            }
+        }
+    }
+
+    Ok(patch)
+}
+
+pub fn parse_member_ids(members: &[MemberRequest]) -> Result<Vec<Uuid>, ScimError> {
+    members
+        .iter()
+        .map(|member| Uuid::parse_str(&member.value).map_err(|_| ScimError::invalid_value("members must reference user ids")))
+        .collect()
+}
+
+pub fn parse_filter(filter: &str) -> Result<(String, String), ScimError> {
+    static PATTERN: OnceLock<Regex> = OnceLock::new();
+    let pattern = PATTERN.get_or_init(|| {
+        Regex::new(r#"(?i)^\s*([a-z0-9_.:]+)\s+eq\s+"((?:[^"\\]|\\.)*)"\s*$"#).expect("valid filter pattern")
+    });
+
+    let captures = pattern
+        .captures(filter)
+        .ok_or_else(|| ScimError::invalid_filter("only `attribute eq \"value\"` filters are supported"))?;
+    let value = captures[2].replace("\\\"", "\"").replace("\\\\", "\\");
+    Ok((attribute_name(&captures[1]), value))
+}
+
+pub fn user_filter_column(attribute: &str) -> Result<&'static str, ScimError> {
+    match attribute {
+        "username" => Ok("username"),
+        "emails" => Ok("email"),
+        "externalid" => Ok("scim_external_id"),
+        "id" => Ok("id::text"),
+        _ => Err(ScimError::invalid_filter(format!("cannot filter users by {}", attribute))),
+    }
+}
+
+pub fn service_provider_config() -> Value {
+    json!({
+        "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig"],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": MAX_PAGE_SIZE },
+        "changePassword": { "supported": true },
+        "sort": { "supported": false },
+        "etag": { "supported": false },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Bearer token",
+            "description": "The server's PROVISIONING_TOKEN",
+            "primary": true,
+        }],
+    })
+}
+
+pub fn resource_types() -> Value {
+    let resources = [("User", "/Users", USER_SCHEMA), ("Group", "/Groups", GROUP_SCHEMA)]
+        .iter()
+        .map(|(name, endpoint, schema)| {
+            json!({
+                "schemas": ["urn:ietf:params:scim:schemas:core:2.0:ResourceType"],
+                "id": name,
+                "name": name,
+                "endpoint": endpoint,
+                "schema": schema,
+                "meta": { "resourceType": "ResourceType", "location": format!("/scim/v2/ResourceTypes/{}", name) },
+            })
+        })
+        .collect::<Vec<_>>();
+
+    json!({
+        "schemas": [LIST_SCHEMA],
+        "totalResults": resources.len(),
+        "startIndex": 1,
+        "itemsPerPage": resources.len(),
+        "Resources": resources,
+    })
+}
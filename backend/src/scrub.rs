@@ -0,0 +1,197 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::models::FileInfo;
+use crate::operations::Progress;
+use crate::{alerts, database, AppState};
+
+const BATCH_SIZE: i64 = 50;
+const RECHECK_AFTER_DAYS: i32 = 6;
+const PAUSE_BETWEEN_FILES: Duration = Duration::from_millis(20);
+const MAX_LISTED_FINDINGS: usize = 20;
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+
+struct Finding {
+    file_id: Uuid,
+    user_id: Uuid,
+    filename: String,
+    path: String,
+    status: &'static str,
+    detail: String,
+}
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+async fn check(state: &AppState, file: &FileInfo, expected: &str) -> anyhow::Result<Option<Finding>> {
+    let (file_path, encoding) = (PathBuf::from(&file.file_path), file.encoding());
+    let file_storage = state.file_storage.clone();
+    let stored_path = file.file_path.clone();
+    let (status, actual, error) = if !tokio::task::spawn_blocking(move || file_storage.file_exists(&stored_path)).await? {
+        ("missing", None, Some("file is missing from storage".to_string()))
+    } else {
+        let file_storage = state.file_storage.clone();
+        match tokio::task::spawn_blocking(move || file_storage.compute_sha256(&file_path, encoding)).await? {
+            Ok(actual) if actual.eq_ignore_ascii_case(expected) => ("ok", Some(actual), None),
+            Ok(actual) => ("mismatch", Some(actual), None),
+            Err(e) => ("error", None, Some(format!("{:#}", e))),
+        }
+    };
+
+    if status != "ok" {
+        let current = database::get_file_by_id(&state.db, &file.id).await?;
+        let unchanged = current.is_some_and(|current| {
+            !current.is_deleted
+                && current.file_path == file.file_path
+                && current.checksum.as_deref() == Some(expected)
+        });
+        if !unchanged {
+            return Ok(None);
+        }
+    }
+
+    database::record_file_scrub(&state.db, &file.id, status, expected, actual.as_deref(), error.as_deref()).await?;
+
+    if status == "ok" {
+        let file_storage = state.file_storage.clone();
+        let (stored_path, user_id) = (file.file_path.clone(), file.user_id);
+        match tokio::task::spawn_blocking(move || file_storage.replicate(&stored_path, &user_id)).await? {
+            Ok(0) => {}
+            Ok(restored) => info!("Integrity scrub restored {} missing copies of file {}", restored, file.id),
+            Err(e) => warn!("Failed to restore the replicas of file {}: {:#}", file.id, e),
+        }
+        return Ok(None);
+    }
+    let detail = match (&actual, &error) {
+        (Some(actual), _) => format!("expected sha256 {}, found {}", expected, actual),
+        (_, Some(error)) => error.clone(),
+        _ => String::new(),
+    };
+    warn!("Integrity scrub found {} file {} at {}: {}", status, file.id, file.file_path, detail);
+    Ok(Some(Finding {
+        file_id: file.id,
+        user_id: file.user_id,
+        filename: file.original_filename.clone(),
+        path: file.file_path.clone(),
+        status,
+        detail,
+    }))
+}
+
+async fn report(state: &AppState, findings: &[Finding]) {
+    let mut lines = Vec::new();
+    let mut owners = HashMap::new();
+    for finding in findings {
+        if let Entry::Vacant(entry) = owners.entry(finding.user_id) {
+            let username = match database::get_user_by_id(&state.db, &finding.user_id).await {
+                Ok(Some(user)) => user.username,
+                _ => finding.user_id.to_string(),
+            };
+            entry.insert(username);
+        }
+        let owner = &owners[&finding.user_id];
+        lines.push(format!(
+            "- {} ({}): {} owned by {} at {}: {}",
+            finding.status, finding.file_id, finding.filename, owner, finding.path, finding.detail
+        ));
+    }
+
+    let mut message = format!("The integrity scrub found {} damaged or unreadable files:\n", findings.len());
+    message.push_str(&lines[..lines.len().min(MAX_LISTED_FINDINGS)].join("\n"));
+    if lines.len() > MAX_LISTED_FINDINGS {
+        message.push_str(&format!("\n...and {} more; see GET /admin/scrub", lines.len() - MAX_LISTED_FINDINGS));
+    }
+
+    let data = serde_json::json!({
+        "alert": "integrity_scrub",
+        "status": "firing",
+        "findings": findings.iter().map(|finding| serde_json::json!({
+            "file_id": finding.file_id,
+            "user_id": finding.user_id,
+            "owner": owners.get(&finding.user_id),
+            "filename": finding.filename,
+            "path": finding.path,
+            "status": finding.status,
+        })).collect::<Vec<_>>(),
+    });
+    let subject = format!("[Local Drive] Integrity scrub found {} damaged files", findings.len());
+    alerts::notify_admins(state, &subject, &message, &data).await;
+}
+
+fn throttle(state: &AppState, file_size: i64, elapsed: Duration) -> Duration {
+    let rate = state.config.scrub_bytes_per_second;
+    if rate == 0 {
+        return PAUSE_BETWEEN_FILES;
+    }
+    let budget = Duration::from_secs_f64(file_size.max(0) as f64 / rate as f64);
+    budget.saturating_sub(elapsed).max(PAUSE_BETWEEN_FILES)
+}
+
+async fn scrub(state: &AppState, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    let mut checked = 0i64;
+    let mut bytes = 0i64;
+    let mut findings = Vec::new();
+    let mut seen = Vec::new();
+
+    'batches: loop {
+        let files = database::get_files_to_scrub(&state.db, RECHECK_AFTER_DAYS, &seen, BATCH_SIZE).await?;
+        if files.is_empty() {
+            break;
+        }
+
+        for file in &files {
+            seen.push(file.id);
+            if checked > 0 && bytes + file.file_size > state.config.scrub_max_bytes {
+                break 'batches;
+            }
+            let expected = match &file.checksum {
+                Some(checksum) => checksum,
+                None => continue,
+            };
+
+            let started = Instant::now();
+            match check(state, file, expected).await {
+                Ok(Some(finding)) => findings.push(finding),
+                Ok(None) => {}
+                Err(e) => error!("Failed to scrub file {}: {:#}", file.id, e),
+            }
+
+            checked += 1;
+            bytes += file.file_size;
+            if let Some(progress) = progress {
+                progress.update(checked, None).await?;
+            }
+            tokio::time::sleep(throttle(state, file.file_size, started.elapsed())).await;
+        }
+
+        if (files.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    info!("Integrity scrub checked {} files ({} bytes), {} findings", checked, bytes, findings.len());
+    if !findings.is_empty() {
+        report(state, &findings).await;
+    }
+
+    Ok(serde_json::json!({
+        "checked": checked,
+        "bytes": bytes,
+        "findings": findings.len(),
+    }))
+}
+
+pub async fn run(state: &AppState, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("an integrity scrub is already running");
+    }
+    let result = scrub(state, progress).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
@@ -0,0 +1,409 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use crate::AppState;
+
+const RISKY_MIME_TYPES: &[&str] = &[
+    "image/svg+xml",
+    "text/html",
+    "application/xhtml+xml",
+    "text/xml",
+    "application/xml",
+    "text/javascript",
+    "application/javascript",
+];
+
+const RISKY_EXTENSIONS: &[&str] = &["svg", "svgz", "html", "htm", "xhtml", "xht", "xml", "js", "mjs"];
+
+pub const SANDBOXED_CONTENT_TYPE: &str = "application/octet-stream";
+pub const SVG_CONTENT_TYPE: &str = "image/svg+xml";
+// Sent with sanitized SVGs so that anything the sanitizer misses still cannot
+// run scripts or load resources on the app origin.
+pub const SVG_CONTENT_SECURITY_POLICY: &str = "sandbox; default-src 'none'";
+
+pub async fn security_headers_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+    set_configured_header(headers, header::CONTENT_SECURITY_POLICY, &state.config.content_security_policy);
+    set_configured_header(headers, header::X_FRAME_OPTIONS, &state.config.frame_options);
+    set_configured_header(headers, header::REFERRER_POLICY, &state.config.referrer_policy);
+
+    response
+}
+
+fn set_configured_header(headers: &mut HeaderMap, name: HeaderName, value: &Option<String>) {
+    if headers.contains_key(&name) {
+        return;
+    }
+
+    if let Some(value) = value.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+        headers.insert(name, value);
+    }
+}
+
+pub fn is_risky_content(mime_type: Option<&str>, filename: &str) -> bool {
+    let risky_mime = mime_type
+        .map(|mime| {
+            let essence = mime.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+            RISKY_MIME_TYPES.contains(&essence.as_str())
+        })
+        .unwrap_or(false);
+
+    risky_mime || extension_of(filename).is_some_and(|ext| RISKY_EXTENSIONS.contains(&ext.as_str()))
+}
+
+pub fn is_svg(mime_type: Option<&str>, filename: &str) -> bool {
+    mime_type.is_some_and(|mime| mime.starts_with("image/svg+xml"))
+        || extension_of(filename).as_deref() == Some("svg")
+}
+
+fn extension_of(filename: &str) -> Option<String> {
+    std::path::Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+const SVG_ELEMENTS: &[&str] = &[
+    "svg", "g", "defs", "symbol", "use", "title", "desc", "path", "rect", "circle", "ellipse", "line",
+    "polyline", "polygon", "text", "tspan", "textPath", "linearGradient", "radialGradient", "stop", "pattern",
+    "clipPath", "mask", "marker", "filter", "feBlend", "feColorMatrix", "feComposite", "feDropShadow",
+    "feFlood", "feGaussianBlur", "feMerge", "feMergeNode", "feMorphology", "feOffset",
+];
+
+const SVG_ATTRIBUTES: &[&str] = &[
+    "id", "class", "style", "transform", "d", "x", "y", "x1", "y1", "x2", "y2", "cx", "cy", "r", "rx", "ry",
+    "fx", "fy", "dx", "dy", "width", "height", "viewBox", "preserveAspectRatio", "points", "pathLength",
+    "fill", "fill-opacity", "fill-rule", "stroke", "stroke-width", "stroke-opacity", "stroke-linecap",
+    "stroke-linejoin", "stroke-dasharray", "stroke-dashoffset", "stroke-miterlimit", "opacity", "color",
+    "display", "visibility", "offset", "stop-color", "stop-opacity", "gradientUnits", "gradientTransform",
+    "spreadMethod", "patternUnits", "patternContentUnits", "patternTransform", "clip-path", "clip-rule",
+    "clipPathUnits", "mask", "maskUnits", "maskContentUnits", "marker-start", "marker-mid", "marker-end",
+    "markerWidth", "markerHeight", "markerUnits", "refX", "refY", "orient", "font-family", "font-size",
+    "font-weight", "font-style", "text-anchor", "dominant-baseline", "text-decoration", "letter-spacing",
+    "word-spacing", "rotate", "textLength", "lengthAdjust", "startOffset", "filter", "filterUnits",
+    "primitiveUnits", "stdDeviation", "in", "in2", "result", "mode", "type", "values", "operator", "k1",
+    "k2", "k3", "k4", "flood-color", "flood-opacity", "radius", "version", "xml:space", "xmlns",
+    "xmlns:xlink", "href", "xlink:href",
+];
+
+const SVG_NAMESPACES: &[&str] = &["http://www.w3.org/2000/svg", "http://www.w3.org/1999/xlink"];
+
+/// Rebuilds an SVG from an allowlist of elements and attributes. Elements
+/// that are not allowed are dropped with everything inside them; comments,
+/// doctypes and processing instructions are dropped; attribute values are
+/// entity-decoded before they are checked and re-escaped on output, so the
+/// browser sees exactly what was checked. Links may only point into the
+/// document (`#id`).
+pub fn sanitize_svg(data: &[u8]) -> Vec<u8> {
+    let input = String::from_utf8_lossy(data);
+    let mut output = String::with_capacity(input.len());
+    let mut open: Vec<&str> = Vec::new();
+    let mut skip_depth = 0usize;
+    let mut rest: &str = &input;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('<') else {
+            if skip_depth == 0 {
+                output.push_str(&escape_xml(&decode_entities(rest)));
+            }
+            break;
+        };
+        if skip_depth == 0 {
+            output.push_str(&escape_xml(&decode_entities(&rest[..start])));
+        }
+        rest = &rest[start..];
+
+        if let Some(cdata) = rest.strip_prefix("<![CDATA[") {
+            let end = cdata.find("]]>").unwrap_or(cdata.len());
+            if skip_depth == 0 {
+                output.push_str(&escape_xml(&cdata[..end]));
+            }
+            rest = cdata.get(end + 3..).unwrap_or("");
+            continue;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        if let Some(instruction) = rest.strip_prefix("<?") {
+            rest = instruction.find("?>").map_or("", |end| &instruction[end + 2..]);
+            continue;
+        }
+        if let Some(declaration) = rest.strip_prefix("<!") {
+            let end = match (declaration.find('['), declaration.find('>')) {
+                (Some(subset), Some(close)) if subset < close => declaration.find("]>").map(|end| end + 1),
+                (_, close) => close,
+            };
+            rest = end.map_or("", |end| &declaration[end + 1..]);
+            continue;
+        }
+
+        let Some(tag) = parse_tag(rest) else {
+            if skip_depth == 0 {
+                output.push_str("&lt;");
+            }
+            rest = &rest[1..];
+            continue;
+        };
+        rest = &rest[tag.length..];
+
+        if tag.closing {
+            if skip_depth > 0 {
+                skip_depth -= 1;
+            } else if open.last() == Some(&tag.name) {
+                open.pop();
+                output.push_str(&format!("</{}>", tag.name));
+            }
+            continue;
+        }
+        if skip_depth > 0 || !SVG_ELEMENTS.contains(&tag.name) {
+            if !tag.self_closing {
+                skip_depth += 1;
+            }
+            continue;
+        }
+
+        output.push('<');
+        output.push_str(tag.name);
+        for (name, value) in &tag.attributes {
+            let value = decode_entities(value);
+            if SVG_ATTRIBUTES.contains(name) && is_safe_svg_value(name, &value) {
+                output.push_str(&format!(" {}=\"{}\"", name, escape_xml(&value)));
+            }
+        }
+        if tag.self_closing {
+            output.push_str("/>");
+        } else {
+            output.push('>');
+            open.push(tag.name);
+        }
+    }
+
+    while let Some(name) = open.pop() {
+        output.push_str(&format!("</{}>", name));
+    }
+    output.into_bytes()
+}
+
+struct Tag<'a> {
+    name: &'a str,
+    attributes: Vec<(&'a str, &'a str)>,
+    closing: bool,
+    self_closing: bool,
+    length: usize,
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, ':' | '_' | '-' | '.')
+}
+
+// Parses the tag at the start of `input`, which begins with '<'. Anything
+// between attributes that is not an attribute, such as a stray '/', is
+// skipped, the way browsers treat `<svg/onload=...>`.
+fn parse_tag(input: &str) -> Option<Tag<'_>> {
+    let mut position = 1;
+    let closing = input[position..].starts_with('/');
+    if closing {
+        position += 1;
+    }
+    let name_length = input[position..].find(|c: char| !is_name_char(c)).unwrap_or(input.len() - position);
+    if name_length == 0 {
+        return None;
+    }
+    let name = &input[position..position + name_length];
+    position += name_length;
+
+    let mut attributes = Vec::new();
+    loop {
+        let remaining = &input[position..];
+        let trimmed = remaining.trim_start_matches(|c: char| c.is_whitespace());
+        position += remaining.len() - trimmed.len();
+
+        if trimmed.is_empty() {
+            return None;
+        }
+        if trimmed.starts_with('>') {
+            return Some(Tag { name, attributes, closing, self_closing: false, length: position + 1 });
+        }
+        if trimmed.starts_with("/>") {
+            return Some(Tag { name, attributes, closing, self_closing: true, length: position + 2 });
+        }
+
+        let attribute_length = trimmed
+            .find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/'))
+            .unwrap_or(trimmed.len());
+        if attribute_length == 0 {
+            position += 1;
+            continue;
+        }
+        let attribute = &trimmed[..attribute_length];
+        position += attribute_length;
+
+        let remaining = &input[position..];
+        let after_name = remaining.trim_start_matches(|c: char| c.is_whitespace());
+        let Some(value_start) = after_name.strip_prefix('=') else {
+            attributes.push((attribute, ""));
+            continue;
+        };
+        let value_start = value_start.trim_start_matches(|c: char| c.is_whitespace());
+        position += remaining.len() - value_start.len();
+
+        let (value, consumed) = match value_start.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let end = value_start[1..].find(quote)?;
+                (&value_start[1..end + 1], end + 2)
+            }
+            _ => {
+                let end = value_start.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(value_start.len());
+                (&value_start[..end], end)
+            }
+        };
+        attributes.push((attribute, value));
+        position += consumed;
+    }
+}
+
+fn is_safe_svg_value(name: &str, value: &str) -> bool {
+    let compact: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    match name {
+        "href" | "xlink:href" => return compact.starts_with('#'),
+        "xmlns" | "xmlns:xlink" => return SVG_NAMESPACES.contains(&value.trim()),
+        _ => {}
+    }
+
+    if ["javascript:", "vbscript:", "data:", "expression", "@import", "\\"]
+        .iter()
+        .any(|pattern| compact.contains(pattern))
+    {
+        return false;
+    }
+    // Paint and clip references may only point into the document.
+    compact
+        .match_indices("url(")
+        .all(|(index, pattern)| compact[index + pattern.len()..].trim_start_matches(['"', '\'']).starts_with('#'))
+}
+
+fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..].find(';').map(|end| &rest[1..end + 1]);
+        let character = entity.and_then(|entity| match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok().and_then(char::from_u32),
+                Some(decimal) => decimal.parse().ok().and_then(char::from_u32),
+                None => None,
+            },
+        });
+
+        match (entity, character) {
+            (Some(entity), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(svg: &str) -> String {
+        String::from_utf8(sanitize_svg(svg.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn keeps_drawing_elements() {
+        let svg = sanitize(
+            r##"<?xml version="1.0"?><!-- logo --><svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+<defs><linearGradient id="g"><stop offset="0" stop-color="#fff"/></linearGradient></defs>
+<path d="M0 0L10 10" fill="url(#g)" style="stroke: #000"/><text x="1">A &amp; B</text></svg>"##,
+        );
+        assert_eq!(
+            svg,
+            r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 10 10">
+<defs><linearGradient id="g"><stop offset="0" stop-color="#fff"/></linearGradient></defs>
+<path d="M0 0L10 10" fill="url(#g)" style="stroke: #000"/><text x="1">A &amp; B</text></svg>"##
+        );
+    }
+
+    #[test]
+    fn drops_event_handlers_after_a_slash() {
+        let svg = sanitize("<svg/onload=alert(1)>");
+        assert!(!svg.contains("onload"), "{}", svg);
+        assert!(!svg.contains("alert"), "{}", svg);
+    }
+
+    #[test]
+    fn drops_nested_script_tags() {
+        let svg = sanitize("<svg><scr<script></script>ipt>alert(1)</script></svg>");
+        assert!(!svg.contains("<script"), "{}", svg);
+        assert!(!svg.contains("<scr"), "{}", svg);
+    }
+
+    #[test]
+    fn drops_unquoted_javascript_links() {
+        let svg = sanitize("<svg><a href=javascript:alert(1)><text>x</text></a><use href=javascript:alert(1) /></svg>");
+        assert!(!svg.contains("javascript"), "{}", svg);
+    }
+
+    #[test]
+    fn drops_entity_encoded_javascript_links() {
+        let svg = sanitize(r#"<svg><use xlink:href="&#106;avascript:alert(1)"/><use href="&#x6A;avascript:alert(1)"/></svg>"#);
+        assert!(!svg.contains("avascript"), "{}", svg);
+        assert_eq!(svg, "<svg><use/><use/></svg>");
+    }
+
+    #[test]
+    fn drops_foreign_content_and_external_references() {
+        let svg = sanitize(
+            r#"<svg><foreignObject><iframe src="https://evil.example"/></foreignObject><style>@import "x"</style>
+<rect fill="url(https://evil.example/x)" style="background:url(javascript:alert(1))"/><image href="https://evil.example/x.png"/></svg>"#,
+        );
+        assert_eq!(svg, "<svg>\n<rect/></svg>");
+    }
+
+    #[test]
+    fn escapes_stray_markup() {
+        assert_eq!(sanitize("<svg><text>1 < 2 & <!DOCTYPE x [<!ENTITY e 'x'>]></text></svg>"), "<svg><text>1 &lt; 2 &amp; </text></svg>");
+        assert_eq!(sanitize("<svg><text><![CDATA[<script>]]></text>"), "<svg><text>&lt;script&gt;</text></svg>");
+    }
+}
@@ -0,0 +1,299 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+pub const STREAMING_PAYLOAD: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD";
+pub const STREAMING_UNSIGNED_PAYLOAD: &str = "STREAMING-UNSIGNED-PAYLOAD-TRAILER";
+pub const EMPTY_PAYLOAD_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+pub struct SigningParams<'a> {
+    pub access_key: &'a str,
+    pub secret_key: &'a str,
+    pub region: &'a str,
+    pub service: &'a str,
+}
+
+pub struct Credential {
+    pub access_key: String,
+    pub date: String,
+    pub region: String,
+    pub service: String,
+    pub signed_headers: Vec<String>,
+    pub signature: String,
+}
+
+impl Credential {
+    pub fn scope(&self) -> String {
+        format!("{}/{}/{}/aws4_request", self.date, self.region, self.service)
+    }
+}
+
+pub struct SignedHeaders {
+    pub authorization: String,
+    pub amz_date: String,
+    pub content_sha256: String,
+}
+
+pub fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+pub fn canonical_query_string(params: &[(String, String)]) -> String {
+    let mut encoded: Vec<(String, String)> = params
+        .iter()
+        .map(|(k, v)| (uri_encode(k, true), uri_encode(v, true)))
+        .collect();
+    encoded.sort();
+    encoded
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn signing_key(secret_key: &str, date: &str, region: &str, service: &str) -> Vec<u8> {
+    let date_key = hmac(format!("AWS4{}", secret_key).as_bytes(), date);
+    let region_key = hmac(&date_key, region);
+    let service_key = hmac(&region_key, service);
+    hmac(&service_key, "aws4_request")
+}
+
+pub fn canonical_request(
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+) -> (String, String) {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    headers.sort();
+
+    let canonical_headers: String = headers.iter().map(|(k, v)| format!("{}:{}\n", k, v)).collect();
+    let signed_headers = headers.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(";");
+
+    let request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method, canonical_uri, canonical_query, canonical_headers, signed_headers, payload_hash
+    );
+
+    (request, signed_headers)
+}
+
+pub fn signature(
+    params: &SigningParams,
+    timestamp: &DateTime<Utc>,
+    canonical_request: &str,
+) -> String {
+    let amz_date = timestamp.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = timestamp.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/{}/aws4_request", date, params.region, params.service);
+
+    let key = signing_key(params.secret_key, &date, params.region, params.service);
+    sign_string(&key, &string_to_sign(&amz_date, &scope, canonical_request))
+}
+
+pub fn string_to_sign(amz_date: &str, scope: &str, canonical_request: &str) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    )
+}
+
+// Each chunk of a STREAMING-AWS4-HMAC-SHA256-PAYLOAD body is signed over the previous
+// chunk's signature, starting from the request signature.
+pub fn chunk_string_to_sign(amz_date: &str, scope: &str, previous_signature: &str, chunk_sha256: &str) -> String {
+    format!(
+        "{}-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+        ALGORITHM, amz_date, scope, previous_signature, EMPTY_PAYLOAD_SHA256, chunk_sha256
+    )
+}
+
+pub fn sign_string(signing_key: &[u8], string_to_sign: &str) -> String {
+    hex::encode(hmac(signing_key, string_to_sign))
+}
+
+pub fn parse_authorization(value: &str) -> Option<Credential> {
+    let fields = value.trim().strip_prefix(ALGORITHM)?;
+    let mut credential = None;
+    let mut signed_headers = None;
+    let mut signature = None;
+    for field in fields.split(',') {
+        match field.trim().split_once('=')? {
+            ("Credential", value) => credential = Some(value.trim()),
+            ("SignedHeaders", value) => signed_headers = Some(value.trim()),
+            ("Signature", value) => signature = Some(value.trim()),
+            _ => {}
+        }
+    }
+
+    let mut scope = credential?.split('/');
+    let (access_key, date, region, service) = (scope.next()?, scope.next()?, scope.next()?, scope.next()?);
+    if scope.next()? != "aws4_request" || scope.next().is_some() {
+        return None;
+    }
+
+    Some(Credential {
+        access_key: access_key.to_string(),
+        date: date.to_string(),
+        region: region.to_string(),
+        service: service.to_string(),
+        signed_headers: signed_headers?.split(';').map(str::to_ascii_lowercase).collect(),
+        signature: signature?.to_ascii_lowercase(),
+    })
+}
+
+pub fn sign(
+    params: &SigningParams,
+    method: &str,
+    host: &str,
+    canonical_uri: &str,
+    query: &[(String, String)],
+    payload_hash: &str,
+    timestamp: DateTime<Utc>,
+) -> SignedHeaders {
+    let amz_date = timestamp.format("%Y%m%dT%H%M%SZ").to_string();
+    let headers = vec![
+        ("host".to_string(), host.to_string()),
+        ("x-amz-content-sha256".to_string(), payload_hash.to_string()),
+        ("x-amz-date".to_string(), amz_date.clone()),
+    ];
+
+    let (request, signed_headers) = canonical_request(
+        method,
+        canonical_uri,
+        &canonical_query_string(query),
+        &headers,
+        payload_hash,
+    );
+    let signature = signature(params, &timestamp, &request);
+
+    let authorization = format!(
+        "{} Credential={}/{}/{}/{}/aws4_request, SignedHeaders={}, Signature={}",
+        ALGORITHM,
+        params.access_key,
+        timestamp.format("%Y%m%d"),
+        params.region,
+        params.service,
+        signed_headers,
+        signature
+    );
+
+    SignedHeaders {
+        authorization,
+        amz_date,
+        content_sha256: payload_hash.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // Values from the AWS Signature Version 4 documentation and test suite.
+    const SECRET_KEY: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+
+    #[test]
+    fn derives_signing_key() {
+        let key = signing_key(SECRET_KEY, "20150830", "us-east-1", "iam");
+        assert_eq!(hex::encode(key), "c4afb1cc5771d871763a393e44b703571b55cc28424d1a5e86da6ed3c154a4b9");
+    }
+
+    #[test]
+    fn signs_get_vanilla() {
+        let params = SigningParams { access_key: "AKIDEXAMPLE", secret_key: SECRET_KEY, region: "us-east-1", service: "service" };
+        let timestamp = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = vec![
+            ("Host".to_string(), "example.amazonaws.com".to_string()),
+            ("X-Amz-Date".to_string(), "20150830T123600Z".to_string()),
+        ];
+
+        let (request, signed_headers) = canonical_request("GET", "/", "", &headers, EMPTY_PAYLOAD_SHA256);
+        assert_eq!(signed_headers, "host;x-amz-date");
+        assert_eq!(
+            signature(&params, &timestamp, &request),
+            "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn encodes_uris() {
+        assert_eq!(uri_encode("a b/c~d", false), "a%20b/c~d");
+        assert_eq!(uri_encode("a b/c~d", true), "a%20b%2Fc~d");
+        assert_eq!(uri_encode("é", true), "%C3%A9");
+    }
+
+    #[test]
+    fn sorts_canonical_query() {
+        let params = vec![
+            ("prefix".to_string(), "a b".to_string()),
+            ("list-type".to_string(), "2".to_string()),
+            ("uploads".to_string(), String::new()),
+        ];
+        assert_eq!(canonical_query_string(&params), "list-type=2&prefix=a%20b&uploads=");
+    }
+
+    #[test]
+    fn parses_authorization() {
+        let credential = parse_authorization(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/s3/aws4_request, \
+             SignedHeaders=host;X-Amz-Date, Signature=ABCDEF",
+        )
+        .unwrap();
+        assert_eq!(credential.access_key, "AKIDEXAMPLE");
+        assert_eq!(credential.scope(), "20150830/us-east-1/s3/aws4_request");
+        assert_eq!(credential.signed_headers, vec!["host", "x-amz-date"]);
+        assert_eq!(credential.signature, "abcdef");
+    }
+
+    #[test]
+    fn rejects_malformed_authorization() {
+        assert!(parse_authorization("AWS4-HMAC-SHA256 Credential=AKID/20150830/us-east-1/s3/other, SignedHeaders=host, Signature=ab").is_none());
+        assert!(parse_authorization("AWS4-HMAC-SHA256 Credential=AKID/20150830/us-east-1/s3/aws4_request, Signature=ab").is_none());
+        assert!(parse_authorization("Basic dXNlcjpwYXNz").is_none());
+    }
+
+    #[test]
+    fn signed_request_verifies() {
+        let params = SigningParams { access_key: "AKIDEXAMPLE", secret_key: SECRET_KEY, region: "us-east-1", service: "s3" };
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap();
+        let signed = sign(&params, "PUT", "example.com", "/bucket/key", &[], UNSIGNED_PAYLOAD, timestamp);
+
+        let credential = parse_authorization(&signed.authorization).unwrap();
+        assert_eq!(signed.amz_date, "20240102T030405Z");
+        assert_eq!(credential.signed_headers, vec!["host", "x-amz-content-sha256", "x-amz-date"]);
+
+        let headers = vec![
+            ("host".to_string(), "example.com".to_string()),
+            ("x-amz-content-sha256".to_string(), UNSIGNED_PAYLOAD.to_string()),
+            ("x-amz-date".to_string(), signed.amz_date.clone()),
+        ];
+        let (request, _) = canonical_request("PUT", "/bucket/key", "", &headers, UNSIGNED_PAYLOAD);
+        let key = signing_key(SECRET_KEY, &credential.date, &credential.region, &credential.service);
+        assert_eq!(sign_string(&key, &string_to_sign(&signed.amz_date, &credential.scope(), &request)), credential.signature);
+    }
+}
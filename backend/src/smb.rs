@@ -0,0 +1,214 @@
+use std::io::{self, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use crate::config::Config;
+use crate::models::{MountEntry, SmbCredentials};
+use crate::mounts::normalize_relative_path;
+
+pub struct Share {
+    pub service: String,
+    pub directory: String,
+}
+
+pub fn parse_share(unc: &str) -> anyhow::Result<Share> {
+    let unc = unc.trim().replace('\\', "/");
+    let rest = unc
+        .strip_prefix("smb://")
+        .or_else(|| unc.strip_prefix("//"))
+        .ok_or_else(|| anyhow::anyhow!("SMB shares must look like //server/share"))?;
+
+    let mut parts = rest.splitn(3, '/');
+    let server = parts.next().unwrap_or_default();
+    let share = parts.next().unwrap_or_default();
+    if server.is_empty() || share.is_empty() {
+        anyhow::bail!("SMB shares must look like //server/share");
+    }
+    if [server, share].iter().any(|part| part.chars().any(|c| c.is_whitespace() || c == '"' || c == ';')) {
+        anyhow::bail!("invalid SMB server or share name");
+    }
+
+    let directory = normalize_relative_path(parts.next().unwrap_or_default())?;
+    check_path(&directory)?;
+
+    Ok(Share {
+        service: format!("//{}/{}", server, share),
+        directory,
+    })
+}
+
+pub fn display_path(share: &Share) -> String {
+    if share.directory.is_empty() {
+        share.service.clone()
+    } else {
+        format!("{}/{}", share.service, share.directory)
+    }
+}
+
+fn check_path(path: &str) -> anyhow::Result<()> {
+    if path.chars().any(|c| c == '"' || c == ';' || c.is_control()) {
+        anyhow::bail!("SMB paths may not contain quotes, semicolons or control characters");
+    }
+    Ok(())
+}
+
+fn remote_path(share: &Share, relative: &str) -> anyhow::Result<String> {
+    check_path(relative)?;
+    let path = match (share.directory.is_empty(), relative.is_empty()) {
+        (true, _) => relative.to_string(),
+        (false, true) => share.directory.clone(),
+        (false, false) => format!("{}/{}", share.directory, relative),
+    };
+    Ok(path.replace('/', "\\"))
+}
+
+fn command(config: &Config, share: &Share, credentials: &SmbCredentials, script: &str) -> Command {
+    let mut command = Command::new(&config.smbclient_path);
+    command
+        .arg(&share.service)
+        .arg("-E")
+        .arg("-U")
+        .arg(&credentials.username)
+        .arg("-c")
+        .arg(script)
+        .env("PASSWD", &credentials.password)
+        .stdin(Stdio::null());
+    if let Some(domain) = credentials.domain.as_deref().filter(|domain| !domain.is_empty()) {
+        command.arg("-W").arg(domain);
+    }
+    command
+}
+
+fn failure(status: std::process::ExitStatus, stdout: &[u8], stderr: &[u8]) -> anyhow::Error {
+    let output = format!("{}\n{}", String::from_utf8_lossy(stderr), String::from_utf8_lossy(stdout));
+    let message = output
+        .lines()
+        .map(str::trim)
+        .find(|line| line.contains("NT_STATUS_"))
+        .or_else(|| output.lines().map(str::trim).rfind(|line| !line.is_empty()))
+        .unwrap_or("no output");
+    anyhow::anyhow!("smbclient exited with {}: {}", status, message)
+}
+
+fn split_last_token(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end();
+    let index = line.rfind(char::is_whitespace)?;
+    Some((&line[..index], &line[index + 1..]))
+}
+
+fn parse_entry(line: &str, parent_path: &str) -> Option<MountEntry> {
+    if !line.starts_with("  ") {
+        return None;
+    }
+
+    let mut rest = line;
+    let mut date = Vec::with_capacity(5);
+    for _ in 0..5 {
+        let (head, token) = split_last_token(rest)?;
+        date.push(token);
+        rest = head;
+    }
+    date.reverse();
+    let (rest, size) = split_last_token(rest)?;
+    let (rest, attributes) = split_last_token(rest)?;
+    let size: i64 = size.parse().ok()?;
+    if !attributes.chars().all(|c| c.is_ascii_uppercase()) {
+        return None;
+    }
+
+    let name = rest.trim();
+    if name.is_empty() || name == "." || name == ".." {
+        return None;
+    }
+
+    let is_dir = attributes.contains('D');
+    let modified_at = NaiveDateTime::parse_from_str(&date.join(" "), "%a %b %d %H:%M:%S %Y")
+        .ok()
+        .map(|naive| DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc));
+
+    Some(MountEntry {
+        path: if parent_path.is_empty() { name.to_string() } else { format!("{}/{}", parent_path, name) },
+        name: name.to_string(),
+        is_dir,
+        size: if is_dir { 0 } else { size },
+        modified_at,
+    })
+}
+
+pub fn parse_listing(output: &str, parent_path: &str) -> Vec<MountEntry> {
+    let mut entries: Vec<MountEntry> = output.lines().filter_map(|line| parse_entry(line, parent_path)).collect();
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+pub async fn list(config: &Config, unc: &str, credentials: &SmbCredentials, relative: &str) -> anyhow::Result<Vec<MountEntry>> {
+    let share = parse_share(unc)?;
+    let directory = remote_path(&share, relative)?;
+    let pattern = if directory.is_empty() { "*".to_string() } else { format!("{}\\*", directory) };
+
+    let output = tokio::process::Command::from(command(config, &share, credentials, &format!("ls \"{}\"", pattern)))
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(failure(output.status, &output.stdout, &output.stderr));
+    }
+
+    Ok(parse_listing(&String::from_utf8_lossy(&output.stdout), relative))
+}
+
+pub async fn stat(config: &Config, unc: &str, credentials: &SmbCredentials, relative: &str) -> anyhow::Result<Option<MountEntry>> {
+    let (parent, name) = match relative.rsplit_once('/') {
+        Some((parent, name)) => (parent, name),
+        None => ("", relative),
+    };
+
+    Ok(list(config, unc, credentials, parent)
+        .await?
+        .into_iter()
+        .find(|entry| entry.name == name))
+}
+
+fn download_command(config: &Config, unc: &str, credentials: &SmbCredentials, relative: &str) -> anyhow::Result<Command> {
+    let share = parse_share(unc)?;
+    let mut command = command(config, &share, credentials, &format!("get \"{}\" -", remote_path(&share, relative)?));
+    command.stdout(Stdio::piped()).stderr(Stdio::null());
+    Ok(command)
+}
+
+pub fn stream(config: &Config, unc: &str, credentials: &SmbCredentials, relative: &str) -> anyhow::Result<tokio::process::ChildStdout> {
+    let mut child = tokio::process::Command::from(download_command(config, unc, credentials, relative)?).spawn()?;
+    child.stdout.take().ok_or_else(|| anyhow::anyhow!("smbclient has no stdout"))
+}
+
+pub struct Download {
+    child: Child,
+    stdout: ChildStdout,
+}
+
+impl Read for Download {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.stdout.read(buf)?;
+        if bytes_read == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!("smbclient exited with {}", status)));
+            }
+        }
+        Ok(bytes_read)
+    }
+}
+
+impl Drop for Download {
+    fn drop(&mut self) {
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+pub fn open(config: &Config, unc: &str, credentials: &SmbCredentials, relative: &str) -> anyhow::Result<Download> {
+    let mut child = download_command(config, unc, credentials, relative)?.spawn()?;
+    let stdout = child.stdout.take().ok_or_else(|| anyhow::anyhow!("smbclient has no stdout"))?;
+    Ok(Download { child, stdout })
+}
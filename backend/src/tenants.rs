@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use axum::http::StatusCode;
+use tracing::warn;
+use uuid::Uuid;
+use crate::models::User;
+use crate::{database, AppState};
+
+pub async fn managed_user(state: &AppState, admin: &User, user_id: &Uuid) -> Result<User, StatusCode> {
+    let user = database::get_user_by_id(&state.db, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    match manages(admin, &user) {
+        true => Ok(user),
+        false => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+// Instance admins manage everyone; a tenant admin only manages users of their tenant.
+fn manages(admin: &User, user: &User) -> bool {
+    match admin.tenant_id {
+        Some(tenant_id) => user.tenant_id == Some(tenant_id),
+        None => true,
+    }
+}
+
+pub fn same_tenant(a: &User, b: &User) -> bool {
+    a.tenant_id == b.tenant_id
+}
+
+pub async fn refresh_storage_roots(state: &AppState) -> anyhow::Result<()> {
+    let mut roots: HashMap<Uuid, Vec<PathBuf>> = HashMap::new();
+    for (user_id, paths) in database::get_tenant_storage_roots(&state.db).await? {
+        let resolved: Vec<PathBuf> = paths
+            .iter()
+            .filter_map(|path| {
+                let root = state.file_storage.storage_root(path);
+                if root.is_none() {
+                    warn!("Tenant storage root {} is not one of STORAGE_PATHS; ignoring it", path);
+                }
+                root
+            })
+            .collect();
+        roots.insert(user_id, resolved);
+    }
+
+    state.file_storage.set_tenant_roots(roots);
+    Ok(())
+}
+
+pub async fn check_quota(state: &AppState, user_id: &Uuid, upload_size: i64) -> Result<(), StatusCode> {
+    let storage = database::get_tenant_storage(&state.db, user_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match storage {
+        Some((Some(quota), used)) if used.saturating_add(upload_size) > quota => Err(StatusCode::INSUFFICIENT_STORAGE),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn user(is_admin: bool, tenant_id: Option<Uuid>) -> User {
+        User {
+            id: Uuid::new_v4(),
+            username: "user".to_string(),
+            email: "user@example.com".to_string(),
+            password_hash: String::new(),
+            is_admin,
+            tenant_id,
+            deactivated_at: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn instance_admin_manages_everyone() {
+        let admin = user(true, None);
+        assert!(manages(&admin, &user(false, None)));
+        assert!(manages(&admin, &user(false, Some(Uuid::new_v4()))));
+    }
+
+    #[test]
+    fn tenant_admin_manages_only_their_tenant() {
+        let tenant_id = Uuid::new_v4();
+        let admin = user(true, Some(tenant_id));
+        assert!(manages(&admin, &user(false, Some(tenant_id))));
+        assert!(!manages(&admin, &user(false, Some(Uuid::new_v4()))));
+        assert!(!manages(&admin, &user(false, None)));
+        assert!(!manages(&admin, &user(true, None)));
+    }
+
+    #[test]
+    fn same_tenant_compares_tenants() {
+        let tenant_id = Uuid::new_v4();
+        assert!(same_tenant(&user(false, Some(tenant_id)), &user(true, Some(tenant_id))));
+        assert!(same_tenant(&user(false, None), &user(false, None)));
+        assert!(!same_tenant(&user(false, Some(tenant_id)), &user(false, None)));
+    }
+}
@@ -0,0 +1,37 @@
+use std::io::Cursor;
+use std::path::PathBuf;
+use image::{ImageFormat, ImageReader};
+use crate::file_storage::FileStorage;
+use crate::models::StorageEncoding;
+use crate::s3;
+
+pub const MAX_DIMENSION: u32 = 320;
+
+const SUPPORTED_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+pub fn is_supported(mime_type: Option<&str>) -> bool {
+    mime_type.is_some_and(|mime_type| SUPPORTED_TYPES.contains(&mime_type))
+}
+
+pub fn cache_path(file_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.thumb.jpg", file_path))
+}
+
+pub fn load_or_create(file_storage: &FileStorage, file_path: &str, encoding: Option<StorageEncoding>) -> anyhow::Result<Vec<u8>> {
+    let cache = cache_path(file_path);
+    if let Ok(data) = std::fs::read(&cache) {
+        return Ok(data);
+    }
+
+    let source = Cursor::new(file_storage.get_file_data(file_path, encoding)?);
+    let image = ImageReader::new(source).with_guessed_format()?.decode()?;
+    let thumbnail = image.thumbnail(MAX_DIMENSION, MAX_DIMENSION).to_rgb8();
+
+    let mut data = Vec::new();
+    thumbnail.write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)?;
+    if !file_storage.is_encrypted() && !s3::is_s3_path(file_path) {
+        let _ = std::fs::write(&cache, &data);
+    }
+
+    Ok(data)
+}
@@ -0,0 +1,136 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::models::{FileInfo, LifecycleRule, StorageTier};
+use crate::operations::Progress;
+use crate::s3::S3Placement;
+use crate::{database, AppState};
+
+const BATCH_SIZE: i64 = 50;
+const PAUSE_BETWEEN_FILES: Duration = Duration::from_millis(20);
+
+static RUNNING: AtomicBool = AtomicBool::new(false);
+static RETRIEVING: Mutex<Option<HashSet<Uuid>>> = Mutex::new(None);
+
+pub fn is_running() -> bool {
+    RUNNING.load(Ordering::SeqCst)
+}
+
+async fn move_file(state: &AppState, file: &FileInfo, tier: StorageTier) -> anyhow::Result<bool> {
+    let file_storage = state.file_storage.clone();
+    let (file_path, user_id, filename) = (file.file_path.clone(), file.user_id, file.filename.clone());
+    let (disk_path, target) =
+        tokio::task::spawn_blocking(move || file_storage.copy_to_tier(&file_path, &user_id, &filename, tier)).await??;
+    let (disk_path, target) = (disk_path.to_string_lossy().to_string(), target.to_string_lossy().to_string());
+
+    let moved = database::set_file_storage(&state.db, &file.id, &file.file_path, &target, &disk_path).await;
+    let stale = match &moved {
+        Ok(true) => file.file_path.clone(),
+        _ => target,
+    };
+    let file_storage = state.file_storage.clone();
+    let removed = tokio::task::spawn_blocking(move || file_storage.delete_file(&stale)).await?;
+    if let Err(e) = removed {
+        warn!("Failed to remove the old copy of file {} after moving it to {} storage: {:#}", file.id, tier.as_str(), e);
+    }
+
+    moved
+}
+
+async fn apply_rule(state: &AppState, rule: &LifecycleRule, progress: Option<&Progress>, moved: &mut i64, bytes: &mut i64) -> anyhow::Result<()> {
+    let mut seen = Vec::new();
+    loop {
+        let files = database::get_lifecycle_candidates(&state.db, rule, &seen, BATCH_SIZE).await?;
+        if files.is_empty() {
+            break;
+        }
+
+        for file in &files {
+            seen.push(file.id);
+            match move_file(state, file, StorageTier::Cold).await {
+                Ok(true) => {
+                    *moved += 1;
+                    *bytes += file.file_size;
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to move file {} to cold storage: {:#}", file.id, e),
+            }
+            if let Some(progress) = progress {
+                progress.update(*moved, None).await?;
+            }
+            tokio::time::sleep(PAUSE_BETWEEN_FILES).await;
+        }
+
+        if (files.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    database::mark_lifecycle_rule_run(&state.db, &rule.id).await
+}
+
+async fn apply(state: &AppState, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    if state.config.s3.is_none() {
+        anyhow::bail!("lifecycle rules need S3_BUCKET to be set");
+    }
+
+    let mut moved = 0i64;
+    let mut bytes = 0i64;
+    for rule in database::get_lifecycle_rules(&state.db).await? {
+        if rule.is_enabled {
+            apply_rule(state, &rule, progress, &mut moved, &mut bytes).await?;
+        }
+    }
+
+    info!("Lifecycle rules moved {} files ({} bytes) to cold storage", moved, bytes);
+    Ok(serde_json::json!({
+        "moved": moved,
+        "bytes": bytes,
+    }))
+}
+
+pub async fn run(state: &AppState, progress: Option<&Progress>) -> anyhow::Result<serde_json::Value> {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        anyhow::bail!("lifecycle rules are already running");
+    }
+    let result = apply(state, progress).await;
+    RUNNING.store(false, Ordering::SeqCst);
+    result
+}
+
+pub fn retrieve(state: &AppState, file: &FileInfo) {
+    let overflow = state.config.s3.as_ref().is_some_and(|s3| s3.placement == S3Placement::Overflow);
+    if !overflow || file.storage_tier != StorageTier::Cold.as_str() {
+        return;
+    }
+    if let Ok(mut retrieving) = RETRIEVING.lock() {
+        if !retrieving.get_or_insert_with(HashSet::new).insert(file.id) {
+            return;
+        }
+    }
+
+    let state = state.clone();
+    let file_id = file.id;
+    tokio::spawn(async move {
+        let result = async {
+            match database::get_file_by_id(&state.db, &file_id).await? {
+                Some(file) if file.storage_tier == StorageTier::Cold.as_str() => move_file(&state, &file, StorageTier::Hot).await,
+                _ => Ok(false),
+            }
+        }
+        .await;
+        match result {
+            Ok(true) => info!("Retrieved file {} from cold storage", file_id),
+            Ok(false) => {}
+            Err(e) => warn!("Failed to retrieve file {} from cold storage: {:#}", file_id, e),
+        }
+        if let Ok(mut retrieving) = RETRIEVING.lock() {
+            if let Some(retrieving) = retrieving.as_mut() {
+                retrieving.remove(&file_id);
+            }
+        }
+    });
+}
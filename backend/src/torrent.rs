@@ -0,0 +1,123 @@
+use std::io::Read;
+use sha1::{Digest, Sha1};
+use crate::sigv4::uri_encode;
+
+const MIN_PIECE_LENGTH: u64 = 256 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+const TARGET_PIECE_COUNT: u64 = 2000;
+
+pub struct Torrent {
+    pub info_hash: String,
+    pub data: Vec<u8>,
+}
+
+fn encode_bytes(out: &mut Vec<u8>, value: &[u8]) {
+    out.extend_from_slice(value.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(value);
+}
+
+fn encode_int(out: &mut Vec<u8>, value: i64) {
+    out.extend_from_slice(format!("i{}e", value).as_bytes());
+}
+
+pub fn piece_length_for(size: u64) -> u64 {
+    let mut piece_length = MIN_PIECE_LENGTH;
+    while piece_length < MAX_PIECE_LENGTH && size / piece_length > TARGET_PIECE_COUNT {
+        piece_length *= 2;
+    }
+    piece_length
+}
+
+pub fn hash_pieces(
+    mut file: impl Read,
+    piece_length: u64,
+    mut on_piece: impl FnMut(u64) -> anyhow::Result<()>,
+) -> anyhow::Result<(Vec<u8>, u64)> {
+    let mut buffer = vec![0u8; piece_length as usize];
+    let mut pieces = Vec::new();
+    let mut total = 0u64;
+
+    loop {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            let read = file.read(&mut buffer[filled..])?;
+            if read == 0 {
+                break;
+            }
+            filled += read;
+        }
+        if filled == 0 {
+            break;
+        }
+
+        pieces.extend_from_slice(&Sha1::digest(&buffer[..filled]));
+        total += filled as u64;
+        on_piece(total)?;
+
+        if filled < buffer.len() {
+            break;
+        }
+    }
+
+    Ok((pieces, total))
+}
+
+pub fn build(name: &str, length: u64, piece_length: u64, pieces: &[u8], web_seed: &str, trackers: &[String]) -> Torrent {
+    let mut info = Vec::new();
+    info.push(b'd');
+    encode_bytes(&mut info, b"length");
+    encode_int(&mut info, length as i64);
+    encode_bytes(&mut info, b"name");
+    encode_bytes(&mut info, name.as_bytes());
+    encode_bytes(&mut info, b"piece length");
+    encode_int(&mut info, piece_length as i64);
+    encode_bytes(&mut info, b"pieces");
+    encode_bytes(&mut info, pieces);
+    info.push(b'e');
+
+    let info_hash = hex::encode(Sha1::digest(&info));
+
+    let mut data = Vec::new();
+    data.push(b'd');
+    if let Some(first) = trackers.first() {
+        encode_bytes(&mut data, b"announce");
+        encode_bytes(&mut data, first.as_bytes());
+        encode_bytes(&mut data, b"announce-list");
+        data.push(b'l');
+        for tracker in trackers {
+            data.push(b'l');
+            encode_bytes(&mut data, tracker.as_bytes());
+            data.push(b'e');
+        }
+        data.push(b'e');
+    }
+    encode_bytes(&mut data, b"created by");
+    encode_bytes(&mut data, b"local-drive");
+    encode_bytes(&mut data, b"creation date");
+    encode_int(&mut data, chrono::Utc::now().timestamp());
+    encode_bytes(&mut data, b"info");
+    data.extend_from_slice(&info);
+    encode_bytes(&mut data, b"url-list");
+    data.push(b'l');
+    encode_bytes(&mut data, web_seed.as_bytes());
+    data.push(b'e');
+    data.push(b'e');
+
+    Torrent { info_hash, data }
+}
+
+pub fn magnet_uri(info_hash: &str, name: &str, length: i64, web_seed: &str, trackers: &[String]) -> String {
+    let mut uri = format!(
+        "magnet:?xt=urn:btih:{}&dn={}&xl={}&ws={}",
+        info_hash,
+        uri_encode(name, true),
+        length,
+        uri_encode(web_seed, true)
+    );
+    for tracker in trackers {
+        uri.push_str("&tr=");
+        uri.push_str(&uri_encode(tracker, true));
+    }
+    uri
+}
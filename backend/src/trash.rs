@@ -0,0 +1,92 @@
+use tracing::{error, info, warn};
+use uuid::Uuid;
+use crate::{database, AppState};
+
+pub const PURGED_NOTIFICATION: &str = "trash_purged";
+
+pub async fn user_limit(state: &AppState, user_id: &Uuid) -> anyhow::Result<Option<i64>> {
+    let limit = database::get_user_trash_limit(&state.db, user_id).await?;
+    Ok(limit.or(state.config.trash_size_limit))
+}
+
+fn format_size(bytes: i64) -> String {
+    let mb = bytes as f64 / (1024.0 * 1024.0);
+    if mb >= 1024.0 {
+        format!("{:.1} GB", mb / 1024.0)
+    } else {
+        format!("{:.1} MB", mb)
+    }
+}
+
+pub async fn enforce_limit(state: &AppState, user_id: &Uuid) -> anyhow::Result<(usize, i64)> {
+    let limit = match user_limit(state, user_id).await? {
+        Some(limit) => limit,
+        None => return Ok((0, 0)),
+    };
+
+    let (mut used, _) = database::get_trash_usage(&state.db, user_id).await?;
+    if used <= limit {
+        return Ok((0, 0));
+    }
+
+    let mut purged = Vec::new();
+    let mut freed = 0i64;
+    for file in database::get_deleted_files(&state.db, user_id).await?.into_iter().rev() {
+        if used <= limit {
+            break;
+        }
+        let file_path = file.file_path.clone();
+        if let Err(e) = state.file_storage.blocking(move |storage| storage.delete_file(&file_path)).await {
+            warn!("Failed to remove trashed blob {}: {}", file.file_path, e);
+            continue;
+        }
+        database::delete_file_record(&state.db, &file.id).await?;
+        used -= file.file_size;
+        freed += file.file_size;
+        purged.push(file.original_filename);
+    }
+
+    if purged.is_empty() {
+        return Ok((0, 0));
+    }
+
+    info!("Purged {} files ({} bytes) from the trash of user {} to stay under {} bytes", purged.len(), freed, user_id, limit);
+
+    let (noun, verb) = if purged.len() == 1 { ("file", "was") } else { ("files", "were") };
+    let message = format!(
+        "{} {} ({}) {} permanently deleted from your trash because it exceeded its {} limit",
+        purged.len(),
+        noun,
+        format_size(freed),
+        verb,
+        format_size(limit)
+    );
+    let data = serde_json::json!({
+        "files": purged,
+        "bytes_freed": freed,
+        "limit_bytes": limit,
+    });
+    database::create_notification(&state.db, user_id, PURGED_NOTIFICATION, &message, Some(&data)).await?;
+
+    Ok((purged.len(), freed))
+}
+
+pub async fn enforce_limit_quietly(state: &AppState, user_id: &Uuid) {
+    if let Err(e) = enforce_limit(state, user_id).await {
+        warn!("Failed to enforce trash size limit for user {}: {}", user_id, e);
+    }
+}
+
+pub async fn enforce_all(state: &AppState) {
+    let users = match database::get_users_over_trash_limit(&state.db, state.config.trash_size_limit).await {
+        Ok(users) => users,
+        Err(e) => {
+            error!("Failed to find users over their trash limit: {}", e);
+            return;
+        }
+    };
+
+    for user_id in users {
+        enforce_limit_quietly(state, &user_id).await;
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, DurationRound, Utc};
+use uuid::Uuid;
+use crate::config::Config;
+use crate::{auth, database, AppState};
+
+pub const RETENTION_DAYS: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UsageKey {
+    pub bucket: DateTime<Utc>,
+    pub user_id: Option<Uuid>,
+    pub method: String,
+    pub route: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UsageCounts {
+    pub requests: i64,
+    pub client_errors: i64,
+    pub server_errors: i64,
+    pub total_duration_ms: i64,
+}
+
+#[derive(Default)]
+pub struct UsageRecorder {
+    pending: Mutex<HashMap<UsageKey, UsageCounts>>,
+}
+
+impl UsageRecorder {
+    pub fn record(&self, key: UsageKey, status: u16, duration_ms: i64) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = pending.entry(key).or_default();
+        counts.requests += 1;
+        counts.total_duration_ms += duration_ms;
+        match status {
+            400..=499 => counts.client_errors += 1,
+            500..=599 => counts.server_errors += 1,
+            _ => {}
+        }
+    }
+
+    fn take(&self) -> HashMap<UsageKey, UsageCounts> {
+        std::mem::take(&mut *self.pending.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    fn restore(&self, entries: HashMap<UsageKey, UsageCounts>) {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        for (key, counts) in entries {
+            let existing = pending.entry(key).or_default();
+            existing.requests += counts.requests;
+            existing.client_errors += counts.client_errors;
+            existing.server_errors += counts.server_errors;
+            existing.total_duration_ms += counts.total_duration_ms;
+        }
+    }
+
+    pub async fn flush(&self, db: &sqlx::PgPool) -> anyhow::Result<usize> {
+        let entries = self.take();
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let count = entries.len();
+        if let Err(e) = database::add_api_usage(db, &entries).await {
+            self.restore(entries);
+            return Err(e);
+        }
+
+        Ok(count)
+    }
+}
+
+fn request_user_id(request: &Request, config: &Config) -> Option<Uuid> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")?;
+    let claims = auth::verify_jwt_token(token, config).ok()?;
+    Uuid::parse_str(&claims.sub).ok()
+}
+
+pub async fn api_usage_middleware(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "<unmatched>".to_string());
+    let method = request.method().to_string();
+    let user_id = request_user_id(&request, &state.config);
+
+    let response = next.run(request).await;
+
+    let bucket = Utc::now()
+        .duration_trunc(chrono::Duration::hours(1))
+        .unwrap_or_else(|_| Utc::now());
+    state.api_usage.record(
+        UsageKey { bucket, user_id, method, route },
+        response.status().as_u16(),
+        started.elapsed().as_millis() as i64,
+    );
+
+    response
+}
@@ -0,0 +1,182 @@
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use serde::Deserialize;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+use crate::config::Config;
+use crate::models::{FileInfo, FileMetadata};
+use crate::s3;
+use crate::{database, AppState};
+
+const BATCH_SIZE: i64 = 10;
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(120);
+const POSTER_MAX_WIDTH: u32 = 640;
+const POSTER_OFFSET_SECONDS: f64 = 1.0;
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "mov", "webm", "mkv", "avi", "wmv", "mpg", "mpeg", "3gp"];
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_name: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+pub fn poster_path(file_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.poster.jpg", file_path))
+}
+
+async fn run(program: &str, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let mut command = Command::new(program);
+    command.args(args).stdin(Stdio::null()).kill_on_drop(true);
+
+    let output = tokio::time::timeout(FFMPEG_TIMEOUT, command.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("{} timed out", program))?
+        .map_err(|e| anyhow::anyhow!("failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        anyhow::bail!("{} exited with {}: {}", program, output.status, stderr.lines().last().unwrap_or_default());
+    }
+
+    Ok(output.stdout)
+}
+
+async fn probe(config: &Config, file: &FileInfo, source: &Path) -> anyhow::Result<FileMetadata> {
+    let output = run(
+        &config.ffprobe_path,
+        &[
+            "-v", "error",
+            "-select_streams", "v:0",
+            "-show_entries", "stream=codec_name,width,height:format=duration",
+            "-of", "json",
+            &source.to_string_lossy(),
+        ],
+    )
+    .await?;
+    let probe: ProbeOutput = serde_json::from_slice(&output)?;
+    let stream = probe.streams.into_iter().next().ok_or_else(|| anyhow::anyhow!("no video stream"))?;
+
+    Ok(FileMetadata {
+        file_id: file.id,
+        status: "ready".to_string(),
+        duration_seconds: probe
+            .format
+            .and_then(|format| format.duration)
+            .and_then(|duration| duration.parse().ok()),
+        width: stream.width,
+        height: stream.height,
+        video_codec: stream.codec_name,
+        poster_path: None,
+        error: None,
+        updated_at: chrono::Utc::now(),
+    })
+}
+
+async fn extract_poster(config: &Config, file: &FileInfo, source: &Path, duration: Option<f64>) -> anyhow::Result<PathBuf> {
+    if s3::is_s3_path(&file.file_path) {
+        anyhow::bail!("posters are not kept for files stored in S3");
+    }
+
+    let offset = duration.map_or(0.0, |duration| (duration / 2.0).min(POSTER_OFFSET_SECONDS));
+    let target = poster_path(&file.file_path);
+    let scale = format!("scale='min({},iw)':-2", POSTER_MAX_WIDTH);
+
+    run(
+        &config.ffmpeg_path,
+        &[
+            "-v", "error",
+            "-y",
+            "-ss", &format!("{:.3}", offset),
+            "-i", &source.to_string_lossy(),
+            "-frames:v", "1",
+            "-vf", &scale,
+            "-f", "image2",
+            &target.to_string_lossy(),
+        ],
+    )
+    .await?;
+
+    Ok(target)
+}
+
+async fn process_file(state: &AppState, file: &FileInfo) -> anyhow::Result<()> {
+    let result = async {
+        let file_storage = state.file_storage.clone();
+        let (file_path, encoding) = (file.file_path.clone(), file.encoding());
+        let source = tokio::task::spawn_blocking(move || file_storage.materialize(&file_path, encoding)).await??;
+
+        let mut metadata = probe(&state.config, file, source.path()).await?;
+        match extract_poster(&state.config, file, source.path(), metadata.duration_seconds).await {
+            Ok(poster) => metadata.poster_path = Some(poster.to_string_lossy().to_string()),
+            Err(e) => warn!("Failed to extract a poster frame for file {}: {:#}", file.id, e),
+        }
+        anyhow::Ok(metadata)
+    }
+    .await;
+
+    let metadata = match result {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("Failed to read video metadata of file {}: {:#}", file.id, e);
+            FileMetadata {
+                file_id: file.id,
+                status: "failed".to_string(),
+                duration_seconds: None,
+                width: None,
+                height: None,
+                video_codec: None,
+                poster_path: None,
+                error: Some(format!("{:#}", e)),
+                updated_at: chrono::Utc::now(),
+            }
+        }
+    };
+
+    database::finish_file_metadata(&state.db, &metadata).await
+}
+
+pub async fn process(state: &AppState) {
+    let mut processed = 0;
+
+    loop {
+        let files = match database::claim_videos_for_metadata(&state.db, VIDEO_EXTENSIONS, BATCH_SIZE).await {
+            Ok(files) => files,
+            Err(e) => {
+                error!("Failed to claim videos for metadata extraction: {}", e);
+                break;
+            }
+        };
+        if files.is_empty() {
+            break;
+        }
+
+        for file in &files {
+            if let Err(e) = process_file(state, file).await {
+                error!("Failed to record the metadata of video {}: {}", file.id, e);
+            }
+        }
+
+        processed += files.len();
+        if (files.len() as i64) < BATCH_SIZE {
+            break;
+        }
+    }
+
+    if processed > 0 {
+        info!("Extracted metadata from {} videos", processed);
+    }
+}
@@ -0,0 +1,386 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use p256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use serde::Deserialize;
+use serde_cbor::Value;
+use sha2::{Digest, Sha256};
+use crate::config::Config;
+use crate::models::User;
+
+pub const CHALLENGE_LENGTH: usize = 32;
+pub const TIMEOUT_MS: u64 = 300_000;
+
+const COSE_ALG_ES256: i128 = -7;
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_DATA: u8 = 0x40;
+
+#[derive(Debug, Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+pub fn new_challenge() -> Vec<u8> {
+    let mut challenge = vec![0u8; CHALLENGE_LENGTH];
+    OsRng.fill_bytes(&mut challenge);
+    challenge
+}
+
+pub fn decode(value: &str) -> anyhow::Result<Vec<u8>> {
+    Ok(URL_SAFE_NO_PAD.decode(value.trim_end_matches('='))?)
+}
+
+pub fn creation_options(config: &Config, user: &User, challenge: &[u8], exclude: &[Vec<u8>]) -> serde_json::Value {
+    serde_json::json!({
+        "rp": { "id": config.webauthn_rp_id, "name": config.webauthn_rp_name },
+        "user": {
+            "id": URL_SAFE_NO_PAD.encode(user.id.as_bytes()),
+            "name": user.username,
+            "displayName": user.username,
+        },
+        "challenge": URL_SAFE_NO_PAD.encode(challenge),
+        "pubKeyCredParams": [{ "type": "public-key", "alg": COSE_ALG_ES256 as i64 }],
+        "timeout": TIMEOUT_MS,
+        "attestation": "none",
+        "excludeCredentials": exclude
+            .iter()
+            .map(|id| serde_json::json!({ "type": "public-key", "id": URL_SAFE_NO_PAD.encode(id) }))
+            .collect::<Vec<_>>(),
+        "authenticatorSelection": {
+            "residentKey": "preferred",
+            "userVerification": "required",
+        },
+    })
+}
+
+/// Stand-in credential ids for a username with no passkeys, so login options
+/// look the same whether or not the account exists. Derived from the server
+/// secret so repeated requests for the same name return the same ids.
+pub fn decoy_credentials(config: &Config, username: &str) -> Vec<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config.jwt_secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(b"webauthn-decoy:");
+    mac.update(username.to_lowercase().as_bytes());
+    vec![mac.finalize().into_bytes().to_vec()]
+}
+
+pub fn request_options(config: &Config, challenge: &[u8], allow: &[Vec<u8>]) -> serde_json::Value {
+    serde_json::json!({
+        "rpId": config.webauthn_rp_id,
+        "challenge": URL_SAFE_NO_PAD.encode(challenge),
+        "timeout": TIMEOUT_MS,
+        "userVerification": "required",
+        "allowCredentials": allow
+            .iter()
+            .map(|id| serde_json::json!({ "type": "public-key", "id": URL_SAFE_NO_PAD.encode(id) }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+fn verify_client_data(config: &Config, client_data_json: &[u8], kind: &str, challenge: &[u8]) -> anyhow::Result<()> {
+    let client_data: CollectedClientData = serde_json::from_slice(client_data_json)?;
+
+    if client_data.kind != kind {
+        anyhow::bail!("unexpected client data type {}", client_data.kind);
+    }
+    if decode(&client_data.challenge)? != challenge {
+        anyhow::bail!("challenge mismatch");
+    }
+    if client_data.origin != config.webauthn_origin {
+        anyhow::bail!("unexpected origin {}", client_data.origin);
+    }
+
+    Ok(())
+}
+
+fn parse_authenticator_data(data: &[u8]) -> anyhow::Result<AuthenticatorData> {
+    if data.len() < 37 {
+        anyhow::bail!("authenticator data too short");
+    }
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & FLAG_ATTESTED_DATA != 0 {
+        let rest = data.get(37 + 16..).ok_or_else(|| anyhow::anyhow!("missing attested credential data"))?;
+        if rest.len() < 2 {
+            anyhow::bail!("missing credential id length");
+        }
+        let id_length = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        let credential_id = rest
+            .get(2..2 + id_length)
+            .ok_or_else(|| anyhow::anyhow!("truncated credential id"))?
+            .to_vec();
+
+        let mut deserializer = serde_cbor::Deserializer::from_slice(&rest[2 + id_length..]);
+        let cose_key: Value = serde::Deserialize::deserialize(&mut deserializer)?;
+        Some((credential_id, cose_key_to_sec1(&cose_key)?))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+fn cose_key_to_sec1(key: &Value) -> anyhow::Result<Vec<u8>> {
+    let map = match key {
+        Value::Map(map) => map,
+        _ => anyhow::bail!("credential public key is not a COSE map"),
+    };
+    let get = |label: i128| map.get(&Value::Integer(label));
+
+    match (get(1), get(3), get(-1)) {
+        (Some(Value::Integer(2)), Some(Value::Integer(COSE_ALG_ES256)), Some(Value::Integer(1))) => {}
+        _ => anyhow::bail!("only ES256 (P-256) credentials are supported"),
+    }
+
+    let (x, y) = match (get(-2), get(-3)) {
+        (Some(Value::Bytes(x)), Some(Value::Bytes(y))) if x.len() == 32 && y.len() == 32 => (x, y),
+        _ => anyhow::bail!("invalid EC2 key coordinates"),
+    };
+
+    let mut sec1 = Vec::with_capacity(65);
+    sec1.push(0x04);
+    sec1.extend_from_slice(x);
+    sec1.extend_from_slice(y);
+    VerifyingKey::from_sec1_bytes(&sec1)?;
+
+    Ok(sec1)
+}
+
+fn check_flags_and_rp(config: &Config, data: &AuthenticatorData) -> anyhow::Result<()> {
+    if data.rp_id_hash != Sha256::digest(config.webauthn_rp_id.as_bytes()).as_slice() {
+        anyhow::bail!("relying party id mismatch");
+    }
+    if data.flags & FLAG_USER_PRESENT == 0 || data.flags & FLAG_USER_VERIFIED == 0 {
+        anyhow::bail!("user presence and verification are required");
+    }
+
+    Ok(())
+}
+
+pub fn verify_registration(
+    config: &Config,
+    challenge: &[u8],
+    client_data_json: &[u8],
+    attestation_object: &[u8],
+) -> anyhow::Result<RegisteredCredential> {
+    verify_client_data(config, client_data_json, "webauthn.create", challenge)?;
+
+    let attestation: Value = serde_cbor::from_slice(attestation_object)?;
+    let auth_data = match &attestation {
+        Value::Map(map) => match map.get(&Value::Text("authData".to_string())) {
+            Some(Value::Bytes(bytes)) => bytes,
+            _ => anyhow::bail!("attestation object has no authData"),
+        },
+        _ => anyhow::bail!("attestation object is not a map"),
+    };
+
+    let data = parse_authenticator_data(auth_data)?;
+    check_flags_and_rp(config, &data)?;
+
+    let (credential_id, public_key) = data
+        .attested_credential
+        .ok_or_else(|| anyhow::anyhow!("attestation has no credential data"))?;
+
+    Ok(RegisteredCredential {
+        credential_id,
+        public_key,
+        sign_count: data.sign_count,
+    })
+}
+
+pub fn verify_assertion(
+    config: &Config,
+    challenge: &[u8],
+    public_key: &[u8],
+    stored_sign_count: u32,
+    client_data_json: &[u8],
+    authenticator_data: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<u32> {
+    verify_client_data(config, client_data_json, "webauthn.get", challenge)?;
+
+    let data = parse_authenticator_data(authenticator_data)?;
+    check_flags_and_rp(config, &data)?;
+
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+
+    let key = VerifyingKey::from_sec1_bytes(public_key)?;
+    let signature = Signature::from_der(signature)?;
+    key.verify(&signed, &signature)?;
+
+    if (data.sign_count != 0 || stored_sign_count != 0) && data.sign_count <= stored_sign_count {
+        anyhow::bail!("signature counter did not increase; the authenticator may be cloned");
+    }
+
+    Ok(data.sign_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use p256::ecdsa::{signature::Signer, SigningKey};
+
+    const ORIGIN: &str = "https://drive.example.com";
+    const RP_ID: &str = "drive.example.com";
+
+    fn config() -> Config {
+        let mut config = Config::from_env().unwrap();
+        config.webauthn_rp_id = RP_ID.to_string();
+        config.webauthn_origin = ORIGIN.to_string();
+        config
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    fn client_data(kind: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": kind,
+            "challenge": URL_SAFE_NO_PAD.encode(challenge),
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    fn attestation_object(credential_id: &[u8]) -> Vec<u8> {
+        let point = signing_key().verifying_key().to_encoded_point(false);
+        let cose_key = Value::Map(BTreeMap::from([
+            (Value::Integer(1), Value::Integer(2)),
+            (Value::Integer(3), Value::Integer(COSE_ALG_ES256)),
+            (Value::Integer(-1), Value::Integer(1)),
+            (Value::Integer(-2), Value::Bytes(point.x().unwrap().to_vec())),
+            (Value::Integer(-3), Value::Bytes(point.y().unwrap().to_vec())),
+        ]));
+
+        let mut auth_data = authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED | FLAG_ATTESTED_DATA, 0);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(credential_id);
+        auth_data.extend_from_slice(&serde_cbor::to_vec(&cose_key).unwrap());
+
+        serde_cbor::to_vec(&Value::Map(BTreeMap::from([
+            (Value::Text("fmt".to_string()), Value::Text("none".to_string())),
+            (Value::Text("attStmt".to_string()), Value::Map(BTreeMap::new())),
+            (Value::Text("authData".to_string()), Value::Bytes(auth_data)),
+        ])))
+        .unwrap()
+    }
+
+    fn sign(authenticator_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+        let mut signed = authenticator_data.to_vec();
+        signed.extend_from_slice(&Sha256::digest(client_data_json));
+        let signature: Signature = signing_key().sign(&signed);
+        signature.to_der().as_bytes().to_vec()
+    }
+
+    fn registered() -> RegisteredCredential {
+        let challenge = new_challenge();
+        verify_registration(&config(), &challenge, &client_data("webauthn.create", &challenge, ORIGIN), &attestation_object(b"credential"))
+            .unwrap()
+    }
+
+    #[test]
+    fn registers_es256_credential() {
+        let credential = registered();
+        assert_eq!(credential.credential_id, b"credential");
+        assert_eq!(credential.public_key, signing_key().verifying_key().to_encoded_point(false).as_bytes());
+        assert_eq!(credential.sign_count, 0);
+    }
+
+    #[test]
+    fn registration_checks_challenge_and_origin() {
+        let config = config();
+        let challenge = new_challenge();
+        let attestation = attestation_object(b"credential");
+
+        let wrong_challenge = client_data("webauthn.create", &new_challenge(), ORIGIN);
+        assert!(verify_registration(&config, &challenge, &wrong_challenge, &attestation).is_err());
+
+        let wrong_origin = client_data("webauthn.create", &challenge, "https://evil.example.com");
+        assert!(verify_registration(&config, &challenge, &wrong_origin, &attestation).is_err());
+
+        let wrong_type = client_data("webauthn.get", &challenge, ORIGIN);
+        assert!(verify_registration(&config, &challenge, &wrong_type, &attestation).is_err());
+    }
+
+    #[test]
+    fn verifies_assertion() {
+        let config = config();
+        let public_key = registered().public_key;
+        let challenge = new_challenge();
+        let client_data_json = client_data("webauthn.get", &challenge, ORIGIN);
+        let auth_data = authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 5);
+        let signature = sign(&auth_data, &client_data_json);
+
+        let sign_count = verify_assertion(&config, &challenge, &public_key, 4, &client_data_json, &auth_data, &signature).unwrap();
+        assert_eq!(sign_count, 5);
+    }
+
+    #[test]
+    fn rejects_bad_assertions() {
+        let config = config();
+        let public_key = registered().public_key;
+        let challenge = new_challenge();
+        let client_data_json = client_data("webauthn.get", &challenge, ORIGIN);
+        let verified = FLAG_USER_PRESENT | FLAG_USER_VERIFIED;
+
+        let auth_data = authenticator_data(RP_ID, verified, 5);
+        let mut tampered = auth_data.clone();
+        tampered[36] = 6;
+        let signature = sign(&auth_data, &client_data_json);
+        assert!(verify_assertion(&config, &challenge, &public_key, 0, &client_data_json, &tampered, &signature).is_err());
+
+        assert!(verify_assertion(&config, &challenge, &public_key, 5, &client_data_json, &auth_data, &signature).is_err());
+
+        let unverified = authenticator_data(RP_ID, FLAG_USER_PRESENT, 5);
+        let signature = sign(&unverified, &client_data_json);
+        assert!(verify_assertion(&config, &challenge, &public_key, 0, &client_data_json, &unverified, &signature).is_err());
+
+        let other_rp = authenticator_data("evil.example.com", verified, 5);
+        let signature = sign(&other_rp, &client_data_json);
+        assert!(verify_assertion(&config, &challenge, &public_key, 0, &client_data_json, &other_rp, &signature).is_err());
+    }
+
+    #[test]
+    fn decoy_credentials_are_stable_per_username() {
+        let config = config();
+        assert_eq!(decoy_credentials(&config, "alice"), decoy_credentials(&config, "Alice"));
+        assert_ne!(decoy_credentials(&config, "alice"), decoy_credentials(&config, "bob"));
+
+        let options = request_options(&config, &new_challenge(), &decoy_credentials(&config, "alice"));
+        assert_eq!(options["allowCredentials"].as_array().unwrap().len(), 1);
+    }
+}
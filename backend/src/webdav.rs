@@ -0,0 +1,397 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use uuid::Uuid;
+use crate::remote_fetch::percent_decode;
+use crate::sigv4::uri_encode;
+
+pub const PREFIX: &str = "/dav";
+pub const ALLOWED_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, PROPPATCH, MKCOL, MOVE, LOCK, UNLOCK";
+pub const COMPLIANCE_CLASSES: &str = "1, 2";
+pub const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+pub const MAX_XML_BODY_SIZE: usize = 1024 * 1024;
+
+const DEFAULT_LOCK_SECONDS: u64 = 3600;
+const MAX_LOCK_SECONDS: u64 = 24 * 3600;
+const TOKEN_SCHEME: &str = "opaquelocktoken:";
+// Locks live in memory, so each user and the server as a whole get a bounded number.
+const MAX_LOCKS_PER_USER: usize = 1000;
+const MAX_LOCKS: usize = 100_000;
+
+static LOCKS: Mutex<BTreeMap<String, Lock>> = Mutex::new(BTreeMap::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    Zero,
+    One,
+    Infinity,
+}
+
+pub struct Resource {
+    pub components: Vec<String>,
+    pub is_dir: bool,
+    pub size: i64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub created: DateTime<Utc>,
+    pub modified: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Lock {
+    pub token: String,
+    pub user_id: Uuid,
+    pub path: Vec<String>,
+    pub deep: bool,
+    pub owner: Option<String>,
+    pub timeout_seconds: u64,
+    expires_at: Instant,
+}
+
+pub fn depth(headers: &HeaderMap, default: Depth) -> Option<Depth> {
+    match headers.get("depth").map(|value| value.to_str().map(str::trim)) {
+        None => Some(default),
+        Some(Ok("0")) => Some(Depth::Zero),
+        Some(Ok("1")) => Some(Depth::One),
+        Some(Ok(value)) if value.eq_ignore_ascii_case("infinity") => Some(Depth::Infinity),
+        Some(_) => None,
+    }
+}
+
+pub fn overwrite(headers: &HeaderMap) -> bool {
+    !headers
+        .get("overwrite")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim().eq_ignore_ascii_case("f"))
+}
+
+// Destination is usually an absolute URL; anything before the first "dav" segment is the
+// scheme, host and whatever prefix a reverse proxy added.
+pub fn destination(headers: &HeaderMap) -> Option<Vec<String>> {
+    let value = headers.get("destination")?.to_str().ok()?.trim();
+    let path = match value.split_once("://") {
+        Some((_, rest)) => rest.find('/').map(|index| &rest[index..]).unwrap_or("/"),
+        None => value,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default();
+
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    segments.find(|segment| *segment == PREFIX.trim_start_matches('/'))?;
+    let components: Vec<String> = segments.map(percent_decode).collect();
+    components.iter().all(|name| valid_name(name)).then_some(components)
+}
+
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains('/') && !name.contains('\\')
+}
+
+pub fn href(components: &[String], is_dir: bool) -> String {
+    let mut href = format!("{}/", PREFIX);
+    href.push_str(
+        &components
+            .iter()
+            .map(|name| uri_encode(name, true))
+            .collect::<Vec<_>>()
+            .join("/"),
+    );
+    if is_dir && !components.is_empty() {
+        href.push('/');
+    }
+    href
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn http_date(timestamp: &DateTime<Utc>) -> String {
+    timestamp.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+const SUPPORTED_LOCK: &str = "<D:supportedlock><D:lockentry><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype></D:lockentry></D:supportedlock>";
+
+pub fn multistatus(resources: &[Resource]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n");
+
+    for resource in resources {
+        let name = resource.components.last().map(String::as_str).unwrap_or_default();
+        xml.push_str("<D:response>");
+        xml.push_str(&format!("<D:href>{}</D:href>", escape_xml(&href(&resource.components, resource.is_dir))));
+        xml.push_str("<D:propstat><D:prop>");
+        xml.push_str(&format!("<D:displayname>{}</D:displayname>", escape_xml(name)));
+        if resource.is_dir {
+            xml.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            xml.push_str("<D:resourcetype/>");
+            xml.push_str(&format!("<D:getcontentlength>{}</D:getcontentlength>", resource.size));
+            let content_type = resource.content_type.as_deref().unwrap_or("application/octet-stream");
+            xml.push_str(&format!("<D:getcontenttype>{}</D:getcontenttype>", escape_xml(content_type)));
+        }
+        if let Some(etag) = &resource.etag {
+            xml.push_str(&format!("<D:getetag>\"{}\"</D:getetag>", escape_xml(etag)));
+        }
+        xml.push_str(&format!("<D:creationdate>{}</D:creationdate>", resource.created.format("%Y-%m-%dT%H:%M:%SZ")));
+        xml.push_str(&format!("<D:getlastmodified>{}</D:getlastmodified>", http_date(&resource.modified)));
+        xml.push_str(SUPPORTED_LOCK);
+        xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n");
+    }
+
+    xml.push_str("</D:multistatus>\n");
+    xml
+}
+
+pub struct PropertyUpdate {
+    pub properties: Vec<(String, String)>,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+// Properties are not stored; PROPPATCH answers success for each of them so Windows and
+// macOS can finish copies, and a Win32LastModifiedTime becomes the file's modification time.
+pub fn parse_property_update(body: &str) -> PropertyUpdate {
+    static PROP_BLOCK: OnceLock<Regex> = OnceLock::new();
+    static ELEMENT: OnceLock<Regex> = OnceLock::new();
+    static NAMESPACE: OnceLock<Regex> = OnceLock::new();
+    static MODIFIED: OnceLock<Regex> = OnceLock::new();
+    let prop_block = PROP_BLOCK.get_or_init(|| {
+        Regex::new(r"(?s)<(?:[A-Za-z_][\w.-]*:)?prop\b[^>]*>(.*?)</(?:[A-Za-z_][\w.-]*:)?prop\s*>").unwrap()
+    });
+    let element = ELEMENT.get_or_init(|| Regex::new(r"<(/?)(?:([A-Za-z_][\w.-]*):)?([A-Za-z_][\w.-]*)\b[^>]*?(/?)>").unwrap());
+    let namespace = NAMESPACE.get_or_init(|| Regex::new(r#"xmlns(?::([A-Za-z_][\w.-]*))?\s*=\s*["']([^"']*)["']"#).unwrap());
+    let modified = MODIFIED.get_or_init(|| {
+        Regex::new(r"<(?:[A-Za-z_][\w.-]*:)?Win32LastModifiedTime\b[^>]*>([^<]*)<").unwrap()
+    });
+
+    let namespaces: BTreeMap<String, String> = namespace
+        .captures_iter(body)
+        .map(|captures| {
+            let prefix = captures.get(1).map(|m| m.as_str()).unwrap_or_default().to_string();
+            (prefix, captures[2].to_string())
+        })
+        .collect();
+
+    let mut properties = Vec::new();
+    for block in prop_block.captures_iter(body) {
+        let mut open_elements = 0usize;
+        for captures in element.captures_iter(&block[1]) {
+            if !captures[1].is_empty() {
+                open_elements = open_elements.saturating_sub(1);
+                continue;
+            }
+            if open_elements == 0 {
+                let prefix = captures.get(2).map(|m| m.as_str()).unwrap_or_default();
+                let uri = namespaces.get(prefix).cloned().unwrap_or_default();
+                properties.push((captures[3].to_string(), uri));
+            }
+            if captures[4].is_empty() {
+                open_elements += 1;
+            }
+        }
+    }
+
+    PropertyUpdate {
+        properties,
+        modified: modified
+            .captures(body)
+            .and_then(|captures| DateTime::parse_from_rfc2822(captures[1].trim()).ok())
+            .map(|timestamp| timestamp.with_timezone(&Utc)),
+    }
+}
+
+pub fn proppatch_multistatus(components: &[String], is_dir: bool, properties: &[(String, String)]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n<D:response>");
+    xml.push_str(&format!("<D:href>{}</D:href>", escape_xml(&href(components, is_dir))));
+    xml.push_str("<D:propstat><D:prop>");
+    for (name, uri) in properties {
+        xml.push_str(&format!("<{} xmlns=\"{}\"/>", name, escape_xml(uri)));
+    }
+    xml.push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n</D:multistatus>\n");
+    xml
+}
+
+pub fn lock_timeout(headers: &HeaderMap) -> u64 {
+    let requested = headers
+        .get("timeout")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| {
+            value.split(',').map(str::trim).find_map(|timeout| {
+                if timeout.eq_ignore_ascii_case("infinite") {
+                    Some(MAX_LOCK_SECONDS)
+                } else {
+                    timeout.strip_prefix("Second-")?.parse().ok()
+                }
+            })
+        })
+        .unwrap_or(DEFAULT_LOCK_SECONDS);
+    requested.clamp(1, MAX_LOCK_SECONDS)
+}
+
+pub fn lock_owner(body: &str) -> Option<String> {
+    static OWNER: OnceLock<Regex> = OnceLock::new();
+    static TAG: OnceLock<Regex> = OnceLock::new();
+    let owner = OWNER.get_or_init(|| {
+        Regex::new(r"(?s)<(?:[A-Za-z_][\w.-]*:)?owner\b[^>]*>(.*?)</(?:[A-Za-z_][\w.-]*:)?owner\s*>").unwrap()
+    });
+    let tag = TAG.get_or_init(|| Regex::new(r"<[^>]*>").unwrap());
+
+    let inner = owner.captures(body)?;
+    let text = tag.replace_all(&inner[1], "").trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// Lock-Token on UNLOCK, and every token named in an If header.
+pub fn submitted_tokens(headers: &HeaderMap) -> Vec<String> {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let token = TOKEN.get_or_init(|| Regex::new(r"<(opaquelocktoken:[^>\s]+)>").unwrap());
+
+    ["lock-token", "if"]
+        .iter()
+        .filter_map(|name| headers.get(*name)?.to_str().ok())
+        .flat_map(|value| token.captures_iter(value).map(|captures| captures[1].to_string()).collect::<Vec<_>>())
+        .collect()
+}
+
+fn covers(lock: &Lock, path: &[String], with_descendants: bool) -> bool {
+    let ancestor = path.starts_with(&lock.path) && (lock.deep || path.len() == lock.path.len());
+    ancestor || (with_descendants && lock.path.starts_with(path))
+}
+
+fn live_locks() -> std::sync::MutexGuard<'static, BTreeMap<String, Lock>> {
+    let mut locks = LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    locks.retain(|_, lock| lock.expires_at > now);
+    locks
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockRefused {
+    Conflict,
+    TooManyLocks,
+}
+
+pub fn acquire(
+    user_id: Uuid,
+    path: &[String],
+    deep: bool,
+    owner: Option<String>,
+    timeout_seconds: u64,
+) -> Result<Lock, LockRefused> {
+    let mut locks = live_locks();
+    let mut held = 0;
+    for lock in locks.values().filter(|lock| lock.user_id == user_id) {
+        if covers(lock, path, deep) || (deep && lock.path.starts_with(path)) {
+            return Err(LockRefused::Conflict);
+        }
+        held += 1;
+    }
+    if held >= MAX_LOCKS_PER_USER || locks.len() >= MAX_LOCKS {
+        return Err(LockRefused::TooManyLocks);
+    }
+
+    let lock = Lock {
+        token: format!("{}{}", TOKEN_SCHEME, Uuid::new_v4()),
+        user_id,
+        path: path.to_vec(),
+        deep,
+        owner,
+        timeout_seconds,
+        expires_at: Instant::now() + Duration::from_secs(timeout_seconds),
+    };
+    locks.insert(lock.token.clone(), lock.clone());
+    Ok(lock)
+}
+
+pub fn refresh(user_id: Uuid, path: &[String], tokens: &[String], timeout_seconds: u64) -> Option<Lock> {
+    let mut locks = live_locks();
+    let token = tokens
+        .iter()
+        .find(|token| locks.get(*token).is_some_and(|lock| lock.user_id == user_id && covers(lock, path, false)))?;
+    let lock = locks.get_mut(token)?;
+    lock.timeout_seconds = timeout_seconds;
+    lock.expires_at = Instant::now() + Duration::from_secs(timeout_seconds);
+    Some(lock.clone())
+}
+
+pub fn release(user_id: Uuid, path: &[String], token: &str) -> bool {
+    let mut locks = live_locks();
+    match locks.get(token) {
+        Some(lock) if lock.user_id == user_id && covers(lock, path, false) => {
+            locks.remove(token);
+            true
+        }
+        _ => false,
+    }
+}
+
+// A change is allowed when every lock on the path (and, for collections, below it) was
+// named by the client.
+pub fn may_modify(user_id: Uuid, path: &[String], with_descendants: bool, headers: &HeaderMap) -> bool {
+    let tokens = submitted_tokens(headers);
+    live_locks()
+        .values()
+        .filter(|lock| lock.user_id == user_id && covers(lock, path, with_descendants))
+        .all(|lock| tokens.contains(&lock.token))
+}
+
+pub fn discard(user_id: Uuid, path: &[String]) {
+    live_locks().retain(|_, lock| lock.user_id != user_id || !lock.path.starts_with(path));
+}
+
+pub fn lock_discovery(lock: &Lock) -> String {
+    let owner = lock
+        .owner
+        .as_deref()
+        .map(|owner| format!("<D:owner>{}</D:owner>", escape_xml(owner)))
+        .unwrap_or_default();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:prop xmlns:D=\"DAV:\"><D:lockdiscovery><D:activelock>\
+<D:locktype><D:write/></D:locktype><D:lockscope><D:exclusive/></D:lockscope>\
+<D:depth>{}</D:depth>{}<D:timeout>Second-{}</D:timeout>\
+<D:locktoken><D:href>{}</D:href></D:locktoken><D:lockroot><D:href>{}</D:href></D:lockroot>\
+</D:activelock></D:lockdiscovery></D:prop>\n",
+        if lock.deep { "infinity" } else { "0" },
+        owner,
+        lock.timeout_seconds,
+        lock.token,
+        escape_xml(&href(&lock.path, false)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(name: &str) -> Vec<String> {
+        vec!["docs".to_string(), name.to_string()]
+    }
+
+    #[test]
+    fn refuses_conflicting_locks() {
+        let user_id = Uuid::new_v4();
+        acquire(user_id, &path("a.txt"), false, None, 60).unwrap();
+        assert_eq!(acquire(user_id, &path("a.txt"), false, None, 60).unwrap_err(), LockRefused::Conflict);
+        assert_eq!(acquire(user_id, &["docs".to_string()], true, None, 60).unwrap_err(), LockRefused::Conflict);
+        discard(user_id, &[]);
+    }
+
+    #[test]
+    fn caps_locks_per_user() {
+        let user_id = Uuid::new_v4();
+        for index in 0..MAX_LOCKS_PER_USER {
+            acquire(user_id, &path(&index.to_string()), false, None, 60).unwrap();
+        }
+        assert_eq!(acquire(user_id, &path("one-more"), false, None, 60).unwrap_err(), LockRefused::TooManyLocks);
+        assert!(acquire(Uuid::new_v4(), &path("one-more"), false, None, 60).is_ok());
+
+        discard(user_id, &[]);
+        assert!(acquire(user_id, &path("one-more"), false, None, 60).is_ok());
+        discard(user_id, &[]);
+    }
+}
@@ -0,0 +1,61 @@
+'use client';
+
+import { useEffect, useState } from 'react';
+import { useRouter } from 'next/navigation';
+import { Spinner } from '@/components/ui/spinner';
+import { useAuthStore } from '@/store/auth';
+import { authApi } from '@/lib/api';
+
+const ERROR_MESSAGES: Record<string, string> = {
+  access_denied: 'Sign-in was cancelled or denied by the identity provider',
+  invalid_state: 'The sign-in attempt expired, please try again',
+  email_conflict: 'An account with this email already exists',
+  missing_email: 'The identity provider did not share an email address',
+  account_deactivated: 'This account has been deactivated',
+};
+
+export default function OidcCallbackPage() {
+  const { login } = useAuthStore();
+  const router = useRouter();
+  const [error, setError] = useState('');
+
+  useEffect(() => {
+    const query = new URLSearchParams(window.location.search);
+    const errorCode = query.get('error');
+    if (errorCode) {
+      setError(ERROR_MESSAGES[errorCode] || 'Single sign-on failed');
+      return;
+    }
+
+    const fragment = new URLSearchParams(window.location.hash.slice(1));
+    const token = fragment.get('token');
+    window.history.replaceState(null, '', window.location.pathname);
+    if (!token) {
+      setError('Single sign-on failed');
+      return;
+    }
+
+    authApi
+      .getProfile(token)
+      .then((user) => {
+        login(token, user);
+        router.push('/');
+      })
+      .catch(() => setError('Single sign-on failed'));
+  }, [login, router]);
+
+  return (
+    <div className="min-h-screen flex items-center justify-center p-4">
+      {error ? (
+        <div className="space-y-4 text-center">
+          <p className="text-destructive">{error}</p>
+          <a href="/login" className="text-primary underline">
+            Back to sign in
+          </a>
+        </div>
+      ) : (
+        <Spinner size="lg" />
+      )}
+    </div>
+  );
+}
@@ -18,18 +18,38 @@ export default function LoginPage() {
   const { login, setLoading, isLoading, isAuthenticated, initializeAuth, isLoading: authLoading } = useAuthStore();
   const router = useRouter();
   const [authInitialized, setAuthInitialized] = useState(false);
+  const [oidcEnabled, setOidcEnabled] = useState(false);
 
   useEffect(() => {
     initializeAuth();
     setAuthInitialized(true);
   }, [initializeAuth]);
 
+  useEffect(() => {
+    authApi.oidcEnabled().then(setOidcEnabled).catch(() => setOidcEnabled(false));
+  }, []);
+
   useEffect(() => {
     if (authInitialized && isAuthenticated && !authLoading) {
       router.push('/');
     }
   }, [isAuthenticated, authLoading, authInitialized, router]);
 
+  const handlePasskeyLogin = async () => {
+    setError('');
+    setLoading(true);
+
+    try {
+      const response = await authApi.loginWithPasskey(username);
+      login(response.token, response.user);
+      router.push('/');
+    } catch {
+      setError('Passkey sign-in failed');
+    } finally {
+      setLoading(false);
+    }
+  };
+
   const handleSubmit = async (e: React.FormEvent) => {
     e.preventDefault();
     setError('');
@@ -131,6 +151,30 @@ export default function LoginPage() {
               )}
             </Button>
 
+            {authApi.passkeysSupported() && (
+              <Button
+                type="button"
+                variant="outline"
+                className="w-full h-11 text-base font-medium"
+                disabled={isLoading}
+                onClick={handlePasskeyLogin}
+              >
+                Sign in with a passkey
+              </Button>
+            )}
+
+            {oidcEnabled && (
+              <Button
+                type="button"
+                variant="outline"
+                className="w-full h-11 text-base font-medium"
+                onClick={() => {
+                  window.location.href = authApi.oidcLoginUrl();
+                }}
+              >
+                Sign in with SSO
+              </Button>
+            )}
 
           </form>
         </div>
@@ -6,7 +6,7 @@ import { Button } from '@/components/ui/button';
 import { Spinner } from '@/components/ui/spinner';
 import { ChunkedFileUpload } from '@/components/ui/chunked-file-upload';
 import { useAuthStore } from '@/store/auth';
-import { filesApi, StorageInfo, FileInfo } from '@/lib/api';
+import { filesApi, UserStorageInfo, FileInfo } from '@/lib/api';
 import {
   Upload,
   Settings,
@@ -46,7 +46,7 @@ function formatDate(dateString: string): string {
 export default function Dashboard() {
   const router = useRouter();
   const { user, isAuthenticated, logout, initializeAuth, isLoading: authLoading } = useAuthStore();
-  const [storageInfo, setStorageInfo] = useState<StorageInfo | null>(null);
+  const [storageInfo, setStorageInfo] = useState<UserStorageInfo | null>(null);
   const [files, setFiles] = useState<FileInfo[]>([]);
   const [trashFiles, setTrashFiles] = useState<FileInfo[]>([]);
   const [loading, setLoading] = useState(true);
@@ -281,16 +281,18 @@ export default function Dashboard() {
                 </div>
                 <p className="mt-1 text-xs text-muted-foreground">
                   {storageInfo ? (
-                    `${formatFileSize(storageInfo.used_space)} of ${formatFileSize(storageInfo.total_space)} used`
+                    storageInfo.quota_bytes !== null
+                      ? `${formatFileSize(storageInfo.used_bytes + storageInfo.trash_bytes)} of ${formatFileSize(storageInfo.quota_bytes)} used`
+                      : `${formatFileSize(storageInfo.used_bytes + storageInfo.trash_bytes)} used, ${formatFileSize(storageInfo.remaining_bytes)} free`
                   ) : (
                     'Loading storage info...'
                   )}
                 </p>
               </div>
-              {storageInfo && storageInfo.disk_count > 1 && (
+              {storageInfo && storageInfo.trash_bytes > 0 && (
                 <div className="mt-3 pt-3 border-t border-border">
                   <p className="text-xs text-muted-foreground">
-                    {storageInfo.disk_count} disks available
+                    {formatFileSize(storageInfo.trash_bytes)} in trash
                   </p>
                 </div>
               )}
@@ -44,8 +44,8 @@ export interface User {
   id: string;
   username: string;
   email: string;
-  password_hash: string;
   is_admin: boolean;
+  deactivated_at?: string | null;
   created_at: string;
   updated_at: string;
 }
@@ -59,6 +59,8 @@ export interface FileInfo {
   disk_path: string;
   file_size: number;
   mime_type?: string;
+  client_modified_at?: string;
+  keep_offline: boolean;
   created_at: string;
   updated_at: string;
   is_deleted: boolean;
@@ -67,6 +69,7 @@ export interface FileInfo {
 
 export interface AuthResponse {
   token: string;
+  expires_at: string;
   user: User;
 }
 
@@ -88,10 +91,21 @@ export interface StorageInfo {
   disks: DiskInfo[];
 }
 
+export interface UserStorageInfo {
+  used_bytes: number;
+  trash_bytes: number;
+  file_count: number;
+  trash_count: number;
+  quota_bytes: number | null;
+  remaining_bytes: number;
+  usage_percentage: number;
+}
+
 export interface InitiateChunkedUploadRequest {
   filename: string;
   total_size: number;
   chunk_size: number;
+  client_modified_at?: string;
 }
 
 export interface InitiateChunkedUploadResponse {
@@ -118,6 +132,7 @@ export interface ChunkedUpload {
   temp_path: string;
   disk_path: string;
   is_completed: boolean;
+  missing_chunks?: number[];
   created_at: string;
   updated_at: string;
 }
@@ -132,12 +147,116 @@ export interface ChunkedUploadProgress {
   error?: string;
 }
 
+const toBase64Url = (buffer: ArrayBuffer): string =>
+  btoa(String.fromCharCode(...new Uint8Array(buffer)))
+    .replace(/\+/g, '-')
+    .replace(/\//g, '_')
+    .replace(/=+$/, '');
+
+const fromBase64Url = (value: string): ArrayBuffer => {
+  const base64 = value.replace(/-/g, '+').replace(/_/g, '/');
+  const binary = atob(base64 + '='.repeat((4 - (base64.length % 4)) % 4));
+  return Uint8Array.from(binary, (c) => c.charCodeAt(0)).buffer;
+};
+
+export interface Passkey {
+  id: string;
+  name: string;
+  created_at: string;
+  last_used_at: string | null;
+}
+
 export const authApi = {
   login: async (data: LoginRequest): Promise<AuthResponse> => {
     const response = await api.post('/auth/login', data);
     return response.data;
   },
 
+  getProfile: async (token: string): Promise<User> => {
+    const response = await api.get('/user/profile', {
+      headers: { Authorization: `Bearer ${token}` },
+    });
+    return response.data;
+  },
+
+  oidcEnabled: async (): Promise<boolean> => {
+    const response = await api.get('/capabilities');
+    return Boolean(response.data?.features?.oidc);
+  },
+
+  oidcLoginUrl: (): string => `${API_BASE_URL}/auth/oidc/login`,
+
+  passkeysSupported: (): boolean =>
+    typeof window !== 'undefined' && typeof window.PublicKeyCredential !== 'undefined',
+
+  loginWithPasskey: async (username?: string): Promise<AuthResponse> => {
+    const start = await api.post('/auth/webauthn/login/start', { username: username || null });
+    const options = start.data.public_key;
+    const credential = (await navigator.credentials.get({
+      publicKey: {
+        ...options,
+        challenge: fromBase64Url(options.challenge),
+        allowCredentials: options.allowCredentials.map((c: { type: 'public-key'; id: string }) => ({
+          ...c,
+          id: fromBase64Url(c.id),
+        })),
+      },
+    })) as PublicKeyCredential;
+    const response = credential.response as AuthenticatorAssertionResponse;
+
+    const finish = await api.post('/auth/webauthn/login/finish', {
+      challenge_id: start.data.challenge_id,
+      credential: {
+        id: toBase64Url(credential.rawId),
+        response: {
+          clientDataJSON: toBase64Url(response.clientDataJSON),
+          authenticatorData: toBase64Url(response.authenticatorData),
+          signature: toBase64Url(response.signature),
+          userHandle: response.userHandle ? toBase64Url(response.userHandle) : null,
+        },
+      },
+    });
+    return finish.data;
+  },
+
+  registerPasskey: async (name?: string): Promise<Passkey> => {
+    const start = await api.post('/auth/webauthn/register/start');
+    const options = start.data.public_key;
+    const credential = (await navigator.credentials.create({
+      publicKey: {
+        ...options,
+        challenge: fromBase64Url(options.challenge),
+        user: { ...options.user, id: fromBase64Url(options.user.id) },
+        excludeCredentials: options.excludeCredentials.map((c: { type: 'public-key'; id: string }) => ({
+          ...c,
+          id: fromBase64Url(c.id),
+        })),
+      },
+    })) as PublicKeyCredential;
+    const response = credential.response as AuthenticatorAttestationResponse;
+
+    const finish = await api.post('/auth/webauthn/register/finish', {
+      challenge_id: start.data.challenge_id,
+      name,
+      credential: {
+        id: toBase64Url(credential.rawId),
+        response: {
+          clientDataJSON: toBase64Url(response.clientDataJSON),
+          attestationObject: toBase64Url(response.attestationObject),
+        },
+      },
+    });
+    return finish.data;
+  },
+
+  getPasskeys: async (): Promise<Passkey[]> => {
+    const response = await api.get('/user/passkeys');
+    return response.data;
+  },
+
+  deletePasskey: async (id: string): Promise<void> => {
+    await api.delete(`/user/passkeys/${id}`);
+  },
 
 };
 
@@ -218,7 +337,7 @@ export const filesApi = {
     return response.data;
   },
 
-  getUserStorageInfo: async (): Promise<StorageInfo> => {
+  getUserStorageInfo: async (): Promise<UserStorageInfo> => {
     const response = await api.get('/user/storage');
     return response.data;
   },
@@ -279,6 +398,7 @@ export const filesApi = {
         filename: file.name,
         total_size: file.size,
         chunk_size: chunkSize,
+        client_modified_at: file.lastModified ? new Date(file.lastModified).toISOString() : undefined,
       });
 
       const { upload_id, total_chunks } = initResponse;
@@ -5,8 +5,8 @@ export interface User {
   id: string;
   username: string;
   email: string;
-  password_hash: string;
   is_admin: boolean;
+  deactivated_at?: string | null;
   created_at: string;
   updated_at: string;
 }